@@ -115,11 +115,16 @@
                     path: type:
                     (craneLib.filterCargoSources path type)
                     || (type == "directory" && baseNameOf path == "migrations")
-                    || (builtins.match ".*\\.sql$" path != null);
+                    || (builtins.match ".*\\.sql$" path != null)
+                    || (type == "directory" && baseNameOf path == "templates")
+                    || (builtins.match ".*/templates/.*\\.txt$" path != null)
+                    || (type == "directory" && baseNameOf path == ".sqlx")
+                    || (builtins.match ".*/\\.sqlx/.*\\.json$" path != null);
                 };
                 commonArgs = {
                   inherit pname src;
                   strictDeps = true;
+                  SQLX_OFFLINE = "true";
                   nativeBuildInputs = [ pkgs.pkg-config ];
                   buildInputs = [ pkgs.openssl ];
                 };
@@ -0,0 +1,10 @@
+ALTER TABLE passkey ADD COLUMN login_count INTEGER NOT NULL DEFAULT 0;
+ALTER TABLE passkey ADD COLUMN last_ip TEXT;
+ALTER TABLE passkey ADD COLUMN last_user_agent TEXT;
+
+CREATE TABLE seen_device (
+    user_id    TEXT NOT NULL REFERENCES user(id),
+    user_agent TEXT NOT NULL,
+    first_seen TEXT NOT NULL DEFAULT (datetime('now')),
+    PRIMARY KEY (user_id, user_agent)
+);
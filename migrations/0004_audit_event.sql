@@ -0,0 +1,11 @@
+CREATE TABLE audit_event (
+    id         INTEGER PRIMARY KEY AUTOINCREMENT,
+    kind       TEXT NOT NULL,
+    user_id    TEXT,
+    ip         TEXT,
+    user_agent TEXT,
+    detail     TEXT,
+    created    TEXT NOT NULL DEFAULT (datetime('now'))
+);
+
+CREATE INDEX audit_event_created ON audit_event (created);
@@ -0,0 +1,12 @@
+CREATE TABLE session (
+    id            TEXT PRIMARY KEY,
+    user_id       TEXT NOT NULL REFERENCES user(id),
+    token_hash    TEXT NOT NULL UNIQUE,
+    previous_hash TEXT,
+    created       TEXT NOT NULL DEFAULT (datetime('now')),
+    last_used     TEXT NOT NULL DEFAULT (datetime('now')),
+    expires_at    TEXT NOT NULL,
+    revoked_at    TEXT
+);
+
+CREATE INDEX session_previous_hash ON session (previous_hash);
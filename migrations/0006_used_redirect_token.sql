@@ -0,0 +1,4 @@
+CREATE TABLE used_redirect_token (
+    jti        TEXT PRIMARY KEY,
+    expires_at TEXT NOT NULL
+);
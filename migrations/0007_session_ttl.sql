@@ -0,0 +1 @@
+ALTER TABLE session ADD COLUMN ttl_seconds INTEGER NOT NULL DEFAULT 604800;
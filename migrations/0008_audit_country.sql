@@ -0,0 +1 @@
+ALTER TABLE audit_event ADD COLUMN country TEXT;
@@ -0,0 +1,6 @@
+CREATE TABLE passkey_invite (
+    token_hash TEXT PRIMARY KEY,
+    user_id    TEXT NOT NULL REFERENCES user(id),
+    created    TEXT NOT NULL DEFAULT (datetime('now')),
+    expires_at TEXT NOT NULL
+);
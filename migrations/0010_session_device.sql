@@ -0,0 +1,4 @@
+ALTER TABLE session ADD COLUMN name TEXT;
+ALTER TABLE session ADD COLUMN user_agent TEXT;
+ALTER TABLE session ADD COLUMN ip TEXT;
+ALTER TABLE session ADD COLUMN country TEXT;
@@ -0,0 +1,9 @@
+CREATE TABLE login_context (
+    user_id    TEXT NOT NULL REFERENCES user(id),
+    kind       TEXT NOT NULL,
+    value      TEXT NOT NULL,
+    count      INTEGER NOT NULL DEFAULT 1,
+    first_seen TEXT NOT NULL DEFAULT (datetime('now')),
+    last_seen  TEXT NOT NULL DEFAULT (datetime('now')),
+    PRIMARY KEY (user_id, kind, value)
+);
@@ -0,0 +1,8 @@
+CREATE TABLE totp_secret (
+    user_id   TEXT PRIMARY KEY REFERENCES user(id),
+    secret    BLOB NOT NULL,
+    confirmed INTEGER NOT NULL DEFAULT 0,
+    -- Last accepted time step, so a code can't be used twice.
+    last_step INTEGER,
+    created   TEXT NOT NULL DEFAULT (datetime('now'))
+);
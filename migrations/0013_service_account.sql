@@ -0,0 +1,10 @@
+CREATE TABLE service_account (
+    id         TEXT PRIMARY KEY,
+    name       TEXT NOT NULL UNIQUE,
+    -- Space-separated, e.g. "metrics:read forward-auth:verify".
+    scopes     TEXT NOT NULL,
+    token_hash TEXT NOT NULL UNIQUE,
+    created    TEXT NOT NULL DEFAULT (datetime('now')),
+    expires_at TEXT,
+    last_used  TEXT
+);
@@ -0,0 +1,6 @@
+-- Named leases so only one replica runs each background task at a time.
+CREATE TABLE lease (
+    name       TEXT PRIMARY KEY,
+    holder     TEXT NOT NULL,
+    expires_at TEXT NOT NULL
+);
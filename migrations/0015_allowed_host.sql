@@ -0,0 +1,5 @@
+-- Allowed hosts added at runtime through the admin API, on top of the config file's.
+CREATE TABLE allowed_host (
+    host  TEXT PRIMARY KEY,
+    added TEXT NOT NULL DEFAULT (datetime('now'))
+);
@@ -0,0 +1,12 @@
+-- Origins each user has been handed a session on via a login redirect token.
+CREATE TABLE connected_app (
+    user_id    TEXT NOT NULL,
+    origin     TEXT NOT NULL,
+    first_used TEXT NOT NULL DEFAULT (datetime('now')),
+    last_used  TEXT NOT NULL DEFAULT (datetime('now')),
+    count      INTEGER NOT NULL DEFAULT 1,
+    PRIMARY KEY (user_id, origin)
+);
+
+-- Where a session was opened when not on rp_origin, so one app's sessions can be revoked.
+ALTER TABLE session ADD COLUMN origin TEXT;
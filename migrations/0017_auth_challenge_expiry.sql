@@ -0,0 +1,3 @@
+-- Housekeeping deletes expired challenges in batches by expires_at. SQLite rejects
+-- datetime('now') in a partial index predicate, so this indexes every row.
+CREATE INDEX auth_challenge_expires_at ON auth_challenge (expires_at);
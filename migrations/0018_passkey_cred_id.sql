@@ -0,0 +1,6 @@
+-- The credential ID as stored inside `data` (base64url), so logins find the signing
+-- passkey with one indexed lookup. Not unique: an imported backup may repeat a
+-- credential under another user.
+ALTER TABLE passkey ADD COLUMN cred_id TEXT;
+UPDATE passkey SET cred_id = json_extract(data, '$.cred.cred_id');
+CREATE INDEX passkey_cred_id ON passkey (cred_id);
@@ -0,0 +1,35 @@
+-- Structured columns beside the webauthn-rs `Passkey` JSON in `data`, which stays
+-- the copy webauthn-rs reads back. Every write of `data` sets them too
+-- (`db::StoredPasskey`); here they are filled from it once. `cred_id` came with
+-- 0018.
+-- COSE public key as JSON.
+ALTER TABLE passkey ADD COLUMN public_key TEXT;
+ALTER TABLE passkey ADD COLUMN sign_count INTEGER NOT NULL DEFAULT 0;
+ALTER TABLE passkey ADD COLUMN backup_eligible INTEGER NOT NULL DEFAULT 0;
+ALTER TABLE passkey ADD COLUMN backup_state INTEGER NOT NULL DEFAULT 0;
+-- Only packed and TPM attestation carry an AAGUID into the stored credential.
+ALTER TABLE passkey ADD COLUMN aaguid TEXT;
+UPDATE passkey SET
+    cred_id = json_extract(data, '$.cred.cred_id'),
+    public_key = json_extract(data, '$.cred.cred'),
+    sign_count = COALESCE(json_extract(data, '$.cred.counter'), 0),
+    backup_eligible = COALESCE(json_extract(data, '$.cred.backup_eligible'), 0),
+    backup_state = COALESCE(json_extract(data, '$.cred.backup_state'), 0),
+    aaguid = COALESCE(
+        json_extract(data, '$.cred.attestation.metadata.Packed.aaguid'),
+        json_extract(data, '$.cred.attestation.metadata.Tpm.aaguid')
+    );
+
+-- A backup imported under a second user could repeat a credential. Before
+-- cred_id becomes unique, keep each credential's newest row and audit every
+-- copy dropped (kind `passkey_dropped`) under the user who lost it.
+INSERT INTO audit_event (kind, user_id, detail)
+SELECT 'passkey_dropped', user_id, 'duplicate credential: ' || name
+FROM passkey
+WHERE cred_id IS NOT NULL
+  AND id NOT IN (SELECT MAX(id) FROM passkey WHERE cred_id IS NOT NULL GROUP BY cred_id);
+DELETE FROM passkey
+WHERE cred_id IS NOT NULL
+  AND id NOT IN (SELECT MAX(id) FROM passkey WHERE cred_id IS NOT NULL GROUP BY cred_id);
+DROP INDEX passkey_cred_id;
+CREATE UNIQUE INDEX passkey_cred_id ON passkey (cred_id);
@@ -0,0 +1,3 @@
+-- SHA-256 of the challenge cookie of the browser that started the challenge; only
+-- a request carrying the same cookie can take it.
+ALTER TABLE auth_challenge ADD COLUMN binding TEXT NOT NULL DEFAULT '';
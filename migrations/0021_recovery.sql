@@ -0,0 +1,14 @@
+-- One printed recovery URL per user; only its SHA-256 is kept.
+CREATE TABLE recovery_code (
+    user_id    TEXT PRIMARY KEY REFERENCES user(id),
+    token_hash TEXT NOT NULL UNIQUE,
+    created    TEXT NOT NULL DEFAULT (datetime('now'))
+);
+
+-- A reset asked for with the recovery URL. It can be completed from ready_at on,
+-- unless a signed-in session cancels it first.
+CREATE TABLE recovery_request (
+    user_id      TEXT PRIMARY KEY REFERENCES user(id),
+    requested_at TEXT NOT NULL DEFAULT (datetime('now')),
+    ready_at     TEXT NOT NULL
+);
@@ -0,0 +1,3 @@
+-- The app (redirect origin) a login was for, and per-user history lookups.
+ALTER TABLE audit_event ADD COLUMN app TEXT;
+CREATE INDEX audit_event_user ON audit_event (user_id, id);
@@ -0,0 +1,3 @@
+-- Temporary passkeys (a borrowed security key) stop signing in after this. NULL
+-- keeps the passkey until it is removed or `passkeys.max_age_days` retires it.
+ALTER TABLE passkey ADD COLUMN expires_at TEXT;
@@ -0,0 +1,10 @@
+-- Passkey names are unique per user, ignoring ASCII case, so the device list is
+-- unambiguous. Existing repeats keep the oldest name and get their id appended.
+UPDATE passkey SET name = name || ' (' || id || ')'
+WHERE EXISTS (
+    SELECT 1 FROM passkey AS older
+    WHERE older.user_id = passkey.user_id
+      AND older.name = passkey.name COLLATE NOCASE
+      AND older.id < passkey.id
+);
+CREATE UNIQUE INDEX passkey_user_name ON passkey (user_id, name COLLATE NOCASE);
@@ -0,0 +1,6 @@
+-- Deleted passkeys stay restorable for `passkeys.delete_grace_hours`, then
+-- housekeeping purges them. Only live passkeys hold their name.
+ALTER TABLE passkey ADD COLUMN deleted_at TEXT;
+DROP INDEX passkey_user_name;
+CREATE UNIQUE INDEX passkey_user_name ON passkey (user_id, name COLLATE NOCASE)
+    WHERE deleted_at IS NULL;
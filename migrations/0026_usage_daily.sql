@@ -0,0 +1,9 @@
+-- Aggregated daily counters behind the admin stats' usage section. Rows hold
+-- counts only: no user, session or address.
+CREATE TABLE usage_daily (
+    day    TEXT NOT NULL,             -- YYYY-MM-DD, UTC
+    metric TEXT NOT NULL,             -- 'sessions' (used that day) or 'redirects'
+    app    TEXT NOT NULL DEFAULT '',  -- redirect origin, for 'redirects'
+    count  INTEGER NOT NULL,
+    PRIMARY KEY (day, metric, app)
+);
@@ -0,0 +1,4 @@
+-- Like names, only live passkeys hold their credential, so a deleted passkey can
+-- be registered or imported again within its restore window.
+DROP INDEX passkey_cred_id;
+CREATE UNIQUE INDEX passkey_cred_id ON passkey (cred_id) WHERE deleted_at IS NULL;
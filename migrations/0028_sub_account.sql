@@ -0,0 +1,9 @@
+-- Delegated sub-accounts: users the owner creates, with `parent_id` set, that may
+-- only get sessions for the origins listed in `sub_account_app`.
+ALTER TABLE user ADD COLUMN parent_id TEXT REFERENCES user(id);
+
+CREATE TABLE sub_account_app (
+    user_id TEXT NOT NULL REFERENCES user(id),
+    origin  TEXT NOT NULL,  -- normalized, lowercase
+    PRIMARY KEY (user_id, origin)
+);
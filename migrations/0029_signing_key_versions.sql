@@ -0,0 +1,12 @@
+-- JWT signing keys become versioned: `kid` names the key in every token header.
+-- The housekeeping leader adds a version every 30 days and keeps the newest three
+-- (`signing_key::KEEP`). The single key so far becomes kid `legacy`, which tokens
+-- minted before this migration (no `kid`) are verified against.
+CREATE TABLE signing_key_version (
+    kid TEXT PRIMARY KEY,
+    secret BLOB NOT NULL,
+    created TEXT NOT NULL DEFAULT (datetime('now'))
+);
+INSERT INTO signing_key_version (kid, secret, created)
+SELECT 'legacy', secret, created FROM signing_key;
+DROP TABLE signing_key;
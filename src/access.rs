@@ -0,0 +1,146 @@
+use std::net::IpAddr;
+use std::str::FromStr;
+
+use crate::config::{AccessControlConfig, AccessRulesConfig, ConfigError, invalid};
+
+/// An address range in CIDR notation; a bare address is a single-host range.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct IpNet {
+    addr: IpAddr,
+    prefix: u8,
+}
+
+impl IpNet {
+    pub fn contains(&self, ip: IpAddr) -> bool {
+        match (self.addr, ip.to_canonical()) {
+            (IpAddr::V4(net), IpAddr::V4(ip)) => {
+                let mask = u32::MAX
+                    .checked_shl(32 - u32::from(self.prefix))
+                    .unwrap_or(0);
+                u32::from(net) & mask == u32::from(ip) & mask
+            }
+            (IpAddr::V6(net), IpAddr::V6(ip)) => {
+                let mask = u128::MAX
+                    .checked_shl(128 - u32::from(self.prefix))
+                    .unwrap_or(0);
+                u128::from(net) & mask == u128::from(ip) & mask
+            }
+            _ => false,
+        }
+    }
+}
+
+impl FromStr for IpNet {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let (addr, prefix) = match s.trim().split_once('/') {
+            Some((addr, prefix)) => (addr, Some(prefix)),
+            None => (s.trim(), None),
+        };
+        let addr = addr
+            .parse::<IpAddr>()
+            .map_err(|_| format!("invalid address in {s:?}"))?
+            .to_canonical();
+        let max = if addr.is_ipv4() { 32 } else { 128 };
+        let prefix = match prefix {
+            Some(prefix) => prefix
+                .parse::<u8>()
+                .ok()
+                .filter(|p| *p <= max)
+                .ok_or_else(|| format!("invalid prefix length in {s:?}"))?,
+            None => max,
+        };
+        Ok(Self { addr, prefix })
+    }
+}
+
+pub fn parse_nets(values: &[String], key: &'static str) -> Result<Vec<IpNet>, ConfigError> {
+    values
+        .iter()
+        .map(|v| v.parse().map_err(|e| invalid(key, e)))
+        .collect()
+}
+
+#[derive(Debug, Default)]
+pub struct AccessRules {
+    allow: Vec<IpNet>,
+    deny: Vec<IpNet>,
+}
+
+impl AccessRules {
+    fn load(
+        config: &AccessRulesConfig,
+        allow_key: &'static str,
+        deny_key: &'static str,
+    ) -> Result<Self, ConfigError> {
+        Ok(Self {
+            allow: parse_nets(&config.allow, allow_key)?,
+            deny: parse_nets(&config.deny, deny_key)?,
+        })
+    }
+
+    /// Deny wins; a non-empty allow list admits only matching addresses.
+    pub fn permits(&self, ip: IpAddr) -> bool {
+        !self.deny.iter().any(|net| net.contains(ip))
+            && (self.allow.is_empty() || self.allow.iter().any(|net| net.contains(ip)))
+    }
+}
+
+#[derive(Debug, Default)]
+pub struct AccessControl {
+    /// Applies to `/api/register/*` (new users and passkeys).
+    pub register: AccessRules,
+    /// Applies to every other request.
+    pub default: AccessRules,
+}
+
+impl AccessControl {
+    pub fn load(config: &AccessControlConfig) -> Result<Self, ConfigError> {
+        Ok(Self {
+            register: AccessRules::load(
+                &config.register,
+                "access_control.register.allow",
+                "access_control.register.deny",
+            )?,
+            default: AccessRules::load(
+                &config.default,
+                "access_control.default.allow",
+                "access_control.default.deny",
+            )?,
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn net(s: &str) -> IpNet {
+        s.parse().unwrap()
+    }
+
+    #[test]
+    fn ipnet_matches_prefix() {
+        assert!(net("192.168.0.0/16").contains("192.168.4.2".parse().unwrap()));
+        assert!(!net("192.168.0.0/16").contains("192.169.0.1".parse().unwrap()));
+        assert!(net("192.168.0.0/16").contains("::ffff:192.168.1.1".parse().unwrap()));
+        assert!(net("fd00::/8").contains("fd12::1".parse().unwrap()));
+        assert!(net("0.0.0.0/0").contains("8.8.8.8".parse().unwrap()));
+        assert!(net("10.0.0.1").contains("10.0.0.1".parse().unwrap()));
+        assert!(!net("10.0.0.1").contains("10.0.0.2".parse().unwrap()));
+        assert!("10.0.0.0/33".parse::<IpNet>().is_err());
+    }
+
+    #[test]
+    fn deny_overrides_allow() {
+        let rules = AccessRules {
+            allow: vec![net("10.0.0.0/8")],
+            deny: vec![net("10.0.0.13")],
+        };
+        assert!(rules.permits("10.1.2.3".parse().unwrap()));
+        assert!(!rules.permits("10.0.0.13".parse().unwrap()));
+        assert!(!rules.permits("203.0.113.1".parse().unwrap()));
+        assert!(AccessRules::default().permits("203.0.113.1".parse().unwrap()));
+    }
+}
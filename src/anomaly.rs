@@ -0,0 +1,143 @@
+use crate::db::Db;
+use crate::user_agent;
+
+/// Score at which a login counts as anomalous: a new country on its own, or a new
+/// network together with a new device.
+pub const ANOMALY_SCORE: u8 = 2;
+
+/// Where a login came from, as far as history comparisons go.
+pub struct LoginSignals {
+    pub country: Option<String>,
+    pub asn: Option<u32>,
+    /// Browser family + OS ("Firefox/Linux"), stable across browser updates.
+    pub device: String,
+}
+
+impl LoginSignals {
+    pub fn new(country: Option<String>, asn: Option<u32>, user_agent: &str) -> Self {
+        let d = user_agent::parse(user_agent);
+        Self {
+            country,
+            asn,
+            device: format!(
+                "{}/{}",
+                d.browser.unwrap_or("unknown"),
+                d.os.unwrap_or("unknown")
+            ),
+        }
+    }
+
+    fn entries(&self) -> Vec<(&'static str, String, u8)> {
+        let mut entries = vec![("device", self.device.clone(), 1)];
+        if let Some(country) = &self.country {
+            entries.push(("country", country.clone(), 2));
+        }
+        if let Some(asn) = self.asn {
+            entries.push(("asn", asn.to_string(), 1));
+        }
+        entries
+    }
+}
+
+#[derive(Debug, PartialEq, Eq)]
+pub struct Assessment {
+    pub score: u8,
+    /// Signal kinds never seen for this user before.
+    pub unseen: Vec<&'static str>,
+}
+
+impl Assessment {
+    pub fn is_anomalous(&self) -> bool {
+        self.score >= ANOMALY_SCORE
+    }
+
+    pub fn describe(&self) -> String {
+        format!("new {}", self.unseen.join(", "))
+    }
+}
+
+fn assess_with(signals: &LoginSignals, seen: impl Fn(&str, &str) -> bool) -> Assessment {
+    let mut assessment = Assessment {
+        score: 0,
+        unseen: Vec::new(),
+    };
+    for (kind, value, weight) in signals.entries() {
+        if !seen(kind, &value) {
+            assessment.score += weight;
+            assessment.unseen.push(kind);
+        }
+    }
+    assessment
+}
+
+/// Compare a login against the user's history. Returns `None` until the user has
+/// `min_logins` recorded logins, since there is nothing to deviate from yet.
+pub async fn assess(
+    db: &Db,
+    user_id: &str,
+    signals: &LoginSignals,
+    min_logins: u32,
+) -> Result<Option<Assessment>, sqlx::Error> {
+    let history = db.login_context(user_id).await?;
+    let logins: i64 = history
+        .iter()
+        .filter(|(kind, _, _)| kind == "device")
+        .map(|(_, _, count)| count)
+        .sum();
+    if logins < i64::from(min_logins) {
+        return Ok(None);
+    }
+    Ok(Some(assess_with(signals, |kind, value| {
+        history.iter().any(|(k, v, _)| k == kind && v == value)
+    })))
+}
+
+/// Add a successful login to the user's history.
+pub async fn remember(db: &Db, user_id: &str, signals: &LoginSignals) -> Result<(), sqlx::Error> {
+    for (kind, value, _) in signals.entries() {
+        db.remember_login_context(user_id, kind, &value).await?;
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn signals(country: Option<&str>, asn: Option<u32>, device: &str) -> LoginSignals {
+        LoginSignals {
+            country: country.map(str::to_owned),
+            asn,
+            device: device.to_owned(),
+        }
+    }
+
+    #[test]
+    fn familiar_login_scores_zero() {
+        let a = assess_with(&signals(Some("DE"), Some(3320), "Firefox/Linux"), |_, _| {
+            true
+        });
+        assert_eq!(a.score, 0);
+        assert!(!a.is_anomalous());
+    }
+
+    #[test]
+    fn new_country_alone_is_anomalous() {
+        let a = assess_with(&signals(Some("BR"), Some(3320), "Firefox/Linux"), |k, _| {
+            k != "country"
+        });
+        assert!(a.is_anomalous());
+        assert_eq!(a.describe(), "new country");
+    }
+
+    #[test]
+    fn new_device_alone_is_not() {
+        let a = assess_with(&signals(None, None, "Safari/iOS"), |_, _| false);
+        assert_eq!(a.unseen, vec!["device"]);
+        assert!(!a.is_anomalous());
+
+        let a = assess_with(&signals(None, Some(64496), "Safari/iOS"), |_, _| false);
+        assert!(a.is_anomalous());
+        assert_eq!(a.describe(), "new device, asn");
+    }
+}
@@ -0,0 +1,312 @@
+use std::collections::BTreeMap;
+use std::sync::atomic::Ordering;
+
+use axum::extract::State;
+use axum::http::{HeaderMap, StatusCode, header};
+use axum::response::IntoResponse;
+use axum::routing::get;
+use axum::{Json, Router};
+use serde::{Deserialize, Serialize};
+
+use super::{service_accounts, sub_accounts};
+use crate::audit;
+use crate::auth::{AuthUser, MaybeAuthUser};
+use crate::db::{EventCounts, MigrationStatus, PasskeyExpiryCounts};
+use crate::diagnostics::{self, LogEntry};
+use crate::origin;
+use crate::service_account::{self, LOG_LEVEL_WRITE, METRICS_READ};
+use crate::session;
+use crate::state::AppState;
+use crate::usage::{self, Usage};
+
+#[derive(Serialize)]
+struct Stats {
+    #[serde(flatten)]
+    events: EventCounts,
+    active_sessions: i64,
+    passkeys: i64,
+    /// Reminders for temporary and `passkeys.max_age_days` expiry.
+    #[serde(flatten)]
+    passkey_expiry: PasskeyExpiryCounts,
+    db_size_bytes: i64,
+    /// Deleted by this replica's housekeeping since it started.
+    expired_challenges_purged: u64,
+    /// Client IPs `[login_throttle]` refuses right now; absent when it is off.
+    throttled_clients: Option<i64>,
+    /// Sessions used and redirects per day over the last `usage::STATS_DAYS` days.
+    usage: Usage,
+    uptime_seconds: u64,
+}
+
+/// A bug-report bundle; see `AppConfig::diagnostics` for what config is included.
+#[derive(Serialize)]
+struct Diagnostics {
+    version: &'static str,
+    os: &'static str,
+    arch: &'static str,
+    instance_id: String,
+    uptime_seconds: u64,
+    maintenance: bool,
+    sqlite_version: String,
+    migrations: MigrationStatus,
+    config: serde_json::Value,
+    /// This replica's recent warnings and errors, oldest first.
+    recent_logs: Vec<LogEntry>,
+}
+
+#[derive(Serialize, Deserialize)]
+struct Maintenance {
+    enabled: bool,
+}
+
+#[derive(Deserialize)]
+struct LogLevelRequest {
+    /// `rust_log` directives, e.g. `info,den::api::auth=debug`, or a preset name.
+    filter: String,
+}
+
+#[derive(Serialize)]
+struct LogLevel {
+    filter: String,
+    /// What each name accepted by `PUT` expands to.
+    presets: BTreeMap<String, String>,
+}
+
+#[derive(Serialize)]
+struct AllowedHostsResponse {
+    /// From the config file (rp_origin, `allowed_hosts`, `[[apps]]`); read-only here.
+    configured: Vec<String>,
+    runtime: Vec<String>,
+}
+
+#[derive(Deserialize)]
+struct AllowedHostsRequest {
+    hosts: Vec<String>,
+}
+
+pub fn router() -> Router<AppState> {
+    Router::new()
+        .route("/stats", get(stats))
+        .route("/metrics", get(metrics))
+        .route("/diagnostics", get(diagnostics))
+        .route("/maintenance", get(maintenance).post(set_maintenance))
+        .route("/allowed-hosts", get(allowed_hosts).put(set_allowed_hosts))
+        .route("/log-level", get(log_level).put(set_log_level))
+        .nest("/service-accounts", service_accounts::router())
+        .nest("/sub-accounts", sub_accounts::router())
+}
+
+/// This replica's maintenance flag.
+async fn maintenance(State(state): State<AppState>, _auth: AuthUser) -> Json<Maintenance> {
+    Json(Maintenance {
+        enabled: state.maintenance.load(Ordering::Relaxed),
+    })
+}
+
+/// Flip maintenance mode on this replica only; others keep their own flag.
+async fn set_maintenance(
+    State(state): State<AppState>,
+    auth: AuthUser,
+    Json(req): Json<Maintenance>,
+) -> Json<Maintenance> {
+    state.maintenance.store(req.enabled, Ordering::Relaxed);
+    tracing::warn!(
+        enabled = req.enabled,
+        user_id = auth.user_id,
+        "maintenance mode toggled"
+    );
+    Json(req)
+}
+
+/// This replica's log filter; same access as `set_log_level`.
+async fn log_level(
+    State(state): State<AppState>,
+    auth: MaybeAuthUser,
+    headers: HeaderMap,
+) -> Result<Json<LogLevel>, StatusCode> {
+    if auth.0.is_none() {
+        service_account::require(&state.db, &headers, LOG_LEVEL_WRITE).await?;
+    }
+    let filter = diagnostics::log_filter().ok_or(StatusCode::CONFLICT)?;
+    Ok(Json(LogLevel {
+        filter,
+        presets: diagnostics::log_presets(&state.log_presets),
+    }))
+}
+
+/// Swap this replica's log filter until restart, e.g. to debug one module during
+/// an incident. Sessions and connections are untouched. The user or a service
+/// account with `log-level:write` may call it.
+async fn set_log_level(
+    State(state): State<AppState>,
+    auth: MaybeAuthUser,
+    headers: HeaderMap,
+    Json(req): Json<LogLevelRequest>,
+) -> Result<Json<LogLevel>, StatusCode> {
+    let by = match auth.0 {
+        Some(user) => user.user_id,
+        None => {
+            service_account::require(&state.db, &headers, LOG_LEVEL_WRITE)
+                .await?
+                .name
+        }
+    };
+    diagnostics::log_filter().ok_or(StatusCode::CONFLICT)?;
+    let directives = diagnostics::expand_log_preset(&req.filter, &state.log_presets);
+    let filter = diagnostics::set_log_filter(directives).map_err(|error| {
+        tracing::debug!(error, "rejected log filter");
+        StatusCode::BAD_REQUEST
+    })?;
+    tracing::warn!(filter, by, "log filter changed");
+    Ok(Json(LogLevel {
+        filter,
+        presets: diagnostics::log_presets(&state.log_presets),
+    }))
+}
+
+async fn allowed_hosts(
+    State(state): State<AppState>,
+    _auth: AuthUser,
+) -> Json<AllowedHostsResponse> {
+    Json(allowed_hosts_response(&state))
+}
+
+/// Replace the runtime hosts. Other replicas pick the change up within a minute.
+async fn set_allowed_hosts(
+    State(state): State<AppState>,
+    auth: AuthUser,
+    Json(req): Json<AllowedHostsRequest>,
+) -> Result<Json<AllowedHostsResponse>, StatusCode> {
+    let mut hosts = req
+        .hosts
+        .iter()
+        .map(|host| origin::normalize_host(host))
+        .collect::<Option<Vec<_>>>()
+        .ok_or(StatusCode::BAD_REQUEST)?;
+    hosts.sort();
+    hosts.dedup();
+    state
+        .db
+        .replace_allowed_hosts(&hosts)
+        .await
+        .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?;
+    tracing::warn!(
+        hosts = ?hosts,
+        user_id = auth.user_id,
+        "runtime allowed hosts replaced"
+    );
+    state.allowed_hosts.set_runtime(hosts);
+    Ok(Json(allowed_hosts_response(&state)))
+}
+
+fn allowed_hosts_response(state: &AppState) -> AllowedHostsResponse {
+    let mut configured: Vec<String> = state.allowed_hosts.configured().iter().cloned().collect();
+    configured.sort();
+    AllowedHostsResponse {
+        configured,
+        runtime: state.allowed_hosts.runtime().to_vec(),
+    }
+}
+
+/// Readable by the user or a service account with `metrics:read`.
+async fn stats(
+    State(state): State<AppState>,
+    auth: MaybeAuthUser,
+    headers: HeaderMap,
+) -> Result<Json<Stats>, StatusCode> {
+    if auth.0.is_none() {
+        service_account::require(&state.db, &headers, METRICS_READ).await?;
+    }
+    let events = state
+        .db
+        .event_counts()
+        .await
+        .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?;
+    let active_sessions = session::active_count(&state.storage)
+        .await
+        .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?;
+    let passkeys = state
+        .db
+        .passkey_count()
+        .await
+        .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?;
+    let passkey_expiry = state
+        .db
+        .passkey_expiry_counts(state.passkeys.max_age_days, state.passkeys.warning_days)
+        .await
+        .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?;
+    let db_size_bytes = state
+        .db
+        .size_bytes()
+        .await
+        .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?;
+    let throttled_clients = match state.login_throttle {
+        Some(throttle) => Some(
+            audit::throttled_clients(&state.db, throttle)
+                .await
+                .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?,
+        ),
+        None => None,
+    };
+    let usage = usage::summary(&state.db, usage::STATS_DAYS)
+        .await
+        .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?;
+
+    Ok(Json(Stats {
+        events,
+        active_sessions,
+        passkeys,
+        passkey_expiry,
+        db_size_bytes,
+        expired_challenges_purged: state.expired_challenges_purged.load(Ordering::Relaxed),
+        throttled_clients,
+        usage,
+        uptime_seconds: state.started.elapsed().as_secs(),
+    }))
+}
+
+const OPENMETRICS: &str = "application/openmetrics-text; version=1.0.0; charset=utf-8";
+
+/// Prometheus scrape target; same access as `stats`.
+async fn metrics(
+    State(state): State<AppState>,
+    auth: MaybeAuthUser,
+    headers: HeaderMap,
+) -> Result<impl IntoResponse, StatusCode> {
+    if auth.0.is_none() {
+        service_account::require(&state.db, &headers, METRICS_READ).await?;
+    }
+    Ok((
+        [(header::CONTENT_TYPE, OPENMETRICS)],
+        state.metrics.render(),
+    ))
+}
+
+async fn diagnostics(
+    State(state): State<AppState>,
+    _auth: AuthUser,
+) -> Result<Json<Diagnostics>, StatusCode> {
+    let sqlite_version = state
+        .db
+        .sqlite_version()
+        .await
+        .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?;
+    let migrations = state
+        .db
+        .migration_status()
+        .await
+        .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?;
+
+    Ok(Json(Diagnostics {
+        version: env!("CARGO_PKG_VERSION"),
+        os: std::env::consts::OS,
+        arch: std::env::consts::ARCH,
+        instance_id: state.instance_id.to_string(),
+        uptime_seconds: state.started.elapsed().as_secs(),
+        maintenance: state.maintenance.load(Ordering::Relaxed),
+        sqlite_version,
+        migrations,
+        config: (*state.diagnostics_config).clone(),
+        recent_logs: diagnostics::recent_logs(),
+    }))
+}
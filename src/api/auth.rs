@@ -1,18 +1,50 @@
-use axum::extract::{Path, Query, State};
-use axum::http::{HeaderMap, StatusCode};
-use axum::response::Redirect;
-use axum::routing::{get, patch, post};
+use std::net::{IpAddr, SocketAddr};
+
+use axum::extract::{ConnectInfo, Path, Query, State};
+use axum::http::{HeaderMap, HeaderName, StatusCode, header};
+use axum::response::{Html, Redirect};
+use axum::routing::{delete, get, patch, post, put};
 use axum::{Json, Router};
-use axum_extra::extract::cookie::{Cookie, CookieJar};
-use jsonwebtoken::{DecodingKey, EncodingKey, Header, Validation, decode, encode};
+use axum_extra::extract::cookie::CookieJar;
+use jsonwebtoken::Validation;
 use serde::{Deserialize, Serialize};
 use time::{Duration, OffsetDateTime};
+use url::{Position, Url};
 use uuid::Uuid;
 use webauthn_rs::prelude::*;
 
+use super::error::ApiError;
+use crate::anomaly::{self, Assessment, LoginSignals};
+use crate::audit::{self, AuditEvent, AuditKind};
 use crate::auth::{self, AuthUser, MaybeAuthUser};
-use crate::origin::{normalize_origin, origin_host, request_fallback_scheme, request_origin};
+use crate::config::{HookEvent, LoginMethod};
+use crate::connected_app;
+use crate::db::{self, BulkPasskeyOutcome, InviteInfo, PasskeyInfo, PasskeyQuery};
+use crate::hooks::HookContext;
+use crate::logout;
+use crate::notify::SecurityEvent;
+use crate::origin::{
+    client_ip, host_in_domain, is_related_origin, normalize_origin, origin_host,
+    request_fallback_scheme, request_origin, request_user_agent,
+};
+use crate::plugin::{PluginContext, Verdict};
+use crate::policy;
+use crate::puzzle::Puzzle;
+use crate::session;
 use crate::state::AppState;
+use crate::usage;
+
+const INVITE_TTL_MINUTES: i64 = 15;
+/// A batch covers a drawer of security keys enrolled one after another.
+const BATCH_INVITE_TTL_MINUTES: i64 = 60;
+const MAX_BATCH_INVITES: u32 = 20;
+/// Largest `limit` on `GET /api/passkeys`; without one, every passkey is listed.
+const MAX_PASSKEY_PAGE: i64 = 100;
+/// Passkeys matching the listing's filter, across all pages.
+const TOTAL_COUNT_HEADER: HeaderName = HeaderName::from_static("x-total-count");
+/// Ids one `POST /api/passkeys/bulk` may touch.
+const MAX_BULK_PASSKEYS: usize = 100;
+const CHALLENGE_TTL_MINUTES: i64 = 5;
 
 // --- Types ---
 
@@ -20,6 +52,8 @@ use crate::state::AppState;
 struct RegisterBeginRequest {
     user_name: Option<String>,
     passkey_name: String,
+    bootstrap_token: Option<String>,
+    invite_token: Option<String>,
 }
 
 #[derive(Serialize)]
@@ -41,9 +75,19 @@ struct LoginCompleteRequest {
 }
 
 #[derive(Deserialize)]
-struct LoginBeginRequest {
+struct LoginRecoveryRequest {
+    challenge_id: String,
+    /// The token from the printed recovery URL.
+    token: String,
+}
+
+#[derive(Deserialize)]
+pub struct LoginBeginRequest {
     redirect_origin: Option<String>,
     redirect_path: Option<String>,
+    /// A `GET /api/login/puzzle` token and its solution, when `[login_puzzle]` asks.
+    puzzle: Option<String>,
+    nonce: Option<String>,
 }
 
 #[derive(Serialize, Deserialize)]
@@ -53,22 +97,61 @@ struct RegistrationContext {
     user_name: String,
     passkey_name: String,
     is_new_user: bool,
+    /// Hash of the invite that authorised this registration, consumed on completion.
+    #[serde(default)]
+    invite_hash: Option<String>,
+}
+
+#[derive(Serialize, Deserialize)]
+enum AuthenticationState {
+    Passkey(PasskeyAuthentication),
+    Discoverable(DiscoverableAuthentication),
+    /// A step-up answered with the recovery code, for users with a single passkey.
+    RecoveryCode,
 }
 
 #[derive(Serialize, Deserialize)]
 struct AuthenticationContext {
-    webauthn_state: PasskeyAuthentication,
-    user_id: String,
+    webauthn_state: AuthenticationState,
     redirect_origin: Option<String>,
     redirect_path: Option<String>,
+    /// Set on the follow-up challenge after an anomalous login.
+    #[serde(default)]
+    step_up: Option<StepUp>,
+}
+
+/// Who must answer a step-up, and with what they may not.
+#[derive(Serialize, Deserialize)]
+struct StepUp {
+    user_id: String,
+    /// The passkey that signed the first assertion; a second one must differ.
+    credential: CredentialID,
 }
 
 #[derive(Serialize)]
-struct PasskeyInfo {
+struct InviteResponse {
+    url: String,
+    expires_at: String,
+}
+
+#[derive(Deserialize)]
+struct BatchInviteRequest {
+    count: u32,
+}
+
+#[derive(Serialize)]
+struct BatchInvite {
     id: i64,
-    name: String,
-    created: String,
-    last_used: Option<String>,
+    url: String,
+    expires_at: String,
+}
+
+#[derive(Serialize)]
+struct RecoveryStatus {
+    /// A synced passkey, or at least two passkeys that are hopefully on different devices.
+    survives_device_loss: bool,
+    synced: usize,
+    device_bound: usize,
 }
 
 #[derive(Deserialize)]
@@ -76,14 +159,61 @@ struct RenameRequest {
     name: String,
 }
 
+#[derive(Deserialize)]
+struct NameQuery {
+    name: String,
+}
+
+#[derive(Serialize)]
+struct AvailableName {
+    name: String,
+}
+
+#[derive(Deserialize)]
+struct BulkRename {
+    id: i64,
+    name: String,
+}
+
+/// Applied all at once or not at all; renames run before deletes.
+#[derive(Deserialize)]
+struct BulkPasskeyRequest {
+    #[serde(default)]
+    rename: Vec<BulkRename>,
+    #[serde(default)]
+    delete: Vec<i64>,
+}
+
+#[derive(Deserialize)]
+struct ExpiryRequest {
+    /// Days until a temporary passkey stops signing in; `None` makes it permanent.
+    expires_in_days: Option<u32>,
+}
+
+#[derive(Serialize)]
+struct ExpiryResponse {
+    expires_at: Option<String>,
+}
+
+/// `auth::token_key` purposes: redirect (and puzzle) tokens are signed apart from
+/// sessions, so a leaked `/api/login/redirect?token=` URL is never a session cookie.
+const REDIRECT_TOKEN_KEY: &str = "login-redirect";
+const LOGOUT_TOKEN_KEY: &str = "frontchannel-logout";
+
 #[derive(Serialize, Deserialize)]
 struct LoginRedirectClaims {
     iss: String,
     aud: String,
     sub: String,
+    jti: String,
     path: String,
     iat: i64,
     exp: i64,
+    /// App policy the target origin resolved to when the token was issued.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    app: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    scope: Option<String>,
 }
 
 #[derive(Deserialize)]
@@ -91,6 +221,23 @@ struct RedirectCompleteQuery {
     token: String,
 }
 
+/// Single-use proof that `logout/all` sent the browser to this host's
+/// `logout/frontchannel`; `aud` is the host's origin.
+#[derive(Serialize, Deserialize)]
+struct FrontchannelLogoutClaims {
+    iss: String,
+    aud: String,
+    sub: String,
+    jti: String,
+    iat: i64,
+    exp: i64,
+}
+
+#[derive(Deserialize)]
+struct FrontchannelLogoutQuery {
+    token: Option<String>,
+}
+
 #[derive(Deserialize)]
 struct RedirectStartRequest {
     redirect_path: Option<String>,
@@ -104,48 +251,230 @@ pub fn router() -> Router<AppState> {
         .route("/register/complete", post(register_complete))
         .route("/login/begin", post(login_begin))
         .route("/login/complete", post(login_complete))
+        .route("/login/recovery", post(login_recovery))
+        .route("/login/conditional", get(login_conditional))
+        .route("/login/puzzle", get(login_puzzle))
         .route(
             "/login/redirect",
             post(redirect_start).get(redirect_complete),
         )
+        .route("/refresh", post(refresh))
         .route("/logout", post(logout))
+        .route("/logout/all", post(logout_all))
+        .route("/logout/frontchannel", get(logout_frontchannel))
         .route("/passkeys", get(list_passkeys))
+        .route("/passkeys/invite", post(create_invite))
+        .route("/passkeys/invites", get(list_invites).post(create_invites))
+        .route("/passkeys/invites/{id}", delete(revoke_invite))
+        .route("/passkeys/recovery", get(passkey_recovery))
+        .route("/passkeys/bulk", post(bulk_update_passkeys))
+        .route("/passkeys/available-name", get(available_passkey_name))
         .route(
             "/passkeys/{id}",
             patch(rename_passkey).delete(delete_passkey),
         )
+        .route("/passkeys/{id}/expiry", put(set_passkey_expiry))
+        .route("/passkeys/{id}/restore", post(restore_passkey))
 }
 
 // --- Handlers ---
 
-fn request_secure_cookie(headers: &HeaderMap, fallback: bool) -> bool {
+pub fn request_secure_cookie(headers: &HeaderMap, fallback: bool) -> bool {
     let scheme = if fallback { "https" } else { "http" };
     request_origin(headers, scheme).map_or(fallback, |o| o.starts_with("https://"))
 }
 
+pub fn request_cookie_domain<'a>(state: &'a AppState, headers: &HeaderMap) -> Option<&'a str> {
+    let fallback_scheme = request_fallback_scheme(headers, &state.rp_origin);
+    let origin = request_origin(headers, fallback_scheme)?;
+    auth::cookie_domain_for(state, &origin)
+}
+
+/// Open a server-side session and attach its access + refresh cookies, once the
+/// `session` hooks (if any) allow it.
+pub async fn start_session(
+    state: &AppState,
+    jar: CookieJar,
+    user_id: &str,
+    ttl: Duration,
+    secure: bool,
+    domain: Option<&str>,
+    client: &session::ClientInfo<'_>,
+) -> Result<CookieJar, ApiError> {
+    if state.hooks.has(HookEvent::Session) {
+        let user = state
+            .db
+            .get_user(user_id)
+            .await
+            .map_err(|_| ApiError::INTERNAL)?;
+        check_hooks(
+            state,
+            &HookContext {
+                event: HookEvent::Session,
+                user_id,
+                user_name: user.as_ref().map(|u| u.name.as_str()),
+                passkey_name: None,
+                ip: client.ip,
+                country: client.country,
+                user_agent: client.user_agent,
+                origin: client.origin,
+            },
+        )
+        .await?;
+    }
+    issue_session(state, jar, user_id, ttl, secure, domain, client).await
+}
+
+/// `start_session` without the hooks, for break-glass: the way back in when a
+/// hook is what locks everyone out.
+pub async fn issue_session(
+    state: &AppState,
+    jar: CookieJar,
+    user_id: &str,
+    ttl: Duration,
+    secure: bool,
+    domain: Option<&str>,
+    client: &session::ClientInfo<'_>,
+) -> Result<CookieJar, ApiError> {
+    let session = session::create(&state.storage, user_id, ttl, client)
+        .await
+        .map_err(|_| ApiError::INTERNAL)?;
+    let audience = auth::TokenAudience::for_origin(state, client.origin);
+    let sub_account = state
+        .db
+        .sub_account_apps(user_id)
+        .await
+        .map_err(|_| ApiError::INTERNAL)?;
+    let token = auth::create_token(
+        &state.signing_keys,
+        &state.instance_id,
+        user_id,
+        &session.id,
+        audience,
+        sub_account,
+    )
+    .map_err(|_| ApiError::INTERNAL)?;
+    Ok(jar
+        .add(auth::session_cookie(
+            &state.cookie,
+            token,
+            ttl,
+            secure,
+            domain,
+        ))
+        .add(auth::refresh_cookie(
+            &state.cookie,
+            session.refresh_token,
+            ttl,
+            secure,
+            domain,
+        )))
+}
+
+/// Run the `[[hooks]]` for `context.event`; a veto is refused as `hook_denied`.
+pub async fn check_hooks(state: &AppState, context: &HookContext<'_>) -> Result<(), ApiError> {
+    let Err(reason) = state.hooks.run(context).await else {
+        return Ok(());
+    };
+    tracing::warn!(
+        event = ?context.event,
+        user_id = context.user_id,
+        ip = context.ip,
+        reason,
+        "vetoed by hook"
+    );
+    Err(ApiError::HOOK_DENIED)
+}
+
+/// Enforce the per-app policy (if any) and the `redirect` hooks for `origin`; returns
+/// the session lifetime to use. Runs both when a redirect token is issued and when it
+/// is redeemed, so `access_hours` hold at either end.
+pub async fn app_session_ttl(
+    state: &AppState,
+    origin: &str,
+    user_id: &str,
+) -> Result<Duration, ApiError> {
+    if state.hooks.has(HookEvent::Redirect) {
+        let user = state
+            .db
+            .get_user(user_id)
+            .await
+            .map_err(|_| ApiError::INTERNAL)?;
+        check_hooks(
+            state,
+            &HookContext {
+                event: HookEvent::Redirect,
+                user_id,
+                user_name: user.as_ref().map(|u| u.name.as_str()),
+                passkey_name: None,
+                ip: None,
+                country: None,
+                user_agent: None,
+                origin: Some(origin),
+            },
+        )
+        .await?;
+    }
+    let Some(app) = state.apps.get(origin) else {
+        return Ok(auth::SESSION_TTL);
+    };
+    if app.allowed_users.is_some() {
+        let user = state
+            .db
+            .get_user(user_id)
+            .await
+            .map_err(|_| ApiError::INTERNAL)?;
+        if !app.allows(user_id, user.as_ref().map(|u| u.name.as_str())) {
+            tracing::warn!(app = app.name, user_id, "user denied by app policy");
+            return Err(ApiError::APP_ACCESS_DENIED);
+        }
+    }
+    if !app.open_at(OffsetDateTime::now_utc()) {
+        tracing::warn!(app = app.name, user_id, "app outside its access hours");
+        audit::record(
+            state,
+            AuditKind::AccessDenied,
+            AuditEvent {
+                user_id: Some(user_id),
+                detail: Some("outside access hours"),
+                app: Some(&app.origin),
+                ..Default::default()
+            },
+        )
+        .await;
+        return Err(ApiError::APP_CLOSED);
+    }
+    Ok(app.session_ttl.unwrap_or(auth::SESSION_TTL))
+}
+
 fn normalize_redirect_origin(
     state: &AppState,
     origin: Option<&str>,
-) -> Result<Option<String>, StatusCode> {
+) -> Result<Option<String>, ApiError> {
     let Some(origin) = origin else {
         return Ok(None);
     };
-    let normalized = normalize_origin(origin).ok_or(StatusCode::BAD_REQUEST)?;
+    let normalized = normalize_origin(origin).ok_or(ApiError::INVALID_REDIRECT)?;
     if normalized.eq_ignore_ascii_case(&state.rp_origin) {
         return Ok(None);
     }
-    let host = origin_host(&normalized).ok_or(StatusCode::BAD_REQUEST)?;
+    let host = origin_host(&normalized).ok_or(ApiError::INVALID_REDIRECT)?;
     if !state.allowed_hosts.contains(&host) {
-        return Err(StatusCode::BAD_REQUEST);
+        return Err(ApiError::INVALID_REDIRECT);
     }
     Ok(Some(normalized))
 }
 
+/// Where on the target origin to land: path, query and fragment of the page the
+/// login started from, percent-encoded so it is a valid `Location`. Anything that
+/// could leave the origin falls back to `/`.
 fn normalize_redirect_path(path: Option<&str>) -> String {
-    let path = path
-        .map(str::trim)
-        .filter(|p| p.starts_with('/') && !p.starts_with("//") && !p.contains('\\'));
-    path.map_or_else(|| "/".into(), Into::into)
+    let base = Url::parse("http://redirect.invalid/").expect("static base URL");
+    path.map(str::trim)
+        .filter(|p| p.starts_with('/') && !p.starts_with("//") && !p.contains('\\'))
+        .and_then(|p| base.join(p).ok())
+        .filter(|url| url.origin() == base.origin())
+        .map_or_else(|| "/".into(), |url| url[Position::BeforePath..].to_owned())
 }
 
 fn redirect_complete_url(origin: &str, token: &str) -> String {
@@ -157,46 +486,54 @@ fn issue_login_redirect_token(
     user_id: &str,
     origin: &str,
     path: &str,
-) -> Result<String, StatusCode> {
+) -> Result<String, ApiError> {
     let now = OffsetDateTime::now_utc();
-    encode(
-        &Header::default(),
-        &LoginRedirectClaims {
-            iss: state.rp_origin.clone(),
-            aud: origin.to_string(),
-            sub: user_id.to_string(),
-            path: path.to_string(),
-            iat: now.unix_timestamp(),
-            exp: (now + Duration::seconds(60)).unix_timestamp(),
-        },
-        &EncodingKey::from_secret(&state.jwt_secret),
-    )
-    .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)
+    let audience = auth::TokenAudience::for_origin(state, Some(origin));
+    state
+        .signing_keys
+        .encode(
+            Some(REDIRECT_TOKEN_KEY),
+            &LoginRedirectClaims {
+                iss: state.rp_origin.clone(),
+                aud: audience.aud,
+                sub: user_id.to_string(),
+                jti: Uuid::new_v4().to_string(),
+                path: path.to_string(),
+                iat: now.unix_timestamp(),
+                exp: (now + Duration::seconds(60)).unix_timestamp(),
+                app: audience.app,
+                scope: audience.scope,
+            },
+        )
+        .map_err(|_| ApiError::INTERNAL)
 }
 
 async fn register_begin(
     State(state): State<AppState>,
     auth: MaybeAuthUser,
+    jar: CookieJar,
+    headers: HeaderMap,
     Json(req): Json<RegisterBeginRequest>,
-) -> Result<Json<BeginResponse<CreationChallengeResponse>>, StatusCode> {
-    sqlx::query("DELETE FROM auth_challenge WHERE expires_at < datetime('now')")
-        .execute(&state.db)
-        .await
-        .ok();
+) -> Result<(CookieJar, Json<BeginResponse<CreationChallengeResponse>>), ApiError> {
+    let existing = state.db.only_user().await.map_err(|_| ApiError::INTERNAL)?;
 
-    let existing: Option<(String, String)> = sqlx::query_as("SELECT id, name FROM user LIMIT 1")
-        .fetch_optional(&state.db)
-        .await
-        .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?;
-
-    if existing.is_some() && auth.0.is_none() {
-        return Err(StatusCode::UNAUTHORIZED);
+    let (invite_hash, invited) = match (&existing, &auth.0, req.invite_token.as_deref()) {
+        (Some(_), None, Some(token)) => {
+            let (hash, user) = invited_user(&state, token).await?;
+            (Some(hash), Some(user))
+        }
+        (Some(_), None, None) => return Err(ApiError::UNAUTHENTICATED),
+        _ => (None, None),
+    };
+    if existing.is_none() && !auth::bootstrap_token_ok(&state, req.bootstrap_token.as_deref()) {
+        tracing::warn!("first-run registration attempted without a valid bootstrap token");
+        return Err(ApiError::BOOTSTRAP_TOKEN_INVALID);
     }
 
-    let (user_id, user_name, is_new_user) = match existing {
-        Some((id, name)) => (
-            id.parse().map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?,
-            name,
+    let (user_id, user_name, is_new_user) = match invited.or(existing) {
+        Some(user) => (
+            user.id.parse().map_err(|_| ApiError::INTERNAL)?,
+            user.name,
             false,
         ),
         None => {
@@ -205,21 +542,29 @@ async fn register_begin(
                 .as_deref()
                 .map(str::trim)
                 .filter(|n| !n.is_empty())
-                .ok_or(StatusCode::BAD_REQUEST)?;
+                .ok_or(ApiError::NAME_REQUIRED)?;
             (Uuid::new_v4(), name.to_string(), true)
         }
     };
 
+    // Checked up front too, so a taken name fails before the ceremony.
+    if !is_new_user
+        && state
+            .db
+            .passkey_name_taken(&user_id.to_string(), &req.passkey_name)
+            .await
+            .map_err(|_| ApiError::INTERNAL)?
+    {
+        return Err(ApiError::PASSKEY_NAME_TAKEN);
+    }
+
     // Get existing passkeys to exclude
     let existing_passkeys: Vec<Passkey> = if !is_new_user {
-        let rows: Vec<(String,)> = sqlx::query_as("SELECT data FROM passkey WHERE user_id = ?")
-            .bind(user_id.to_string())
-            .fetch_all(&state.db)
+        state
+            .db
+            .user_passkeys(&user_id.to_string())
             .await
-            .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?;
-        rows.into_iter()
-            .filter_map(|(data,)| serde_json::from_str(&data).ok())
-            .collect()
+            .map_err(|_| ApiError::INTERNAL)?
     } else {
         vec![]
     };
@@ -240,8 +585,13 @@ async fn register_begin(
         .start_passkey_registration(user_id, &user_name, &user_name, exclude)
         .map_err(|e| {
             tracing::error!(error = %e, "registration start failed");
-            StatusCode::INTERNAL_SERVER_ERROR
+            ApiError::INTERNAL
         })?;
+    let ccr = if state.attestation.is_some() {
+        request_direct_attestation(ccr)?
+    } else {
+        ccr
+    };
 
     let challenge_id = Uuid::new_v4().to_string();
     let context = RegistrationContext {
@@ -250,127 +600,301 @@ async fn register_begin(
         user_name,
         passkey_name: req.passkey_name,
         is_new_user,
+        invite_hash,
     };
-    let state_json =
-        serde_json::to_string(&context).map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?;
-
-    sqlx::query("INSERT INTO auth_challenge (id, state, kind, expires_at) VALUES (?, ?, 'registration', datetime('now', '+5 minutes'))")
-        .bind(&challenge_id)
-        .bind(&state_json)
-        .execute(&state.db)
+    let state_json = serde_json::to_string(&context).map_err(|_| ApiError::INTERNAL)?;
+    let secure = request_secure_cookie(&headers, state.secure_cookies);
+    let (jar, binding) = auth::bind_challenge(&state.cookie, jar, secure);
+
+    state
+        .storage
+        .insert_challenge(
+            &challenge_id,
+            db::CHALLENGE_REGISTRATION,
+            &binding,
+            &state_json,
+            CHALLENGE_TTL_MINUTES,
+        )
         .await
-        .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?;
+        .map_err(|_| ApiError::INTERNAL)?;
 
-    Ok(Json(BeginResponse {
-        challenge_id,
-        options: ccr,
-    }))
+    Ok((
+        jar,
+        Json(BeginResponse {
+            challenge_id,
+            options: ccr,
+        }),
+    ))
+}
+
+/// The passkey builder always asks for `"none"` attestation, which lets browsers
+/// withhold the AAGUID and certificates an attestation policy needs.
+fn request_direct_attestation(
+    ccr: CreationChallengeResponse,
+) -> Result<CreationChallengeResponse, ApiError> {
+    let mut options = serde_json::to_value(ccr).map_err(|_| ApiError::INTERNAL)?;
+    options["publicKey"]["attestation"] = "direct".into();
+    serde_json::from_value(options).map_err(|_| ApiError::INTERNAL)
 }
 
 async fn register_complete(
     State(state): State<AppState>,
+    ConnectInfo(peer): ConnectInfo<SocketAddr>,
     auth: MaybeAuthUser,
     jar: CookieJar,
     headers: HeaderMap,
     Json(req): Json<RegisterCompleteRequest>,
-) -> Result<(CookieJar, Json<serde_json::Value>), StatusCode> {
-    // Fetch and delete challenge (single-use)
-    let row: Option<(String,)> = sqlx::query_as(
-        "DELETE FROM auth_challenge WHERE id = ? AND kind = 'registration' AND expires_at > datetime('now') RETURNING state",
-    )
-    .bind(&req.challenge_id)
-    .fetch_optional(&state.db)
-    .await
-    .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?;
-
-    let (state_json,) = row.ok_or(StatusCode::BAD_REQUEST)?;
+) -> Result<(CookieJar, Json<serde_json::Value>), ApiError> {
+    let state_json = state
+        .storage
+        .take_challenge(
+            &req.challenge_id,
+            db::CHALLENGE_REGISTRATION,
+            &auth::presented_binding(&state.cookie, &jar),
+        )
+        .await
+        .map_err(|_| ApiError::INTERNAL)?
+        .ok_or(ApiError::CHALLENGE_EXPIRED)?;
     let context: RegistrationContext =
-        serde_json::from_str(&state_json).map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?;
+        serde_json::from_str(&state_json).map_err(|_| ApiError::INTERNAL)?;
 
-    // If not new user, require auth
-    if !context.is_new_user && auth.0.is_none() {
-        return Err(StatusCode::UNAUTHORIZED);
-    }
+    // If not new user, require auth or an invite. The invite is consumed with the
+    // passkey insert, once every check below has passed, so it stays single-use
+    // without a rejected ceremony costing it.
+    let invite_hash = if !context.is_new_user && auth.0.is_none() {
+        Some(
+            context
+                .invite_hash
+                .as_deref()
+                .ok_or(ApiError::UNAUTHENTICATED)?,
+        )
+    } else {
+        None
+    };
 
     let passkey = state
         .webauthn
         .finish_passkey_registration(&req.credential, &context.webauthn_state)
         .map_err(|e| {
             tracing::error!(error = %e, "registration finish failed");
-            StatusCode::BAD_REQUEST
+            ApiError::REGISTRATION_REJECTED
         })?;
+    let client = client_ip(&headers, peer.ip(), &state.trusted_proxies);
+    let country = state.geoip.as_ref().and_then(|g| g.country(client));
+    let user_agent = request_user_agent(&headers);
+    if let Some(policy) = &state.attestation {
+        let attestation_object: &[u8] = req.credential.response.attestation_object.as_ref();
+        match policy.check(attestation_object) {
+            Ok(aaguid) => tracing::info!(%aaguid, "authenticator attestation accepted"),
+            Err(reason) => {
+                tracing::warn!(reason, "authenticator rejected by attestation policy");
+                return Err(ApiError::AUTHENTICATOR_NOT_ALLOWED);
+            }
+        }
+    }
 
-    // Create user if new — atomic guard ensures only one user can ever be created
+    check_hooks(
+        &state,
+        &HookContext {
+            event: HookEvent::Registration,
+            user_id: &context.user_id,
+            user_name: Some(&context.user_name),
+            passkey_name: Some(&context.passkey_name),
+            ip: Some(&client.to_string()),
+            country: country.as_deref(),
+            user_agent: Some(&user_agent),
+            origin: None,
+        },
+    )
+    .await?;
+
+    // Create user if new — atomic guard ensures only one user can ever be created,
+    // and never without its first passkey
     if context.is_new_user {
-        let result = sqlx::query(
-            "INSERT INTO user (id, name) SELECT ?, ? WHERE NOT EXISTS (SELECT 1 FROM user)",
-        )
-        .bind(&context.user_id)
-        .bind(&context.user_name)
-        .execute(&state.db)
-        .await
-        .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?;
-        if result.rows_affected() == 0 {
-            return Err(StatusCode::CONFLICT);
+        let created = state
+            .db
+            .create_only_user_with_passkey(
+                &context.user_id,
+                &context.user_name,
+                &context.passkey_name,
+                &passkey,
+            )
+            .await
+            .map_err(|_| ApiError::INTERNAL)?;
+        if !created {
+            return Err(ApiError::ALREADY_REGISTERED);
+        }
+    } else if let Some(hash) = invite_hash {
+        let consumed = state
+            .db
+            .insert_invited_passkey(&context.user_id, &context.passkey_name, &passkey, hash)
+            .await
+            .map_err(passkey_write_error)?;
+        if !consumed {
+            return Err(ApiError::INVITE_INVALID);
         }
+    } else {
+        state
+            .db
+            .insert_passkey(&context.user_id, &context.passkey_name, &passkey)
+            .await
+            .map_err(passkey_write_error)?;
     }
 
-    // Store passkey
-    let passkey_data =
-        serde_json::to_string(&passkey).map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?;
-    sqlx::query("INSERT INTO passkey (user_id, name, data) VALUES (?, ?, ?)")
-        .bind(&context.user_id)
-        .bind(&context.passkey_name)
-        .bind(&passkey_data)
-        .execute(&state.db)
-        .await
-        .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?;
-
     if context.is_new_user {
-        let token = auth::create_token(&state.jwt_secret, &context.user_id)
-            .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?;
-        let cookie =
-            auth::session_cookie(token, request_secure_cookie(&headers, state.secure_cookies));
-        return Ok((
-            jar.add(cookie),
-            Json(serde_json::json!({ "success": true })),
-        ));
+        let jar = start_session(
+            &state,
+            jar,
+            &context.user_id,
+            auth::SESSION_TTL,
+            request_secure_cookie(&headers, state.secure_cookies),
+            request_cookie_domain(&state, &headers),
+            &session::ClientInfo {
+                user_agent: Some(&user_agent),
+                ip: Some(&client.to_string()),
+                country: country.as_deref(),
+                origin: None,
+            },
+        )
+        .await?;
+        return Ok((jar, Json(serde_json::json!({ "success": true }))));
     }
     Ok((jar, Json(serde_json::json!({ "success": true }))))
 }
 
-async fn login_begin(
+/// The invite's hash and the user it enrolls a passkey for: the owner, or a
+/// sub-account. The invite is only consumed when `register_complete` stores the
+/// passkey, so an abandoned or rejected ceremony can be retried.
+async fn invited_user(state: &AppState, token: &str) -> Result<(String, db::User), ApiError> {
+    let hash = session::hash_token(token);
+    let owner = state
+        .db
+        .invite_owner(&hash)
+        .await
+        .map_err(|_| ApiError::INTERNAL)?
+        .ok_or(ApiError::INVITE_INVALID)?;
+    let user = state
+        .db
+        .get_user(&owner)
+        .await
+        .map_err(|_| ApiError::INTERNAL)?
+        .ok_or(ApiError::INVITE_INVALID)?;
+    Ok((hash, user))
+}
+
+async fn create_invite(
     State(state): State<AppState>,
-    Json(req): Json<LoginBeginRequest>,
-) -> Result<Json<BeginResponse<RequestChallengeResponse>>, StatusCode> {
-    let redirect_origin = normalize_redirect_origin(&state, req.redirect_origin.as_deref())?;
-    let redirect_path = redirect_origin
-        .as_ref()
-        .map(|_| normalize_redirect_path(req.redirect_path.as_deref()));
+    auth: AuthUser,
+) -> Result<Json<InviteResponse>, ApiError> {
+    let token = session::new_refresh_token();
+    let expires_at = state
+        .db
+        .create_invite(
+            &session::hash_token(&token),
+            &auth.user_id,
+            INVITE_TTL_MINUTES,
+        )
+        .await
+        .map_err(|_| ApiError::INTERNAL)?;
+
+    tracing::info!(user_id = auth.user_id, "passkey invite created");
+    Ok(Json(InviteResponse {
+        url: format!("{}/invite?token={token}", state.rp_origin),
+        expires_at,
+    }))
+}
 
-    sqlx::query("DELETE FROM auth_challenge WHERE expires_at < datetime('now')")
-        .execute(&state.db)
+/// Mint `count` single-use invites at once, one per security key to enroll.
+async fn create_invites(
+    State(state): State<AppState>,
+    auth: AuthUser,
+    Json(req): Json<BatchInviteRequest>,
+) -> Result<Json<Vec<BatchInvite>>, ApiError> {
+    if !(1..=MAX_BATCH_INVITES).contains(&req.count) {
+        return Err(ApiError::BAD_REQUEST);
+    }
+    let tokens: Vec<String> = (0..req.count)
+        .map(|_| session::new_refresh_token())
+        .collect();
+    let hashes: Vec<String> = tokens.iter().map(|t| session::hash_token(t)).collect();
+    let invites = state
+        .db
+        .create_invites(&hashes, &auth.user_id, BATCH_INVITE_TTL_MINUTES)
         .await
-        .ok();
+        .map_err(|_| ApiError::INTERNAL)?;
+
+    tracing::info!(
+        user_id = auth.user_id,
+        count = req.count,
+        "passkey invites created"
+    );
+    Ok(Json(
+        invites
+            .into_iter()
+            .zip(tokens)
+            .map(|(invite, token)| BatchInvite {
+                id: invite.id,
+                url: format!("{}/invite?token={token}", state.rp_origin),
+                expires_at: invite.expires_at,
+            })
+            .collect(),
+    ))
+}
 
-    // Get all passkeys
-    let rows: Vec<(String, String)> = sqlx::query_as("SELECT user_id, data FROM passkey")
-        .fetch_all(&state.db)
+/// Outstanding invites; their tokens were only shown when minted.
+async fn list_invites(
+    State(state): State<AppState>,
+    auth: AuthUser,
+) -> Result<Json<Vec<InviteInfo>>, ApiError> {
+    let invites = state
+        .db
+        .list_invites(&auth.user_id)
         .await
-        .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?;
+        .map_err(|_| ApiError::INTERNAL)?;
 
-    if rows.is_empty() {
-        return Err(StatusCode::BAD_REQUEST);
+    Ok(Json(invites))
+}
+
+async fn revoke_invite(
+    State(state): State<AppState>,
+    auth: AuthUser,
+    Path(id): Path<i64>,
+) -> Result<StatusCode, ApiError> {
+    let revoked = state
+        .db
+        .revoke_invite(&auth.user_id, id)
+        .await
+        .map_err(|_| ApiError::INTERNAL)?;
+
+    if !revoked {
+        return Err(ApiError::NOT_FOUND);
     }
+    Ok(StatusCode::NO_CONTENT)
+}
 
-    let user_id = rows[0].0.clone();
-    let passkeys: Vec<Passkey> = rows
+async fn login_begin(
+    State(state): State<AppState>,
+    ConnectInfo(peer): ConnectInfo<SocketAddr>,
+    jar: CookieJar,
+    headers: HeaderMap,
+    Json(req): Json<LoginBeginRequest>,
+) -> Result<(CookieJar, Json<BeginResponse<RequestChallengeResponse>>), ApiError> {
+    let client = client_ip(&headers, peer.ip(), &state.trusted_proxies);
+    let ip = client.to_string();
+    check_login_throttle(&state, &ip).await?;
+    check_login_puzzle(&state, client, &req).await?;
+    let (redirect_origin, redirect_path) = login_redirect_target(&state, &req)?;
+
+    let passkeys: Vec<Passkey> = state
+        .db
+        .all_passkeys()
+        .await
+        .map_err(|_| ApiError::INTERNAL)?
         .into_iter()
-        .filter_map(|(_, data)| serde_json::from_str(&data).ok())
+        .map(|record| record.passkey)
         .collect();
-
     if passkeys.is_empty() {
-        return Err(StatusCode::INTERNAL_SERVER_ERROR);
+        return Err(ApiError::NO_PASSKEYS);
     }
 
     let (rcr, auth_state) = state
@@ -378,122 +902,917 @@ async fn login_begin(
         .start_passkey_authentication(&passkeys)
         .map_err(|e| {
             tracing::error!(error = %e, "authentication start failed");
-            StatusCode::INTERNAL_SERVER_ERROR
+            ApiError::INTERNAL
         })?;
 
-    let challenge_id = Uuid::new_v4().to_string();
-    let context = AuthenticationContext {
-        webauthn_state: auth_state,
-        user_id,
-        redirect_origin,
-        redirect_path,
+    let secure = request_secure_cookie(&headers, state.secure_cookies);
+    let (jar, binding) = auth::bind_challenge(&state.cookie, jar, secure);
+    let challenge_id = store_authentication_challenge(
+        &state,
+        &binding,
+        AuthenticationContext {
+            webauthn_state: AuthenticationState::Passkey(auth_state),
+            redirect_origin,
+            redirect_path,
+            step_up: None,
+        },
+    )
+    .await?;
+
+    Ok((
+        jar,
+        Json(BeginResponse {
+            challenge_id,
+            options: rcr,
+        }),
+    ))
+}
+
+/// Assertion options for conditional mediation (passkey autofill). No credentials are
+/// listed; the user is resolved from the discoverable credential's user handle.
+async fn login_conditional(
+    State(state): State<AppState>,
+    ConnectInfo(peer): ConnectInfo<SocketAddr>,
+    jar: CookieJar,
+    headers: HeaderMap,
+    Query(req): Query<LoginBeginRequest>,
+) -> Result<(CookieJar, Json<BeginResponse<RequestChallengeResponse>>), ApiError> {
+    let client = client_ip(&headers, peer.ip(), &state.trusted_proxies);
+    let ip = client.to_string();
+    check_login_throttle(&state, &ip).await?;
+    check_login_puzzle(&state, client, &req).await?;
+    let (redirect_origin, redirect_path) = login_redirect_target(&state, &req)?;
+
+    let (rcr, auth_state) = state
+        .webauthn
+        .start_discoverable_authentication()
+        .map_err(|e| {
+            tracing::error!(error = %e, "conditional authentication start failed");
+            ApiError::INTERNAL
+        })?;
+
+    let secure = request_secure_cookie(&headers, state.secure_cookies);
+    let (jar, binding) = auth::bind_challenge(&state.cookie, jar, secure);
+    let challenge_id = store_authentication_challenge(
+        &state,
+        &binding,
+        AuthenticationContext {
+            webauthn_state: AuthenticationState::Discoverable(auth_state),
+            redirect_origin,
+            redirect_path,
+            step_up: None,
+        },
+    )
+    .await?;
+
+    Ok((
+        jar,
+        Json(BeginResponse {
+            challenge_id,
+            options: rcr,
+        }),
+    ))
+}
+
+/// Where to send the browser after logging in: back to `path` on this origin, or for
+/// another `origin` straight there when a domain-wide cookie already covers it,
+/// otherwise through a one-time redirect token.
+pub fn post_login_redirect(
+    state: &AppState,
+    user_id: &str,
+    origin: Option<&str>,
+    path: Option<&str>,
+    cookie_domain: Option<&str>,
+) -> Option<String> {
+    let Some(origin) = origin else {
+        return path.map(str::to_owned);
     };
-    let state_json =
-        serde_json::to_string(&context).map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?;
+    let path = path.unwrap_or("/");
+    if cookie_domain.is_some() && auth::cookie_domain_for(state, origin).is_some() {
+        return Some(format!("{origin}{path}"));
+    }
+    issue_login_redirect_token(state, user_id, origin, path)
+        .ok()
+        .map(|t| redirect_complete_url(origin, &t))
+}
 
-    sqlx::query("INSERT INTO auth_challenge (id, state, kind, expires_at) VALUES (?, ?, 'authentication', datetime('now', '+5 minutes'))")
-        .bind(&challenge_id)
-        .bind(&state_json)
-        .execute(&state.db)
+pub fn login_redirect_target(
+    state: &AppState,
+    req: &LoginBeginRequest,
+) -> Result<(Option<String>, Option<String>), ApiError> {
+    let redirect_origin = normalize_redirect_origin(state, req.redirect_origin.as_deref())?;
+    // Same-origin logins keep an explicit path so deep links survive the login page.
+    let redirect_path = (redirect_origin.is_some() || req.redirect_path.is_some())
+        .then(|| normalize_redirect_path(req.redirect_path.as_deref()));
+    Ok((redirect_origin, redirect_path))
+}
+
+async fn store_authentication_challenge(
+    state: &AppState,
+    binding: &str,
+    context: AuthenticationContext,
+) -> Result<String, ApiError> {
+    let challenge_id = Uuid::new_v4().to_string();
+    let state_json = serde_json::to_string(&context).map_err(|_| ApiError::INTERNAL)?;
+
+    state
+        .storage
+        .insert_challenge(
+            &challenge_id,
+            db::CHALLENGE_AUTHENTICATION,
+            binding,
+            &state_json,
+            CHALLENGE_TTL_MINUTES,
+        )
         .await
-        .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?;
+        .map_err(|_| ApiError::INTERNAL)?;
 
-    Ok(Json(BeginResponse {
-        challenge_id,
-        options: rcr,
-    }))
+    Ok(challenge_id)
 }
 
 async fn login_complete(
     State(state): State<AppState>,
+    ConnectInfo(peer): ConnectInfo<SocketAddr>,
     jar: CookieJar,
     headers: HeaderMap,
     Json(req): Json<LoginCompleteRequest>,
-) -> Result<(CookieJar, Json<serde_json::Value>), StatusCode> {
-    // Fetch and delete challenge (single-use)
-    let row: Option<(String,)> = sqlx::query_as(
-        "DELETE FROM auth_challenge WHERE id = ? AND kind = 'authentication' AND expires_at > datetime('now') RETURNING state",
-    )
-    .bind(&req.challenge_id)
-    .fetch_optional(&state.db)
-    .await
-    .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?;
-
-    let (state_json,) = row.ok_or(StatusCode::BAD_REQUEST)?;
+) -> Result<(CookieJar, Json<serde_json::Value>), ApiError> {
+    let binding = auth::presented_binding(&state.cookie, &jar);
+    let state_json = state
+        .storage
+        .take_challenge(&req.challenge_id, db::CHALLENGE_AUTHENTICATION, &binding)
+        .await
+        .map_err(|_| ApiError::INTERNAL)?
+        .ok_or(ApiError::CHALLENGE_EXPIRED)?;
     let context: AuthenticationContext =
-        serde_json::from_str(&state_json).map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?;
-
-    let auth_result = state
-        .webauthn
-        .finish_passkey_authentication(&req.credential, &context.webauthn_state)
-        .map_err(|e| {
+        serde_json::from_str(&state_json).map_err(|_| ApiError::INTERNAL)?;
+
+    let client = client_ip(&headers, peer.ip(), &state.trusted_proxies);
+    let ip = client.to_string();
+    check_login_throttle(&state, &ip).await?;
+    let country = state.geoip.as_ref().and_then(|g| g.country(client));
+    let user_agent = request_user_agent(&headers);
+
+    let auth_result = match context.webauthn_state {
+        AuthenticationState::Passkey(auth_state) => state
+            .webauthn
+            .finish_passkey_authentication(&req.credential, &auth_state),
+        AuthenticationState::Discoverable(auth_state) => {
+            let (user_handle, _) = state
+                .webauthn
+                .identify_discoverable_authentication(&req.credential)
+                .map_err(|_| ApiError::PASSKEY_REJECTED)?;
+            let keys: Vec<DiscoverableKey> = state
+                .db
+                .user_passkeys(&user_handle.to_string())
+                .await
+                .map_err(|_| ApiError::INTERNAL)?
+                .iter()
+                .map(DiscoverableKey::from)
+                .collect();
+            state
+                .webauthn
+                .finish_discoverable_authentication(&req.credential, auth_state, &keys)
+        }
+        AuthenticationState::RecoveryCode => return Err(ApiError::CHALLENGE_EXPIRED),
+    };
+    let auth_result = match auth_result {
+        Ok(auth_result) => auth_result,
+        Err(e) => {
+            if matches!(e, WebauthnError::CredentialPossibleCompromise) {
+                tracing::warn!(credential_id = %req.credential.id, "passkey sign count regressed");
+                state.notifier.send(SecurityEvent::SignCountRegression {
+                    credential_id: req.credential.id.clone(),
+                });
+            }
             tracing::error!(error = %e, "authentication finish failed");
-            StatusCode::UNAUTHORIZED
-        })?;
+            audit::record(
+                &state,
+                AuditKind::LoginFailed,
+                AuditEvent {
+                    ip: Some(&ip),
+                    country: country.as_deref(),
+                    user_agent: Some(&user_agent),
+                    detail: Some(&e.to_string()),
+                    ..Default::default()
+                },
+            )
+            .await;
+            return Err(ApiError::PASSKEY_REJECTED);
+        }
+    };
 
-    // Update the authenticated passkey: persist credential state (counter, backup flags) and last_used
-    let rows: Vec<(i64, String)> = sqlx::query_as("SELECT id, data FROM passkey WHERE user_id = ?")
-        .bind(&context.user_id)
-        .fetch_all(&state.db)
+    // Resolve the owner from the credential that actually signed.
+    let mut record = state
+        .db
+        .credential_passkey(auth_result.cred_id())
+        .await
+        .map_err(|_| ApiError::INTERNAL)?
+        .ok_or(ApiError::PASSKEY_REJECTED)?;
+    let changed = record
+        .passkey
+        .update_credential(&auth_result)
+        .ok_or(ApiError::PASSKEY_REJECTED)?;
+    let aaguid = record.aaguid();
+    let (pk_id, user_id, passkey_name) = (record.id, record.user_id, record.name);
+    let expired = state
+        .db
+        .passkey_expired(pk_id, state.passkeys.max_age_days)
         .await
-        .unwrap_or_default();
-    for (pk_id, data) in rows {
-        if let Ok(mut pk) = serde_json::from_str::<Passkey>(&data)
-            && let Some(changed) = pk.update_credential(&auth_result)
+        .map_err(|_| ApiError::INTERNAL)?;
+    if expired {
+        tracing::warn!(user_id, passkey = passkey_name, "expired passkey rejected");
+        audit::record(
+            &state,
+            AuditKind::LoginFailed,
+            AuditEvent {
+                user_id: Some(&user_id),
+                ip: Some(&ip),
+                country: country.as_deref(),
+                user_agent: Some(&user_agent),
+                detail: Some(&format!("passkey expired: {passkey_name}")),
+                app: context.redirect_origin.as_deref(),
+            },
+        )
+        .await;
+        return Err(ApiError::PASSKEY_EXPIRED);
+    }
+    check_login_country(&state, country.as_deref(), &user_id, &ip, Some(&user_agent)).await?;
+    let sibling_origin = sibling_login_origin(&state, &headers);
+    check_login_policy(
+        &state,
+        policy::Request {
+            ip: client,
+            app: &state.rp_origin,
+            method: LoginMethod::Passkey,
+            aaguid,
+        },
+        context.redirect_origin.as_deref(),
+        sibling_origin.as_deref(),
+        &user_id,
+        country.as_deref(),
+        Some(&user_agent),
+    )
+    .await?;
+    let ttl = login_session_ttl(
+        &state,
+        &user_id,
+        context.redirect_origin.as_deref(),
+        sibling_origin.as_deref(),
+    )
+    .await?;
+
+    // Persist credential state (counter, backup flags) and usage stats
+    state
+        .db
+        .record_passkey_use(pk_id, changed.then_some(&record.passkey), &ip, &user_agent)
+        .await
+        .ok();
+
+    let signals = LoginSignals::new(
+        country.clone(),
+        state.asn.as_ref().and_then(|a| a.asn(client)),
+        &user_agent,
+    );
+    // Also asked after a step-up, so a second passkey can't get past a deny.
+    let verdict = check_policy_plugin(
+        &state,
+        &PluginContext {
+            user_id: &user_id,
+            passkey: &passkey_name,
+            ip: &ip,
+            country: country.as_deref(),
+            asn: signals.asn,
+            device: &signals.device,
+            user_agent: &user_agent,
+            origin: context.redirect_origin.as_deref(),
+        },
+    )
+    .await?;
+    match &context.step_up {
+        Some(step_up)
+            if step_up.user_id != user_id || step_up.credential == *auth_result.cred_id() =>
         {
-            let query = if changed {
-                let updated_data =
-                    serde_json::to_string(&pk).map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?;
-                sqlx::query("UPDATE passkey SET data = ?, last_used = datetime('now') WHERE id = ?")
-                    .bind(updated_data)
-                    .bind(pk_id)
-            } else {
-                sqlx::query("UPDATE passkey SET last_used = datetime('now') WHERE id = ?")
-                    .bind(pk_id)
-            };
-            query.execute(&state.db).await.ok();
-            break;
+            return Err(ApiError::PASSKEY_REJECTED);
+        }
+        Some(_) => {}
+        None => {
+            let anomalous = check_login_anomaly(
+                &state,
+                &user_id,
+                &signals,
+                &ip,
+                &user_agent,
+                context.redirect_origin.as_deref(),
+            )
+            .await?;
+            if !anomalous && verdict == Verdict::StepUp {
+                tracing::info!(user_id, ip, "policy plugin asked for step-up");
+            }
+            if anomalous || verdict == Verdict::StepUp {
+                let step_up = start_step_up(
+                    &state,
+                    &binding,
+                    StepUp {
+                        user_id,
+                        credential: auth_result.cred_id().clone(),
+                    },
+                    context.redirect_origin.as_deref(),
+                    context.redirect_path.as_deref(),
+                )
+                .await?;
+                return Ok((jar, Json(step_up)));
+            }
         }
     }
 
-    // Issue JWT
-    let secure_cookie = request_secure_cookie(&headers, state.secure_cookies);
-    let token = auth::create_token(&state.jwt_secret, &context.user_id)
-        .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?;
-    let cookie = auth::session_cookie(token, secure_cookie);
+    finish_login(
+        &state,
+        jar,
+        &headers,
+        VerifiedLogin {
+            user_id: &user_id,
+            factor: &passkey_name,
+            ip: &ip,
+            country: country.as_deref(),
+            user_agent: &user_agent,
+            signals: &signals,
+            redirect_origin: context.redirect_origin.as_deref(),
+            redirect_path: context.redirect_path.as_deref(),
+            sibling_origin: sibling_origin.as_deref(),
+            ttl,
+        },
+    )
+    .await
+}
 
-    let user_name: Option<(String,)> = sqlx::query_as("SELECT name FROM user WHERE id = ?")
-        .bind(&context.user_id)
-        .fetch_optional(&state.db)
+/// Answer a step-up with the token from the printed recovery URL, when the user has
+/// no other passkey to sign it with. The code stays valid.
+async fn login_recovery(
+    State(state): State<AppState>,
+    ConnectInfo(peer): ConnectInfo<SocketAddr>,
+    jar: CookieJar,
+    headers: HeaderMap,
+    Json(req): Json<LoginRecoveryRequest>,
+) -> Result<(CookieJar, Json<serde_json::Value>), ApiError> {
+    let binding = auth::presented_binding(&state.cookie, &jar);
+    let state_json = state
+        .storage
+        .take_challenge(&req.challenge_id, db::CHALLENGE_AUTHENTICATION, &binding)
         .await
-        .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?;
+        .map_err(|_| ApiError::INTERNAL)?
+        .ok_or(ApiError::CHALLENGE_EXPIRED)?;
+    let context: AuthenticationContext =
+        serde_json::from_str(&state_json).map_err(|_| ApiError::INTERNAL)?;
+    let (AuthenticationState::RecoveryCode, Some(step_up)) =
+        (&context.webauthn_state, &context.step_up)
+    else {
+        return Err(ApiError::CHALLENGE_EXPIRED);
+    };
+    let user_id = step_up.user_id.as_str();
+
+    let client = client_ip(&headers, peer.ip(), &state.trusted_proxies);
+    let ip = client.to_string();
+    check_login_throttle(&state, &ip).await?;
+    let country = state.geoip.as_ref().and_then(|g| g.country(client));
+    let user_agent = request_user_agent(&headers);
 
-    let redirect_url = context.redirect_origin.as_deref().and_then(|origin| {
-        let path = context.redirect_path.as_deref().unwrap_or("/");
-        issue_login_redirect_token(&state, &context.user_id, origin, path)
-            .ok()
-            .map(|t| redirect_complete_url(origin, &t))
+    let owner = state
+        .db
+        .recovery_code_owner(&session::hash_token(&req.token))
+        .await
+        .map_err(|_| ApiError::INTERNAL)?;
+    if state.recovery.is_none() || owner.as_deref() != Some(user_id) {
+        tracing::warn!(user_id, ip, "step-up recovery code rejected");
+        audit::record(
+            &state,
+            AuditKind::LoginFailed,
+            AuditEvent {
+                user_id: Some(user_id),
+                ip: Some(&ip),
+                country: country.as_deref(),
+                user_agent: Some(&user_agent),
+                detail: Some("step-up recovery code rejected"),
+                app: context.redirect_origin.as_deref(),
+            },
+        )
+        .await;
+        return Err(ApiError::RECOVERY_CODE_INVALID);
+    }
+    // Whatever the checks below decide, whoever presented the code holds it.
+    state.notifier.send(SecurityEvent::RecoveryCodeUsed {
+        user_agent: user_agent.clone(),
+        ip: ip.clone(),
     });
 
+    check_login_country(&state, country.as_deref(), user_id, &ip, Some(&user_agent)).await?;
+    // A recovery code is no authenticator; rules about one see the passkey that signed first.
+    let first_aaguid = state
+        .db
+        .credential_passkey(&step_up.credential)
+        .await
+        .map_err(|_| ApiError::INTERNAL)?
+        .and_then(|record| record.aaguid());
+    let sibling_origin = sibling_login_origin(&state, &headers);
+    check_login_policy(
+        &state,
+        policy::Request {
+            ip: client,
+            app: &state.rp_origin,
+            method: LoginMethod::Passkey,
+            aaguid: first_aaguid,
+        },
+        context.redirect_origin.as_deref(),
+        sibling_origin.as_deref(),
+        user_id,
+        country.as_deref(),
+        Some(&user_agent),
+    )
+    .await?;
+    let ttl = login_session_ttl(
+        &state,
+        user_id,
+        context.redirect_origin.as_deref(),
+        sibling_origin.as_deref(),
+    )
+    .await?;
+    let signals = LoginSignals::new(
+        country.clone(),
+        state.asn.as_ref().and_then(|a| a.asn(client)),
+        &user_agent,
+    );
+
+    finish_login(
+        &state,
+        jar,
+        &headers,
+        VerifiedLogin {
+            user_id,
+            factor: "recovery code",
+            ip: &ip,
+            country: country.as_deref(),
+            user_agent: &user_agent,
+            signals: &signals,
+            redirect_origin: context.redirect_origin.as_deref(),
+            redirect_path: context.redirect_path.as_deref(),
+            sibling_origin: sibling_origin.as_deref(),
+            ttl,
+        },
+    )
+    .await
+}
+
+/// Check the app a login is for (its users, access hours and `redirect` hooks)
+/// before anything about the login is recorded; returns the session lifetime.
+async fn login_session_ttl(
+    state: &AppState,
+    user_id: &str,
+    redirect_origin: Option<&str>,
+    sibling_origin: Option<&str>,
+) -> Result<Duration, ApiError> {
+    if let Some(origin) = redirect_origin {
+        app_session_ttl(state, origin, user_id).await?;
+    }
+    match sibling_origin {
+        Some(origin) => app_session_ttl(state, origin, user_id).await,
+        None => Ok(auth::SESSION_TTL),
+    }
+}
+
+/// A login that passed every check, step-up included.
+struct VerifiedLogin<'a> {
+    user_id: &'a str,
+    /// What signed it: the passkey's name, or "recovery code".
+    factor: &'a str,
+    ip: &'a str,
+    country: Option<&'a str>,
+    user_agent: &'a str,
+    signals: &'a LoginSignals,
+    redirect_origin: Option<&'a str>,
+    redirect_path: Option<&'a str>,
+    sibling_origin: Option<&'a str>,
+    ttl: Duration,
+}
+
+/// Record a verified login (login context, device, audit) and issue its session.
+async fn finish_login(
+    state: &AppState,
+    jar: CookieJar,
+    headers: &HeaderMap,
+    login: VerifiedLogin<'_>,
+) -> Result<(CookieJar, Json<serde_json::Value>), ApiError> {
+    let user_id = login.user_id;
+    if let Err(error) = anomaly::remember(&state.db, user_id, login.signals).await {
+        tracing::warn!(error = %error, "failed to record login context");
+    }
+
+    record_device(state, user_id, login.factor, login.user_agent, login.ip).await;
+    audit::record(
+        state,
+        AuditKind::Login,
+        AuditEvent {
+            user_id: Some(user_id),
+            ip: Some(login.ip),
+            country: login.country,
+            user_agent: Some(login.user_agent),
+            detail: Some(login.factor),
+            app: login.redirect_origin,
+        },
+    )
+    .await;
+
+    // Issue session
+    let secure_cookie = request_secure_cookie(headers, state.secure_cookies);
+    let cookie_domain = request_cookie_domain(state, headers);
+    let jar = start_session(
+        state,
+        jar,
+        user_id,
+        login.ttl,
+        secure_cookie,
+        cookie_domain,
+        &session::ClientInfo {
+            user_agent: Some(login.user_agent),
+            ip: Some(login.ip),
+            country: login.country,
+            origin: login.sibling_origin,
+        },
+    )
+    .await?;
+    if let Some(origin) = login.sibling_origin {
+        connected_app::record(&state.db, user_id, origin).await;
+        usage::record_redirect(&state.db, origin).await;
+    }
+
+    let user = state
+        .db
+        .get_user(user_id)
+        .await
+        .map_err(|_| ApiError::INTERNAL)?;
+
+    let redirect_url = post_login_redirect(
+        state,
+        user_id,
+        login.redirect_origin,
+        login.redirect_path,
+        cookie_domain,
+    );
+
     Ok((
-        jar.add(cookie),
+        jar,
         Json(serde_json::json!({
             "success": true,
-            "user_name": user_name.map(|u| u.0),
+            "user_name": user.map(|u| u.name),
             "redirect_url": redirect_url,
         })),
     ))
 }
 
+/// The related origin (other than rp_origin) a login ran on directly. Its session
+/// belongs to that app, as if it had arrived through a redirect token.
+fn sibling_login_origin(state: &AppState, headers: &HeaderMap) -> Option<String> {
+    let fallback_scheme = request_fallback_scheme(headers, &state.rp_origin);
+    let origin = request_origin(headers, fallback_scheme)?;
+    (!origin.eq_ignore_ascii_case(&state.rp_origin)
+        && is_related_origin(&state.related_origins, &origin))
+    .then_some(origin)
+}
+
+/// Score the login against the user's history. Unusual ones are audited and, with
+/// `login_anomaly.step_up`, need a fresh challenge the client must also sign.
+async fn check_login_anomaly(
+    state: &AppState,
+    user_id: &str,
+    signals: &LoginSignals,
+    ip: &str,
+    user_agent: &str,
+    redirect_origin: Option<&str>,
+) -> Result<bool, ApiError> {
+    let assessment = anomaly::assess(&state.db, user_id, signals, state.login_anomaly.min_logins)
+        .await
+        .map_err(|_| ApiError::INTERNAL)?;
+    let Some(assessment) = assessment.filter(Assessment::is_anomalous) else {
+        return Ok(false);
+    };
+    let detail = assessment.describe();
+    tracing::warn!(user_id, ip, detail, "anomalous login");
+    audit::record(
+        state,
+        AuditKind::LoginAnomaly,
+        AuditEvent {
+            user_id: Some(user_id),
+            ip: Some(ip),
+            country: signals.country.as_deref(),
+            user_agent: Some(user_agent),
+            detail: Some(&detail),
+            app: redirect_origin,
+        },
+    )
+    .await;
+    Ok(state.login_anomaly.step_up)
+}
+
+/// Ask for a second assertion before the login completes, from one of the user's
+/// other passkeys: `{step_up}`. With no other passkey, the recovery code answers
+/// it instead: `{step_up_recovery}`. Without either, the login is refused.
+async fn start_step_up(
+    state: &AppState,
+    binding: &str,
+    step_up: StepUp,
+    redirect_origin: Option<&str>,
+    redirect_path: Option<&str>,
+) -> Result<serde_json::Value, ApiError> {
+    let others: Vec<Passkey> = state
+        .db
+        .user_passkeys(&step_up.user_id)
+        .await
+        .map_err(|_| ApiError::INTERNAL)?
+        .into_iter()
+        .filter(|p| *p.cred_id() != step_up.credential)
+        .collect();
+    let (options, webauthn_state) = if others.is_empty() {
+        let has_code = state.recovery.is_some()
+            && state
+                .db
+                .has_recovery_code(&step_up.user_id)
+                .await
+                .map_err(|_| ApiError::INTERNAL)?;
+        if !has_code {
+            tracing::warn!(
+                user_id = step_up.user_id,
+                "step-up needs another passkey or a recovery code; the user has neither"
+            );
+            return Err(ApiError::STEP_UP_UNAVAILABLE);
+        }
+        (None, AuthenticationState::RecoveryCode)
+    } else {
+        let (rcr, auth_state) = state
+            .webauthn
+            .start_passkey_authentication(&others)
+            .map_err(|e| {
+                tracing::error!(error = %e, "step-up authentication start failed");
+                ApiError::INTERNAL
+            })?;
+        (Some(rcr), AuthenticationState::Passkey(auth_state))
+    };
+    let challenge_id = store_authentication_challenge(
+        state,
+        binding,
+        AuthenticationContext {
+            webauthn_state,
+            redirect_origin: redirect_origin.map(str::to_owned),
+            redirect_path: redirect_path.map(str::to_owned),
+            step_up: Some(step_up),
+        },
+    )
+    .await?;
+    Ok(match options {
+        Some(options) => serde_json::json!({
+            "step_up": BeginResponse { challenge_id, options },
+        }),
+        None => serde_json::json!({ "step_up_recovery": { "challenge_id": challenge_id } }),
+    })
+}
+
+/// `[policy_plugin]`'s verdict on a passkey sign-in. A deny is audited and refused
+/// here; a step-up is left to the caller.
+#[cfg(feature = "policy-plugin")]
+async fn check_policy_plugin(
+    state: &AppState,
+    context: &PluginContext<'_>,
+) -> Result<Verdict, ApiError> {
+    let Some(plugin) = state.policy_plugin.clone() else {
+        return Ok(Verdict::Allow);
+    };
+    let verdict = plugin.evaluate(context).await;
+    if verdict != Verdict::Deny {
+        return Ok(verdict);
+    }
+    tracing::warn!(
+        user_id = context.user_id,
+        ip = context.ip,
+        "login denied by policy plugin"
+    );
+    audit::record(
+        state,
+        AuditKind::LoginFailed,
+        AuditEvent {
+            user_id: Some(context.user_id),
+            ip: Some(context.ip),
+            country: context.country,
+            user_agent: Some(context.user_agent),
+            detail: Some("policy plugin denied"),
+            app: context.origin,
+        },
+    )
+    .await;
+    Err(ApiError::POLICY_DENIED)
+}
+
+#[cfg(not(feature = "policy-plugin"))]
+async fn check_policy_plugin(
+    _state: &AppState,
+    _context: &PluginContext<'_>,
+) -> Result<Verdict, ApiError> {
+    Ok(Verdict::Allow)
+}
+
+/// A proof-of-work puzzle for this client, which `login_begin` then wants solved.
+async fn login_puzzle(
+    State(state): State<AppState>,
+    ConnectInfo(peer): ConnectInfo<SocketAddr>,
+    headers: HeaderMap,
+) -> Result<Json<Puzzle>, ApiError> {
+    let puzzle = state.login_puzzle.as_deref().ok_or(ApiError::NOT_FOUND)?;
+    let ip = client_ip(&headers, peer.ip(), &state.trusted_proxies).to_string();
+    puzzle
+        .issue(&state.signing_keys, &ip)
+        .map(Json)
+        .map_err(|_| ApiError::INTERNAL)
+}
+
+/// With `[login_puzzle]`, start a ceremony (and store its challenge) only for exempt
+/// networks or a fresh solution. Each solution is burned like a redirect token.
+async fn check_login_puzzle(
+    state: &AppState,
+    client: IpAddr,
+    req: &LoginBeginRequest,
+) -> Result<(), ApiError> {
+    let Some(puzzle) = state
+        .login_puzzle
+        .as_deref()
+        .filter(|puzzle| puzzle.required(client))
+    else {
+        return Ok(());
+    };
+    let (Some(token), Some(nonce)) = (&req.puzzle, &req.nonce) else {
+        return Err(ApiError::LOGIN_PUZZLE_REQUIRED);
+    };
+    let claims = puzzle
+        .check(&state.signing_keys, &client.to_string(), token, nonce)
+        .ok_or(ApiError::LOGIN_PUZZLE_REQUIRED)?;
+    let first_use = state
+        .storage
+        .burn_redirect_token(&claims.jti, claims.exp)
+        .await
+        .map_err(|_| ApiError::INTERNAL)?;
+    if !first_use {
+        tracing::warn!(jti = claims.jti, "login puzzle solution replayed");
+        return Err(ApiError::LOGIN_PUZZLE_REQUIRED);
+    }
+    Ok(())
+}
+
+/// With `[login_throttle]`, refuse a client that failed too often lately and tell
+/// it when to come back. Refused attempts aren't failures, so waiting always works.
+pub async fn check_login_throttle(state: &AppState, ip: &str) -> Result<(), ApiError> {
+    let Some(throttle) = state.login_throttle else {
+        return Ok(());
+    };
+    let retry_after = audit::throttled_for(&state.db, ip, throttle)
+        .await
+        .map_err(|_| ApiError::INTERNAL)?;
+    match retry_after {
+        Some(seconds) => {
+            tracing::warn!(ip, retry_after = seconds, "login throttled");
+            Err(ApiError::LOGIN_THROTTLED.retry_after(seconds))
+        }
+        None => Ok(()),
+    }
+}
+
+/// Refuse logins from `deny_login_countries`, auditing the attempt.
+pub async fn check_login_country(
+    state: &AppState,
+    country: Option<&str>,
+    user_id: &str,
+    ip: &str,
+    user_agent: Option<&str>,
+) -> Result<(), ApiError> {
+    let Some(country) = country.filter(|c| state.deny_login_countries.contains(*c)) else {
+        return Ok(());
+    };
+    tracing::warn!(user_id, ip, country, "login denied by country policy");
+    audit::record(
+        state,
+        AuditKind::LoginFailed,
+        AuditEvent {
+            user_id: Some(user_id),
+            ip: Some(ip),
+            country: Some(country),
+            user_agent,
+            detail: Some("country denied"),
+            ..Default::default()
+        },
+    )
+    .await;
+    Err(ApiError::COUNTRY_DENIED)
+}
+
+/// `check_access_policy` for every app a login's session is for: the redirect target
+/// and the sibling origin the cookie is minted on, or `request.app` with neither.
+async fn check_login_policy(
+    state: &AppState,
+    request: policy::Request<'_>,
+    redirect_origin: Option<&str>,
+    sibling_origin: Option<&str>,
+    user_id: &str,
+    country: Option<&str>,
+    user_agent: Option<&str>,
+) -> Result<(), ApiError> {
+    if redirect_origin.is_none() && sibling_origin.is_none() {
+        return check_access_policy(state, &request, user_id, country, user_agent).await;
+    }
+    for app in [redirect_origin, sibling_origin].into_iter().flatten() {
+        let request = policy::Request { app, ..request };
+        check_access_policy(state, &request, user_id, country, user_agent).await?;
+    }
+    Ok(())
+}
+
+/// Refuse a session `[[policies]]` deny, or one for an app outside a sub-account's
+/// list, auditing the attempt under the rule's name.
+pub async fn check_access_policy(
+    state: &AppState,
+    request: &policy::Request<'_>,
+    user_id: &str,
+    country: Option<&str>,
+    user_agent: Option<&str>,
+) -> Result<(), ApiError> {
+    let allowed_apps = state
+        .db
+        .sub_account_apps(user_id)
+        .await
+        .map_err(|_| ApiError::INTERNAL)?;
+    let rule = if policy::app_allowed(allowed_apps.as_deref(), request.app) {
+        match state.policies.denied_by(request, OffsetDateTime::now_utc()) {
+            Some(rule) => rule,
+            None => return Ok(()),
+        }
+    } else {
+        policy::SUB_ACCOUNT_RULE
+    };
+    let ip = request.ip.to_string();
+    tracing::warn!(
+        user_id,
+        ip,
+        app = request.app,
+        rule,
+        "login denied by policy"
+    );
+    let app = (!request.app.eq_ignore_ascii_case(&state.rp_origin)).then_some(request.app);
+    audit::record(
+        state,
+        AuditKind::LoginFailed,
+        AuditEvent {
+            user_id: Some(user_id),
+            ip: Some(&ip),
+            country,
+            user_agent,
+            detail: Some(&format!("policy denied: {rule}")),
+            app,
+        },
+    )
+    .await;
+    Err(ApiError::POLICY_DENIED)
+}
+
+/// Remember the device for this user and alert when an unseen one shows up after the first.
+async fn record_device(state: &AppState, user_id: &str, passkey: &str, user_agent: &str, ip: &str) {
+    let inserted = state
+        .db
+        .remember_device(user_id, user_agent)
+        .await
+        .unwrap_or(false);
+    if !inserted {
+        return;
+    }
+    let known = state.db.device_count(user_id).await.unwrap_or(0);
+    if known > 1 {
+        tracing::warn!(
+            passkey,
+            user_agent,
+            ip,
+            "login from never-before-seen device"
+        );
+        state.notifier.send(SecurityEvent::NewDevice {
+            passkey: passkey.to_string(),
+            user_agent: user_agent.to_string(),
+            ip: ip.to_string(),
+        });
+    }
+}
+
 async fn redirect_start(
     State(state): State<AppState>,
     auth: AuthUser,
     Json(req): Json<RedirectStartRequest>,
-) -> Result<Json<serde_json::Value>, StatusCode> {
+) -> Result<Json<serde_json::Value>, ApiError> {
     // QR login links should always use the configured RP origin as the canonical host.
     // If we later want to support minting QR links for other hosts, reintroduce strict
     // validation (similar to login_begin/login_complete).
     let target_origin = state.rp_origin.clone();
+    app_session_ttl(&state, &target_origin, &auth.user_id).await?;
     let target_path = normalize_redirect_path(req.redirect_path.as_deref());
     let token = issue_login_redirect_token(&state, &auth.user_id, &target_origin, &target_path)?;
 
@@ -504,133 +1823,512 @@ async fn redirect_start(
 
 async fn redirect_complete(
     State(state): State<AppState>,
+    ConnectInfo(peer): ConnectInfo<SocketAddr>,
     jar: CookieJar,
     Query(query): Query<RedirectCompleteQuery>,
     headers: HeaderMap,
-) -> Result<(CookieJar, Redirect), StatusCode> {
+) -> Result<(CookieJar, Redirect), ApiError> {
+    let fallback_scheme = request_fallback_scheme(&headers, &state.rp_origin);
+    let origin = request_origin(&headers, fallback_scheme).ok_or(ApiError::BAD_REQUEST)?;
+    if !origin_host(&origin).is_some_and(|h| state.allowed_hosts.contains(&h)) {
+        return Err(ApiError::REDIRECT_TOKEN_INVALID);
+    }
+    let expected = auth::TokenAudience::for_origin(&state, Some(&origin));
     let mut validation = Validation::default();
-    validation.validate_aud = false;
+    // Single-use: the burned jti is only remembered for so long past `exp`.
+    validation.leeway = 0;
+    validation.set_issuer(&[&state.rp_origin]);
+    validation.set_audience(&[&expected.aud]);
+    validation.set_required_spec_claims(&["exp", "iss", "aud", "sub"]);
+
+    let claims = state
+        .signing_keys
+        .decode::<LoginRedirectClaims>(Some(REDIRECT_TOKEN_KEY), &query.token, &validation)
+        .map_err(|_| ApiError::REDIRECT_TOKEN_INVALID)?
+        .claims;
+
+    // An app added, renamed or removed since issue changes what the token grants.
+    if claims.app != expected.app {
+        return Err(ApiError::REDIRECT_TOKEN_INVALID);
+    }
 
-    let claims = decode::<LoginRedirectClaims>(
-        &query.token,
-        &DecodingKey::from_secret(&state.jwt_secret),
-        &validation,
+    // Redirect tokens are single-use: burn the jti until the token would have expired anyway.
+    let first_use = state
+        .storage
+        .burn_redirect_token(&claims.jti, claims.exp)
+        .await
+        .map_err(|_| ApiError::INTERNAL)?;
+    if !first_use {
+        tracing::warn!(jti = claims.jti, "login redirect token replayed");
+        return Err(ApiError::REDIRECT_TOKEN_INVALID);
+    }
+
+    let client = client_ip(&headers, peer.ip(), &state.trusted_proxies);
+    let ip = client.to_string();
+    let country = state.geoip.as_ref().and_then(|g| g.country(client));
+    let user_agent = request_user_agent(&headers);
+    check_login_country(
+        &state,
+        country.as_deref(),
+        &claims.sub,
+        &ip,
+        Some(&user_agent),
+    )
+    .await?;
+    check_access_policy(
+        &state,
+        &policy::Request {
+            ip: client,
+            app: &claims.aud,
+            method: LoginMethod::Redirect,
+            aaguid: None,
+        },
+        &claims.sub,
+        country.as_deref(),
+        Some(&user_agent),
+    )
+    .await?;
+
+    let ttl = app_session_ttl(&state, &claims.aud, &claims.sub).await?;
+    let jar = start_session(
+        &state,
+        jar,
+        &claims.sub,
+        ttl,
+        origin.starts_with("https://"),
+        auth::cookie_domain_for(&state, &origin),
+        &session::ClientInfo {
+            user_agent: Some(&user_agent),
+            ip: Some(&ip),
+            country: country.as_deref(),
+            origin: Some(&origin),
+        },
     )
-    .map_err(|_| StatusCode::UNAUTHORIZED)?
-    .claims;
+    .await?;
+    connected_app::record(&state.db, &claims.sub, &origin).await;
+    usage::record_redirect(&state.db, &origin).await;
 
-    if !claims.iss.eq_ignore_ascii_case(&state.rp_origin) {
-        return Err(StatusCode::UNAUTHORIZED);
-    }
+    Ok((
+        jar,
+        Redirect::to(&normalize_redirect_path(Some(&claims.path))),
+    ))
+}
 
+async fn refresh(
+    State(state): State<AppState>,
+    jar: CookieJar,
+    headers: HeaderMap,
+) -> Result<CookieJar, ApiError> {
+    let presented = jar
+        .get(&state.cookie.refresh_name())
+        .map(|c| c.value().to_owned())
+        .ok_or(ApiError::SESSION_EXPIRED)?;
+    let rotated = session::rotate(&state.storage, &presented)
+        .await
+        .map_err(|_| ApiError::INTERNAL)?
+        .ok_or(ApiError::SESSION_EXPIRED)?;
+
+    let secure = request_secure_cookie(&headers, state.secure_cookies);
+    let domain = request_cookie_domain(&state, &headers);
     let fallback_scheme = request_fallback_scheme(&headers, &state.rp_origin);
-    let origin = request_origin(&headers, fallback_scheme).ok_or(StatusCode::BAD_REQUEST)?;
-    if !claims.aud.eq_ignore_ascii_case(&origin) {
-        return Err(StatusCode::UNAUTHORIZED);
-    }
-    let aud_host = origin_host(&claims.aud).ok_or(StatusCode::UNAUTHORIZED)?;
-    if !state.allowed_hosts.contains(&aud_host) {
-        return Err(StatusCode::UNAUTHORIZED);
+    let origin = request_origin(&headers, fallback_scheme);
+    let audience = auth::TokenAudience::for_origin(&state, origin.as_deref());
+    let sub_account = state
+        .db
+        .sub_account_apps(&rotated.user_id)
+        .await
+        .map_err(|_| ApiError::INTERNAL)?;
+    let token = auth::create_token(
+        &state.signing_keys,
+        &state.instance_id,
+        &rotated.user_id,
+        &rotated.id,
+        audience,
+        sub_account,
+    )
+    .map_err(|_| ApiError::INTERNAL)?;
+    Ok(jar
+        .add(auth::session_cookie(
+            &state.cookie,
+            token,
+            rotated.ttl,
+            secure,
+            domain,
+        ))
+        .add(auth::refresh_cookie(
+            &state.cookie,
+            rotated.refresh_token,
+            rotated.ttl,
+            secure,
+            domain,
+        )))
+}
+
+async fn logout(State(state): State<AppState>, jar: CookieJar, headers: HeaderMap) -> CookieJar {
+    end_host_session(&state, jar, &headers).await
+}
+
+/// Revoke the session behind this host's refresh cookie and clear its cookies.
+async fn end_host_session(state: &AppState, jar: CookieJar, headers: &HeaderMap) -> CookieJar {
+    if let Some(refresh) = jar.get(&state.cookie.refresh_name())
+        && let Ok(Some((session_id, user_id))) =
+            session::revoke_by_token(&state.storage, refresh.value()).await
+    {
+        logout::notify_apps(state, &user_id, Some(&session_id));
     }
+    let [access, refresh] =
+        auth::removal_cookies(&state.cookie, request_cookie_domain(state, headers));
+    jar.remove(access).remove(refresh)
+}
 
-    let token = auth::create_token(&state.jwt_secret, &claims.sub)
-        .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?;
-    let cookie = auth::session_cookie(token, origin.starts_with("https://"));
+/// Sign out on every host. All of the user's sessions are revoked, so refresh and
+/// forward-auth's session fallback stop at once; access JWTs still held by other
+/// hosts are cleared by the client loading `frontchannel_urls` in hidden iframes.
+async fn logout_all(
+    State(state): State<AppState>,
+    auth: AuthUser,
+    jar: CookieJar,
+    headers: HeaderMap,
+) -> Result<(CookieJar, Json<serde_json::Value>), ApiError> {
+    let revoked = session::revoke_all(&state.storage, &auth.user_id)
+        .await
+        .map_err(|_| ApiError::INTERNAL)?;
+    tracing::info!(user_id = auth.user_id, revoked, "signed out everywhere");
+    logout::notify_apps(&state, &auth.user_id, None);
 
+    let fallback_scheme = request_fallback_scheme(&headers, &state.rp_origin);
+    let current_host = request_origin(&headers, fallback_scheme).and_then(|o| origin_host(&o));
+    let cookie_domain = request_cookie_domain(&state, &headers);
+    let frontchannel_urls = frontchannel_logout_urls(
+        &state,
+        &auth.user_id,
+        current_host.as_deref(),
+        cookie_domain,
+    )?;
+
+    let [access, refresh] = auth::removal_cookies(&state.cookie, cookie_domain);
     Ok((
-        jar.add(cookie),
-        Redirect::to(&normalize_redirect_path(Some(&claims.path))),
+        jar.remove(access).remove(refresh),
+        Json(serde_json::json!({
+            "revoked": revoked,
+            "frontchannel_urls": frontchannel_urls,
+        })),
     ))
 }
 
-async fn logout(jar: CookieJar) -> CookieJar {
-    jar.remove(
-        Cookie::build(("den_session", ""))
-            .path("/")
-            .max_age(time::Duration::ZERO)
-            .build(),
-    )
+/// Every allowed host except the one already cleared: the current host, and the
+/// hosts sharing its `cookie_domain` cookie. Each URL carries a logout token for
+/// its own origin.
+fn frontchannel_logout_urls(
+    state: &AppState,
+    user_id: &str,
+    current_host: Option<&str>,
+    cookie_domain: Option<&str>,
+) -> Result<Vec<String>, ApiError> {
+    let scheme = if state.rp_origin.starts_with("https://") {
+        "https"
+    } else {
+        "http"
+    };
+    let mut urls = state
+        .allowed_hosts
+        .all()
+        .iter()
+        .filter(|host| Some(host.as_str()) != current_host)
+        .filter(|host| !cookie_domain.is_some_and(|domain| host_in_domain(host, domain)))
+        .map(|host| {
+            let origin = format!("{scheme}://{host}");
+            let token = issue_frontchannel_logout_token(state, user_id, &origin)?;
+            Ok(format!("{origin}/api/logout/frontchannel?token={token}"))
+        })
+        .collect::<Result<Vec<_>, ApiError>>()?;
+    urls.sort();
+    Ok(urls)
+}
+
+fn issue_frontchannel_logout_token(
+    state: &AppState,
+    user_id: &str,
+    origin: &str,
+) -> Result<String, ApiError> {
+    let now = OffsetDateTime::now_utc();
+    state
+        .signing_keys
+        .encode(
+            Some(LOGOUT_TOKEN_KEY),
+            &FrontchannelLogoutClaims {
+                iss: state.rp_origin.clone(),
+                aud: auth::TokenAudience::for_origin(state, Some(origin)).aud,
+                sub: user_id.to_string(),
+                jti: Uuid::new_v4().to_string(),
+                iat: now.unix_timestamp(),
+                exp: (now + Duration::seconds(60)).unix_timestamp(),
+            },
+        )
+        .map_err(|_| ApiError::INTERNAL)
+}
+
+/// Loaded in a hidden iframe by `logout/all` so this host drops its cookies. Only
+/// same-site hosts get their cookies in an iframe unless `cookie_same_site = "none"`.
+/// Without the single-use token from `logout/all` nothing is revoked or cleared, so
+/// a cross-site `<img>` can't sign anyone out.
+async fn logout_frontchannel(
+    State(state): State<AppState>,
+    Query(query): Query<FrontchannelLogoutQuery>,
+    jar: CookieJar,
+    headers: HeaderMap,
+) -> Result<
+    (
+        CookieJar,
+        [(HeaderName, &'static str); 1],
+        Html<&'static str>,
+    ),
+    ApiError,
+> {
+    let token = query.token.ok_or(ApiError::FORBIDDEN)?;
+    let fallback_scheme = request_fallback_scheme(&headers, &state.rp_origin);
+    let origin = request_origin(&headers, fallback_scheme).ok_or(ApiError::BAD_REQUEST)?;
+    let mut validation = Validation::default();
+    validation.leeway = 0;
+    validation.set_issuer(&[&state.rp_origin]);
+    validation.set_audience(&[&auth::TokenAudience::for_origin(&state, Some(&origin)).aud]);
+    validation.set_required_spec_claims(&["exp", "iss", "aud", "sub"]);
+    let claims = state
+        .signing_keys
+        .decode::<FrontchannelLogoutClaims>(Some(LOGOUT_TOKEN_KEY), &token, &validation)
+        .map_err(|_| ApiError::FORBIDDEN)?
+        .claims;
+    let first_use = state
+        .storage
+        .burn_redirect_token(&claims.jti, claims.exp)
+        .await
+        .map_err(|_| ApiError::INTERNAL)?;
+    if !first_use {
+        tracing::warn!(jti = claims.jti, "frontchannel logout token replayed");
+        return Err(ApiError::FORBIDDEN);
+    }
+
+    Ok((
+        end_host_session(&state, jar, &headers).await,
+        [(header::CACHE_CONTROL, "no-store")],
+        Html("<!doctype html><title>Signed out</title>"),
+    ))
 }
 
+/// `?sort=created|last_used|name|login_count&order=asc|desc&limit=&offset=&q=`,
+/// with the filtered total in `X-Total-Count`.
 async fn list_passkeys(
     State(state): State<AppState>,
     auth: AuthUser,
-) -> Result<Json<Vec<PasskeyInfo>>, StatusCode> {
-    let rows: Vec<(i64, String, String, Option<String>)> =
-        sqlx::query_as("SELECT id, name, created, last_used FROM passkey WHERE user_id = ?")
-            .bind(&auth.user_id)
-            .fetch_all(&state.db)
-            .await
-            .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?;
+    Query(mut query): Query<PasskeyQuery>,
+) -> Result<([(HeaderName, String); 1], Json<Vec<PasskeyInfo>>), ApiError> {
+    query.q = query.q.filter(|q| !q.trim().is_empty());
+    query.limit = query.limit.map(|limit| limit.clamp(1, MAX_PASSKEY_PAGE));
+    query.offset = query.offset.max(0);
+    let page = state
+        .db
+        .list_passkeys_page(&auth.user_id, state.passkeys.max_age_days, &query)
+        .await
+        .map_err(|_| ApiError::INTERNAL)?;
 
-    Ok(Json(
-        rows.into_iter()
-            .map(|(id, name, created, last_used)| PasskeyInfo {
-                id,
-                name,
-                created,
-                last_used,
-            })
-            .collect(),
+    Ok((
+        [(TOTAL_COUNT_HEADER, page.total.to_string())],
+        Json(page.passkeys),
     ))
 }
 
+/// Whether losing one device would lock the account out, so the UI can suggest a
+/// synced passkey.
+async fn passkey_recovery(
+    State(state): State<AppState>,
+    auth: AuthUser,
+) -> Result<Json<RecoveryStatus>, ApiError> {
+    let passkeys = state
+        .db
+        .list_passkeys(&auth.user_id, state.passkeys.max_age_days)
+        .await
+        .map_err(|_| ApiError::INTERNAL)?;
+
+    let synced = passkeys.iter().filter(|pk| pk.backup_state).count();
+    let device_bound = passkeys.len() - synced;
+    Ok(Json(RecoveryStatus {
+        survives_device_loss: synced > 0 || device_bound >= 2,
+        synced,
+        device_bound,
+    }))
+}
+
+/// The requested name, or the first free `name 2`, `name 3`, … to offer instead.
+async fn available_passkey_name(
+    State(state): State<AppState>,
+    auth: AuthUser,
+    Query(query): Query<NameQuery>,
+) -> Result<Json<AvailableName>, ApiError> {
+    let name = query.name.trim();
+    if name.is_empty() {
+        return Err(ApiError::BAD_REQUEST);
+    }
+    let name = state
+        .db
+        .available_passkey_name(&auth.user_id, name)
+        .await
+        .map_err(|_| ApiError::INTERNAL)?;
+    Ok(Json(AvailableName { name }))
+}
+
+/// Passkey writes fail on the `passkey_user_name` index when the name is taken, and
+/// on `passkey_cred_id` when the credential is already stored. SQLite names the
+/// index's columns, not the index, in the message.
+fn passkey_write_error(e: sqlx::Error) -> ApiError {
+    let sqlx::Error::Database(db) = e else {
+        return ApiError::INTERNAL;
+    };
+    if !db.is_unique_violation() {
+        return ApiError::INTERNAL;
+    }
+    match db.message().rsplit_once(": ").map(|(_, columns)| columns) {
+        Some("passkey.user_id, passkey.name") => ApiError::PASSKEY_NAME_TAKEN,
+        Some("passkey.cred_id") => ApiError::PASSKEY_ALREADY_ADDED,
+        _ => ApiError::INTERNAL,
+    }
+}
+
 async fn rename_passkey(
     State(state): State<AppState>,
     auth: AuthUser,
     Path(id): Path<i64>,
     Json(req): Json<RenameRequest>,
-) -> Result<StatusCode, StatusCode> {
-    let result = sqlx::query("UPDATE passkey SET name = ? WHERE id = ? AND user_id = ?")
-        .bind(&req.name)
-        .bind(id)
-        .bind(&auth.user_id)
-        .execute(&state.db)
+) -> Result<StatusCode, ApiError> {
+    let renamed = state
+        .db
+        .rename_passkey(&auth.user_id, id, &req.name)
         .await
-        .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?;
+        .map_err(passkey_write_error)?;
 
-    if result.rows_affected() == 0 {
-        return Err(StatusCode::NOT_FOUND);
+    if !renamed {
+        return Err(ApiError::NOT_FOUND);
     }
 
     Ok(StatusCode::NO_CONTENT)
 }
 
+/// Mark a passkey temporary (a borrowed security key) or permanent again.
+async fn set_passkey_expiry(
+    State(state): State<AppState>,
+    auth: AuthUser,
+    Path(id): Path<i64>,
+    Json(req): Json<ExpiryRequest>,
+) -> Result<Json<ExpiryResponse>, ApiError> {
+    if req.expires_in_days == Some(0) {
+        return Err(ApiError::BAD_REQUEST);
+    }
+    let expires_at = state
+        .db
+        .set_passkey_expiry(&auth.user_id, id, req.expires_in_days)
+        .await
+        .map_err(|_| ApiError::INTERNAL)?
+        .ok_or(ApiError::NOT_FOUND)?;
+
+    Ok(Json(ExpiryResponse { expires_at }))
+}
+
 async fn delete_passkey(
     State(state): State<AppState>,
     auth: AuthUser,
     Path(id): Path<i64>,
-) -> Result<StatusCode, StatusCode> {
-    let result = sqlx::query(
-        "DELETE FROM passkey WHERE id = ? AND user_id = ? \
-         AND (SELECT COUNT(*) FROM passkey WHERE user_id = ?) > 1",
-    )
-    .bind(id)
-    .bind(&auth.user_id)
-    .bind(&auth.user_id)
-    .execute(&state.db)
-    .await
-    .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?;
+) -> Result<StatusCode, ApiError> {
+    let removed = state
+        .db
+        .delete_passkey(&auth.user_id, id, state.passkeys.max_age_days)
+        .await
+        .map_err(|_| ApiError::INTERNAL)?;
 
-    if result.rows_affected() > 0 {
+    if let Some(passkey) = removed {
+        state
+            .notifier
+            .send(SecurityEvent::PasskeyRemoved { passkey });
         return Ok(StatusCode::NO_CONTENT);
     }
     // Distinguish "not found" from "last passkey"
-    let exists: bool =
-        sqlx::query_scalar("SELECT EXISTS(SELECT 1 FROM passkey WHERE id = ? AND user_id = ?)")
-            .bind(id)
-            .bind(&auth.user_id)
-            .fetch_one(&state.db)
-            .await
-            .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?;
+    let exists = state
+        .db
+        .passkey_exists(&auth.user_id, id)
+        .await
+        .map_err(|_| ApiError::INTERNAL)?;
 
     Err(if exists {
-        StatusCode::BAD_REQUEST
+        ApiError::LAST_PASSKEY
     } else {
-        StatusCode::NOT_FOUND
+        ApiError::NOT_FOUND
     })
 }
 
+async fn bulk_update_passkeys(
+    State(state): State<AppState>,
+    auth: AuthUser,
+    Json(req): Json<BulkPasskeyRequest>,
+) -> Result<StatusCode, ApiError> {
+    let mut ids: Vec<i64> = req
+        .rename
+        .iter()
+        .map(|r| r.id)
+        .chain(req.delete.iter().copied())
+        .collect();
+    let count = ids.len();
+    ids.sort_unstable();
+    ids.dedup();
+    // Each id at most once, so no passkey is renamed twice or renamed and deleted;
+    // `bulk_update_passkeys` orders the writes so names can move between passkeys.
+    if count == 0 || count > MAX_BULK_PASSKEYS || ids.len() != count {
+        return Err(ApiError::BAD_REQUEST);
+    }
+    let rename: Vec<(i64, String)> = req.rename.into_iter().map(|r| (r.id, r.name)).collect();
+    let outcome = state
+        .db
+        .bulk_update_passkeys(
+            &auth.user_id,
+            &rename,
+            &req.delete,
+            state.passkeys.max_age_days,
+        )
+        .await
+        .map_err(passkey_write_error)?;
+
+    match outcome {
+        BulkPasskeyOutcome::Done(removed) => {
+            for passkey in removed {
+                state
+                    .notifier
+                    .send(SecurityEvent::PasskeyRemoved { passkey });
+            }
+            Ok(StatusCode::NO_CONTENT)
+        }
+        BulkPasskeyOutcome::NotFound => Err(ApiError::NOT_FOUND),
+        BulkPasskeyOutcome::LastPasskey => Err(ApiError::LAST_PASSKEY),
+    }
+}
+
+/// Undo a delete within `passkeys.delete_grace_hours`.
+async fn restore_passkey(
+    State(state): State<AppState>,
+    auth: AuthUser,
+    Path(id): Path<i64>,
+) -> Result<StatusCode, ApiError> {
+    let restored = state
+        .db
+        .restore_passkey(&auth.user_id, id, state.passkeys.delete_grace_hours)
+        .await
+        .map_err(passkey_write_error)?
+        .ok_or(ApiError::NOT_FOUND)?;
+
+    tracing::info!(
+        user_id = auth.user_id,
+        passkey = restored,
+        "passkey restored"
+    );
+    Ok(StatusCode::NO_CONTENT)
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
@@ -645,4 +2343,13 @@ mod tests {
     fn normalize_redirect_path_accepts_regular_relative_path() {
         assert_eq!(normalize_redirect_path(Some("/dashboard")), "/dashboard");
     }
+
+    #[test]
+    fn normalize_redirect_path_keeps_query_and_fragment() {
+        assert_eq!(
+            normalize_redirect_path(Some("/wiki/a page?rev=2&x=y#Heading 1")),
+            "/wiki/a%20page?rev=2&x=y#Heading%201"
+        );
+        assert_eq!(normalize_redirect_path(Some("/a/../../etc")), "/etc");
+    }
 }
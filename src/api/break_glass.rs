@@ -0,0 +1,142 @@
+use std::net::SocketAddr;
+use std::path::Path;
+use std::time::{Duration, SystemTime};
+
+use axum::extract::{ConnectInfo, State};
+use axum::http::HeaderMap;
+use axum::routing::post;
+use axum::{Json, Router};
+use axum_extra::extract::cookie::CookieJar;
+use serde::Deserialize;
+
+use super::auth::{
+    check_login_throttle, issue_session, request_cookie_domain, request_secure_cookie,
+};
+use super::error::ApiError;
+use crate::audit::{self, AuditEvent, AuditKind};
+use crate::auth;
+use crate::origin::{client_ip, request_user_agent};
+use crate::session;
+use crate::state::AppState;
+
+/// A token file older than this is deleted unused: break-glass is for right now.
+const MAX_TOKEN_AGE: Duration = Duration::from_secs(15 * 60);
+/// Shorter tokens are refused, so the file can't hold a guessable word.
+const MIN_TOKEN_LEN: usize = 32;
+
+#[derive(Deserialize)]
+struct BreakGlassRequest {
+    token: String,
+}
+
+pub fn router() -> Router<AppState> {
+    Router::new().route("/login/break-glass", post(login))
+}
+
+/// Sign in as the instance's user with the token an admin wrote to
+/// `break_glass_token_file` on the host. The file is deleted by the login that
+/// uses it, so each token works once.
+async fn login(
+    State(state): State<AppState>,
+    ConnectInfo(peer): ConnectInfo<SocketAddr>,
+    jar: CookieJar,
+    headers: HeaderMap,
+    Json(req): Json<BreakGlassRequest>,
+) -> Result<(CookieJar, Json<serde_json::Value>), ApiError> {
+    let path = state
+        .break_glass_token_file
+        .as_deref()
+        .ok_or(ApiError::NOT_FOUND)?;
+    let ip = client_ip(&headers, peer.ip(), &state.trusted_proxies).to_string();
+    // Before the token is read, so a throttled client can't keep guessing.
+    check_login_throttle(&state, &ip).await?;
+    let user_agent = request_user_agent(&headers);
+
+    let accepted = match fresh_token(path).await {
+        Some(expected) if auth::secret_matches(&req.token, &expected) => {
+            // Whoever removes the file owns the token; a concurrent replay loses.
+            tokio::fs::remove_file(path).await.is_ok()
+        }
+        _ => false,
+    };
+    if !accepted {
+        audit::record(
+            &state,
+            AuditKind::LoginFailed,
+            AuditEvent {
+                ip: Some(&ip),
+                user_agent: Some(&user_agent),
+                detail: Some("break-glass"),
+                ..Default::default()
+            },
+        )
+        .await;
+        return Err(ApiError::UNAUTHENTICATED);
+    }
+    let user = state
+        .db
+        .only_user()
+        .await
+        .map_err(|_| ApiError::INTERNAL)?
+        .ok_or(ApiError::NO_USER)?;
+
+    tracing::warn!(user_id = user.id, ip, "break-glass login");
+    audit::record(
+        &state,
+        AuditKind::Login,
+        AuditEvent {
+            user_id: Some(&user.id),
+            ip: Some(&ip),
+            user_agent: Some(&user_agent),
+            detail: Some("break-glass"),
+            ..Default::default()
+        },
+    )
+    .await;
+
+    // Skips `[[hooks]]`: break-glass is the way in when a hook locks everyone out.
+    let jar = issue_session(
+        &state,
+        jar,
+        &user.id,
+        auth::SESSION_TTL,
+        request_secure_cookie(&headers, state.secure_cookies),
+        request_cookie_domain(&state, &headers),
+        &session::ClientInfo {
+            user_agent: Some(&user_agent),
+            ip: Some(&ip),
+            country: None,
+            origin: None,
+        },
+    )
+    .await?;
+
+    Ok((
+        jar,
+        Json(serde_json::json!({ "success": true, "user_name": user.name })),
+    ))
+}
+
+/// The token in `path`, unless the file is missing, stale or too short. A stale
+/// file is removed so it can't be used later.
+async fn fresh_token(path: &Path) -> Option<String> {
+    let modified = tokio::fs::metadata(path).await.ok()?.modified().ok()?;
+    let age = SystemTime::now()
+        .duration_since(modified)
+        .unwrap_or_default();
+    if age > MAX_TOKEN_AGE {
+        tracing::warn!(path = %path.display(), "removing stale break-glass token file");
+        let _ = tokio::fs::remove_file(path).await;
+        return None;
+    }
+    let token = tokio::fs::read_to_string(path).await.ok()?;
+    let token = token.trim();
+    if token.len() < MIN_TOKEN_LEN {
+        tracing::warn!(
+            path = %path.display(),
+            "break-glass token file holds fewer than {MIN_TOKEN_LEN} characters; ignoring it"
+        );
+        return None;
+    }
+    Some(token.to_owned())
+}
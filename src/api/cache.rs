@@ -0,0 +1,102 @@
+use axum::body::Body;
+use axum::extract::Request;
+use axum::http::{HeaderValue, Method, StatusCode, header};
+use axum::middleware::Next;
+use axum::response::{IntoResponse, Response};
+use sha2::{Digest, Sha256};
+
+const CACHEABLE_CONTROL: &str = "private, max-age=60";
+/// Cacheable bodies are small JSON; anything past this isn't hashed.
+const MAX_ETAG_BODY: usize = 1 << 20;
+
+/// `Cache-Control` for API responses whose handler set none: `no-store`, so no
+/// browser or proxy keeps auth state, except successful `is_cacheable` responses,
+/// which may be kept briefly and are revalidated by a content `ETag` (or the
+/// `Last-Modified` a file response already has).
+pub async fn headers(request: Request, next: Next) -> Response {
+    let cacheable = matches!(*request.method(), Method::GET | Method::HEAD)
+        && is_cacheable(request.uri().path());
+    let if_none_match = request.headers().get(header::IF_NONE_MATCH).cloned();
+    let mut response = next.run(request).await;
+    if response.headers().contains_key(header::CACHE_CONTROL) {
+        return response;
+    }
+    if !cacheable || !matches!(response.status(), StatusCode::OK | StatusCode::NOT_MODIFIED) {
+        response
+            .headers_mut()
+            .insert(header::CACHE_CONTROL, HeaderValue::from_static("no-store"));
+        return response;
+    }
+    response.headers_mut().insert(
+        header::CACHE_CONTROL,
+        HeaderValue::from_static(CACHEABLE_CONTROL),
+    );
+    // `ServeFile` already answered conditionals for the logo.
+    if response.status() == StatusCode::NOT_MODIFIED
+        || response.headers().contains_key(header::LAST_MODIFIED)
+    {
+        return response;
+    }
+
+    let (mut parts, body) = response.into_parts();
+    let Ok(body) = axum::body::to_bytes(body, MAX_ETAG_BODY).await else {
+        return StatusCode::INTERNAL_SERVER_ERROR.into_response();
+    };
+    let etag = format!("\"{}\"", &format!("{:x}", Sha256::digest(&body))[..32]);
+    let matched = if_none_match
+        .as_ref()
+        .and_then(|v| v.to_str().ok())
+        .is_some_and(|tags| etag_matches(tags, &etag));
+    parts.headers.insert(
+        header::ETAG,
+        HeaderValue::from_str(&etag).expect("hex is a valid header value"),
+    );
+    if matched {
+        parts.status = StatusCode::NOT_MODIFIED;
+        parts.headers.remove(header::CONTENT_LENGTH);
+        return Response::from_parts(parts, Body::empty());
+    }
+    Response::from_parts(parts, Body::from(body))
+}
+
+/// Public, user-independent documents the SPA fetches on every load. Paths are
+/// relative to `/api`, which `nest` strips.
+fn is_cacheable(path: &str) -> bool {
+    matches!(
+        path,
+        "/config/client" | "/config/branding" | "/config/branding/logo"
+    ) || path
+        .strip_prefix("/config/i18n/")
+        .is_some_and(|lang| !lang.is_empty())
+}
+
+/// `If-None-Match` uses weak comparison (RFC 9110 13.1.2), so a `W/` prefix, which
+/// proxies that re-encode the body may add, still matches.
+fn etag_matches(if_none_match: &str, etag: &str) -> bool {
+    if_none_match
+        .split(',')
+        .map(str::trim)
+        .any(|tag| tag == "*" || tag.strip_prefix("W/").unwrap_or(tag) == etag)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn only_public_config_is_cacheable() {
+        assert!(is_cacheable("/config/client"));
+        assert!(is_cacheable("/config/i18n/auto"));
+        assert!(!is_cacheable("/config/i18n/"));
+        assert!(!is_cacheable("/passkeys"));
+        assert!(!is_cacheable("/sessions"));
+    }
+
+    #[test]
+    fn if_none_match_compares_weakly() {
+        assert!(etag_matches("\"a\"", "\"a\""));
+        assert!(etag_matches("\"b\", W/\"a\"", "\"a\""));
+        assert!(etag_matches("*", "\"a\""));
+        assert!(!etag_matches("\"b\"", "\"a\""));
+    }
+}
@@ -0,0 +1,119 @@
+use axum::Json;
+use axum::Router;
+use axum::body::Body;
+use std::collections::BTreeMap;
+
+use axum::extract::{Path, Request, State};
+use axum::http::{HeaderMap, StatusCode, header};
+use axum::response::{IntoResponse, Response};
+use axum::routing::get;
+use serde::Serialize;
+use tower::ServiceExt;
+use tower_http::services::ServeFile;
+
+use crate::branding::Branding;
+use crate::i18n;
+use crate::state::AppState;
+
+/// Public runtime settings so one SPA build works for every deployment.
+#[derive(Serialize)]
+struct ClientConfig {
+    rp_id: String,
+    setup_complete: bool,
+    branding: Branding,
+    login_methods: Vec<&'static str>,
+    /// Button text for `/api/oidc/login`, when upstream OIDC is configured.
+    upstream_oidc_label: Option<String>,
+    allowed_redirect_hosts: Vec<String>,
+    /// Hours a recovery reset waits, when recovery URLs are enabled.
+    recovery_delay_hours: Option<u32>,
+}
+
+#[derive(Serialize)]
+struct Messages<'a> {
+    lang: &'static str,
+    languages: Vec<&'static str>,
+    strings: &'a BTreeMap<String, String>,
+}
+
+pub fn router() -> Router<AppState> {
+    Router::new()
+        .route("/client", get(client))
+        .route("/branding", get(branding))
+        .route("/branding/logo", get(branding_logo))
+        .route("/i18n/{lang}", get(messages))
+}
+
+async fn client(State(state): State<AppState>) -> Result<Json<ClientConfig>, StatusCode> {
+    let setup_complete = state
+        .db
+        .user_exists()
+        .await
+        .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?;
+
+    let mut login_methods = vec!["passkey", "conditional"];
+    if state.client_cert.is_some() {
+        login_methods.push("client_certificate");
+    }
+    if state.upstream_oidc.is_some() {
+        login_methods.push("upstream_oidc");
+    }
+    if state.ldap.is_some() {
+        login_methods.push("ldap");
+    }
+    #[cfg(feature = "dev-auth")]
+    if state.dev_login_password.is_some() {
+        login_methods.push("dev_password");
+    }
+    let mut allowed_redirect_hosts: Vec<String> =
+        state.allowed_hosts.all().iter().cloned().collect();
+    allowed_redirect_hosts.sort();
+
+    Ok(Json(ClientConfig {
+        rp_id: state.rp_id.clone(),
+        setup_complete,
+        branding: (*state.branding).clone(),
+        login_methods,
+        upstream_oidc_label: state.upstream_oidc.as_ref().map(|o| o.label().to_owned()),
+        allowed_redirect_hosts,
+        recovery_delay_hours: state.recovery.map(|r| r.delay_hours),
+    }))
+}
+
+async fn branding(State(state): State<AppState>) -> Json<Branding> {
+    Json((*state.branding).clone())
+}
+
+async fn branding_logo(State(state): State<AppState>, request: Request) -> Response {
+    let Some(path) = &state.branding.logo_path else {
+        return StatusCode::NOT_FOUND.into_response();
+    };
+    match ServeFile::new(path).oneshot(request).await {
+        Ok(response) => response.map(Body::new),
+        Err(_) => StatusCode::INTERNAL_SERVER_ERROR.into_response(),
+    }
+}
+
+/// UI strings for `lang` (`auto` to go by `Accept-Language`), falling back per key to English.
+async fn messages(
+    State(state): State<AppState>,
+    Path(lang): Path<String>,
+    headers: HeaderMap,
+) -> Response {
+    let accept_language = headers
+        .get(header::ACCEPT_LANGUAGE)
+        .and_then(|v| v.to_str().ok());
+    let lang = state.catalogs.negotiate(&lang, accept_language);
+    (
+        [
+            (header::CONTENT_LANGUAGE, lang),
+            (header::VARY, "Accept-Language"),
+        ],
+        Json(Messages {
+            lang,
+            languages: i18n::languages().collect(),
+            strings: state.catalogs.strings(lang),
+        }),
+    )
+        .into_response()
+}
@@ -0,0 +1,81 @@
+use axum::extract::{Query, State};
+use axum::http::StatusCode;
+use axum::routing::get;
+use axum::{Json, Router};
+use serde::{Deserialize, Serialize};
+
+use crate::auth::AuthUser;
+use crate::logout;
+use crate::session;
+use crate::state::AppState;
+
+#[derive(Serialize)]
+struct ConnectedAppInfo {
+    origin: String,
+    /// The `[[apps]]` name for this origin, if it has a policy.
+    name: Option<String>,
+    first_used: String,
+    last_used: String,
+    count: i64,
+}
+
+#[derive(Deserialize)]
+struct RevokeQuery {
+    origin: String,
+}
+
+pub fn router() -> Router<AppState> {
+    Router::new().route("/", get(list_apps).delete(revoke_app))
+}
+
+async fn list_apps(
+    State(state): State<AppState>,
+    auth: AuthUser,
+) -> Result<Json<Vec<ConnectedAppInfo>>, StatusCode> {
+    let rows = state
+        .db
+        .connected_apps(&auth.user_id)
+        .await
+        .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?;
+
+    Ok(Json(
+        rows.into_iter()
+            .map(|row| ConnectedAppInfo {
+                name: state.apps.get(&row.origin).map(|app| app.name.clone()),
+                origin: row.origin,
+                first_used: row.first_used,
+                last_used: row.last_used,
+                count: row.count,
+            })
+            .collect(),
+    ))
+}
+
+/// Forget an app and end every session it was handed.
+async fn revoke_app(
+    State(state): State<AppState>,
+    auth: AuthUser,
+    Query(query): Query<RevokeQuery>,
+) -> Result<StatusCode, StatusCode> {
+    let known = state
+        .db
+        .remove_connected_app(&auth.user_id, &query.origin)
+        .await
+        .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?;
+    if !known {
+        return Err(StatusCode::NOT_FOUND);
+    }
+    let revoked = session::revoke_origin(&state.storage, &auth.user_id, &query.origin)
+        .await
+        .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?;
+
+    tracing::info!(
+        origin = query.origin,
+        sessions = revoked.len(),
+        "connected app revoked"
+    );
+    for id in &revoked {
+        logout::notify_apps(&state, &auth.user_id, Some(id));
+    }
+    Ok(StatusCode::NO_CONTENT)
+}
@@ -0,0 +1,97 @@
+use std::net::SocketAddr;
+
+use axum::extract::{ConnectInfo, State};
+use axum::http::{HeaderMap, StatusCode};
+use axum::routing::post;
+use axum::{Json, Router};
+use axum_extra::extract::cookie::CookieJar;
+use serde::Deserialize;
+
+use super::auth::{request_cookie_domain, request_secure_cookie, start_session};
+use crate::audit::{self, AuditEvent, AuditKind};
+use crate::auth;
+use crate::origin::{client_ip, request_user_agent};
+use crate::session;
+use crate::state::AppState;
+
+#[derive(Deserialize)]
+struct DevLoginRequest {
+    password: String,
+}
+
+pub fn router() -> Router<AppState> {
+    Router::new().route("/dev-login", post(login))
+}
+
+/// Sign in as the instance's user with `dev_login_password`, so the SPA can be worked
+/// on without an authenticator. The user must already exist (register a passkey once).
+async fn login(
+    State(state): State<AppState>,
+    ConnectInfo(peer): ConnectInfo<SocketAddr>,
+    jar: CookieJar,
+    headers: HeaderMap,
+    Json(req): Json<DevLoginRequest>,
+) -> Result<(CookieJar, Json<serde_json::Value>), StatusCode> {
+    let expected = state
+        .dev_login_password
+        .as_deref()
+        .ok_or(StatusCode::NOT_FOUND)?;
+    let ip = client_ip(&headers, peer.ip(), &state.trusted_proxies).to_string();
+    let user_agent = request_user_agent(&headers);
+
+    if !auth::secret_matches(&req.password, expected) {
+        audit::record(
+            &state,
+            AuditKind::LoginFailed,
+            AuditEvent {
+                ip: Some(&ip),
+                user_agent: Some(&user_agent),
+                detail: Some("dev-login"),
+                ..Default::default()
+            },
+        )
+        .await;
+        return Err(StatusCode::UNAUTHORIZED);
+    }
+    let user = state
+        .db
+        .only_user()
+        .await
+        .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?
+        .ok_or(StatusCode::CONFLICT)?;
+
+    tracing::warn!(user_id = user.id, "dev login");
+    audit::record(
+        &state,
+        AuditKind::Login,
+        AuditEvent {
+            user_id: Some(&user.id),
+            ip: Some(&ip),
+            user_agent: Some(&user_agent),
+            detail: Some("dev-login"),
+            ..Default::default()
+        },
+    )
+    .await;
+
+    let jar = start_session(
+        &state,
+        jar,
+        &user.id,
+        auth::SESSION_TTL,
+        request_secure_cookie(&headers, state.secure_cookies),
+        request_cookie_domain(&state, &headers),
+        &session::ClientInfo {
+            user_agent: Some(&user_agent),
+            ip: Some(&ip),
+            country: None,
+            origin: None,
+        },
+    )
+    .await?;
+
+    Ok((
+        jar,
+        Json(serde_json::json!({ "success": true, "user_name": user.name })),
+    ))
+}
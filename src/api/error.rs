@@ -0,0 +1,278 @@
+use axum::Json;
+use axum::http::{HeaderValue, StatusCode, header};
+use axum::response::{IntoResponse, Response};
+use serde::Serialize;
+
+use crate::middleware::current_request_id;
+
+/// JSON error body for the auth endpoints: a stable `code` the frontend can branch on,
+/// a human-readable `message`, and whether retrying the same request might succeed.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct ApiError {
+    pub status: StatusCode,
+    pub code: &'static str,
+    pub message: &'static str,
+    pub retryable: bool,
+    /// Seconds until a retry can succeed, sent as `Retry-After` too.
+    pub retry_after: Option<u64>,
+}
+
+#[derive(Serialize)]
+struct ErrorBody {
+    code: &'static str,
+    message: &'static str,
+    retryable: bool,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    retry_after: Option<u64>,
+    request_id: Option<String>,
+}
+
+const fn error(status: StatusCode, code: &'static str, message: &'static str) -> ApiError {
+    ApiError {
+        status,
+        code,
+        message,
+        retryable: false,
+        retry_after: None,
+    }
+}
+
+impl ApiError {
+    const fn retryable(self) -> Self {
+        Self {
+            retryable: true,
+            ..self
+        }
+    }
+
+    pub fn retry_after(self, seconds: u64) -> Self {
+        Self {
+            retry_after: Some(seconds),
+            ..self.retryable()
+        }
+    }
+
+    pub const INTERNAL: Self = error(
+        StatusCode::INTERNAL_SERVER_ERROR,
+        "internal",
+        "Something went wrong on our side. Please try again.",
+    )
+    .retryable();
+    pub const UPSTREAM_UNAVAILABLE: Self = error(
+        StatusCode::BAD_GATEWAY,
+        "upstream_unavailable",
+        "The sign-in provider could not be reached. Please try again.",
+    )
+    .retryable();
+    pub const UPSTREAM_REJECTED: Self = error(
+        StatusCode::UNAUTHORIZED,
+        "upstream_rejected",
+        "The sign-in provider did not confirm who you are.",
+    );
+    pub const BAD_REQUEST: Self = error(
+        StatusCode::BAD_REQUEST,
+        "bad_request",
+        "The request was malformed.",
+    );
+    pub const NOT_FOUND: Self = error(StatusCode::NOT_FOUND, "not_found", "Not found.");
+    pub const UNAUTHENTICATED: Self = error(
+        StatusCode::UNAUTHORIZED,
+        "unauthenticated",
+        "You need to sign in first.",
+    );
+    pub const FORBIDDEN: Self = error(
+        StatusCode::FORBIDDEN,
+        "forbidden",
+        "You are not allowed to do that.",
+    );
+    pub const CHALLENGE_EXPIRED: Self = error(
+        StatusCode::BAD_REQUEST,
+        "challenge_expired",
+        "The sign-in request expired. Please start again.",
+    )
+    .retryable();
+    pub const NAME_REQUIRED: Self = error(
+        StatusCode::BAD_REQUEST,
+        "name_required",
+        "Enter a user name.",
+    );
+    pub const BOOTSTRAP_TOKEN_INVALID: Self = error(
+        StatusCode::FORBIDDEN,
+        "bootstrap_token_invalid",
+        "The setup token is missing or wrong.",
+    );
+    pub const INVITE_INVALID: Self = error(
+        StatusCode::FORBIDDEN,
+        "invite_invalid",
+        "This invite link is invalid, used, or expired.",
+    );
+    pub const ALREADY_REGISTERED: Self = error(
+        StatusCode::CONFLICT,
+        "already_registered",
+        "This instance already has a user.",
+    );
+    pub const REGISTRATION_REJECTED: Self = error(
+        StatusCode::BAD_REQUEST,
+        "registration_rejected",
+        "The passkey could not be registered.",
+    );
+    pub const AUTHENTICATOR_NOT_ALLOWED: Self = error(
+        StatusCode::FORBIDDEN,
+        "authenticator_not_allowed",
+        "This kind of passkey is not allowed here. Use an approved security key.",
+    );
+    pub const NO_PASSKEYS: Self = error(
+        StatusCode::BAD_REQUEST,
+        "no_passkeys",
+        "No passkeys are registered yet.",
+    );
+    pub const PASSKEY_REJECTED: Self = error(
+        StatusCode::UNAUTHORIZED,
+        "passkey_rejected",
+        "That passkey was not accepted.",
+    );
+    pub const PASSKEY_EXPIRED: Self = error(
+        StatusCode::UNAUTHORIZED,
+        "passkey_expired",
+        "That passkey has expired. Sign in with another passkey.",
+    );
+    pub const COUNTRY_DENIED: Self = error(
+        StatusCode::FORBIDDEN,
+        "country_denied",
+        "Sign-in is not allowed from your location.",
+    );
+    pub const APP_ACCESS_DENIED: Self = error(
+        StatusCode::FORBIDDEN,
+        "app_access_denied",
+        "You are not allowed to use this app.",
+    );
+    pub const APP_CLOSED: Self = error(
+        StatusCode::FORBIDDEN,
+        "app_closed",
+        "This app can't be used at this time.",
+    );
+    pub const INVALID_REDIRECT: Self = error(
+        StatusCode::BAD_REQUEST,
+        "invalid_redirect",
+        "The redirect target is not allowed.",
+    );
+    pub const REDIRECT_TOKEN_INVALID: Self = error(
+        StatusCode::UNAUTHORIZED,
+        "redirect_token_invalid",
+        "This sign-in link is invalid, used, or expired.",
+    );
+    pub const SESSION_EXPIRED: Self = error(
+        StatusCode::UNAUTHORIZED,
+        "session_expired",
+        "Your session has ended. Please sign in again.",
+    );
+    pub const STEP_UP_UNAVAILABLE: Self = error(
+        StatusCode::FORBIDDEN,
+        "step_up_unavailable",
+        "This sign-in needs confirming with another passkey or your recovery URL, and this account has neither.",
+    );
+    pub const RECOVERY_CODE_INVALID: Self = error(
+        StatusCode::UNAUTHORIZED,
+        "recovery_code_invalid",
+        "That recovery URL is not valid.",
+    );
+    pub const LAST_PASSKEY: Self = error(
+        StatusCode::BAD_REQUEST,
+        "last_passkey",
+        "You can't remove your only passkey.",
+    );
+    pub const PASSKEY_NAME_TAKEN: Self = error(
+        StatusCode::CONFLICT,
+        "passkey_name_taken",
+        "You already have a passkey with that name.",
+    );
+    pub const PASSKEY_ALREADY_ADDED: Self = error(
+        StatusCode::CONFLICT,
+        "passkey_already_added",
+        "This passkey is already registered.",
+    );
+    pub const LOGIN_THROTTLED: Self = error(
+        StatusCode::TOO_MANY_REQUESTS,
+        "login_throttled",
+        "Too many failed sign-ins. Please wait before trying again.",
+    )
+    .retryable();
+    pub const LOGIN_PUZZLE_REQUIRED: Self = error(
+        StatusCode::PRECONDITION_REQUIRED,
+        "login_puzzle_required",
+        "Solve a sign-in puzzle first.",
+    );
+    pub const HOOK_DENIED: Self = error(
+        StatusCode::FORBIDDEN,
+        "hook_denied",
+        "This was refused by a server policy. Contact your administrator.",
+    );
+    pub const POLICY_DENIED: Self = error(
+        StatusCode::FORBIDDEN,
+        "policy_denied",
+        "Sign-in was refused by this server's login policy.",
+    );
+    pub const RECOVERY_NOT_READY: Self = error(
+        StatusCode::CONFLICT,
+        "recovery_not_ready",
+        "The waiting period hasn't passed yet.",
+    );
+    pub const NO_USER: Self = error(
+        StatusCode::CONFLICT,
+        "no_user",
+        "This instance has no user yet.",
+    );
+}
+
+impl IntoResponse for ApiError {
+    fn into_response(self) -> Response {
+        let body = ErrorBody {
+            code: self.code,
+            message: self.message,
+            retryable: self.retryable,
+            retry_after: self.retry_after,
+            request_id: current_request_id(),
+        };
+        let mut response = (self.status, Json(body)).into_response();
+        if let Some(seconds) = self.retry_after {
+            response
+                .headers_mut()
+                .insert(header::RETRY_AFTER, HeaderValue::from(seconds));
+        }
+        response
+    }
+}
+
+/// Generic mapping for errors from extractors and shared helpers.
+impl From<StatusCode> for ApiError {
+    fn from(status: StatusCode) -> Self {
+        match status {
+            StatusCode::BAD_REQUEST => Self::BAD_REQUEST,
+            StatusCode::UNAUTHORIZED => Self::UNAUTHENTICATED,
+            StatusCode::FORBIDDEN => Self::FORBIDDEN,
+            StatusCode::NOT_FOUND => Self::NOT_FOUND,
+            StatusCode::BAD_GATEWAY => Self::UPSTREAM_UNAVAILABLE,
+            _ => Self::INTERNAL,
+        }
+    }
+}
+
+/// Lets handlers that still return bare status codes call the shared login helpers.
+impl From<ApiError> for StatusCode {
+    fn from(error: ApiError) -> Self {
+        error.status
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn status_codes_round_trip() {
+        for error in [ApiError::INTERNAL, ApiError::NOT_FOUND, ApiError::FORBIDDEN] {
+            assert_eq!(ApiError::from(StatusCode::from(error)), error);
+        }
+        assert!(ApiError::from(StatusCode::SERVICE_UNAVAILABLE).retryable);
+    }
+}
@@ -0,0 +1,213 @@
+use axum::extract::{Query, State};
+use axum::http::{HeaderMap, HeaderName, HeaderValue, Method, StatusCode, header};
+use axum::response::{IntoResponse, Response};
+use axum_extra::extract::cookie::CookieJar;
+use serde::Deserialize;
+use time::OffsetDateTime;
+use url::Url;
+
+use crate::audit::{self, AuditEvent, AuditKind};
+use crate::auth;
+use crate::origin::{origin_host, request_fallback_scheme, request_origin};
+use crate::policy;
+use crate::service_account::{self, FORWARD_AUTH_VERIFY};
+use crate::session;
+use crate::state::AppState;
+
+#[derive(Deserialize)]
+pub struct VerifyQuery {
+    /// Login portal URL (Authelia convention); unauthenticated requests are sent
+    /// there with `?rd=<original url>`. Without it, den answers 401.
+    rd: Option<String>,
+}
+
+/// Forward-auth endpoint for Traefik `forwardAuth`, Caddy `forward_auth` and nginx
+/// `auth_request`. Answers 200 plus identity headers for a live session, or for a
+/// service account token with `forward-auth:verify` (reported without groups). A
+/// sub-account's session only passes for its own apps, also without groups.
+pub async fn verify(
+    State(state): State<AppState>,
+    Query(query): Query<VerifyQuery>,
+    jar: CookieJar,
+    headers: HeaderMap,
+) -> Response {
+    let fallback_scheme = request_fallback_scheme(&headers, &state.rp_origin);
+    let origin = request_origin(&headers, fallback_scheme);
+
+    let service = match service_account::authenticate(&state.db, &headers).await {
+        Ok(service) => service,
+        Err(_) => return StatusCode::INTERNAL_SERVER_ERROR.into_response(),
+    };
+    let (user_id, user_name, groups, session_id, allowed_apps) = match service {
+        Some(account) if account.has_scope(FORWARD_AUTH_VERIFY) => {
+            (account.id, account.name, String::new(), None, None)
+        }
+        Some(account) => {
+            tracing::warn!(
+                account = account.name,
+                "service account lacks forward-auth:verify"
+            );
+            return StatusCode::FORBIDDEN.into_response();
+        }
+        None => {
+            let (user_id, session_id, allowed_apps) = match session_user(&state, &jar).await {
+                Ok(Some(session)) => session,
+                Ok(None) => return unauthenticated(&state, &query, &headers, origin.as_deref()),
+                Err(status) => return status.into_response(),
+            };
+            let user = match state.db.get_user(&user_id).await {
+                Ok(user) => user,
+                Err(_) => return StatusCode::INTERNAL_SERVER_ERROR.into_response(),
+            };
+            let Some(user) = user else {
+                return unauthenticated(&state, &query, &headers, origin.as_deref());
+            };
+            let groups = match allowed_apps {
+                Some(_) => String::new(),
+                None => state.forward_auth.groups.join(","),
+            };
+            (user_id, user.name, groups, session_id, allowed_apps)
+        }
+    };
+
+    if allowed_apps.is_some()
+        && !origin
+            .as_deref()
+            .is_some_and(|o| policy::app_allowed(allowed_apps.as_deref(), o))
+    {
+        tracing::warn!(
+            user_id,
+            origin,
+            "forward-auth denied outside sub-account apps"
+        );
+        audit::record(
+            &state,
+            AuditKind::AccessDenied,
+            AuditEvent {
+                user_id: Some(&user_id),
+                detail: Some(&format!("policy denied: {}", policy::SUB_ACCOUNT_RULE)),
+                app: origin.as_deref(),
+                ..Default::default()
+            },
+        )
+        .await;
+        return StatusCode::FORBIDDEN.into_response();
+    }
+
+    if let Some(app) = origin.as_deref().and_then(|o| state.apps.get(o)) {
+        if !app.allows(&user_id, Some(&user_name)) {
+            tracing::warn!(app = app.name, user_id, "forward-auth denied by app policy");
+            return StatusCode::FORBIDDEN.into_response();
+        }
+        if !app.open_at(OffsetDateTime::now_utc()) {
+            tracing::warn!(app = app.name, user_id, "forward-auth outside access hours");
+            audit::record(
+                &state,
+                AuditKind::AccessDenied,
+                AuditEvent {
+                    user_id: Some(&user_id),
+                    detail: Some("outside access hours"),
+                    app: Some(&app.origin),
+                    ..Default::default()
+                },
+            )
+            .await;
+            return StatusCode::FORBIDDEN.into_response();
+        }
+    }
+
+    let settings = &state.forward_auth;
+    let mut response = StatusCode::OK.into_response();
+    for (name, value) in [
+        (&settings.user_header, user_name.as_str()),
+        (&settings.name_header, user_name.as_str()),
+        (&settings.groups_header, groups.as_str()),
+        (
+            &settings.session_header,
+            session_id.as_deref().unwrap_or_default(),
+        ),
+    ] {
+        if let (Ok(name), Ok(value)) = (
+            HeaderName::try_from(name.as_str()),
+            HeaderValue::from_str(value),
+        ) {
+            response.headers_mut().insert(name, value);
+        }
+    }
+    response
+}
+
+/// User id, session id and, for a sub-account, its apps (from the token's claims).
+type SessionUser = (String, Option<String>, Option<Vec<String>>);
+
+/// Resolve the session cookie to its user and session id. Expired access tokens are
+/// accepted while their backing session is live, since proxied hosts can't reach
+/// `/api/refresh`. A sub-account's token is only trusted while its session is live
+/// too, as its sessions are revoked whenever its apps change.
+async fn session_user(
+    state: &AppState,
+    jar: &CookieJar,
+) -> Result<Option<SessionUser>, StatusCode> {
+    let Some(cookie) = jar.get(&state.cookie.name) else {
+        return Ok(None);
+    };
+    let claims = match auth::claims_from_token(&state.signing_keys, cookie.value()) {
+        Ok(claims) if claims.sub_account.is_none() => {
+            return Ok(Some((claims.sub, claims.sid, None)));
+        }
+        Ok(claims) => claims,
+        Err(_) => match auth::claims_ignoring_expiry(&state.signing_keys, cookie.value()) {
+            Ok(claims) => claims,
+            Err(_) => return Ok(None),
+        },
+    };
+    let Some(sid) = claims.sid else {
+        return Ok(None);
+    };
+    let owner = session::live_user(&state.storage, &sid)
+        .await
+        .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?;
+    Ok(owner
+        .filter(|owner| *owner == claims.sub)
+        .map(|owner| (owner, Some(sid), claims.sub_account)))
+}
+
+fn unauthenticated(
+    state: &AppState,
+    query: &VerifyQuery,
+    headers: &HeaderMap,
+    origin: Option<&str>,
+) -> Response {
+    let (Some(rd), Some(origin)) = (query.rd.as_deref(), origin) else {
+        return StatusCode::UNAUTHORIZED.into_response();
+    };
+    let Some(mut portal) = Url::parse(rd)
+        .ok()
+        .filter(|u| origin_host(u.as_str()).is_some_and(|h| state.allowed_hosts.contains(&h)))
+    else {
+        tracing::warn!(rd, "ignoring forward-auth rd outside allowed hosts");
+        return StatusCode::UNAUTHORIZED.into_response();
+    };
+
+    let uri = headers
+        .get("x-forwarded-uri")
+        .and_then(|v| v.to_str().ok())
+        .filter(|v| v.starts_with('/'))
+        .unwrap_or("/");
+    portal
+        .query_pairs_mut()
+        .append_pair("rd", &format!("{origin}{uri}"));
+
+    // Match Authelia: 302 for safe methods, 303 so other methods re-issue as GET.
+    let method = headers
+        .get("x-forwarded-method")
+        .and_then(|v| v.to_str().ok())
+        .and_then(|v| Method::from_bytes(v.as_bytes()).ok())
+        .unwrap_or(Method::GET);
+    let status = if method == Method::GET || method == Method::HEAD {
+        StatusCode::FOUND
+    } else {
+        StatusCode::SEE_OTHER
+    };
+    (status, [(header::LOCATION, portal.to_string())]).into_response()
+}
@@ -8,16 +8,22 @@ use crate::state::AppState;
 #[derive(Serialize)]
 pub struct Health {
     pub status: &'static str,
+    /// Which replica answered, for checking a load balancer's spread.
+    pub instance: String,
 }
 
 pub async fn check(State(state): State<AppState>) -> Result<Json<Health>, StatusCode> {
-    sqlx::query_scalar::<_, i64>("SELECT 1")
-        .fetch_one(&state.db)
-        .await
-        .map_err(|error| {
-            tracing::warn!(error = %error, "health check database ping failed");
-            StatusCode::SERVICE_UNAVAILABLE
-        })?;
+    state.db.ping().await.map_err(|error| {
+        tracing::warn!(error = %error, "health check database ping failed");
+        StatusCode::SERVICE_UNAVAILABLE
+    })?;
+    state.storage.ping().await.map_err(|error| {
+        tracing::warn!(error = %error, "health check storage ping failed");
+        StatusCode::SERVICE_UNAVAILABLE
+    })?;
 
-    Ok(Json(Health { status: "ok" }))
+    Ok(Json(Health {
+        status: "ok",
+        instance: state.instance_id.to_string(),
+    }))
 }
@@ -0,0 +1,263 @@
+use std::net::SocketAddr;
+
+use axum::extract::{ConnectInfo, State};
+use axum::http::{HeaderMap, StatusCode};
+use axum::routing::{get, post};
+use axum::{Json, Router};
+use axum_extra::extract::cookie::CookieJar;
+use serde::{Deserialize, Serialize};
+
+use super::auth::{
+    LoginBeginRequest, app_session_ttl, check_access_policy, check_login_country,
+    check_login_throttle, login_redirect_target, post_login_redirect, request_cookie_domain,
+    request_secure_cookie, start_session,
+};
+use super::error::ApiError;
+use crate::audit::{self, AuditEvent, AuditKind};
+use crate::auth::{self, AuthUser};
+use crate::config::LoginMethod;
+use crate::db::ConfirmedTotp;
+use crate::origin::{client_ip, request_user_agent};
+use crate::policy;
+use crate::session;
+use crate::state::AppState;
+use crate::totp;
+
+#[derive(Deserialize)]
+struct LdapLoginRequest {
+    username: String,
+    password: String,
+    code: String,
+    #[serde(flatten)]
+    redirect: LoginBeginRequest,
+}
+
+#[derive(Serialize)]
+struct TotpStatus {
+    enrolled: bool,
+}
+
+#[derive(Serialize)]
+struct TotpEnrollment {
+    uri: String,
+}
+
+#[derive(Deserialize)]
+struct TotpConfirmRequest {
+    code: String,
+}
+
+pub fn router() -> Router<AppState> {
+    Router::new()
+        .route("/login", post(login))
+        .route("/totp", get(totp_status).delete(remove_totp))
+        .route("/totp/enroll", post(enroll_totp))
+        .route("/totp/confirm", post(confirm_totp))
+}
+
+/// Passkey-less fallback: an LDAP bind plus a confirmed TOTP code. Neither factor
+/// is accepted on its own.
+async fn login(
+    State(state): State<AppState>,
+    ConnectInfo(peer): ConnectInfo<SocketAddr>,
+    jar: CookieJar,
+    headers: HeaderMap,
+    Json(req): Json<LdapLoginRequest>,
+) -> Result<(CookieJar, Json<serde_json::Value>), ApiError> {
+    let ldap = state.ldap.clone().ok_or(ApiError::NOT_FOUND)?;
+    let (redirect_origin, redirect_path) = login_redirect_target(&state, &req.redirect)?;
+
+    let client = client_ip(&headers, peer.ip(), &state.trusted_proxies);
+    let ip = client.to_string();
+    // Before the bind, so a throttled client can't keep guessing passwords.
+    check_login_throttle(&state, &ip).await?;
+    let country = state.geoip.as_ref().and_then(|g| g.country(client));
+    let user_agent = request_user_agent(&headers);
+    let fail = async |detail: &str| {
+        audit::record(
+            &state,
+            AuditKind::LoginFailed,
+            AuditEvent {
+                ip: Some(&ip),
+                country: country.as_deref(),
+                user_agent: Some(&user_agent),
+                detail: Some(&format!("ldap {}: {detail}", req.username)),
+                ..Default::default()
+            },
+        )
+        .await;
+        ApiError::UNAUTHENTICATED
+    };
+
+    if !ldap.allows(&req.username) {
+        return Err(fail("not in ldap.allowed_users").await);
+    }
+    // den is single-user: an allowed LDAP identity maps to the one user, and only once
+    // that user has enrolled TOTP from a passkey session.
+    let enrolled = state
+        .db
+        .confirmed_totp()
+        .await
+        .map_err(|_| ApiError::INTERNAL)?;
+    let Some(ConfirmedTotp {
+        user_id,
+        secret,
+        last_step,
+    }) = enrolled
+    else {
+        return Err(fail("no totp enrolled").await);
+    };
+
+    match ldap.verify(&req.username, &req.password).await {
+        Ok(true) => {}
+        Ok(false) => return Err(fail("invalid credentials").await),
+        Err(error) => {
+            tracing::error!(error = %error, "ldap bind failed");
+            return Err(ApiError::UPSTREAM_UNAVAILABLE);
+        }
+    }
+
+    let now = time::OffsetDateTime::now_utc().unix_timestamp();
+    let Some(step) = totp::verify(&secret, &req.code, now, last_step) else {
+        return Err(fail("invalid totp code").await);
+    };
+    let consumed = state
+        .db
+        .consume_totp_step(&user_id, step)
+        .await
+        .map_err(|_| ApiError::INTERNAL)?;
+    if !consumed {
+        return Err(fail("totp code replayed").await);
+    }
+
+    check_login_country(&state, country.as_deref(), &user_id, &ip, Some(&user_agent)).await?;
+    check_access_policy(
+        &state,
+        &policy::Request {
+            ip: client,
+            app: redirect_origin.as_deref().unwrap_or(&state.rp_origin),
+            method: LoginMethod::Ldap,
+            aaguid: None,
+        },
+        &user_id,
+        country.as_deref(),
+        Some(&user_agent),
+    )
+    .await?;
+    if let Some(origin) = redirect_origin.as_deref() {
+        app_session_ttl(&state, origin, &user_id).await?;
+    }
+    audit::record(
+        &state,
+        AuditKind::Login,
+        AuditEvent {
+            user_id: Some(&user_id),
+            ip: Some(&ip),
+            country: country.as_deref(),
+            user_agent: Some(&user_agent),
+            detail: Some(&format!("ldap: {}", req.username)),
+            app: redirect_origin.as_deref(),
+        },
+    )
+    .await;
+
+    let cookie_domain = request_cookie_domain(&state, &headers);
+    let jar = start_session(
+        &state,
+        jar,
+        &user_id,
+        auth::SESSION_TTL,
+        request_secure_cookie(&headers, state.secure_cookies),
+        cookie_domain,
+        &session::ClientInfo {
+            user_agent: Some(&user_agent),
+            ip: Some(&ip),
+            country: country.as_deref(),
+            origin: None,
+        },
+    )
+    .await?;
+
+    let redirect_url = post_login_redirect(
+        &state,
+        &user_id,
+        redirect_origin.as_deref(),
+        redirect_path.as_deref(),
+        cookie_domain,
+    );
+    Ok((
+        jar,
+        Json(serde_json::json!({
+            "success": true,
+            "redirect_url": redirect_url,
+        })),
+    ))
+}
+
+async fn totp_status(
+    State(state): State<AppState>,
+    auth: AuthUser,
+) -> Result<Json<TotpStatus>, StatusCode> {
+    let enrolled = state
+        .db
+        .totp_enrolled(&auth.user_id)
+        .await
+        .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?;
+    Ok(Json(TotpStatus { enrolled }))
+}
+
+/// Start (or restart) enrollment; TOTP login stays off until `/totp/confirm`.
+async fn enroll_totp(
+    State(state): State<AppState>,
+    auth: AuthUser,
+) -> Result<Json<TotpEnrollment>, StatusCode> {
+    let user = state
+        .db
+        .get_user(&auth.user_id)
+        .await
+        .ok()
+        .flatten()
+        .ok_or(StatusCode::INTERNAL_SERVER_ERROR)?;
+    let secret = totp::generate_secret();
+    state
+        .db
+        .start_totp_enrollment(&auth.user_id, &secret)
+        .await
+        .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?;
+    Ok(Json(TotpEnrollment {
+        uri: totp::provisioning_uri(&secret, "den", &user.name),
+    }))
+}
+
+async fn confirm_totp(
+    State(state): State<AppState>,
+    auth: AuthUser,
+    Json(req): Json<TotpConfirmRequest>,
+) -> Result<StatusCode, StatusCode> {
+    let secret = state
+        .db
+        .pending_totp_secret(&auth.user_id)
+        .await
+        .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?;
+    let secret = secret.ok_or(StatusCode::NOT_FOUND)?;
+    let now = time::OffsetDateTime::now_utc().unix_timestamp();
+    let step = totp::verify(&secret, &req.code, now, None).ok_or(StatusCode::BAD_REQUEST)?;
+    state
+        .db
+        .confirm_totp(&auth.user_id, step)
+        .await
+        .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?;
+    Ok(StatusCode::NO_CONTENT)
+}
+
+async fn remove_totp(
+    State(state): State<AppState>,
+    auth: AuthUser,
+) -> Result<StatusCode, StatusCode> {
+    state
+        .db
+        .delete_totp(&auth.user_id)
+        .await
+        .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?;
+    Ok(StatusCode::NO_CONTENT)
+}
@@ -0,0 +1,93 @@
+use axum::extract::{Query, State};
+use axum::http::StatusCode;
+use axum::routing::get;
+use axum::{Json, Router};
+use serde::{Deserialize, Serialize};
+
+use crate::audit;
+use crate::auth::AuthUser;
+use crate::state::AppState;
+use crate::user_agent;
+
+const DEFAULT_LIMIT: i64 = 20;
+const MAX_LIMIT: i64 = 100;
+
+#[derive(Deserialize)]
+struct HistoryQuery {
+    /// Only events older than this id: the previous page's `next_before`.
+    before: Option<i64>,
+    limit: Option<i64>,
+}
+
+#[derive(Serialize)]
+struct LoginHistory {
+    logins: Vec<LoginInfo>,
+    /// Cursor for the next page, absent on the last one.
+    next_before: Option<i64>,
+}
+
+#[derive(Serialize)]
+struct LoginInfo {
+    id: i64,
+    /// `login`, `login_failed` or `login_anomaly`.
+    kind: String,
+    time: String,
+    browser: Option<String>,
+    os: Option<&'static str>,
+    ip: Option<String>,
+    country: Option<String>,
+    /// The origin the login redirected to, if any.
+    app: Option<String>,
+    /// The `[[apps]]` name for `app`, if it has a policy.
+    app_name: Option<String>,
+    detail: Option<String>,
+}
+
+pub fn router() -> Router<AppState> {
+    Router::new().route("/", get(list_logins))
+}
+
+async fn list_logins(
+    State(state): State<AppState>,
+    auth: AuthUser,
+    Query(query): Query<HistoryQuery>,
+) -> Result<Json<LoginHistory>, StatusCode> {
+    let limit = query.limit.unwrap_or(DEFAULT_LIMIT).clamp(1, MAX_LIMIT);
+    // One extra row tells whether another page exists.
+    let mut rows = audit::logins(&state.db, &auth.user_id, query.before, limit + 1)
+        .await
+        .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?;
+    let next_before = if rows.len() as i64 > limit {
+        rows.truncate(limit as usize);
+        rows.last().map(|row| row.id)
+    } else {
+        None
+    };
+
+    let logins = rows
+        .into_iter()
+        .map(|row| {
+            let device = user_agent::parse(row.user_agent.as_deref().unwrap_or_default());
+            LoginInfo {
+                app_name: row
+                    .app
+                    .as_deref()
+                    .and_then(|origin| state.apps.get(origin))
+                    .map(|app| app.name.clone()),
+                id: row.id,
+                kind: row.kind,
+                time: row.created,
+                browser: device.browser_label(),
+                os: device.os,
+                ip: row.ip,
+                country: row.country,
+                app: row.app,
+                detail: row.detail,
+            }
+        })
+        .collect();
+    Ok(Json(LoginHistory {
+        logins,
+        next_before,
+    }))
+}
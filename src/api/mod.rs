@@ -1,11 +1,71 @@
+mod admin;
 mod auth;
+mod break_glass;
+mod cache;
+mod config;
+mod connected_apps;
+#[cfg(feature = "dev-auth")]
+mod dev_login;
+mod error;
+mod forward_auth;
 mod health;
+mod ldap;
+mod login_history;
+mod oidc;
+mod passkey_backup;
+mod recovery;
+mod service_accounts;
+mod sessions;
+mod setup;
+mod sub_accounts;
+#[cfg(feature = "testing")]
+mod testing;
 
 use crate::state::AppState;
 use axum::Router;
+use axum::middleware::from_fn;
+use error::ApiError;
 
 pub fn router() -> Router<AppState> {
     Router::new()
         .route("/health", axum::routing::get(health::check))
+        .route("/verify", axum::routing::get(forward_auth::verify))
         .merge(auth::router())
+        .merge(passkey_backup::router())
+        .merge(break_glass::router())
+        .nest("/admin", admin::router())
+        .nest("/config", config::router())
+        .nest("/ldap", ldap::router())
+        .nest("/oidc", oidc::router())
+        .nest("/recovery", recovery::router())
+        .nest("/sessions", sessions::router())
+        .nest("/setup", setup::router())
+        .nest("/user/apps", connected_apps::router())
+        .nest("/user/logins", login_history::router())
+        .merge(dev_routes())
+        .nest("/testing", testing_routes())
+        // Unknown API paths answer here, not in the frontend fallback, so they
+        // get a JSON error and `cache::headers` too.
+        .fallback(|| async { ApiError::NOT_FOUND })
+        .layer(from_fn(cache::headers))
+}
+
+#[cfg(feature = "dev-auth")]
+fn dev_routes() -> Router<AppState> {
+    dev_login::router()
+}
+
+#[cfg(not(feature = "dev-auth"))]
+fn dev_routes() -> Router<AppState> {
+    Router::new()
+}
+
+#[cfg(feature = "testing")]
+fn testing_routes() -> Router<AppState> {
+    testing::router()
+}
+
+#[cfg(not(feature = "testing"))]
+fn testing_routes() -> Router<AppState> {
+    Router::new()
 }
@@ -0,0 +1,232 @@
+use std::net::SocketAddr;
+
+use axum::Router;
+use axum::extract::{ConnectInfo, Query, State};
+use axum::http::HeaderMap;
+use axum::response::Redirect;
+use axum::routing::get;
+use axum_extra::extract::cookie::CookieJar;
+use serde::{Deserialize, Serialize};
+use uuid::Uuid;
+
+use super::auth::{
+    LoginBeginRequest, app_session_ttl, check_access_policy, check_login_country,
+    check_login_throttle, login_redirect_target, post_login_redirect, request_cookie_domain,
+    request_secure_cookie, start_session,
+};
+use super::error::ApiError;
+use crate::audit::{self, AuditEvent, AuditKind};
+use crate::auth;
+use crate::config::LoginMethod;
+use crate::db;
+use crate::origin::{client_ip, request_user_agent};
+use crate::policy;
+use crate::session;
+use crate::state::AppState;
+
+/// Server-side half of an upstream authorization request, keyed by `state`.
+#[derive(Serialize, Deserialize)]
+struct OidcContext {
+    nonce: String,
+    verifier: String,
+    redirect_origin: Option<String>,
+    redirect_path: Option<String>,
+}
+
+#[derive(Deserialize)]
+struct CallbackQuery {
+    state: String,
+    code: Option<String>,
+    error: Option<String>,
+}
+
+pub fn router() -> Router<AppState> {
+    Router::new()
+        .route("/login", get(login))
+        .route("/callback", get(callback))
+}
+
+/// Start an authorization-code flow at the upstream IdP; takes the same redirect
+/// parameters as `/api/login/begin`.
+async fn login(
+    State(state): State<AppState>,
+    jar: CookieJar,
+    headers: HeaderMap,
+    Query(req): Query<LoginBeginRequest>,
+) -> Result<(CookieJar, Redirect), ApiError> {
+    let oidc = state.upstream_oidc.clone().ok_or(ApiError::NOT_FOUND)?;
+    let (redirect_origin, redirect_path) = login_redirect_target(&state, &req)?;
+
+    let request = tokio::task::spawn_blocking(move || oidc.authorize())
+        .await
+        .map_err(|_| ApiError::INTERNAL)?
+        .map_err(|e| {
+            tracing::error!(error = e, "upstream oidc discovery failed");
+            ApiError::UPSTREAM_UNAVAILABLE
+        })?;
+
+    let context = OidcContext {
+        nonce: request.nonce,
+        verifier: request.verifier,
+        redirect_origin,
+        redirect_path,
+    };
+    let state_json = serde_json::to_string(&context).map_err(|_| ApiError::INTERNAL)?;
+    let secure = request_secure_cookie(&headers, state.secure_cookies);
+    let (jar, binding) = auth::bind_challenge(&state.cookie, jar, secure);
+    state
+        .storage
+        .insert_challenge(
+            &request.state,
+            db::CHALLENGE_OIDC,
+            &binding,
+            &state_json,
+            10,
+        )
+        .await
+        .map_err(|_| ApiError::INTERNAL)?;
+
+    Ok((jar, Redirect::to(&request.url)))
+}
+
+async fn callback(
+    State(state): State<AppState>,
+    ConnectInfo(peer): ConnectInfo<SocketAddr>,
+    jar: CookieJar,
+    headers: HeaderMap,
+    Query(query): Query<CallbackQuery>,
+) -> Result<(CookieJar, Redirect), ApiError> {
+    let oidc = state.upstream_oidc.clone().ok_or(ApiError::NOT_FOUND)?;
+    let state_json = state
+        .storage
+        .take_challenge(
+            &query.state,
+            db::CHALLENGE_OIDC,
+            &auth::presented_binding(&state.cookie, &jar),
+        )
+        .await
+        .map_err(|_| ApiError::INTERNAL)?
+        .ok_or(ApiError::CHALLENGE_EXPIRED)?;
+    let context: OidcContext = serde_json::from_str(&state_json).map_err(|_| ApiError::INTERNAL)?;
+
+    let client = client_ip(&headers, peer.ip(), &state.trusted_proxies);
+    let ip = client.to_string();
+    // Before the code exchange, as `ldap::login` checks before the bind.
+    check_login_throttle(&state, &ip).await?;
+    let country = state.geoip.as_ref().and_then(|g| g.country(client));
+    let user_agent = request_user_agent(&headers);
+
+    let outcome = match (query.error, query.code) {
+        (Some(error), _) => Err(format!("idp returned {error}")),
+        (None, None) => Err("callback without code".into()),
+        (None, Some(code)) => {
+            let (verifier, nonce) = (context.verifier, context.nonce);
+            tokio::task::spawn_blocking(move || oidc.complete(&code, &verifier, &nonce))
+                .await
+                .map_err(|_| ApiError::INTERNAL)?
+        }
+    };
+    let upstream_user = match outcome {
+        Ok(user) => user,
+        Err(error) => {
+            tracing::warn!(error, "upstream oidc login failed");
+            audit::record(
+                &state,
+                AuditKind::LoginFailed,
+                AuditEvent {
+                    ip: Some(&ip),
+                    country: country.as_deref(),
+                    user_agent: Some(&user_agent),
+                    detail: Some(&format!("upstream_oidc: {error}")),
+                    ..Default::default()
+                },
+            )
+            .await;
+            return Err(ApiError::UPSTREAM_REJECTED);
+        }
+    };
+
+    let user_id = den_user(&state, &upstream_user).await?;
+    check_login_country(&state, country.as_deref(), &user_id, &ip, Some(&user_agent)).await?;
+    check_access_policy(
+        &state,
+        &policy::Request {
+            ip: client,
+            app: context
+                .redirect_origin
+                .as_deref()
+                .unwrap_or(&state.rp_origin),
+            method: LoginMethod::Oidc,
+            aaguid: None,
+        },
+        &user_id,
+        country.as_deref(),
+        Some(&user_agent),
+    )
+    .await?;
+    if let Some(origin) = context.redirect_origin.as_deref() {
+        app_session_ttl(&state, origin, &user_id).await?;
+    }
+    audit::record(
+        &state,
+        AuditKind::Login,
+        AuditEvent {
+            user_id: Some(&user_id),
+            ip: Some(&ip),
+            country: country.as_deref(),
+            user_agent: Some(&user_agent),
+            detail: Some(&format!("upstream_oidc: {upstream_user}")),
+            app: context.redirect_origin.as_deref(),
+        },
+    )
+    .await;
+
+    let cookie_domain = request_cookie_domain(&state, &headers);
+    let jar = start_session(
+        &state,
+        jar,
+        &user_id,
+        auth::SESSION_TTL,
+        request_secure_cookie(&headers, state.secure_cookies),
+        cookie_domain,
+        &session::ClientInfo {
+            user_agent: Some(&user_agent),
+            ip: Some(&ip),
+            country: country.as_deref(),
+            origin: None,
+        },
+    )
+    .await?;
+
+    let target = post_login_redirect(
+        &state,
+        &user_id,
+        context.redirect_origin.as_deref(),
+        context.redirect_path.as_deref(),
+        cookie_domain,
+    );
+    Ok((jar, Redirect::to(target.as_deref().unwrap_or("/"))))
+}
+
+/// den is single-user: an allowed upstream identity signs in as that user, and
+/// creates it (named after the upstream claim) on a fresh instance.
+async fn den_user(state: &AppState, upstream_user: &str) -> Result<String, ApiError> {
+    let existing = state.db.only_user().await.map_err(|_| ApiError::INTERNAL)?;
+    if let Some(user) = existing {
+        return Ok(user.id);
+    }
+    let id = Uuid::new_v4().to_string();
+    let created = state
+        .db
+        .create_only_user(&id, upstream_user)
+        .await
+        .map_err(|_| ApiError::INTERNAL)?;
+    if !created {
+        return Err(ApiError::ALREADY_REGISTERED);
+    }
+    tracing::info!(
+        user = upstream_user,
+        "created user from upstream oidc login"
+    );
+    Ok(id)
+}
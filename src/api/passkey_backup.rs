@@ -0,0 +1,158 @@
+use axum::extract::State;
+use axum::http::{HeaderMap, StatusCode};
+use axum::routing::{get, post};
+use axum::{Json, Router};
+use serde::{Deserialize, Serialize};
+use webauthn_rs::prelude::Passkey;
+
+use crate::auth::{self, AuthUser, MaybeAuthUser};
+use crate::backup::{self, Envelope, MIN_PASSPHRASE_LEN};
+use crate::db::BackupPasskey;
+use crate::state::AppState;
+
+/// Kept out of the URL so it never lands in proxy access logs.
+const PASSPHRASE_HEADER: &str = "x-den-passphrase";
+
+#[derive(Serialize, Deserialize)]
+struct PasskeyBackup {
+    exported_at: i64,
+    user_id: String,
+    user_name: String,
+    passkeys: Vec<BackupPasskey>,
+}
+
+#[derive(Deserialize)]
+struct ImportRequest {
+    passphrase: String,
+    envelope: Envelope,
+    /// Required for a fresh instance when `bootstrap_token` is configured.
+    bootstrap_token: Option<String>,
+}
+
+pub fn router() -> Router<AppState> {
+    Router::new()
+        .route("/passkeys/export", get(export_passkeys))
+        .route("/passkeys/import", post(import_passkeys))
+}
+
+async fn export_passkeys(
+    State(state): State<AppState>,
+    auth: AuthUser,
+    headers: HeaderMap,
+) -> Result<Json<Envelope>, StatusCode> {
+    let passphrase = headers
+        .get(PASSPHRASE_HEADER)
+        .and_then(|v| v.to_str().ok())
+        .filter(|p| p.chars().count() >= MIN_PASSPHRASE_LEN)
+        .ok_or(StatusCode::BAD_REQUEST)?
+        .to_owned();
+    if passphrase_breached(&state, &passphrase).await {
+        return Err(StatusCode::UNPROCESSABLE_ENTITY);
+    }
+
+    let user = state
+        .db
+        .get_user(&auth.user_id)
+        .await
+        .ok()
+        .flatten()
+        .ok_or(StatusCode::INTERNAL_SERVER_ERROR)?;
+    let passkeys = state
+        .db
+        .backup_passkeys(&auth.user_id)
+        .await
+        .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?;
+    let count = passkeys.len();
+
+    let plaintext = serde_json::to_vec(&PasskeyBackup {
+        exported_at: time::OffsetDateTime::now_utc().unix_timestamp(),
+        user_id: auth.user_id.clone(),
+        user_name: user.name,
+        passkeys,
+    })
+    .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?;
+    let envelope = tokio::task::spawn_blocking(move || backup::seal(&passphrase, &plaintext))
+        .await
+        .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?
+        .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?;
+
+    tracing::info!(user_id = auth.user_id, count, "exported passkey backup");
+    Ok(Json(envelope))
+}
+
+/// Known-breached passphrases are refused. Lookup failures only log: a flaky breach
+/// source shouldn't block backups.
+async fn passphrase_breached(state: &AppState, passphrase: &str) -> bool {
+    let Some(check) = state.breach_check.clone() else {
+        return false;
+    };
+    let passphrase = passphrase.to_owned();
+    match tokio::task::spawn_blocking(move || check.is_breached(&passphrase)).await {
+        Ok(Ok(breached)) => breached,
+        Ok(Err(error)) => {
+            tracing::warn!(error = %error, "breached password check failed");
+            false
+        }
+        Err(_) => false,
+    }
+}
+
+/// Restores into a fresh instance (no user yet) or merges into the signed-in user.
+/// Credentials are bound to the original user handle, so a different user is a conflict.
+async fn import_passkeys(
+    State(state): State<AppState>,
+    auth: MaybeAuthUser,
+    Json(req): Json<ImportRequest>,
+) -> Result<Json<serde_json::Value>, StatusCode> {
+    let existing = state
+        .db
+        .only_user()
+        .await
+        .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?
+        .map(|user| user.id);
+    if existing.is_some() && auth.0.is_none() {
+        return Err(StatusCode::UNAUTHORIZED);
+    }
+    if existing.is_none() && !auth::bootstrap_token_ok(&state, req.bootstrap_token.as_deref()) {
+        return Err(StatusCode::FORBIDDEN);
+    }
+
+    let ImportRequest {
+        passphrase,
+        envelope,
+        ..
+    } = req;
+    let plaintext = tokio::task::spawn_blocking(move || backup::open(&passphrase, &envelope))
+        .await
+        .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?
+        .ok_or(StatusCode::BAD_REQUEST)?;
+    let backup: PasskeyBackup =
+        serde_json::from_slice(&plaintext).map_err(|_| StatusCode::BAD_REQUEST)?;
+    if existing.as_ref().is_some_and(|id| *id != backup.user_id) {
+        return Err(StatusCode::CONFLICT);
+    }
+
+    let parsed = backup
+        .passkeys
+        .iter()
+        .map(|passkey| serde_json::from_str::<Passkey>(&passkey.data).map(|pk| (passkey, pk)))
+        .collect::<Result<Vec<_>, _>>()
+        .map_err(|_| StatusCode::BAD_REQUEST)?;
+    let create_user = existing.is_none().then_some(backup.user_name.as_str());
+    let (imported, skipped) = state
+        .db
+        .import_passkeys(&backup.user_id, create_user, &parsed)
+        .await
+        .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?;
+
+    tracing::info!(
+        user_id = backup.user_id,
+        imported,
+        skipped,
+        "imported passkey backup"
+    );
+    Ok(Json(serde_json::json!({
+        "imported": imported,
+        "skipped": skipped,
+    })))
+}
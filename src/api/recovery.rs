@@ -0,0 +1,217 @@
+use axum::extract::State;
+use axum::http::StatusCode;
+use axum::routing::{get, post};
+use axum::{Json, Router};
+use serde::{Deserialize, Serialize};
+
+use super::error::ApiError;
+use crate::auth::AuthUser;
+use crate::config::RecoveryConfig;
+use crate::db::PendingRecovery;
+use crate::logout;
+use crate::notify::SecurityEvent;
+use crate::session;
+use crate::state::AppState;
+
+/// How long the passkey invite handed out by a completed recovery stays valid.
+const RECOVERY_INVITE_TTL_MINUTES: i64 = 15;
+
+#[derive(Serialize)]
+struct RecoveryStatus {
+    has_code: bool,
+    pending: Option<PendingRecovery>,
+    delay_hours: u32,
+}
+
+#[derive(Serialize)]
+struct RecoveryCode {
+    url: String,
+}
+
+#[derive(Deserialize)]
+struct TokenRequest {
+    token: String,
+}
+
+#[derive(Serialize)]
+struct RequestResponse {
+    ready_at: String,
+}
+
+#[derive(Serialize)]
+struct CompleteResponse {
+    invite_url: String,
+}
+
+pub fn router() -> Router<AppState> {
+    Router::new()
+        .route("/", get(status))
+        .route("/code", post(create_code))
+        .route("/request", post(request_recovery).delete(cancel_recovery))
+        .route("/complete", post(complete_recovery))
+}
+
+fn enabled(state: &AppState) -> Result<RecoveryConfig, ApiError> {
+    state.recovery.ok_or(ApiError::NOT_FOUND)
+}
+
+async fn status(
+    State(state): State<AppState>,
+    auth: AuthUser,
+) -> Result<Json<RecoveryStatus>, StatusCode> {
+    let config = enabled(&state)?;
+    let has_code = state
+        .db
+        .has_recovery_code(&auth.user_id)
+        .await
+        .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?;
+    let pending = state
+        .db
+        .pending_recovery(&auth.user_id)
+        .await
+        .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?;
+    Ok(Json(RecoveryStatus {
+        has_code,
+        pending,
+        delay_hours: config.delay_hours,
+    }))
+}
+
+/// Issue a new recovery URL to print, invalidating the previous one.
+async fn create_code(
+    State(state): State<AppState>,
+    auth: AuthUser,
+) -> Result<Json<RecoveryCode>, StatusCode> {
+    enabled(&state)?;
+    let token = session::new_refresh_token();
+    state
+        .db
+        .set_recovery_code(&auth.user_id, &session::hash_token(&token))
+        .await
+        .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?;
+
+    tracing::info!(user_id = auth.user_id, "recovery code created");
+    Ok(Json(RecoveryCode {
+        url: format!("{}/recover?token={token}", state.rp_origin),
+    }))
+}
+
+/// Start the recovery delay. Repeating the request reports the same `ready_at`
+/// without notifying again.
+async fn request_recovery(
+    State(state): State<AppState>,
+    Json(req): Json<TokenRequest>,
+) -> Result<Json<RequestResponse>, ApiError> {
+    let config = enabled(&state)?;
+    let user_id = state
+        .db
+        .recovery_code_owner(&session::hash_token(&req.token))
+        .await
+        .map_err(|_| ApiError::INTERNAL)?
+        .ok_or(ApiError::RECOVERY_CODE_INVALID)?;
+    let started = state
+        .db
+        .request_recovery(&user_id, config.delay_hours)
+        .await
+        .map_err(|_| ApiError::INTERNAL)?;
+    // Gone only if a signed-in session just cancelled it, which revoked the URL too.
+    let pending = state
+        .db
+        .pending_recovery(&user_id)
+        .await
+        .map_err(|_| ApiError::INTERNAL)?
+        .ok_or(ApiError::RECOVERY_CODE_INVALID)?;
+
+    if started {
+        tracing::warn!(
+            user_id,
+            ready_at = pending.ready_at,
+            "account recovery requested"
+        );
+        state.notifier.send(SecurityEvent::RecoveryRequested {
+            user_name: user_name(&state, &user_id).await,
+            ready_at: pending.ready_at.clone(),
+        });
+    }
+    Ok(Json(RequestResponse {
+        ready_at: pending.ready_at,
+    }))
+}
+
+/// Any signed-in session can stop a pending recovery. The recovery URL is revoked
+/// too; the owner prints a new one.
+async fn cancel_recovery(
+    State(state): State<AppState>,
+    auth: AuthUser,
+) -> Result<StatusCode, StatusCode> {
+    enabled(&state)?;
+    let cancelled = state
+        .db
+        .cancel_recovery(&auth.user_id)
+        .await
+        .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?;
+    if !cancelled {
+        return Err(StatusCode::NOT_FOUND);
+    }
+
+    tracing::info!(user_id = auth.user_id, "account recovery cancelled");
+    state.notifier.send(SecurityEvent::RecoveryCancelled {
+        user_name: user_name(&state, &auth.user_id).await,
+    });
+    Ok(StatusCode::NO_CONTENT)
+}
+
+/// Once the delay has passed: sign out every session and hand back a passkey
+/// invite for the recovered account. The recovery URL is used up.
+async fn complete_recovery(
+    State(state): State<AppState>,
+    Json(req): Json<TokenRequest>,
+) -> Result<Json<CompleteResponse>, ApiError> {
+    enabled(&state)?;
+    let token_hash = session::hash_token(&req.token);
+    let user_id = state
+        .db
+        .recovery_code_owner(&token_hash)
+        .await
+        .map_err(|_| ApiError::INTERNAL)?
+        .ok_or(ApiError::RECOVERY_CODE_INVALID)?;
+    let completed = state
+        .db
+        .complete_recovery(&user_id, &token_hash)
+        .await
+        .map_err(|_| ApiError::INTERNAL)?;
+    if !completed {
+        return Err(ApiError::RECOVERY_NOT_READY);
+    }
+
+    let revoked = session::revoke_all(&state.storage, &user_id)
+        .await
+        .map_err(|_| ApiError::INTERNAL)?;
+    logout::notify_apps(&state, &user_id, None);
+    let invite = session::new_refresh_token();
+    state
+        .db
+        .create_invite(
+            &session::hash_token(&invite),
+            &user_id,
+            RECOVERY_INVITE_TTL_MINUTES,
+        )
+        .await
+        .map_err(|_| ApiError::INTERNAL)?;
+
+    tracing::warn!(user_id, revoked, "account recovered");
+    state.notifier.send(SecurityEvent::RecoveryCompleted {
+        user_name: user_name(&state, &user_id).await,
+    });
+    Ok(Json(CompleteResponse {
+        invite_url: format!("{}/invite?token={invite}", state.rp_origin),
+    }))
+}
+
+/// For notifications; falls back to the id if the lookup fails.
+async fn user_name(state: &AppState, user_id: &str) -> String {
+    match state.db.get_user(user_id).await {
+        Ok(Some(user)) => user.name,
+        _ => user_id.to_owned(),
+    }
+}
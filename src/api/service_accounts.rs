@@ -0,0 +1,104 @@
+use axum::extract::{Path, State};
+use axum::http::StatusCode;
+use axum::routing::{delete, get};
+use axum::{Json, Router};
+use serde::{Deserialize, Serialize};
+use uuid::Uuid;
+
+use crate::auth::AuthUser;
+use crate::db::ServiceAccountInfo;
+use crate::service_account;
+use crate::session;
+use crate::state::AppState;
+
+#[derive(Deserialize)]
+struct CreateRequest {
+    name: String,
+    scopes: Vec<String>,
+    /// Never expires when omitted.
+    expires_in_days: Option<u32>,
+}
+
+#[derive(Serialize)]
+struct CreateResponse {
+    id: String,
+    /// Shown once; only its hash is stored.
+    token: String,
+    expires_at: Option<String>,
+}
+
+pub fn router() -> Router<AppState> {
+    Router::new()
+        .route("/", get(list).post(create))
+        .route("/{id}", delete(revoke))
+}
+
+async fn list(
+    State(state): State<AppState>,
+    _auth: AuthUser,
+) -> Result<Json<Vec<ServiceAccountInfo>>, StatusCode> {
+    let accounts = state
+        .db
+        .list_service_accounts()
+        .await
+        .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?;
+    Ok(Json(accounts))
+}
+
+async fn create(
+    State(state): State<AppState>,
+    auth: AuthUser,
+    Json(req): Json<CreateRequest>,
+) -> Result<Json<CreateResponse>, StatusCode> {
+    let name = req.name.trim();
+    if name.is_empty() || name.len() > 64 {
+        return Err(StatusCode::BAD_REQUEST);
+    }
+    let scopes = service_account::normalize_scopes(&req.scopes).ok_or(StatusCode::BAD_REQUEST)?;
+    let id = Uuid::new_v4().to_string();
+    let token = service_account::new_token();
+
+    let expires_at = state
+        .db
+        .create_service_account(
+            &id,
+            name,
+            &scopes,
+            &session::hash_token(&token),
+            req.expires_in_days,
+        )
+        .await
+        .map_err(|e| match e {
+            sqlx::Error::Database(db) if db.is_unique_violation() => StatusCode::CONFLICT,
+            _ => StatusCode::INTERNAL_SERVER_ERROR,
+        })?;
+
+    tracing::info!(
+        user_id = auth.user_id,
+        account = name,
+        scopes,
+        "created service account"
+    );
+    Ok(Json(CreateResponse {
+        id,
+        token,
+        expires_at,
+    }))
+}
+
+async fn revoke(
+    State(state): State<AppState>,
+    auth: AuthUser,
+    Path(id): Path<String>,
+) -> Result<StatusCode, StatusCode> {
+    let deleted = state
+        .db
+        .delete_service_account(&id)
+        .await
+        .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?;
+    if !deleted {
+        return Err(StatusCode::NOT_FOUND);
+    }
+    tracing::info!(user_id = auth.user_id, id, "revoked service account");
+    Ok(StatusCode::NO_CONTENT)
+}
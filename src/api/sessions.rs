@@ -0,0 +1,98 @@
+use axum::extract::{Path, State};
+use axum::http::StatusCode;
+use axum::routing::{get, patch};
+use axum::{Json, Router};
+use serde::{Deserialize, Serialize};
+
+use crate::auth::AuthUser;
+use crate::logout;
+use crate::session;
+use crate::state::AppState;
+use crate::user_agent;
+
+#[derive(Serialize)]
+struct SessionInfo {
+    id: String,
+    name: Option<String>,
+    browser: Option<String>,
+    os: Option<&'static str>,
+    ip: Option<String>,
+    country: Option<String>,
+    created: String,
+    last_used: String,
+    current: bool,
+}
+
+#[derive(Deserialize)]
+struct RenameRequest {
+    name: Option<String>,
+}
+
+pub fn router() -> Router<AppState> {
+    Router::new()
+        .route("/", get(list_sessions))
+        .route("/{id}", patch(rename_session).delete(revoke_session))
+}
+
+async fn list_sessions(
+    State(state): State<AppState>,
+    auth: AuthUser,
+) -> Result<Json<Vec<SessionInfo>>, StatusCode> {
+    let rows = session::list(&state.storage, &auth.user_id)
+        .await
+        .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?;
+
+    Ok(Json(
+        rows.into_iter()
+            .map(|row| {
+                let device = user_agent::parse(row.user_agent.as_deref().unwrap_or_default());
+                SessionInfo {
+                    current: auth.session_id.as_deref() == Some(row.id.as_str()),
+                    id: row.id,
+                    name: row.name,
+                    browser: device.browser_label(),
+                    os: device.os,
+                    ip: row.ip,
+                    country: row.country,
+                    created: row.created,
+                    last_used: row.last_used,
+                }
+            })
+            .collect(),
+    ))
+}
+
+/// Label a session ("old laptop"); an empty or missing name clears it.
+async fn rename_session(
+    State(state): State<AppState>,
+    auth: AuthUser,
+    Path(id): Path<String>,
+    Json(req): Json<RenameRequest>,
+) -> Result<StatusCode, StatusCode> {
+    let name = req.name.as_deref().map(str::trim).filter(|n| !n.is_empty());
+    let renamed = session::rename(&state.storage, &auth.user_id, &id, name)
+        .await
+        .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?;
+
+    if !renamed {
+        return Err(StatusCode::NOT_FOUND);
+    }
+    Ok(StatusCode::NO_CONTENT)
+}
+
+async fn revoke_session(
+    State(state): State<AppState>,
+    auth: AuthUser,
+    Path(id): Path<String>,
+) -> Result<StatusCode, StatusCode> {
+    let revoked = session::revoke(&state.storage, &auth.user_id, &id)
+        .await
+        .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?;
+
+    if !revoked {
+        return Err(StatusCode::NOT_FOUND);
+    }
+    tracing::info!(session_id = id, "session revoked");
+    logout::notify_apps(&state, &auth.user_id, Some(&id));
+    Ok(StatusCode::NO_CONTENT)
+}
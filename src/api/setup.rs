@@ -0,0 +1,29 @@
+use axum::extract::State;
+use axum::http::StatusCode;
+use axum::routing::get;
+use axum::{Json, Router};
+use serde::Serialize;
+
+use crate::state::AppState;
+
+#[derive(Serialize)]
+struct SetupStatus {
+    setup_complete: bool,
+    bootstrap_token_required: bool,
+}
+
+pub fn router() -> Router<AppState> {
+    Router::new().route("/status", get(status))
+}
+
+async fn status(State(state): State<AppState>) -> Result<Json<SetupStatus>, StatusCode> {
+    let setup_complete = state
+        .db
+        .user_exists()
+        .await
+        .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?;
+    Ok(Json(SetupStatus {
+        setup_complete,
+        bootstrap_token_required: !setup_complete && state.bootstrap_token.is_some(),
+    }))
+}
@@ -0,0 +1,201 @@
+use axum::extract::{Path, State};
+use axum::http::StatusCode;
+use axum::routing::{delete, get, post, put};
+use axum::{Json, Router};
+use serde::{Deserialize, Serialize};
+use uuid::Uuid;
+
+use crate::auth::AuthUser;
+use crate::db::SubAccountInfo;
+use crate::logout;
+use crate::origin::{normalize_origin, origin_host};
+use crate::session;
+use crate::state::AppState;
+
+/// Invites for a sub-account go to someone else, so they outlive the owner's own.
+const INVITE_TTL_MINUTES: i64 = 24 * 60;
+
+#[derive(Deserialize)]
+struct CreateRequest {
+    name: String,
+    /// Origins of allowed hosts the sub-account may sign in to; never den's own.
+    apps: Vec<String>,
+}
+
+#[derive(Deserialize)]
+struct AppsRequest {
+    /// Replaces the list; empty shuts the sub-account out of every app.
+    apps: Vec<String>,
+}
+
+#[derive(Serialize)]
+struct CreateResponse {
+    id: String,
+    invite: Invite,
+}
+
+/// A passkey invite for the sub-account; the URL is shown once.
+#[derive(Serialize)]
+struct Invite {
+    url: String,
+    expires_at: String,
+}
+
+pub fn router() -> Router<AppState> {
+    Router::new()
+        .route("/", get(list).post(create))
+        .route("/{id}", delete(remove))
+        .route("/{id}/apps", put(set_apps))
+        .route("/{id}/invite", post(invite))
+}
+
+async fn list(
+    State(state): State<AppState>,
+    auth: AuthUser,
+) -> Result<Json<Vec<SubAccountInfo>>, StatusCode> {
+    let accounts = state
+        .db
+        .list_sub_accounts(&auth.user_id)
+        .await
+        .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?;
+    Ok(Json(accounts))
+}
+
+async fn create(
+    State(state): State<AppState>,
+    auth: AuthUser,
+    Json(req): Json<CreateRequest>,
+) -> Result<Json<CreateResponse>, StatusCode> {
+    let name = req.name.trim();
+    if name.is_empty() || name.len() > 64 {
+        return Err(StatusCode::BAD_REQUEST);
+    }
+    let apps = normalize_apps(&state, &req.apps).ok_or(StatusCode::BAD_REQUEST)?;
+    let id = Uuid::new_v4().to_string();
+    let created = state
+        .db
+        .create_sub_account(&id, name, &auth.user_id, &apps)
+        .await
+        .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?;
+    if !created {
+        return Err(StatusCode::CONFLICT);
+    }
+    let invite = new_invite(&state, &id).await?;
+
+    tracing::info!(
+        user_id = auth.user_id,
+        account = name,
+        apps = apps.join(" "),
+        "created sub-account"
+    );
+    Ok(Json(CreateResponse { id, invite }))
+}
+
+/// Replace the sub-account's apps and sign it out everywhere: its tokens carry the
+/// old list.
+async fn set_apps(
+    State(state): State<AppState>,
+    auth: AuthUser,
+    Path(id): Path<String>,
+    Json(req): Json<AppsRequest>,
+) -> Result<StatusCode, StatusCode> {
+    let apps = normalize_apps(&state, &req.apps).ok_or(StatusCode::BAD_REQUEST)?;
+    let updated = state
+        .db
+        .set_sub_account_apps(&auth.user_id, &id, &apps)
+        .await
+        .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?;
+    if !updated {
+        return Err(StatusCode::NOT_FOUND);
+    }
+    session::revoke_all(&state.storage, &id)
+        .await
+        .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?;
+    logout::notify_apps(&state, &id, None);
+    tracing::info!(
+        user_id = auth.user_id,
+        id,
+        apps = apps.join(" "),
+        "changed sub-account apps"
+    );
+    Ok(StatusCode::NO_CONTENT)
+}
+
+/// Another passkey invite, e.g. for a sub-account whose first one lapsed.
+async fn invite(
+    State(state): State<AppState>,
+    auth: AuthUser,
+    Path(id): Path<String>,
+) -> Result<Json<Invite>, StatusCode> {
+    let ours = state
+        .db
+        .is_sub_account_of(&auth.user_id, &id)
+        .await
+        .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?;
+    if !ours {
+        return Err(StatusCode::NOT_FOUND);
+    }
+    let invite = new_invite(&state, &id).await?;
+    tracing::info!(user_id = auth.user_id, id, "sub-account invite created");
+    Ok(Json(invite))
+}
+
+/// Sign the sub-account out everywhere, then delete it and its passkeys.
+async fn remove(
+    State(state): State<AppState>,
+    auth: AuthUser,
+    Path(id): Path<String>,
+) -> Result<StatusCode, StatusCode> {
+    let ours = state
+        .db
+        .is_sub_account_of(&auth.user_id, &id)
+        .await
+        .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?;
+    if !ours {
+        return Err(StatusCode::NOT_FOUND);
+    }
+    session::revoke_all(&state.storage, &id)
+        .await
+        .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?;
+    logout::notify_apps(&state, &id, None);
+    let deleted = state
+        .db
+        .delete_sub_account(&auth.user_id, &id)
+        .await
+        .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?;
+    if !deleted {
+        return Err(StatusCode::NOT_FOUND);
+    }
+    tracing::info!(user_id = auth.user_id, id, "deleted sub-account");
+    Ok(StatusCode::NO_CONTENT)
+}
+
+async fn new_invite(state: &AppState, user_id: &str) -> Result<Invite, StatusCode> {
+    let token = session::new_refresh_token();
+    let expires_at = state
+        .db
+        .create_invite(&session::hash_token(&token), user_id, INVITE_TTL_MINUTES)
+        .await
+        .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?;
+    Ok(Invite {
+        url: format!("{}/invite?token={token}", state.rp_origin),
+        expires_at,
+    })
+}
+
+/// Normalized, lowercase origins, each on an allowed host; `None` if any isn't, or
+/// names den itself, whose API is the owner's alone.
+fn normalize_apps(state: &AppState, apps: &[String]) -> Option<Vec<String>> {
+    let mut normalized = apps
+        .iter()
+        .map(|app| {
+            let origin = normalize_origin(app)?.to_ascii_lowercase();
+            let host = origin_host(&origin)?;
+            (state.allowed_hosts.contains(&host) && !origin.eq_ignore_ascii_case(&state.rp_origin))
+                .then_some(origin)
+        })
+        .collect::<Option<Vec<_>>>()?;
+    normalized.sort();
+    normalized.dedup();
+    Some(normalized)
+}
@@ -0,0 +1,70 @@
+use axum::extract::State;
+use axum::http::StatusCode;
+use axum::routing::{delete, post};
+use axum::{Json, Router};
+use url::Url;
+use webauthn_authenticator_rs::WebauthnAuthenticator;
+use webauthn_authenticator_rs::softpasskey::SoftPasskey;
+use webauthn_rs::prelude::{
+    CreationChallengeResponse, PublicKeyCredential, RegisterPublicKeyCredential,
+    RequestChallengeResponse,
+};
+
+use crate::state::AppState;
+
+/// A server-side soft passkey standing in for the browser's authenticator, so E2E
+/// runs (see `web/e2e/soft-authenticator.js`) can drive the real SPA ceremonies.
+pub fn router() -> Router<AppState> {
+    Router::new()
+        .route("/authenticator", delete(reset))
+        .route("/authenticator/create", post(create))
+        .route("/authenticator/get", post(get))
+}
+
+/// `navigator.credentials.create`: takes the `/api/register/begin` options.
+async fn create(
+    State(state): State<AppState>,
+    Json(options): Json<CreationChallengeResponse>,
+) -> Result<Json<RegisterPublicKeyCredential>, StatusCode> {
+    let origin = Url::parse(&state.rp_origin).map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?;
+    let mut authenticator = state
+        .soft_authenticator
+        .lock()
+        .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?;
+    authenticator
+        .do_registration(origin, options)
+        .map(Json)
+        .map_err(|e| {
+            tracing::warn!(error = ?e, "soft authenticator registration failed");
+            StatusCode::BAD_REQUEST
+        })
+}
+
+/// `navigator.credentials.get`: takes the `/api/login/begin` options.
+async fn get(
+    State(state): State<AppState>,
+    Json(options): Json<RequestChallengeResponse>,
+) -> Result<Json<PublicKeyCredential>, StatusCode> {
+    let origin = Url::parse(&state.rp_origin).map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?;
+    let mut authenticator = state
+        .soft_authenticator
+        .lock()
+        .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?;
+    authenticator
+        .do_authentication(origin, options)
+        .map(Json)
+        .map_err(|e| {
+            tracing::warn!(error = ?e, "soft authenticator assertion failed");
+            StatusCode::BAD_REQUEST
+        })
+}
+
+/// Forget every credential, e.g. between E2E tests sharing one backend.
+async fn reset(State(state): State<AppState>) -> Result<StatusCode, StatusCode> {
+    let mut authenticator = state
+        .soft_authenticator
+        .lock()
+        .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?;
+    *authenticator = WebauthnAuthenticator::new(SoftPasskey::new(true));
+    Ok(StatusCode::NO_CONTENT)
+}
@@ -0,0 +1,172 @@
+use std::collections::HashMap;
+
+use time::{Duration, OffsetDateTime};
+use url::Url;
+
+use crate::config::{AppPolicyConfig, ConfigError, invalid};
+use crate::origin::{normalize_origin, origin_host};
+use crate::policy::Window;
+
+#[derive(Debug, Clone)]
+pub struct AppPolicy {
+    pub name: String,
+    pub origin: String,
+    /// User ids or names allowed to reach this app; everyone when `None`.
+    pub allowed_users: Option<Vec<String>>,
+    pub session_ttl: Option<Duration>,
+    /// Granted in the `scope` claim of tokens issued for this app.
+    pub scopes: Vec<String>,
+    pub backchannel_logout: Option<BackchannelLogout>,
+    /// When the app can be reached (`access_hours`, `access_days`).
+    pub access: Window,
+}
+
+/// Where and with which key to send this app's logout tokens.
+#[derive(Debug, Clone)]
+pub struct BackchannelLogout {
+    pub uri: Url,
+    pub secret: String,
+}
+
+impl AppPolicy {
+    pub fn allows(&self, user_id: &str, user_name: Option<&str>) -> bool {
+        self.allowed_users.as_ref().is_none_or(|users| {
+            users
+                .iter()
+                .any(|u| u == user_id || Some(u.as_str()) == user_name)
+        })
+    }
+
+    pub fn open_at(&self, now: OffsetDateTime) -> bool {
+        self.access.contains(now)
+    }
+}
+
+/// Per-origin application policies, keyed by normalized origin.
+#[derive(Debug, Default)]
+pub struct AppPolicies {
+    by_origin: HashMap<String, AppPolicy>,
+}
+
+impl AppPolicies {
+    pub fn load(configured: &[AppPolicyConfig]) -> Result<Self, ConfigError> {
+        let by_origin = configured
+            .iter()
+            .map(|app| {
+                let origin = normalize_origin(&app.origin).ok_or_else(|| {
+                    invalid(
+                        "apps.origin",
+                        format!(
+                            "{:?} for app {:?} is not an http(s) origin",
+                            app.origin, app.name
+                        ),
+                    )
+                })?;
+                let policy = AppPolicy {
+                    name: app.name.clone(),
+                    origin: origin.clone(),
+                    allowed_users: app.allowed_users.clone(),
+                    session_ttl: app.session_ttl_seconds.map(Duration::seconds),
+                    scopes: app.scopes.clone(),
+                    backchannel_logout: backchannel_logout(app)?,
+                    access: Window::load(
+                        app.access_hours.as_deref(),
+                        &app.access_days,
+                        "apps.access_hours",
+                    )?,
+                };
+                Ok((origin.to_ascii_lowercase(), policy))
+            })
+            .collect::<Result<_, ConfigError>>()?;
+        Ok(Self { by_origin })
+    }
+
+    pub fn get(&self, origin: &str) -> Option<&AppPolicy> {
+        let origin = normalize_origin(origin)?;
+        self.by_origin.get(&origin.to_ascii_lowercase())
+    }
+
+    pub fn iter(&self) -> impl Iterator<Item = &AppPolicy> {
+        self.by_origin.values()
+    }
+
+    /// Hosts of all configured apps; these join `allowed_hosts` automatically.
+    pub fn hosts(&self) -> impl Iterator<Item = String> + '_ {
+        self.by_origin
+            .values()
+            .filter_map(|app| origin_host(&app.origin))
+    }
+}
+
+fn backchannel_logout(app: &AppPolicyConfig) -> Result<Option<BackchannelLogout>, ConfigError> {
+    let Some(uri) = app.logout_uri.as_deref() else {
+        return Ok(None);
+    };
+    let uri = Url::parse(uri)
+        .ok()
+        .filter(|u| matches!(u.scheme(), "http" | "https"))
+        .ok_or_else(|| {
+            invalid(
+                "apps.logout_uri",
+                format!("{uri:?} for app {:?} is not an http(s) URL", app.name),
+            )
+        })?;
+    let secret = app
+        .logout_secret
+        .clone()
+        .filter(|s| !s.is_empty())
+        .ok_or_else(|| {
+            invalid(
+                "apps.logout_secret",
+                format!("app {:?} has a logout_uri but no logout_secret", app.name),
+            )
+        })?;
+    Ok(Some(BackchannelLogout { uri, secret }))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn policies() -> AppPolicies {
+        AppPolicies::load(&[AppPolicyConfig {
+            name: "grafana".into(),
+            origin: "https://Grafana.lab.example:443".into(),
+            allowed_users: Some(vec!["brian".into()]),
+            session_ttl_seconds: Some(3600),
+            scopes: Vec::new(),
+            logout_uri: None,
+            logout_secret: None,
+            access_hours: Some("06:00-01:00".into()),
+            access_days: Vec::new(),
+        }])
+        .unwrap()
+    }
+
+    #[test]
+    fn lookup_normalizes_origin() {
+        let policies = policies();
+        let app = policies.get("https://grafana.lab.example").unwrap();
+        assert_eq!(app.name, "grafana");
+        assert_eq!(app.session_ttl, Some(Duration::hours(1)));
+        assert!(policies.get("https://other.lab.example").is_none());
+    }
+
+    #[test]
+    fn allowed_users_match_id_or_name() {
+        let policies = policies();
+        let app = policies.get("https://grafana.lab.example").unwrap();
+        assert!(app.allows("some-uuid", Some("brian")));
+        assert!(!app.allows("some-uuid", Some("guest")));
+    }
+
+    #[test]
+    fn access_hours_close_the_app() {
+        let policies = policies();
+        let app = policies.get("https://grafana.lab.example").unwrap();
+        let midnight = OffsetDateTime::from_unix_timestamp(1_767_225_600).unwrap();
+        assert!(app.open_at(midnight));
+        assert!(!app.open_at(midnight + Duration::hours(3)));
+        assert!(app.open_at(midnight + Duration::hours(6)));
+    }
+}
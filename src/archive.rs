@@ -0,0 +1,173 @@
+use std::io::{self, Read, Write};
+
+const BLOCK: usize = 512;
+
+/// Append a regular file to a ustar stream.
+pub fn write_entry(w: &mut impl Write, name: &str, data: &[u8], mtime: u64) -> io::Result<()> {
+    if name.len() > 100 {
+        return Err(io::Error::new(
+            io::ErrorKind::InvalidInput,
+            "archive entry name too long",
+        ));
+    }
+    let mut header = [0u8; BLOCK];
+    header[..name.len()].copy_from_slice(name.as_bytes());
+    write_octal(&mut header[100..108], 0o600);
+    write_octal(&mut header[108..116], 0);
+    write_octal(&mut header[116..124], 0);
+    write_octal(&mut header[124..136], data.len() as u64);
+    write_octal(&mut header[136..148], mtime);
+    header[156] = b'0';
+    header[257..263].copy_from_slice(b"ustar\0");
+    header[263..265].copy_from_slice(b"00");
+    header[148..156].fill(b' ');
+    let checksum: u32 = header.iter().map(|&b| u32::from(b)).sum();
+    header[148..155].copy_from_slice(format!("{checksum:06o}\0").as_bytes());
+
+    w.write_all(&header)?;
+    w.write_all(data)?;
+    w.write_all(&[0u8; BLOCK][..padding(data.len() as u64) as usize])
+}
+
+/// Terminate the stream with the two zero blocks tar expects.
+pub fn finish(w: &mut impl Write) -> io::Result<()> {
+    w.write_all(&[0u8; BLOCK * 2])
+}
+
+/// Walk a ustar stream, handing `visit` each regular file's name, size and
+/// contents. Nothing is buffered here, so a header's size is never trusted for
+/// an allocation; whatever `visit` leaves unread is skipped.
+pub fn read_entries(
+    r: &mut impl Read,
+    mut visit: impl FnMut(&str, u64, &mut dyn Read) -> io::Result<()>,
+) -> io::Result<()> {
+    let invalid = |msg: &str| io::Error::new(io::ErrorKind::InvalidData, msg.to_owned());
+    loop {
+        let mut header = [0u8; BLOCK];
+        r.read_exact(&mut header)?;
+        if header.iter().all(|&b| b == 0) {
+            return Ok(());
+        }
+        let mut stored = header;
+        stored[148..156].fill(b' ');
+        let checksum: u32 = stored.iter().map(|&b| u32::from(b)).sum();
+        if parse_octal(&header[148..156]) != Some(u64::from(checksum)) {
+            return Err(invalid("archive header checksum mismatch"));
+        }
+
+        let name_end = header[..100].iter().position(|&b| b == 0).unwrap_or(100);
+        let name = std::str::from_utf8(&header[..name_end])
+            .map_err(|_| invalid("archive entry name is not utf-8"))?;
+        let size = parse_octal(&header[124..136]).ok_or_else(|| invalid("bad entry size"))?;
+
+        let mut entry = r.by_ref().take(size);
+        if matches!(header[156], b'0' | 0) {
+            visit(name, size, &mut entry)?;
+        }
+        io::copy(&mut entry, &mut io::sink())?;
+        if entry.limit() != 0 {
+            return Err(io::ErrorKind::UnexpectedEof.into());
+        }
+        io::copy(&mut r.by_ref().take(padding(size)), &mut io::sink())?;
+    }
+}
+
+/// The whole of an entry `read_entries` passed along, refused above `limit`
+/// bytes.
+pub fn read_to_vec(entry: &mut dyn Read, size: u64, limit: u64) -> io::Result<Vec<u8>> {
+    if size > limit {
+        return Err(io::Error::new(
+            io::ErrorKind::InvalidData,
+            format!("archive entry of {size} bytes is over the {limit} byte limit"),
+        ));
+    }
+    let mut data = Vec::new();
+    entry.read_to_end(&mut data)?;
+    Ok(data)
+}
+
+fn padding(len: u64) -> u64 {
+    let block = BLOCK as u64;
+    (block - len % block) % block
+}
+
+fn write_octal(field: &mut [u8], value: u64) {
+    let digits = field.len() - 1;
+    field[..digits].copy_from_slice(format!("{value:0digits$o}").as_bytes());
+    field[digits] = 0;
+}
+
+fn parse_octal(field: &[u8]) -> Option<u64> {
+    let text = std::str::from_utf8(field).ok()?;
+    let text = text.trim_matches(|c: char| c == '\0' || c == ' ');
+    u64::from_str_radix(text, 8).ok()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn entries(buf: &[u8]) -> io::Result<Vec<(String, Vec<u8>)>> {
+        let mut entries = Vec::new();
+        read_entries(&mut &buf[..], |name, size, entry| {
+            entries.push((name.to_owned(), read_to_vec(entry, size, 4096)?));
+            Ok(())
+        })?;
+        Ok(entries)
+    }
+
+    #[test]
+    fn entries_roundtrip() {
+        let mut buf = Vec::new();
+        write_entry(&mut buf, "manifest.json", b"{}", 0).unwrap();
+        write_entry(&mut buf, "den.db", &[7u8; 1000], 1_700_000_000).unwrap();
+        finish(&mut buf).unwrap();
+        assert_eq!(buf.len() % BLOCK, 0);
+
+        let entries = entries(&buf).unwrap();
+        assert_eq!(entries.len(), 2);
+        assert_eq!(entries[0], ("manifest.json".to_owned(), b"{}".to_vec()));
+        assert_eq!(entries[1].1, vec![7u8; 1000]);
+
+        // A visitor that reads nothing still lands on the next header.
+        let mut names = Vec::new();
+        read_entries(&mut buf.as_slice(), |name, _, _| {
+            names.push(name.to_owned());
+            Ok(())
+        })
+        .unwrap();
+        assert_eq!(names, ["manifest.json", "den.db"]);
+    }
+
+    #[test]
+    fn corrupt_header_is_rejected() {
+        let mut buf = Vec::new();
+        write_entry(&mut buf, "a", b"x", 0).unwrap();
+        finish(&mut buf).unwrap();
+        buf[0] = b'b';
+        assert!(entries(&buf).is_err());
+    }
+
+    #[test]
+    fn oversized_entries_are_refused_not_allocated() {
+        let mut buf = Vec::new();
+        write_entry(&mut buf, "den.db", &[1u8; 8000], 0).unwrap();
+        finish(&mut buf).unwrap();
+        let error = entries(&buf).unwrap_err();
+        assert_eq!(error.kind(), io::ErrorKind::InvalidData);
+
+        // A header claiming far more than the stream holds is cut short, not
+        // trusted for a buffer.
+        let mut header = Vec::new();
+        write_entry(&mut header, "den.db", &[], 0).unwrap();
+        header[124..136].copy_from_slice(b"77777777777\0");
+        header[148..156].fill(b' ');
+        let checksum: u32 = header.iter().map(|&b| u32::from(b)).sum();
+        header[148..155].copy_from_slice(format!("{checksum:06o}\0").as_bytes());
+        let error = read_entries(&mut header.as_slice(), |_, _, entry| {
+            entry.read_to_end(&mut Vec::new()).map(drop)
+        })
+        .unwrap_err();
+        assert_eq!(error.kind(), io::ErrorKind::UnexpectedEof);
+    }
+}
@@ -0,0 +1,324 @@
+use std::collections::HashSet;
+
+use openssl::error::ErrorStack;
+use openssl::stack::Stack;
+use openssl::x509::store::{X509Store, X509StoreBuilder};
+use openssl::x509::{X509, X509StoreContext};
+use uuid::Uuid;
+
+use crate::config::{AttestationConfig, ConfigError, invalid};
+
+/// Authenticator data: rpIdHash (32), flags (1), signCount (4), then the AAGUID.
+const AAGUID_OFFSET: usize = 37;
+/// Flag bit set when attested credential data (AAGUID, credential) follows.
+const FLAG_ATTESTED_DATA: u8 = 0x40;
+const MAX_CBOR_DEPTH: usize = 16;
+
+/// Which authenticators may register passkeys, judged from the attestation object
+/// of `finish_passkey_registration`. webauthn-rs has already verified the
+/// attestation signature; this only looks at the AAGUID and the x5c chain.
+pub struct AttestationPolicy {
+    allowed_aaguids: Option<HashSet<Uuid>>,
+    denied_aaguids: HashSet<Uuid>,
+    trusted_roots: Option<X509Store>,
+}
+
+impl AttestationPolicy {
+    pub fn load(config: &AttestationConfig) -> Result<Self, ConfigError> {
+        let parse = |aaguids: &[String], key: &'static str| {
+            aaguids
+                .iter()
+                .map(|a| {
+                    Uuid::parse_str(a.trim())
+                        .map_err(|_| invalid(key, format!("{a:?} is not an AAGUID")))
+                })
+                .collect::<Result<HashSet<_>, _>>()
+        };
+        Ok(Self {
+            allowed_aaguids: (!config.allowed_aaguids.is_empty())
+                .then(|| parse(&config.allowed_aaguids, "attestation.allowed_aaguids"))
+                .transpose()?,
+            denied_aaguids: parse(&config.denied_aaguids, "attestation.denied_aaguids")?,
+            trusted_roots: config
+                .trusted_roots
+                .as_deref()
+                .map(load_roots)
+                .transpose()?,
+        })
+    }
+
+    /// The authenticator's AAGUID if its attestation satisfies the policy.
+    pub fn check(&self, attestation_object: &[u8]) -> Result<Uuid, String> {
+        let attestation = parse_attestation_object(attestation_object)?;
+        let aaguid = attestation.aaguid;
+        if self.denied_aaguids.contains(&aaguid) {
+            return Err(format!("authenticator {aaguid} is denied"));
+        }
+        if let Some(allowed) = &self.allowed_aaguids
+            && !allowed.contains(&aaguid)
+        {
+            return Err(format!("authenticator {aaguid} is not allowed"));
+        }
+        if let Some(roots) = &self.trusted_roots {
+            verify_chain(roots, &attestation.x5c)
+                .map_err(|e| format!("authenticator {aaguid}: {e}"))?;
+        }
+        Ok(aaguid)
+    }
+}
+
+fn load_roots(path: &str) -> Result<X509Store, ConfigError> {
+    const KEY: &str = "attestation.trusted_roots";
+    let pem = std::fs::read(path).map_err(|source| ConfigError::ReadFile {
+        key: KEY,
+        path: path.into(),
+        source,
+    })?;
+    let certs =
+        X509::stack_from_pem(&pem).map_err(|e| invalid(KEY, format!("invalid PEM: {e}")))?;
+    if certs.is_empty() {
+        return Err(invalid(KEY, format!("{path} contains no certificates")));
+    }
+    let store = (|| {
+        let mut store = X509StoreBuilder::new()?;
+        for cert in certs {
+            store.add_cert(cert)?;
+        }
+        Ok::<_, ErrorStack>(store.build())
+    })()
+    .map_err(|e| invalid(KEY, format!("failed to build the root store: {e}")))?;
+    Ok(store)
+}
+
+fn verify_chain(roots: &X509Store, x5c: &[Vec<u8>]) -> Result<(), String> {
+    let (leaf, intermediates) = x5c
+        .split_first()
+        .ok_or("attestation has no certificate (self or none attestation)")?;
+    let leaf = X509::from_der(leaf).map_err(|_| "invalid attestation certificate")?;
+    let mut chain = Stack::new().map_err(|e| e.to_string())?;
+    for der in intermediates {
+        let cert = X509::from_der(der).map_err(|_| "invalid attestation certificate")?;
+        chain.push(cert).map_err(|e| e.to_string())?;
+    }
+    let mut ctx = X509StoreContext::new().map_err(|e| e.to_string())?;
+    let verified = ctx
+        .init(roots, &leaf, &chain, |c| c.verify_cert())
+        .map_err(|e| e.to_string())?;
+    if !verified {
+        return Err("attestation certificate does not chain to a trusted root".into());
+    }
+    Ok(())
+}
+
+struct Attestation {
+    aaguid: Uuid,
+    /// DER certificates, attestation certificate first; empty for self/none attestation.
+    x5c: Vec<Vec<u8>>,
+}
+
+/// CBOR `{"fmt": ..., "attStmt": {...}, "authData": bytes}`.
+fn parse_attestation_object(data: &[u8]) -> Result<Attestation, String> {
+    let mut reader = Reader { data, pos: 0 };
+    let Cbor::Map(entries) = reader.value(0)? else {
+        return Err("attestation object is not a map".into());
+    };
+    let mut auth_data = None;
+    let mut x5c = Vec::new();
+    for (key, value) in entries {
+        match (key, value) {
+            (Cbor::Text(key), Cbor::Bytes(bytes)) if key == "authData" => auth_data = Some(bytes),
+            (Cbor::Text(key), Cbor::Map(statement)) if key == "attStmt" => {
+                for (key, value) in statement {
+                    if let (Cbor::Text(key), Cbor::Array(certs)) = (key, value)
+                        && key == "x5c"
+                    {
+                        x5c = certs
+                            .into_iter()
+                            .filter_map(|c| match c {
+                                Cbor::Bytes(der) => Some(der),
+                                _ => None,
+                            })
+                            .collect();
+                    }
+                }
+            }
+            _ => {}
+        }
+    }
+    let auth_data = auth_data.ok_or("attestation object has no authData")?;
+    let flags = *auth_data.get(32).ok_or("authData too short")?;
+    if flags & FLAG_ATTESTED_DATA == 0 {
+        return Err("authData carries no attested credential".into());
+    }
+    let aaguid = auth_data
+        .get(AAGUID_OFFSET..AAGUID_OFFSET + 16)
+        .and_then(|bytes| Uuid::from_slice(bytes).ok())
+        .ok_or("authData too short")?;
+    Ok(Attestation { aaguid, x5c })
+}
+
+/// The subset of CBOR attestation objects use; anything else is skipped as `Other`.
+#[derive(Debug, PartialEq)]
+enum Cbor {
+    Int(i128),
+    Bytes(Vec<u8>),
+    Text(String),
+    Array(Vec<Cbor>),
+    Map(Vec<(Cbor, Cbor)>),
+    Other,
+}
+
+struct Reader<'a> {
+    data: &'a [u8],
+    pos: usize,
+}
+
+impl Reader<'_> {
+    fn value(&mut self, depth: usize) -> Result<Cbor, String> {
+        if depth > MAX_CBOR_DEPTH {
+            return Err("CBOR nested too deeply".into());
+        }
+        let initial = self.take(1)?[0];
+        let (major, info) = (initial >> 5, initial & 0x1f);
+        if major == 7 {
+            // Simple values and floats: skip their payload.
+            self.take(match info {
+                24 => 1,
+                25 => 2,
+                26 => 4,
+                27 => 8,
+                _ => 0,
+            })?;
+            return Ok(Cbor::Other);
+        }
+        let arg = self.argument(info)?;
+        let len = usize::try_from(arg).map_err(|_| "CBOR length too large")?;
+        match major {
+            0 => Ok(Cbor::Int(i128::from(arg))),
+            1 => Ok(Cbor::Int(-1 - i128::from(arg))),
+            2 => Ok(Cbor::Bytes(self.take(len)?.to_vec())),
+            3 => String::from_utf8(self.take(len)?.to_vec())
+                .map(Cbor::Text)
+                .map_err(|_| "CBOR text is not UTF-8".into()),
+            4 => {
+                let mut items = Vec::with_capacity(len.min(64));
+                for _ in 0..len {
+                    items.push(self.value(depth + 1)?);
+                }
+                Ok(Cbor::Array(items))
+            }
+            5 => {
+                let mut entries = Vec::with_capacity(len.min(64));
+                for _ in 0..len {
+                    entries.push((self.value(depth + 1)?, self.value(depth + 1)?));
+                }
+                Ok(Cbor::Map(entries))
+            }
+            // Tag: the tagged value is all we need.
+            _ => self.value(depth + 1),
+        }
+    }
+
+    fn argument(&mut self, info: u8) -> Result<u64, String> {
+        let width = match info {
+            0..=23 => return Ok(u64::from(info)),
+            24 => 1,
+            25 => 2,
+            26 => 4,
+            27 => 8,
+            _ => return Err("indefinite-length CBOR is not supported".into()),
+        };
+        Ok(self
+            .take(width)?
+            .iter()
+            .fold(0, |acc, &b| (acc << 8) | u64::from(b)))
+    }
+
+    fn take(&mut self, len: usize) -> Result<&[u8], String> {
+        let end = self
+            .pos
+            .checked_add(len)
+            .filter(|&end| end <= self.data.len())
+            .ok_or("truncated CBOR")?;
+        let bytes = &self.data[self.pos..end];
+        self.pos = end;
+        Ok(bytes)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const YUBIKEY_5: &str = "cb69481e-8ff7-4039-93ec-0a2729a154a8";
+
+    /// `{"fmt": "packed", "attStmt": {"alg": -7, "x5c": [h'3001']}, "authData": ...}`
+    fn attestation_object(aaguid: Uuid) -> Vec<u8> {
+        let mut auth_data = vec![0u8; 32];
+        auth_data.push(FLAG_ATTESTED_DATA | 0x01);
+        auth_data.extend_from_slice(&[0, 0, 0, 7]);
+        auth_data.extend_from_slice(aaguid.as_bytes());
+        auth_data.extend_from_slice(&[0, 0]);
+
+        let mut out = vec![0xa3];
+        out.extend_from_slice(b"\x63fmt\x66packed");
+        out.extend_from_slice(b"\x67attStmt\xa2\x63alg\x26\x63x5c\x81\x42\x30\x01");
+        out.extend_from_slice(b"\x68authData\x58");
+        out.push(auth_data.len() as u8);
+        out.extend_from_slice(&auth_data);
+        out
+    }
+
+    fn policy(allowed: &[&str], denied: &[&str]) -> AttestationPolicy {
+        AttestationPolicy::load(&AttestationConfig {
+            allowed_aaguids: allowed.iter().map(|a| a.to_string()).collect(),
+            denied_aaguids: denied.iter().map(|a| a.to_string()).collect(),
+            trusted_roots: None,
+        })
+        .unwrap()
+    }
+
+    #[test]
+    fn bad_settings_are_config_errors() {
+        let missing = AttestationPolicy::load(&AttestationConfig {
+            allowed_aaguids: Vec::new(),
+            denied_aaguids: Vec::new(),
+            trusted_roots: Some("/nonexistent/roots.pem".into()),
+        });
+        assert_eq!(
+            missing.err().unwrap().exit_code(),
+            crate::config::EX_NOINPUT
+        );
+        let garbage = AttestationPolicy::load(&AttestationConfig {
+            allowed_aaguids: vec!["yubikey".into()],
+            denied_aaguids: Vec::new(),
+            trusted_roots: None,
+        });
+        assert!(matches!(
+            garbage,
+            Err(ConfigError::Invalid {
+                key: "attestation.allowed_aaguids",
+                ..
+            })
+        ));
+    }
+
+    #[test]
+    fn parses_aaguid_and_certificates() {
+        let aaguid = Uuid::parse_str(YUBIKEY_5).unwrap();
+        let parsed = parse_attestation_object(&attestation_object(aaguid)).unwrap();
+        assert_eq!(parsed.aaguid, aaguid);
+        assert_eq!(parsed.x5c, vec![vec![0x30, 0x01]]);
+        assert!(parse_attestation_object(b"\xa1\x63fmt").is_err());
+    }
+
+    #[test]
+    fn allow_and_deny_lists_match_aaguids() {
+        let yubikey = attestation_object(Uuid::parse_str(YUBIKEY_5).unwrap());
+        let other = attestation_object(Uuid::nil());
+        assert!(policy(&[YUBIKEY_5], &[]).check(&yubikey).is_ok());
+        assert!(policy(&[YUBIKEY_5], &[]).check(&other).is_err());
+        assert!(policy(&[], &[YUBIKEY_5]).check(&yubikey).is_err());
+        assert!(policy(&[], &[YUBIKEY_5]).check(&other).is_ok());
+    }
+}
@@ -0,0 +1,102 @@
+use crate::config::LoginThrottleConfig;
+use crate::db::{Db, LoginRecord};
+use crate::state::AppState;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum AuditKind {
+    Login,
+    LoginFailed,
+    LoginAnomaly,
+    /// A request for a `[honeypot]` decoy path.
+    Honeypot,
+    /// A signed-in user turned away from an app outside its `access_hours`.
+    AccessDenied,
+}
+
+impl AuditKind {
+    pub fn as_str(self) -> &'static str {
+        match self {
+            Self::Login => "login",
+            Self::LoginFailed => "login_failed",
+            Self::LoginAnomaly => "login_anomaly",
+            Self::Honeypot => "honeypot",
+            Self::AccessDenied => "access_denied",
+        }
+    }
+}
+
+#[derive(Debug, Default)]
+pub struct AuditEvent<'a> {
+    pub user_id: Option<&'a str>,
+    pub ip: Option<&'a str>,
+    pub country: Option<&'a str>,
+    pub user_agent: Option<&'a str>,
+    pub detail: Option<&'a str>,
+    /// Redirect origin the login was for.
+    pub app: Option<&'a str>,
+}
+
+/// Best-effort append to the audit log, and to `audit_export` when configured; a
+/// failed write is logged, never surfaced.
+pub async fn record(state: &AppState, kind: AuditKind, event: AuditEvent<'_>) {
+    if let Some(exporter) = &state.audit_export {
+        exporter.export(kind, &event);
+    }
+    let result = state
+        .db
+        .insert_audit_event(
+            kind.as_str(),
+            event.user_id,
+            event.ip,
+            event.country,
+            event.user_agent,
+            event.detail,
+            event.app,
+        )
+        .await;
+    if let Err(error) = result {
+        tracing::warn!(error = %error, kind = kind.as_str(), "failed to record audit event");
+    }
+}
+
+/// `user_id`'s login events, newest first, older than `before` (an event id) when
+/// given. Failures before den knew who was signing in (a rejected passkey, a wrong
+/// password) carry no user; den is single-user, so they are included too.
+pub async fn logins(
+    db: &Db,
+    user_id: &str,
+    before: Option<i64>,
+    limit: i64,
+) -> Result<Vec<LoginRecord>, sqlx::Error> {
+    db.login_history(user_id, before.unwrap_or(i64::MAX), limit)
+        .await
+}
+
+/// Seconds until `ip` may sign in again when it failed `max_failures` times within
+/// the window: the most recent `max_failures`-th failure has to age out first.
+pub async fn throttled_for(
+    db: &Db,
+    ip: &str,
+    throttle: LoginThrottleConfig,
+) -> Result<Option<u64>, sqlx::Error> {
+    let seconds = db
+        .failed_login_age_out(
+            ip,
+            throttle.window_minutes,
+            i64::from(throttle.max_failures) - 1,
+        )
+        .await?;
+    Ok(seconds.map(|seconds| seconds.max(1) as u64))
+}
+
+/// Client IPs that hit a honeypot within the last `block_minutes`, with the seconds
+/// until their latest hit ages out.
+pub async fn honeypot_hits(db: &Db, block_minutes: u32) -> Result<Vec<(String, i64)>, sqlx::Error> {
+    db.honeypot_hits(block_minutes).await
+}
+
+/// How many client IPs `throttled_for` refuses right now.
+pub async fn throttled_clients(db: &Db, throttle: LoginThrottleConfig) -> Result<i64, sqlx::Error> {
+    db.throttled_clients(throttle.window_minutes, throttle.max_failures)
+        .await
+}
@@ -0,0 +1,496 @@
+use std::fs::{File, OpenOptions};
+use std::io::{self, Write};
+use std::net::{Ipv4Addr, Ipv6Addr, SocketAddr, TcpStream, ToSocketAddrs, UdpSocket};
+use std::path::{Path, PathBuf};
+use std::sync::mpsc::{self, SyncSender, TrySendError};
+use std::time::Duration;
+
+use serde::Serialize;
+use time::OffsetDateTime;
+use url::Url;
+
+use crate::audit::{AuditEvent, AuditKind};
+use crate::config::{AuditExportConfig, AuditFormat};
+
+/// Lines the writer thread may fall behind before new events are dropped.
+const QUEUE: usize = 1024;
+const SYSLOG_PORT: u16 = 514;
+const SYSLOG_TIMEOUT: Duration = Duration::from_secs(5);
+/// RFC 5424 facility 10: security/authorization messages (authpriv).
+const FACILITY_AUTHPRIV: u8 = 10;
+
+/// Streams audit events to `audit_export` from a dedicated thread, so a slow disk
+/// or syslog server never holds up a login; when the queue is full the event is
+/// dropped with a warning (the database copy is still written).
+pub struct AuditExporter {
+    format: AuditFormat,
+    /// `instance_id`: the CEF `dvchost` and the syslog HOSTNAME.
+    host: String,
+    queue: SyncSender<Line>,
+}
+
+struct Line {
+    kind: AuditKind,
+    at: OffsetDateTime,
+    text: String,
+}
+
+impl AuditExporter {
+    pub fn start(config: AuditExportConfig, host: &str) -> Self {
+        let format = config.format();
+        let mut sink = match config {
+            AuditExportConfig::File {
+                path,
+                max_bytes,
+                keep,
+                ..
+            } => Sink::File(RotatingFile {
+                path: PathBuf::from(path),
+                max_bytes,
+                keep,
+                file: None,
+                size: 0,
+            }),
+            AuditExportConfig::Syslog { address, .. } => Sink::Syslog(Syslog {
+                address,
+                host: host.to_owned(),
+                udp: None,
+                tcp: None,
+            }),
+        };
+        let (queue, lines) = mpsc::sync_channel::<Line>(QUEUE);
+        std::thread::Builder::new()
+            .name("audit-export".into())
+            .spawn(move || {
+                for line in lines {
+                    if let Err(error) = sink.write(&line) {
+                        tracing::warn!(
+                            error = %error,
+                            kind = line.kind.as_str(),
+                            "audit export failed"
+                        );
+                    }
+                }
+            })
+            .expect("failed to spawn the audit export thread");
+        Self {
+            format,
+            host: host.to_owned(),
+            queue,
+        }
+    }
+
+    pub fn export(&self, kind: AuditKind, event: &AuditEvent<'_>) {
+        let at = OffsetDateTime::now_utc();
+        let text = match self.format {
+            AuditFormat::Jsonl => jsonl(kind, event, &self.host, at),
+            AuditFormat::Cef => cef(kind, event, &self.host, at),
+            AuditFormat::Fail2ban => fail2ban(kind, event, &self.host, at),
+        };
+        match self.queue.try_send(Line { kind, at, text }) {
+            Ok(()) => {}
+            Err(TrySendError::Full(_)) => {
+                tracing::warn!(
+                    kind = kind.as_str(),
+                    "audit export queue full; event dropped"
+                );
+            }
+            Err(TrySendError::Disconnected(_)) => {
+                tracing::warn!(kind = kind.as_str(), "audit export thread stopped");
+            }
+        }
+    }
+}
+
+/// ArcSight severity (0-10) and name, and the RFC 5424 severity.
+fn describe(kind: AuditKind) -> (u8, &'static str, u8) {
+    match kind {
+        AuditKind::Login => (3, "Login", 6),
+        AuditKind::LoginFailed => (5, "Login failed", 5),
+        AuditKind::LoginAnomaly => (7, "Login anomaly", 4),
+        AuditKind::Honeypot => (6, "Honeypot hit", 4),
+        AuditKind::AccessDenied => (4, "App access denied", 5),
+    }
+}
+
+#[derive(Serialize)]
+struct JsonRecord<'a> {
+    time: String,
+    host: &'a str,
+    kind: &'static str,
+    user_id: Option<&'a str>,
+    ip: Option<&'a str>,
+    country: Option<&'a str>,
+    user_agent: Option<&'a str>,
+    detail: Option<&'a str>,
+    app: Option<&'a str>,
+}
+
+fn jsonl(kind: AuditKind, event: &AuditEvent<'_>, host: &str, at: OffsetDateTime) -> String {
+    serde_json::to_string(&JsonRecord {
+        time: rfc3339(at),
+        host,
+        kind: kind.as_str(),
+        user_id: event.user_id,
+        ip: event.ip,
+        country: event.country,
+        user_agent: event.user_agent,
+        detail: event.detail,
+        app: event.app,
+    })
+    .expect("audit records serialize")
+}
+
+fn cef(kind: AuditKind, event: &AuditEvent<'_>, host: &str, at: OffsetDateTime) -> String {
+    let (severity, name, _) = describe(kind);
+    let mut line = format!(
+        "CEF:0|den|den|{}|{}|{name}|{severity}|rt={}",
+        env!("CARGO_PKG_VERSION"),
+        kind.as_str(),
+        at.unix_timestamp() * 1000 + i64::from(at.millisecond())
+    );
+    let fields = [
+        ("dvchost", Some(host)),
+        ("suid", event.user_id),
+        ("src", event.ip),
+        ("requestClientApplication", event.user_agent),
+        ("cs1Label", event.country.map(|_| "country")),
+        ("cs1", event.country),
+        ("cs2Label", event.app.map(|_| "app")),
+        ("cs2", event.app),
+        ("msg", event.detail),
+    ];
+    for (key, value) in fields {
+        if let Some(value) = value {
+            line.push_str(&format!(" {key}={}", cef_escape(value)));
+        }
+    }
+    line
+}
+
+/// CEF extension values: backslash, `=` and line breaks are escaped.
+fn cef_escape(value: &str) -> String {
+    let mut out = String::with_capacity(value.len());
+    for c in value.chars() {
+        match c {
+            '\\' => out.push_str("\\\\"),
+            '=' => out.push_str("\\="),
+            '\n' => out.push_str("\\n"),
+            '\r' => out.push_str("\\r"),
+            c => out.push(c),
+        }
+    }
+    out
+}
+
+/// `<time> <host> den: <kind> from <ip|->`, then whichever of `user`, `country`,
+/// `app`, `user_agent` and `detail` are set, as `key="value"`. The prefix is stable,
+/// so fail2ban can match `den: (?:login_failed|honeypot) from <HOST>`; free-form
+/// values only come after the address, quoted, so they can't pose as one.
+fn fail2ban(kind: AuditKind, event: &AuditEvent<'_>, host: &str, at: OffsetDateTime) -> String {
+    let mut line = format!(
+        "{} {} den: {} from {}",
+        rfc3339(at),
+        syslog_field(host),
+        kind.as_str(),
+        event.ip.unwrap_or("-")
+    );
+    let fields = [
+        ("user", event.user_id),
+        ("country", event.country),
+        ("app", event.app),
+        ("user_agent", event.user_agent),
+        ("detail", event.detail),
+    ];
+    for (key, value) in fields {
+        if let Some(value) = value {
+            line.push_str(&format!(" {key}=\"{}\"", quote_escape(value)));
+        }
+    }
+    line
+}
+
+/// Backslash, double quote and line breaks escaped, so a value stays in its quotes
+/// and on its line.
+fn quote_escape(value: &str) -> String {
+    let mut out = String::with_capacity(value.len());
+    for c in value.chars() {
+        match c {
+            '\\' => out.push_str("\\\\"),
+            '"' => out.push_str("\\\""),
+            '\n' => out.push_str("\\n"),
+            '\r' => out.push_str("\\r"),
+            c => out.push(c),
+        }
+    }
+    out
+}
+
+fn rfc3339(at: OffsetDateTime) -> String {
+    format!(
+        "{:04}-{:02}-{:02}T{:02}:{:02}:{:02}.{:03}Z",
+        at.year(),
+        u8::from(at.month()),
+        at.day(),
+        at.hour(),
+        at.minute(),
+        at.second(),
+        at.millisecond()
+    )
+}
+
+enum Sink {
+    File(RotatingFile),
+    Syslog(Syslog),
+}
+
+impl Sink {
+    fn write(&mut self, line: &Line) -> io::Result<()> {
+        match self {
+            Self::File(file) => file.append(&line.text),
+            Self::Syslog(syslog) => syslog.send(line),
+        }
+    }
+}
+
+struct RotatingFile {
+    path: PathBuf,
+    max_bytes: u64,
+    keep: u32,
+    file: Option<File>,
+    size: u64,
+}
+
+impl RotatingFile {
+    fn append(&mut self, text: &str) -> io::Result<()> {
+        let len = text.len() as u64 + 1;
+        if self.file.is_none() {
+            self.open()?;
+        }
+        if self.size > 0 && self.size + len > self.max_bytes {
+            self.rotate()?;
+        }
+        let file = self.file.as_mut().expect("opened above");
+        file.write_all(format!("{text}\n").as_bytes())?;
+        self.size += len;
+        Ok(())
+    }
+
+    fn open(&mut self) -> io::Result<()> {
+        let mut options = OpenOptions::new();
+        options.create(true).append(true);
+        #[cfg(unix)]
+        std::os::unix::fs::OpenOptionsExt::mode(&mut options, 0o600);
+        let file = options.open(&self.path)?;
+        self.size = file.metadata()?.len();
+        self.file = Some(file);
+        Ok(())
+    }
+
+    /// `path` becomes `path.1`, `path.1` becomes `path.2`, ..., dropping past `keep`.
+    fn rotate(&mut self) -> io::Result<()> {
+        self.file = None;
+        for i in (1..self.keep).rev() {
+            match std::fs::rename(numbered(&self.path, i), numbered(&self.path, i + 1)) {
+                Err(e) if e.kind() != io::ErrorKind::NotFound => return Err(e),
+                _ => {}
+            }
+        }
+        if self.keep > 0 {
+            std::fs::rename(&self.path, numbered(&self.path, 1))?;
+        } else {
+            std::fs::remove_file(&self.path)?;
+        }
+        self.open()
+    }
+}
+
+fn numbered(path: &Path, n: u32) -> PathBuf {
+    let mut name = path.as_os_str().to_owned();
+    name.push(format!(".{n}"));
+    PathBuf::from(name)
+}
+
+struct Syslog {
+    /// `udp://host[:port]` or `tcp://host[:port]`, checked at config load.
+    address: String,
+    host: String,
+    udp: Option<UdpSocket>,
+    tcp: Option<TcpStream>,
+}
+
+impl Syslog {
+    fn send(&mut self, line: &Line) -> io::Result<()> {
+        let (_, _, severity) = describe(line.kind);
+        // <PRI>VERSION TIMESTAMP HOSTNAME APP-NAME PROCID MSGID STRUCTURED-DATA MSG
+        let message = format!(
+            "<{}>1 {} {} den {} {} - {}",
+            FACILITY_AUTHPRIV * 8 + severity,
+            rfc3339(line.at),
+            syslog_field(&self.host),
+            std::process::id(),
+            line.kind.as_str(),
+            line.text
+        );
+        let url = Url::parse(&self.address).map_err(io::Error::other)?;
+        if url.scheme() == "udp" {
+            return self.send_udp(&url, message.as_bytes());
+        }
+        // RFC 6587 octet counting; reconnect once if the server dropped us.
+        let framed = format!("{} {message}", message.len());
+        if let Some(stream) = &mut self.tcp
+            && stream.write_all(framed.as_bytes()).is_ok()
+        {
+            return Ok(());
+        }
+        let mut stream = TcpStream::connect_timeout(&resolve(&url)?, SYSLOG_TIMEOUT)?;
+        stream.set_write_timeout(Some(SYSLOG_TIMEOUT))?;
+        let result = stream.write_all(framed.as_bytes());
+        self.tcp = Some(stream);
+        result
+    }
+
+    fn send_udp(&mut self, url: &Url, message: &[u8]) -> io::Result<()> {
+        if self.udp.is_none() {
+            let target = resolve(url)?;
+            let local: SocketAddr = if target.is_ipv4() {
+                (Ipv4Addr::UNSPECIFIED, 0).into()
+            } else {
+                (Ipv6Addr::UNSPECIFIED, 0).into()
+            };
+            let socket = UdpSocket::bind(local)?;
+            socket.connect(target)?;
+            self.udp = Some(socket);
+        }
+        let socket = self.udp.as_ref().expect("bound above");
+        socket.send(message).map(|_| ())
+    }
+}
+
+fn resolve(url: &Url) -> io::Result<SocketAddr> {
+    let host = url.host_str().unwrap_or_default().trim_matches(['[', ']']);
+    (host, url.port().unwrap_or(SYSLOG_PORT))
+        .to_socket_addrs()?
+        .next()
+        .ok_or_else(|| io::Error::new(io::ErrorKind::NotFound, "syslog host has no address"))
+}
+
+/// HOSTNAME is printable ASCII without spaces, or `-` when there's nothing usable.
+fn syslog_field(value: &str) -> String {
+    let value: String = value.chars().filter(|c| c.is_ascii_graphic()).collect();
+    if value.is_empty() { "-".into() } else { value }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn event() -> AuditEvent<'static> {
+        AuditEvent {
+            user_id: Some("u1"),
+            ip: Some("192.0.2.1"),
+            country: Some("DE"),
+            user_agent: Some("Firefox on Linux"),
+            detail: Some("new country=DE\nnew device"),
+            app: None,
+        }
+    }
+
+    fn at() -> OffsetDateTime {
+        OffsetDateTime::from_unix_timestamp(1_767_225_600).unwrap()
+            + time::Duration::milliseconds(42)
+    }
+
+    #[test]
+    fn formats_jsonl_and_cef() {
+        let line = jsonl(AuditKind::LoginAnomaly, &event(), "den-1", at());
+        let value: serde_json::Value = serde_json::from_str(&line).unwrap();
+        assert_eq!(value["time"], "2026-01-01T00:00:00.042Z");
+        assert_eq!(value["kind"], "login_anomaly");
+        assert_eq!(value["country"], "DE");
+
+        assert_eq!(
+            cef(AuditKind::LoginAnomaly, &event(), "den-1", at()),
+            format!(
+                "CEF:0|den|den|{}|login_anomaly|Login anomaly|7|rt=1767225600042 \
+                 dvchost=den-1 suid=u1 src=192.0.2.1 requestClientApplication=Firefox on Linux \
+                 cs1Label=country cs1=DE msg=new country\\=DE\\nnew device",
+                env!("CARGO_PKG_VERSION")
+            )
+        );
+        let failed = AuditEvent::default();
+        assert!(
+            cef(AuditKind::LoginFailed, &failed, "den-1", at())
+                .ends_with("|5|rt=1767225600042 dvchost=den-1")
+        );
+    }
+
+    #[test]
+    fn formats_fail2ban_lines_with_the_address_first() {
+        let spoof = AuditEvent {
+            user_agent: Some("x\" den: login_failed from 198.51.100.7"),
+            ..event()
+        };
+        assert_eq!(
+            fail2ban(AuditKind::LoginFailed, &spoof, "den 1", at()),
+            "2026-01-01T00:00:00.042Z den1 den: login_failed from 192.0.2.1 user=\"u1\" \
+             country=\"DE\" user_agent=\"x\\\" den: login_failed from 198.51.100.7\" \
+             detail=\"new country=DE\\nnew device\""
+        );
+        assert_eq!(
+            fail2ban(AuditKind::Honeypot, &AuditEvent::default(), "den-1", at()),
+            "2026-01-01T00:00:00.042Z den-1 den: honeypot from -"
+        );
+    }
+
+    #[test]
+    fn rotates_past_max_bytes_and_keeps_the_newest_files() {
+        let dir = std::env::temp_dir().join(format!("den-audit-{}", std::process::id()));
+        std::fs::create_dir_all(&dir).unwrap();
+        let path = dir.join("audit.jsonl");
+        let mut file = RotatingFile {
+            path: path.clone(),
+            max_bytes: 10,
+            keep: 2,
+            file: None,
+            size: 0,
+        };
+        for line in ["one", "two", "three", "four", "five"] {
+            file.append(line).unwrap();
+        }
+        // "three" and "four" don't fit after what's already there; "five" does.
+        let read = |p: &Path| std::fs::read_to_string(p).unwrap();
+        assert_eq!(read(&path), "four\nfive\n");
+        assert_eq!(read(&numbered(&path, 1)), "three\n");
+        assert_eq!(read(&numbered(&path, 2)), "one\ntwo\n");
+        assert!(!numbered(&path, 3).exists());
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn syslog_messages_use_authpriv_and_octet_counting_over_tcp() {
+        let server = std::net::TcpListener::bind("127.0.0.1:0").unwrap();
+        let mut syslog = Syslog {
+            address: format!("tcp://{}", server.local_addr().unwrap()),
+            host: "den 1".into(),
+            udp: None,
+            tcp: None,
+        };
+        syslog
+            .send(&Line {
+                kind: AuditKind::LoginFailed,
+                at: at(),
+                text: "CEF:0|den".into(),
+            })
+            .unwrap();
+        drop(syslog);
+        let (mut stream, _) = server.accept().unwrap();
+        let mut received = String::new();
+        io::Read::read_to_string(&mut stream, &mut received).unwrap();
+        let message = format!(
+            "<85>1 2026-01-01T00:00:00.042Z den1 den {} login_failed - CEF:0|den",
+            std::process::id()
+        );
+        assert_eq!(received, format!("{} {message}", message.len()));
+    }
+}
@@ -1,63 +1,300 @@
-use axum::extract::FromRequestParts;
+use std::net::SocketAddr;
+
+use axum::extract::{ConnectInfo, FromRequestParts};
 use axum::http::StatusCode;
 use axum::http::request::Parts;
 use axum_extra::extract::cookie::{Cookie, CookieJar, SameSite};
-use jsonwebtoken::{DecodingKey, EncodingKey, Header, Validation, decode, encode};
+use jsonwebtoken::Validation;
 use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
 use time::Duration;
 
+use crate::origin::{host_in_domain, normalize_origin, origin_host};
+use crate::session;
+use crate::signing_key::SigningKeys;
 use crate::state::AppState;
 
+/// Lifetime of the access JWT; the refresh token carries the session beyond this.
+pub const ACCESS_TOKEN_TTL: Duration = Duration::minutes(15);
+pub const SESSION_TTL: Duration = Duration::days(7);
+/// `typ` of access tokens; anything else signed with the JWT secret is not a session.
+const SESSION_TOKEN_TYPE: &str = "session";
+
 #[derive(Debug, Serialize, Deserialize)]
 pub struct Claims {
+    /// Always [`SESSION_TOKEN_TYPE`]; required so no other token passes as a session.
+    pub typ: String,
     pub sub: String,
+    #[serde(default)]
+    pub sid: Option<String>,
     pub iat: i64,
     pub exp: i64,
+    /// Origin the token was issued on.
+    #[serde(default)]
+    pub aud: Option<String>,
+    /// App policy name for `aud`, when one is configured.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub app: Option<String>,
+    /// Space-separated scopes granted by that app policy.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub scope: Option<String>,
+    /// The origins a delegated sub-account may reach; absent for the owner. Set at
+    /// mint and refresh, and the sub-account's sessions are revoked when it changes.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub sub_account: Option<Vec<String>>,
+    /// `instance_id` of the replica that minted the token, for tracing. Any replica
+    /// verifies any other's tokens, so nothing checks it.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub iid: Option<String>,
+}
+
+/// Audience claims for tokens bound to one origin, driven by the app policy config.
+pub struct TokenAudience {
+    pub aud: String,
+    pub app: Option<String>,
+    pub scope: Option<String>,
+}
+
+impl TokenAudience {
+    /// Falls back to `rp_origin` when `origin` isn't an allowed host.
+    pub fn for_origin(state: &AppState, origin: Option<&str>) -> Self {
+        let origin = origin
+            .filter(|o| origin_host(o).is_some_and(|h| state.allowed_hosts.contains(&h)))
+            .and_then(normalize_origin)
+            .unwrap_or_else(|| state.rp_origin.clone())
+            .to_ascii_lowercase();
+        let app = state.apps.get(&origin);
+        Self {
+            aud: origin,
+            app: app.map(|a| a.name.clone()),
+            scope: app
+                .filter(|a| !a.scopes.is_empty())
+                .map(|a| a.scopes.join(" ")),
+        }
+    }
 }
 
 #[derive(Clone)]
 pub struct AuthUser {
     pub user_id: String,
+    pub session_id: Option<String>,
 }
 
 pub struct MaybeAuthUser(pub Option<AuthUser>);
 
-pub fn create_token(secret: &[u8], user_id: &str) -> Result<String, jsonwebtoken::errors::Error> {
+#[derive(Debug, Clone)]
+pub struct CookieSettings {
+    pub name: String,
+    pub same_site: SameSite,
+    pub domain: Option<String>,
+    pub partitioned: bool,
+}
+
+impl CookieSettings {
+    pub fn refresh_name(&self) -> String {
+        format!("{}_refresh", self.name)
+    }
+
+    pub fn challenge_name(&self) -> String {
+        format!("{}_challenge", self.name)
+    }
+}
+
+pub fn create_token(
+    keys: &SigningKeys,
+    instance_id: &str,
+    user_id: &str,
+    session_id: &str,
+    audience: TokenAudience,
+    sub_account: Option<Vec<String>>,
+) -> Result<String, jsonwebtoken::errors::Error> {
     let now = time::OffsetDateTime::now_utc();
     let claims = Claims {
+        typ: SESSION_TOKEN_TYPE.into(),
         sub: user_id.to_string(),
+        sid: Some(session_id.to_string()),
         iat: now.unix_timestamp(),
-        exp: (now + Duration::days(7)).unix_timestamp(),
+        exp: (now + ACCESS_TOKEN_TTL).unix_timestamp(),
+        aud: Some(audience.aud),
+        app: audience.app,
+        scope: audience.scope,
+        sub_account,
+        iid: Some(instance_id.to_string()),
     };
-    encode(
-        &Header::default(),
-        &claims,
-        &EncodingKey::from_secret(secret),
-    )
+    keys.encode(None, &claims)
+}
+
+/// The HMAC key for one kind of non-session token (`purpose`), derived from
+/// a signing key's secret so such a token never verifies as another kind.
+pub fn token_key(secret: &[u8], purpose: &str) -> Vec<u8> {
+    let mut hasher = Sha256::new();
+    hasher.update(purpose.as_bytes());
+    hasher.update([0]);
+    hasher.update(secret);
+    hasher.finalize().to_vec()
+}
+
+/// Session cookies are shared across `cookie_domain`, so `aud` names where the
+/// token was issued rather than restricting where it's accepted; `typ` and `sid`
+/// are what tell a session apart.
+fn session_validation() -> Validation {
+    let mut validation = Validation::default();
+    validation.validate_aud = false;
+    validation
 }
 
-pub fn user_id_from_token(
-    secret: &[u8],
+fn decode_session(
+    keys: &SigningKeys,
     token: &str,
-) -> Result<String, jsonwebtoken::errors::Error> {
-    decode::<Claims>(
-        token,
-        &DecodingKey::from_secret(secret),
-        &Validation::default(),
-    )
-    .map(|d| d.claims.sub)
+    validation: &Validation,
+) -> Result<Claims, jsonwebtoken::errors::Error> {
+    let claims = keys.decode::<Claims>(None, token, validation)?.claims;
+    if claims.typ != SESSION_TOKEN_TYPE || claims.sid.is_none() {
+        return Err(jsonwebtoken::errors::ErrorKind::InvalidToken.into());
+    }
+    Ok(claims)
+}
+
+pub fn claims_from_token(
+    keys: &SigningKeys,
+    token: &str,
+) -> Result<Claims, jsonwebtoken::errors::Error> {
+    decode_session(keys, token, &session_validation())
+}
+
+/// Like [`claims_from_token`] but accepts an expired access token, for callers that
+/// re-check the backing session (`sid`) themselves.
+pub fn claims_ignoring_expiry(
+    keys: &SigningKeys,
+    token: &str,
+) -> Result<Claims, jsonwebtoken::errors::Error> {
+    let mut validation = session_validation();
+    validation.validate_exp = false;
+    decode_session(keys, token, &validation)
+}
+
+/// First-run gate: true when no `bootstrap_token` is configured or `presented` matches.
+pub fn bootstrap_token_ok(state: &AppState, presented: Option<&str>) -> bool {
+    let Some(expected) = &state.bootstrap_token else {
+        return true;
+    };
+    presented.is_some_and(|presented| secret_matches(presented, expected))
+}
+
+/// Compares digests in constant time so neither content nor length leaks.
+pub fn secret_matches(presented: &str, expected: &str) -> bool {
+    let presented = Sha256::digest(presented.as_bytes());
+    let expected = Sha256::digest(expected.as_bytes());
+    openssl::memcmp::eq(&presented, &expected)
 }
 
-pub fn session_cookie(token: String, secure: bool) -> Cookie<'static> {
-    Cookie::build(("den_session", token))
+/// The configured `cookie_domain`, if `origin` falls inside it.
+pub fn cookie_domain_for<'a>(state: &'a AppState, origin: &str) -> Option<&'a str> {
+    let domain = state.cookie.domain.as_deref()?;
+    let host = origin_host(origin)?;
+    host_in_domain(&host, domain).then_some(domain)
+}
+
+/// The cookie outlives the JWT inside it so forward-auth can fall back to the
+/// session lookup on hosts where `/api/refresh` isn't reachable.
+pub fn session_cookie(
+    settings: &CookieSettings,
+    token: String,
+    ttl: Duration,
+    secure: bool,
+    domain: Option<&str>,
+) -> Cookie<'static> {
+    let mut cookie = Cookie::build((settings.name.clone(), token))
         .path("/")
         .http_only(true)
-        .same_site(SameSite::Strict)
-        .max_age(Duration::days(7))
-        .secure(secure)
-        .build()
+        .same_site(settings.same_site)
+        .max_age(ttl)
+        // Browsers drop SameSite=None cookies that aren't Secure.
+        .secure(secure || settings.same_site == SameSite::None)
+        .partitioned(settings.partitioned);
+    if let Some(domain) = domain {
+        cookie = cookie.domain(domain.to_owned());
+    }
+    cookie.build()
 }
 
+/// Refresh cookies are only sent to the API, never to page loads.
+pub fn refresh_cookie(
+    settings: &CookieSettings,
+    token: String,
+    ttl: Duration,
+    secure: bool,
+    domain: Option<&str>,
+) -> Cookie<'static> {
+    let mut cookie = Cookie::build((settings.refresh_name(), token))
+        .path("/api")
+        .http_only(true)
+        .same_site(settings.same_site)
+        .max_age(ttl)
+        .secure(secure || settings.same_site == SameSite::None)
+        .partitioned(settings.partitioned);
+    if let Some(domain) = domain {
+        cookie = cookie.domain(domain.to_owned());
+    }
+    cookie.build()
+}
+
+/// The binding for challenges this browser starts: the hash of its challenge cookie,
+/// minted here on first use. The cookie lasts for the browser session and is Lax so
+/// an upstream IdP's redirect back to `/api/oidc/callback` still carries it.
+pub fn bind_challenge(
+    settings: &CookieSettings,
+    jar: CookieJar,
+    secure: bool,
+) -> (CookieJar, String) {
+    if let Some(nonce) = jar.get(&settings.challenge_name()) {
+        let binding = session::hash_token(nonce.value());
+        return (jar, binding);
+    }
+    let nonce = session::new_refresh_token();
+    let binding = session::hash_token(&nonce);
+    let same_site = match settings.same_site {
+        SameSite::None => SameSite::None,
+        _ => SameSite::Lax,
+    };
+    let cookie = Cookie::build((settings.challenge_name(), nonce))
+        .path("/api")
+        .http_only(true)
+        .same_site(same_site)
+        .secure(secure || same_site == SameSite::None)
+        .partitioned(settings.partitioned)
+        .build();
+    (jar.add(cookie), binding)
+}
+
+/// The binding a completing request presents; without the cookie it matches nothing
+/// `bind_challenge` stored.
+pub fn presented_binding(settings: &CookieSettings, jar: &CookieJar) -> String {
+    jar.get(&settings.challenge_name())
+        .map(|nonce| session::hash_token(nonce.value()))
+        .unwrap_or_default()
+}
+
+pub fn removal_cookies(settings: &CookieSettings, domain: Option<&str>) -> [Cookie<'static>; 2] {
+    [
+        (settings.name.clone(), "/"),
+        (settings.refresh_name(), "/api"),
+    ]
+    .map(|(name, path)| {
+        // A partitioned cookie is only cleared by a removal that is partitioned too.
+        let mut cookie = Cookie::build((name, ""))
+            .path(path)
+            .max_age(Duration::ZERO)
+            .partitioned(settings.partitioned);
+        if let Some(domain) = domain {
+            cookie = cookie.domain(domain.to_owned());
+        }
+        cookie.build()
+    })
+}
+
+/// den's own API is for its owner; a delegated sub-account's session only serves
+/// the apps it was given (see `policy::app_allowed`).
 impl FromRequestParts<AppState> for AuthUser {
     type Rejection = StatusCode;
 
@@ -66,10 +303,38 @@ impl FromRequestParts<AppState> for AuthUser {
         state: &AppState,
     ) -> Result<Self, Self::Rejection> {
         let jar = CookieJar::from_request_parts(parts, state).await.unwrap();
-        let cookie = jar.get("den_session").ok_or(StatusCode::UNAUTHORIZED)?;
-        let user_id = user_id_from_token(&state.jwt_secret, cookie.value())
-            .map_err(|_| StatusCode::UNAUTHORIZED)?;
-        Ok(AuthUser { user_id })
+        if let Some(cookie) = jar.get(&state.cookie.name)
+            && let Ok(claims) = claims_from_token(&state.signing_keys, cookie.value())
+        {
+            if claims.sub_account.is_some() {
+                return Err(StatusCode::FORBIDDEN);
+            }
+            return Ok(AuthUser {
+                user_id: claims.sub,
+                session_id: claims.sid,
+            });
+        }
+
+        // Automation clients without WebAuthn: client cert CN names the user.
+        if let Some(client_cert) = &state.client_cert
+            && let Some(ConnectInfo(peer)) = parts.extensions.get::<ConnectInfo<SocketAddr>>()
+            && let Some(subject) = client_cert.subject(&parts.headers, peer.ip())
+        {
+            let user = state
+                .db
+                .owner_by_name(&subject)
+                .await
+                .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?;
+            if let Some(user) = user {
+                return Ok(AuthUser {
+                    user_id: user.id,
+                    session_id: None,
+                });
+            }
+            tracing::warn!(subject, "client certificate does not match the owner");
+        }
+
+        Err(StatusCode::UNAUTHORIZED)
     }
 }
 
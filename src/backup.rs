@@ -0,0 +1,104 @@
+use base64::Engine;
+use base64::engine::general_purpose::STANDARD as BASE64;
+use openssl::error::ErrorStack;
+use openssl::hash::MessageDigest;
+use openssl::pkcs5::pbkdf2_hmac;
+use openssl::rand::rand_bytes;
+use openssl::symm::{Cipher, decrypt_aead, encrypt_aead};
+use serde::{Deserialize, Serialize};
+
+const ENVELOPE_VERSION: u32 = 1;
+const KDF_ITERATIONS: u32 = 600_000;
+pub const MIN_PASSPHRASE_LEN: usize = 12;
+
+/// Passphrase-encrypted JSON envelope: PBKDF2-HMAC-SHA256 key, AES-256-GCM payload.
+#[derive(Debug, Serialize, Deserialize)]
+pub struct Envelope {
+    pub version: u32,
+    pub kdf: String,
+    pub iterations: u32,
+    pub salt: String,
+    pub nonce: String,
+    pub ciphertext: String,
+    pub tag: String,
+}
+
+fn derive_key(passphrase: &str, salt: &[u8], iterations: u32) -> Result<[u8; 32], ErrorStack> {
+    let mut key = [0u8; 32];
+    pbkdf2_hmac(
+        passphrase.as_bytes(),
+        salt,
+        iterations as usize,
+        MessageDigest::sha256(),
+        &mut key,
+    )?;
+    Ok(key)
+}
+
+/// CPU-heavy (key derivation); call from a blocking task.
+pub fn seal(passphrase: &str, plaintext: &[u8]) -> Result<Envelope, ErrorStack> {
+    let mut salt = [0u8; 16];
+    let mut nonce = [0u8; 12];
+    rand_bytes(&mut salt)?;
+    rand_bytes(&mut nonce)?;
+    let key = derive_key(passphrase, &salt, KDF_ITERATIONS)?;
+    let mut tag = [0u8; 16];
+    let ciphertext = encrypt_aead(
+        Cipher::aes_256_gcm(),
+        &key,
+        Some(&nonce),
+        b"den-backup-v1",
+        plaintext,
+        &mut tag,
+    )?;
+    Ok(Envelope {
+        version: ENVELOPE_VERSION,
+        kdf: "pbkdf2-sha256".into(),
+        iterations: KDF_ITERATIONS,
+        salt: BASE64.encode(salt),
+        nonce: BASE64.encode(nonce),
+        ciphertext: BASE64.encode(ciphertext),
+        tag: BASE64.encode(tag),
+    })
+}
+
+/// `None` for an unknown format, wrong passphrase or tampered payload.
+/// CPU-heavy (key derivation); call from a blocking task.
+pub fn open(passphrase: &str, envelope: &Envelope) -> Option<Vec<u8>> {
+    if envelope.version != ENVELOPE_VERSION
+        || envelope.kdf != "pbkdf2-sha256"
+        // Bound attacker-chosen work factors.
+        || !(100_000..=10_000_000).contains(&envelope.iterations)
+    {
+        return None;
+    }
+    let salt = BASE64.decode(&envelope.salt).ok()?;
+    let nonce = BASE64.decode(&envelope.nonce).ok()?;
+    let ciphertext = BASE64.decode(&envelope.ciphertext).ok()?;
+    let tag = BASE64.decode(&envelope.tag).ok()?;
+    let key = derive_key(passphrase, &salt, envelope.iterations).ok()?;
+    decrypt_aead(
+        Cipher::aes_256_gcm(),
+        &key,
+        Some(&nonce),
+        b"den-backup-v1",
+        &ciphertext,
+        &tag,
+    )
+    .ok()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn seal_then_open_roundtrips() {
+        let envelope = seal("correct horse battery", b"secret").unwrap();
+        assert_eq!(
+            open("correct horse battery", &envelope).as_deref(),
+            Some(&b"secret"[..])
+        );
+        assert_eq!(open("wrong passphrase!!", &envelope), None);
+    }
+}
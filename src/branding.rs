@@ -0,0 +1,102 @@
+use serde::Serialize;
+
+use crate::config::{BrandingConfig, ConfigError, invalid};
+
+pub const LOGO_URL: &str = "/api/config/branding/logo";
+
+/// Public branding as the SPA sees it; `logo_url` replaces the on-disk path.
+#[derive(Debug, Clone, Default, Serialize)]
+pub struct Branding {
+    pub title: Option<String>,
+    pub logo_url: Option<&'static str>,
+    pub accent_color: Option<String>,
+    pub footer_text: Option<String>,
+    #[serde(skip)]
+    pub logo_path: Option<String>,
+}
+
+impl Branding {
+    pub fn load(config: BrandingConfig) -> Result<Self, ConfigError> {
+        if let Some(color) = &config.accent_color
+            && !is_hex_color(color)
+        {
+            return Err(invalid(
+                "branding.accent_color",
+                format!("{color:?} is not a hex color like \"#3b82f6\""),
+            ));
+        }
+        Ok(Self {
+            title: config.title,
+            logo_url: config.logo_path.as_ref().map(|_| LOGO_URL),
+            accent_color: config.accent_color,
+            footer_text: config.footer_text,
+            logo_path: config.logo_path,
+        })
+    }
+
+    /// Whether served HTML needs rewriting (title or accent color set).
+    pub fn injects_html(&self) -> bool {
+        self.title.is_some() || self.accent_color.is_some()
+    }
+
+    /// Apply title and accent color to `index.html` so the first paint is already branded.
+    pub fn inject(&self, html: &str) -> String {
+        let mut html = html.to_owned();
+        if let Some(title) = &self.title
+            && let (Some(start), Some(end)) = (html.find("<title>"), html.find("</title>"))
+            && start < end
+        {
+            html.replace_range(start + "<title>".len()..end, &escape_html(title));
+        }
+        if let Some(color) = &self.accent_color
+            && let Some(head_end) = html.find("</head>")
+        {
+            let style = format!("<style>:root,.dark{{--primary:{color};--ring:{color}}}</style>");
+            html.insert_str(head_end, &style);
+        }
+        html
+    }
+}
+
+fn is_hex_color(value: &str) -> bool {
+    value.strip_prefix('#').is_some_and(|hex| {
+        matches!(hex.len(), 3 | 4 | 6 | 8) && hex.bytes().all(|b| b.is_ascii_hexdigit())
+    })
+}
+
+fn escape_html(value: &str) -> String {
+    value
+        .replace('&', "&amp;")
+        .replace('<', "&lt;")
+        .replace('>', "&gt;")
+        .replace('"', "&quot;")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn inject_sets_title_and_accent() {
+        let branding = Branding {
+            title: Some("Lab <SSO>".into()),
+            accent_color: Some("#3b82f6".into()),
+            ..Default::default()
+        };
+        let html = branding.inject("<html><head><title>den</title></head></html>");
+        assert_eq!(
+            html,
+            "<html><head><title>Lab &lt;SSO&gt;</title>\
+             <style>:root,.dark{--primary:#3b82f6;--ring:#3b82f6}</style></head></html>"
+        );
+    }
+
+    #[test]
+    fn accent_color_must_be_hex() {
+        assert!(is_hex_color("#fff"));
+        assert!(is_hex_color("#3b82f6"));
+        assert!(!is_hex_color("red"));
+        assert!(!is_hex_color("#12345"));
+        assert!(!is_hex_color("#fff;}body{display:none"));
+    }
+}
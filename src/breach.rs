@@ -0,0 +1,182 @@
+use std::fs::File;
+use std::io::{self, BufRead, BufReader, BufWriter, Read, Seek, SeekFrom, Write};
+use std::path::Path;
+
+use openssl::sha::sha1;
+use url::Url;
+
+use crate::config::BreachedPasswordsConfig;
+use crate::http;
+
+const BLOOM_MAGIC: &[u8; 8] = b"DENBLOOM";
+const BLOOM_HEADER_LEN: u64 = 12;
+/// ~0.1% false positives at 14.4 bits per entry.
+const BLOOM_HASHES: u32 = 10;
+const BLOOM_BITS_PER_ENTRY: f64 = 14.38;
+
+/// Known-breached password lookup, either online through the HIBP range API
+/// (k-anonymity: only the first 5 hex chars of the SHA-1 leave the host) or against
+/// a local Bloom filter built with `den breach-filter`. Blocking.
+pub struct BreachCheck {
+    config: BreachedPasswordsConfig,
+}
+
+impl BreachCheck {
+    pub fn new(config: BreachedPasswordsConfig) -> Self {
+        Self { config }
+    }
+
+    pub fn is_breached(&self, password: &str) -> io::Result<bool> {
+        let digest = sha1(password.as_bytes());
+        match &self.config {
+            BreachedPasswordsConfig::Hibp { url } => hibp_contains(url, &digest),
+            BreachedPasswordsConfig::Bloom { path } => bloom_contains(path, &digest),
+        }
+    }
+}
+
+fn hibp_contains(base: &str, digest: &[u8; 20]) -> io::Result<bool> {
+    let hex = hex_upper(digest);
+    let (prefix, suffix) = hex.split_at(5);
+    let url = Url::parse(&format!("{}/{prefix}", base.trim_end_matches('/')))
+        .map_err(io::Error::other)?;
+    // Padding hides the real result size from anyone watching the response length.
+    let response = http::get(&url, &[("Add-Padding", "true")])?;
+    if !response.is_success() {
+        return Err(io::Error::other(format!(
+            "breach range query returned {}",
+            response.status
+        )));
+    }
+    Ok(range_contains(
+        &String::from_utf8_lossy(&response.body),
+        suffix,
+    ))
+}
+
+/// Range bodies are `SUFFIX:COUNT` lines; padding entries have count 0.
+fn range_contains(body: &str, suffix: &str) -> bool {
+    body.lines().any(|line| {
+        line.split_once(':').is_some_and(|(s, count)| {
+            s.eq_ignore_ascii_case(suffix) && count.trim().parse::<u64>().is_ok_and(|c| c > 0)
+        })
+    })
+}
+
+fn bloom_contains(path: &Path, digest: &[u8; 20]) -> io::Result<bool> {
+    let mut file = File::open(path)?;
+    let mut header = [0u8; BLOOM_HEADER_LEN as usize];
+    file.read_exact(&mut header)?;
+    let bits = (file.metadata()?.len() - BLOOM_HEADER_LEN) * 8;
+    if &header[..8] != BLOOM_MAGIC || bits == 0 {
+        return Err(io::Error::new(
+            io::ErrorKind::InvalidData,
+            "not a den breach filter",
+        ));
+    }
+    let hashes = u32::from_le_bytes(header[8..].try_into().unwrap());
+    for index in bloom_indexes(digest, hashes, bits) {
+        let mut byte = [0u8];
+        file.seek(SeekFrom::Start(BLOOM_HEADER_LEN + index / 8))?;
+        file.read_exact(&mut byte)?;
+        if byte[0] & (1 << (index % 8)) == 0 {
+            return Ok(false);
+        }
+    }
+    Ok(true)
+}
+
+/// Double hashing over the SHA-1 digest, so filters can be built straight from
+/// HIBP's published hash lists without knowing the passwords.
+fn bloom_indexes(digest: &[u8; 20], hashes: u32, bits: u64) -> impl Iterator<Item = u64> {
+    let h1 = u64::from_le_bytes(digest[..8].try_into().unwrap());
+    let h2 = u64::from_le_bytes(digest[8..16].try_into().unwrap()) | 1;
+    (0..u64::from(hashes)).map(move |i| h1.wrapping_add(i.wrapping_mul(h2)) % bits)
+}
+
+/// Build a filter from a file of SHA-1 hex hashes, one per line (`HASH` or
+/// `HASH:COUNT`, as in the HIBP downloads). Holds the filter in memory. Returns the
+/// number of hashes added.
+pub fn build_bloom(input: &Path, output: &Path) -> io::Result<u64> {
+    let hashes = || -> io::Result<_> {
+        Ok(BufReader::new(File::open(input)?)
+            .lines()
+            .map(|line| line.map(|l| parse_hash_line(&l))))
+    };
+    let mut count = 0u64;
+    for digest in hashes()? {
+        count += u64::from(digest?.is_some());
+    }
+    if count == 0 {
+        return Err(io::Error::new(
+            io::ErrorKind::InvalidData,
+            "no SHA-1 hashes in input",
+        ));
+    }
+    let bytes = ((count as f64 * BLOOM_BITS_PER_ENTRY) / 8.0).ceil() as u64;
+    let mut filter = vec![0u8; bytes as usize];
+    for digest in hashes()? {
+        if let Some(digest) = digest? {
+            for index in bloom_indexes(&digest, BLOOM_HASHES, bytes * 8) {
+                filter[(index / 8) as usize] |= 1 << (index % 8);
+            }
+        }
+    }
+    let mut out = BufWriter::new(File::create(output)?);
+    out.write_all(BLOOM_MAGIC)?;
+    out.write_all(&BLOOM_HASHES.to_le_bytes())?;
+    out.write_all(&filter)?;
+    out.flush()?;
+    Ok(count)
+}
+
+fn parse_hash_line(line: &str) -> Option<[u8; 20]> {
+    let hex = line.split(':').next()?.trim();
+    if hex.len() != 40 {
+        return None;
+    }
+    let mut digest = [0u8; 20];
+    for (i, byte) in digest.iter_mut().enumerate() {
+        *byte = u8::from_str_radix(hex.get(i * 2..i * 2 + 2)?, 16).ok()?;
+    }
+    Some(digest)
+}
+
+fn hex_upper(bytes: &[u8]) -> String {
+    bytes.iter().map(|b| format!("{b:02X}")).collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn range_contains_ignores_padding() {
+        let body =
+            "0018A45C4D1DEF81644B54AB7F969B88D65:1\r\n1E4C9B93F3F0682250B6CF8331B7EE68FD8:0\r\n";
+        assert!(range_contains(body, "0018a45c4d1def81644b54ab7f969b88d65"));
+        assert!(!range_contains(body, "1E4C9B93F3F0682250B6CF8331B7EE68FD8"));
+    }
+
+    #[test]
+    fn bloom_round_trip() {
+        let dir = std::env::temp_dir().join(format!("den-breach-{}", std::process::id()));
+        std::fs::create_dir_all(&dir).unwrap();
+        let (input, output) = (dir.join("hashes.txt"), dir.join("filter.bin"));
+        let breached = sha1(b"password");
+        std::fs::write(
+            &input,
+            format!(
+                "{}:1\nnot-a-hash\n{}\n",
+                hex_upper(&breached),
+                hex_upper(&sha1(b"hunter2"))
+            ),
+        )
+        .unwrap();
+
+        assert_eq!(build_bloom(&input, &output).unwrap(), 2);
+        assert!(bloom_contains(&output, &breached).unwrap());
+        assert!(!bloom_contains(&output, &sha1(b"correct horse battery staple")).unwrap());
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+}
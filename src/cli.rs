@@ -0,0 +1,540 @@
+use std::fs::{self, File, OpenOptions};
+use std::io::{self, BufWriter, Write};
+use std::path::{Path, PathBuf};
+
+use serde::{Deserialize, Serialize};
+
+use crate::archive;
+use crate::breach;
+use crate::config::{self, AppConfig};
+use crate::db::Db;
+use crate::http;
+use crate::session;
+
+const ARCHIVE_FORMAT: &str = "den-export";
+const ARCHIVE_VERSION: u32 = 1;
+/// Largest `manifest.json` or `config.toml` an import reads; `den.db` is
+/// streamed to disk instead.
+const MAX_ARCHIVE_TEXT: u64 = 1 << 20;
+const DEFAULT_INVITE_TTL_MINUTES: i64 = 10;
+/// A console invite is for getting back in now, not for handing out.
+const MAX_INVITE_TTL_MINUTES: i64 = 24 * 60;
+
+pub const USAGE: &str = "usage:
+  den [--config <config.toml>] <command>
+                                        use this config file (the default database
+                                        goes next to it) instead of the platform's;
+                                        DEN_DATA_DIR=<dir> keeps config.toml, den.db
+                                        and relative paths in <dir> instead
+  den                                   run the server
+  den doctor                            check config, database and web assets,
+                                        listing every problem found
+  den config check                      print the effective config (after includes,
+                                        *_file secrets and defaults; secrets redacted)
+  den log-level [<filter>]              show or set the running server's log filter
+                                        (rust_log syntax or a preset: quiet, auth-debug,
+                                        sql-trace, [log_presets]) until it restarts;
+                                        needs a log-level:write service token in DEN_TOKEN
+  den export --output <den.tar.zst>     dump database + config to an archive
+  den import --input <den.tar.zst> [--force]
+                                        restore an archive (server must be stopped)
+  den breach-filter --input <hashes.txt> --output <filter.bin>
+                                        build an offline breached-password filter
+                                        from SHA-1 hashes (one per line)
+  den passkey invite [--ttl <10m>]      print a one-time passkey registration URL
+                                        for the user, to regain access from the host
+                                        (units: m, h, d; at most 1d)";
+
+pub enum Command {
+    Serve,
+    Doctor,
+    ConfigCheck,
+    LogLevel { filter: Option<String> },
+    Export { output: PathBuf },
+    Import { input: PathBuf, force: bool },
+    BreachFilter { input: PathBuf, output: PathBuf },
+    PasskeyInvite { ttl_minutes: i64 },
+}
+
+/// Remove `--config <path>` (or `--config=<path>`) from anywhere in `args`.
+pub fn take_config_flag(args: &mut Vec<String>) -> Result<Option<PathBuf>, String> {
+    let Some(i) = args
+        .iter()
+        .position(|arg| arg == "--config" || arg.starts_with("--config="))
+    else {
+        return Ok(None);
+    };
+    let flag = args.remove(i);
+    let path = match flag.strip_prefix("--config=") {
+        Some(path) => path.to_owned(),
+        None if i < args.len() => args.remove(i),
+        None => return Err("--config needs a value".into()),
+    };
+    if path.is_empty() {
+        return Err("--config needs a value".into());
+    }
+    Ok(Some(PathBuf::from(path)))
+}
+
+pub fn parse(args: &[String]) -> Result<Command, String> {
+    match args {
+        [] => Ok(Command::Serve),
+        [cmd] if cmd == "doctor" => Ok(Command::Doctor),
+        [cmd, sub] if cmd == "config" && sub == "check" => Ok(Command::ConfigCheck),
+        [cmd] if cmd == "log-level" => Ok(Command::LogLevel { filter: None }),
+        [cmd, filter] if cmd == "log-level" => Ok(Command::LogLevel {
+            filter: Some(filter.clone()),
+        }),
+        [cmd, rest @ ..] if cmd == "export" => {
+            let (output, _) = path_flags(rest, "--output", "-o", false)?;
+            Ok(Command::Export { output })
+        }
+        [cmd, rest @ ..] if cmd == "import" => {
+            let (input, force) = path_flags(rest, "--input", "-i", true)?;
+            Ok(Command::Import { input, force })
+        }
+        [cmd, rest @ ..] if cmd == "breach-filter" => {
+            let (mut input, mut output) = (None, None);
+            let mut args = rest.iter();
+            while let Some(arg) = args.next() {
+                let slot = match arg.as_str() {
+                    "--input" | "-i" => &mut input,
+                    "--output" | "-o" => &mut output,
+                    other => return Err(format!("unexpected argument {other:?}\n\n{USAGE}")),
+                };
+                let value = args.next().ok_or_else(|| format!("{arg} needs a value"))?;
+                *slot = Some(PathBuf::from(value));
+            }
+            Ok(Command::BreachFilter {
+                input: input.ok_or_else(|| format!("missing --input\n\n{USAGE}"))?,
+                output: output.ok_or_else(|| format!("missing --output\n\n{USAGE}"))?,
+            })
+        }
+        [cmd, sub, rest @ ..] if cmd == "passkey" && sub == "invite" => {
+            let ttl_minutes = match rest {
+                [] => DEFAULT_INVITE_TTL_MINUTES,
+                [flag, value] if flag == "--ttl" => parse_ttl(value)?,
+                [flag] if flag == "--ttl" => return Err("--ttl needs a value".into()),
+                [other, ..] => return Err(format!("unexpected argument {other:?}\n\n{USAGE}")),
+            };
+            Ok(Command::PasskeyInvite { ttl_minutes })
+        }
+        [cmd, ..] if cmd == "help" || cmd == "--help" || cmd == "-h" => Err(USAGE.to_owned()),
+        [cmd, ..] => Err(format!("unknown command {cmd:?}\n\n{USAGE}")),
+    }
+}
+
+fn path_flags(
+    args: &[String],
+    long: &str,
+    short: &str,
+    allow_force: bool,
+) -> Result<(PathBuf, bool), String> {
+    let (mut path, mut force) = (None, false);
+    let mut args = args.iter();
+    while let Some(arg) = args.next() {
+        match arg.as_str() {
+            a if a == long || a == short => {
+                let value = args.next().ok_or_else(|| format!("{long} needs a value"))?;
+                path = Some(PathBuf::from(value));
+            }
+            "--force" if allow_force => force = true,
+            other => return Err(format!("unexpected argument {other:?}\n\n{USAGE}")),
+        }
+    }
+    let path = path.ok_or_else(|| format!("missing {long}\n\n{USAGE}"))?;
+    Ok((path, force))
+}
+
+/// `30m`, `2h` or `1d` in minutes, within `MAX_INVITE_TTL_MINUTES`.
+fn parse_ttl(value: &str) -> Result<i64, String> {
+    let invalid = || format!("invalid --ttl {value:?}: expected a number with m, h or d");
+    let unit = match value.chars().last() {
+        Some('m') => 1,
+        Some('h') => 60,
+        Some('d') => 24 * 60,
+        _ => return Err(invalid()),
+    };
+    let count: i64 = value[..value.len() - 1].parse().map_err(|_| invalid())?;
+    let minutes = count.checked_mul(unit).ok_or_else(invalid)?;
+    if !(1..=MAX_INVITE_TTL_MINUTES).contains(&minutes) {
+        return Err(format!("--ttl must be between 1m and 1d, got {value:?}"));
+    }
+    Ok(minutes)
+}
+
+#[derive(Serialize, Deserialize)]
+struct Manifest {
+    format: String,
+    version: u32,
+    den_version: String,
+    created: i64,
+}
+
+pub async fn run(command: Command, config: &AppConfig) -> io::Result<()> {
+    let database_path = &config.database_path;
+    match command {
+        Command::Serve | Command::Doctor => Ok(()),
+        Command::ConfigCheck => config_check(config),
+        Command::LogLevel { filter } => log_level(config, filter).await,
+        Command::Export { output } => export(database_path, &config.database, &output).await,
+        Command::Import { input, force } => import(database_path, &input, force),
+        Command::PasskeyInvite { ttl_minutes } => passkey_invite(config, ttl_minutes).await,
+        Command::BreachFilter { input, output } => {
+            let count = breach::build_bloom(&input, &output)?;
+            println!(
+                "wrote breach filter for {count} hashes to {}",
+                output.display()
+            );
+            Ok(())
+        }
+    }
+}
+
+/// The settings den runs with, secrets reduced to whether they're set as in
+/// `GET /api/admin/diagnostics`, plus where the config and database are. Reaching
+/// here means the config loaded, so unknown keys and bad values were already refused.
+fn config_check(config: &AppConfig) -> io::Result<()> {
+    let mut effective = config.diagnostics();
+    effective["config_file"] =
+        serde_json::json!(config::config_file_path().map_err(io::Error::other)?);
+    effective["database_path"] = serde_json::json!(config.database_path);
+    println!(
+        "{}",
+        serde_json::to_string_pretty(&effective).map_err(io::Error::other)?
+    );
+    Ok(())
+}
+
+/// `GET` or `PUT /api/admin/log-level` on the server at `rp_origin`, which is the
+/// only place the filter lives, authenticated by the service token in `DEN_TOKEN`.
+async fn log_level(config: &AppConfig, filter: Option<String>) -> io::Result<()> {
+    let token = std::env::var("DEN_TOKEN").map_err(|_| {
+        io::Error::new(
+            io::ErrorKind::NotFound,
+            "set DEN_TOKEN to a service token with the log-level:write scope",
+        )
+    })?;
+    let url = url::Url::parse(&config.rp_origin)
+        .and_then(|origin| origin.join("/api/admin/log-level"))
+        .map_err(io::Error::other)?;
+    let response = tokio::task::spawn_blocking(move || {
+        let authorization = format!("Bearer {token}");
+        let mut headers = vec![("Authorization", authorization.as_str())];
+        match filter {
+            Some(filter) => {
+                headers.push(("Content-Type", "application/json"));
+                let body = serde_json::json!({ "filter": filter }).to_string();
+                http::send("PUT", &url, &headers, body.as_bytes())
+            }
+            None => http::get(&url, &headers),
+        }
+    })
+    .await
+    .map_err(io::Error::other)??;
+    if !response.is_success() {
+        return Err(io::Error::other(format!(
+            "server answered HTTP {}",
+            response.status
+        )));
+    }
+    let level: serde_json::Value =
+        serde_json::from_slice(&response.body).map_err(io::Error::other)?;
+    println!("{}", level["filter"].as_str().unwrap_or_default());
+    Ok(())
+}
+
+/// Mint an invite straight in the database, for whoever has a shell on the host but
+/// no browser session left. Only the URL goes to stdout, so it can be piped.
+async fn passkey_invite(config: &AppConfig, ttl_minutes: i64) -> io::Result<()> {
+    if !config.database_path.exists() {
+        return Err(io::Error::new(
+            io::ErrorKind::NotFound,
+            format!(
+                "no database at {}; start den and finish /setup first",
+                config.database_path.display()
+            ),
+        ));
+    }
+    let pool = crate::connect_database(&config.database_path, &config.database)
+        .await
+        .map_err(io::Error::other)?;
+    let db = Db::new(
+        pool,
+        std::time::Duration::from_millis(config.database.slow_query_ms),
+    );
+    let user = db
+        .only_user()
+        .await
+        .map_err(io::Error::other)?
+        .ok_or_else(|| io::Error::new(io::ErrorKind::NotFound, "no user yet; use /setup"))?;
+    let token = session::new_refresh_token();
+    let expires_at = db
+        .create_invite(&session::hash_token(&token), &user.id, ttl_minutes)
+        .await
+        .map_err(io::Error::other)?;
+
+    println!("{}/invite?token={token}", config.rp_origin);
+    eprintln!(
+        "one-time passkey invite for {}, valid until {expires_at} UTC",
+        user.name
+    );
+    Ok(())
+}
+
+/// Archive layout (zstd'd ustar): `manifest.json`, `den.db` (a `VACUUM INTO` snapshot
+/// holding users, passkeys, signing key, sessions and audit history), `config.toml`.
+async fn export(
+    database_path: &Path,
+    database: &config::DatabaseConfig,
+    output: &Path,
+) -> io::Result<()> {
+    // The snapshot sits beside the output, created empty and owner-only before
+    // `VACUUM INTO` fills it, so no other user can open or pre-create it.
+    let mut snapshot = output.as_os_str().to_owned();
+    snapshot.push(".snapshot");
+    let snapshot = PathBuf::from(snapshot);
+    private_file(&snapshot)
+        .map_err(|e| io::Error::new(e.kind(), format!("create {}: {e}", snapshot.display())))?;
+    let database = snapshot_database(database_path, database, &snapshot).await;
+    let _ = fs::remove_file(&snapshot);
+    let database = database?;
+    let config = fs::read(config::config_file_path().map_err(io::Error::other)?)?;
+
+    let now = time::OffsetDateTime::now_utc().unix_timestamp();
+    let manifest = serde_json::to_vec_pretty(&Manifest {
+        format: ARCHIVE_FORMAT.into(),
+        version: ARCHIVE_VERSION,
+        den_version: env!("CARGO_PKG_VERSION").into(),
+        created: now,
+    })?;
+
+    // The archive carries the JWT signing key; keep it owner-only.
+    let file = private_file(output)?;
+    let mut zst = zstd::Encoder::new(BufWriter::new(file), 0)?;
+    let mtime = now.max(0) as u64;
+    archive::write_entry(&mut zst, "manifest.json", &manifest, mtime)?;
+    archive::write_entry(&mut zst, "den.db", &database, mtime)?;
+    archive::write_entry(&mut zst, "config.toml", &config, mtime)?;
+    archive::finish(&mut zst)?;
+    zst.finish()?.flush()?;
+
+    println!(
+        "exported {} ({} byte database) to {}",
+        ARCHIVE_FORMAT,
+        database.len(),
+        output.display()
+    );
+    Ok(())
+}
+
+async fn snapshot_database(
+    database_path: &Path,
+    database: &config::DatabaseConfig,
+    snapshot: &Path,
+) -> io::Result<Vec<u8>> {
+    let db = Db::new(
+        sqlx::SqlitePool::connect(&format!("sqlite:{}", database_path.display()))
+            .await
+            .map_err(io::Error::other)?,
+        std::time::Duration::from_millis(database.slow_query_ms),
+    );
+    let vacuumed = db.vacuum_into(&snapshot.to_string_lossy()).await;
+    db.pool().close().await;
+    vacuumed.map_err(io::Error::other)?;
+    fs::read(snapshot)
+}
+
+/// A new file only the owner can read: archives, snapshots and restored databases
+/// all hold the JWT signing key.
+fn private_file(path: &Path) -> io::Result<File> {
+    let mut options = OpenOptions::new();
+    options.write(true).create_new(true);
+    #[cfg(unix)]
+    std::os::unix::fs::OpenOptionsExt::mode(&mut options, 0o600);
+    options.open(path)
+}
+
+fn import(database_path: &Path, input: &Path, force: bool) -> io::Result<()> {
+    let invalid = |msg: String| io::Error::new(io::ErrorKind::InvalidData, msg);
+    if database_path.exists() && !force {
+        return Err(io::Error::new(
+            io::ErrorKind::AlreadyExists,
+            format!(
+                "database already exists at {}; pass --force to replace it",
+                database_path.display()
+            ),
+        ));
+    }
+    if let Some(parent) = database_path.parent() {
+        fs::create_dir_all(parent)?;
+    }
+
+    // `den.db` goes straight to the staged file; export writes the manifest
+    // first, so it is checked before any of the database is.
+    let staged = database_path.with_extension("db.importing");
+    let mut manifest = None;
+    let mut database = false;
+    let mut archived_config = None;
+    let mut reader = zstd::Decoder::new(File::open(input)?)?;
+    let read = archive::read_entries(&mut reader, |name, size, entry| {
+        match name {
+            "manifest.json" => {
+                let data = archive::read_to_vec(entry, size, MAX_ARCHIVE_TEXT)?;
+                let parsed: Manifest =
+                    serde_json::from_slice(&data).map_err(|e| invalid(e.to_string()))?;
+                if parsed.format != ARCHIVE_FORMAT || parsed.version > ARCHIVE_VERSION {
+                    return Err(invalid(format!(
+                        "unsupported archive {} v{}",
+                        parsed.format, parsed.version
+                    )));
+                }
+                manifest = Some(parsed);
+            }
+            "den.db" => {
+                if manifest.is_none() {
+                    return Err(invalid("archive has no manifest.json before den.db".into()));
+                }
+                let mut magic = [0u8; 16];
+                entry.read_exact(&mut magic)?;
+                if magic != *b"SQLite format 3\0" {
+                    return Err(invalid("archive has no valid den.db".into()));
+                }
+                let _ = fs::remove_file(&staged);
+                let mut file = BufWriter::new(private_file(&staged)?);
+                file.write_all(&magic)?;
+                io::copy(entry, &mut file)?;
+                file.into_inner().map_err(|e| e.into_error())?.sync_all()?;
+                database = true;
+            }
+            "config.toml" => {
+                archived_config = Some(archive::read_to_vec(entry, size, MAX_ARCHIVE_TEXT)?);
+            }
+            _ => {}
+        }
+        Ok(())
+    });
+    if let Err(error) = read {
+        let _ = fs::remove_file(&staged);
+        return Err(error);
+    }
+    if manifest.is_none() {
+        return Err(invalid("archive has no manifest.json".into()));
+    }
+    if !database {
+        return Err(invalid("archive has no valid den.db".into()));
+    }
+
+    for suffix in ["-wal", "-shm"] {
+        let mut sidecar = database_path.as_os_str().to_owned();
+        sidecar.push(suffix);
+        let _ = fs::remove_file(sidecar);
+    }
+    fs::rename(&staged, database_path)?;
+    println!("restored database to {}", database_path.display());
+
+    if let Some(archived) = archived_config {
+        let config_path = config::config_file_path().map_err(io::Error::other)?;
+        let current = fs::read_to_string(&config_path).unwrap_or_default();
+        if force || config::is_default_config(&current) {
+            fs::write(&config_path, archived)?;
+            println!("restored config to {}", config_path.display());
+        } else {
+            let aside = config_path.with_extension("toml.imported");
+            fs::write(&aside, archived)?;
+            println!(
+                "kept existing config; archived config written to {}",
+                aside.display()
+            );
+        }
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn args(values: &[&str]) -> Vec<String> {
+        values.iter().map(|v| v.to_string()).collect()
+    }
+
+    #[test]
+    fn parse_recognizes_commands() {
+        assert!(matches!(parse(&[]), Ok(Command::Serve)));
+        assert!(matches!(
+            parse(&args(&["export", "-o", "x.tar.zst"])),
+            Ok(Command::Export { .. })
+        ));
+        assert!(matches!(
+            parse(&args(&["import", "--input", "x.tar.zst", "--force"])),
+            Ok(Command::Import { force: true, .. })
+        ));
+        assert!(parse(&args(&["export", "--force", "-o", "x"])).is_err());
+        assert!(parse(&args(&["import"])).is_err());
+        assert!(matches!(
+            parse(&args(&["breach-filter", "-i", "hashes.txt", "-o", "f.bin"])),
+            Ok(Command::BreachFilter { .. })
+        ));
+        assert!(parse(&args(&["breach-filter", "-i", "hashes.txt"])).is_err());
+        assert!(matches!(
+            parse(&args(&["passkey", "invite"])),
+            Ok(Command::PasskeyInvite { ttl_minutes: 10 })
+        ));
+        assert!(matches!(
+            parse(&args(&["passkey", "invite", "--ttl", "2h"])),
+            Ok(Command::PasskeyInvite { ttl_minutes: 120 })
+        ));
+        assert!(parse(&args(&["passkey", "invite", "--ttl", "2d"])).is_err());
+        assert!(parse(&args(&["passkey", "invite", "--ttl", "10"])).is_err());
+        assert!(parse(&args(&["passkey"])).is_err());
+        assert!(matches!(
+            parse(&args(&["config", "check"])),
+            Ok(Command::ConfigCheck)
+        ));
+        assert!(parse(&args(&["config"])).is_err());
+        assert!(matches!(
+            parse(&args(&["log-level", "info,den=debug"])),
+            Ok(Command::LogLevel { filter: Some(_) })
+        ));
+        assert!(parse(&args(&["log-level", "info", "debug"])).is_err());
+        assert!(parse(&args(&["bogus"])).is_err());
+    }
+
+    #[test]
+    fn config_flag_is_taken_from_anywhere() {
+        let mut rest = args(&["export", "--config", "/etc/den.toml", "-o", "x"]);
+        assert_eq!(
+            take_config_flag(&mut rest),
+            Ok(Some(PathBuf::from("/etc/den.toml")))
+        );
+        assert_eq!(rest, args(&["export", "-o", "x"]));
+        let mut rest = args(&["--config=den.toml"]);
+        assert_eq!(
+            take_config_flag(&mut rest),
+            Ok(Some(PathBuf::from("den.toml")))
+        );
+        assert!(rest.is_empty());
+        assert!(take_config_flag(&mut args(&["doctor", "--config"])).is_err());
+        assert_eq!(take_config_flag(&mut args(&["doctor"])), Ok(None));
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn private_files_are_new_and_owner_only() {
+        use std::os::unix::fs::PermissionsExt;
+
+        let dir = std::env::temp_dir().join(format!("den-cli-test-{}", std::process::id()));
+        fs::create_dir_all(&dir).unwrap();
+        let path = dir.join("den.db.snapshot");
+        let _ = fs::remove_file(&path);
+        private_file(&path).unwrap();
+        let mode = fs::metadata(&path).unwrap().permissions().mode();
+        assert_eq!(mode & 0o777, 0o600);
+        assert_eq!(
+            private_file(&path).unwrap_err().kind(),
+            io::ErrorKind::AlreadyExists
+        );
+        fs::remove_dir_all(&dir).unwrap();
+    }
+}
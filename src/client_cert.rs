@@ -0,0 +1,129 @@
+use std::collections::HashSet;
+use std::net::IpAddr;
+
+use axum::http::{HeaderMap, HeaderName};
+use base64::Engine;
+use base64::engine::general_purpose::STANDARD as BASE64;
+use openssl::error::ErrorStack;
+use openssl::nid::Nid;
+use openssl::stack::Stack;
+use openssl::x509::store::{X509Store, X509StoreBuilder};
+use openssl::x509::{X509, X509PurposeId, X509StoreContext};
+
+use crate::config::{ClientCertConfig, ConfigError, invalid};
+
+/// Client certificates presented to a TLS-terminating proxy and forwarded in a
+/// header. Only trusted proxies may set the header; the cert must chain to `ca_path`.
+pub struct ClientCertAuth {
+    store: X509Store,
+    header: HeaderName,
+    trusted_proxies: HashSet<IpAddr>,
+}
+
+impl ClientCertAuth {
+    pub fn load(config: &ClientCertConfig) -> Result<Self, ConfigError> {
+        const KEY: &str = "client_cert.ca_path";
+        let pem = std::fs::read(&config.ca_path).map_err(|source| ConfigError::ReadFile {
+            key: KEY,
+            path: config.ca_path.clone().into(),
+            source,
+        })?;
+        let certs =
+            X509::stack_from_pem(&pem).map_err(|e| invalid(KEY, format!("invalid PEM: {e}")))?;
+        if certs.is_empty() {
+            return Err(invalid(
+                KEY,
+                format!("{} contains no certificates", config.ca_path),
+            ));
+        }
+        let store = (|| {
+            let mut store = X509StoreBuilder::new()?;
+            for cert in certs {
+                store.add_cert(cert)?;
+            }
+            store.set_purpose(X509PurposeId::SSL_CLIENT)?;
+            Ok::<_, ErrorStack>(store.build())
+        })()
+        .map_err(|e| invalid(KEY, format!("failed to build the CA store: {e}")))?;
+        Ok(Self {
+            store,
+            header: HeaderName::try_from(config.header.as_str()).map_err(|_| {
+                invalid(
+                    "client_cert.header",
+                    format!("{:?} is not a header name", config.header),
+                )
+            })?,
+            trusted_proxies: config
+                .trusted_proxies
+                .iter()
+                .map(IpAddr::to_canonical)
+                .collect(),
+        })
+    }
+
+    /// Subject CN of a verified client certificate, if a trusted proxy forwarded one.
+    pub fn subject(&self, headers: &HeaderMap, peer: IpAddr) -> Option<String> {
+        if !self.trusted_proxies.contains(&peer.to_canonical()) {
+            return None;
+        }
+        let value = headers.get(&self.header)?.to_str().ok()?;
+        let cert = decode_cert(value)?;
+
+        let chain = Stack::new().ok()?;
+        let mut ctx = X509StoreContext::new().ok()?;
+        let verified = ctx
+            .init(&self.store, &cert, &chain, |c| c.verify_cert())
+            .ok()?;
+        if !verified {
+            tracing::warn!("rejected client certificate not signed by configured CA");
+            return None;
+        }
+
+        let cn = cert.subject_name().entries_by_nid(Nid::COMMONNAME).next()?;
+        Some(cn.data().as_utf8().ok()?.to_string())
+    }
+}
+
+/// Accepts URL-escaped PEM (nginx `$ssl_client_escaped_cert`, Caddy) or bare
+/// base64 DER (Traefik `X-Forwarded-Tls-Client-Cert`).
+fn decode_cert(value: &str) -> Option<X509> {
+    let value = percent_decode(value.trim())?;
+    if value.starts_with("-----BEGIN") {
+        return X509::from_pem(value.as_bytes()).ok();
+    }
+    let der = BASE64.decode(value.replace(['\n', '\r', ' '], "")).ok()?;
+    X509::from_der(&der).ok()
+}
+
+/// Strict `%XX` decoding; `None` on malformed escapes or non-UTF-8 output.
+pub fn percent_decode(value: &str) -> Option<String> {
+    let bytes = value.as_bytes();
+    let mut out = Vec::with_capacity(bytes.len());
+    let mut i = 0;
+    while i < bytes.len() {
+        if bytes[i] == b'%' {
+            let hex = std::str::from_utf8(bytes.get(i + 1..i + 3)?).ok()?;
+            out.push(u8::from_str_radix(hex, 16).ok()?);
+            i += 3;
+        } else {
+            out.push(bytes[i]);
+            i += 1;
+        }
+    }
+    String::from_utf8(out).ok()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn percent_decode_handles_escaped_pem() {
+        assert_eq!(
+            percent_decode("-----BEGIN%20CERTIFICATE-----%0AMII%2B").as_deref(),
+            Some("-----BEGIN CERTIFICATE-----\nMII+")
+        );
+        assert_eq!(percent_decode("%zz"), None);
+        assert_eq!(percent_decode("%4"), None);
+    }
+}
@@ -1,31 +1,1264 @@
+use std::collections::BTreeMap;
+use std::fmt;
+use std::io;
+use std::net::{IpAddr, Ipv4Addr, Ipv6Addr, SocketAddr};
 use std::path::{Path, PathBuf};
+use std::sync::OnceLock;
 
-use serde::Deserialize;
+use serde::{Deserialize, Serialize};
+use serde_json::{Value, json};
+#[cfg(not(windows))]
 use xdg::BaseDirectories;
 
+use crate::access::IpNet;
+
 const DEFAULT_PORT: u16 = 3000;
 const DEFAULT_RUST_LOG: &str = "info";
 const DEFAULT_RP_ID: &str = "localhost";
 const DEFAULT_RP_ORIGIN: &str = "http://localhost:3000";
+const DEFAULT_COOKIE_NAME: &str = "den_session";
+/// About 16 million hashes; more would stall slow phones for minutes.
+const MAX_PUZZLE_DIFFICULTY: u8 = 24;
+/// Tarpitted requests hold a connection; past a minute clients have given up anyway.
+const MAX_HONEYPOT_DELAY_SECONDS: u32 = 60;
+/// Every sign-in waits on its hooks; past this a hook should answer asynchronously.
+const MAX_HOOK_TIMEOUT_MS: u64 = 10_000;
+/// Each sign-in instantiates the plugin afresh; this caps what one can claim.
+const MAX_PLUGIN_MEMORY_MB: u32 = 256;
+
+/// sysexits(3) codes, so service managers can tell a bad config (restarting won't
+/// help; systemd `RestartPreventExitStatus=78`) from a transient failure.
+pub const EX_NOINPUT: i32 = 66;
+pub const EX_UNAVAILABLE: i32 = 69;
+pub const EX_CANTCREAT: i32 = 73;
+pub const EX_CONFIG: i32 = 78;
+
+/// Why the config could not be loaded.
+#[derive(Debug)]
+pub enum ConfigError {
+    /// The config directory or default file could not be created.
+    Create {
+        path: PathBuf,
+        source: io::Error,
+    },
+    Read {
+        path: PathBuf,
+        source: io::Error,
+    },
+    /// A file a key points at (a CA bundle, a template) could not be read.
+    ReadFile {
+        key: &'static str,
+        path: PathBuf,
+        source: io::Error,
+    },
+    /// Not TOML, or a key of the wrong type; `path` is unset for inline configs.
+    Parse {
+        path: Option<PathBuf>,
+        source: toml::de::Error,
+    },
+    /// A key den doesn't know, usually a typo; `suggestion` is the closest known key.
+    UnknownKey {
+        path: Option<PathBuf>,
+        key: String,
+        suggestion: Option<String>,
+    },
+    /// A key holds a value den can't run with.
+    Invalid {
+        key: &'static str,
+        message: String,
+    },
+    /// The database or `[storage]` backend failed while startup state was read.
+    Storage {
+        action: &'static str,
+        message: String,
+    },
+}
+
+impl ConfigError {
+    pub fn exit_code(&self) -> i32 {
+        match self {
+            Self::Create { .. } => EX_CANTCREAT,
+            Self::Read { .. } | Self::ReadFile { .. } => EX_NOINPUT,
+            Self::Parse { .. } | Self::UnknownKey { .. } | Self::Invalid { .. } => EX_CONFIG,
+            Self::Storage { .. } => EX_UNAVAILABLE,
+        }
+    }
+}
+
+impl fmt::Display for ConfigError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Create { path, source } => {
+                write!(f, "failed to create {}: {source}", path.display())
+            }
+            Self::Read { path, source } => {
+                write!(f, "failed to read config file {}: {source}", path.display())
+            }
+            Self::ReadFile { key, path, source } => {
+                write!(
+                    f,
+                    "failed to read `{key}` file {}: {source}",
+                    path.display()
+                )
+            }
+            Self::Parse {
+                path: Some(path),
+                source,
+            } => write!(
+                f,
+                "invalid TOML in config file {}: {source}",
+                path.display()
+            ),
+            Self::Parse { path: None, source } => write!(f, "invalid TOML in config: {source}"),
+            Self::UnknownKey {
+                path,
+                key,
+                suggestion,
+            } => {
+                write!(f, "unknown key `{key}` in config")?;
+                if let Some(path) = path {
+                    write!(f, " file {}", path.display())?;
+                }
+                match suggestion {
+                    Some(suggestion) => write!(f, "; did you mean `{suggestion}`?"),
+                    None => Ok(()),
+                }
+            }
+            Self::Invalid { key, message } => write!(f, "invalid `{key}` in config: {message}"),
+            Self::Storage { action, message } => write!(f, "failed to {action}: {message}"),
+        }
+    }
+}
+
+impl std::error::Error for ConfigError {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            Self::Create { source, .. }
+            | Self::Read { source, .. }
+            | Self::ReadFile { source, .. } => Some(source),
+            Self::Parse { source, .. } => Some(source),
+            Self::UnknownKey { .. } | Self::Invalid { .. } | Self::Storage { .. } => None,
+        }
+    }
+}
+
+pub(crate) fn invalid(key: &'static str, message: impl Into<String>) -> ConfigError {
+    ConfigError::Invalid {
+        key,
+        message: message.into(),
+    }
+}
+
+/// `Parse`, or `UnknownKey` for serde's "unknown field `x`, expected one of `a`, `b`"
+/// with the nearest of the expected keys, if any is close.
+fn deserialize_error(path: Option<PathBuf>, source: toml::de::Error) -> ConfigError {
+    let Some((key, expected)) = source
+        .message()
+        .strip_prefix("unknown field `")
+        .and_then(|rest| rest.split_once('`'))
+    else {
+        return ConfigError::Parse { path, source };
+    };
+    let suggestion = expected
+        .split('`')
+        .skip(1)
+        .step_by(2)
+        .map(|known| (edit_distance(key, known), known))
+        .min()
+        .filter(|&(distance, _)| distance <= key.len() / 3 + 1)
+        .map(|(_, known)| known.to_owned());
+    ConfigError::UnknownKey {
+        path,
+        key: key.to_owned(),
+        suggestion,
+    }
+}
+
+/// Levenshtein distance, in characters.
+fn edit_distance(a: &str, b: &str) -> usize {
+    let b: Vec<char> = b.chars().collect();
+    let mut row: Vec<usize> = (0..=b.len()).collect();
+    for (i, a) in a.chars().enumerate() {
+        let mut diagonal = row[0];
+        row[0] = i + 1;
+        for (j, &b) in b.iter().enumerate() {
+            let substitute = diagonal + usize::from(a != b);
+            diagonal = row[j + 1];
+            row[j + 1] = substitute.min(row[j] + 1).min(diagonal + 1);
+        }
+    }
+    row[b.len()]
+}
 
 #[derive(Debug, Deserialize, Default)]
+#[serde(deny_unknown_fields)]
 struct FileConfig {
     port: Option<u16>,
+    tls: Option<TlsConfig>,
+    bind_address: Option<String>,
+    listen: Option<Vec<String>>,
+    ready_file: Option<String>,
     rust_log: Option<String>,
     rp_id: Option<String>,
     rp_origin: Option<String>,
     allowed_hosts: Option<Vec<String>>,
     database_path: Option<String>,
+    cookie_domain: Option<String>,
+    cookie_name: Option<String>,
+    cookie_same_site: Option<CookieSameSite>,
+    cookie_partitioned: Option<bool>,
+    storage: Option<StorageConfig>,
+    instance_id: Option<String>,
+    alert_webhook_url: Option<String>,
+    smtp: Option<SmtpConfig>,
+    push: Option<PushConfig>,
+    notification_templates: Option<String>,
+    apps: Option<Vec<AppPolicyConfig>>,
+    forward_auth: Option<ForwardAuthConfig>,
+    client_cert: Option<ClientCertConfig>,
+    attestation: Option<AttestationConfig>,
+    related_origins: Option<bool>,
+    security_txt: Option<SecurityTxtConfig>,
+    recovery: Option<RecoveryConfig>,
+    upstream_oidc: Option<UpstreamOidcConfig>,
+    ldap: Option<LdapConfig>,
+    breached_passwords: Option<BreachedPasswordsConfig>,
+    trusted_proxies: Option<Vec<String>>,
+    access_control: Option<AccessControlConfig>,
+    geoip_database: Option<String>,
+    asn_database: Option<String>,
+    deny_login_countries: Option<Vec<String>>,
+    login_anomaly: Option<LoginAnomalyConfig>,
+    login_throttle: Option<LoginThrottleConfig>,
+    login_puzzle: Option<LoginPuzzleConfig>,
+    honeypot: Option<HoneypotConfig>,
+    hooks: Option<Vec<HookConfig>>,
+    policy_plugin: Option<PolicyPluginConfig>,
+    lua_policy: Option<LuaPolicyConfig>,
+    policies: Option<Vec<PolicyRuleConfig>>,
+    passkeys: Option<PasskeyPolicyConfig>,
+    audit_export: Option<AuditExportConfig>,
+    slo: Option<SloConfig>,
+    database: Option<DatabaseConfig>,
+    maintenance: Option<bool>,
+    compression: Option<CompressionConfig>,
+    branding: Option<BrandingConfig>,
+    web_integrity: Option<WebIntegrityConfig>,
+    spa_routes: Option<Vec<String>>,
+    web_source: Option<String>,
+    error_pages: Option<ErrorPagesConfig>,
+    bootstrap_token: Option<String>,
+    default_language: Option<String>,
+    dev_login_password: Option<String>,
+    break_glass_token_file: Option<PathBuf>,
+    log_presets: Option<BTreeMap<String, String>>,
+}
+
+#[derive(Debug, Clone, Default, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct BrandingConfig {
+    pub title: Option<String>,
+    /// Image file served at `/api/config/branding/logo`.
+    pub logo_path: Option<String>,
+    pub accent_color: Option<String>,
+    pub footer_text: Option<String>,
+}
+
+/// HTML files served for the frontend's 404s and maintenance 503s; relative paths
+/// are resolved against the config file's directory.
+#[derive(Debug, Clone, Default, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct ErrorPagesConfig {
+    /// Unknown paths that aren't SPA routes, instead of the SPA's not-found page.
+    pub not_found: Option<PathBuf>,
+    /// Browser navigations refused in maintenance mode: auth writes and `/login`.
+    pub maintenance: Option<PathBuf>,
+}
+
+/// Verify the web directory against a manifest signed at build time (see
+/// `web/vite.config.ts`), so tampered login-page JS is caught at startup.
+#[derive(Debug, Clone, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct WebIntegrityConfig {
+    /// Ed25519 public key (PEM) for the build's `DEN_WEB_SIGNING_KEY`.
+    pub public_key: String,
+    #[serde(default)]
+    pub on_mismatch: MismatchAction,
+}
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum MismatchAction {
+    /// Exit instead of serving the assets.
+    #[default]
+    Refuse,
+    /// Log a warning and serve them anyway.
+    Warn,
+}
+
+#[derive(Debug, Clone, Copy, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct LoginAnomalyConfig {
+    /// Ask for a second passkey assertion when a login looks unusual.
+    pub step_up: bool,
+    /// Logins to observe before judging new ones against the history.
+    pub min_logins: u32,
+}
+
+impl Default for LoginAnomalyConfig {
+    fn default() -> Self {
+        Self {
+            step_up: true,
+            min_logins: 3,
+        }
+    }
+}
+
+/// Refuse sign-ins from a client that failed `max_failures` times within
+/// `window_minutes`, until the oldest of those failures ages out.
+#[derive(Debug, Clone, Copy, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct LoginThrottleConfig {
+    pub max_failures: u32,
+    pub window_minutes: u32,
+}
+
+impl Default for LoginThrottleConfig {
+    fn default() -> Self {
+        Self {
+            max_failures: 10,
+            window_minutes: 15,
+        }
+    }
+}
+
+/// Make clients outside `exempt` solve a proof-of-work puzzle before a login
+/// ceremony starts, so scanners can't fill the challenge table for free.
+#[derive(Debug, Clone, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct LoginPuzzleConfig {
+    /// Leading zero bits the solution's SHA-256 needs; each one doubles the work.
+    pub difficulty: u8,
+    /// Networks (CIDR) that sign in without a puzzle.
+    pub exempt: Vec<String>,
+}
+
+impl Default for LoginPuzzleConfig {
+    fn default() -> Self {
+        Self {
+            difficulty: 16,
+            exempt: Vec::new(),
+        }
+    }
+}
+
+/// Decoy paths scanners probe for. A hit is audited, answered with a slow 404, and
+/// its client refused everywhere for `block_minutes`.
+#[derive(Debug, Clone, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct HoneypotConfig {
+    pub paths: Vec<String>,
+    pub delay_seconds: u32,
+    pub block_minutes: u32,
+    /// Networks (CIDR) never blocked, so a misfiring monitor can't lock out admins.
+    pub exempt: Vec<String>,
+}
+
+impl Default for HoneypotConfig {
+    fn default() -> Self {
+        Self {
+            paths: [
+                "/wp-login.php",
+                "/wp-admin",
+                "/xmlrpc.php",
+                "/.env",
+                "/.git/config",
+                "/phpmyadmin",
+            ]
+            .map(String::from)
+            .to_vec(),
+            delay_seconds: 10,
+            block_minutes: 60,
+            exempt: Vec::new(),
+        }
+    }
+}
+
+/// An external policy check run at `event`, which can veto it. Exactly one of
+/// `command` (argv, no shell; the context arrives as JSON on stdin, exit 0 allows)
+/// or `url` (the context is POSTed as JSON; 2xx allows, 403 vetoes).
+#[derive(Debug, Clone, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct HookConfig {
+    pub event: HookEvent,
+    pub command: Option<Vec<String>>,
+    pub url: Option<String>,
+    #[serde(default = "default_hook_timeout_ms")]
+    pub timeout_ms: u64,
+    /// What a hook that fails to answer (crash, timeout, unexpected status) means.
+    #[serde(default)]
+    pub on_error: HookFailure,
+}
+
+fn default_hook_timeout_ms() -> u64 {
+    2000
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Deserialize, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum HookEvent {
+    /// Before any sign-in opens a session (break-glass excepted).
+    Session,
+    /// Before a new passkey is stored.
+    Registration,
+    /// Before a sign-in is handed to another origin (redirect tokens, sibling logins).
+    Redirect,
+}
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum HookFailure {
+    #[default]
+    Deny,
+    Allow,
+}
+
+/// A WASM module asked about every passkey sign-in (`policy-plugin` builds only).
+/// It exports `memory`, `alloc(len) -> ptr` and `evaluate(ptr, len) -> verdict`,
+/// imports nothing, and gets the login context as JSON; 0 allows, 1 denies, 2 asks
+/// for a second passkey.
+#[derive(Debug, Clone, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct PolicyPluginConfig {
+    pub path: String,
+    /// Instructions (wasmtime fuel) per sign-in before the module is stopped.
+    #[serde(default = "default_plugin_fuel")]
+    pub fuel: u64,
+    #[serde(default = "default_plugin_memory_mb")]
+    pub max_memory_mb: u32,
+    /// What a trap, exhausted fuel or an unknown verdict means.
+    #[serde(default)]
+    pub on_error: HookFailure,
+}
+
+fn default_plugin_fuel() -> u64 {
+    10_000_000
+}
+
+fn default_plugin_memory_mb() -> u32 {
+    16
+}
+
+/// A Lua script (`lua-policy` builds only) that may define `on_login`,
+/// `on_register` and `on_redirect`. Each gets the `[[hooks]]` context as a table
+/// and returns nothing or `"allow"` to allow, or `"deny"` plus an optional reason.
+#[derive(Debug, Clone, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct LuaPolicyConfig {
+    /// Relative to the config file's directory; reloaded when it changes.
+    pub script: PathBuf,
+    #[serde(default = "default_lua_timeout_ms")]
+    pub timeout_ms: u64,
+    /// What a script error or timeout means.
+    #[serde(default)]
+    pub on_error: HookFailure,
+}
+
+fn default_lua_timeout_ms() -> u64 {
+    100
+}
+
+/// One `[[policies]]` rule. Rules are tried in order and the first one whose
+/// conditions all hold decides; a condition left out holds for everything, and a
+/// sign-in no rule matches is allowed.
+#[derive(Debug, Clone, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct PolicyRuleConfig {
+    /// Named in the log and audit trail when the rule denies.
+    pub name: String,
+    pub effect: PolicyEffect,
+    /// Client networks (CIDR).
+    #[serde(default)]
+    pub networks: Vec<String>,
+    /// UTC time of day, `"08:00-18:00"`; a window like `"22:00-06:00"` wraps past
+    /// midnight.
+    pub hours: Option<String>,
+    /// UTC days of the week.
+    #[serde(default)]
+    pub days: Vec<PolicyDay>,
+    /// Origins the session is for; den's own `rp_origin` for a plain sign-in.
+    #[serde(default)]
+    pub apps: Vec<String>,
+    /// How the user proved who they are.
+    #[serde(default)]
+    pub methods: Vec<LoginMethod>,
+    /// Authenticator models (attestation AAGUIDs) of the signing passkey. Only packed
+    /// and TPM certificate attestation record one; a passkey without, or a sign-in by
+    /// another method, matches no such rule.
+    #[serde(default)]
+    pub aaguids: Vec<String>,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum PolicyEffect {
+    Allow,
+    Deny,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum PolicyDay {
+    Mon,
+    Tue,
+    Wed,
+    Thu,
+    Fri,
+    Sat,
+    Sun,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum LoginMethod {
+    Passkey,
+    Ldap,
+    Oidc,
+    /// A redirect token handed to an app (or a QR login link) being redeemed.
+    Redirect,
+}
+
+#[derive(Debug, Clone, Copy, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct PasskeyPolicyConfig {
+    /// Passkeys older than this stop signing in, forcing rotation; temporary
+    /// passkeys keep their own earlier expiry.
+    pub max_age_days: Option<u32>,
+    /// How far ahead `/api/admin/stats` counts passkeys as expiring soon.
+    pub warning_days: u32,
+    /// How long a deleted passkey can be restored before housekeeping purges it.
+    pub delete_grace_hours: u32,
+}
+
+impl Default for PasskeyPolicyConfig {
+    fn default() -> Self {
+        Self {
+            max_age_days: None,
+            warning_days: 14,
+            delete_grace_hours: 24,
+        }
+    }
+}
+
+/// Copies of audit events outside the database, so they survive restores and can
+/// be shipped to a SIEM.
+#[derive(Debug, Clone, Deserialize)]
+#[serde(tag = "target", rename_all = "lowercase", deny_unknown_fields)]
+pub enum AuditExportConfig {
+    /// Append-only file, rotated to `<path>.1` .. `<path>.<keep>` past `max_bytes`.
+    File {
+        path: String,
+        #[serde(default)]
+        format: AuditFormat,
+        #[serde(default = "default_audit_max_bytes")]
+        max_bytes: u64,
+        #[serde(default = "default_audit_keep")]
+        keep: u32,
+    },
+    /// RFC 5424 syslog (facility authpriv) to `udp://host[:514]` or `tcp://host[:514]`.
+    Syslog {
+        address: String,
+        #[serde(default = "default_syslog_format")]
+        format: AuditFormat,
+    },
+}
+
+impl AuditExportConfig {
+    pub fn format(&self) -> AuditFormat {
+        match self {
+            Self::File { format, .. } | Self::Syslog { format, .. } => *format,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum AuditFormat {
+    /// One JSON object per line.
+    #[default]
+    Jsonl,
+    /// ArcSight Common Event Format.
+    Cef,
+    /// `<time> <host> den: <kind> from <ip> key="value"...`, for fail2ban filters.
+    Fail2ban,
+}
+
+fn default_audit_max_bytes() -> u64 {
+    10 * 1024 * 1024
+}
+
+fn default_audit_keep() -> u32 {
+    5
+}
+
+fn default_syslog_format() -> AuditFormat {
+    AuditFormat::Cef
+}
+
+/// Availability objective over every request den answers: a 5xx, or a response
+/// slower than `latency_ms`, spends error budget.
+#[derive(Debug, Clone, Copy, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct SloConfig {
+    /// Fraction of requests that must be good, e.g. 0.999.
+    pub objective: f64,
+    pub latency_ms: Option<u64>,
+    /// Warn when the budget burns this many times faster than sustainable over both
+    /// the last 5 minutes and the last hour; 14.4 spends 2% of a 30-day budget in
+    /// an hour.
+    pub burn_rate: f64,
+}
+
+impl Default for SloConfig {
+    fn default() -> Self {
+        Self {
+            objective: 0.999,
+            latency_ms: None,
+            burn_rate: 14.4,
+        }
+    }
+}
+
+/// tower-http takes one level for every encoding, so only the semantic levels are exposed.
+#[derive(Debug, Clone, Copy, Default, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum CompressionQuality {
+    Fastest,
+    #[default]
+    Default,
+    Best,
+}
+
+#[derive(Debug, Clone, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct CompressionConfig {
+    pub gzip: bool,
+    pub br: bool,
+    pub zstd: bool,
+    pub level: CompressionQuality,
+    /// Responses smaller than this many bytes are sent uncompressed.
+    pub min_size: u16,
+}
+
+impl Default for CompressionConfig {
+    fn default() -> Self {
+        Self {
+            gzip: true,
+            br: true,
+            zstd: true,
+            level: CompressionQuality::Default,
+            min_size: 256,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum JournalMode {
+    Delete,
+    Truncate,
+    Persist,
+    Memory,
+    Wal,
+    Off,
+}
+
+#[derive(Debug, Clone, Copy, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Synchronous {
+    Off,
+    Normal,
+    Full,
+    Extra,
+}
+
+#[derive(Debug, Clone, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct DatabaseConfig {
+    pub journal_mode: JournalMode,
+    pub synchronous: Synchronous,
+    pub busy_timeout_ms: u64,
+    pub max_connections: u32,
+    /// Recycle pooled connections after this long; kept indefinitely when omitted.
+    pub max_lifetime_seconds: Option<u64>,
+    /// Queries at least this slow are logged as warnings.
+    pub slow_query_ms: u64,
+}
+
+impl Default for DatabaseConfig {
+    fn default() -> Self {
+        Self {
+            journal_mode: JournalMode::Wal,
+            synchronous: Synchronous::Normal,
+            busy_timeout_ms: 5_000,
+            max_connections: 8,
+            max_lifetime_seconds: None,
+            slow_query_ms: 200,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Default, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct AccessRulesConfig {
+    pub allow: Vec<String>,
+    pub deny: Vec<String>,
+}
+
+#[derive(Debug, Clone, Default, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct AccessControlConfig {
+    pub register: AccessRulesConfig,
+    pub default: AccessRulesConfig,
+}
+
+#[derive(Debug, Clone, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct ClientCertConfig {
+    /// PEM bundle of CAs that issue automation client certificates.
+    pub ca_path: String,
+    #[serde(default = "default_client_cert_header")]
+    pub header: String,
+    /// Peers allowed to set `header`; loopback when omitted.
+    #[serde(default = "default_trusted_proxies")]
+    pub trusted_proxies: Vec<IpAddr>,
+}
+
+/// Which authenticators may register passkeys, checked against their attestation.
+#[derive(Debug, Clone, Default, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct AttestationConfig {
+    /// Only these AAGUIDs may register; any when empty.
+    #[serde(default)]
+    pub allowed_aaguids: Vec<String>,
+    #[serde(default)]
+    pub denied_aaguids: Vec<String>,
+    /// PEM bundle of attestation roots (e.g. vendor CAs); when set, registration needs
+    /// an attestation certificate chaining to one of them.
+    pub trusted_roots: Option<String>,
+}
+
+/// Fields of `/.well-known/security.txt` (RFC 9116).
+#[derive(Debug, Clone, Default, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct SecurityTxtConfig {
+    /// `mailto:`, `https:` or `tel:` URIs; at least one is required.
+    #[serde(default)]
+    pub contact: Vec<String>,
+    /// RFC 3339 timestamp; one year after each request when unset.
+    pub expires: Option<String>,
+    #[serde(default)]
+    pub encryption: Vec<String>,
+    pub acknowledgments: Option<String>,
+    #[serde(default)]
+    pub preferred_languages: Vec<String>,
+    pub policy: Option<String>,
+    pub hiring: Option<String>,
+}
+
+/// Account recovery through a printed recovery URL: using it schedules a reset
+/// that any signed-in session can cancel until the delay runs out.
+#[derive(Debug, Clone, Copy, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct RecoveryConfig {
+    #[serde(default = "default_recovery_delay_hours")]
+    pub delay_hours: u32,
+}
+
+fn default_recovery_delay_hours() -> u32 {
+    72
+}
+
+#[derive(Debug, Clone, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct UpstreamOidcConfig {
+    /// Issuer URL; `/.well-known/openid-configuration` is resolved against it.
+    pub issuer: String,
+    pub client_id: String,
+    pub client_secret: String,
+    #[serde(default = "default_oidc_scopes")]
+    pub scopes: Vec<String>,
+    /// ID token claim compared against `allowed_users`.
+    #[serde(default = "default_oidc_user_claim")]
+    pub user_claim: String,
+    pub allowed_users: Vec<String>,
+    /// Login button text.
+    #[serde(default = "default_oidc_label")]
+    pub label: String,
+}
+
+#[derive(Debug, Clone, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct LdapConfig {
+    /// `ldaps://host[:port]`, or `ldap://` on a trusted network.
+    pub url: String,
+    /// Bind DN with `{user}` standing in for the login name, e.g. `uid={user},ou=people,dc=home`.
+    pub bind_dn: String,
+    /// LDAP login names allowed to sign in as the den user.
+    pub allowed_users: Vec<String>,
+}
+
+#[derive(Debug, Clone, Deserialize)]
+#[serde(tag = "source", rename_all = "lowercase", deny_unknown_fields)]
+pub enum BreachedPasswordsConfig {
+    /// k-anonymity range API; `url` is the range endpoint prefix.
+    Hibp {
+        #[serde(default = "default_hibp_url")]
+        url: String,
+    },
+    /// Offline filter written by `den breach-filter`.
+    Bloom { path: PathBuf },
+}
+
+fn default_hibp_url() -> String {
+    "https://api.pwnedpasswords.com/range".into()
+}
+
+fn default_oidc_scopes() -> Vec<String> {
+    vec!["openid".into(), "profile".into(), "email".into()]
+}
+
+fn default_oidc_user_claim() -> String {
+    "preferred_username".into()
+}
+
+fn default_oidc_label() -> String {
+    "Single sign-on".into()
+}
+
+fn default_client_cert_header() -> String {
+    "X-SSL-Client-Cert".into()
+}
+
+fn default_trusted_proxies() -> Vec<IpAddr> {
+    vec![Ipv4Addr::LOCALHOST.into(), Ipv6Addr::LOCALHOST.into()]
+}
+
+#[derive(Debug, Clone, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct ForwardAuthConfig {
+    pub user_header: String,
+    pub name_header: String,
+    pub groups_header: String,
+    /// den session id, matching the `sid` of back-channel logout tokens.
+    pub session_header: String,
+    /// Static groups reported for the den user.
+    pub groups: Vec<String>,
+}
+
+impl Default for ForwardAuthConfig {
+    fn default() -> Self {
+        Self {
+            user_header: "Remote-User".into(),
+            name_header: "Remote-Name".into(),
+            groups_header: "Remote-Groups".into(),
+            session_header: "Remote-Session".into(),
+            groups: Vec::new(),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct AppPolicyConfig {
+    pub name: String,
+    pub origin: String,
+    pub allowed_users: Option<Vec<String>>,
+    pub session_ttl_seconds: Option<i64>,
+    #[serde(default)]
+    pub scopes: Vec<String>,
+    /// OIDC back-channel logout endpoint, sent a logout token when a den session ends.
+    pub logout_uri: Option<String>,
+    /// HS256 key the app verifies logout tokens with; required with `logout_uri`.
+    pub logout_secret: Option<String>,
+    /// UTC time of day the app can be reached, as in `[[policies]]` (`"06:00-01:00"`
+    /// keeps it closed from 1am to 6am); checked for redirects and forward-auth.
+    pub access_hours: Option<String>,
+    /// UTC days of the week the app can be reached.
+    #[serde(default)]
+    pub access_days: Vec<PolicyDay>,
+}
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum CookieSameSite {
+    #[default]
+    Strict,
+    Lax,
+    None,
+}
+
+#[derive(Debug, Clone, Copy, Default, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum SmtpTls {
+    #[default]
+    Starttls,
+    Implicit,
+}
+
+#[derive(Debug, Clone, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct SmtpConfig {
+    pub host: String,
+    pub port: Option<u16>,
+    pub username: Option<String>,
+    pub password: Option<String>,
+    pub from: String,
+    pub to: String,
+    #[serde(default)]
+    pub tls: SmtpTls,
+    /// Event kinds to email about; all events when omitted.
+    pub events: Option<Vec<String>>,
+}
+
+impl SmtpConfig {
+    pub fn port(&self) -> u16 {
+        self.port.unwrap_or(match self.tls {
+            SmtpTls::Starttls => 587,
+            SmtpTls::Implicit => 465,
+        })
+    }
+}
+
+/// Backend for challenges, sessions and used redirect tokens.
+#[derive(Debug, Clone, Default, Deserialize)]
+#[serde(tag = "backend", rename_all = "lowercase", deny_unknown_fields)]
+pub enum StorageConfig {
+    #[default]
+    Sqlite,
+    /// `redis://[[user]:password@]host[:port][/db]`, shared by every den replica.
+    Redis { redis_url: String },
+}
+
+#[derive(Debug, Clone, Deserialize)]
+#[serde(tag = "provider", rename_all = "lowercase", deny_unknown_fields)]
+pub enum PushConfig {
+    /// `url` is the full topic URL, e.g. `https://ntfy.sh/my-den`.
+    Ntfy {
+        url: String,
+        token: Option<String>,
+    },
+    Gotify {
+        url: String,
+        token: String,
+    },
+}
+
+impl PushConfig {
+    pub fn url(&self) -> &str {
+        match self {
+            Self::Ntfy { url, .. } | Self::Gotify { url, .. } => url,
+        }
+    }
+}
+
+/// The certificate `tls:` listeners present; they offer h2 and http/1.1 over ALPN.
+#[derive(Debug, Clone, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct TlsConfig {
+    /// PEM chain, leaf first.
+    pub cert_path: String,
+    /// PEM private key for the leaf (PKCS#8, PKCS#1 or SEC1).
+    pub key_path: String,
+    /// Also answer HTTP/3 on each `tls:` listener's UDP port; `http3` builds only.
+    #[serde(default)]
+    pub enable_h3: bool,
+}
+
+/// One entry of `listen`: `host:port`, `tls:host:port` (with `[tls]`), or
+/// `unix:/path/to/socket`.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum ListenAddr {
+    Tcp(SocketAddr),
+    Tls(SocketAddr),
+    Unix(PathBuf),
+}
+
+impl ListenAddr {
+    fn parse(value: &str) -> Result<Self, String> {
+        if let Some(path) = value.strip_prefix("unix:") {
+            return match path.trim() {
+                "" => Err("unix: needs a socket path".into()),
+                path => Ok(Self::Unix(PathBuf::from(path))),
+            };
+        }
+        let (addr, tls) = match value.strip_prefix("tls:") {
+            Some(addr) => (addr.trim(), true),
+            None => (value, false),
+        };
+        let addr = addr
+            .parse()
+            .map_err(|_| format!("{value:?} is not host:port, tls:host:port or unix:/path"))?;
+        Ok(if tls {
+            Self::Tls(addr)
+        } else {
+            Self::Tcp(addr)
+        })
+    }
+}
+
+impl fmt::Display for ListenAddr {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Tcp(addr) => addr.fmt(f),
+            Self::Tls(addr) => write!(f, "tls:{addr}"),
+            Self::Unix(path) => write!(f, "unix:{}", path.display()),
+        }
+    }
 }
 
 #[derive(Debug)]
 pub struct AppConfig {
-    pub port: u16,
+    /// Every address den serves on, sharing one router; never empty. Built from
+    /// `bind_address`/`port` unless `listen` is set. Port 0 lets the OS pick one.
+    pub listen: Vec<ListenAddr>,
+    /// Written with the listening TCP ports once den accepts connections.
+    pub ready_file: Option<PathBuf>,
+    /// Set exactly when some `listen` entry is `tls:`.
+    pub tls: Option<TlsConfig>,
     pub rust_log: String,
     pub rp_id: String,
     pub rp_origin: String,
     pub allowed_hosts: Vec<String>,
     pub database_path: PathBuf,
+    pub cookie_domain: Option<String>,
+    pub cookie_name: String,
+    pub cookie_same_site: CookieSameSite,
+    /// CHIPS: host-only cookies with the `Partitioned` attribute, for apps embedded
+    /// in iframes on other sites.
+    pub cookie_partitioned: bool,
+    pub storage: StorageConfig,
+    /// Names this replica when claiming background-task leases; a random id per
+    /// process when unset.
+    pub instance_id: Option<String>,
+    pub alert_webhook_url: Option<String>,
+    pub smtp: Option<SmtpConfig>,
+    pub push: Option<PushConfig>,
+    /// Directory of `<event>.txt` overrides for email/push text; `templates/` next to
+    /// the config file when unset.
+    pub notification_templates: Option<PathBuf>,
+    pub apps: Vec<AppPolicyConfig>,
+    pub forward_auth: ForwardAuthConfig,
+    pub client_cert: Option<ClientCertConfig>,
+    pub attestation: Option<AttestationConfig>,
+    /// Publish the configured allowed hosts in `/.well-known/webauthn` and accept
+    /// passkey ceremonies from them.
+    pub related_origins: bool,
+    pub security_txt: Option<SecurityTxtConfig>,
+    pub recovery: Option<RecoveryConfig>,
+    pub upstream_oidc: Option<UpstreamOidcConfig>,
+    pub ldap: Option<LdapConfig>,
+    pub breached_passwords: Option<BreachedPasswordsConfig>,
+    pub trusted_proxies: Vec<String>,
+    pub access_control: AccessControlConfig,
+    pub geoip_database: Option<PathBuf>,
+    pub asn_database: Option<PathBuf>,
+    pub deny_login_countries: Vec<String>,
+    pub login_anomaly: LoginAnomalyConfig,
+    /// Off unless `[login_throttle]` is present.
+    pub login_throttle: Option<LoginThrottleConfig>,
+    /// Off unless `[login_puzzle]` is present.
+    pub login_puzzle: Option<LoginPuzzleConfig>,
+    /// Off unless `[honeypot]` is present.
+    pub honeypot: Option<HoneypotConfig>,
+    pub hooks: Vec<HookConfig>,
+    pub policy_plugin: Option<PolicyPluginConfig>,
+    pub lua_policy: Option<LuaPolicyConfig>,
+    pub policies: Vec<PolicyRuleConfig>,
+    pub passkeys: PasskeyPolicyConfig,
+    pub audit_export: Option<AuditExportConfig>,
+    pub slo: Option<SloConfig>,
+    pub database: DatabaseConfig,
+    pub maintenance: bool,
+    pub compression: CompressionConfig,
+    pub branding: BrandingConfig,
+    pub web_integrity: Option<WebIntegrityConfig>,
+    /// Client-side routes that get `index.html` (`$name` matches a segment, a
+    /// trailing `$` the rest); the web build's `den-routes.json` when unset.
+    pub spa_routes: Option<Vec<String>>,
+    /// Fetch the web build from this origin into a cache next to the database
+    /// instead of serving a local directory.
+    pub web_source: Option<WebSource>,
+    pub error_pages: ErrorPagesConfig,
+    pub bootstrap_token: Option<String>,
+    /// Catalog served when negotiation finds no bundled match; English when unset.
+    pub default_language: Option<String>,
+    /// Password for `POST /api/dev-login`; ignored unless built with `dev-auth`.
+    pub dev_login_password: Option<String>,
+    /// A token written here on the host signs in once via `POST /api/login/break-glass`.
+    pub break_glass_token_file: Option<PathBuf>,
+    /// Named log filters on top of `diagnostics::LOG_PRESETS`, usable as `rust_log`
+    /// or with `PUT /api/admin/log-level`.
+    pub log_presets: BTreeMap<String, String>,
+}
+
+impl AppConfig {
+    /// Settings safe to paste into a bug report (`GET /api/admin/diagnostics`). An
+    /// allow-list: secrets, credentials and URLs that may embed them (webhooks, push
+    /// topics, Redis) are reduced to whether they're set, so a new field stays out
+    /// until it is added here.
+    pub fn diagnostics(&self) -> Value {
+        let lower = |value: &dyn std::fmt::Debug| format!("{value:?}").to_lowercase();
+        json!({
+            "listen": self.listen.iter().map(ToString::to_string).collect::<Vec<_>>(),
+            "tls": self.tls.as_ref().map(|t| json!({
+                "cert_path": t.cert_path,
+                "enable_h3": t.enable_h3,
+            })),
+            "rust_log": self.rust_log,
+            "rp_id": self.rp_id,
+            "rp_origin": self.rp_origin,
+            "allowed_hosts": self.allowed_hosts,
+            "cookie": {
+                "name": self.cookie_name,
+                "domain": self.cookie_domain,
+                "same_site": lower(&self.cookie_same_site),
+                "partitioned": self.cookie_partitioned,
+            },
+            "storage": match self.storage {
+                StorageConfig::Sqlite => "sqlite",
+                StorageConfig::Redis { .. } => "redis",
+            },
+            "database": {
+                "journal_mode": lower(&self.database.journal_mode),
+                "synchronous": lower(&self.database.synchronous),
+                "max_connections": self.database.max_connections,
+                "busy_timeout_ms": self.database.busy_timeout_ms,
+                "slow_query_ms": self.database.slow_query_ms,
+            },
+            "alert_webhook": self.alert_webhook_url.is_some(),
+            "smtp": self.smtp.as_ref().map(|smtp| json!({
+                "host": smtp.host,
+                "port": smtp.port(),
+                "tls": lower(&smtp.tls),
+                "authenticated": smtp.username.is_some(),
+                "events": smtp.events,
+            })),
+            "push": self.push.as_ref().map(|push| match push {
+                PushConfig::Ntfy { .. } => "ntfy",
+                PushConfig::Gotify { .. } => "gotify",
+            }),
+            "notification_templates": self.notification_templates,
+            "apps": self.apps.iter().map(|app| json!({
+                "name": app.name,
+                "origin": app.origin,
+                "restricted_users": app.allowed_users.is_some(),
+                "session_ttl_seconds": app.session_ttl_seconds,
+                "scopes": app.scopes,
+                "backchannel_logout": app.logout_uri.is_some(),
+                "access_hours": app.access_hours,
+                "access_days": app.access_days.iter().map(|d| lower(d)).collect::<Vec<_>>(),
+            })).collect::<Vec<_>>(),
+            "client_cert": self.client_cert.as_ref().map(|c| json!({ "header": c.header })),
+            "attestation": self.attestation.as_ref().map(|a| json!({
+                "allowed_aaguids": a.allowed_aaguids,
+                "denied_aaguids": a.denied_aaguids,
+                "trusted_roots": a.trusted_roots.is_some(),
+            })),
+            "related_origins": self.related_origins,
+            "security_txt": self.security_txt.is_some(),
+            "recovery_delay_hours": self.recovery.map(|r| r.delay_hours),
+            "upstream_oidc": self.upstream_oidc.as_ref().map(|o| json!({
+                "issuer": o.issuer,
+                "scopes": o.scopes,
+                "user_claim": o.user_claim,
+            })),
+            "ldap": self.ldap.is_some(),
+            "breached_passwords": self.breached_passwords.as_ref().map(|b| match b {
+                BreachedPasswordsConfig::Hibp { .. } => "hibp",
+                BreachedPasswordsConfig::Bloom { .. } => "bloom",
+            }),
+            "trusted_proxies": self.trusted_proxies,
+            "geoip_database": self.geoip_database.is_some(),
+            "asn_database": self.asn_database.is_some(),
+            "deny_login_countries": self.deny_login_countries,
+            "login_anomaly": {
+                "step_up": self.login_anomaly.step_up,
+                "min_logins": self.login_anomaly.min_logins,
+            },
+            "login_throttle": self.login_throttle.map(|t| json!({
+                "max_failures": t.max_failures,
+                "window_minutes": t.window_minutes,
+            })),
+            "login_puzzle": self.login_puzzle.as_ref().map(|p| json!({
+                "difficulty": p.difficulty,
+                "exempt": p.exempt,
+            })),
+            "honeypot": self.honeypot.as_ref().map(|h| json!({
+                "paths": h.paths,
+                "delay_seconds": h.delay_seconds,
+                "block_minutes": h.block_minutes,
+                "exempt": h.exempt,
+            })),
+            "hooks": self.hooks.iter().map(|h| json!({
+                "event": lower(&h.event),
+                "command": h.command.is_some(),
+                "url": h.url.is_some(),
+                "timeout_ms": h.timeout_ms,
+                "on_error": lower(&h.on_error),
+            })).collect::<Vec<_>>(),
+            "policy_plugin": self.policy_plugin.as_ref().map(|p| json!({
+                "path": p.path,
+                "fuel": p.fuel,
+                "max_memory_mb": p.max_memory_mb,
+                "on_error": lower(&p.on_error),
+            })),
+            "lua_policy": self.lua_policy.as_ref().map(|l| json!({
+                "script": l.script,
+                "timeout_ms": l.timeout_ms,
+                "on_error": lower(&l.on_error),
+            })),
+            "policies": self.policies.iter().map(|p| json!({
+                "name": p.name,
+                "effect": lower(&p.effect),
+                "networks": p.networks,
+                "hours": p.hours,
+                "days": p.days.iter().map(|d| lower(d)).collect::<Vec<_>>(),
+                "apps": p.apps,
+                "methods": p.methods.iter().map(|m| lower(m)).collect::<Vec<_>>(),
+            })).collect::<Vec<_>>(),
+            "passkeys": {
+                "max_age_days": self.passkeys.max_age_days,
+                "warning_days": self.passkeys.warning_days,
+                "delete_grace_hours": self.passkeys.delete_grace_hours,
+            },
+            "audit_export": self.audit_export.as_ref().map(|export| json!({
+                "target": match export {
+                    AuditExportConfig::File { .. } => "file",
+                    AuditExportConfig::Syslog { .. } => "syslog",
+                },
+                "format": lower(&export.format()),
+            })),
+            "slo": self.slo.map(|slo| json!({
+                "objective": slo.objective,
+                "latency_ms": slo.latency_ms,
+                "burn_rate": slo.burn_rate,
+            })),
+            "maintenance": self.maintenance,
+            "web_integrity": self.web_integrity.as_ref().map(|w| lower(&w.on_mismatch)),
+            "spa_routes": self.spa_routes,
+            "web_source": self.web_source.is_some(),
+            "error_pages": {
+                "not_found": self.error_pages.not_found,
+                "maintenance": self.error_pages.maintenance,
+            },
+            "bootstrap_token": self.bootstrap_token.is_some(),
+            "default_language": self.default_language,
+            "dev_login": self.dev_login_password.is_some(),
+            "break_glass_token_file": self.break_glass_token_file.is_some(),
+            "log_presets": self.log_presets,
+        })
+    }
 }
 
 #[derive(Debug)]
@@ -39,19 +1272,194 @@ fn non_empty_string(value: Option<String>) -> Option<String> {
     (!s.is_empty()).then_some(s)
 }
 
-fn resolve_den_paths() -> DenPaths {
-    let xdg = BaseDirectories::with_prefix("den");
+fn is_http_url(value: &str) -> bool {
+    url::Url::parse(value).is_ok_and(|u| matches!(u.scheme(), "http" | "https") && u.has_host())
+}
+
+/// Where `web_source` builds are fetched from.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct WebSource {
+    /// URL prefix, ending in `/`; `s3://` is already mapped to the bucket's
+    /// virtual-hosted HTTPS endpoint.
+    pub url: url::Url,
+    /// For `s3://` sources, the region GETs are SigV4-signed for when AWS
+    /// credentials are in the environment; without them they go out anonymously.
+    pub s3_region: Option<String>,
+}
+
+const DEFAULT_S3_REGION: &str = "us-east-1";
+
+/// `AWS_REGION`, else `AWS_DEFAULT_REGION`, as the AWS tools read them.
+fn aws_region() -> Option<String> {
+    ["AWS_REGION", "AWS_DEFAULT_REGION"]
+        .into_iter()
+        .find_map(|name| non_empty_string(std::env::var(name).ok()))
+}
+
+/// `web_source` as the URL prefix builds are fetched from. `s3://bucket/prefix`
+/// goes to the bucket's endpoint in `region` (us-east-1 when unset).
+fn web_source(source: &str, region: Option<String>) -> Result<WebSource, String> {
+    let (url, s3_region) = match source.strip_prefix("s3://") {
+        Some(rest) => {
+            let (bucket, prefix) = rest.split_once('/').unwrap_or((rest, ""));
+            if bucket.is_empty() {
+                return Err("s3:// needs a bucket".into());
+            }
+            let region = region.unwrap_or_else(|| DEFAULT_S3_REGION.to_owned());
+            let endpoint = if region == DEFAULT_S3_REGION {
+                format!("https://{bucket}.s3.amazonaws.com/{prefix}")
+            } else {
+                format!("https://{bucket}.s3.{region}.amazonaws.com/{prefix}")
+            };
+            (url::Url::parse(&endpoint), Some(region))
+        }
+        None => (url::Url::parse(source), None),
+    };
+    let mut url = url.map_err(|e| e.to_string())?;
+    if !matches!(url.scheme(), "http" | "https") {
+        return Err("must be an http(s):// or s3:// URL".into());
+    }
+    if !url.path().ends_with('/') {
+        url.set_path(&format!("{}/", url.path()));
+    }
+    Ok(WebSource { url, s3_region })
+}
+
+/// RFC 6265 cookie-name: a token (visible ASCII without separators).
+fn is_valid_cookie_name(name: &str) -> bool {
+    !name.is_empty()
+        && name
+            .bytes()
+            .all(|b| b.is_ascii_graphic() && !b"()<>@,;:\\\"/[]?={}".contains(&b))
+}
+
+/// Keeps config, database and every relative path in one directory (a container
+/// volume), with no platform lookup.
+pub const ENV_DATA_DIR: &str = "DEN_DATA_DIR";
+
+/// Set by `den --config <path>`, which skips discovery entirely.
+static CONFIG_PATH: OnceLock<PathBuf> = OnceLock::new();
+
+/// Use `path` as the config file, with the default database next to it, instead
+/// of the platform location. Call before anything loads the config.
+pub fn set_config_path(path: PathBuf) {
+    let _ = CONFIG_PATH.set(path);
+}
+
+fn resolve_den_paths() -> Result<DenPaths, ConfigError> {
+    match CONFIG_PATH.get() {
+        Some(config_path) => Ok(explicit_den_paths(config_path)),
+        None => match data_dir() {
+            Some(dir) => Ok(explicit_den_paths(&dir.join("config.toml"))),
+            None => platform_den_paths(),
+        },
+    }
+}
+
+/// `$DEN_DATA_DIR`, unless `--config` names a config file elsewhere.
+pub fn data_dir() -> Option<PathBuf> {
+    if CONFIG_PATH.get().is_some() {
+        return None;
+    }
+    std::env::var_os(ENV_DATA_DIR)
+        .filter(|dir| !dir.is_empty())
+        .map(PathBuf::from)
+}
+
+fn explicit_den_paths(config_path: &Path) -> DenPaths {
     DenPaths {
-        config_path: xdg
-            .place_config_file("config.toml")
-            .unwrap_or_else(|e| panic!("failed to prepare config path: {e}")),
-        default_database_path: xdg
-            .get_data_home()
-            .expect("XDG data home is not available")
-            .join("den.db"),
+        config_path: config_path.to_owned(),
+        default_database_path: config_path.with_file_name("den.db"),
     }
 }
 
+/// `%APPDATA%\den\config.toml` and `%LOCALAPPDATA%\den\den.db`; a service account
+/// without a profile falls back to `%ProgramData%\den`.
+#[cfg(windows)]
+fn platform_den_paths() -> Result<DenPaths, ConfigError> {
+    let var = |name: &str| {
+        std::env::var_os(name)
+            .filter(|value| !value.is_empty())
+            .map(PathBuf::from)
+    };
+    let config_home = var("APPDATA")
+        .or_else(|| var("ProgramData"))
+        .ok_or_else(|| ConfigError::Create {
+            path: PathBuf::from("den\\config.toml"),
+            source: io::Error::new(
+                io::ErrorKind::NotFound,
+                "neither APPDATA nor ProgramData is set; pass --config",
+            ),
+        })?;
+    let data_home = var("LOCALAPPDATA").unwrap_or_else(|| config_home.clone());
+    Ok(DenPaths {
+        config_path: config_home.join("den").join("config.toml"),
+        default_database_path: data_home.join("den").join("den.db"),
+    })
+}
+
+/// `~/Library/Application Support/den` (`/Library/...` for a daemon without HOME),
+/// unless an XDG config from before this default exists.
+#[cfg(target_os = "macos")]
+fn platform_den_paths() -> Result<DenPaths, ConfigError> {
+    let xdg = BaseDirectories::with_prefix("den");
+    if let Some(config_path) = xdg.find_config_file("config.toml")
+        && let Some(data_home) = xdg.get_data_home()
+    {
+        return Ok(DenPaths {
+            config_path,
+            default_database_path: data_home.join("den.db"),
+        });
+    }
+    let library = std::env::var_os("HOME")
+        .filter(|home| !home.is_empty())
+        .map(|home| PathBuf::from(home).join("Library"))
+        .unwrap_or_else(|| PathBuf::from("/Library"));
+    let dir = library.join("Application Support").join("den");
+    Ok(DenPaths {
+        config_path: dir.join("config.toml"),
+        default_database_path: dir.join("den.db"),
+    })
+}
+
+#[cfg(not(any(windows, target_os = "macos")))]
+fn platform_den_paths() -> Result<DenPaths, ConfigError> {
+    let xdg = BaseDirectories::with_prefix("den");
+    let config_path =
+        xdg.place_config_file("config.toml")
+            .map_err(|source| ConfigError::Create {
+                path: PathBuf::from("den/config.toml"),
+                source,
+            })?;
+    let data_home = xdg.get_data_home().ok_or_else(|| ConfigError::Create {
+        path: PathBuf::from("den/den.db"),
+        source: io::Error::new(io::ErrorKind::NotFound, "no XDG data home; set HOME"),
+    })?;
+    Ok(DenPaths {
+        config_path,
+        default_database_path: data_home.join("den.db"),
+    })
+}
+
+/// The config file and default database locations; unlike `config_file_path`, a
+/// missing config file is left missing.
+pub fn den_paths() -> Result<(PathBuf, PathBuf), ConfigError> {
+    let paths = resolve_den_paths()?;
+    Ok((paths.config_path, paths.default_database_path))
+}
+
+/// Path of the active config file, created with defaults if missing.
+pub fn config_file_path() -> Result<PathBuf, ConfigError> {
+    let config_path = resolve_den_paths()?.config_path;
+    ensure_config_file(&config_path)?;
+    Ok(config_path)
+}
+
+/// Whether `contents` is the untouched generated default config.
+pub fn is_default_config(contents: &str) -> bool {
+    contents == default_config_contents()
+}
+
 fn default_config_contents() -> String {
     format!(
         "port = {DEFAULT_PORT}\n\
@@ -62,47 +1470,298 @@ allowed_hosts = []\n"
     )
 }
 
-fn ensure_config_file(config_path: &Path) {
-    let parent = config_path
-        .parent()
-        .expect("config path must have a parent");
-    std::fs::create_dir_all(parent).unwrap_or_else(|e| {
-        panic!(
-            "failed to create config directory at {}: {e}",
-            parent.display()
-        )
-    });
+fn ensure_config_file(config_path: &Path) -> Result<(), ConfigError> {
+    if let Some(parent) = config_path.parent() {
+        std::fs::create_dir_all(parent).map_err(|source| ConfigError::Create {
+            path: parent.to_owned(),
+            source,
+        })?;
+    }
     if config_path.exists() {
+        return Ok(());
+    }
+    std::fs::write(config_path, default_config_contents()).map_err(|source| ConfigError::Create {
+        path: config_path.to_owned(),
+        source,
+    })
+}
+
+fn read_file_config(config_path: &Path) -> Result<FileConfig, ConfigError> {
+    let contents = std::fs::read_to_string(config_path).map_err(|source| ConfigError::Read {
+        path: config_path.to_owned(),
+        source,
+    })?;
+    expand_config(&contents, Some(config_path))
+}
+
+/// `<key>_file` settings that name a file den uses rather than a secret to read in.
+const PATH_SETTINGS: &[&str] = &["ready_file", "break_glass_token_file"];
+
+/// Parse `contents`, overlay its `include` files in order and read `<key>_file`
+/// secrets, resolving relative paths against the config file's directory.
+fn expand_config(contents: &str, config_path: Option<&Path>) -> Result<FileConfig, ConfigError> {
+    let dir = config_path
+        .and_then(Path::parent)
+        .unwrap_or_else(|| Path::new("."));
+    let parse_error = |path: Option<&Path>| {
+        let path = path.map(Path::to_owned);
+        move |source| ConfigError::Parse { path, source }
+    };
+    let mut table: toml::Table = toml::from_str(contents).map_err(parse_error(config_path))?;
+    if let Some(include) = table.remove("include") {
+        let not_paths = || invalid("include", "must be an array of paths");
+        let toml::Value::Array(patterns) = include else {
+            return Err(not_paths());
+        };
+        for pattern in patterns {
+            let toml::Value::String(pattern) = pattern else {
+                return Err(not_paths());
+            };
+            for path in include_paths(&dir.join(pattern))? {
+                let contents =
+                    std::fs::read_to_string(&path).map_err(|source| ConfigError::Read {
+                        path: path.clone(),
+                        source,
+                    })?;
+                let included: toml::Table =
+                    toml::from_str(&contents).map_err(parse_error(Some(path.as_path())))?;
+                if included.contains_key("include") {
+                    return Err(invalid(
+                        "include",
+                        format!("{} may not include other files", path.display()),
+                    ));
+                }
+                merge_tables(&mut table, included);
+            }
+        }
+    }
+    read_secret_files(&mut table, dir)?;
+    toml::Value::Table(table)
+        .try_into()
+        .map_err(|source| deserialize_error(config_path.map(Path::to_owned), source))
+}
+
+/// The files an `include` entry names: the path itself or, with `*` or `?` in the
+/// file name, every matching file in its directory in name order.
+fn include_paths(pattern: &Path) -> Result<Vec<PathBuf>, ConfigError> {
+    let name = pattern
+        .file_name()
+        .and_then(|name| name.to_str())
+        .unwrap_or_default();
+    if !name.contains(['*', '?']) {
+        return Ok(vec![pattern.to_owned()]);
+    }
+    let dir = pattern.parent().unwrap_or_else(|| Path::new("."));
+    let entries = match std::fs::read_dir(dir) {
+        Ok(entries) => entries,
+        // An empty `conf.d/` may as well be missing.
+        Err(e) if e.kind() == io::ErrorKind::NotFound => return Ok(Vec::new()),
+        Err(source) => {
+            return Err(ConfigError::Read {
+                path: dir.to_owned(),
+                source,
+            });
+        }
+    };
+    let mut paths: Vec<PathBuf> = entries
+        .flatten()
+        .map(|entry| entry.path())
+        .filter(|path| {
+            path.is_file()
+                && path
+                    .file_name()
+                    .and_then(|file| file.to_str())
+                    // Editors' and Kubernetes' dotfiles stay out unless asked for.
+                    .is_some_and(|file| {
+                        (name.starts_with('.') || !file.starts_with('.'))
+                            && wildcard_match(name.as_bytes(), file.as_bytes())
+                    })
+        })
+        .collect();
+    paths.sort();
+    Ok(paths)
+}
+
+fn wildcard_match(pattern: &[u8], name: &[u8]) -> bool {
+    match (pattern.split_first(), name.split_first()) {
+        (None, _) => name.is_empty(),
+        (Some((b'*', rest)), _) => {
+            wildcard_match(rest, name) || (!name.is_empty() && wildcard_match(pattern, &name[1..]))
+        }
+        (Some((b'?', rest)), Some((_, name_rest))) => wildcard_match(rest, name_rest),
+        (Some((p, rest)), Some((n, name_rest))) => p == n && wildcard_match(rest, name_rest),
+        (Some(_), None) => false,
+    }
+}
+
+/// Overlay `from` on `into`: tables merge key by key, anything else is replaced.
+fn merge_tables(into: &mut toml::Table, from: toml::Table) {
+    for (key, value) in from {
+        match (into.get_mut(&key), value) {
+            (Some(toml::Value::Table(into)), toml::Value::Table(from)) => merge_tables(into, from),
+            (_, value) => {
+                into.insert(key, value);
+            }
+        }
+    }
+}
+
+/// Replace every `<key>_file = "path"`, at any depth, with `<key>` set to the file's
+/// contents, so secrets can come from docker or Kubernetes secret mounts.
+fn read_secret_files(table: &mut toml::Table, dir: &Path) -> Result<(), ConfigError> {
+    let secret_keys: Vec<String> = table
+        .keys()
+        .filter(|key| key.ends_with("_file") && !PATH_SETTINGS.contains(&key.as_str()))
+        .cloned()
+        .collect();
+    for key in secret_keys {
+        let name = key.trim_end_matches("_file");
+        if table.contains_key(name) {
+            return Err(invalid(
+                "*_file",
+                format!("`{name}` and `{key}` are both set"),
+            ));
+        }
+        let Some(toml::Value::String(path)) = table.remove(&key) else {
+            return Err(invalid("*_file", format!("`{key}` must be a path")));
+        };
+        let path = dir.join(path);
+        let secret = std::fs::read_to_string(&path).map_err(|source| ConfigError::Read {
+            path: path.clone(),
+            source,
+        })?;
+        // Secret files usually end in a newline nobody meant as part of the value.
+        table.insert(
+            name.to_owned(),
+            toml::Value::String(secret.trim_end().to_owned()),
+        );
+    }
+    for (_, value) in table.iter_mut() {
+        match value {
+            toml::Value::Table(table) => read_secret_files(table, dir)?,
+            toml::Value::Array(items) => {
+                for item in items {
+                    if let toml::Value::Table(table) = item {
+                        read_secret_files(table, dir)?;
+                    }
+                }
+            }
+            _ => {}
+        }
+    }
+    Ok(())
+}
+
+pub fn load_app_config() -> Result<AppConfig, ConfigError> {
+    let den_paths = resolve_den_paths()?;
+    ensure_config_file(&den_paths.config_path)?;
+    let file = read_file_config(&den_paths.config_path)?;
+    let mut config = resolve_app_config(file, den_paths.default_database_path)?;
+    apply_config_dir(&mut config, &den_paths.config_path);
+    if let Some(dir) = data_dir() {
+        apply_data_dir(&mut config, &dir);
+    }
+    Ok(config)
+}
+
+/// Settings that live next to the config file: the default `templates/` directory,
+/// relative `[error_pages]` paths, a relative `break_glass_token_file` and the
+/// `[lua_policy]` script.
+pub fn apply_config_dir(config: &mut AppConfig, config_path: &Path) {
+    let Some(dir) = config_path.parent() else {
         return;
+    };
+    if config.notification_templates.is_none() {
+        config.notification_templates = Some(dir.join("templates"));
+    }
+    let pages = &mut config.error_pages;
+    for path in [
+        &mut pages.not_found,
+        &mut pages.maintenance,
+        &mut config.break_glass_token_file,
+    ]
+    .into_iter()
+    .flatten()
+    {
+        *path = dir.join(&*path);
+    }
+    if let Some(lua) = &mut config.lua_policy {
+        lua.script = dir.join(&lua.script);
     }
-    std::fs::write(config_path, default_config_contents()).unwrap_or_else(|e| {
-        panic!(
-            "failed to write default config file at {}: {e}",
-            config_path.display()
-        )
-    });
 }
 
-fn read_file_config(config_path: &Path) -> FileConfig {
-    let contents = std::fs::read_to_string(config_path).unwrap_or_else(|e| {
-        panic!(
-            "failed to read config file at {}: {e}",
-            config_path.display()
-        )
-    });
-    toml::from_str(&contents).unwrap_or_else(|e| {
-        panic!(
-            "invalid TOML in config file at {}: {e}",
-            config_path.display()
-        )
-    })
+/// In `DEN_DATA_DIR` mode every relative path in the config, from `database_path` to
+/// key and CA files, is inside the data directory rather than the working directory.
+pub fn apply_data_dir(config: &mut AppConfig, dir: &Path) {
+    let join = |path: &mut PathBuf| {
+        if path.is_relative() {
+            *path = dir.join(&*path);
+        }
+    };
+    let join_str = |path: &mut String| {
+        if Path::new(path.as_str()).is_relative() {
+            *path = dir.join(path.as_str()).to_string_lossy().into_owned();
+        }
+    };
+    join(&mut config.database_path);
+    for path in [
+        &mut config.ready_file,
+        &mut config.geoip_database,
+        &mut config.asn_database,
+    ]
+    .into_iter()
+    .flatten()
+    {
+        join(path);
+    }
+    if let Some(BreachedPasswordsConfig::Bloom { path }) = &mut config.breached_passwords {
+        join(path);
+    }
+    if let Some(web_integrity) = &mut config.web_integrity {
+        join_str(&mut web_integrity.public_key);
+    }
+    if let Some(tls) = &mut config.tls {
+        join_str(&mut tls.cert_path);
+        join_str(&mut tls.key_path);
+    }
+    if let Some(client_cert) = &mut config.client_cert {
+        join_str(&mut client_cert.ca_path);
+    }
+    if let Some(roots) = config
+        .attestation
+        .as_mut()
+        .and_then(|attestation| attestation.trusted_roots.as_mut())
+    {
+        join_str(roots);
+    }
 }
 
-pub fn load_app_config() -> AppConfig {
-    let den_paths = resolve_den_paths();
-    ensure_config_file(&den_paths.config_path);
-    let file = read_file_config(&den_paths.config_path);
+/// Resolve config from TOML text instead of the XDG config file (integration tests).
+/// `include` and `<key>_file` paths are relative to the working directory.
+pub fn parse_app_config(
+    contents: &str,
+    default_database_path: PathBuf,
+) -> Result<AppConfig, ConfigError> {
+    resolve_app_config(expand_config(contents, None)?, default_database_path)
+}
+
+/// `parse_app_config` for the text of `config_path`, which relative `include` and
+/// `<key>_file` paths are resolved against.
+pub fn parse_config_file(
+    contents: &str,
+    config_path: &Path,
+    default_database_path: PathBuf,
+) -> Result<AppConfig, ConfigError> {
+    resolve_app_config(
+        expand_config(contents, Some(config_path))?,
+        default_database_path,
+    )
+}
 
+fn resolve_app_config(
+    file: FileConfig,
+    default_database_path: PathBuf,
+) -> Result<AppConfig, ConfigError> {
     let allowed_hosts = file
         .allowed_hosts
         .unwrap_or_default()
@@ -111,16 +1770,505 @@ pub fn load_app_config() -> AppConfig {
         .filter(|value| !value.is_empty())
         .collect();
 
-    AppConfig {
-        port: file.port.unwrap_or(DEFAULT_PORT),
+    let rp_origin =
+        non_empty_string(file.rp_origin).unwrap_or_else(|| DEFAULT_RP_ORIGIN.to_owned());
+    match url::Url::parse(&rp_origin) {
+        Ok(url) if matches!(url.scheme(), "http" | "https") && url.host_str().is_some() => {}
+        _ => {
+            return Err(invalid(
+                "rp_origin",
+                format!("{rp_origin:?} is not an http(s) origin"),
+            ));
+        }
+    }
+    let cookie_name =
+        non_empty_string(file.cookie_name).unwrap_or_else(|| DEFAULT_COOKIE_NAME.to_owned());
+    if !is_valid_cookie_name(&cookie_name) {
+        return Err(invalid(
+            "cookie_name",
+            format!("{cookie_name:?} is not a cookie token"),
+        ));
+    }
+    let cookie_same_site = file.cookie_same_site.unwrap_or_default();
+    if cookie_same_site == CookieSameSite::None && !rp_origin.starts_with("https://") {
+        return Err(invalid(
+            "cookie_same_site",
+            "\"none\" requires an https rp_origin",
+        ));
+    }
+    let cookie_domain = non_empty_string(file.cookie_domain)
+        .map(|d| d.trim_start_matches('.').to_ascii_lowercase());
+    let cookie_partitioned = file.cookie_partitioned.unwrap_or(false);
+    if cookie_partitioned {
+        // Only third-party (cross-site) requests carry partitioned cookies.
+        if cookie_same_site != CookieSameSite::None {
+            return Err(invalid(
+                "cookie_partitioned",
+                "requires cookie_same_site = \"none\"",
+            ));
+        }
+        // Each top-level site gets its own partition, so a domain-wide cookie
+        // couldn't be shared anyway; every host goes through the redirect-token hop.
+        if cookie_domain.is_some() {
+            return Err(invalid(
+                "cookie_partitioned",
+                "uses per-host cookies; remove cookie_domain",
+            ));
+        }
+    }
+
+    let forward_auth = file.forward_auth.unwrap_or_default();
+    for name in [
+        &forward_auth.user_header,
+        &forward_auth.name_header,
+        &forward_auth.groups_header,
+        &forward_auth.session_header,
+    ] {
+        if axum::http::HeaderName::try_from(name.as_str()).is_err() {
+            return Err(invalid(
+                "forward_auth",
+                format!("{name:?} is not a header name"),
+            ));
+        }
+    }
+
+    let geoip_database = non_empty_string(file.geoip_database).map(PathBuf::from);
+    let deny_login_countries: Vec<String> = file
+        .deny_login_countries
+        .unwrap_or_default()
+        .iter()
+        .map(|c| c.trim().to_ascii_uppercase())
+        .collect();
+    if !deny_login_countries.is_empty() && geoip_database.is_none() {
+        return Err(invalid("deny_login_countries", "requires geoip_database"));
+    }
+
+    if let Some(oidc) = &file.upstream_oidc {
+        if url::Url::parse(&oidc.issuer).is_err() {
+            return Err(invalid(
+                "upstream_oidc.issuer",
+                format!("{:?} is not a URL", oidc.issuer),
+            ));
+        }
+        if oidc.allowed_users.is_empty() {
+            return Err(invalid(
+                "upstream_oidc.allowed_users",
+                "must list at least one user",
+            ));
+        }
+    }
+
+    if let Some(ldap) = &file.ldap {
+        match url::Url::parse(&ldap.url) {
+            Ok(url) if matches!(url.scheme(), "ldap" | "ldaps") && url.host_str().is_some() => {}
+            _ => {
+                return Err(invalid(
+                    "ldap.url",
+                    format!("{:?} is not an ldap:// or ldaps:// URL", ldap.url),
+                ));
+            }
+        }
+        if !ldap.bind_dn.contains("{user}") {
+            return Err(invalid("ldap.bind_dn", "must contain {user}"));
+        }
+        if ldap.allowed_users.is_empty() {
+            return Err(invalid("ldap.allowed_users", "must list at least one user"));
+        }
+    }
+
+    if let Some(BreachedPasswordsConfig::Hibp { url }) = &file.breached_passwords
+        && url::Url::parse(url).is_err()
+    {
+        return Err(invalid(
+            "breached_passwords.url",
+            format!("{url:?} is not a URL"),
+        ));
+    }
+
+    if let Some(security_txt) = &file.security_txt
+        && security_txt.contact.iter().all(|c| c.trim().is_empty())
+    {
+        return Err(invalid("security_txt.contact", "needs at least one URI"));
+    }
+
+    if let Some(recovery) = &file.recovery
+        && recovery.delay_hours == 0
+    {
+        return Err(invalid("recovery.delay_hours", "must be at least 1"));
+    }
+
+    if let Some(web_integrity) = &file.web_integrity
+        && web_integrity.public_key.trim().is_empty()
+    {
+        return Err(invalid("web_integrity.public_key", "must not be empty"));
+    }
+
+    if let Some(route) = file
+        .spa_routes
+        .iter()
+        .flatten()
+        .find(|route| !route.starts_with('/'))
+    {
+        return Err(invalid(
+            "spa_routes",
+            format!("{route:?} must start with /"),
+        ));
+    }
+
+    let web_source = match non_empty_string(file.web_source) {
+        Some(source) => {
+            Some(web_source(&source, aws_region()).map_err(|e| invalid("web_source", e))?)
+        }
+        None => None,
+    };
+
+    if file.passkeys.and_then(|p| p.max_age_days) == Some(0) {
+        return Err(invalid("passkeys.max_age_days", "must be at least 1"));
+    }
+
+    let bind_address: IpAddr = match non_empty_string(file.bind_address.clone()) {
+        Some(address) => address
+            .trim_start_matches('[')
+            .trim_end_matches(']')
+            .parse()
+            .map_err(|_| invalid("bind_address", format!("{address:?} is not an IP address")))?,
+        None => Ipv6Addr::UNSPECIFIED.into(),
+    };
+    let listen = match file.listen.filter(|listen| !listen.is_empty()) {
+        Some(_) if file.port.is_some() || file.bind_address.is_some() => {
+            return Err(invalid(
+                "listen",
+                "replaces port and bind_address; remove them or list that address in listen",
+            ));
+        }
+        Some(listen) => listen
+            .iter()
+            .map(|value| ListenAddr::parse(value.trim()))
+            .collect::<Result<_, _>>()
+            .map_err(|message| invalid("listen", message))?,
+        // Without `listen`, `[tls]` alone serves TLS on `bind_address:port`.
+        None => {
+            let addr = SocketAddr::new(bind_address, file.port.unwrap_or(DEFAULT_PORT));
+            vec![match file.tls {
+                Some(_) => ListenAddr::Tls(addr),
+                None => ListenAddr::Tcp(addr),
+            }]
+        }
+    };
+    let tls_listeners = listen.iter().any(|addr| matches!(addr, ListenAddr::Tls(_)));
+    match &file.tls {
+        None if tls_listeners => {
+            return Err(invalid("listen", "tls: entries need a [tls] certificate"));
+        }
+        Some(_) if !tls_listeners => {
+            return Err(invalid(
+                "tls",
+                "no listen entry is tls:; add one or remove [tls]",
+            ));
+        }
+        Some(tls) if tls.cert_path.trim().is_empty() || tls.key_path.trim().is_empty() => {
+            return Err(invalid("tls", "cert_path and key_path must not be empty"));
+        }
+        _ => {}
+    }
+
+    match &file.audit_export {
+        Some(AuditExportConfig::File {
+            path, max_bytes, ..
+        }) => {
+            if path.trim().is_empty() {
+                return Err(invalid("audit_export.path", "must not be empty"));
+            }
+            if *max_bytes < 1024 {
+                return Err(invalid("audit_export.max_bytes", "must be at least 1024"));
+            }
+        }
+        Some(AuditExportConfig::Syslog { address, .. }) => match url::Url::parse(address) {
+            Ok(url) if matches!(url.scheme(), "udp" | "tcp") && url.host_str().is_some() => {}
+            _ => {
+                return Err(invalid(
+                    "audit_export.address",
+                    format!("{address:?} is not a udp:// or tcp:// address"),
+                ));
+            }
+        },
+        None => {}
+    }
+
+    if let Some(slo) = &file.slo {
+        if !(slo.objective > 0.0 && slo.objective < 1.0) {
+            return Err(invalid(
+                "slo.objective",
+                "must be between 0 and 1, e.g. 0.999",
+            ));
+        }
+        if !(slo.burn_rate.is_finite() && slo.burn_rate > 0.0) {
+            return Err(invalid("slo.burn_rate", "must be positive"));
+        }
+    }
+
+    if let Some(throttle) = file.login_throttle {
+        if throttle.max_failures == 0 {
+            return Err(invalid("login_throttle.max_failures", "must be at least 1"));
+        }
+        if throttle.window_minutes == 0 {
+            return Err(invalid(
+                "login_throttle.window_minutes",
+                "must be at least 1",
+            ));
+        }
+    }
+    let login_throttle = file.login_throttle;
+
+    if let Some(puzzle) = &file.login_puzzle {
+        if !(1..=MAX_PUZZLE_DIFFICULTY).contains(&puzzle.difficulty) {
+            return Err(invalid(
+                "login_puzzle.difficulty",
+                format!("must be between 1 and {MAX_PUZZLE_DIFFICULTY}"),
+            ));
+        }
+        for net in &puzzle.exempt {
+            net.parse::<IpNet>()
+                .map_err(|e| invalid("login_puzzle.exempt", e))?;
+        }
+    }
+    let login_puzzle = file.login_puzzle;
+
+    if let Some(honeypot) = &file.honeypot {
+        for path in &honeypot.paths {
+            if !path.starts_with('/') || path == "/" || path == "/api" || path.starts_with("/api/")
+            {
+                return Err(invalid(
+                    "honeypot.paths",
+                    format!("{path:?} must be a path outside /api, starting with /"),
+                ));
+            }
+        }
+        if honeypot.delay_seconds > MAX_HONEYPOT_DELAY_SECONDS {
+            return Err(invalid(
+                "honeypot.delay_seconds",
+                format!("must be at most {MAX_HONEYPOT_DELAY_SECONDS}"),
+            ));
+        }
+        if honeypot.block_minutes == 0 {
+            return Err(invalid("honeypot.block_minutes", "must be at least 1"));
+        }
+        for net in &honeypot.exempt {
+            net.parse::<IpNet>()
+                .map_err(|e| invalid("honeypot.exempt", e))?;
+        }
+    }
+    let honeypot = file.honeypot;
+
+    let hooks = file.hooks.unwrap_or_default();
+    for hook in &hooks {
+        match (&hook.command, &hook.url) {
+            (Some(command), None) => {
+                if command.first().is_none_or(|program| program.is_empty()) {
+                    return Err(invalid("hooks.command", "must name a program"));
+                }
+            }
+            (None, Some(url)) => {
+                if !url::Url::parse(url).is_ok_and(|u| matches!(u.scheme(), "http" | "https")) {
+                    return Err(invalid("hooks.url", "must be an http(s) URL"));
+                }
+            }
+            _ => {
+                return Err(invalid(
+                    "hooks",
+                    "each hook needs exactly one of command or url",
+                ));
+            }
+        }
+        if !(1..=MAX_HOOK_TIMEOUT_MS).contains(&hook.timeout_ms) {
+            return Err(invalid(
+                "hooks.timeout_ms",
+                format!("must be between 1 and {MAX_HOOK_TIMEOUT_MS}"),
+            ));
+        }
+    }
+
+    if let Some(plugin) = &file.policy_plugin {
+        if plugin.path.trim().is_empty() {
+            return Err(invalid("policy_plugin.path", "must name a .wasm file"));
+        }
+        if plugin.fuel == 0 {
+            return Err(invalid("policy_plugin.fuel", "must be at least 1"));
+        }
+        if !(1..=MAX_PLUGIN_MEMORY_MB).contains(&plugin.max_memory_mb) {
+            return Err(invalid(
+                "policy_plugin.max_memory_mb",
+                format!("must be between 1 and {MAX_PLUGIN_MEMORY_MB}"),
+            ));
+        }
+    }
+    let policy_plugin = file.policy_plugin;
+
+    if let Some(lua) = &file.lua_policy {
+        if lua.script.as_os_str().is_empty() {
+            return Err(invalid("lua_policy.script", "must name a .lua file"));
+        }
+        if !(1..=MAX_HOOK_TIMEOUT_MS).contains(&lua.timeout_ms) {
+            return Err(invalid(
+                "lua_policy.timeout_ms",
+                format!("must be between 1 and {MAX_HOOK_TIMEOUT_MS}"),
+            ));
+        }
+    }
+    let lua_policy = file.lua_policy;
+
+    let apps = file.apps.unwrap_or_default();
+    for app in &apps {
+        if let Some(hours) = &app.access_hours
+            && crate::policy::parse_hours(hours).is_none()
+        {
+            return Err(invalid(
+                "apps.access_hours",
+                format!("{hours:?} must look like \"06:00-01:00\""),
+            ));
+        }
+    }
+
+    let policies = file.policies.unwrap_or_default();
+    for rule in &policies {
+        if rule.name.trim().is_empty() {
+            return Err(invalid("policies.name", "must not be empty"));
+        }
+        for net in &rule.networks {
+            net.parse::<IpNet>()
+                .map_err(|e| invalid("policies.networks", e))?;
+        }
+        if let Some(hours) = &rule.hours
+            && crate::policy::parse_hours(hours).is_none()
+        {
+            return Err(invalid(
+                "policies.hours",
+                format!("{hours:?} must look like \"08:00-18:00\""),
+            ));
+        }
+        for app in &rule.apps {
+            if crate::origin::normalize_origin(app).is_none() {
+                return Err(invalid(
+                    "policies.apps",
+                    format!("{app:?} is not an http(s) origin"),
+                ));
+            }
+        }
+    }
+
+    let log_presets = file.log_presets.unwrap_or_default();
+    for (name, directives) in &log_presets {
+        if name.is_empty() || name.contains(|c: char| c == '=' || c == ',' || c.is_whitespace()) {
+            return Err(invalid(
+                "log_presets",
+                format!("{name:?} could be mistaken for a filter directive"),
+            ));
+        }
+        if let Err(e) = tracing_subscriber::EnvFilter::try_new(directives) {
+            return Err(invalid("log_presets", format!("{name}: {e}")));
+        }
+    }
+
+    // The same loaders `den::app` runs, so `den config check` catches what startup would.
+    crate::apps::AppPolicies::load(&apps)?;
+    let trusted_proxies = file
+        .trusted_proxies
+        .unwrap_or_else(|| vec!["127.0.0.0/8".into(), "::1".into()]);
+    crate::access::parse_nets(&trusted_proxies, "trusted_proxies")?;
+    let access_control = file.access_control.unwrap_or_default();
+    crate::access::AccessControl::load(&access_control)?;
+    let branding = file.branding.unwrap_or_default();
+    crate::branding::Branding::load(branding.clone())?;
+    let storage = file.storage.unwrap_or_default();
+    if let StorageConfig::Redis { redis_url } = &storage {
+        crate::redis::Redis::new(redis_url).map_err(|e| invalid("storage.redis_url", e))?;
+    }
+    let alert_webhook_url = non_empty_string(file.alert_webhook_url);
+    if let Some(url) = &alert_webhook_url
+        && !is_http_url(url)
+    {
+        return Err(invalid(
+            "alert_webhook_url",
+            format!("{url:?} is not an http(s) URL"),
+        ));
+    }
+    if let Some(push) = &file.push
+        && !is_http_url(push.url())
+    {
+        return Err(invalid(
+            "push.url",
+            format!("{:?} is not an http(s) URL", push.url()),
+        ));
+    }
+
+    let database = file.database.unwrap_or_default();
+    if database.max_connections == 0 {
+        return Err(invalid("database.max_connections", "must be at least 1"));
+    }
+
+    Ok(AppConfig {
+        listen,
+        ready_file: non_empty_string(file.ready_file).map(PathBuf::from),
+        tls: file.tls,
         rust_log: non_empty_string(file.rust_log).unwrap_or_else(|| DEFAULT_RUST_LOG.to_owned()),
         rp_id: non_empty_string(file.rp_id).unwrap_or_else(|| DEFAULT_RP_ID.to_owned()),
-        rp_origin: non_empty_string(file.rp_origin).unwrap_or_else(|| DEFAULT_RP_ORIGIN.to_owned()),
+        rp_origin,
         allowed_hosts,
         database_path: non_empty_string(file.database_path)
             .map(PathBuf::from)
-            .unwrap_or(den_paths.default_database_path),
-    }
+            .unwrap_or(default_database_path),
+        cookie_domain,
+        cookie_name,
+        cookie_same_site,
+        cookie_partitioned,
+        storage,
+        instance_id: non_empty_string(file.instance_id),
+        alert_webhook_url,
+        smtp: file.smtp,
+        push: file.push,
+        notification_templates: non_empty_string(file.notification_templates).map(PathBuf::from),
+        apps,
+        forward_auth,
+        client_cert: file.client_cert,
+        attestation: file.attestation,
+        related_origins: file.related_origins.unwrap_or(false),
+        security_txt: file.security_txt,
+        recovery: file.recovery,
+        upstream_oidc: file.upstream_oidc,
+        ldap: file.ldap,
+        breached_passwords: file.breached_passwords,
+        trusted_proxies,
+        access_control,
+        geoip_database,
+        asn_database: non_empty_string(file.asn_database).map(PathBuf::from),
+        deny_login_countries,
+        login_anomaly: file.login_anomaly.unwrap_or_default(),
+        login_throttle,
+        login_puzzle,
+        honeypot,
+        hooks,
+        policy_plugin,
+        lua_policy,
+        policies,
+        passkeys: file.passkeys.unwrap_or_default(),
+        audit_export: file.audit_export,
+        slo: file.slo,
+        database,
+        maintenance: file.maintenance.unwrap_or(false),
+        compression: file.compression.unwrap_or_default(),
+        branding,
+        web_integrity: file.web_integrity,
+        spa_routes: file.spa_routes,
+        web_source,
+        error_pages: file.error_pages.unwrap_or_default(),
+        bootstrap_token: non_empty_string(file.bootstrap_token),
+        default_language: non_empty_string(file.default_language),
+        dev_login_password: non_empty_string(file.dev_login_password),
+        break_glass_token_file: file
+            .break_glass_token_file
+            .filter(|path| !path.as_os_str().is_empty()),
+        log_presets,
+    })
 }
 
 #[cfg(test)]
@@ -132,4 +2280,543 @@ mod tests {
         let config = default_config_contents();
         assert!(!config.contains("database_path"));
     }
+
+    #[test]
+    fn explicit_config_path_puts_the_database_beside_it() {
+        let paths = explicit_den_paths(Path::new("/etc/den/den.toml"));
+        assert_eq!(paths.config_path, PathBuf::from("/etc/den/den.toml"));
+        assert_eq!(
+            paths.default_database_path,
+            PathBuf::from("/etc/den/den.db")
+        );
+    }
+
+    #[test]
+    fn data_dir_holds_relative_paths() {
+        let mut config = parse_app_config(
+            "database_path = \"db/den.db\"\n\
+             ready_file = \"/run/den/ready\"\n\
+             [web_integrity]\n\
+             public_key = \"keys/web.pem\"\n",
+            PathBuf::from("/data/den.db"),
+        )
+        .unwrap();
+        apply_data_dir(&mut config, Path::new("/data"));
+        assert_eq!(config.database_path, PathBuf::from("/data/db/den.db"));
+        assert_eq!(config.ready_file, Some(PathBuf::from("/run/den/ready")));
+        assert_eq!(
+            config.web_integrity.unwrap().public_key,
+            "/data/keys/web.pem"
+        );
+    }
+
+    #[test]
+    fn includes_and_secret_files_are_merged_in() {
+        let dir = std::env::temp_dir().join(format!("den-config-include-{}", std::process::id()));
+        let _ = std::fs::remove_dir_all(&dir);
+        std::fs::create_dir_all(dir.join("conf.d")).unwrap();
+        std::fs::write(
+            dir.join("conf.d/10-smtp.toml"),
+            "[smtp]\nhost = \"mail\"\nfrom = \"den@example.com\"\nto = \"me@example.com\"\n\
+             password_file = \"smtp-password\"\n",
+        )
+        .unwrap();
+        std::fs::write(dir.join("conf.d/20-rp.toml"), "rp_id = \"den.example\"\n").unwrap();
+        std::fs::write(dir.join("conf.d/.30-rp.toml"), "rp_id = \"hidden\"\n").unwrap();
+        std::fs::write(dir.join("smtp-password"), "hunter2\n").unwrap();
+        std::fs::write(dir.join("token"), "boot-token\n").unwrap();
+        let config_path = dir.join("config.toml");
+
+        let config = parse_config_file(
+            "rp_id = \"localhost\"\n\
+             bootstrap_token_file = \"token\"\n\
+             include = [\"conf.d/*.toml\"]\n",
+            &config_path,
+            PathBuf::from("den.db"),
+        )
+        .unwrap();
+        assert_eq!(config.rp_id, "den.example");
+        assert_eq!(config.bootstrap_token.as_deref(), Some("boot-token"));
+        assert_eq!(config.smtp.unwrap().password.as_deref(), Some("hunter2"));
+
+        let both = parse_config_file(
+            "bootstrap_token = \"a\"\nbootstrap_token_file = \"token\"\n",
+            &config_path,
+            PathBuf::from("den.db"),
+        );
+        assert!(matches!(
+            both,
+            Err(ConfigError::Invalid { key: "*_file", .. })
+        ));
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn unknown_keys_are_refused_with_the_nearest_match() {
+        let unknown = |contents: &str| match parse_app_config(contents, PathBuf::from("den.db")) {
+            Err(ConfigError::UnknownKey {
+                key, suggestion, ..
+            }) => (key, suggestion),
+            other => panic!("expected an unknown key, got {other:?}"),
+        };
+        assert_eq!(unknown("prot = 3000"), ("prot".into(), Some("port".into())));
+        assert_eq!(
+            unknown("[smtp]\nhots = \"mail\"\nfrom = \"a@b\"\nto = \"c@d\""),
+            ("hots".into(), Some("host".into()))
+        );
+        assert_eq!(unknown("frobnicate = true"), ("frobnicate".into(), None));
+        assert_eq!(
+            ConfigError::UnknownKey {
+                path: None,
+                key: "prot".into(),
+                suggestion: Some("port".into()),
+            }
+            .to_string(),
+            "unknown key `prot` in config; did you mean `port`?"
+        );
+    }
+
+    #[test]
+    fn wildcards_match_whole_names() {
+        assert!(wildcard_match(b"*.toml", b"10-smtp.toml"));
+        assert!(wildcard_match(b"??-*.toml", b"10-smtp.toml"));
+        assert!(!wildcard_match(b"*.toml", b"smtp.toml.bak"));
+        assert!(!wildcard_match(b"?.toml", b".toml"));
+    }
+
+    #[test]
+    fn diagnostics_leave_out_secrets() {
+        let config = parse_app_config(
+            "bootstrap_token = \"boot-secret\"\n\
+             alert_webhook_url = \"https://hooks.example/hook-secret\"\n\
+             [storage]\n\
+             backend = \"redis\"\n\
+             redis_url = \"redis://:redis-secret@cache\"\n\
+             [smtp]\n\
+             host = \"mail.example\"\n\
+             username = \"den\"\n\
+             password = \"smtp-secret\"\n\
+             from = \"den@example.com\"\n\
+             to = \"me@example.com\"\n",
+            PathBuf::from("den.db"),
+        )
+        .unwrap();
+        let diagnostics = config.diagnostics();
+        assert!(!diagnostics.to_string().contains("secret"));
+        assert_eq!(diagnostics["storage"], "redis");
+        assert_eq!(diagnostics["smtp"]["authenticated"], true);
+        assert_eq!(diagnostics["bootstrap_token"], true);
+    }
+
+    #[test]
+    fn cookie_names_must_be_tokens() {
+        assert!(is_valid_cookie_name("den_session"));
+        assert!(is_valid_cookie_name("__Host-den"));
+        assert!(!is_valid_cookie_name("den session"));
+        assert!(!is_valid_cookie_name("den;x"));
+        assert!(!is_valid_cookie_name(""));
+    }
+
+    #[test]
+    fn partitioned_cookies_need_cross_site_and_per_host() {
+        let parse = |extra: &str| {
+            let contents = format!(
+                "rp_origin = \"https://auth.example.com\"\ncookie_partitioned = true\n{extra}"
+            );
+            parse_app_config(&contents, PathBuf::from("den.db"))
+        };
+        let config = parse("cookie_same_site = \"none\"").unwrap();
+        assert!(config.cookie_partitioned);
+        assert!(parse("cookie_same_site = \"lax\"").is_err());
+        assert!(parse("cookie_same_site = \"none\"\ncookie_domain = \"example.com\"").is_err());
+    }
+
+    #[test]
+    fn errors_name_the_key_and_exit_as_config_errors() {
+        let error =
+            parse_app_config("cookie_name = \"den session\"", PathBuf::from("den.db")).unwrap_err();
+        assert!(matches!(
+            error,
+            ConfigError::Invalid {
+                key: "cookie_name",
+                ..
+            }
+        ));
+        assert_eq!(error.exit_code(), EX_CONFIG);
+        let error = parse_app_config("port = \"80\"", PathBuf::from("den.db")).unwrap_err();
+        assert!(matches!(error, ConfigError::Parse { path: None, .. }));
+        assert_eq!(error.exit_code(), EX_CONFIG);
+        let error = parse_app_config("rp_origin = \"auth.example.com\"", PathBuf::from("den.db"))
+            .unwrap_err();
+        assert!(
+            error
+                .to_string()
+                .starts_with("invalid `rp_origin` in config")
+        );
+    }
+
+    #[test]
+    fn settings_loaded_at_startup_are_checked_up_front() {
+        let key = |contents: &str| match parse_app_config(contents, PathBuf::from("den.db")) {
+            Err(ConfigError::Invalid { key, .. }) => key,
+            other => panic!("expected an invalid key, got {other:?}"),
+        };
+        assert_eq!(key("trusted_proxies = [\"not-a-cidr\"]"), "trusted_proxies");
+        assert_eq!(
+            key("[access_control.register]\ndeny = [\"10.0.0.0/40\"]"),
+            "access_control.register.deny"
+        );
+        assert_eq!(
+            key("alert_webhook_url = \"not a url\""),
+            "alert_webhook_url"
+        );
+        assert_eq!(
+            key("[push]\nprovider = \"ntfy\"\nurl = \"ntfy.sh/den\""),
+            "push.url"
+        );
+        assert_eq!(
+            key("[[apps]]\nname = \"wiki\"\norigin = \"wiki\""),
+            "apps.origin"
+        );
+        assert_eq!(
+            key(
+                "[[apps]]\nname = \"wiki\"\norigin = \"https://wiki.example\"\n\
+                 logout_uri = \"https://wiki.example/logout\""
+            ),
+            "apps.logout_secret"
+        );
+        assert_eq!(
+            key("[branding]\naccent_color = \"blue\""),
+            "branding.accent_color"
+        );
+        assert_eq!(
+            key("[storage]\nbackend = \"redis\"\nredis_url = \"http://redis\""),
+            "storage.redis_url"
+        );
+    }
+
+    #[test]
+    fn login_puzzle_checks_difficulty_and_networks() {
+        let parse = |contents| parse_app_config(contents, PathBuf::from("den.db"));
+        let config = parse("[login_puzzle]\nexempt = [\"10.0.0.0/8\"]").unwrap();
+        assert_eq!(config.login_puzzle.unwrap().difficulty, 16);
+        assert!(parse("").unwrap().login_puzzle.is_none());
+        assert!(matches!(
+            parse("[login_puzzle]\ndifficulty = 30").unwrap_err(),
+            ConfigError::Invalid {
+                key: "login_puzzle.difficulty",
+                ..
+            }
+        ));
+        assert!(matches!(
+            parse("[login_puzzle]\nexempt = [\"10.0.0.0/33\"]").unwrap_err(),
+            ConfigError::Invalid {
+                key: "login_puzzle.exempt",
+                ..
+            }
+        ));
+    }
+
+    #[test]
+    fn hooks_need_exactly_one_target() {
+        let parse = |contents: &str| {
+            parse_app_config(
+                &format!("[[hooks]]\nevent = \"session\"\n{contents}"),
+                PathBuf::from("den.db"),
+            )
+        };
+        let config = parse("url = \"https://policy.example/check?key=hook-secret\"").unwrap();
+        assert_eq!(config.hooks[0].timeout_ms, 2000);
+        assert_eq!(config.hooks[0].on_error, HookFailure::Deny);
+        assert!(!config.diagnostics().to_string().contains("secret"));
+        assert!(parse("command = [\"/usr/local/bin/policy\"]\non_error = \"allow\"").is_ok());
+        let key = |contents| match parse(contents).unwrap_err() {
+            ConfigError::Invalid { key, .. } => key,
+            error => panic!("{error}"),
+        };
+        assert_eq!(key(""), "hooks");
+        assert_eq!(
+            key("command = [\"true\"]\nurl = \"https://policy.example\""),
+            "hooks"
+        );
+        assert_eq!(key("command = []"), "hooks.command");
+        assert_eq!(key("url = \"ftp://policy.example\""), "hooks.url");
+        assert_eq!(
+            key("command = [\"true\"]\ntimeout_ms = 60000"),
+            "hooks.timeout_ms"
+        );
+    }
+
+    #[test]
+    fn lua_policy_script_sits_next_to_the_config() {
+        let mut config = parse_app_config(
+            "[lua_policy]\nscript = \"policy.lua\"",
+            PathBuf::from("den.db"),
+        )
+        .unwrap();
+        apply_config_dir(&mut config, Path::new("/etc/den/config.toml"));
+        let lua = config.lua_policy.unwrap();
+        assert_eq!(lua.script, Path::new("/etc/den/policy.lua"));
+        assert_eq!(lua.timeout_ms, 100);
+        assert!(
+            parse_app_config(
+                "[lua_policy]\nscript = \"policy.lua\"\ntimeout_ms = 0",
+                PathBuf::from("den.db")
+            )
+            .is_err()
+        );
+    }
+
+    #[test]
+    fn app_access_hours_are_checked_up_front() {
+        let parse = |hours: &str| {
+            parse_app_config(
+                &format!(
+                    "[[apps]]\nname = \"admin\"\norigin = \"https://admin.example\"\n\
+                     access_hours = {hours:?}\naccess_days = [\"mon\"]"
+                ),
+                PathBuf::from("den.db"),
+            )
+        };
+        let config = parse("06:00-01:00").unwrap();
+        assert_eq!(config.apps[0].access_days, [PolicyDay::Mon]);
+        assert_eq!(
+            config.diagnostics()["apps"][0]["access_hours"],
+            "06:00-01:00"
+        );
+        assert!(matches!(
+            parse("6am-1am").unwrap_err(),
+            ConfigError::Invalid {
+                key: "apps.access_hours",
+                ..
+            }
+        ));
+    }
+
+    #[test]
+    fn policies_are_checked_up_front() {
+        let parse = |contents: &str| {
+            parse_app_config(
+                &format!("[[policies]]\nname = \"office\"\neffect = \"deny\"\n{contents}"),
+                PathBuf::from("den.db"),
+            )
+        };
+        let config = parse(
+            "networks = [\"10.0.0.0/8\"]\nhours = \"22:00-06:00\"\ndays = [\"sat\", \"sun\"]\n\
+             apps = [\"https://wiki.example\"]\nmethods = [\"ldap\", \"redirect\"]",
+        )
+        .unwrap();
+        assert_eq!(config.policies[0].effect, PolicyEffect::Deny);
+        assert_eq!(
+            config.diagnostics()["policies"][0]["methods"][1],
+            "redirect"
+        );
+        let key = |contents| match parse(contents).unwrap_err() {
+            ConfigError::Invalid { key, .. } => key,
+            error => panic!("{error}"),
+        };
+        assert_eq!(key("networks = [\"10.0.0.0/33\"]"), "policies.networks");
+        assert_eq!(key("hours = \"8-18\""), "policies.hours");
+        assert_eq!(key("hours = \"08:00-08:00\""), "policies.hours");
+        assert_eq!(key("apps = [\"wiki.example\"]"), "policies.apps");
+        assert!(parse("methods = [\"smoke-signal\"]").is_err());
+    }
+
+    #[test]
+    fn policy_plugin_limits_are_bounded() {
+        let parse = |contents: &str| {
+            parse_app_config(
+                &format!("[policy_plugin]\npath = \"policy.wasm\"\n{contents}"),
+                PathBuf::from("den.db"),
+            )
+        };
+        let plugin = parse("").unwrap().policy_plugin.unwrap();
+        assert_eq!((plugin.fuel, plugin.max_memory_mb), (10_000_000, 16));
+        assert_eq!(plugin.on_error, HookFailure::Deny);
+        assert!(matches!(
+            parse("fuel = 0").unwrap_err(),
+            ConfigError::Invalid {
+                key: "policy_plugin.fuel",
+                ..
+            }
+        ));
+        assert!(matches!(
+            parse("max_memory_mb = 1024").unwrap_err(),
+            ConfigError::Invalid {
+                key: "policy_plugin.max_memory_mb",
+                ..
+            }
+        ));
+    }
+
+    #[test]
+    fn bind_address_defaults_to_ipv6_any_and_accepts_brackets() {
+        let config = parse_app_config("", PathBuf::from("den.db")).unwrap();
+        assert_eq!(
+            config.listen,
+            vec![ListenAddr::Tcp("[::]:3000".parse().unwrap())]
+        );
+        assert_eq!(config.ready_file, None);
+        let config = parse_app_config(
+            "port = 0\nbind_address = \"[::1]\"\nready_file = \"/run/den/port\"",
+            PathBuf::from("den.db"),
+        )
+        .unwrap();
+        assert_eq!(
+            config.listen,
+            vec![ListenAddr::Tcp("[::1]:0".parse().unwrap())]
+        );
+        assert_eq!(config.ready_file, Some(PathBuf::from("/run/den/port")));
+        let error =
+            parse_app_config("bind_address = \"localhost\"", PathBuf::from("den.db")).unwrap_err();
+        assert!(matches!(
+            error,
+            ConfigError::Invalid {
+                key: "bind_address",
+                ..
+            }
+        ));
+    }
+
+    #[test]
+    fn listen_takes_tcp_and_unix_addresses() {
+        let config = parse_app_config(
+            "listen = [\"[::1]:3000\", \"unix:/run/den/den.sock\", \"0.0.0.0:8080\"]",
+            PathBuf::from("den.db"),
+        )
+        .unwrap();
+        assert_eq!(
+            config.listen,
+            vec![
+                ListenAddr::Tcp("[::1]:3000".parse().unwrap()),
+                ListenAddr::Unix(PathBuf::from("/run/den/den.sock")),
+                ListenAddr::Tcp("0.0.0.0:8080".parse().unwrap()),
+            ]
+        );
+        assert_eq!(config.listen[1].to_string(), "unix:/run/den/den.sock");
+        for contents in [
+            "port = 3000\nlisten = [\"[::1]:3000\"]",
+            "listen = [\"localhost:3000\"]",
+            "listen = [\"unix:\"]",
+            "listen = [\"tls:[::]:443\"]",
+        ] {
+            let error = parse_app_config(contents, PathBuf::from("den.db")).unwrap_err();
+            assert!(
+                matches!(error, ConfigError::Invalid { key: "listen", .. }),
+                "{contents}"
+            );
+        }
+    }
+
+    #[test]
+    fn tls_listeners_and_the_certificate_come_together() {
+        let tls = "[tls]\ncert_path = \"/etc/den/cert.pem\"\nkey_path = \"/etc/den/key.pem\"";
+        let config = parse_app_config(
+            &format!("listen = [\"tls:[::]:443\", \"[::1]:3000\"]\n{tls}\nenable_h3 = true"),
+            PathBuf::from("den.db"),
+        )
+        .unwrap();
+        assert_eq!(
+            config.listen[0],
+            ListenAddr::Tls("[::]:443".parse().unwrap())
+        );
+        assert_eq!(config.listen[0].to_string(), "tls:[::]:443");
+        assert!(config.tls.unwrap().enable_h3);
+
+        let config =
+            parse_app_config(&format!("port = 8443\n{tls}"), PathBuf::from("den.db")).unwrap();
+        assert_eq!(
+            config.listen,
+            vec![ListenAddr::Tls("[::]:8443".parse().unwrap())]
+        );
+
+        for contents in [
+            format!("listen = [\"[::1]:3000\"]\n{tls}"),
+            "[tls]\ncert_path = \"\"\nkey_path = \"/etc/den/key.pem\"".to_owned(),
+        ] {
+            let error = parse_app_config(&contents, PathBuf::from("den.db")).unwrap_err();
+            assert!(
+                matches!(error, ConfigError::Invalid { key: "tls", .. }),
+                "{contents}"
+            );
+        }
+    }
+
+    #[test]
+    fn audit_export_defaults_format_per_target() {
+        let config = parse_app_config(
+            "[audit_export]\ntarget = \"file\"\npath = \"/var/log/den/audit.jsonl\"",
+            PathBuf::from("den.db"),
+        )
+        .unwrap();
+        assert!(matches!(
+            config.audit_export,
+            Some(AuditExportConfig::File {
+                format: AuditFormat::Jsonl,
+                max_bytes: 10_485_760,
+                keep: 5,
+                ..
+            })
+        ));
+        let config = parse_app_config(
+            "[audit_export]\ntarget = \"syslog\"\naddress = \"udp://siem.lan\"",
+            PathBuf::from("den.db"),
+        )
+        .unwrap();
+        assert_eq!(config.audit_export.unwrap().format(), AuditFormat::Cef);
+        let config = parse_app_config(
+            "[audit_export]\ntarget = \"file\"\npath = \"/var/log/den/auth.log\"\nformat = \"fail2ban\"",
+            PathBuf::from("den.db"),
+        )
+        .unwrap();
+        assert_eq!(config.audit_export.unwrap().format(), AuditFormat::Fail2ban);
+        let error = parse_app_config(
+            "[audit_export]\ntarget = \"syslog\"\naddress = \"siem.lan:514\"",
+            PathBuf::from("den.db"),
+        )
+        .unwrap_err();
+        assert!(matches!(
+            error,
+            ConfigError::Invalid {
+                key: "audit_export.address",
+                ..
+            }
+        ));
+    }
+
+    #[test]
+    fn storage_defaults_to_sqlite() {
+        let config = parse_app_config("", PathBuf::from("den.db")).unwrap();
+        assert!(matches!(config.storage, StorageConfig::Sqlite));
+        let config = parse_app_config(
+            "[storage]\nbackend = \"redis\"\nredis_url = \"redis://redis.lan\"\n",
+            PathBuf::from("den.db"),
+        )
+        .unwrap();
+        assert!(
+            matches!(config.storage, StorageConfig::Redis { redis_url } if redis_url == "redis://redis.lan")
+        );
+    }
+
+    #[test]
+    fn s3_sources_use_the_bucket_endpoint() {
+        let s3 = web_source("s3://den-web/builds/main", None).unwrap();
+        assert_eq!(
+            s3.url.as_str(),
+            "https://den-web.s3.amazonaws.com/builds/main/"
+        );
+        assert_eq!(s3.s3_region.as_deref(), Some("us-east-1"));
+        assert_eq!(
+            web_source("s3://den-web", Some("eu-central-1".into()))
+                .unwrap()
+                .url
+                .as_str(),
+            "https://den-web.s3.eu-central-1.amazonaws.com/"
+        );
+        let http = web_source("https://cdn.example/den/", Some("eu-central-1".into())).unwrap();
+        assert_eq!(http.url.as_str(), "https://cdn.example/den/");
+        assert_eq!(http.s3_region, None);
+        assert!(web_source("s3:///prefix", None).is_err());
+        assert!(web_source("ftp://example/web", None).is_err());
+    }
 }
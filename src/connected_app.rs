@@ -0,0 +1,8 @@
+use crate::db::Db;
+
+/// Best-effort: note that `user_id` redeemed a redirect token on `origin`.
+pub async fn record(db: &Db, user_id: &str, origin: &str) {
+    if let Err(error) = db.record_connected_app(user_id, origin).await {
+        tracing::warn!(error = %error, origin, "failed to record connected app");
+    }
+}
@@ -0,0 +1,2587 @@
+use std::time::{Duration, Instant};
+
+use serde::{Deserialize, Serialize};
+use sqlx::{SqliteExecutor, SqlitePool};
+use tracing::Instrument;
+use uuid::Uuid;
+use webauthn_rs::prelude::{CredentialID, Passkey};
+
+use crate::signing_key::SigningKey;
+
+/// `auth_challenge.kind` values.
+pub const CHALLENGE_REGISTRATION: &str = "registration";
+pub const CHALLENGE_AUTHENTICATION: &str = "authentication";
+pub const CHALLENGE_OIDC: &str = "oidc";
+
+/// Typed access to den's tables. Every query runs in a `db` tracing span and is
+/// logged when it takes longer than `database.slow_query_ms`. Queries here are
+/// checked at compile time against `.sqlx/`; see AGENTS.md to regenerate it.
+#[derive(Clone)]
+pub struct Db {
+    pool: SqlitePool,
+    slow_query: Duration,
+}
+
+#[derive(sqlx::FromRow)]
+pub struct User {
+    pub id: String,
+    pub name: String,
+}
+
+/// A stored credential with its decoded WebAuthn state.
+pub struct PasskeyRecord {
+    pub id: i64,
+    pub user_id: String,
+    pub name: String,
+    pub passkey: Passkey,
+}
+
+impl PasskeyRecord {
+    /// The authenticator model its attestation named, as the `aaguid` column holds it.
+    pub fn aaguid(&self) -> Option<Uuid> {
+        StoredPasskey::new(&self.passkey).ok()?.aaguid?.parse().ok()
+    }
+}
+
+#[derive(Serialize, sqlx::FromRow)]
+pub struct PasskeyInfo {
+    pub id: i64,
+    pub name: String,
+    pub created: String,
+    pub last_used: Option<String>,
+    pub login_count: i64,
+    pub last_ip: Option<String>,
+    pub last_user_agent: Option<String>,
+    /// The authenticator may sync this passkey (BE flag).
+    pub backup_eligible: bool,
+    /// It is synced now (BS flag), so it outlives the device.
+    pub backup_state: bool,
+    /// Authenticator model, when its attestation named one.
+    pub aaguid: Option<String>,
+    /// Marked temporary by the user.
+    pub temporary: bool,
+    /// When it stops signing in: its temporary expiry or `passkeys.max_age_days`
+    /// after creation, whichever comes first.
+    pub expires_at: Option<String>,
+}
+
+/// Sort key for `Db::list_passkeys_page`; ties break on `id`, so pages never overlap.
+#[derive(Debug, Clone, Copy, Default, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum PasskeySort {
+    #[default]
+    Created,
+    LastUsed,
+    Name,
+    LoginCount,
+}
+
+#[derive(Debug, Clone, Copy, Default, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum SortOrder {
+    #[default]
+    Asc,
+    Desc,
+}
+
+/// Which of a user's passkeys `Db::list_passkeys_page` returns, in what order.
+#[derive(Debug, Clone, Default, Deserialize)]
+#[serde(default)]
+pub struct PasskeyQuery {
+    /// Only names containing this, case-insensitively.
+    pub q: Option<String>,
+    pub sort: PasskeySort,
+    pub order: SortOrder,
+    /// All when unset.
+    pub limit: Option<i64>,
+    pub offset: i64,
+}
+
+/// One page of a user's passkeys and how many match in total.
+pub struct PasskeyPage {
+    pub passkeys: Vec<PasskeyInfo>,
+    pub total: i64,
+}
+
+/// Result of `Db::bulk_update_passkeys`; anything but `Done` changed nothing.
+pub enum BulkPasskeyOutcome {
+    /// Names of the deleted passkeys.
+    Done(Vec<String>),
+    NotFound,
+    LastPasskey,
+}
+
+/// A passkey row as written to encrypted backups.
+#[derive(Serialize, Deserialize, sqlx::FromRow)]
+pub struct BackupPasskey {
+    pub name: String,
+    pub data: String,
+    pub created: String,
+}
+
+/// An outstanding passkey invite; `id` is the row's `rowid`, as the table is keyed
+/// by the token hash.
+#[derive(Serialize, sqlx::FromRow)]
+pub struct InviteInfo {
+    pub id: i64,
+    pub created: String,
+    pub expires_at: String,
+}
+
+#[derive(sqlx::FromRow)]
+pub struct ConfirmedTotp {
+    pub user_id: String,
+    pub secret: Vec<u8>,
+    pub last_step: Option<i64>,
+}
+
+#[derive(sqlx::FromRow)]
+pub struct SessionRecord {
+    pub id: String,
+    pub name: Option<String>,
+    pub user_agent: Option<String>,
+    pub ip: Option<String>,
+    pub country: Option<String>,
+    pub created: String,
+    pub last_used: String,
+}
+
+/// A delegated sub-account and the origins it may reach.
+#[derive(Serialize)]
+pub struct SubAccountInfo {
+    pub id: String,
+    pub name: String,
+    pub created: String,
+    /// Normalized, lowercase origins.
+    pub apps: Vec<String>,
+    /// Live passkeys; none until an invite is used.
+    pub passkeys: i64,
+}
+
+#[derive(Serialize, sqlx::FromRow)]
+pub struct ServiceAccountInfo {
+    pub id: String,
+    pub name: String,
+    pub scopes: String,
+    pub created: String,
+    pub expires_at: Option<String>,
+    pub last_used: Option<String>,
+}
+
+/// Passkeys past their expiry, and ones reaching it within `passkeys.warning_days`.
+#[derive(Serialize, sqlx::FromRow)]
+pub struct PasskeyExpiryCounts {
+    pub passkeys_expired: i64,
+    pub passkeys_expiring: i64,
+}
+
+#[derive(Serialize, sqlx::FromRow)]
+pub struct EventCounts {
+    pub logins_24h: i64,
+    pub logins_7d: i64,
+    pub failures_24h: i64,
+    pub failures_7d: i64,
+}
+
+/// An `audit_event` row as shown in the user's own login history.
+#[derive(Debug, sqlx::FromRow)]
+pub struct LoginRecord {
+    pub id: i64,
+    pub kind: String,
+    pub created: String,
+    pub ip: Option<String>,
+    pub country: Option<String>,
+    pub user_agent: Option<String>,
+    pub detail: Option<String>,
+    pub app: Option<String>,
+}
+
+#[derive(Debug, Serialize, sqlx::FromRow)]
+pub struct PendingRecovery {
+    pub requested_at: String,
+    pub ready_at: String,
+    /// Whether `ready_at` has passed.
+    pub ready: bool,
+}
+
+#[derive(Debug, Serialize, sqlx::FromRow)]
+pub struct UsageDay {
+    pub day: String,
+    pub active_sessions: i64,
+    pub redirects: i64,
+}
+
+#[derive(Debug, Serialize, sqlx::FromRow)]
+pub struct AppUsage {
+    pub app: String,
+    pub redirects: i64,
+    pub last_day: String,
+}
+
+#[derive(Debug, Serialize, sqlx::FromRow)]
+pub struct ConnectedApp {
+    pub origin: String,
+    pub first_used: String,
+    pub last_used: String,
+    pub count: i64,
+}
+
+/// Applied migrations compared with the ones compiled into this binary.
+#[derive(Serialize)]
+pub struct MigrationStatus {
+    pub latest_applied: Option<i64>,
+    pub applied: usize,
+    /// Compiled in but not yet recorded in `_sqlx_migrations`.
+    pub pending: Vec<i64>,
+    /// Recorded with `success = 0`.
+    pub failed: Vec<i64>,
+}
+
+/// `passkey.cred_id`: the credential ID as serialized inside `data`.
+fn credential_key(cred_id: &CredentialID) -> Result<String, sqlx::Error> {
+    match serde_json::to_value(cred_id).map_err(|e| sqlx::Error::Encode(e.into()))? {
+        serde_json::Value::String(key) => Ok(key),
+        other => Ok(other.to_string()),
+    }
+}
+
+/// `name` with the lowest suffix from 2 up that none of `taken` equals, ignoring
+/// ASCII case as SQLite's `NOCASE` does.
+fn available_name(taken: &[String], name: &str) -> String {
+    let free = |candidate: &str| !taken.iter().any(|t| t.eq_ignore_ascii_case(candidate));
+    if free(name) {
+        return name.to_owned();
+    }
+    (2..)
+        .map(|n| format!("{name} {n}"))
+        .find(|candidate| free(candidate))
+        .expect("finitely many names are taken")
+}
+
+/// The `datetime()` modifier for `passkeys.max_age_days`, bound as `?2` in the
+/// effective-expiry expression `MIN(COALESCE(expires_at, datetime(created, ?2)),
+/// COALESCE(datetime(created, ?2), expires_at))`, which is NULL only when neither
+/// limit applies.
+fn max_age_modifier(max_age_days: Option<u32>) -> Option<String> {
+    max_age_days.map(|days| format!("+{days} days"))
+}
+
+/// A passkey as written: the webauthn-rs JSON in `data`, plus the columns read
+/// from the same fields, which every write sets alongside it.
+struct StoredPasskey {
+    data: String,
+    cred_id: Option<String>,
+    /// COSE public key as JSON.
+    public_key: Option<String>,
+    sign_count: i64,
+    backup_eligible: bool,
+    backup_state: bool,
+    /// Only packed and TPM attestation carry one into the stored credential.
+    aaguid: Option<String>,
+}
+
+impl StoredPasskey {
+    fn new(passkey: &Passkey) -> Result<Self, sqlx::Error> {
+        let encode = |e: serde_json::Error| sqlx::Error::Encode(e.into());
+        let value = serde_json::to_value(passkey).map_err(encode)?;
+        let cred = &value["cred"];
+        // What SQLite's json_extract gives: strings as-is, anything else as JSON.
+        let text = |v: &serde_json::Value| match v {
+            serde_json::Value::Null => None,
+            serde_json::Value::String(s) => Some(s.clone()),
+            other => Some(other.to_string()),
+        };
+        let metadata = &cred["attestation"]["metadata"];
+        Ok(Self {
+            data: serde_json::to_string(passkey).map_err(encode)?,
+            cred_id: text(&cred["cred_id"]),
+            public_key: text(&cred["cred"]),
+            sign_count: cred["counter"].as_i64().unwrap_or(0),
+            backup_eligible: cred["backup_eligible"].as_bool().unwrap_or(false),
+            backup_state: cred["backup_state"].as_bool().unwrap_or(false),
+            aaguid: text(&metadata["Packed"]["aaguid"])
+                .or_else(|| text(&metadata["Tpm"]["aaguid"])),
+        })
+    }
+
+    async fn insert(
+        &self,
+        executor: impl SqliteExecutor<'_>,
+        user_id: &str,
+        name: &str,
+    ) -> Result<(), sqlx::Error> {
+        sqlx::query!(
+            "INSERT INTO passkey (user_id, name, data, cred_id, public_key, sign_count, \
+             backup_eligible, backup_state, aaguid) VALUES (?, ?, ?, ?, ?, ?, ?, ?, ?)",
+            user_id,
+            name,
+            self.data,
+            self.cred_id,
+            self.public_key,
+            self.sign_count,
+            self.backup_eligible,
+            self.backup_state,
+            self.aaguid,
+        )
+        .execute(executor)
+        .await
+        .map(|_| ())
+    }
+}
+
+async fn sub_account_of(
+    executor: impl SqliteExecutor<'_>,
+    parent_id: &str,
+    id: &str,
+) -> Result<bool, sqlx::Error> {
+    sqlx::query_scalar!(
+        r#"SELECT EXISTS(SELECT 1 FROM user WHERE id = ? AND parent_id = ?) AS "found!: bool""#,
+        id,
+        parent_id,
+    )
+    .fetch_one(executor)
+    .await
+}
+
+fn decode_passkey(data: &str) -> Option<Passkey> {
+    serde_json::from_str(data)
+        .inspect_err(|error| tracing::warn!(error = %error, "skipping undecodable passkey"))
+        .ok()
+}
+
+struct PasskeyRow {
+    id: i64,
+    user_id: String,
+    name: String,
+    data: String,
+}
+
+fn passkey_record(row: PasskeyRow) -> Option<PasskeyRecord> {
+    Some(PasskeyRecord {
+        passkey: decode_passkey(&row.data)?,
+        id: row.id,
+        user_id: row.user_id,
+        name: row.name,
+    })
+}
+
+impl Db {
+    pub fn new(pool: SqlitePool, slow_query: Duration) -> Self {
+        Self { pool, slow_query }
+    }
+
+    /// The pool behind every query here, for closing it before touching the file.
+    pub fn pool(&self) -> &SqlitePool {
+        &self.pool
+    }
+
+    pub async fn timed<T>(
+        &self,
+        query: &'static str,
+        run: impl Future<Output = Result<T, sqlx::Error>>,
+    ) -> Result<T, sqlx::Error> {
+        let started = Instant::now();
+        let result = run.instrument(tracing::debug_span!("db", query)).await;
+        let elapsed = started.elapsed();
+        if elapsed >= self.slow_query {
+            tracing::warn!(
+                query,
+                elapsed_ms = elapsed.as_millis() as u64,
+                "slow database query"
+            );
+        }
+        result
+    }
+
+    pub async fn migrate(&self) -> Result<(), sqlx::migrate::MigrateError> {
+        sqlx::migrate!().run(&self.pool).await
+    }
+
+    pub async fn ping(&self) -> Result<(), sqlx::Error> {
+        self.timed(
+            "ping",
+            sqlx::query_scalar!("SELECT 1").fetch_one(&self.pool),
+        )
+        .await
+        .map(|_| ())
+    }
+
+    // --- Signing keys ---
+
+    /// Every signing key version, newest first.
+    pub async fn signing_keys(&self) -> Result<Vec<SigningKey>, sqlx::Error> {
+        self.timed(
+            "signing_keys",
+            sqlx::query_as!(
+                SigningKey,
+                r#"SELECT kid AS "kid!", secret,
+                          CAST(strftime('%s', created) AS INTEGER) AS "created!: i64"
+                   FROM signing_key_version ORDER BY created DESC, kid DESC"#
+            )
+            .fetch_all(&self.pool),
+        )
+        .await
+    }
+
+    /// Store `key` unless its `kid` is taken, then drop all but the newest `keep`.
+    pub async fn add_signing_key(&self, key: &SigningKey, keep: i64) -> Result<(), sqlx::Error> {
+        self.timed("add_signing_key", async {
+            let mut tx = self.pool.begin().await?;
+            sqlx::query!(
+                "INSERT OR IGNORE INTO signing_key_version (kid, secret, created) \
+                 VALUES (?, ?, datetime(?, 'unixepoch'))",
+                key.kid,
+                key.secret,
+                key.created
+            )
+            .execute(&mut *tx)
+            .await?;
+            sqlx::query!(
+                "DELETE FROM signing_key_version WHERE kid NOT IN \
+                 (SELECT kid FROM signing_key_version ORDER BY created DESC, kid DESC LIMIT ?)",
+                keep
+            )
+            .execute(&mut *tx)
+            .await?;
+            tx.commit().await
+        })
+        .await
+    }
+
+    // --- Leases ---
+
+    /// Take or renew the `name` lease for `holder`; false while another holder's
+    /// lease is unexpired.
+    pub async fn acquire_lease(
+        &self,
+        name: &str,
+        holder: &str,
+        ttl_seconds: i64,
+    ) -> Result<bool, sqlx::Error> {
+        let result = self
+            .timed(
+                "acquire_lease",
+                sqlx::query!(
+                    "INSERT INTO lease (name, holder, expires_at) \
+                     VALUES (?1, ?2, datetime('now', ?3 || ' seconds')) \
+                     ON CONFLICT (name) DO UPDATE SET holder = excluded.holder, \
+                     expires_at = excluded.expires_at \
+                     WHERE lease.holder = excluded.holder OR lease.expires_at <= datetime('now')",
+                    name,
+                    holder,
+                    ttl_seconds,
+                )
+                .execute(&self.pool),
+            )
+            .await?;
+        Ok(result.rows_affected() > 0)
+    }
+
+    // --- Runtime allowed hosts ---
+
+    pub async fn allowed_hosts(&self) -> Result<Vec<String>, sqlx::Error> {
+        self.timed(
+            "allowed_hosts",
+            sqlx::query_scalar!(r#"SELECT host AS "host!" FROM allowed_host ORDER BY host"#)
+                .fetch_all(&self.pool),
+        )
+        .await
+    }
+
+    pub async fn replace_allowed_hosts(&self, hosts: &[String]) -> Result<(), sqlx::Error> {
+        self.timed("replace_allowed_hosts", async {
+            let mut tx = self.pool.begin().await?;
+            sqlx::query!("DELETE FROM allowed_host")
+                .execute(&mut *tx)
+                .await?;
+            for host in hosts {
+                sqlx::query!("INSERT OR IGNORE INTO allowed_host (host) VALUES (?)", host)
+                    .execute(&mut *tx)
+                    .await?;
+            }
+            tx.commit().await
+        })
+        .await
+    }
+
+    // --- Users ---
+
+    pub async fn get_user(&self, id: &str) -> Result<Option<User>, sqlx::Error> {
+        self.timed(
+            "get_user",
+            sqlx::query_as!(
+                User,
+                r#"SELECT id AS "id!", name FROM user WHERE id = ?"#,
+                id
+            )
+            .fetch_optional(&self.pool),
+        )
+        .await
+    }
+
+    /// The owner named `name`; sub-accounts never match.
+    pub async fn owner_by_name(&self, name: &str) -> Result<Option<User>, sqlx::Error> {
+        self.timed(
+            "owner_by_name",
+            sqlx::query_as!(
+                User,
+                r#"SELECT id AS "id!", name FROM user WHERE name = ? AND parent_id IS NULL"#,
+                name
+            )
+            .fetch_optional(&self.pool),
+        )
+        .await
+    }
+
+    /// den is single-user: the user, once one exists. Sub-accounts don't count.
+    pub async fn only_user(&self) -> Result<Option<User>, sqlx::Error> {
+        self.timed(
+            "only_user",
+            sqlx::query_as!(
+                User,
+                r#"SELECT id AS "id!", name FROM user WHERE parent_id IS NULL LIMIT 1"#
+            )
+            .fetch_optional(&self.pool),
+        )
+        .await
+    }
+
+    pub async fn user_exists(&self) -> Result<bool, sqlx::Error> {
+        self.timed(
+            "user_exists",
+            sqlx::query_scalar!(r#"SELECT EXISTS (SELECT 1 FROM user) AS "exists!: bool""#)
+                .fetch_one(&self.pool),
+        )
+        .await
+    }
+
+    /// Create the instance's user; false when one already exists (atomic guard).
+    pub async fn create_only_user(&self, id: &str, name: &str) -> Result<bool, sqlx::Error> {
+        let result = self
+            .timed(
+                "create_only_user",
+                sqlx::query!(
+                    "INSERT INTO user (id, name) SELECT ?, ? WHERE NOT EXISTS (SELECT 1 FROM user)",
+                    id,
+                    name,
+                )
+                .execute(&self.pool),
+            )
+            .await?;
+        Ok(result.rows_affected() > 0)
+    }
+
+    /// `create_only_user` and the user's first passkey in one transaction, so a crash
+    /// cannot leave the only user without a way to sign in. False if a user exists.
+    pub async fn create_only_user_with_passkey(
+        &self,
+        id: &str,
+        name: &str,
+        passkey_name: &str,
+        passkey: &Passkey,
+    ) -> Result<bool, sqlx::Error> {
+        let stored = StoredPasskey::new(passkey)?;
+        self.timed("create_only_user_with_passkey", async {
+            let mut tx = self.pool.begin().await?;
+            let created = sqlx::query!(
+                "INSERT INTO user (id, name) SELECT ?, ? WHERE NOT EXISTS (SELECT 1 FROM user)",
+                id,
+                name,
+            )
+            .execute(&mut *tx)
+            .await?
+            .rows_affected();
+            if created == 0 {
+                return Ok(false);
+            }
+            stored.insert(&mut *tx, id, passkey_name).await?;
+            tx.commit().await?;
+            Ok(true)
+        })
+        .await
+    }
+
+    /// Delete users nothing refers to, not even a passkey: what a registration
+    /// interrupted before it was made atomic left behind, with setup closed and no
+    /// way to sign in. Returns their names. Users of `[upstream_oidc]` have no
+    /// passkeys by design, so this is only for instances without it. Sub-accounts
+    /// have none until their invite is used, and are left alone.
+    pub async fn remove_stranded_users(&self) -> Result<Vec<String>, sqlx::Error> {
+        self.timed(
+            "remove_stranded_users",
+            sqlx::query_scalar!(
+                "DELETE FROM user WHERE parent_id IS NULL \
+                 AND NOT EXISTS (SELECT 1 FROM passkey WHERE user_id = user.id) \
+                 AND NOT EXISTS (SELECT 1 FROM session WHERE user_id = user.id) \
+                 AND NOT EXISTS (SELECT 1 FROM seen_device WHERE user_id = user.id) \
+                 AND NOT EXISTS (SELECT 1 FROM passkey_invite WHERE user_id = user.id) \
+                 AND NOT EXISTS (SELECT 1 FROM login_context WHERE user_id = user.id) \
+                 AND NOT EXISTS (SELECT 1 FROM totp_secret WHERE user_id = user.id) \
+                 AND NOT EXISTS (SELECT 1 FROM recovery_code WHERE user_id = user.id) \
+                 AND NOT EXISTS (SELECT 1 FROM recovery_request WHERE user_id = user.id) \
+                 RETURNING name",
+            )
+            .fetch_all(&self.pool),
+        )
+        .await
+    }
+
+    // --- Passkeys ---
+
+    pub async fn user_passkeys(&self, user_id: &str) -> Result<Vec<Passkey>, sqlx::Error> {
+        let rows = self
+            .timed(
+                "user_passkeys",
+                sqlx::query_scalar!(
+                    "SELECT data FROM passkey WHERE user_id = ? AND deleted_at IS NULL",
+                    user_id
+                )
+                .fetch_all(&self.pool),
+            )
+            .await?;
+        Ok(rows
+            .iter()
+            .filter_map(|data| decode_passkey(data))
+            .collect())
+    }
+
+    pub async fn all_passkeys(&self) -> Result<Vec<PasskeyRecord>, sqlx::Error> {
+        let rows = self
+            .timed(
+                "all_passkeys",
+                sqlx::query_as!(
+                    PasskeyRow,
+                    r#"SELECT id AS "id!", user_id, name, data FROM passkey WHERE deleted_at IS NULL"#
+                )
+                .fetch_all(&self.pool),
+            )
+            .await?;
+        Ok(rows.into_iter().filter_map(passkey_record).collect())
+    }
+
+    /// The passkey for the credential that signed a login.
+    pub async fn credential_passkey(
+        &self,
+        cred_id: &CredentialID,
+    ) -> Result<Option<PasskeyRecord>, sqlx::Error> {
+        let cred_id = credential_key(cred_id)?;
+        let row = self
+            .timed(
+                "credential_passkey",
+                sqlx::query_as!(
+                    PasskeyRow,
+                    r#"SELECT id AS "id!", user_id, name, data FROM passkey
+                       WHERE cred_id = ? AND deleted_at IS NULL"#,
+                    cred_id
+                )
+                .fetch_optional(&self.pool),
+            )
+            .await?;
+        Ok(row.and_then(passkey_record))
+    }
+
+    pub async fn insert_passkey(
+        &self,
+        user_id: &str,
+        name: &str,
+        passkey: &Passkey,
+    ) -> Result<(), sqlx::Error> {
+        let stored = StoredPasskey::new(passkey)?;
+        self.timed("insert_passkey", stored.insert(&self.pool, user_id, name))
+            .await
+    }
+
+    /// Persist credential state after a login (only when it changed) plus usage stats.
+    pub async fn record_passkey_use(
+        &self,
+        id: i64,
+        updated: Option<&Passkey>,
+        ip: &str,
+        user_agent: &str,
+    ) -> Result<(), sqlx::Error> {
+        let stored = updated.map(StoredPasskey::new).transpose()?;
+        let stored = stored.as_ref();
+        let data = stored.map(|s| &s.data);
+        let sign_count = stored.map(|s| s.sign_count);
+        let backup_eligible = stored.map(|s| s.backup_eligible);
+        let backup_state = stored.map(|s| s.backup_state);
+        self.timed(
+            "record_passkey_use",
+            sqlx::query!(
+                "UPDATE passkey SET data = COALESCE(?, data), \
+                 sign_count = COALESCE(?, sign_count), \
+                 backup_eligible = COALESCE(?, backup_eligible), \
+                 backup_state = COALESCE(?, backup_state), \
+                 last_used = datetime('now'), login_count = login_count + 1, \
+                 last_ip = ?, last_user_agent = ? WHERE id = ?",
+                data,
+                sign_count,
+                backup_eligible,
+                backup_state,
+                ip,
+                user_agent,
+                id,
+            )
+            .execute(&self.pool),
+        )
+        .await
+        .map(|_| ())
+    }
+
+    /// Every passkey of the user, oldest first.
+    pub async fn list_passkeys(
+        &self,
+        user_id: &str,
+        max_age_days: Option<u32>,
+    ) -> Result<Vec<PasskeyInfo>, sqlx::Error> {
+        let page = self
+            .list_passkeys_page(user_id, max_age_days, &PasskeyQuery::default())
+            .await?;
+        Ok(page.passkeys)
+    }
+
+    pub async fn list_passkeys_page(
+        &self,
+        user_id: &str,
+        max_age_days: Option<u32>,
+        query: &PasskeyQuery,
+    ) -> Result<PasskeyPage, sqlx::Error> {
+        // The sort key is picked in SQL so the statement stays fixed; `lower(name)`
+        // folds ASCII only, like `NOCASE`.
+        let sort = match query.sort {
+            PasskeySort::Created => "created",
+            PasskeySort::LastUsed => "last_used",
+            PasskeySort::Name => "name",
+            PasskeySort::LoginCount => "login_count",
+        };
+        let descending = matches!(query.order, SortOrder::Desc);
+        let total = self
+            .timed(
+                "count_passkeys",
+                sqlx::query_scalar!(
+                    "SELECT COUNT(*) FROM passkey WHERE user_id = ?1 AND deleted_at IS NULL \
+                     AND (?2 IS NULL OR instr(lower(name), lower(?2)) > 0)",
+                    user_id,
+                    query.q,
+                )
+                .fetch_one(&self.pool),
+            )
+            .await?;
+        let max_age = max_age_modifier(max_age_days);
+        // SQLite reads a negative LIMIT as none.
+        let limit = query.limit.unwrap_or(-1);
+        let passkeys = self
+            .timed(
+                "list_passkeys",
+                sqlx::query_as!(
+                    PasskeyInfo,
+                    r#"SELECT id AS "id!", name, created, last_used, login_count, last_ip,
+                       last_user_agent, backup_eligible AS "backup_eligible: bool",
+                       backup_state AS "backup_state: bool", aaguid,
+                       expires_at IS NOT NULL AS "temporary!: bool",
+                       MIN(COALESCE(expires_at, datetime(created, ?2)),
+                           COALESCE(datetime(created, ?2), expires_at)) AS "expires_at: String"
+                     FROM passkey WHERE user_id = ?1 AND deleted_at IS NULL
+                     AND (?3 IS NULL OR instr(lower(name), lower(?3)) > 0)
+                     ORDER BY
+                       CASE WHEN NOT ?7 THEN CASE ?6 WHEN 'created' THEN created
+                         WHEN 'last_used' THEN last_used WHEN 'name' THEN lower(name)
+                         ELSE login_count END END ASC,
+                       CASE WHEN ?7 THEN CASE ?6 WHEN 'created' THEN created
+                         WHEN 'last_used' THEN last_used WHEN 'name' THEN lower(name)
+                         ELSE login_count END END DESC,
+                       CASE WHEN NOT ?7 THEN id END ASC, id DESC
+                     LIMIT ?4 OFFSET ?5"#,
+                    user_id,
+                    max_age,
+                    query.q,
+                    limit,
+                    query.offset,
+                    sort,
+                    descending,
+                )
+                .fetch_all(&self.pool),
+            )
+            .await?;
+        Ok(PasskeyPage { passkeys, total })
+    }
+
+    /// Whether the passkey is past its temporary expiry or `passkeys.max_age_days`.
+    pub async fn passkey_expired(
+        &self,
+        id: i64,
+        max_age_days: Option<u32>,
+    ) -> Result<bool, sqlx::Error> {
+        let max_age = max_age_modifier(max_age_days);
+        self.timed(
+            "passkey_expired",
+            sqlx::query_scalar!(
+                r#"SELECT COALESCE(MIN(COALESCE(expires_at, datetime(created, ?2)),
+                     COALESCE(datetime(created, ?2), expires_at)) <= datetime('now'), 0)
+                     AS "expired!: bool"
+                 FROM passkey WHERE id = ?1"#,
+                id,
+                max_age,
+            )
+            .fetch_one(&self.pool),
+        )
+        .await
+    }
+
+    /// Make a passkey temporary, expiring `days` from now, or permanent again with
+    /// `None`. Returns the new expiry, or `Ok(None)` when the passkey isn't the user's.
+    pub async fn set_passkey_expiry(
+        &self,
+        user_id: &str,
+        id: i64,
+        days: Option<u32>,
+    ) -> Result<Option<Option<String>>, sqlx::Error> {
+        let expires = days.map(|days| format!("+{days} days"));
+        self.timed(
+            "set_passkey_expiry",
+            sqlx::query_scalar!(
+                "UPDATE passkey SET expires_at = datetime('now', ?) \
+                 WHERE id = ? AND user_id = ? AND deleted_at IS NULL RETURNING expires_at",
+                expires,
+                id,
+                user_id,
+            )
+            .fetch_optional(&self.pool),
+        )
+        .await
+    }
+
+    pub async fn rename_passkey(
+        &self,
+        user_id: &str,
+        id: i64,
+        name: &str,
+    ) -> Result<bool, sqlx::Error> {
+        let result = self
+            .timed(
+                "rename_passkey",
+                sqlx::query!(
+                    "UPDATE passkey SET name = ? \
+                     WHERE id = ? AND user_id = ? AND deleted_at IS NULL",
+                    name,
+                    id,
+                    user_id,
+                )
+                .execute(&self.pool),
+            )
+            .await?;
+        Ok(result.rows_affected() > 0)
+    }
+
+    /// Delete a passkey unless no other unexpired one would be left; returns its name
+    /// when removed. The row stays, marked `deleted_at`, until `purge_deleted_passkeys`.
+    pub async fn delete_passkey(
+        &self,
+        user_id: &str,
+        id: i64,
+        max_age_days: Option<u32>,
+    ) -> Result<Option<String>, sqlx::Error> {
+        let max_age = max_age_modifier(max_age_days);
+        self.timed(
+            "delete_passkey",
+            sqlx::query_scalar!(
+                "UPDATE passkey SET deleted_at = datetime('now') \
+                 WHERE id = ?1 AND user_id = ?3 AND deleted_at IS NULL \
+                 AND EXISTS (SELECT 1 FROM passkey AS other \
+                   WHERE other.user_id = ?3 AND other.id != ?1 AND other.deleted_at IS NULL \
+                   AND COALESCE(MIN(COALESCE(other.expires_at, datetime(other.created, ?2)), \
+                       COALESCE(datetime(other.created, ?2), other.expires_at)) \
+                       > datetime('now'), 1)) \
+                 RETURNING name",
+                id,
+                max_age,
+                user_id,
+            )
+            .fetch_optional(&self.pool),
+        )
+        .await
+    }
+
+    /// Undo a delete less than `grace_hours` old; returns the name, or `Ok(None)` when
+    /// there is no such deleted passkey or its credential was added again since. A
+    /// name taken since is a unique violation.
+    pub async fn restore_passkey(
+        &self,
+        user_id: &str,
+        id: i64,
+        grace_hours: u32,
+    ) -> Result<Option<String>, sqlx::Error> {
+        let grace = format!("-{grace_hours} hours");
+        self.timed(
+            "restore_passkey",
+            sqlx::query_scalar!(
+                "UPDATE passkey SET deleted_at = NULL \
+                 WHERE id = ? AND user_id = ? AND deleted_at > datetime('now', ?) \
+                 AND NOT EXISTS (SELECT 1 FROM passkey AS live \
+                   WHERE live.cred_id = passkey.cred_id AND live.deleted_at IS NULL) \
+                 RETURNING name",
+                id,
+                user_id,
+                grace,
+            )
+            .fetch_optional(&self.pool),
+        )
+        .await
+    }
+
+    /// Drop passkeys deleted at least `grace_hours` ago, returning how many went.
+    pub async fn purge_deleted_passkeys(&self, grace_hours: u32) -> Result<u64, sqlx::Error> {
+        let grace = format!("-{grace_hours} hours");
+        self.timed(
+            "purge_deleted_passkeys",
+            sqlx::query!(
+                "DELETE FROM passkey WHERE deleted_at <= datetime('now', ?)",
+                grace
+            )
+            .execute(&self.pool),
+        )
+        .await
+        .map(|result| result.rows_affected())
+    }
+
+    /// Rename and delete several of the user's passkeys in one transaction, deleting as
+    /// `delete_passkey` does. An id that is not one of the user's live passkeys, or
+    /// leaving them no unexpired passkey, rolls all of it back.
+    pub async fn bulk_update_passkeys(
+        &self,
+        user_id: &str,
+        rename: &[(i64, String)],
+        delete: &[i64],
+        max_age_days: Option<u32>,
+    ) -> Result<BulkPasskeyOutcome, sqlx::Error> {
+        self.timed("bulk_update_passkeys", async {
+            let mut tx = self.pool.begin().await?;
+            // Deletes free their names first, and renames go through a placeholder
+            // name each, so the unique name index only sees the final state: a
+            // deleted name can be reused and two passkeys can swap names.
+            let mut removed = Vec::with_capacity(delete.len());
+            for id in delete {
+                let name = sqlx::query_scalar!(
+                    "UPDATE passkey SET deleted_at = datetime('now') \
+                     WHERE id = ? AND user_id = ? AND deleted_at IS NULL RETURNING name",
+                    id,
+                    user_id,
+                )
+                .fetch_optional(&mut *tx)
+                .await?;
+                let Some(name) = name else {
+                    return Ok(BulkPasskeyOutcome::NotFound);
+                };
+                removed.push(name);
+            }
+            for (id, _) in rename {
+                let placeholder = format!("renaming {}", Uuid::new_v4());
+                let renamed = sqlx::query!(
+                    "UPDATE passkey SET name = ? \
+                     WHERE id = ? AND user_id = ? AND deleted_at IS NULL",
+                    placeholder,
+                    id,
+                    user_id,
+                )
+                .execute(&mut *tx)
+                .await?
+                .rows_affected();
+                if renamed == 0 {
+                    return Ok(BulkPasskeyOutcome::NotFound);
+                }
+            }
+            for (id, name) in rename {
+                sqlx::query!(
+                    "UPDATE passkey SET name = ? \
+                     WHERE id = ? AND user_id = ? AND deleted_at IS NULL",
+                    name,
+                    id,
+                    user_id,
+                )
+                .execute(&mut *tx)
+                .await?;
+            }
+            let max_age = max_age_modifier(max_age_days);
+            let left = sqlx::query_scalar!(
+                "SELECT COUNT(*) FROM passkey WHERE user_id = ?1 AND deleted_at IS NULL \
+                 AND COALESCE(MIN(COALESCE(expires_at, datetime(created, ?2)), \
+                     COALESCE(datetime(created, ?2), expires_at)) > datetime('now'), 1)",
+                user_id,
+                max_age,
+            )
+            .fetch_one(&mut *tx)
+            .await?;
+            if left == 0 {
+                return Ok(BulkPasskeyOutcome::LastPasskey);
+            }
+            tx.commit().await?;
+            Ok(BulkPasskeyOutcome::Done(removed))
+        })
+        .await
+    }
+
+    /// Whether the user has a passkey by this name, ignoring ASCII case like the
+    /// `passkey_user_name` index.
+    pub async fn passkey_name_taken(&self, user_id: &str, name: &str) -> Result<bool, sqlx::Error> {
+        self.timed(
+            "passkey_name_taken",
+            sqlx::query_scalar!(
+                r#"SELECT EXISTS(SELECT 1 FROM passkey
+                 WHERE user_id = ? AND name = ? COLLATE NOCASE AND deleted_at IS NULL)
+                 AS "taken!: bool""#,
+                user_id,
+                name,
+            )
+            .fetch_one(&self.pool),
+        )
+        .await
+    }
+
+    /// `name`, or the first of `name 2`, `name 3`, … the user has no passkey by.
+    pub async fn available_passkey_name(
+        &self,
+        user_id: &str,
+        name: &str,
+    ) -> Result<String, sqlx::Error> {
+        let taken = self
+            .timed(
+                "passkey_names",
+                sqlx::query_scalar!(
+                    "SELECT name FROM passkey WHERE user_id = ? AND deleted_at IS NULL",
+                    user_id
+                )
+                .fetch_all(&self.pool),
+            )
+            .await?;
+        Ok(available_name(&taken, name))
+    }
+
+    pub async fn passkey_exists(&self, user_id: &str, id: i64) -> Result<bool, sqlx::Error> {
+        self.timed(
+            "passkey_exists",
+            sqlx::query_scalar!(
+                r#"SELECT EXISTS(SELECT 1 FROM passkey
+                 WHERE id = ? AND user_id = ? AND deleted_at IS NULL) AS "exists!: bool""#,
+                id,
+                user_id,
+            )
+            .fetch_one(&self.pool),
+        )
+        .await
+    }
+
+    pub async fn passkey_count(&self) -> Result<i64, sqlx::Error> {
+        self.timed(
+            "passkey_count",
+            sqlx::query_scalar!("SELECT COUNT(*) FROM passkey WHERE deleted_at IS NULL")
+                .fetch_one(&self.pool),
+        )
+        .await
+    }
+
+    pub async fn passkey_expiry_counts(
+        &self,
+        max_age_days: Option<u32>,
+        warning_days: u32,
+    ) -> Result<PasskeyExpiryCounts, sqlx::Error> {
+        let warning = format!("+{warning_days} days");
+        let max_age = max_age_modifier(max_age_days);
+        self.timed(
+            "passkey_expiry_counts",
+            sqlx::query_as!(
+                PasskeyExpiryCounts,
+                r#"SELECT COALESCE(SUM(expiry <= datetime('now')), 0) AS "passkeys_expired!: i64",
+                   COALESCE(SUM(expiry > datetime('now') AND expiry <= datetime('now', ?1)), 0)
+                     AS "passkeys_expiring!: i64"
+                 FROM (SELECT MIN(COALESCE(expires_at, datetime(created, ?2)),
+                     COALESCE(datetime(created, ?2), expires_at)) AS expiry
+                   FROM passkey WHERE deleted_at IS NULL)"#,
+                warning,
+                max_age,
+            )
+            .fetch_one(&self.pool),
+        )
+        .await
+    }
+
+    pub async fn backup_passkeys(&self, user_id: &str) -> Result<Vec<BackupPasskey>, sqlx::Error> {
+        self.timed(
+            "backup_passkeys",
+            sqlx::query_as!(
+                BackupPasskey,
+                "SELECT name, data, created FROM passkey WHERE user_id = ? AND deleted_at IS NULL",
+                user_id
+            )
+            .fetch_all(&self.pool),
+        )
+        .await
+    }
+
+    /// Restore backed-up passkeys in one transaction, creating the user when `create_user`
+    /// is given. Credentials already stored, for any user, are skipped. Returns (imported, skipped).
+    pub async fn import_passkeys(
+        &self,
+        user_id: &str,
+        create_user: Option<&str>,
+        passkeys: &[(&BackupPasskey, Passkey)],
+    ) -> Result<(u32, u32), sqlx::Error> {
+        self.timed("import_passkeys", async {
+            let mut tx = self.pool.begin().await?;
+            if let Some(name) = create_user {
+                sqlx::query!("INSERT INTO user (id, name) VALUES (?, ?)", user_id, name)
+                    .execute(&mut *tx)
+                    .await?;
+            }
+            let mut taken = sqlx::query_scalar!(
+                "SELECT name FROM passkey WHERE user_id = ? AND deleted_at IS NULL",
+                user_id
+            )
+            .fetch_all(&mut *tx)
+            .await?;
+            let (mut imported, mut skipped) = (0, 0);
+            // Names are made free first, so the conflict left is `cred_id`: a
+            // credential stored for anyone is skipped.
+            for (row, passkey) in passkeys {
+                let name = available_name(&taken, &row.name);
+                let stored = StoredPasskey::new(passkey)?;
+                let inserted = sqlx::query!(
+                    "INSERT INTO passkey (user_id, name, created, data, cred_id, public_key, \
+                     sign_count, backup_eligible, backup_state, aaguid) \
+                     VALUES (?, ?, ?, ?, ?, ?, ?, ?, ?, ?) ON CONFLICT DO NOTHING",
+                    user_id,
+                    name,
+                    row.created,
+                    stored.data,
+                    stored.cred_id,
+                    stored.public_key,
+                    stored.sign_count,
+                    stored.backup_eligible,
+                    stored.backup_state,
+                    stored.aaguid,
+                )
+                .execute(&mut *tx)
+                .await?
+                .rows_affected();
+                if inserted == 0 {
+                    skipped += 1;
+                } else {
+                    imported += 1;
+                    taken.push(name);
+                }
+            }
+            tx.commit().await?;
+            Ok((imported, skipped))
+        })
+        .await
+    }
+
+    // --- Auth challenges ---
+
+    /// Delete up to `limit` expired challenges, returning how many went.
+    pub async fn purge_expired_challenges(&self, limit: i64) -> Result<u64, sqlx::Error> {
+        self.timed(
+            "purge_expired_challenges",
+            sqlx::query!(
+                "DELETE FROM auth_challenge WHERE id IN (SELECT id FROM auth_challenge \
+                 WHERE expires_at < datetime('now') LIMIT ?)",
+                limit
+            )
+            .execute(&self.pool),
+        )
+        .await
+        .map(|result| result.rows_affected())
+    }
+
+    pub async fn insert_challenge(
+        &self,
+        id: &str,
+        kind: &str,
+        binding: &str,
+        state_json: &str,
+        ttl_minutes: i64,
+    ) -> Result<(), sqlx::Error> {
+        let ttl = format!("+{ttl_minutes} minutes");
+        self.timed(
+            "insert_challenge",
+            sqlx::query!(
+                "INSERT INTO auth_challenge (id, state, kind, binding, expires_at) \
+                 VALUES (?, ?, ?, ?, datetime('now', ?))",
+                id,
+                state_json,
+                kind,
+                binding,
+                ttl,
+            )
+            .execute(&self.pool),
+        )
+        .await
+        .map(|_| ())
+    }
+
+    /// Fetch and delete an unexpired challenge (single-use), returning its state JSON.
+    /// A wrong `binding` leaves the challenge for its owner.
+    pub async fn take_challenge(
+        &self,
+        id: &str,
+        kind: &str,
+        binding: &str,
+    ) -> Result<Option<String>, sqlx::Error> {
+        self.timed(
+            "take_challenge",
+            sqlx::query_scalar!(
+                "DELETE FROM auth_challenge WHERE id = ? AND kind = ? AND binding = ? \
+                 AND expires_at > datetime('now') RETURNING state",
+                id,
+                kind,
+                binding,
+            )
+            .fetch_optional(&self.pool),
+        )
+        .await
+    }
+
+    // --- Passkey invites ---
+
+    pub async fn purge_expired_invites(&self) -> Result<(), sqlx::Error> {
+        self.timed(
+            "purge_expired_invites",
+            sqlx::query!("DELETE FROM passkey_invite WHERE expires_at <= datetime('now')")
+                .execute(&self.pool),
+        )
+        .await
+        .map(|_| ())
+    }
+
+    /// Returns the invite's expiry.
+    pub async fn create_invite(
+        &self,
+        token_hash: &str,
+        user_id: &str,
+        ttl_minutes: i64,
+    ) -> Result<String, sqlx::Error> {
+        let ttl = format!("+{ttl_minutes} minutes");
+        self.timed(
+            "create_invite",
+            sqlx::query_scalar!(
+                "INSERT INTO passkey_invite (token_hash, user_id, expires_at) \
+                 VALUES (?, ?, datetime('now', ?)) RETURNING expires_at",
+                token_hash,
+                user_id,
+                ttl,
+            )
+            .fetch_one(&self.pool),
+        )
+        .await
+    }
+
+    /// Mint one invite per hash in a single transaction, for enrolling several
+    /// security keys in a row.
+    pub async fn create_invites(
+        &self,
+        token_hashes: &[String],
+        user_id: &str,
+        ttl_minutes: i64,
+    ) -> Result<Vec<InviteInfo>, sqlx::Error> {
+        let ttl = format!("+{ttl_minutes} minutes");
+        self.timed("create_invites", async {
+            let mut tx = self.pool.begin().await?;
+            let mut invites = Vec::with_capacity(token_hashes.len());
+            for token_hash in token_hashes {
+                let invite = sqlx::query_as!(
+                    InviteInfo,
+                    r#"INSERT INTO passkey_invite (token_hash, user_id, expires_at)
+                     VALUES (?, ?, datetime('now', ?))
+                     RETURNING rowid AS "id!: i64", created, expires_at"#,
+                    token_hash,
+                    user_id,
+                    ttl,
+                )
+                .fetch_one(&mut *tx)
+                .await?;
+                invites.push(invite);
+            }
+            tx.commit().await?;
+            Ok(invites)
+        })
+        .await
+    }
+
+    pub async fn list_invites(&self, user_id: &str) -> Result<Vec<InviteInfo>, sqlx::Error> {
+        self.timed(
+            "list_invites",
+            sqlx::query_as!(
+                InviteInfo,
+                r#"SELECT rowid AS "id!: i64", created, expires_at FROM passkey_invite
+                 WHERE user_id = ? AND expires_at > datetime('now') ORDER BY rowid"#,
+                user_id
+            )
+            .fetch_all(&self.pool),
+        )
+        .await
+    }
+
+    pub async fn revoke_invite(&self, user_id: &str, id: i64) -> Result<bool, sqlx::Error> {
+        let result = self
+            .timed(
+                "revoke_invite",
+                sqlx::query!(
+                    "DELETE FROM passkey_invite WHERE rowid = ? AND user_id = ?",
+                    id,
+                    user_id,
+                )
+                .execute(&self.pool),
+            )
+            .await?;
+        Ok(result.rows_affected() > 0)
+    }
+
+    pub async fn invite_owner(&self, token_hash: &str) -> Result<Option<String>, sqlx::Error> {
+        self.timed(
+            "invite_owner",
+            sqlx::query_scalar!(
+                "SELECT user_id FROM passkey_invite \
+                 WHERE token_hash = ? AND expires_at > datetime('now')",
+                token_hash
+            )
+            .fetch_optional(&self.pool),
+        )
+        .await
+    }
+
+    /// `insert_passkey`, consuming the invite in the same transaction, so a passkey
+    /// that fails to insert leaves the invite usable. False, with nothing written,
+    /// if the invite is gone or expired.
+    pub async fn insert_invited_passkey(
+        &self,
+        user_id: &str,
+        name: &str,
+        passkey: &Passkey,
+        token_hash: &str,
+    ) -> Result<bool, sqlx::Error> {
+        let stored = StoredPasskey::new(passkey)?;
+        self.timed("insert_invited_passkey", async {
+            let mut tx = self.pool.begin().await?;
+            let consumed = sqlx::query!(
+                "DELETE FROM passkey_invite WHERE token_hash = ? AND user_id = ? \
+                 AND expires_at > datetime('now')",
+                token_hash,
+                user_id,
+            )
+            .execute(&mut *tx)
+            .await?
+            .rows_affected();
+            if consumed == 0 {
+                return Ok(false);
+            }
+            stored.insert(&mut *tx, user_id, name).await?;
+            tx.commit().await?;
+            Ok(true)
+        })
+        .await
+    }
+
+    // --- Login redirect tokens ---
+
+    /// Delete up to `limit` burned `jti`s past their expiry, returning how many went.
+    pub async fn purge_used_redirect_tokens(&self, limit: i64) -> Result<u64, sqlx::Error> {
+        self.timed(
+            "purge_used_redirect_tokens",
+            sqlx::query!(
+                "DELETE FROM used_redirect_token WHERE jti IN (SELECT jti FROM used_redirect_token \
+                 WHERE expires_at < datetime('now') LIMIT ?)",
+                limit
+            )
+            .execute(&self.pool),
+        )
+        .await
+        .map(|result| result.rows_affected())
+    }
+
+    /// Burn a redirect token's `jti` until `until` (unix seconds); false if it was
+    /// already used.
+    pub async fn burn_redirect_token(&self, jti: &str, until: i64) -> Result<bool, sqlx::Error> {
+        let result = self
+            .timed(
+                "burn_redirect_token",
+                sqlx::query!(
+                    "INSERT OR IGNORE INTO used_redirect_token (jti, expires_at) \
+                     VALUES (?, datetime(?, 'unixepoch'))",
+                    jti,
+                    until,
+                )
+                .execute(&self.pool),
+            )
+            .await?;
+        Ok(result.rows_affected() > 0)
+    }
+
+    // --- Seen devices ---
+
+    /// Returns whether the device is new for this user.
+    pub async fn remember_device(
+        &self,
+        user_id: &str,
+        user_agent: &str,
+    ) -> Result<bool, sqlx::Error> {
+        let result = self
+            .timed(
+                "remember_device",
+                sqlx::query!(
+                    "INSERT OR IGNORE INTO seen_device (user_id, user_agent) VALUES (?, ?)",
+                    user_id,
+                    user_agent,
+                )
+                .execute(&self.pool),
+            )
+            .await?;
+        Ok(result.rows_affected() > 0)
+    }
+
+    pub async fn device_count(&self, user_id: &str) -> Result<i64, sqlx::Error> {
+        self.timed(
+            "device_count",
+            sqlx::query_scalar!(
+                "SELECT COUNT(*) FROM seen_device WHERE user_id = ?",
+                user_id
+            )
+            .fetch_one(&self.pool),
+        )
+        .await
+    }
+
+    // --- TOTP ---
+
+    pub async fn confirmed_totp(&self) -> Result<Option<ConfirmedTotp>, sqlx::Error> {
+        self.timed(
+            "confirmed_totp",
+            sqlx::query_as!(
+                ConfirmedTotp,
+                r#"SELECT user_id AS "user_id!", secret, last_step FROM totp_secret
+                 WHERE confirmed = 1 LIMIT 1"#
+            )
+            .fetch_optional(&self.pool),
+        )
+        .await
+    }
+
+    /// Advance `last_step` to `step`; false when the step was already used (replay).
+    pub async fn consume_totp_step(&self, user_id: &str, step: i64) -> Result<bool, sqlx::Error> {
+        let result = self
+            .timed(
+                "consume_totp_step",
+                sqlx::query!(
+                    "UPDATE totp_secret SET last_step = ?1 \
+                     WHERE user_id = ?2 AND (last_step IS NULL OR last_step < ?1)",
+                    step,
+                    user_id,
+                )
+                .execute(&self.pool),
+            )
+            .await?;
+        Ok(result.rows_affected() > 0)
+    }
+
+    pub async fn totp_enrolled(&self, user_id: &str) -> Result<bool, sqlx::Error> {
+        self.timed(
+            "totp_enrolled",
+            sqlx::query_scalar!(
+                r#"SELECT EXISTS(SELECT 1 FROM totp_secret WHERE user_id = ? AND confirmed = 1)
+                 AS "enrolled!: bool""#,
+                user_id
+            )
+            .fetch_one(&self.pool),
+        )
+        .await
+    }
+
+    /// Store a new unconfirmed secret, replacing any previous enrollment.
+    pub async fn start_totp_enrollment(
+        &self,
+        user_id: &str,
+        secret: &[u8],
+    ) -> Result<(), sqlx::Error> {
+        self.timed(
+            "start_totp_enrollment",
+            sqlx::query!(
+                "INSERT INTO totp_secret (user_id, secret) VALUES (?, ?) \
+                 ON CONFLICT (user_id) DO UPDATE SET secret = excluded.secret, confirmed = 0, \
+                 last_step = NULL, created = datetime('now')",
+                user_id,
+                secret,
+            )
+            .execute(&self.pool),
+        )
+        .await
+        .map(|_| ())
+    }
+
+    pub async fn pending_totp_secret(&self, user_id: &str) -> Result<Option<Vec<u8>>, sqlx::Error> {
+        self.timed(
+            "pending_totp_secret",
+            sqlx::query_scalar!(
+                "SELECT secret FROM totp_secret WHERE user_id = ? AND confirmed = 0",
+                user_id
+            )
+            .fetch_optional(&self.pool),
+        )
+        .await
+    }
+
+    pub async fn confirm_totp(&self, user_id: &str, step: i64) -> Result<(), sqlx::Error> {
+        self.timed(
+            "confirm_totp",
+            sqlx::query!(
+                "UPDATE totp_secret SET confirmed = 1, last_step = ? WHERE user_id = ?",
+                step,
+                user_id,
+            )
+            .execute(&self.pool),
+        )
+        .await
+        .map(|_| ())
+    }
+
+    pub async fn delete_totp(&self, user_id: &str) -> Result<(), sqlx::Error> {
+        self.timed(
+            "delete_totp",
+            sqlx::query!("DELETE FROM totp_secret WHERE user_id = ?", user_id).execute(&self.pool),
+        )
+        .await
+        .map(|_| ())
+    }
+
+    // --- Sessions (token handling lives in `session`) ---
+
+    #[allow(clippy::too_many_arguments)]
+    pub async fn create_session(
+        &self,
+        id: &str,
+        user_id: &str,
+        token_hash: &str,
+        ttl_seconds: i64,
+        user_agent: Option<&str>,
+        ip: Option<&str>,
+        country: Option<&str>,
+        origin: Option<&str>,
+    ) -> Result<(), sqlx::Error> {
+        self.timed(
+            "create_session",
+            sqlx::query!(
+                "INSERT INTO session (id, user_id, token_hash, ttl_seconds, expires_at, \
+                 user_agent, ip, country, origin) \
+                 VALUES (?1, ?2, ?3, ?4, datetime('now', ?4 || ' seconds'), ?5, ?6, ?7, ?8)",
+                id,
+                user_id,
+                token_hash,
+                ttl_seconds,
+                user_agent,
+                ip,
+                country,
+                origin,
+            )
+            .execute(&self.pool),
+        )
+        .await
+        .map(|_| ())
+    }
+
+    /// Swap the live session's `presented` token hash for `fresh`, returning its
+    /// `(id, user_id, ttl_seconds)`.
+    pub async fn rotate_session(
+        &self,
+        presented: &str,
+        fresh: &str,
+    ) -> Result<Option<(String, String, i64)>, sqlx::Error> {
+        let row = self
+            .timed(
+                "rotate_session",
+                sqlx::query!(
+                    r#"UPDATE session SET previous_hash = token_hash, token_hash = ?,
+                     last_used = datetime('now'), expires_at = datetime('now', ttl_seconds || ' seconds')
+                     WHERE token_hash = ? AND revoked_at IS NULL AND expires_at > datetime('now')
+                     RETURNING id AS "id!", user_id, ttl_seconds"#,
+                    fresh,
+                    presented,
+                )
+                .fetch_optional(&self.pool),
+            )
+            .await?;
+        Ok(row.map(|row| (row.id, row.user_id, row.ttl_seconds)))
+    }
+
+    /// Revoke the session that rotated `previous_hash` away more than
+    /// `grace_seconds` ago, returning its id.
+    pub async fn revoke_reused_session(
+        &self,
+        previous_hash: &str,
+        grace_seconds: i64,
+    ) -> Result<Option<String>, sqlx::Error> {
+        let since = -grace_seconds;
+        self.timed(
+            "revoke_reused_session",
+            sqlx::query_scalar!(
+                r#"UPDATE session SET revoked_at = datetime('now')
+                 WHERE previous_hash = ? AND revoked_at IS NULL
+                 AND last_used < datetime('now', ? || ' seconds') RETURNING id AS "id!""#,
+                previous_hash,
+                since,
+            )
+            .fetch_optional(&self.pool),
+        )
+        .await
+    }
+
+    /// Owner of a live (unrevoked, unexpired) session.
+    pub async fn live_session_user(&self, id: &str) -> Result<Option<String>, sqlx::Error> {
+        self.timed(
+            "live_session_user",
+            sqlx::query_scalar!(
+                "SELECT user_id FROM session \
+                 WHERE id = ? AND revoked_at IS NULL AND expires_at > datetime('now')",
+                id
+            )
+            .fetch_optional(&self.pool),
+        )
+        .await
+    }
+
+    /// Revoke the session with `token_hash`, returning its `(id, user_id)` if it was live.
+    pub async fn revoke_session_by_token(
+        &self,
+        token_hash: &str,
+    ) -> Result<Option<(String, String)>, sqlx::Error> {
+        let row = self
+            .timed(
+                "revoke_session_by_token",
+                sqlx::query!(
+                    r#"UPDATE session SET revoked_at = datetime('now')
+                     WHERE token_hash = ? AND revoked_at IS NULL RETURNING id AS "id!", user_id"#,
+                    token_hash
+                )
+                .fetch_optional(&self.pool),
+            )
+            .await?;
+        Ok(row.map(|row| (row.id, row.user_id)))
+    }
+
+    /// End every live session of `user_id`, returning how many there were.
+    pub async fn revoke_user_sessions(&self, user_id: &str) -> Result<u64, sqlx::Error> {
+        let result = self
+            .timed(
+                "revoke_user_sessions",
+                sqlx::query!(
+                    "UPDATE session SET revoked_at = datetime('now') \
+                     WHERE user_id = ? AND revoked_at IS NULL",
+                    user_id
+                )
+                .execute(&self.pool),
+            )
+            .await?;
+        Ok(result.rows_affected())
+    }
+
+    /// Revoke `user_id`'s live sessions opened on `origin`, returning their ids.
+    pub async fn revoke_origin_sessions(
+        &self,
+        user_id: &str,
+        origin: &str,
+    ) -> Result<Vec<String>, sqlx::Error> {
+        self.timed(
+            "revoke_origin_sessions",
+            sqlx::query_scalar!(
+                r#"UPDATE session SET revoked_at = datetime('now')
+                 WHERE user_id = ? AND origin = ? AND revoked_at IS NULL RETURNING id AS "id!""#,
+                user_id,
+                origin,
+            )
+            .fetch_all(&self.pool),
+        )
+        .await
+    }
+
+    pub async fn list_sessions(&self, user_id: &str) -> Result<Vec<SessionRecord>, sqlx::Error> {
+        self.timed(
+            "list_sessions",
+            sqlx::query_as!(
+                SessionRecord,
+                r#"SELECT id AS "id!", name, user_agent, ip, country, created, last_used
+                 FROM session
+                 WHERE user_id = ? AND revoked_at IS NULL AND expires_at > datetime('now')
+                 ORDER BY last_used DESC"#,
+                user_id
+            )
+            .fetch_all(&self.pool),
+        )
+        .await
+    }
+
+    pub async fn rename_session(
+        &self,
+        user_id: &str,
+        id: &str,
+        name: Option<&str>,
+    ) -> Result<bool, sqlx::Error> {
+        let result = self
+            .timed(
+                "rename_session",
+                sqlx::query!(
+                    "UPDATE session SET name = ? WHERE id = ? AND user_id = ? AND revoked_at IS NULL",
+                    name,
+                    id,
+                    user_id,
+                )
+                .execute(&self.pool),
+            )
+            .await?;
+        Ok(result.rows_affected() > 0)
+    }
+
+    pub async fn revoke_session(&self, user_id: &str, id: &str) -> Result<bool, sqlx::Error> {
+        let result = self
+            .timed(
+                "revoke_session",
+                sqlx::query!(
+                    "UPDATE session SET revoked_at = datetime('now') \
+                     WHERE id = ? AND user_id = ? AND revoked_at IS NULL",
+                    id,
+                    user_id,
+                )
+                .execute(&self.pool),
+            )
+            .await?;
+        Ok(result.rows_affected() > 0)
+    }
+
+    pub async fn active_session_count(&self) -> Result<i64, sqlx::Error> {
+        self.timed(
+            "active_session_count",
+            sqlx::query_scalar!(
+                "SELECT COUNT(*) FROM session \
+                 WHERE revoked_at IS NULL AND expires_at > datetime('now')"
+            )
+            .fetch_one(&self.pool),
+        )
+        .await
+    }
+
+    /// Sessions opened or refreshed since midnight UTC.
+    pub async fn sessions_used_today(&self) -> Result<i64, sqlx::Error> {
+        self.timed(
+            "sessions_used_today",
+            sqlx::query_scalar!("SELECT COUNT(*) FROM session WHERE last_used >= date('now')")
+                .fetch_one(&self.pool),
+        )
+        .await
+    }
+
+    /// Drop sessions that ended over a day ago.
+    pub async fn purge_ended_sessions(&self) -> Result<(), sqlx::Error> {
+        self.timed(
+            "purge_ended_sessions",
+            sqlx::query!(
+                "DELETE FROM session WHERE expires_at < datetime('now', '-1 day') \
+                 OR revoked_at < datetime('now', '-1 day')"
+            )
+            .execute(&self.pool),
+        )
+        .await
+        .map(|_| ())
+    }
+
+    // --- Sub-accounts (checks live in `policy`) ---
+
+    /// The origins a sub-account may reach, or `None` for any other user.
+    pub async fn sub_account_apps(
+        &self,
+        user_id: &str,
+    ) -> Result<Option<Vec<String>>, sqlx::Error> {
+        self.timed("sub_account_apps", async {
+            let parent = sqlx::query_scalar!("SELECT parent_id FROM user WHERE id = ?", user_id)
+                .fetch_optional(&self.pool)
+                .await?;
+            if parent.flatten().is_none() {
+                return Ok(None);
+            }
+            sqlx::query_scalar!(
+                "SELECT origin FROM sub_account_app WHERE user_id = ? ORDER BY origin",
+                user_id
+            )
+            .fetch_all(&self.pool)
+            .await
+            .map(Some)
+        })
+        .await
+    }
+
+    pub async fn is_sub_account_of(&self, parent_id: &str, id: &str) -> Result<bool, sqlx::Error> {
+        self.timed(
+            "is_sub_account_of",
+            sub_account_of(&self.pool, parent_id, id),
+        )
+        .await
+    }
+
+    pub async fn list_sub_accounts(
+        &self,
+        parent_id: &str,
+    ) -> Result<Vec<SubAccountInfo>, sqlx::Error> {
+        let rows = self
+            .timed(
+                "list_sub_accounts",
+                sqlx::query!(
+                    r#"SELECT id AS "id!", name, created,
+                       (SELECT json_group_array(origin) FROM sub_account_app
+                         WHERE user_id = user.id) AS "apps!: String",
+                       (SELECT COUNT(*) FROM passkey
+                         WHERE user_id = user.id AND deleted_at IS NULL) AS "passkeys!: i64"
+                     FROM user WHERE parent_id = ? ORDER BY created, name"#,
+                    parent_id
+                )
+                .fetch_all(&self.pool),
+            )
+            .await?;
+        rows.into_iter()
+            .map(|row| {
+                let mut apps: Vec<String> =
+                    serde_json::from_str(&row.apps).map_err(|e| sqlx::Error::Decode(e.into()))?;
+                apps.sort();
+                Ok(SubAccountInfo {
+                    id: row.id,
+                    name: row.name,
+                    created: row.created,
+                    apps,
+                    passkeys: row.passkeys,
+                })
+            })
+            .collect()
+    }
+
+    /// False, with nothing written, when any user already has the name (ignoring
+    /// ASCII case), so forward-auth's user header stays unambiguous.
+    pub async fn create_sub_account(
+        &self,
+        id: &str,
+        name: &str,
+        parent_id: &str,
+        apps: &[String],
+    ) -> Result<bool, sqlx::Error> {
+        self.timed("create_sub_account", async {
+            let mut tx = self.pool.begin().await?;
+            let created = sqlx::query!(
+                "INSERT INTO user (id, name, parent_id) SELECT ?1, ?2, ?3 \
+                 WHERE NOT EXISTS (SELECT 1 FROM user WHERE name = ?2 COLLATE NOCASE)",
+                id,
+                name,
+                parent_id,
+            )
+            .execute(&mut *tx)
+            .await?
+            .rows_affected();
+            if created == 0 {
+                return Ok(false);
+            }
+            for origin in apps {
+                sqlx::query!(
+                    "INSERT OR IGNORE INTO sub_account_app (user_id, origin) VALUES (?, ?)",
+                    id,
+                    origin,
+                )
+                .execute(&mut *tx)
+                .await?;
+            }
+            tx.commit().await?;
+            Ok(true)
+        })
+        .await
+    }
+
+    /// Replace a sub-account's origins; false when `id` is not one of `parent_id`'s.
+    pub async fn set_sub_account_apps(
+        &self,
+        parent_id: &str,
+        id: &str,
+        apps: &[String],
+    ) -> Result<bool, sqlx::Error> {
+        self.timed("set_sub_account_apps", async {
+            let mut tx = self.pool.begin().await?;
+            if !sub_account_of(&mut *tx, parent_id, id).await? {
+                return Ok(false);
+            }
+            sqlx::query!("DELETE FROM sub_account_app WHERE user_id = ?", id)
+                .execute(&mut *tx)
+                .await?;
+            for origin in apps {
+                sqlx::query!(
+                    "INSERT OR IGNORE INTO sub_account_app (user_id, origin) VALUES (?, ?)",
+                    id,
+                    origin,
+                )
+                .execute(&mut *tx)
+                .await?;
+            }
+            tx.commit().await?;
+            Ok(true)
+        })
+        .await
+    }
+
+    /// Delete a sub-account with its passkeys, invites and everything else kept
+    /// for it; false when `id` is not one of `parent_id`'s. Its sessions must be
+    /// revoked first when they live in Redis.
+    pub async fn delete_sub_account(&self, parent_id: &str, id: &str) -> Result<bool, sqlx::Error> {
+        self.timed("delete_sub_account", async {
+            let mut tx = self.pool.begin().await?;
+            if !sub_account_of(&mut *tx, parent_id, id).await? {
+                return Ok(false);
+            }
+            sqlx::query!("DELETE FROM sub_account_app WHERE user_id = ?", id)
+                .execute(&mut *tx)
+                .await?;
+            sqlx::query!("DELETE FROM passkey WHERE user_id = ?", id)
+                .execute(&mut *tx)
+                .await?;
+            sqlx::query!("DELETE FROM passkey_invite WHERE user_id = ?", id)
+                .execute(&mut *tx)
+                .await?;
+            sqlx::query!("DELETE FROM session WHERE user_id = ?", id)
+                .execute(&mut *tx)
+                .await?;
+            sqlx::query!("DELETE FROM seen_device WHERE user_id = ?", id)
+                .execute(&mut *tx)
+                .await?;
+            sqlx::query!("DELETE FROM login_context WHERE user_id = ?", id)
+                .execute(&mut *tx)
+                .await?;
+            sqlx::query!("DELETE FROM totp_secret WHERE user_id = ?", id)
+                .execute(&mut *tx)
+                .await?;
+            sqlx::query!("DELETE FROM recovery_code WHERE user_id = ?", id)
+                .execute(&mut *tx)
+                .await?;
+            sqlx::query!("DELETE FROM recovery_request WHERE user_id = ?", id)
+                .execute(&mut *tx)
+                .await?;
+            sqlx::query!("DELETE FROM user WHERE id = ?", id)
+                .execute(&mut *tx)
+                .await?;
+            tx.commit().await?;
+            Ok(true)
+        })
+        .await
+    }
+
+    // --- Service accounts (token checks live in `service_account`) ---
+
+    /// Resolve an unexpired account by token hash, marking it used, as
+    /// `(id, name, scopes)`.
+    pub async fn use_service_account(
+        &self,
+        token_hash: &str,
+    ) -> Result<Option<(String, String, String)>, sqlx::Error> {
+        let row = self
+            .timed(
+                "use_service_account",
+                sqlx::query!(
+                    r#"UPDATE service_account SET last_used = datetime('now')
+                     WHERE token_hash = ?
+                     AND (expires_at IS NULL OR expires_at > datetime('now'))
+                     RETURNING id AS "id!", name, scopes"#,
+                    token_hash
+                )
+                .fetch_optional(&self.pool),
+            )
+            .await?;
+        Ok(row.map(|row| (row.id, row.name, row.scopes)))
+    }
+
+    pub async fn list_service_accounts(&self) -> Result<Vec<ServiceAccountInfo>, sqlx::Error> {
+        self.timed(
+            "list_service_accounts",
+            sqlx::query_as!(
+                ServiceAccountInfo,
+                r#"SELECT id AS "id!", name, scopes, created, expires_at, last_used
+                 FROM service_account ORDER BY created"#
+            )
+            .fetch_all(&self.pool),
+        )
+        .await
+    }
+
+    /// Returns the account's expiry; a taken name is a unique violation.
+    pub async fn create_service_account(
+        &self,
+        id: &str,
+        name: &str,
+        scopes: &str,
+        token_hash: &str,
+        expires_in_days: Option<u32>,
+    ) -> Result<Option<String>, sqlx::Error> {
+        let expires = expires_in_days.map(|days| format!("+{days} days"));
+        self.timed(
+            "create_service_account",
+            sqlx::query_scalar!(
+                "INSERT INTO service_account (id, name, scopes, token_hash, expires_at) \
+                 VALUES (?1, ?2, ?3, ?4, \
+                   CASE WHEN ?5 IS NULL THEN NULL ELSE datetime('now', ?5) END) \
+                 RETURNING expires_at",
+                id,
+                name,
+                scopes,
+                token_hash,
+                expires,
+            )
+            .fetch_one(&self.pool),
+        )
+        .await
+    }
+
+    pub async fn delete_service_account(&self, id: &str) -> Result<bool, sqlx::Error> {
+        let result = self
+            .timed(
+                "delete_service_account",
+                sqlx::query!("DELETE FROM service_account WHERE id = ?", id).execute(&self.pool),
+            )
+            .await?;
+        Ok(result.rows_affected() > 0)
+    }
+
+    // --- Audit log (best-effort handling lives in `audit`) ---
+
+    #[allow(clippy::too_many_arguments)]
+    pub async fn insert_audit_event(
+        &self,
+        kind: &str,
+        user_id: Option<&str>,
+        ip: Option<&str>,
+        country: Option<&str>,
+        user_agent: Option<&str>,
+        detail: Option<&str>,
+        app: Option<&str>,
+    ) -> Result<(), sqlx::Error> {
+        self.timed(
+            "insert_audit_event",
+            sqlx::query!(
+                "INSERT INTO audit_event (kind, user_id, ip, country, user_agent, detail, app) \
+                 VALUES (?, ?, ?, ?, ?, ?, ?)",
+                kind,
+                user_id,
+                ip,
+                country,
+                user_agent,
+                detail,
+                app,
+            )
+            .execute(&self.pool),
+        )
+        .await
+        .map(|_| ())
+    }
+
+    /// `user_id`'s login events and the ones without a user, newest first, with ids
+    /// below `before`.
+    pub async fn login_history(
+        &self,
+        user_id: &str,
+        before: i64,
+        limit: i64,
+    ) -> Result<Vec<LoginRecord>, sqlx::Error> {
+        self.timed(
+            "login_history",
+            sqlx::query_as!(
+                LoginRecord,
+                r#"SELECT id AS "id!", kind, created, ip, country, user_agent, detail, app
+                 FROM audit_event WHERE (user_id = ? OR user_id IS NULL) AND id < ?
+                 AND kind IN ('login', 'login_failed', 'login_anomaly')
+                 ORDER BY id DESC LIMIT ?"#,
+                user_id,
+                before,
+                limit,
+            )
+            .fetch_all(&self.pool),
+        )
+        .await
+    }
+
+    /// Seconds until the failed sign-in from `ip` that is `offset` places back in the
+    /// last `window_minutes` ages out of that window.
+    pub async fn failed_login_age_out(
+        &self,
+        ip: &str,
+        window_minutes: u32,
+        offset: i64,
+    ) -> Result<Option<i64>, sqlx::Error> {
+        let ahead = format!("+{window_minutes} minutes");
+        let since = format!("-{window_minutes} minutes");
+        self.timed(
+            "failed_login_age_out",
+            sqlx::query_scalar!(
+                r#"SELECT CAST(strftime('%s', created, ?) AS INTEGER)
+                 - CAST(strftime('%s', 'now') AS INTEGER) AS "seconds!: i64" FROM audit_event
+                 WHERE kind = 'login_failed' AND ip = ? AND created > datetime('now', ?)
+                 ORDER BY id DESC LIMIT 1 OFFSET ?"#,
+                ahead,
+                ip,
+                since,
+                offset,
+            )
+            .fetch_optional(&self.pool),
+        )
+        .await
+    }
+
+    /// Client IPs that hit a honeypot within the last `block_minutes`, with the
+    /// seconds until their latest hit ages out.
+    pub async fn honeypot_hits(
+        &self,
+        block_minutes: u32,
+    ) -> Result<Vec<(String, i64)>, sqlx::Error> {
+        let ahead = format!("+{block_minutes} minutes");
+        let since = format!("-{block_minutes} minutes");
+        let rows = self
+            .timed(
+                "honeypot_hits",
+                sqlx::query!(
+                    r#"SELECT ip AS "ip!", CAST(strftime('%s', MAX(created), ?) AS INTEGER)
+                     - CAST(strftime('%s', 'now') AS INTEGER) AS "seconds!: i64" FROM audit_event
+                     WHERE kind = 'honeypot' AND ip IS NOT NULL AND created > datetime('now', ?)
+                     GROUP BY ip"#,
+                    ahead,
+                    since,
+                )
+                .fetch_all(&self.pool),
+            )
+            .await?;
+        Ok(rows.into_iter().map(|row| (row.ip, row.seconds)).collect())
+    }
+
+    /// Client IPs with at least `max_failures` failed sign-ins in the last
+    /// `window_minutes`.
+    pub async fn throttled_clients(
+        &self,
+        window_minutes: u32,
+        max_failures: u32,
+    ) -> Result<i64, sqlx::Error> {
+        let since = format!("-{window_minutes} minutes");
+        self.timed(
+            "throttled_clients",
+            sqlx::query_scalar!(
+                "SELECT COUNT(*) FROM (SELECT ip FROM audit_event \
+                 WHERE kind = 'login_failed' AND ip IS NOT NULL AND created > datetime('now', ?) \
+                 GROUP BY ip HAVING COUNT(*) >= ?)",
+                since,
+                max_failures,
+            )
+            .fetch_one(&self.pool),
+        )
+        .await
+    }
+
+    // --- Login context (scoring lives in `anomaly`) ---
+
+    /// Every `(kind, value, count)` seen on `user_id`'s logins.
+    pub async fn login_context(
+        &self,
+        user_id: &str,
+    ) -> Result<Vec<(String, String, i64)>, sqlx::Error> {
+        let rows = self
+            .timed(
+                "login_context",
+                sqlx::query!(
+                    "SELECT kind, value, count FROM login_context WHERE user_id = ?",
+                    user_id
+                )
+                .fetch_all(&self.pool),
+            )
+            .await?;
+        Ok(rows
+            .into_iter()
+            .map(|row| (row.kind, row.value, row.count))
+            .collect())
+    }
+
+    pub async fn remember_login_context(
+        &self,
+        user_id: &str,
+        kind: &str,
+        value: &str,
+    ) -> Result<(), sqlx::Error> {
+        self.timed(
+            "remember_login_context",
+            sqlx::query!(
+                "INSERT INTO login_context (user_id, kind, value) VALUES (?, ?, ?) \
+                 ON CONFLICT (user_id, kind, value) \
+                 DO UPDATE SET count = count + 1, last_seen = datetime('now')",
+                user_id,
+                kind,
+                value,
+            )
+            .execute(&self.pool),
+        )
+        .await
+        .map(|_| ())
+    }
+
+    // --- Connected apps ---
+
+    /// Note that `user_id` redeemed a redirect token on `origin`.
+    pub async fn record_connected_app(
+        &self,
+        user_id: &str,
+        origin: &str,
+    ) -> Result<(), sqlx::Error> {
+        self.timed(
+            "record_connected_app",
+            sqlx::query!(
+                "INSERT INTO connected_app (user_id, origin) VALUES (?, ?) \
+                 ON CONFLICT (user_id, origin) DO UPDATE SET \
+                 last_used = datetime('now'), count = count + 1",
+                user_id,
+                origin,
+            )
+            .execute(&self.pool),
+        )
+        .await
+        .map(|_| ())
+    }
+
+    /// Most recently used first.
+    pub async fn connected_apps(&self, user_id: &str) -> Result<Vec<ConnectedApp>, sqlx::Error> {
+        self.timed(
+            "connected_apps",
+            sqlx::query_as!(
+                ConnectedApp,
+                "SELECT origin, first_used, last_used, count FROM connected_app \
+                 WHERE user_id = ? ORDER BY last_used DESC, origin",
+                user_id
+            )
+            .fetch_all(&self.pool),
+        )
+        .await
+    }
+
+    /// False if `user_id` never connected to `origin`.
+    pub async fn remove_connected_app(
+        &self,
+        user_id: &str,
+        origin: &str,
+    ) -> Result<bool, sqlx::Error> {
+        let result = self
+            .timed(
+                "remove_connected_app",
+                sqlx::query!(
+                    "DELETE FROM connected_app WHERE user_id = ? AND origin = ?",
+                    user_id,
+                    origin,
+                )
+                .execute(&self.pool),
+            )
+            .await?;
+        Ok(result.rows_affected() > 0)
+    }
+
+    // --- Account recovery (hashed recovery URLs and pending time-delayed resets) ---
+
+    /// Store a new recovery URL hash for `user_id`, replacing the old one.
+    pub async fn set_recovery_code(
+        &self,
+        user_id: &str,
+        token_hash: &str,
+    ) -> Result<(), sqlx::Error> {
+        self.timed(
+            "set_recovery_code",
+            sqlx::query!(
+                "INSERT INTO recovery_code (user_id, token_hash) VALUES (?, ?) \
+                 ON CONFLICT (user_id) DO UPDATE SET \
+                 token_hash = excluded.token_hash, created = datetime('now')",
+                user_id,
+                token_hash,
+            )
+            .execute(&self.pool),
+        )
+        .await
+        .map(|_| ())
+    }
+
+    pub async fn has_recovery_code(&self, user_id: &str) -> Result<bool, sqlx::Error> {
+        self.timed(
+            "has_recovery_code",
+            sqlx::query_scalar!(
+                r#"SELECT EXISTS(SELECT 1 FROM recovery_code WHERE user_id = ?) AS "found!: bool""#,
+                user_id
+            )
+            .fetch_one(&self.pool),
+        )
+        .await
+    }
+
+    pub async fn recovery_code_owner(
+        &self,
+        token_hash: &str,
+    ) -> Result<Option<String>, sqlx::Error> {
+        self.timed(
+            "recovery_code_owner",
+            sqlx::query_scalar!(
+                r#"SELECT user_id AS "user_id!" FROM recovery_code WHERE token_hash = ?"#,
+                token_hash
+            )
+            .fetch_optional(&self.pool),
+        )
+        .await
+    }
+
+    /// Start the recovery delay for `user_id`, unless a request is already pending
+    /// (asking again never restarts or shortens the clock). True when this call
+    /// started it.
+    pub async fn request_recovery(
+        &self,
+        user_id: &str,
+        delay_hours: u32,
+    ) -> Result<bool, sqlx::Error> {
+        let delay = format!("+{delay_hours} hours");
+        let result = self
+            .timed(
+                "request_recovery",
+                sqlx::query!(
+                    "INSERT INTO recovery_request (user_id, ready_at) \
+                     VALUES (?, datetime('now', ?)) ON CONFLICT (user_id) DO NOTHING",
+                    user_id,
+                    delay,
+                )
+                .execute(&self.pool),
+            )
+            .await?;
+        Ok(result.rows_affected() > 0)
+    }
+
+    pub async fn pending_recovery(
+        &self,
+        user_id: &str,
+    ) -> Result<Option<PendingRecovery>, sqlx::Error> {
+        self.timed(
+            "pending_recovery",
+            sqlx::query_as!(
+                PendingRecovery,
+                r#"SELECT requested_at, ready_at, ready_at <= datetime('now') AS "ready!: bool"
+                 FROM recovery_request WHERE user_id = ?"#,
+                user_id
+            )
+            .fetch_optional(&self.pool),
+        )
+        .await
+    }
+
+    /// Drop the pending request and the recovery URL with it: whoever asked may
+    /// still hold the URL. False (and nothing changed) if nothing was pending.
+    pub async fn cancel_recovery(&self, user_id: &str) -> Result<bool, sqlx::Error> {
+        self.timed("cancel_recovery", async {
+            let mut tx = self.pool.begin().await?;
+            let request = sqlx::query!("DELETE FROM recovery_request WHERE user_id = ?", user_id)
+                .execute(&mut *tx)
+                .await?;
+            if request.rows_affected() == 0 {
+                return Ok(false);
+            }
+            sqlx::query!("DELETE FROM recovery_code WHERE user_id = ?", user_id)
+                .execute(&mut *tx)
+                .await?;
+            tx.commit().await?;
+            Ok(true)
+        })
+        .await
+    }
+
+    /// Use up a ready request together with the recovery URL that asked for it.
+    /// False (and nothing changed) unless both exist and the delay has passed.
+    pub async fn complete_recovery(
+        &self,
+        user_id: &str,
+        token_hash: &str,
+    ) -> Result<bool, sqlx::Error> {
+        self.timed("complete_recovery", async {
+            let mut tx = self.pool.begin().await?;
+            let request = sqlx::query!(
+                "DELETE FROM recovery_request WHERE user_id = ? AND ready_at <= datetime('now')",
+                user_id
+            )
+            .execute(&mut *tx)
+            .await?;
+            let code = sqlx::query!(
+                "DELETE FROM recovery_code WHERE user_id = ? AND token_hash = ?",
+                user_id,
+                token_hash,
+            )
+            .execute(&mut *tx)
+            .await?;
+            if request.rows_affected() == 0 || code.rows_affected() == 0 {
+                return Ok(false);
+            }
+            tx.commit().await?;
+            Ok(true)
+        })
+        .await
+    }
+
+    // --- Usage counters (retention and the summary live in `usage`) ---
+
+    /// Count a login redirect to `origin` today.
+    pub async fn count_redirect(&self, origin: &str) -> Result<(), sqlx::Error> {
+        self.timed(
+            "count_redirect",
+            sqlx::query!(
+                "INSERT INTO usage_daily (day, metric, app, count) \
+                 VALUES (date('now'), 'redirects', ?, 1) \
+                 ON CONFLICT (day, metric, app) DO UPDATE SET count = count + 1",
+                origin
+            )
+            .execute(&self.pool),
+        )
+        .await
+        .map(|_| ())
+    }
+
+    /// Raise today's session count to `used_today`. Sessions only ever move their
+    /// last use forward, so the day's highest reading is its count.
+    pub async fn record_session_usage(&self, used_today: i64) -> Result<(), sqlx::Error> {
+        self.timed(
+            "record_session_usage",
+            sqlx::query!(
+                "INSERT INTO usage_daily (day, metric, count) VALUES (date('now'), 'sessions', ?) \
+                 ON CONFLICT (day, metric, app) DO UPDATE SET count = MAX(count, excluded.count)",
+                used_today
+            )
+            .execute(&self.pool),
+        )
+        .await
+        .map(|_| ())
+    }
+
+    /// Drop counters older than `days` days.
+    pub async fn purge_usage(&self, days: u32) -> Result<u64, sqlx::Error> {
+        let cutoff = format!("-{days} days");
+        let result = self
+            .timed(
+                "purge_usage",
+                sqlx::query!("DELETE FROM usage_daily WHERE day < date('now', ?)", cutoff)
+                    .execute(&self.pool),
+            )
+            .await?;
+        Ok(result.rows_affected())
+    }
+
+    /// Per-day totals since `since` (a `date()` modifier), newest first.
+    pub async fn usage_days(&self, since: &str) -> Result<Vec<UsageDay>, sqlx::Error> {
+        self.timed(
+            "usage_days",
+            sqlx::query_as!(
+                UsageDay,
+                r#"SELECT day,
+                 COALESCE(SUM(CASE WHEN metric = 'sessions' THEN count END), 0) AS "active_sessions!: i64",
+                 COALESCE(SUM(CASE WHEN metric = 'redirects' THEN count END), 0) AS "redirects!: i64"
+                 FROM usage_daily WHERE day >= date('now', ?) GROUP BY day ORDER BY day DESC"#,
+                since
+            )
+            .fetch_all(&self.pool),
+        )
+        .await
+    }
+
+    /// Redirects per origin since `since` (a `date()` modifier), most used first.
+    pub async fn usage_apps(&self, since: &str) -> Result<Vec<AppUsage>, sqlx::Error> {
+        self.timed(
+            "usage_apps",
+            sqlx::query_as!(
+                AppUsage,
+                r#"SELECT app AS "app!", SUM(count) AS "redirects!: i64", MAX(day) AS "last_day!: String"
+                 FROM usage_daily
+                 WHERE metric = 'redirects' AND day >= date('now', ?)
+                 GROUP BY app ORDER BY SUM(count) DESC, app"#,
+                since
+            )
+            .fetch_all(&self.pool),
+        )
+        .await
+    }
+
+    // --- Stats ---
+
+    pub async fn event_counts(&self) -> Result<EventCounts, sqlx::Error> {
+        self.timed(
+            "event_counts",
+            sqlx::query_as!(
+                EventCounts,
+                r#"SELECT
+                   COALESCE(SUM(kind = 'login' AND created > datetime('now', '-1 day')), 0)
+                     AS "logins_24h!: i64",
+                   COALESCE(SUM(kind = 'login'), 0) AS "logins_7d!: i64",
+                   COALESCE(SUM(kind = 'login_failed' AND created > datetime('now', '-1 day')), 0)
+                     AS "failures_24h!: i64",
+                   COALESCE(SUM(kind = 'login_failed'), 0) AS "failures_7d!: i64"
+                 FROM audit_event WHERE created > datetime('now', '-7 days')"#
+            )
+            .fetch_one(&self.pool),
+        )
+        .await
+    }
+
+    pub async fn migration_status(&self) -> Result<MigrationStatus, sqlx::Error> {
+        let recorded: Vec<(i64, bool)> = self
+            .timed(
+                "migration_status",
+                sqlx::query!(
+                    r#"SELECT version AS "version!", success AS "success: bool"
+                     FROM _sqlx_migrations ORDER BY version"#
+                )
+                .fetch_all(&self.pool),
+            )
+            .await?
+            .into_iter()
+            .map(|row| (row.version, row.success))
+            .collect();
+        let pending = sqlx::migrate!()
+            .iter()
+            .map(|m| m.version)
+            .filter(|version| !recorded.iter().any(|(v, _)| v == version))
+            .collect();
+        Ok(MigrationStatus {
+            latest_applied: recorded.iter().filter(|(_, ok)| *ok).map(|(v, _)| *v).max(),
+            applied: recorded.iter().filter(|(_, ok)| *ok).count(),
+            pending,
+            failed: recorded
+                .iter()
+                .filter(|(_, ok)| !ok)
+                .map(|(v, _)| *v)
+                .collect(),
+        })
+    }
+
+    pub async fn sqlite_version(&self) -> Result<String, sqlx::Error> {
+        self.timed(
+            "sqlite_version",
+            sqlx::query_scalar!(r#"SELECT sqlite_version() AS "version!: String""#)
+                .fetch_one(&self.pool),
+        )
+        .await
+    }
+
+    /// Write a consistent copy of the database to `path`, for `den export`.
+    pub async fn vacuum_into(&self, path: &str) -> Result<(), sqlx::Error> {
+        self.timed(
+            "vacuum_into",
+            sqlx::query!("VACUUM INTO ?", path).execute(&self.pool),
+        )
+        .await
+        .map(|_| ())
+    }
+
+    pub async fn size_bytes(&self) -> Result<i64, sqlx::Error> {
+        self.timed(
+            "size_bytes",
+            sqlx::query_scalar!(
+                r#"SELECT page_count * page_size AS "size!: i64"
+                 FROM pragma_page_count(), pragma_page_size()"#
+            )
+            .fetch_one(&self.pool),
+        )
+        .await
+    }
+}
@@ -0,0 +1,208 @@
+use std::collections::{BTreeMap, VecDeque};
+use std::fmt::{self, Write};
+use std::sync::{Mutex, OnceLock};
+
+use serde::Serialize;
+use time::OffsetDateTime;
+use tracing::field::{Field, Visit};
+use tracing::{Event, Level, Subscriber};
+use tracing_subscriber::layer::{Context, Layer};
+use tracing_subscriber::{EnvFilter, Registry, reload};
+
+/// Warnings and errors kept for `GET /api/admin/diagnostics`.
+const RECENT_LOG_CAPACITY: usize = 100;
+/// Field names whose values never reach the buffer.
+const REDACTED_FIELDS: &[&str] = &[
+    "token",
+    "secret",
+    "password",
+    "key",
+    "cookie",
+    "authorization",
+];
+
+/// Log filters by name, for whoever would rather not write directives during an
+/// incident. Config `log_presets` adds to and overrides these.
+pub const LOG_PRESETS: &[(&str, &str)] = &[
+    ("quiet", "warn"),
+    (
+        "auth-debug",
+        "info,den::auth=debug,den::api::auth=debug,den::session=debug,webauthn_rs=debug",
+    ),
+    // sqlx logs every statement at debug under `sqlx::query`.
+    ("sql-trace", "info,sqlx::query=trace,den::db=trace"),
+];
+
+static RECENT_LOGS: Mutex<VecDeque<LogEntry>> = Mutex::new(VecDeque::new());
+static LOG_FILTER: OnceLock<reload::Handle<EnvFilter, Registry>> = OnceLock::new();
+
+#[derive(Debug, Clone, Serialize)]
+pub struct LogEntry {
+    /// Unix seconds.
+    pub at: i64,
+    pub level: &'static str,
+    pub target: String,
+    pub message: String,
+    /// `name=value` pairs of the event's other fields.
+    pub fields: String,
+}
+
+/// Tracing layer that keeps the last `RECENT_LOG_CAPACITY` warnings and errors in
+/// memory, per process.
+pub struct RecentLogs;
+
+impl<S: Subscriber> Layer<S> for RecentLogs {
+    fn on_event(&self, event: &Event<'_>, _ctx: Context<'_, S>) {
+        let metadata = event.metadata();
+        if *metadata.level() > Level::WARN {
+            return;
+        }
+        let mut visitor = EntryVisitor::default();
+        event.record(&mut visitor);
+        let entry = LogEntry {
+            at: OffsetDateTime::now_utc().unix_timestamp(),
+            level: metadata.level().as_str(),
+            target: metadata.target().to_owned(),
+            message: visitor.message,
+            fields: visitor.fields,
+        };
+        let mut logs = RECENT_LOGS.lock().unwrap_or_else(|e| e.into_inner());
+        if logs.len() == RECENT_LOG_CAPACITY {
+            logs.pop_front();
+        }
+        logs.push_back(entry);
+    }
+}
+
+/// `filter` as a layer whose directives `set_log_filter` can swap while running.
+/// It must sit directly on the registry, below every other layer.
+pub fn reloadable_filter(filter: EnvFilter) -> reload::Layer<EnvFilter, Registry> {
+    let (layer, handle) = reload::Layer::new(filter);
+    let _ = LOG_FILTER.set(handle);
+    layer
+}
+
+/// The directives for `filter`: a preset name (from config `presets` first, then
+/// `LOG_PRESETS`) expands, anything else already is directives.
+pub fn expand_log_preset<'a>(filter: &'a str, presets: &'a BTreeMap<String, String>) -> &'a str {
+    let name = filter.trim();
+    presets
+        .get(name)
+        .map(String::as_str)
+        .or_else(|| {
+            LOG_PRESETS
+                .iter()
+                .find(|(preset, _)| *preset == name)
+                .map(|(_, directives)| *directives)
+        })
+        .unwrap_or(filter)
+}
+
+/// Every preset name with its directives, config overrides applied.
+pub fn log_presets(presets: &BTreeMap<String, String>) -> BTreeMap<String, String> {
+    let mut all: BTreeMap<String, String> = LOG_PRESETS
+        .iter()
+        .map(|(name, directives)| ((*name).to_owned(), (*directives).to_owned()))
+        .collect();
+    all.extend(presets.clone());
+    all
+}
+
+/// The active filter directives, unless logging wasn't set up by `reloadable_filter`.
+pub fn log_filter() -> Option<String> {
+    LOG_FILTER.get()?.with_current(ToString::to_string).ok()
+}
+
+/// Replace the filter with `directives` (`rust_log` syntax) until restart, in this
+/// process only. Returns the new directives.
+pub fn set_log_filter(directives: &str) -> Result<String, String> {
+    let handle = LOG_FILTER.get().ok_or("log filter is not reloadable")?;
+    let directives = directives.trim();
+    if directives.is_empty() {
+        return Err("empty log filter".into());
+    }
+    let filter = EnvFilter::try_new(directives).map_err(|e| e.to_string())?;
+    handle.reload(filter).map_err(|e| e.to_string())?;
+    Ok(log_filter().unwrap_or_default())
+}
+
+/// Oldest first.
+pub fn recent_logs() -> Vec<LogEntry> {
+    let logs = RECENT_LOGS.lock().unwrap_or_else(|e| e.into_inner());
+    logs.iter().cloned().collect()
+}
+
+#[derive(Default)]
+struct EntryVisitor {
+    message: String,
+    fields: String,
+}
+
+impl EntryVisitor {
+    fn push(&mut self, field: &Field, value: fmt::Arguments<'_>) {
+        let name = field.name();
+        if name == "message" {
+            let _ = self.message.write_fmt(value);
+            return;
+        }
+        if !self.fields.is_empty() {
+            self.fields.push(' ');
+        }
+        let lower = name.to_ascii_lowercase();
+        if REDACTED_FIELDS.iter().any(|r| lower.contains(r)) {
+            let _ = write!(self.fields, "{name}=[redacted]");
+        } else {
+            let _ = write!(self.fields, "{name}={value}");
+        }
+    }
+}
+
+impl Visit for EntryVisitor {
+    fn record_str(&mut self, field: &Field, value: &str) {
+        self.push(field, format_args!("{value}"));
+    }
+
+    fn record_debug(&mut self, field: &Field, value: &dyn fmt::Debug) {
+        self.push(field, format_args!("{value:?}"));
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tracing_subscriber::layer::SubscriberExt;
+
+    #[test]
+    fn presets_expand_with_config_first() {
+        let presets = BTreeMap::from([("quiet".to_owned(), "error".to_owned())]);
+        assert_eq!(expand_log_preset("quiet", &presets), "error");
+        assert_eq!(
+            expand_log_preset(" sql-trace ", &BTreeMap::new()),
+            "info,sqlx::query=trace,den::db=trace"
+        );
+        assert_eq!(
+            expand_log_preset("info,den=debug", &presets),
+            "info,den=debug"
+        );
+        assert_eq!(log_presets(&presets)["quiet"], "error");
+        for (_, directives) in LOG_PRESETS {
+            assert!(EnvFilter::try_new(directives).is_ok(), "{directives}");
+        }
+    }
+
+    #[test]
+    fn keeps_warnings_and_redacts_secret_fields() {
+        let subscriber = tracing_subscriber::registry().with(RecentLogs);
+        tracing::subscriber::with_default(subscriber, || {
+            tracing::info!("not kept");
+            tracing::warn!(user_id = "u1", refresh_token = "abc", "diagnostics test");
+        });
+        let entry = recent_logs()
+            .into_iter()
+            .rfind(|e| e.message == "diagnostics test")
+            .unwrap();
+        assert_eq!(entry.level, "WARN");
+        assert_eq!(entry.fields, "user_id=u1 refresh_token=[redacted]");
+        assert!(!recent_logs().iter().any(|e| e.message == "not kept"));
+    }
+}
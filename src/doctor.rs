@@ -0,0 +1,507 @@
+use std::fmt;
+use std::fs::{self, OpenOptions};
+use std::io;
+use std::path::Path;
+use std::time::Duration;
+
+use openssl::x509::X509;
+use sqlx::sqlite::{SqliteConnectOptions, SqlitePoolOptions};
+use url::Url;
+
+use crate::config::{
+    self, AppConfig, BreachedPasswordsConfig, StorageConfig, WebIntegrityConfig, WebSource,
+};
+use crate::db::Db;
+use crate::frontend;
+use crate::listen::Tls;
+use crate::origin;
+use crate::web_integrity;
+use crate::web_source;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Status {
+    Ok,
+    Warn,
+    Fail,
+}
+
+impl fmt::Display for Status {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.pad(match self {
+            Self::Ok => "ok",
+            Self::Warn => "warn",
+            Self::Fail => "FAIL",
+        })
+    }
+}
+
+#[derive(Debug)]
+struct Finding {
+    status: Status,
+    check: &'static str,
+    detail: String,
+    /// What to change, for warnings and failures.
+    hint: Option<String>,
+}
+
+impl Finding {
+    fn ok(check: &'static str, detail: impl Into<String>) -> Self {
+        Self {
+            status: Status::Ok,
+            check,
+            detail: detail.into(),
+            hint: None,
+        }
+    }
+
+    fn warn(check: &'static str, detail: impl Into<String>, hint: impl Into<String>) -> Self {
+        Self {
+            status: Status::Warn,
+            check,
+            detail: detail.into(),
+            hint: Some(hint.into()),
+        }
+    }
+
+    fn fail(check: &'static str, detail: impl Into<String>, hint: impl Into<String>) -> Self {
+        Self {
+            status: Status::Fail,
+            check,
+            detail: detail.into(),
+            hint: Some(hint.into()),
+        }
+    }
+}
+
+/// `den doctor`: check everything the server would trip over at startup (or later),
+/// print every finding, and return whether none failed.
+pub async fn run() -> bool {
+    let mut findings = Vec::new();
+    let (config_path, default_database_path) = match config::den_paths() {
+        Ok(paths) => paths,
+        Err(e) => {
+            findings.push(Finding::fail(
+                "config file",
+                e.to_string(),
+                "den needs HOME (APPDATA on Windows) to find its config; or set DEN_DATA_DIR or pass --config",
+            ));
+            return report(&findings);
+        }
+    };
+    let contents = match fs::read_to_string(&config_path) {
+        Ok(contents) => {
+            findings.push(Finding::ok(
+                "config file",
+                config_path.display().to_string(),
+            ));
+            contents
+        }
+        Err(e) if e.kind() == io::ErrorKind::NotFound => {
+            findings.push(Finding::warn(
+                "config file",
+                format!("{} does not exist", config_path.display()),
+                "`den` writes a default config there on first start; defaults are checked below",
+            ));
+            String::new()
+        }
+        Err(e) => {
+            findings.push(Finding::fail(
+                "config file",
+                format!("{}: {e}", config_path.display()),
+                "make the file readable by the user den runs as",
+            ));
+            return report(&findings);
+        }
+    };
+
+    match config::parse_config_file(&contents, &config_path, default_database_path) {
+        Ok(mut config) => {
+            config::apply_config_dir(&mut config, &config_path);
+            if let Some(dir) = config::data_dir() {
+                config::apply_data_dir(&mut config, &dir);
+            }
+            findings.extend(check_config(&config));
+            findings.push(check_database(&config.database_path).await);
+            match &config.web_source {
+                Some(source) => findings.push(check_web_source(source, &config).await),
+                None => {
+                    findings.push(check_web_assets());
+                    if let Some(web_integrity) = &config.web_integrity {
+                        findings.push(check_web_integrity(web_integrity));
+                    }
+                }
+            }
+        }
+        Err(e) => findings.push(Finding::fail(
+            "config",
+            e.to_string(),
+            "fix this setting; later checks need a config that loads",
+        )),
+    }
+    report(&findings)
+}
+
+fn report(findings: &[Finding]) -> bool {
+    for finding in findings {
+        println!(
+            "{:<5} {}: {}",
+            finding.status, finding.check, finding.detail
+        );
+        if let Some(hint) = &finding.hint {
+            println!("      -> {hint}");
+        }
+    }
+    let failed = findings.iter().filter(|f| f.status == Status::Fail).count();
+    let warned = findings.iter().filter(|f| f.status == Status::Warn).count();
+    println!("\n{failed} failed, {warned} warnings");
+    failed == 0
+}
+
+/// Checks that need only the resolved config.
+fn check_config(config: &AppConfig) -> Vec<Finding> {
+    let mut findings = Vec::new();
+    let rp_host = match Url::parse(&config.rp_origin) {
+        Ok(url) if matches!(url.scheme(), "http" | "https") && url.host_str().is_some() => {
+            if url.path() != "/" || url.query().is_some() {
+                findings.push(Finding::warn(
+                    "rp_origin",
+                    format!("{} has a path or query", config.rp_origin),
+                    "origins are scheme://host[:port]; the rest is ignored",
+                ));
+            } else if url.scheme() == "http" && url.host_str() != Some("localhost") {
+                findings.push(Finding::warn(
+                    "rp_origin",
+                    format!("{} is plain http", config.rp_origin),
+                    "browsers only allow passkeys on https origins (and localhost)",
+                ));
+            } else {
+                findings.push(Finding::ok("rp_origin", config.rp_origin.clone()));
+            }
+            url.host_str().map(str::to_ascii_lowercase)
+        }
+        _ => {
+            findings.push(Finding::fail(
+                "rp_origin",
+                format!("{:?} is not an http(s) origin", config.rp_origin),
+                "set rp_origin to the URL users open den at, e.g. \"https://auth.example.com\"",
+            ));
+            None
+        }
+    };
+
+    if let Some(host) = rp_host {
+        let rp_id = config.rp_id.to_ascii_lowercase();
+        if rp_id_matches(&rp_id, &host) {
+            findings.push(Finding::ok("rp_id", rp_id));
+        } else {
+            findings.push(Finding::fail(
+                "rp_id",
+                format!("{rp_id:?} is neither {host:?} nor a parent domain of it"),
+                format!(
+                    "set rp_id = {host:?} (or a parent domain); browsers reject passkeys otherwise"
+                ),
+            ));
+        }
+    }
+
+    let invalid: Vec<&String> = config
+        .allowed_hosts
+        .iter()
+        .filter(|host| origin::normalize_host(host).is_none())
+        .collect();
+    if invalid.is_empty() {
+        findings.push(Finding::ok(
+            "allowed_hosts",
+            format!("{} configured", config.allowed_hosts.len()),
+        ));
+    } else {
+        findings.push(Finding::fail(
+            "allowed_hosts",
+            format!("not a host or origin: {invalid:?}"),
+            "use \"host[:port]\" or \"https://host[:port]\"; invalid entries are ignored at startup",
+        ));
+    }
+
+    if let StorageConfig::Redis { redis_url } = &config.storage {
+        match Url::parse(redis_url) {
+            Ok(url) if url.scheme() == "redis" && url.host_str().is_some() => {
+                findings.push(Finding::ok("storage", "redis"));
+            }
+            _ => findings.push(Finding::fail(
+                "storage",
+                "storage.redis_url is not a redis:// URL",
+                "use redis://[[user]:password@]host[:port][/db]",
+            )),
+        }
+    }
+
+    if let Some(tls) = &config.tls {
+        findings.push(match Tls::load(tls) {
+            Ok(_) => Finding::ok("tls", tls.cert_path.clone()),
+            Err(e) => Finding::fail(
+                "tls",
+                e.to_string(),
+                "point cert_path at a PEM chain (leaf first) and key_path at its key",
+            ),
+        });
+        if tls.enable_h3 && !cfg!(feature = "http3") {
+            findings.push(Finding::warn(
+                "tls.enable_h3",
+                "this build lacks the http3 feature",
+                "rebuild with --features http3, or drop enable_h3",
+            ));
+        }
+    }
+    if let Some(client_cert) = &config.client_cert {
+        findings.push(check_pem(
+            "client_cert.ca_path",
+            Path::new(&client_cert.ca_path),
+        ));
+    }
+    if let Some(roots) = config
+        .attestation
+        .as_ref()
+        .and_then(|a| a.trusted_roots.as_deref())
+    {
+        findings.push(check_pem("attestation.trusted_roots", Path::new(roots)));
+    }
+    let mut files = Vec::new();
+    if let Some(path) = &config.branding.logo_path {
+        files.push(("branding.logo_path", Path::new(path)));
+    }
+    if let Some(path) = &config.geoip_database {
+        files.push(("geoip_database", path.as_path()));
+    }
+    if let Some(path) = &config.asn_database {
+        files.push(("asn_database", path.as_path()));
+    }
+    if let Some(BreachedPasswordsConfig::Bloom { path }) = &config.breached_passwords {
+        files.push(("breached_passwords.path", path.as_path()));
+    }
+    if let Some(path) = &config.error_pages.not_found {
+        files.push(("error_pages.not_found", path.as_path()));
+    }
+    if let Some(path) = &config.error_pages.maintenance {
+        files.push(("error_pages.maintenance", path.as_path()));
+    }
+    for (check, path) in files {
+        findings.push(match fs::File::open(path) {
+            Ok(_) => Finding::ok(check, path.display().to_string()),
+            Err(e) => Finding::fail(
+                check,
+                format!("{}: {e}", path.display()),
+                "point it at an existing file readable by den",
+            ),
+        });
+    }
+    findings
+}
+
+/// WebAuthn: the RP ID must be the origin's host or a registrable parent of it.
+fn rp_id_matches(rp_id: &str, host: &str) -> bool {
+    host == rp_id
+        || host
+            .strip_suffix(rp_id)
+            .is_some_and(|prefix| prefix.ends_with('.'))
+}
+
+/// TLS trust anchors: readable, and at least one PEM certificate.
+fn check_pem(check: &'static str, path: &Path) -> Finding {
+    let pem = match fs::read(path) {
+        Ok(pem) => pem,
+        Err(e) => {
+            return Finding::fail(
+                check,
+                format!("{}: {e}", path.display()),
+                "point it at a PEM bundle readable by den",
+            );
+        }
+    };
+    match X509::stack_from_pem(&pem) {
+        Ok(certs) if !certs.is_empty() => Finding::ok(
+            check,
+            format!("{} ({} certificates)", path.display(), certs.len()),
+        ),
+        _ => Finding::fail(
+            check,
+            format!("{} holds no PEM certificates", path.display()),
+            "expected -----BEGIN CERTIFICATE----- blocks",
+        ),
+    }
+}
+
+async fn check_database(path: &Path) -> Finding {
+    const CHECK: &str = "database";
+    let dir = path
+        .parent()
+        .filter(|d| !d.as_os_str().is_empty())
+        .unwrap_or_else(|| Path::new("."));
+    // SQLite writes its WAL and journal files next to the database.
+    let probe = dir.join(format!(".den-doctor-{}", std::process::id()));
+    let dir_writable = fs::create_dir_all(dir).and_then(|()| {
+        OpenOptions::new()
+            .write(true)
+            .create_new(true)
+            .open(&probe)?;
+        fs::remove_file(&probe)
+    });
+    if let Err(e) = dir_writable {
+        return Finding::fail(
+            CHECK,
+            format!("{} is not writable: {e}", dir.display()),
+            "set database_path to a directory owned by the user den runs as",
+        );
+    }
+    if !path.exists() {
+        return Finding::ok(CHECK, format!("{} will be created", path.display()));
+    }
+    if let Err(e) = OpenOptions::new().append(true).open(path) {
+        return Finding::fail(
+            CHECK,
+            format!("{}: {e}", path.display()),
+            "make the database file writable by the user den runs as",
+        );
+    }
+
+    let options = SqliteConnectOptions::new().filename(path).read_only(true);
+    let pool = match SqlitePoolOptions::new()
+        .max_connections(1)
+        .connect_with(options)
+        .await
+    {
+        Ok(pool) => pool,
+        Err(e) => {
+            return Finding::fail(
+                CHECK,
+                format!("{}: {e}", path.display()),
+                "is this an SQLite database? restore it from a `den export` archive if not",
+            );
+        }
+    };
+    let db = Db::new(pool, Duration::from_secs(1));
+    let finding = match db.migration_status().await {
+        Ok(status) if !status.failed.is_empty() => Finding::fail(
+            CHECK,
+            format!("migrations {:?} failed", status.failed),
+            "restore from a backup taken before the upgrade",
+        ),
+        Ok(status) if !status.pending.is_empty() => Finding::ok(
+            CHECK,
+            format!(
+                "{} ({} migrations will run on start)",
+                path.display(),
+                status.pending.len()
+            ),
+        ),
+        Ok(_) => Finding::ok(CHECK, path.display().to_string()),
+        // No `_sqlx_migrations` yet: an empty database.
+        Err(_) => Finding::ok(CHECK, format!("{} (not yet migrated)", path.display())),
+    };
+    db.pool().close().await;
+    finding
+}
+
+fn check_web_assets() -> Finding {
+    const CHECK: &str = "web assets";
+    match frontend::resolve_web_out_dir() {
+        Some(dir) if dir.join("index.html").is_file() => {
+            Finding::ok(CHECK, dir.display().to_string())
+        }
+        Some(dir) => Finding::fail(
+            CHECK,
+            format!("{} has no index.html", dir.display()),
+            "rebuild the web UI (`pnpm build` in web/)",
+        ),
+        None => Finding::fail(
+            CHECK,
+            "no web/out directory found",
+            "set DEN_WEB_OUT_DIR, or run den from the repository root; the API works without it",
+        ),
+    }
+}
+
+fn check_web_integrity(config: &WebIntegrityConfig) -> Finding {
+    const CHECK: &str = "web integrity";
+    match web_integrity::check(config) {
+        Ok(detail) => Finding::ok(CHECK, detail),
+        Err(detail) => Finding::fail(
+            CHECK,
+            detail,
+            "redeploy the web build signed with DEN_WEB_SIGNING_KEY, or check who else can write there",
+        ),
+    }
+}
+
+/// Fetches the origin's current build into the cache, as the refresh would, and
+/// verifies it against `[web_integrity]` if set.
+async fn check_web_source(source: &WebSource, config: &AppConfig) -> Finding {
+    const CHECK: &str = "web source";
+    let (url, cache) = (source.clone(), web_source::cache_dir(&config.database_path));
+    let synced = tokio::task::spawn_blocking(move || web_source::sync(&url, &cache)).await;
+    let dir = match synced.unwrap_or_else(|e| Err(e.to_string())) {
+        Ok(dir) => dir,
+        Err(detail) => {
+            return Finding::fail(
+                CHECK,
+                detail,
+                "check that den can reach web_source and that the build there has den-manifest.json",
+            );
+        }
+    };
+    let Some(integrity) = &config.web_integrity else {
+        return Finding::ok(
+            CHECK,
+            format!("{} (cached in {})", source.url, dir.display()),
+        );
+    };
+    match web_integrity::check_dir(integrity, &dir) {
+        Ok(detail) => Finding::ok(CHECK, detail),
+        Err(detail) => Finding::fail(
+            CHECK,
+            detail,
+            "publish a build signed with DEN_WEB_SIGNING_KEY to web_source",
+        ),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::path::PathBuf;
+
+    use super::*;
+
+    fn findings(contents: &str) -> Vec<Finding> {
+        check_config(&config::parse_app_config(contents, PathBuf::from("den.db")).unwrap())
+    }
+
+    fn status(findings: &[Finding], check: &str) -> Status {
+        findings.iter().find(|f| f.check == check).unwrap().status
+    }
+
+    #[test]
+    fn rp_id_must_be_the_origin_host_or_a_parent() {
+        assert!(rp_id_matches("example.com", "auth.example.com"));
+        assert!(rp_id_matches("auth.example.com", "auth.example.com"));
+        assert!(!rp_id_matches("ample.com", "auth.example.com"));
+        assert!(!rp_id_matches("other.com", "auth.example.com"));
+    }
+
+    #[test]
+    fn reports_every_problem_at_once() {
+        let findings = findings(
+            "rp_id = \"example.org\"\n\
+             rp_origin = \"https://auth.example.com\"\n\
+             allowed_hosts = [\"app.example.com\", \"app.example.com/admin\"]\n\
+             geoip_database = \"/nonexistent/den-geoip.mmdb\"\n\
+             listen = [\"tls:[::]:443\"]\n\
+             [tls]\n\
+             cert_path = \"/nonexistent/den-cert.pem\"\n\
+             key_path = \"/nonexistent/den-key.pem\"\n",
+        );
+        assert_eq!(status(&findings, "rp_origin"), Status::Ok);
+        assert_eq!(status(&findings, "rp_id"), Status::Fail);
+        assert_eq!(status(&findings, "allowed_hosts"), Status::Fail);
+        assert_eq!(status(&findings, "geoip_database"), Status::Fail);
+        assert_eq!(status(&findings, "tls"), Status::Fail);
+    }
+}
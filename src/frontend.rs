@@ -1,15 +1,134 @@
+use std::collections::BTreeMap;
 use std::path::{Component, Path, PathBuf};
+use std::sync::{Arc, Mutex, RwLock};
 use std::task::{Context, Poll};
+use std::time::{SystemTime, UNIX_EPOCH};
 
-use axum::body::Body;
-use axum::http::{HeaderValue, Method, Request, StatusCode, header};
+use axum::body::{Body, Bytes};
+use axum::http::{HeaderMap, HeaderValue, Method, Request, StatusCode, header};
 use axum::response::{IntoResponse, Response};
+use serde::Deserialize;
+use time::{Date, Month, OffsetDateTime};
 use tower::Service;
 use tower::ServiceExt;
-use tower_http::services::{ServeDir, ServeFile};
+use tower_http::services::ServeDir;
+
+use crate::branding::Branding;
+use crate::config::ErrorPagesConfig;
+use crate::i18n;
 
 const CACHE_CONTROL_IMMUTABLE: &str = "public, max-age=31536000, immutable";
 const ENV_WEB_OUT_DIR: &str = "DEN_WEB_OUT_DIR";
+/// Route list written by the web build (see `web/vite.config.ts`).
+const ROUTES_FILE: &str = "den-routes.json";
+
+/// The build `web_source` last fetched; wins over local directories.
+static SYNCED: RwLock<Option<(PathBuf, Arc<Build>)>> = RwLock::new(None);
+/// `index.html` files and error pages by path; see `cached_page`.
+static PAGES: Mutex<BTreeMap<PathBuf, Arc<tokio::sync::Mutex<Option<Page>>>>> =
+    Mutex::new(BTreeMap::new());
+
+#[derive(Clone)]
+struct Page {
+    modified: SystemTime,
+    body: Bytes,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+enum Segment {
+    Static(String),
+    /// `$name` or `:name`: any one segment.
+    Param,
+    /// A trailing `$` or `*`: the rest of the path, possibly empty.
+    Splat,
+}
+
+/// What a web directory serves, read once per directory rather than per request.
+#[derive(Debug, Default)]
+struct Build {
+    routes: SpaRoutes,
+    /// See `locales`.
+    locales: Vec<String>,
+}
+
+impl Build {
+    fn new(routes: SpaRoutes, dir: Option<&Path>) -> Self {
+        Self {
+            routes,
+            locales: dir.map(locales).unwrap_or_default(),
+        }
+    }
+}
+
+#[derive(Deserialize)]
+struct RoutesFile {
+    routes: Vec<String>,
+}
+
+/// The SPA's client-side routes, from `spa_routes` or the build's `den-routes.json`.
+/// Without either, any path whose last segment has no dot counts as a route.
+#[derive(Debug, Clone, Default)]
+pub struct SpaRoutes(Option<Vec<Vec<Segment>>>);
+
+impl SpaRoutes {
+    /// `configured` wins over the route file of the web directory found at startup.
+    pub fn load(configured: Option<&[String]>) -> Self {
+        Self::for_dir(configured, resolve_web_out_dir().as_deref())
+    }
+
+    fn for_dir(configured: Option<&[String]>, dir: Option<&Path>) -> Self {
+        let patterns = match configured {
+            Some(routes) => routes.to_vec(),
+            None => {
+                let Some(file) = dir.map(|dir| dir.join(ROUTES_FILE)) else {
+                    return Self::default();
+                };
+                match std::fs::read(&file) {
+                    Ok(json) => match serde_json::from_slice::<RoutesFile>(&json) {
+                        Ok(file) => file.routes,
+                        Err(e) => {
+                            tracing::warn!("ignoring malformed {}: {e}", file.display());
+                            return Self::default();
+                        }
+                    },
+                    Err(_) => return Self::default(),
+                }
+            }
+        };
+        Self(Some(patterns.iter().map(|p| parse_route(p)).collect()))
+    }
+
+    /// Whether `rel_path` (no leading `/`) should get `index.html`.
+    fn is_route(&self, rel_path: &str) -> bool {
+        let Some(routes) = &self.0 else {
+            return !is_asset_path(rel_path);
+        };
+        let segments: Vec<&str> = rel_path.split('/').filter(|s| !s.is_empty()).collect();
+        routes.iter().any(|route| route_matches(route, &segments))
+    }
+}
+
+fn parse_route(pattern: &str) -> Vec<Segment> {
+    pattern
+        .split('/')
+        .filter(|s| !s.is_empty())
+        .map(|s| match s {
+            "$" | "*" => Segment::Splat,
+            _ if s.starts_with('$') || s.starts_with(':') => Segment::Param,
+            _ => Segment::Static(s.to_owned()),
+        })
+        .collect()
+}
+
+fn route_matches(route: &[Segment], path: &[&str]) -> bool {
+    match (route.split_first(), path.split_first()) {
+        (Some((Segment::Splat, _)), _) => true,
+        (None, None) => true,
+        (Some((Segment::Param, rest)), Some((_, path))) => route_matches(rest, path),
+        (Some((Segment::Static(s), rest)), Some((p, path))) if s == p => route_matches(rest, path),
+        _ => false,
+    }
+}
 
 fn cache_control_for_path(path: &str) -> Option<&'static str> {
     if path.starts_with("assets/") {
@@ -39,7 +158,23 @@ fn is_safe_rel_path(path: &str) -> bool {
         .all(|component| matches!(component, Component::Normal(_)))
 }
 
-fn resolve_web_out_dir() -> Option<PathBuf> {
+/// Serve `dir`, a build fetched by `web_source`, from the next request on.
+pub fn serve_synced(dir: PathBuf, configured_routes: Option<&[String]>) {
+    let build = Build::new(
+        SpaRoutes::for_dir(configured_routes, Some(&dir)),
+        Some(&dir),
+    );
+    *SYNCED.write().unwrap_or_else(|e| e.into_inner()) = Some((dir, Arc::new(build)));
+}
+
+fn synced() -> Option<(PathBuf, Arc<Build>)> {
+    SYNCED.read().unwrap_or_else(|e| e.into_inner()).clone()
+}
+
+pub fn resolve_web_out_dir() -> Option<PathBuf> {
+    if let Some((dir, _)) = synced() {
+        return Some(dir);
+    }
     std::env::var_os(ENV_WEB_OUT_DIR)
         .map(PathBuf::from)
         .filter(|p| p.is_dir())
@@ -68,61 +203,342 @@ fn maybe_apply_cache_header(path: &str, response: &mut Response) {
     );
 }
 
-async fn handle_request(request: Request<Body>) -> Response {
-    let Some(root) = resolve_web_out_dir() else {
+/// Responses may come from a precompressed sibling (`accept-encoding`) or a locale
+/// directory (`accept-language`), so caches must key on the header.
+fn add_vary(response: &mut Response, name: &'static str) {
+    let already = response
+        .headers()
+        .get_all(header::VARY)
+        .iter()
+        .filter_map(|v| v.to_str().ok())
+        .flat_map(|v| v.split(','))
+        .any(|v| v.trim().eq_ignore_ascii_case(name));
+    if !already {
+        response
+            .headers_mut()
+            .append(header::VARY, HeaderValue::from_static(name));
+    }
+}
+
+/// Top-level directories of a localized build: language tags (`de`, `pt-BR`) that
+/// hold their own `index.html`.
+fn locales(root: &Path) -> Vec<String> {
+    let Ok(entries) = std::fs::read_dir(root) else {
+        return Vec::new();
+    };
+    entries
+        .flatten()
+        .filter_map(|entry| entry.file_name().into_string().ok())
+        .filter(|name| is_language_tag(name) && root.join(name).join("index.html").is_file())
+        .collect()
+}
+
+fn is_language_tag(name: &str) -> bool {
+    let mut parts = name.split(['-', '_']);
+    let primary = parts.next().unwrap_or_default();
+    (2..=3).contains(&primary.len())
+        && primary.bytes().all(|b| b.is_ascii_alphabetic())
+        && parts.all(|p| (2..=8).contains(&p.len()) && p.bytes().all(|b| b.is_ascii_alphanumeric()))
+}
+
+/// `If-Range` (RFC 9110 13.1.5), which `ServeDir` ignores: the range applies only while
+/// the file's `Last-Modified` is unchanged, else the whole file is sent. den sends no
+/// ETags, so an entity tag never matches.
+fn if_range_matches(headers: &HeaderMap, file: &Path) -> bool {
+    let Some(value) = headers.get(header::IF_RANGE) else {
+        return true;
+    };
+    let Some(since) = value.to_str().ok().and_then(parse_http_date) else {
+        return false;
+    };
+    std::fs::metadata(file)
+        .and_then(|m| m.modified())
+        .ok()
+        .and_then(|modified| modified.duration_since(UNIX_EPOCH).ok())
+        .is_some_and(|modified| i64::try_from(modified.as_secs()) == Ok(since))
+}
+
+/// Unix seconds of an IMF-fixdate (`Sun, 06 Nov 1994 08:49:37 GMT`), the only form
+/// `Last-Modified` is sent in.
+fn parse_http_date(value: &str) -> Option<i64> {
+    const MONTHS: [&str; 12] = [
+        "Jan", "Feb", "Mar", "Apr", "May", "Jun", "Jul", "Aug", "Sep", "Oct", "Nov", "Dec",
+    ];
+    let (_, rest) = value.split_once(", ")?;
+    let mut parts = rest.split(' ');
+    let day: u8 = parts.next()?.parse().ok()?;
+    let month_name = parts.next()?;
+    let month = MONTHS.iter().position(|m| *m == month_name)?;
+    let year: i32 = parts.next()?.parse().ok()?;
+    let mut clock = parts.next()?.split(':').map(|n| n.parse::<u8>().ok());
+    let (hour, minute, second) = (clock.next()??, clock.next()??, clock.next()??);
+    if parts.next()? != "GMT" || parts.next().is_some() {
+        return None;
+    }
+    let month = Month::try_from(month as u8 + 1).ok()?;
+    let at = Date::from_calendar_date(year, month, day)
+        .ok()?
+        .with_hms(hour, minute, second)
+        .ok()?;
+    Some(at.assume_utc().unix_timestamp())
+}
+
+/// `Last-Modified` form of `at` (IMF-fixdate), the inverse of `parse_http_date`.
+fn format_http_date(at: SystemTime) -> String {
+    let at = OffsetDateTime::from(at);
+    format!(
+        "{}, {:02} {} {} {:02}:{:02}:{:02} GMT",
+        &at.weekday().to_string()[..3],
+        at.day(),
+        &at.month().to_string()[..3],
+        at.year(),
+        at.hour(),
+        at.minute(),
+        at.second()
+    )
+}
+
+/// `path` from memory while its mtime and length are unchanged, so SPA navigations
+/// cost one `stat` instead of opening and reading the file (and probing for
+/// precompressed siblings) on slow or network filesystems. Concurrent misses for
+/// the same file wait on a single read.
+async fn cached_page(path: &Path) -> std::io::Result<Page> {
+    let slot = PAGES
+        .lock()
+        .unwrap_or_else(|e| e.into_inner())
+        .entry(path.to_owned())
+        .or_default()
+        .clone();
+    let mut slot = slot.lock().await;
+    let metadata = match tokio::fs::metadata(path).await {
+        Ok(metadata) => metadata,
+        Err(e) => {
+            // Builds swapped in by `web_source` leave their old paths behind.
+            *slot = None;
+            PAGES.lock().unwrap_or_else(|e| e.into_inner()).remove(path);
+            return Err(e);
+        }
+    };
+    let modified = metadata.modified()?;
+    if let Some(page) = &*slot
+        && page.modified == modified
+        && page.body.len() as u64 == metadata.len()
+    {
+        return Ok(page.clone());
+    }
+    let page = Page {
+        modified,
+        body: Bytes::from(tokio::fs::read(path).await?),
+    };
+    *slot = Some(page.clone());
+    Ok(page)
+}
+
+/// `dir/index.html` from the page cache, answering `If-Modified-Since` with 304.
+/// A `Range` is ignored: the page is small and always sent whole, identity-encoded
+/// for the compression layer.
+async fn serve_index(dir: &Path, headers: &HeaderMap) -> Response {
+    let Ok(page) = cached_page(&dir.join("index.html")).await else {
         return StatusCode::NOT_FOUND.into_response();
     };
+    let last_modified = format_http_date(page.modified);
+    let unchanged = headers
+        .get(header::IF_MODIFIED_SINCE)
+        .and_then(|v| v.to_str().ok())
+        .and_then(parse_http_date)
+        .zip(page.modified.duration_since(UNIX_EPOCH).ok())
+        .is_some_and(|(since, modified)| {
+            i64::try_from(modified.as_secs()).is_ok_and(|modified| modified <= since)
+        });
+    if unchanged {
+        return (
+            StatusCode::NOT_MODIFIED,
+            [(header::LAST_MODIFIED, last_modified)],
+        )
+            .into_response();
+    }
+    (
+        [
+            (header::CONTENT_TYPE, "text/html; charset=utf-8".to_owned()),
+            (header::LAST_MODIFIED, last_modified),
+        ],
+        page.body,
+    )
+        .into_response()
+}
+
+/// `index.html` with branding applied; precompressed siblings can't be rewritten, so
+/// this is left to the compression layer.
+async fn branded_index(root: &Path, branding: &Branding) -> Response {
+    match cached_page(&root.join("index.html")).await {
+        Ok(page) => (
+            [(header::CONTENT_TYPE, "text/html; charset=utf-8")],
+            branding.inject(&String::from_utf8_lossy(&page.body)),
+        )
+            .into_response(),
+        Err(_) => StatusCode::NOT_FOUND.into_response(),
+    }
+}
+
+/// An `[error_pages]` file under `status`, revalidated per request so edits apply
+/// without a restart. `no-store`, so no cache keeps it past the outage or once the
+/// path exists.
+pub async fn error_page(path: &Path, status: StatusCode) -> Option<Response> {
+    match cached_page(path).await {
+        Ok(page) => Some(
+            (
+                status,
+                [
+                    (header::CONTENT_TYPE, "text/html; charset=utf-8"),
+                    (header::CACHE_CONTROL, "no-store"),
+                ],
+                page.body,
+            )
+                .into_response(),
+        ),
+        Err(e) => {
+            tracing::warn!("failed to read error page {}: {e}", path.display());
+            None
+        }
+    }
+}
+
+async fn handle_request(
+    mut request: Request<Body>,
+    branding: &Branding,
+    build: &Build,
+    error_pages: &ErrorPagesConfig,
+) -> Response {
+    let synced = synced();
+    let (root, build) = match &synced {
+        Some((dir, synced_build)) => (dir.clone(), &**synced_build),
+        None => match resolve_web_out_dir() {
+            Some(dir) => (dir, build),
+            None => return StatusCode::NOT_FOUND.into_response(),
+        },
+    };
+    let routes = &build.routes;
 
     if request.method() != Method::GET && request.method() != Method::HEAD {
         return StatusCode::NOT_FOUND.into_response();
     }
 
-    // If we need to fall back to `/index.html`, reconstruct a request using the same
-    // method/uri/headers. (Request bodies are irrelevant since we only handle GET/HEAD.)
-    let request_method = request.method().clone();
-    let request_uri = request.uri().clone();
-    let request_headers = request.headers().clone();
-
     let rel_path = request.uri().path().trim_start_matches('/').to_string();
 
     if !rel_path.is_empty() && !is_safe_rel_path(&rel_path) {
         return StatusCode::NOT_FOUND.into_response();
     }
 
-    let dir = ServeDir::new(&root).append_index_html_on_directories(true);
-    let mut res = dir.oneshot(request).await.unwrap().map(Body::new);
-
-    if res.status() == StatusCode::NOT_FOUND {
-        if is_asset_path(&rel_path) {
-            return StatusCode::NOT_FOUND.into_response();
+    if request.headers().contains_key(header::RANGE) {
+        // Ranges are served from the identity file, not a precompressed sibling, so
+        // offsets and `Last-Modified` are the ones download managers and players expect.
+        request.headers_mut().remove(header::ACCEPT_ENCODING);
+        let mut file = root.join(&rel_path);
+        if file.is_dir() {
+            file.push("index.html");
+        }
+        if !if_range_matches(request.headers(), &file) {
+            request.headers_mut().remove(header::RANGE);
         }
+    }
+
+    let request_headers = request.headers().clone();
 
-        let fallback_req = {
-            let mut req = Request::builder()
-                .method(request_method)
-                .uri(request_uri)
-                .body(Body::empty())
-                .unwrap();
-            *req.headers_mut() = request_headers;
-            req
+    // A localized build answers routes with the `index.html` of the locale its first
+    // segment names (`/de/settings`), else of the best `Accept-Language` match, else
+    // its own. Routes are matched without the locale prefix.
+    let locales: &[String] = if is_asset_path(&rel_path) {
+        &[]
+    } else {
+        &build.locales
+    };
+    let first = rel_path.split('/').next().unwrap_or_default();
+    let (index_dir, route_path) = if locales.iter().any(|locale| locale == first) {
+        (
+            root.join(first),
+            rel_path[first.len()..].trim_start_matches('/'),
+        )
+    } else {
+        let accept_language = request
+            .headers()
+            .get(header::ACCEPT_LANGUAGE)
+            .and_then(|v| v.to_str().ok())
+            .unwrap_or_default();
+        let dir = match i18n::best_match(accept_language, locales) {
+            Some(locale) => root.join(locale),
+            None => root.clone(),
         };
+        (dir, rel_path.as_str())
+    };
+    let localized = !locales.is_empty();
 
-        res = ServeFile::new(root.join("index.html"))
-            .oneshot(fallback_req)
-            .await
-            .unwrap()
-            .map(Body::new);
+    let is_route = routes.is_route(route_path);
+    if branding.injects_html()
+        && (rel_path == "index.html" || (is_route && !root.join(&rel_path).is_file()))
+    {
+        let mut res = branded_index(&index_dir, branding).await;
+        if localized {
+            add_vary(&mut res, "accept-language");
+        }
+        return res;
+    }
+
+    // Route navigations skip `ServeDir`, whose miss would cost extra lookups first.
+    let mut res = if is_route && (route_path.is_empty() || !root.join(&rel_path).exists()) {
+        serve_index(&index_dir, &request_headers).await
+    } else {
+        // `.br`/`.gz`/`.zst` siblings are written by the web build (see vite.config.ts).
+        let dir = ServeDir::new(&root)
+            .append_index_html_on_directories(true)
+            .precompressed_br()
+            .precompressed_zstd()
+            .precompressed_gzip();
+        dir.oneshot(request).await.unwrap().map(Body::new)
+    };
+
+    if res.status() == StatusCode::NOT_FOUND {
+        // Unknown paths: missing files stay bare 404s; anything else gets the SPA's
+        // not-found page, under a real 404.
+        if !is_route {
+            if is_asset_path(&rel_path) {
+                return StatusCode::NOT_FOUND.into_response();
+            }
+            if let Some(page) = &error_pages.not_found
+                && let Some(res) = error_page(page, StatusCode::NOT_FOUND).await
+            {
+                return res;
+            }
+            let mut res = if branding.injects_html() {
+                branded_index(&index_dir, branding).await
+            } else {
+                serve_index(&index_dir, &HeaderMap::new()).await
+            };
+            if res.status().is_success() {
+                *res.status_mut() = StatusCode::NOT_FOUND;
+            }
+            return res;
+        }
+
+        res = serve_index(&index_dir, &request_headers).await;
     }
 
     if res.status() != StatusCode::NOT_FOUND {
         maybe_apply_cache_header(&rel_path, &mut res);
+        add_vary(&mut res, "accept-encoding");
+        if localized {
+            add_vary(&mut res, "accept-language");
+        }
     }
 
     res
 }
 
-#[derive(Clone, Copy, Default)]
-pub struct FrontendService;
+#[derive(Clone, Default)]
+pub struct FrontendService {
+    branding: Arc<Branding>,
+    build: Arc<Build>,
+    error_pages: Arc<ErrorPagesConfig>,
+}
 
 impl Service<Request<Body>> for FrontendService {
     type Response = Response;
@@ -136,12 +552,23 @@ impl Service<Request<Body>> for FrontendService {
     }
 
     fn call(&mut self, request: Request<Body>) -> Self::Future {
-        Box::pin(async move { Ok(handle_request(request).await) })
+        let branding = self.branding.clone();
+        let build = self.build.clone();
+        let error_pages = self.error_pages.clone();
+        Box::pin(async move { Ok(handle_request(request, &branding, &build, &error_pages).await) })
     }
 }
 
-pub fn service() -> FrontendService {
-    FrontendService
+pub fn service(
+    branding: Arc<Branding>,
+    routes: SpaRoutes,
+    error_pages: Arc<ErrorPagesConfig>,
+) -> FrontendService {
+    FrontendService {
+        branding,
+        build: Arc::new(Build::new(routes, resolve_web_out_dir().as_deref())),
+        error_pages,
+    }
 }
 
 #[cfg(test)]
@@ -181,5 +608,99 @@ mod tests {
         assert!(is_safe_rel_path("settings.html"));
     }
 
+    #[test]
+    fn vary_accept_encoding_is_added_once() {
+        let mut response = StatusCode::OK.into_response();
+        response
+            .headers_mut()
+            .insert(header::VARY, HeaderValue::from_static("Accept-Encoding"));
+        add_vary(&mut response, "accept-encoding");
+        assert_eq!(response.headers().get_all(header::VARY).iter().count(), 1);
+
+        let mut response = StatusCode::OK.into_response();
+        add_vary(&mut response, "accept-encoding");
+        assert_eq!(
+            response.headers().get(header::VARY).unwrap(),
+            "accept-encoding"
+        );
+    }
+
+    #[test]
+    fn route_lists_match_params_and_splats() {
+        let routes = SpaRoutes(Some(
+            ["/", "/settings", "/users/$id", "/docs/$", "/v1.2/notes"]
+                .iter()
+                .map(|r| parse_route(r))
+                .collect(),
+        ));
+        assert!(routes.0.is_some());
+        assert!(routes.is_route(""));
+        assert!(routes.is_route("settings/"));
+        assert!(routes.is_route("users/jane.doe"));
+        assert!(!routes.is_route("users"));
+        assert!(!routes.is_route("users/a/b"));
+        assert!(routes.is_route("docs"));
+        assert!(routes.is_route("docs/a/b.md"));
+        assert!(routes.is_route("v1.2/notes"));
+        assert!(!routes.is_route("missing"));
+
+        let heuristic = SpaRoutes::default();
+        assert!(heuristic.is_route("missing"));
+        assert!(!heuristic.is_route("users/jane.doe"));
+    }
+
+    #[test]
+    fn if_range_dates_parse_as_imf_fixdate() {
+        assert_eq!(
+            parse_http_date("Sun, 06 Nov 1994 08:49:37 GMT"),
+            Some(784_111_777)
+        );
+        assert_eq!(parse_http_date("Sunday, 06-Nov-94 08:49:37 GMT"), None);
+        assert_eq!(parse_http_date("\"v1\""), None);
+
+        let mut headers = HeaderMap::new();
+        let missing = Path::new("/nonexistent/den");
+        assert!(if_range_matches(&headers, missing));
+        headers.insert(header::IF_RANGE, HeaderValue::from_static("\"etag\""));
+        assert!(!if_range_matches(&headers, missing));
+    }
+
+    #[test]
+    fn locale_directories_need_a_tag_name_and_an_index() {
+        let root = std::env::temp_dir().join(format!("den-locales-{}", std::process::id()));
+        for dir in ["de", "pt-BR", "assets", "fr"] {
+            std::fs::create_dir_all(root.join(dir)).unwrap();
+        }
+        for dir in ["de", "pt-BR", "assets"] {
+            std::fs::write(root.join(dir).join("index.html"), "<html>").unwrap();
+        }
+        let mut found = locales(&root);
+        found.sort();
+        assert_eq!(found, ["de", "pt-BR"]);
+        assert!(is_language_tag("zh_Hant"));
+        assert!(!is_language_tag("v1.2"));
+        std::fs::remove_dir_all(&root).unwrap();
+    }
+
+    #[test]
+    fn last_modified_round_trips_through_if_modified_since() {
+        let at = UNIX_EPOCH + std::time::Duration::from_secs(784_111_777);
+        assert_eq!(format_http_date(at), "Sun, 06 Nov 1994 08:49:37 GMT");
+        assert_eq!(parse_http_date(&format_http_date(at)), Some(784_111_777));
+    }
+
+    #[tokio::test]
+    async fn cached_pages_follow_file_changes() {
+        let path = std::env::temp_dir().join(format!("den-page-{}.html", std::process::id()));
+        std::fs::write(&path, "<p>one</p>").unwrap();
+        assert_eq!(cached_page(&path).await.unwrap().body, "<p>one</p>");
+        // Likely within the same mtime tick; the length change still invalidates.
+        std::fs::write(&path, "<p>three</p>").unwrap();
+        assert_eq!(cached_page(&path).await.unwrap().body, "<p>three</p>");
+        std::fs::remove_file(&path).unwrap();
+        assert!(cached_page(&path).await.is_err());
+        assert!(!PAGES.lock().unwrap().contains_key(&path));
+    }
+
     // Not testing `ServeDir` behavior here; we keep unit tests focused on path/cache helpers.
 }
@@ -0,0 +1,141 @@
+use std::io;
+use std::net::IpAddr;
+use std::path::Path;
+
+use maxminddb::{MaxMindDBError, Reader, geoip2};
+
+/// A MaxMind DB (`.mmdb`) read with the `maxminddb` crate: GeoLite2/GeoIP2
+/// Country or City databases for a country code, ASN databases for the network.
+pub struct GeoIp {
+    reader: Reader<Vec<u8>>,
+}
+
+impl GeoIp {
+    pub fn open(path: &Path) -> io::Result<Self> {
+        Self::from_bytes(std::fs::read(path)?)
+    }
+
+    fn from_bytes(data: Vec<u8>) -> io::Result<Self> {
+        let reader = Reader::from_source(data)
+            .map_err(|e| io::Error::new(io::ErrorKind::InvalidData, e.to_string()))?;
+        Ok(Self { reader })
+    }
+
+    /// ISO 3166-1 alpha-2 country of `ip`, falling back to the registered country.
+    pub fn country(&self, ip: IpAddr) -> Option<String> {
+        let record: geoip2::Country = self.lookup(ip)?;
+        [record.country, record.registered_country]
+            .into_iter()
+            .find_map(|country| country?.iso_code)
+            .map(str::to_owned)
+    }
+
+    /// Autonomous system number of `ip` (GeoLite2/GeoIP2 ASN databases).
+    pub fn asn(&self, ip: IpAddr) -> Option<u32> {
+        self.lookup::<geoip2::Asn>(ip)?.autonomous_system_number
+    }
+
+    /// `None` for addresses the database has nothing on; a record that doesn't
+    /// decode is logged and treated the same.
+    fn lookup<'a, T: serde::Deserialize<'a>>(&'a self, ip: IpAddr) -> Option<T> {
+        let ip = ip.to_canonical();
+        // An IPv4-only tree would otherwise answer for the first 32 bits of an IPv6
+        // address.
+        if ip.is_ipv6() && self.reader.metadata.ip_version == 4 {
+            return None;
+        }
+        match self.reader.lookup(ip) {
+            Ok(record) => Some(record),
+            Err(MaxMindDBError::AddressNotFoundError(_)) => None,
+            Err(error) => {
+                tracing::debug!(ip = %ip, error = %error, "geoip lookup failed");
+                None
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const METADATA_MARKER: &[u8] = b"\xab\xcd\xefMaxMind.com";
+
+    fn string(s: &str) -> Vec<u8> {
+        let mut out = vec![(2 << 5) | s.len() as u8];
+        out.extend_from_slice(s.as_bytes());
+        out
+    }
+
+    /// One-node IPv4 tree: 0.0.0.0/1 maps to `record`, the rest is empty.
+    fn tiny_db_with(record: &[u8]) -> Vec<u8> {
+        let mut db = vec![0, 0, 17, 0, 0, 1];
+        db.extend_from_slice(&[0; 16]);
+        db.extend_from_slice(record);
+        db.extend_from_slice(METADATA_MARKER);
+        db.push((7 << 5) | 9);
+        db.extend(string("binary_format_major_version"));
+        db.extend_from_slice(&[(5 << 5) | 1, 2]);
+        db.extend(string("binary_format_minor_version"));
+        db.push(5 << 5);
+        // uint64 is an extended type: type 0 in the control byte, then 9 - 7.
+        db.extend(string("build_epoch"));
+        db.extend_from_slice(&[1, 2, 1]);
+        db.extend(string("database_type"));
+        db.extend(string("Test"));
+        db.extend(string("description"));
+        db.push(7 << 5);
+        db.extend(string("ip_version"));
+        db.extend_from_slice(&[(5 << 5) | 1, 4]);
+        // An empty array, extended type 11.
+        db.extend(string("languages"));
+        db.extend_from_slice(&[0, 4]);
+        db.extend(string("node_count"));
+        db.extend_from_slice(&[(6 << 5) | 1, 1]);
+        db.extend(string("record_size"));
+        db.extend_from_slice(&[(5 << 5) | 1, 24]);
+        db
+    }
+
+    /// {country: {iso_code: "DE"}}
+    fn tiny_db() -> Vec<u8> {
+        let mut record = vec![(7 << 5) | 1];
+        record.extend(string("country"));
+        record.push((7 << 5) | 1);
+        record.extend(string("iso_code"));
+        record.extend(string("DE"));
+        tiny_db_with(&record)
+    }
+
+    #[test]
+    fn country_lookup_walks_tree() {
+        let db = GeoIp::from_bytes(tiny_db()).unwrap();
+        assert_eq!(
+            db.country("10.0.0.1".parse().unwrap()).as_deref(),
+            Some("DE")
+        );
+        assert_eq!(
+            db.country("::ffff:10.0.0.1".parse().unwrap()).as_deref(),
+            Some("DE")
+        );
+        assert_eq!(db.country("200.0.0.1".parse().unwrap()), None);
+        assert_eq!(db.country("2001:db8::1".parse().unwrap()), None);
+    }
+
+    #[test]
+    fn asn_lookup_reads_uint32() {
+        // {autonomous_system_number: 64496}
+        let mut record = vec![(7 << 5) | 1];
+        record.extend(string("autonomous_system_number"));
+        record.extend_from_slice(&[(6 << 5) | 2, 0xfb, 0xf0]);
+        let db = GeoIp::from_bytes(tiny_db_with(&record)).unwrap();
+        assert_eq!(db.asn("10.0.0.1".parse().unwrap()), Some(64496));
+        assert_eq!(db.country("10.0.0.1".parse().unwrap()), None);
+        assert_eq!(db.asn("200.0.0.1".parse().unwrap()), None);
+    }
+
+    #[test]
+    fn rejects_file_without_metadata() {
+        assert!(GeoIp::from_bytes(vec![0; 64]).is_err());
+    }
+}
@@ -0,0 +1,138 @@
+use std::collections::HashMap;
+use std::net::IpAddr;
+use std::sync::{Arc, RwLock};
+use std::time::{Duration, Instant};
+
+use tokio::sync::{OwnedSemaphorePermit, Semaphore};
+
+use crate::access::{IpNet, parse_nets};
+use crate::config::{ConfigError, HoneypotConfig};
+use crate::middleware::path_matches;
+
+/// Requests held in the tarpit at once; past this, decoys answer right away so a
+/// scanner can't pin every connection.
+const MAX_TARPITTED: usize = 64;
+
+#[derive(Debug)]
+pub struct Honeypot {
+    paths: Vec<String>,
+    pub delay: Duration,
+    pub block: Duration,
+    exempt: Vec<IpNet>,
+    /// Blocked clients and when their block ends.
+    blocked: RwLock<HashMap<IpAddr, Instant>>,
+    tarpit: Arc<Semaphore>,
+}
+
+impl Honeypot {
+    pub fn load(config: &HoneypotConfig) -> Result<Self, ConfigError> {
+        Ok(Self {
+            paths: config.paths.clone(),
+            delay: Duration::from_secs(config.delay_seconds.into()),
+            block: Duration::from_secs(u64::from(config.block_minutes) * 60),
+            exempt: parse_nets(&config.exempt, "honeypot.exempt")?,
+            blocked: RwLock::default(),
+            tarpit: Arc::new(Semaphore::new(MAX_TARPITTED)),
+        })
+    }
+
+    /// `path` is a decoy or below one (`/wp-admin` also traps `/wp-admin/install.php`).
+    pub fn is_decoy(&self, path: &str) -> bool {
+        self.paths.iter().any(|decoy| path_matches(path, decoy))
+    }
+
+    pub fn is_exempt(&self, ip: IpAddr) -> bool {
+        self.exempt.iter().any(|net| net.contains(ip))
+    }
+
+    pub fn is_blocked(&self, ip: IpAddr) -> bool {
+        let blocked = self.blocked.read().unwrap_or_else(|e| e.into_inner());
+        blocked
+            .get(&ip)
+            .is_some_and(|until| *until > Instant::now())
+    }
+
+    /// Block `ip` for `remaining`, unless it is already blocked for longer.
+    pub fn block(&self, ip: IpAddr, remaining: Duration) {
+        if self.is_exempt(ip) {
+            return;
+        }
+        let until = Instant::now() + remaining;
+        let mut blocked = self.blocked.write().unwrap_or_else(|e| e.into_inner());
+        let entry = blocked.entry(ip).or_insert(until);
+        *entry = (*entry).max(until);
+    }
+
+    /// Take in the hits every replica audited (`audit::honeypot_hits`) and forget
+    /// blocks that have ended.
+    pub fn reload(&self, hits: impl IntoIterator<Item = (IpAddr, Duration)>) {
+        for (ip, remaining) in hits {
+            self.block(ip, remaining);
+        }
+        let now = Instant::now();
+        let mut blocked = self.blocked.write().unwrap_or_else(|e| e.into_inner());
+        blocked.retain(|_, until| *until > now);
+    }
+
+    /// A tarpit slot, or None when it is full.
+    pub fn tarpit_slot(&self) -> Option<OwnedSemaphorePermit> {
+        self.tarpit.clone().try_acquire_owned().ok()
+    }
+}
+
+/// Whether `peer` is a private or loopback address that `trusted_proxies` doesn't
+/// cover: most likely an undeclared reverse proxy, so every client would share
+/// the block.
+pub fn undeclared_proxy(peer: IpAddr, trusted: &[IpNet]) -> bool {
+    let peer = peer.to_canonical();
+    let private = match peer {
+        IpAddr::V4(ip) => ip.is_private() || ip.is_loopback() || ip.is_link_local(),
+        IpAddr::V6(ip) => ip.is_loopback() || ip.is_unique_local() || ip.is_unicast_link_local(),
+    };
+    private && !trusted.iter().any(|net| net.contains(peer))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn decoys_block_until_the_window_ends() {
+        let honeypot = Honeypot::load(&HoneypotConfig {
+            exempt: vec!["10.0.0.0/8".into()],
+            ..HoneypotConfig::default()
+        })
+        .unwrap();
+        assert!(honeypot.is_decoy("/.env"));
+        assert!(honeypot.is_decoy("/wp-admin/install.php"));
+        assert!(!honeypot.is_decoy("/.envy"));
+        assert!(!honeypot.is_decoy("/login"));
+
+        let scanner: IpAddr = "192.0.2.1".parse().unwrap();
+        let admin: IpAddr = "10.0.0.5".parse().unwrap();
+        honeypot.block(scanner, Duration::from_secs(60));
+        honeypot.block(admin, Duration::from_secs(60));
+        assert!(honeypot.is_blocked(scanner));
+        assert!(!honeypot.is_blocked(admin));
+
+        // A reload never shortens a block, and drops the ones that ended.
+        honeypot.reload([(scanner, Duration::ZERO)]);
+        assert!(honeypot.is_blocked(scanner));
+        let other: IpAddr = "192.0.2.2".parse().unwrap();
+        honeypot.block(other, Duration::ZERO);
+        honeypot.reload([]);
+        assert!(!honeypot.is_blocked(other));
+        assert_eq!(honeypot.blocked.read().unwrap().len(), 1);
+    }
+
+    #[test]
+    fn private_peers_outside_trusted_proxies_are_not_blocked() {
+        let trusted = parse_nets(&["10.0.0.0/8".into()], "trusted_proxies").unwrap();
+        let proxy = |ip: &str| undeclared_proxy(ip.parse().unwrap(), &trusted);
+        assert!(proxy("127.0.0.1"));
+        assert!(proxy("::ffff:192.168.1.2"));
+        assert!(proxy("fd00::1"));
+        assert!(!proxy("10.0.0.5"));
+        assert!(!proxy("192.0.2.1"));
+    }
+}
@@ -0,0 +1,400 @@
+use std::io::{self, Read, Write};
+#[cfg(unix)]
+use std::os::unix::process::CommandExt;
+use std::process::{Child, Command, Stdio};
+#[cfg(feature = "lua-policy")]
+use std::sync::Arc;
+use std::sync::mpsc;
+use std::time::{Duration, Instant};
+
+use serde::Serialize;
+use url::Url;
+
+use crate::config::{ConfigError, HookConfig, HookEvent, HookFailure, invalid};
+use crate::http;
+#[cfg(feature = "lua-policy")]
+use crate::lua_policy::LuaPolicy;
+
+/// How often a running command is checked for exit.
+const POLL: Duration = Duration::from_millis(10);
+/// Reasons end up in logs; a chatty hook shouldn't flood them.
+const MAX_REASON_CHARS: usize = 200;
+
+/// The `[[hooks]]` config, with URLs parsed once at startup, and the
+/// `[lua_policy]` script in `lua-policy` builds.
+#[derive(Default)]
+pub struct Hooks {
+    hooks: Vec<Hook>,
+    #[cfg(feature = "lua-policy")]
+    script: Option<Arc<LuaPolicy>>,
+}
+
+#[derive(Debug)]
+struct Hook {
+    event: HookEvent,
+    target: Target,
+    timeout: Duration,
+    on_error: HookFailure,
+}
+
+#[derive(Debug, Clone)]
+enum Target {
+    Command(Vec<String>),
+    Url(Url),
+}
+
+impl Target {
+    /// Names the hook in logs without the URL's path or query, which may hold a secret.
+    fn label(&self) -> String {
+        match self {
+            Target::Command(argv) => argv[0].clone(),
+            Target::Url(url) => url.host_str().unwrap_or_default().to_owned(),
+        }
+    }
+}
+
+/// What a hook is told, as one JSON object.
+#[derive(Debug, Serialize)]
+pub struct HookContext<'a> {
+    pub event: HookEvent,
+    pub user_id: &'a str,
+    pub user_name: Option<&'a str>,
+    /// Registration only: the name the new passkey will be stored under.
+    pub passkey_name: Option<&'a str>,
+    pub ip: Option<&'a str>,
+    pub country: Option<&'a str>,
+    pub user_agent: Option<&'a str>,
+    /// Sessions: the origin the session is opened on, when not `rp_origin`.
+    /// Redirects: the origin being handed the sign-in.
+    pub origin: Option<&'a str>,
+}
+
+enum Outcome {
+    Allow,
+    Deny(String),
+    Failed(String),
+}
+
+impl Hooks {
+    pub fn load(config: &[HookConfig]) -> Result<Self, ConfigError> {
+        Ok(Self {
+            hooks: config
+                .iter()
+                .map(|hook| {
+                    let target = match (&hook.command, &hook.url) {
+                        (Some(argv), _) => Target::Command(argv.clone()),
+                        (None, url) => Target::Url(
+                            Url::parse(url.as_deref().unwrap_or_default())
+                                .map_err(|e| invalid("hooks.url", e.to_string()))?,
+                        ),
+                    };
+                    Ok(Hook {
+                        event: hook.event,
+                        target,
+                        timeout: Duration::from_millis(hook.timeout_ms),
+                        on_error: hook.on_error,
+                    })
+                })
+                .collect::<Result<_, ConfigError>>()?,
+            #[cfg(feature = "lua-policy")]
+            script: None,
+        })
+    }
+
+    /// Ask `script` after the hooks, for every event it defines a function for.
+    #[cfg(feature = "lua-policy")]
+    pub fn with_script(self, script: LuaPolicy) -> Self {
+        Self {
+            script: Some(Arc::new(script)),
+            ..self
+        }
+    }
+
+    pub fn has(&self, event: HookEvent) -> bool {
+        self.has_script() || self.hooks.iter().any(|hook| hook.event == event)
+    }
+
+    #[cfg(feature = "lua-policy")]
+    fn has_script(&self) -> bool {
+        self.script.is_some()
+    }
+
+    #[cfg(not(feature = "lua-policy"))]
+    fn has_script(&self) -> bool {
+        false
+    }
+
+    /// Run the hooks for `context.event` in config order. The first veto wins and
+    /// its reason is returned; a hook that fails to answer counts as its `on_error`.
+    pub async fn run(&self, context: &HookContext<'_>) -> Result<(), String> {
+        let body = serde_json::to_vec(context).expect("hook context serializes");
+        for hook in self.hooks.iter().filter(|hook| hook.event == context.event) {
+            match call(hook, &body).await {
+                Outcome::Allow => {}
+                Outcome::Deny(reason) => return Err(reason),
+                Outcome::Failed(error) => {
+                    let hook_name = hook.target.label();
+                    tracing::warn!(hook = hook_name, error, "hook failed");
+                    if hook.on_error == HookFailure::Deny {
+                        return Err(format!("hook {hook_name} failed"));
+                    }
+                }
+            }
+        }
+        self.run_script(context).await
+    }
+
+    #[cfg(feature = "lua-policy")]
+    async fn run_script(&self, context: &HookContext<'_>) -> Result<(), String> {
+        let Some(script) = self.script.clone() else {
+            return Ok(());
+        };
+        let event = context.event;
+        let context = serde_json::to_value(context).expect("hook context serializes");
+        tokio::task::spawn_blocking(move || script.check(event, &context))
+            .await
+            .unwrap_or_else(|error| Err(format!("lua policy panicked: {error}")))
+    }
+
+    #[cfg(not(feature = "lua-policy"))]
+    async fn run_script(&self, _context: &HookContext<'_>) -> Result<(), String> {
+        Ok(())
+    }
+}
+
+async fn call(hook: &Hook, body: &[u8]) -> Outcome {
+    let target = hook.target.clone();
+    let body = body.to_vec();
+    let timeout = hook.timeout;
+    let task = tokio::task::spawn_blocking(move || match &target {
+        Target::Command(argv) => run_command(argv, &body, timeout),
+        Target::Url(url) => post(url, &body),
+    });
+    // Commands are killed at their deadline; a slow URL is abandoned to the HTTP
+    // client's own timeout.
+    match tokio::time::timeout(timeout + POLL, task).await {
+        Ok(Ok(outcome)) => outcome,
+        Ok(Err(error)) => Outcome::Failed(error.to_string()),
+        Err(_) => Outcome::Failed("timed out".into()),
+    }
+}
+
+/// Exit 0 allows, any other exit vetoes with the first line of stdout as the reason.
+fn run_command(argv: &[String], input: &[u8], timeout: Duration) -> Outcome {
+    let mut command = Command::new(&argv[0]);
+    command
+        .args(&argv[1..])
+        .stdin(Stdio::piped())
+        .stdout(Stdio::piped())
+        .stderr(Stdio::null());
+    // Its own process group, so a kill also reaches whatever it started.
+    #[cfg(unix)]
+    command.process_group(0);
+    let mut child = match command.spawn() {
+        Ok(child) => child,
+        Err(error) => return Outcome::Failed(error.to_string()),
+    };
+    // Drained while the hook runs: one that fills the pipe would otherwise block
+    // until its deadline. Only the start is kept for the reason.
+    let (output_tx, output_rx) = mpsc::channel();
+    if let Some(mut stdout) = child.stdout.take() {
+        std::thread::spawn(move || {
+            let mut output = Vec::new();
+            let _ = stdout.by_ref().take(4096).read_to_end(&mut output);
+            let _ = io::copy(&mut stdout, &mut io::sink());
+            let _ = output_tx.send(output);
+        });
+    }
+    // A hook that never reads its input closes the pipe early; that's its choice.
+    if let Some(mut stdin) = child.stdin.take() {
+        let _ = stdin.write_all(input);
+    }
+
+    let deadline = Instant::now() + timeout;
+    let status = loop {
+        match child.try_wait() {
+            Ok(Some(status)) => break status,
+            Ok(None) if Instant::now() < deadline => std::thread::sleep(POLL),
+            Ok(None) => {
+                kill(&mut child);
+                let _ = child.wait();
+                return Outcome::Failed("timed out".into());
+            }
+            Err(error) => return Outcome::Failed(error.to_string()),
+        }
+    };
+    // Anything the hook left running may hold stdout open; don't wait past the
+    // deadline, and end it then so the drain thread finishes too. While it holds
+    // the pipe its group still exists, so the id can't have been reused.
+    let output = output_rx
+        .recv_timeout(deadline.saturating_duration_since(Instant::now()))
+        .unwrap_or_else(|_| {
+            kill_group(child.id());
+            Vec::new()
+        });
+    match status.code() {
+        Some(0) => Outcome::Allow,
+        Some(code) => Outcome::Deny(reason(&output).unwrap_or_else(|| format!("exit {code}"))),
+        None => Outcome::Failed(format!("killed ({status})")),
+    }
+}
+
+/// The hook and, on Unix, everything in its process group.
+fn kill(child: &mut Child) {
+    kill_group(child.id());
+    let _ = child.kill();
+}
+
+#[cfg(unix)]
+fn kill_group(pgid: u32) {
+    // SAFETY: killpg only sends a signal; a group that is already gone is ESRCH.
+    unsafe {
+        libc::killpg(pgid as libc::pid_t, libc::SIGKILL);
+    }
+}
+
+#[cfg(not(unix))]
+fn kill_group(_pgid: u32) {}
+
+/// 2xx allows, 403 vetoes with the body's first line as the reason.
+fn post(url: &Url, body: &[u8]) -> Outcome {
+    match http::post(url, &[("Content-Type", "application/json")], body) {
+        Ok(response) if response.is_success() => Outcome::Allow,
+        Ok(response) if response.status == 403 => {
+            Outcome::Deny(reason(&response.body).unwrap_or_else(|| "HTTP 403".into()))
+        }
+        Ok(response) => Outcome::Failed(format!("HTTP {}", response.status)),
+        Err(error) => Outcome::Failed(error.to_string()),
+    }
+}
+
+fn reason(output: &[u8]) -> Option<String> {
+    let line = String::from_utf8_lossy(output)
+        .lines()
+        .map(str::trim)
+        .find(|line| !line.is_empty())?
+        .chars()
+        .take(MAX_REASON_CHARS)
+        .collect();
+    Some(line)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn hooks(command: &[&str], timeout_ms: u64, on_error: HookFailure) -> Hooks {
+        Hooks::load(&[HookConfig {
+            event: HookEvent::Session,
+            command: Some(command.iter().map(|arg| arg.to_string()).collect()),
+            url: None,
+            timeout_ms,
+            on_error,
+        }])
+        .unwrap()
+    }
+
+    fn context(event: HookEvent) -> HookContext<'static> {
+        HookContext {
+            event,
+            user_id: "u1",
+            user_name: Some("alice"),
+            passkey_name: None,
+            ip: Some("192.0.2.1"),
+            country: None,
+            user_agent: None,
+            origin: None,
+        }
+    }
+
+    #[tokio::test]
+    async fn exit_status_decides_and_stdout_is_the_reason() {
+        let allow = hooks(
+            &["sh", "-c", "grep -q '\"user_name\":\"alice\"'"],
+            2000,
+            HookFailure::Deny,
+        );
+        assert_eq!(allow.run(&context(HookEvent::Session)).await, Ok(()));
+
+        let deny = hooks(
+            &["sh", "-c", "echo; echo ' not today '; exit 3"],
+            2000,
+            HookFailure::Allow,
+        );
+        assert_eq!(
+            deny.run(&context(HookEvent::Session)).await,
+            Err("not today".into())
+        );
+        // Output beyond the pipe's buffer doesn't stall the hook.
+        let chatty = hooks(
+            &["sh", "-c", "head -c 1000000 /dev/zero | tr '\\0' x; exit 1"],
+            2000,
+            HookFailure::Allow,
+        );
+        assert_eq!(
+            chatty.run(&context(HookEvent::Session)).await,
+            Err("x".repeat(MAX_REASON_CHARS))
+        );
+        // Only hooks for the event run.
+        assert_eq!(deny.run(&context(HookEvent::Registration)).await, Ok(()));
+        assert_eq!(
+            hooks(&["false"], 2000, HookFailure::Allow)
+                .run(&context(HookEvent::Session))
+                .await,
+            Err("exit 1".into())
+        );
+    }
+
+    #[tokio::test]
+    async fn failures_follow_on_error() {
+        let slow = hooks(&["sleep", "5"], 50, HookFailure::Deny);
+        let started = Instant::now();
+        assert_eq!(
+            slow.run(&context(HookEvent::Session)).await,
+            Err("hook sleep failed".into())
+        );
+        assert!(started.elapsed() < Duration::from_secs(2));
+
+        let missing = hooks(&["/nonexistent/den-hook"], 2000, HookFailure::Allow);
+        assert_eq!(missing.run(&context(HookEvent::Session)).await, Ok(()));
+    }
+
+    /// Whether `pid` has exited, reaped or not.
+    #[cfg(target_os = "linux")]
+    fn gone(pid: &str) -> bool {
+        std::fs::read_to_string(format!("/proc/{pid}/stat")).map_or(true, |stat| {
+            stat.rsplit_once(") ")
+                .is_some_and(|(_, s)| s.starts_with('Z'))
+        })
+    }
+
+    #[cfg(target_os = "linux")]
+    #[tokio::test]
+    async fn kills_what_the_hook_started() {
+        let dir = std::env::temp_dir().join(format!("den-hooks-{}", std::process::id()));
+        std::fs::create_dir_all(&dir).unwrap();
+        for (name, script, expected) in [
+            (
+                "timeout",
+                "sleep 30 & echo $! > {pid}; wait",
+                Err("hook sh failed".into()),
+            ),
+            ("exited", "sleep 30 & echo $! > {pid}; exit 0", Ok(())),
+        ] {
+            let pid_file = dir.join(name);
+            let script = script.replace("{pid}", &pid_file.to_string_lossy());
+            let hook = hooks(&["sh", "-c", &script], 200, HookFailure::Deny);
+            let started = Instant::now();
+            assert_eq!(hook.run(&context(HookEvent::Session)).await, expected);
+            assert!(started.elapsed() < Duration::from_secs(2));
+            let pid = std::fs::read_to_string(&pid_file).unwrap();
+            let pid = pid.trim();
+            let deadline = Instant::now() + Duration::from_secs(2);
+            while !gone(pid) && Instant::now() < deadline {
+                std::thread::sleep(POLL);
+            }
+            assert!(gone(pid), "{name}: background process {pid} still running");
+        }
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+}
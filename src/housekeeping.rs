@@ -0,0 +1,209 @@
+use std::sync::atomic::Ordering;
+use std::time::Duration;
+
+use time::OffsetDateTime;
+use tokio::time::{Instant, MissedTickBehavior};
+
+use crate::audit;
+use crate::notify::SecurityEvent;
+use crate::session;
+use crate::signing_key::{self, SigningKey};
+use crate::state::AppState;
+use crate::storage::StorageError;
+use crate::usage;
+
+const LEASE: &str = "housekeeping";
+const INTERVAL: Duration = Duration::from_secs(60);
+/// Outlives a late tick so leadership doesn't flap, yet a crashed leader is
+/// replaced within a few minutes.
+const LEASE_TTL_SECONDS: i64 = 180;
+/// Expired rows deleted per statement, so the write lock is never held long.
+const PURGE_BATCH: i64 = 500;
+/// Batches per tick; a backlog past this is left for the next tick.
+const MAX_PURGE_BATCHES: u32 = 20;
+
+/// Once a minute every replica checks its SLO burn rate and reloads the JWT signing
+/// keys, runtime allowed hosts and honeypot blocks; the holder of the `housekeeping`
+/// lease also rotates the signing key when due, purges expired challenges, burned token ids, invites, ended sessions and deleted passkeys past their
+/// grace period, and updates the usage counters.
+pub fn spawn(state: AppState) {
+    tokio::spawn(async move {
+        // Startup already loaded everything; the first tick comes one interval later.
+        let mut interval = tokio::time::interval_at(Instant::now() + INTERVAL, INTERVAL);
+        interval.set_missed_tick_behavior(MissedTickBehavior::Delay);
+        let mut leader = false;
+        loop {
+            interval.tick().await;
+            check_slo(&state);
+            reload_signing_keys(&state).await;
+            match state.db.allowed_hosts().await {
+                Ok(hosts) => state.allowed_hosts.set_runtime(hosts),
+                Err(error) => tracing::warn!(error = %error, "failed to reload allowed hosts"),
+            }
+            reload_honeypot(&state).await;
+            let held = state
+                .storage
+                .acquire_lease(LEASE, &state.instance_id, LEASE_TTL_SECONDS)
+                .await
+                .unwrap_or_else(|error| {
+                    tracing::warn!(error = %error, "housekeeping lease check failed");
+                    false
+                });
+            if held != leader {
+                tracing::info!(
+                    instance_id = &*state.instance_id,
+                    leader = held,
+                    "housekeeping leadership changed"
+                );
+                leader = held;
+            }
+            if leader {
+                run(&state).await;
+            }
+        }
+    });
+}
+
+/// A key the leader adds is verified everywhere within a minute, before
+/// `signing_key::SIGN_AFTER` lets anyone sign with it.
+async fn reload_signing_keys(state: &AppState) {
+    let held = state.signing_keys.all();
+    match state.storage.signing_keys(&held).await {
+        Ok(keys) => state.signing_keys.set(keys),
+        Err(error) => tracing::warn!(error = %error, "failed to reload signing keys"),
+    }
+}
+
+/// Blocks follow the audit log, so a client trapped on one replica is refused by all
+/// of them within a minute, and across restarts.
+async fn reload_honeypot(state: &AppState) {
+    let Some(honeypot) = &state.honeypot else {
+        return;
+    };
+    let block_minutes = (honeypot.block.as_secs() / 60) as u32;
+    match audit::honeypot_hits(&state.db, block_minutes).await {
+        Ok(hits) => honeypot.reload(hits.into_iter().filter_map(|(ip, seconds)| {
+            Some((ip.parse().ok()?, Duration::from_secs(seconds.max(0) as u64)))
+        })),
+        Err(error) => tracing::warn!(error = %error, "failed to reload honeypot blocks"),
+    }
+}
+
+/// Every replica judges its own traffic: the burn rate comes from its in-memory
+/// window, so this runs outside the lease.
+fn check_slo(state: &AppState) {
+    let Some(burn) = state.metrics.check_slo() else {
+        return;
+    };
+    tracing::warn!(
+        objective = burn.objective,
+        burn_rate_5m = burn.fast,
+        burn_rate_1h = burn.slow,
+        "error budget burning fast"
+    );
+    state.notifier.send(SecurityEvent::SloBurn {
+        instance_id: state.instance_id.to_string(),
+        // Rounded so 0.999 reads "99.9%", not a float artifact.
+        objective: format!("{}%", (burn.objective * 10_000.0).round() / 100.0),
+        burn_rate_5m: format!("{:.1}", burn.fast),
+        burn_rate_1h: format!("{:.1}", burn.slow),
+    });
+}
+
+async fn run(state: &AppState) {
+    if let Err(error) = rotate_signing_key(state).await {
+        tracing::warn!(error = %error, "failed to rotate the signing key");
+    }
+    match purge_in_batches("challenge", |limit| {
+        state.storage.purge_expired_challenges(limit)
+    })
+    .await
+    {
+        Ok(purged) => {
+            state
+                .expired_challenges_purged
+                .fetch_add(purged, Ordering::Relaxed);
+            tracing::debug!(purged, "purged expired challenges");
+        }
+        Err(error) => tracing::warn!(error = %error, "failed to purge expired challenges"),
+    }
+    match purge_in_batches("burned token", |limit| {
+        state.storage.purge_used_redirect_tokens(limit)
+    })
+    .await
+    {
+        Ok(purged) => tracing::debug!(purged, "purged burned token ids"),
+        Err(error) => tracing::warn!(error = %error, "failed to purge burned token ids"),
+    }
+    if let Err(error) = state.db.purge_expired_invites().await {
+        tracing::warn!(error = %error, "failed to purge expired invites");
+    }
+    if let Err(error) = session::purge_ended(&state.storage).await {
+        tracing::warn!(error = %error, "failed to purge ended sessions");
+    }
+    match state
+        .db
+        .purge_deleted_passkeys(state.passkeys.delete_grace_hours)
+        .await
+    {
+        Ok(0) => {}
+        Ok(purged) => tracing::info!(purged, "purged deleted passkeys"),
+        Err(error) => tracing::warn!(error = %error, "failed to purge deleted passkeys"),
+    }
+    if let Err(error) = record_usage(state).await {
+        tracing::warn!(error = %error, "failed to update usage counters");
+    }
+}
+
+/// Add a signing key once the newest is `ROTATE_AFTER` old; replicas pick it up
+/// on their next reload.
+async fn rotate_signing_key(state: &AppState) -> Result<(), StorageError> {
+    let due = (OffsetDateTime::now_utc() - signing_key::ROTATE_AFTER).unix_timestamp();
+    if state
+        .signing_keys
+        .all()
+        .first()
+        .is_some_and(|newest| newest.created > due)
+    {
+        return Ok(());
+    }
+    let key = SigningKey::generate();
+    state.storage.add_signing_key(&key).await?;
+    tracing::info!(kid = key.kid, "rotated the JWT signing key");
+    let held = state.signing_keys.all();
+    state
+        .signing_keys
+        .set(state.storage.signing_keys(&held).await?);
+    Ok(())
+}
+
+/// Today's session count, read once a minute so no session id needs storing.
+async fn record_usage(state: &AppState) -> Result<(), StorageError> {
+    let used_today = session::used_today(&state.storage).await?;
+    usage::record_sessions(&state.db, used_today).await?;
+    usage::purge(&state.db).await?;
+    Ok(())
+}
+
+/// Delete expired rows batch by batch while batches come back full, so a login
+/// burst drains within a tick or two and a quiet minute costs one query.
+async fn purge_in_batches<F, Fut>(what: &str, mut purge: F) -> Result<u64, StorageError>
+where
+    F: FnMut(i64) -> Fut,
+    Fut: Future<Output = Result<u64, StorageError>>,
+{
+    let mut purged = 0;
+    for _ in 0..MAX_PURGE_BATCHES {
+        let deleted = purge(PURGE_BATCH).await?;
+        purged += deleted;
+        if deleted < PURGE_BATCH as u64 {
+            return Ok(purged);
+        }
+        tokio::task::yield_now().await;
+    }
+    tracing::warn!(
+        purged,
+        "expired {what} backlog outlasted one housekeeping pass"
+    );
+    Ok(purged)
+}
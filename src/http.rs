@@ -0,0 +1,188 @@
+use std::io::{self, Read};
+use std::sync::OnceLock;
+use std::time::Duration;
+
+use reqwest::blocking::Client;
+use reqwest::redirect::Policy;
+use url::Url;
+
+/// Deadline for a whole exchange: connect, TLS, request and the full body.
+const TIMEOUT: Duration = Duration::from_secs(10);
+/// `get_large` bodies are bigger; they get longer to arrive.
+const LARGE_TIMEOUT: Duration = Duration::from_secs(120);
+/// Responses we care about (discovery documents, JWKS, tokens) are small.
+const MAX_RESPONSE_BYTES: u64 = 1 << 20;
+
+pub struct Response {
+    pub status: u16,
+    pub body: Vec<u8>,
+}
+
+impl Response {
+    pub fn is_success(&self) -> bool {
+        (200..300).contains(&self.status)
+    }
+}
+
+/// Shared blocking client; call only off the async runtime (`spawn_blocking`).
+/// Redirects are not followed, so a URL from config is the only host reached.
+fn client() -> io::Result<&'static Client> {
+    static CLIENT: OnceLock<Client> = OnceLock::new();
+    if let Some(client) = CLIENT.get() {
+        return Ok(client);
+    }
+    let client = Client::builder()
+        .user_agent("den")
+        .redirect(Policy::none())
+        .connect_timeout(TIMEOUT)
+        .build()
+        .map_err(io::Error::other)?;
+    Ok(CLIENT.get_or_init(|| client))
+}
+
+/// Blocking HTTP request with a total deadline and a cap on the response body.
+pub fn send(
+    method: &str,
+    url: &Url,
+    headers: &[(&str, &str)],
+    body: &[u8],
+) -> io::Result<Response> {
+    send_limited(method, url, headers, body, MAX_RESPONSE_BYTES, TIMEOUT)
+}
+
+fn send_limited(
+    method: &str,
+    url: &Url,
+    headers: &[(&str, &str)],
+    body: &[u8],
+    max_response_bytes: u64,
+    timeout: Duration,
+) -> io::Result<Response> {
+    if !matches!(url.scheme(), "http" | "https") {
+        return Err(io::Error::new(
+            io::ErrorKind::InvalidInput,
+            format!("unsupported scheme {}", url.scheme()),
+        ));
+    }
+    let method = reqwest::Method::from_bytes(method.as_bytes())
+        .map_err(|e| io::Error::new(io::ErrorKind::InvalidInput, e))?;
+    let mut request = client()?
+        .request(method, url.clone())
+        .timeout(timeout)
+        .body(body.to_vec());
+    for (name, value) in headers {
+        request = request.header(*name, *value);
+    }
+    let response = request.send().map_err(request_error)?;
+    let status = response.status().as_u16();
+    if response
+        .content_length()
+        .is_some_and(|len| len > max_response_bytes)
+    {
+        return Err(too_large(max_response_bytes));
+    }
+    let mut body = Vec::new();
+    // The request's deadline keeps running while the body is read.
+    response
+        .take(max_response_bytes + 1)
+        .read_to_end(&mut body)?;
+    if body.len() as u64 > max_response_bytes {
+        return Err(too_large(max_response_bytes));
+    }
+    Ok(Response { status, body })
+}
+
+pub fn get(url: &Url, headers: &[(&str, &str)]) -> io::Result<Response> {
+    send("GET", url, headers, &[])
+}
+
+/// `get` for bodies past the usual 1 MiB cap, such as web assets; anything longer
+/// is an error.
+pub fn get_large(
+    url: &Url,
+    headers: &[(&str, &str)],
+    max_response_bytes: u64,
+) -> io::Result<Response> {
+    send_limited("GET", url, headers, &[], max_response_bytes, LARGE_TIMEOUT)
+}
+
+pub fn post(url: &Url, headers: &[(&str, &str)], body: &[u8]) -> io::Result<Response> {
+    send("POST", url, headers, body)
+}
+
+fn request_error(error: reqwest::Error) -> io::Error {
+    let kind = if error.is_timeout() {
+        io::ErrorKind::TimedOut
+    } else {
+        io::ErrorKind::Other
+    };
+    io::Error::new(kind, error)
+}
+
+fn too_large(max_response_bytes: u64) -> io::Error {
+    io::Error::new(
+        io::ErrorKind::InvalidData,
+        format!("response over {max_response_bytes} bytes"),
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use std::io::Write;
+    use std::net::TcpListener;
+    use std::time::Instant;
+
+    use super::*;
+
+    /// A one-shot server writing `response` and then holding the connection open.
+    fn serve(response: &'static [u8]) -> Url {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let url = Url::parse(&format!("http://{}/", listener.local_addr().unwrap())).unwrap();
+        std::thread::spawn(move || {
+            let (mut stream, _) = listener.accept().unwrap();
+            let mut request = [0u8; 1024];
+            let _ = stream.read(&mut request);
+            let _ = stream.write_all(response);
+            std::thread::sleep(Duration::from_secs(5));
+        });
+        url
+    }
+
+    #[test]
+    fn reads_a_framed_body_without_waiting_for_close() {
+        let url = serve(b"HTTP/1.1 200 OK\r\nContent-Length: 2\r\n\r\n{}");
+        let started = Instant::now();
+        let response = get(&url, &[]).unwrap();
+        assert_eq!(
+            (response.status, response.body.as_slice()),
+            (200, &b"{}"[..])
+        );
+        assert!(started.elapsed() < Duration::from_secs(2));
+    }
+
+    #[test]
+    fn a_stalled_body_hits_the_deadline() {
+        let url = serve(b"HTTP/1.1 200 OK\r\nContent-Length: 100\r\n\r\n{");
+        let started = Instant::now();
+        let error = send_limited(
+            "GET",
+            &url,
+            &[],
+            &[],
+            MAX_RESPONSE_BYTES,
+            Duration::from_millis(300),
+        )
+        .err()
+        .unwrap();
+        assert!(started.elapsed() < Duration::from_secs(2), "{error}");
+    }
+
+    #[test]
+    fn oversized_bodies_are_errors() {
+        let url = serve(b"HTTP/1.1 200 OK\r\nContent-Length: 4\r\n\r\nabcd");
+        let error = send_limited("GET", &url, &[], &[], 3, TIMEOUT)
+            .err()
+            .unwrap();
+        assert_eq!(error.kind(), io::ErrorKind::InvalidData);
+    }
+}
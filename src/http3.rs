@@ -0,0 +1,119 @@
+//! HTTP/3 next to the TLS listener (`[tls] enable_h3`): a QUIC endpoint on the
+//! same port over UDP, answering with the same router.
+
+use std::io;
+use std::net::SocketAddr;
+use std::sync::Arc;
+
+use axum::Router;
+use axum::body::Body;
+use axum::extract::ConnectInfo;
+use axum::http::{HeaderValue, Request, Response, StatusCode, header};
+use bytes::{Buf, Bytes, BytesMut};
+use h3::server::RequestResolver;
+use http_body_util::BodyExt;
+use quinn::crypto::rustls::QuicServerConfig;
+use tower::ServiceExt;
+
+type BoxError = Box<dyn std::error::Error + Send + Sync>;
+
+/// Bind UDP `addr` with `server`, which must allow TLS 1.3 and offer ALPN `h3`.
+pub fn endpoint(
+    server: Arc<rustls::ServerConfig>,
+    addr: SocketAddr,
+) -> io::Result<quinn::Endpoint> {
+    let crypto = QuicServerConfig::try_from(server).map_err(io::Error::other)?;
+    quinn::Endpoint::server(quinn::ServerConfig::with_crypto(Arc::new(crypto)), addr)
+}
+
+/// `Alt-Svc` on every response, so browsers that reached den over TCP try QUIC next.
+pub fn advertise(app: Router, port: u16) -> Router {
+    let alt_svc = HeaderValue::try_from(format!("h3=\":{port}\"; ma=86400"))
+        .expect("a port is a valid header value");
+    app.layer(axum::middleware::map_response(
+        move |mut response: axum::response::Response| {
+            let alt_svc = alt_svc.clone();
+            async move {
+                response.headers_mut().insert(header::ALT_SVC, alt_svc);
+                response
+            }
+        },
+    ))
+}
+
+/// Accept QUIC connections until the endpoint closes, each in its own task.
+pub async fn serve(endpoint: quinn::Endpoint, app: Router) -> io::Result<()> {
+    while let Some(incoming) = endpoint.accept().await {
+        tokio::spawn(connection(incoming, app.clone()));
+    }
+    Ok(())
+}
+
+async fn connection(incoming: quinn::Incoming, app: Router) {
+    let peer = incoming.remote_address();
+    let connection = match incoming.await {
+        Ok(connection) => connection,
+        Err(e) => return tracing::debug!(%peer, "QUIC handshake failed: {e}"),
+    };
+    let mut connection =
+        match h3::server::Connection::new(h3_quinn::Connection::new(connection)).await {
+            Ok(connection) => connection,
+            Err(e) => return tracing::debug!(%peer, "HTTP/3 setup failed: {e}"),
+        };
+    loop {
+        match connection.accept().await {
+            Ok(Some(resolver)) => {
+                let app = app.clone();
+                tokio::spawn(async move {
+                    if let Err(e) = respond(resolver, app, peer).await {
+                        tracing::debug!(%peer, "HTTP/3 request failed: {e}");
+                    }
+                });
+            }
+            Ok(None) => break,
+            Err(e) => {
+                tracing::debug!(%peer, "HTTP/3 connection ended: {e}");
+                break;
+            }
+        }
+    }
+}
+
+async fn respond(
+    resolver: RequestResolver<h3_quinn::Connection, Bytes>,
+    app: Router,
+    peer: SocketAddr,
+) -> Result<(), BoxError> {
+    let (request, stream) = resolver.resolve_request().await?;
+    let (mut send, mut recv) = stream.split();
+
+    let mut body = BytesMut::new();
+    while let Some(mut chunk) = recv.recv_data().await? {
+        // Bodies are buffered before routing, so the router's limit is applied here.
+        if body.len() + chunk.remaining() > crate::MAX_REQUEST_BODY {
+            let response = Response::builder()
+                .status(StatusCode::PAYLOAD_TOO_LARGE)
+                .body(())?;
+            send.send_response(response).await?;
+            return Ok(send.finish().await?);
+        }
+        body.extend_from_slice(&chunk.copy_to_bytes(chunk.remaining()));
+    }
+    let mut request: Request<Body> = request.map(|()| Body::from(body.freeze()));
+    request.extensions_mut().insert(ConnectInfo(peer));
+
+    let response = app.oneshot(request).await.unwrap_or_else(|e| match e {});
+    let (parts, mut body) = response.into_parts();
+    send.send_response(Response::from_parts(parts, ())).await?;
+    while let Some(frame) = body.frame().await {
+        match frame?.into_data() {
+            Ok(data) => send.send_data(data).await?,
+            Err(frame) => {
+                if let Ok(trailers) = frame.into_trailers() {
+                    send.send_trailers(trailers).await?;
+                }
+            }
+        }
+    }
+    Ok(send.finish().await?)
+}
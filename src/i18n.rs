@@ -0,0 +1,179 @@
+use std::collections::BTreeMap;
+
+/// Catalogs compiled into the binary, keyed by primary language subtag.
+const BUNDLED: &[(&str, &str)] = &[
+    ("en", include_str!("../i18n/en.toml")),
+    ("de", include_str!("../i18n/de.toml")),
+    ("fr", include_str!("../i18n/fr.toml")),
+];
+/// Complete catalog that fills keys missing from the others.
+const FALLBACK: &str = "en";
+
+/// Translated UI and error strings, flattened to dotted keys (`errors.passkey_rejected`).
+pub struct Catalogs {
+    default: &'static str,
+    strings: BTreeMap<&'static str, BTreeMap<String, String>>,
+}
+
+impl Catalogs {
+    pub fn load(default_language: Option<&str>) -> Self {
+        let parsed: BTreeMap<&'static str, BTreeMap<String, String>> = BUNDLED
+            .iter()
+            .map(|(lang, source)| {
+                let table: toml::Table = toml::from_str(source)
+                    .unwrap_or_else(|e| panic!("bundled {lang} catalog is invalid: {e}"));
+                let mut strings = BTreeMap::new();
+                flatten("", table, &mut strings);
+                (*lang, strings)
+            })
+            .collect();
+        let fallback = &parsed[FALLBACK];
+        let strings = parsed
+            .iter()
+            .map(|(lang, strings)| {
+                let mut merged = fallback.clone();
+                merged.extend(strings.clone());
+                (*lang, merged)
+            })
+            .collect();
+        let default = match default_language {
+            Some(lang) => bundled(lang).unwrap_or_else(|| {
+                panic!(
+                    "default_language must be one of {:?}, got {lang:?}",
+                    languages().collect::<Vec<_>>()
+                )
+            }),
+            None => FALLBACK,
+        };
+        Self { default, strings }
+    }
+
+    /// Pick a catalog: an explicitly requested language if bundled (`auto` requests
+    /// none), then the best `Accept-Language` match, then `default_language`.
+    pub fn negotiate(&self, requested: &str, accept_language: Option<&str>) -> &'static str {
+        bundled(requested)
+            .or_else(|| {
+                accept_language
+                    .into_iter()
+                    .flat_map(preferred_languages)
+                    .find_map(|tag| bundled(&tag))
+            })
+            .unwrap_or(self.default)
+    }
+
+    pub fn strings(&self, lang: &str) -> &BTreeMap<String, String> {
+        &self.strings[lang]
+    }
+}
+
+pub fn languages() -> impl Iterator<Item = &'static str> {
+    BUNDLED.iter().map(|(lang, _)| *lang)
+}
+
+/// The entry of `available` (language tags, as in a localized web build's
+/// directories) that best fits `accept_language`: an exact tag first, then the
+/// requested primary subtag alone (`de-AT` → `de`), then any of its regions.
+pub fn best_match<'a>(accept_language: &str, available: &'a [String]) -> Option<&'a str> {
+    let normalize = |tag: &str| tag.replace('_', "-").to_ascii_lowercase();
+    let primary = |tag: &str| {
+        normalize(tag)
+            .split('-')
+            .next()
+            .unwrap_or_default()
+            .to_owned()
+    };
+    preferred_languages(accept_language).iter().find_map(|tag| {
+        let (tag, wanted) = (normalize(tag), primary(tag));
+        let exact = available.iter().find(|a| normalize(a) == tag);
+        let language = available.iter().find(|a| normalize(a) == wanted);
+        let region = available.iter().find(|a| primary(a) == wanted);
+        exact.or(language).or(region).map(String::as_str)
+    })
+}
+
+/// Bundled catalog for a language tag, matching on the primary subtag (`de-AT` → `de`).
+fn bundled(tag: &str) -> Option<&'static str> {
+    let primary = tag.split(['-', '_']).next()?.trim();
+    languages().find(|lang| lang.eq_ignore_ascii_case(primary))
+}
+
+/// `Accept-Language` tags by descending quality, dropping `q=0` and `*`.
+fn preferred_languages(header: &str) -> Vec<String> {
+    let mut tags: Vec<(String, f32)> = header
+        .split(',')
+        .filter_map(|item| {
+            let mut parts = item.split(';');
+            let tag = parts.next()?.trim();
+            let quality = parts
+                .find_map(|p| p.trim().strip_prefix("q="))
+                .map_or(Some(1.0), |q| q.trim().parse::<f32>().ok())?;
+            (!tag.is_empty() && tag != "*" && quality > 0.0).then(|| (tag.to_owned(), quality))
+        })
+        .collect();
+    // Stable sort keeps header order between equal qualities.
+    tags.sort_by(|a, b| b.1.total_cmp(&a.1));
+    tags.into_iter().map(|(tag, _)| tag).collect()
+}
+
+fn flatten(prefix: &str, table: toml::Table, out: &mut BTreeMap<String, String>) {
+    for (key, value) in table {
+        let key = if prefix.is_empty() {
+            key
+        } else {
+            format!("{prefix}.{key}")
+        };
+        match value {
+            toml::Value::String(s) => {
+                out.insert(key, s);
+            }
+            toml::Value::Table(t) => flatten(&key, t, out),
+            other => panic!("catalog key {key} must be a string or table, got {other}"),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn catalogs_only_translate_fallback_keys() {
+        let catalogs = Catalogs::load(None);
+        let fallback = catalogs.strings(FALLBACK);
+        for lang in languages() {
+            let strings = catalogs.strings(lang);
+            assert_eq!(
+                strings.keys().collect::<Vec<_>>(),
+                fallback.keys().collect::<Vec<_>>(),
+                "{lang} catalog has keys the {FALLBACK} one lacks"
+            );
+        }
+        assert!(fallback.contains_key("errors.passkey_rejected"));
+    }
+
+    #[test]
+    fn negotiate_prefers_request_then_accept_language() {
+        let catalogs = Catalogs::load(Some("fr"));
+        assert_eq!(catalogs.negotiate("de-CH", Some("en")), "de");
+        assert_eq!(
+            catalogs.negotiate("auto", Some("nl;q=0.9, en;q=0.5, de-AT;q=0.8")),
+            "de"
+        );
+        assert_eq!(catalogs.negotiate("auto", Some("de;q=0, nl")), "fr");
+        assert_eq!(catalogs.negotiate("auto", None), "fr");
+    }
+
+    #[test]
+    fn best_match_prefers_exact_tags_then_the_primary_language() {
+        let available: Vec<String> = ["de", "pt-BR", "pt-PT", "zh_Hant"].map(String::from).into();
+        assert_eq!(best_match("pt-pt, de;q=0.5", &available), Some("pt-PT"));
+        assert_eq!(best_match("de-AT", &available), Some("de"));
+        assert_eq!(best_match("pt", &available), Some("pt-BR"));
+        assert_eq!(
+            best_match("zh-Hant-TW;q=0.2, zh-hant", &available),
+            Some("zh_Hant")
+        );
+        assert_eq!(best_match("fr, en;q=0.8", &available), None);
+        assert_eq!(best_match("", &available), None);
+    }
+}
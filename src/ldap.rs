@@ -0,0 +1,98 @@
+use std::io;
+use std::time::Duration;
+
+use ldap3::{LdapConnAsync, LdapConnSettings, LdapError, dn_escape};
+
+use crate::config::LdapConfig;
+
+const LDAP_TIMEOUT: Duration = Duration::from_secs(10);
+const RESULT_SUCCESS: u32 = 0;
+const RESULT_INVALID_CREDENTIALS: u32 = 49;
+
+/// Verifies passwords with a single LDAP simple bind (`ldap3`); no searches, no
+/// writes.
+pub struct LdapVerifier {
+    config: LdapConfig,
+}
+
+impl LdapVerifier {
+    pub fn new(config: LdapConfig) -> Self {
+        Self { config }
+    }
+
+    pub fn allows(&self, user: &str) -> bool {
+        self.config.allowed_users.iter().any(|u| u == user)
+    }
+
+    /// `Ok(false)` for wrong credentials; `Err` when the directory couldn't answer.
+    pub async fn verify(&self, user: &str, password: &str) -> io::Result<bool> {
+        // An empty password is an "unauthenticated bind" that most servers accept.
+        if user.is_empty() || password.is_empty() {
+            return Ok(false);
+        }
+        let settings = LdapConnSettings::new().set_conn_timeout(LDAP_TIMEOUT);
+        let (connection, mut ldap) = LdapConnAsync::with_settings(settings, &self.config.url)
+            .await
+            .map_err(ldap_error)?;
+        tokio::spawn(async move {
+            if let Err(error) = connection.drive().await {
+                tracing::debug!(error = %error, "ldap connection ended");
+            }
+        });
+        let result = ldap
+            .with_timeout(LDAP_TIMEOUT)
+            .simple_bind(&self.bind_dn(user), password)
+            .await
+            .map_err(ldap_error)?;
+        let _ = ldap.unbind().await;
+        match result.rc {
+            RESULT_SUCCESS => Ok(true),
+            RESULT_INVALID_CREDENTIALS => Ok(false),
+            code => Err(io::Error::other(format!(
+                "ldap bind failed with result {code}"
+            ))),
+        }
+    }
+
+    /// RFC 4514 escaping so a login name can't add RDNs to the bind DN.
+    fn bind_dn(&self, user: &str) -> String {
+        self.config.bind_dn.replace("{user}", &dn_escape(user))
+    }
+}
+
+fn ldap_error(error: LdapError) -> io::Error {
+    match error {
+        LdapError::Io { source } => source,
+        LdapError::Timeout { .. } => io::Error::new(io::ErrorKind::TimedOut, "ldap timed out"),
+        error => io::Error::other(error),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn dn_values_are_escaped() {
+        let verifier = LdapVerifier::new(LdapConfig {
+            url: "ldaps://ldap.lan".into(),
+            bind_dn: "uid={user},ou=people,dc=home".into(),
+            allowed_users: vec!["brian".into()],
+        });
+        assert_eq!(verifier.bind_dn("brian"), "uid=brian,ou=people,dc=home");
+        assert_eq!(
+            verifier.bind_dn("x,ou=admins"),
+            "uid=x\\2cou\\3dadmins,ou=people,dc=home"
+        );
+    }
+
+    #[tokio::test]
+    async fn empty_passwords_never_reach_the_directory() {
+        let verifier = LdapVerifier::new(LdapConfig {
+            url: "ldap://192.0.2.1".into(),
+            bind_dn: "uid={user}".into(),
+            allowed_users: Vec::new(),
+        });
+        assert!(!verifier.verify("brian", "").await.unwrap());
+    }
+}
@@ -0,0 +1,524 @@
+#![recursion_limit = "256"]
+
+pub mod access;
+pub mod anomaly;
+pub mod api;
+pub mod apps;
+pub mod archive;
+pub mod attestation;
+pub mod audit;
+pub mod audit_export;
+pub mod auth;
+pub mod backup;
+pub mod branding;
+pub mod breach;
+pub mod cli;
+pub mod client_cert;
+pub mod config;
+pub mod connected_app;
+pub mod db;
+pub mod diagnostics;
+pub mod doctor;
+pub mod frontend;
+pub mod geoip;
+pub mod honeypot;
+pub mod hooks;
+pub mod housekeeping;
+pub mod http;
+#[cfg(feature = "http3")]
+pub mod http3;
+pub mod i18n;
+pub mod ldap;
+pub mod listen;
+pub mod logout;
+#[cfg(feature = "lua-policy")]
+pub mod lua_policy;
+pub mod mailer;
+pub mod metrics;
+pub mod middleware;
+pub mod notify;
+pub mod oidc;
+pub mod origin;
+pub mod plugin;
+pub mod policy;
+pub mod puzzle;
+pub mod redis;
+pub mod service_account;
+pub mod session;
+pub mod signing_key;
+pub mod state;
+pub mod storage;
+pub mod systemd;
+pub mod template;
+pub mod totp;
+pub mod usage;
+pub mod user_agent;
+pub mod web_integrity;
+pub mod web_source;
+pub mod well_known;
+
+use std::path::Path;
+use std::sync::Arc;
+use std::sync::atomic::{AtomicBool, AtomicU64};
+use std::time::{Duration, Instant};
+
+use access::AccessControl;
+use apps::AppPolicies;
+use attestation::AttestationPolicy;
+use audit_export::AuditExporter;
+use auth::CookieSettings;
+use axum::Router;
+use axum::extract::DefaultBodyLimit;
+use axum::http::{Extensions, HeaderMap, StatusCode, Version, header};
+use axum::middleware::{from_fn, from_fn_with_state};
+use axum_extra::extract::cookie::SameSite;
+use branding::Branding;
+use breach::BreachCheck;
+use client_cert::ClientCertAuth;
+use config::{
+    AppConfig, CompressionConfig, CompressionQuality, ConfigError, CookieSameSite, DatabaseConfig,
+    JournalMode, Synchronous, invalid,
+};
+use db::Db;
+use frontend::SpaRoutes;
+use geoip::GeoIp;
+use honeypot::Honeypot;
+use hooks::Hooks;
+use i18n::Catalogs;
+use ldap::LdapVerifier;
+#[cfg(feature = "lua-policy")]
+use lua_policy::LuaPolicy;
+use mailer::Mailer;
+use metrics::Metrics;
+use notify::Notifier;
+use oidc::UpstreamOidc;
+use origin::AllowedHosts;
+#[cfg(feature = "policy-plugin")]
+use plugin::PolicyPlugin;
+use policy::Policies;
+use puzzle::LoginPuzzle;
+use signing_key::SigningKeys;
+use sqlx::SqlitePool;
+use sqlx::sqlite::{SqliteConnectOptions, SqliteJournalMode, SqlitePoolOptions, SqliteSynchronous};
+use state::AppState;
+use storage::Storage;
+use template::Templates;
+use tower_http::CompressionLevel;
+use tower_http::compression::CompressionLayer;
+use tower_http::compression::predicate::{NotForContentType, Predicate, SizeAbove};
+use url::Url;
+use webauthn_rs::prelude::*;
+
+/// Largest request body any route accepts, over TCP and HTTP/3 alike.
+const MAX_REQUEST_BODY: usize = 2 * 1024 * 1024;
+
+/// Build the shared state from resolved config and wrap the API + frontend in the
+/// middleware stack. `db` must already be migrated. Fails on files the config
+/// points at that can't be read or make no sense.
+pub async fn app(config: AppConfig, db: Db) -> Result<Router, ConfigError> {
+    let diagnostics_config = config.diagnostics();
+    let AppConfig {
+        listen: _,
+        tls: _,
+        ready_file: _,
+        web_integrity: _,
+        web_source: _,
+        error_pages,
+        rust_log: _,
+        database_path: _,
+        database: _,
+        rp_id,
+        rp_origin,
+        allowed_hosts: configured_allowed_hosts,
+        cookie_domain,
+        cookie_name,
+        cookie_same_site,
+        cookie_partitioned,
+        storage,
+        instance_id,
+        alert_webhook_url,
+        smtp,
+        push,
+        notification_templates,
+        apps,
+        forward_auth,
+        client_cert,
+        attestation,
+        related_origins,
+        security_txt,
+        recovery,
+        upstream_oidc,
+        ldap,
+        breached_passwords,
+        trusted_proxies,
+        access_control,
+        geoip_database,
+        asn_database,
+        deny_login_countries,
+        login_anomaly,
+        login_throttle,
+        login_puzzle,
+        honeypot,
+        hooks,
+        policy_plugin,
+        lua_policy,
+        policies,
+        passkeys,
+        audit_export,
+        slo,
+        maintenance,
+        compression,
+        branding,
+        spa_routes,
+        bootstrap_token,
+        default_language,
+        dev_login_password,
+        break_glass_token_file,
+        log_presets,
+    } = config;
+
+    let secure_cookies = rp_origin.starts_with("https://");
+    let rp_origin_url = Url::parse(&rp_origin).map_err(|e| invalid("rp_origin", e.to_string()))?;
+    let rp_origin = rp_origin_url.origin().ascii_serialization();
+    let apps = AppPolicies::load(&apps)?;
+    let mut allowed_hosts = origin::load_allowed_hosts(&rp_origin, &configured_allowed_hosts);
+    allowed_hosts.extend(apps.hosts());
+    let allowed_hosts = AllowedHosts::new(allowed_hosts);
+    allowed_hosts.set_runtime(
+        db.allowed_hosts()
+            .await
+            .map_err(|e| storage_error("load runtime allowed hosts", e))?,
+    );
+    if let Some(domain) = &cookie_domain
+        && !origin::origin_host(&rp_origin).is_some_and(|h| origin::host_in_domain(&h, domain))
+    {
+        tracing::warn!(
+            domain,
+            "rp_origin is outside cookie_domain; its sessions stay host-only"
+        );
+    }
+
+    let related_origins = if related_origins {
+        related_origin_urls(&rp_origin_url, &allowed_hosts)
+    } else {
+        Vec::new()
+    };
+    let mut webauthn = WebauthnBuilder::new(&rp_id, &rp_origin_url)
+        .map_err(|e| invalid("rp_id", format!("{rp_id:?} doesn't match rp_origin: {e}")))?
+        .rp_name("den");
+    for origin in &related_origins {
+        webauthn = webauthn.append_allowed_origin(origin);
+    }
+    let webauthn = webauthn
+        .build()
+        .map_err(|e| invalid("rp_id", e.to_string()))?;
+
+    let storage = Storage::new(&storage, db.clone())?;
+    let signing_keys = init_signing_keys(&storage).await?;
+    let instance_id = instance_id.unwrap_or_else(default_instance_id);
+    tracing::info!(instance_id, "instance ready");
+    let audit_export = audit_export.map(|c| Arc::new(AuditExporter::start(c, &instance_id)));
+    let alert_webhook_url = alert_webhook_url
+        .map(|url| Url::parse(&url).map_err(|e| invalid("alert_webhook_url", e.to_string())))
+        .transpose()?;
+    if let Some(push) = &push {
+        Url::parse(push.url()).map_err(|e| invalid("push.url", e.to_string()))?;
+    }
+
+    if let Some(ldap) = &ldap
+        && ldap.url.starts_with("ldap://")
+    {
+        tracing::warn!(
+            url = %ldap.url,
+            "ldap.url is plaintext; passwords cross the network unencrypted"
+        );
+    }
+
+    if dev_login_password.is_some() {
+        if cfg!(feature = "dev-auth") {
+            tracing::warn!(
+                "dev login enabled: POST /api/dev-login accepts a password, never expose this"
+            );
+        } else {
+            tracing::warn!(
+                "dev_login_password is set but this build lacks the dev-auth feature; ignoring it"
+            );
+        }
+    }
+
+    if policy_plugin.is_some() && !cfg!(feature = "policy-plugin") {
+        tracing::warn!(
+            "policy_plugin is set but this build lacks the policy-plugin feature; ignoring it"
+        );
+    }
+    #[cfg(feature = "policy-plugin")]
+    let policy_plugin = policy_plugin
+        .map(|config| {
+            let plugin = PolicyPlugin::load(&config)
+                .map_err(|e| invalid("policy_plugin.path", format!("{}: {e:#}", config.path)))?;
+            tracing::info!(path = config.path, "policy plugin loaded");
+            Ok::<_, ConfigError>(Arc::new(plugin))
+        })
+        .transpose()?;
+
+    if lua_policy.is_some() && !cfg!(feature = "lua-policy") {
+        tracing::warn!(
+            "lua_policy is set but this build lacks the lua-policy feature; ignoring it"
+        );
+    }
+    let hooks = Hooks::load(&hooks)?;
+    #[cfg(feature = "lua-policy")]
+    let hooks = match lua_policy {
+        Some(config) => {
+            let script = LuaPolicy::load(&config).map_err(|e| {
+                invalid(
+                    "lua_policy.script",
+                    format!("{}: {e}", config.script.display()),
+                )
+            })?;
+            tracing::info!(script = %config.script.display(), "lua policy loaded");
+            hooks.with_script(script)
+        }
+        None => hooks,
+    };
+
+    #[cfg(feature = "testing")]
+    tracing::warn!(
+        "testing build: /api/testing/authenticator answers WebAuthn challenges for anyone"
+    );
+
+    let geoip = geoip_database
+        .map(|path| load_geoip(&path, "geoip_database"))
+        .transpose()?;
+    let asn = asn_database
+        .map(|path| load_geoip(&path, "asn_database"))
+        .transpose()?;
+
+    let branding = Arc::new(Branding::load(branding)?);
+    let error_pages = Arc::new(error_pages);
+    let upstream_oidc = upstream_oidc.map(|c| Arc::new(UpstreamOidc::new(c, &rp_origin)));
+
+    let state = AppState {
+        db,
+        storage,
+        instance_id: Arc::from(instance_id),
+        webauthn: Arc::new(webauthn),
+        signing_keys: Arc::new(signing_keys),
+        secure_cookies,
+        rp_id,
+        rp_origin,
+        allowed_hosts: Arc::new(allowed_hosts),
+        related_origins: Arc::new(
+            related_origins
+                .iter()
+                .map(|url| url.origin().ascii_serialization())
+                .collect(),
+        ),
+        security_txt: security_txt.map(Arc::new),
+        recovery,
+        trusted_proxies: Arc::new(access::parse_nets(&trusted_proxies, "trusted_proxies")?),
+        access_control: Arc::new(AccessControl::load(&access_control)?),
+        geoip,
+        asn,
+        deny_login_countries: Arc::new(deny_login_countries.into_iter().collect()),
+        login_anomaly,
+        login_throttle,
+        login_puzzle: login_puzzle
+            .as_ref()
+            .map(|p| LoginPuzzle::load(p).map(Arc::new))
+            .transpose()?,
+        honeypot: honeypot
+            .as_ref()
+            .map(|h| Honeypot::load(h).map(Arc::new))
+            .transpose()?,
+        hooks: Arc::new(hooks),
+        policies: Arc::new(Policies::load(&policies)?),
+        #[cfg(feature = "policy-plugin")]
+        policy_plugin,
+        passkeys,
+        apps: Arc::new(apps),
+        forward_auth: Arc::new(forward_auth),
+        cookie: Arc::new(CookieSettings {
+            name: cookie_name,
+            same_site: match cookie_same_site {
+                CookieSameSite::Strict => SameSite::Strict,
+                CookieSameSite::Lax => SameSite::Lax,
+                CookieSameSite::None => SameSite::None,
+            },
+            domain: cookie_domain,
+            partitioned: cookie_partitioned,
+        }),
+        client_cert: client_cert
+            .map(|c| ClientCertAuth::load(&c).map(Arc::new))
+            .transpose()?,
+        attestation: attestation
+            .map(|c| AttestationPolicy::load(&c).map(Arc::new))
+            .transpose()?,
+        upstream_oidc,
+        ldap: ldap.map(|c| Arc::new(LdapVerifier::new(c))),
+        breach_check: breached_passwords.map(|c| Arc::new(BreachCheck::new(c))),
+        audit_export,
+        notifier: Arc::new(Notifier::new(
+            alert_webhook_url,
+            smtp.map(Mailer::new).transpose()?,
+            push,
+            Templates::load(notification_templates.as_deref())?,
+        )),
+        started: Instant::now(),
+        maintenance: Arc::new(AtomicBool::new(maintenance)),
+        error_pages: error_pages.clone(),
+        expired_challenges_purged: Arc::new(AtomicU64::new(0)),
+        metrics: Arc::new(Metrics::new(slo)),
+        diagnostics_config: Arc::new(diagnostics_config),
+        branding: branding.clone(),
+        catalogs: Arc::new(Catalogs::load(default_language.as_deref())),
+        bootstrap_token,
+        #[cfg(feature = "dev-auth")]
+        dev_login_password,
+        break_glass_token_file,
+        log_presets: Arc::new(log_presets),
+        #[cfg(feature = "testing")]
+        soft_authenticator: Arc::new(std::sync::Mutex::new(
+            webauthn_authenticator_rs::WebauthnAuthenticator::new(
+                webauthn_authenticator_rs::softpasskey::SoftPasskey::new(true),
+            ),
+        )),
+    };
+    housekeeping::spawn(state.clone());
+
+    Ok(Router::new()
+        .nest("/api", api::router())
+        .nest("/.well-known", well_known::router())
+        .fallback_service(frontend::service(
+            branding,
+            SpaRoutes::load(spa_routes.as_deref()),
+            error_pages,
+        ))
+        .layer(from_fn_with_state(
+            state.clone(),
+            middleware::enforce_canonical_auth_origin,
+        ))
+        .layer(from_fn_with_state(
+            state.clone(),
+            middleware::reject_during_maintenance,
+        ))
+        .layer(from_fn_with_state(
+            state.clone(),
+            middleware::enforce_access_control,
+        ))
+        .layer(from_fn_with_state(state.clone(), middleware::trap_scanners))
+        .layer(compression_layer(&compression))
+        .layer(DefaultBodyLimit::max(MAX_REQUEST_BODY))
+        .layer(from_fn_with_state(
+            state.clone(),
+            middleware::record_metrics,
+        ))
+        .layer(from_fn(middleware::assign_request_id))
+        .with_state(state))
+}
+
+/// The configured allowed hosts as origins on rp_origin's scheme, rp_origin first.
+/// Runtime hosts are left out: the `Webauthn` origin list is fixed at startup.
+fn related_origin_urls(rp_origin: &Url, allowed_hosts: &AllowedHosts) -> Vec<Url> {
+    let mut hosts: Vec<_> = allowed_hosts.configured().iter().collect();
+    hosts.sort();
+    let mut origins = vec![rp_origin.clone()];
+    for host in hosts {
+        let url = Url::parse(&format!("{}://{host}", rp_origin.scheme()))
+            .expect("allowed hosts are normalized");
+        if url.origin() != rp_origin.origin() {
+            origins.push(url);
+        }
+    }
+    origins
+}
+
+fn load_geoip(path: &Path, key: &'static str) -> Result<Arc<GeoIp>, ConfigError> {
+    let db = GeoIp::open(path).map_err(|source| ConfigError::ReadFile {
+        key,
+        path: path.to_owned(),
+        source,
+    })?;
+    tracing::info!(path = %path.display(), "{key} loaded");
+    Ok(Arc::new(db))
+}
+
+fn compression_layer(config: &CompressionConfig) -> CompressionLayer<impl Predicate + use<>> {
+    // Same exclusions as tower-http's DefaultPredicate, with a configurable size floor,
+    // plus already-compressed media and byte ranges.
+    let predicate = SizeAbove::new(config.min_size)
+        .and(NotForContentType::GRPC)
+        .and(NotForContentType::IMAGES)
+        .and(NotForContentType::SSE)
+        .and(NotForContentType::const_new("video/"))
+        .and(NotForContentType::const_new("audio/"))
+        .and(not_partial);
+    CompressionLayer::new()
+        .gzip(config.gzip)
+        .br(config.br)
+        .zstd(config.zstd)
+        .quality(match config.level {
+            CompressionQuality::Fastest => CompressionLevel::Fastest,
+            CompressionQuality::Default => CompressionLevel::Default,
+            CompressionQuality::Best => CompressionLevel::Best,
+        })
+        .compress_when(predicate)
+}
+
+/// A 206 body is a slice at identity offsets; compressing it would break `Content-Range`.
+fn not_partial(status: StatusCode, _: Version, headers: &HeaderMap, _: &Extensions) -> bool {
+    status != StatusCode::PARTIAL_CONTENT && !headers.contains_key(header::CONTENT_RANGE)
+}
+
+/// Open the SQLite pool with the configured tuning.
+pub async fn connect_database(
+    database_path: &Path,
+    config: &DatabaseConfig,
+) -> Result<SqlitePool, sqlx::Error> {
+    let options = SqliteConnectOptions::new()
+        .filename(database_path)
+        .create_if_missing(true)
+        .journal_mode(match config.journal_mode {
+            JournalMode::Delete => SqliteJournalMode::Delete,
+            JournalMode::Truncate => SqliteJournalMode::Truncate,
+            JournalMode::Persist => SqliteJournalMode::Persist,
+            JournalMode::Memory => SqliteJournalMode::Memory,
+            JournalMode::Wal => SqliteJournalMode::Wal,
+            JournalMode::Off => SqliteJournalMode::Off,
+        })
+        .synchronous(match config.synchronous {
+            Synchronous::Off => SqliteSynchronous::Off,
+            Synchronous::Normal => SqliteSynchronous::Normal,
+            Synchronous::Full => SqliteSynchronous::Full,
+            Synchronous::Extra => SqliteSynchronous::Extra,
+        })
+        .busy_timeout(Duration::from_millis(config.busy_timeout_ms));
+    SqlitePoolOptions::new()
+        .max_connections(config.max_connections)
+        .max_lifetime(config.max_lifetime_seconds.map(Duration::from_secs))
+        .connect_with(options)
+        .await
+}
+
+async fn init_signing_keys(storage: &Storage) -> Result<SigningKeys, ConfigError> {
+    let keys = storage
+        .signing_keys(&[])
+        .await
+        .map_err(|e| storage_error("load the JWT signing keys", e))?;
+    tracing::info!(keys = keys.len(), "loaded JWT signing keys");
+    Ok(SigningKeys::new(keys))
+}
+
+fn storage_error(action: &'static str, error: impl std::fmt::Display) -> ConfigError {
+    ConfigError::Storage {
+        action,
+        message: error.to_string(),
+    }
+}
+
+/// `<hostname>-<random>`: unique even when replicas share a hostname.
+fn default_instance_id() -> String {
+    let host = std::env::var("HOSTNAME").unwrap_or_else(|_| "den".to_owned());
+    let suffix = Uuid::new_v4().simple().to_string();
+    format!("{host}-{}", &suffix[..8])
+}
@@ -0,0 +1,402 @@
+use std::fmt::Write;
+use std::io;
+use std::net::{IpAddr, Ipv4Addr, Ipv6Addr, SocketAddr};
+use std::path::Path;
+use std::sync::Arc;
+use std::time::Duration;
+
+use axum::Router;
+use axum::extract::{ConnectInfo, Request};
+use axum::http::{HeaderValue, header};
+use hyper_util::rt::{TokioExecutor, TokioIo};
+use hyper_util::server::conn::auto;
+use hyper_util::service::TowerToHyperService;
+use rustls::ServerConfig;
+use rustls::pki_types::pem::PemObject;
+use rustls::pki_types::{CertificateDer, PrivateKeyDer};
+use tokio::net::TcpListener;
+use tokio::task::JoinSet;
+use tokio_rustls::TlsAcceptor;
+use tower::Layer;
+
+use crate::config::{ListenAddr, TlsConfig};
+
+/// What `ConnectInfo<SocketAddr>` reports for Unix socket peers: they are on this
+/// host (usually the reverse proxy), so they count as loopback for
+/// `trusted_proxies`.
+const UNIX_PEER: SocketAddr = SocketAddr::new(IpAddr::V4(Ipv4Addr::LOCALHOST), 0);
+
+/// How long a client gets to finish the TLS handshake before it is dropped.
+const TLS_HANDSHAKE_TIMEOUT: Duration = Duration::from_secs(10);
+
+/// The `[tls]` certificate, loaded once for every `tls:` listener.
+#[derive(Clone)]
+pub struct Tls {
+    acceptor: TlsAcceptor,
+    /// TLS 1.3 with ALPN `h3`, when `enable_h3` is set in an `http3` build.
+    #[cfg(feature = "http3")]
+    quic: Option<Arc<ServerConfig>>,
+}
+
+impl Tls {
+    /// Read the certificate chain and key; ALPN offers h2, then http/1.1.
+    pub fn load(config: &TlsConfig) -> io::Result<Self> {
+        let invalid = |path: &str, e: &dyn std::fmt::Display| {
+            io::Error::new(io::ErrorKind::InvalidData, format!("{path}: {e}"))
+        };
+        let certs = CertificateDer::pem_file_iter(&config.cert_path)
+            .and_then(Iterator::collect::<Result<Vec<_>, _>>)
+            .map_err(|e| invalid(&config.cert_path, &e))?;
+        if certs.is_empty() {
+            return Err(invalid(&config.cert_path, &"no PEM certificates"));
+        }
+        let key = PrivateKeyDer::from_pem_file(&config.key_path)
+            .map_err(|e| invalid(&config.key_path, &e))?;
+        let server = |versions: &[&'static rustls::SupportedProtocolVersion], alpn: &[&[u8]]| {
+            let mut server = ServerConfig::builder_with_provider(
+                rustls::crypto::ring::default_provider().into(),
+            )
+            .with_protocol_versions(versions)
+            .and_then(|builder| {
+                builder
+                    .with_no_client_auth()
+                    .with_single_cert(certs.clone(), key.clone_key())
+            })
+            .map_err(|e| invalid(&config.key_path, &e))?;
+            server.alpn_protocols = alpn.iter().map(|p| p.to_vec()).collect();
+            Ok::<_, io::Error>(Arc::new(server))
+        };
+
+        if config.enable_h3 && !cfg!(feature = "http3") {
+            tracing::warn!(
+                "tls.enable_h3 is set but this build lacks the http3 feature; ignoring it"
+            );
+        }
+        Ok(Self {
+            acceptor: TlsAcceptor::from(server(rustls::DEFAULT_VERSIONS, &[b"h2", b"http/1.1"])?),
+            #[cfg(feature = "http3")]
+            quic: config
+                .enable_h3
+                .then(|| server(&[&rustls::version::TLS13], &[b"h3"]))
+                .transpose()?,
+        })
+    }
+}
+
+/// A bound `listen` entry, ready for `serve`.
+pub enum Listener {
+    Tcp(TcpListener),
+    Tls {
+        listener: TcpListener,
+        tls: Tls,
+        /// HTTP/3 on the same port over UDP.
+        #[cfg(feature = "http3")]
+        quic: Option<quinn::Endpoint>,
+    },
+    #[cfg(unix)]
+    Unix(UnixSocket),
+}
+
+impl Listener {
+    /// Bind one `listen` entry; `tls:` entries need `tls`. `[::]` falls back to
+    /// `0.0.0.0` when the host has no IPv6 (containers with it disabled), but not
+    /// when the port is taken.
+    pub async fn bind(addr: &ListenAddr, tls: Option<&Tls>) -> io::Result<Self> {
+        match addr {
+            ListenAddr::Tcp(addr) => bind_tcp(addr).await.map(Self::Tcp),
+            ListenAddr::Tls(addr) => {
+                let tls = tls.cloned().ok_or_else(|| {
+                    io::Error::new(
+                        io::ErrorKind::InvalidInput,
+                        "tls: needs a [tls] certificate",
+                    )
+                })?;
+                let listener = bind_tcp(addr).await?;
+                #[cfg(feature = "http3")]
+                let quic = match &tls.quic {
+                    Some(server) => Some(crate::http3::endpoint(
+                        server.clone(),
+                        listener.local_addr()?,
+                    )?),
+                    None => None,
+                };
+                Ok(Self::Tls {
+                    listener,
+                    tls,
+                    #[cfg(feature = "http3")]
+                    quic,
+                })
+            }
+            #[cfg(unix)]
+            ListenAddr::Unix(path) => UnixSocket::bind(path).map(Self::Unix),
+            #[cfg(not(unix))]
+            ListenAddr::Unix(_) => Err(io::Error::new(
+                io::ErrorKind::Unsupported,
+                "unix sockets need a unix host",
+            )),
+        }
+    }
+
+    /// The bound address, with the real port when `listen` asked for port 0.
+    pub fn describe(&self) -> io::Result<String> {
+        match self {
+            Self::Tcp(listener) => listener.local_addr().map(|addr| addr.to_string()),
+            Self::Tls {
+                listener,
+                #[cfg(feature = "http3")]
+                quic,
+                ..
+            } => {
+                let tcp = listener.local_addr()?;
+                #[cfg(feature = "http3")]
+                if let Some(quic) = quic {
+                    return Ok(format!("tls:{tcp} and udp:{} (HTTP/3)", quic.local_addr()?));
+                }
+                Ok(format!("tls:{tcp}"))
+            }
+            #[cfg(unix)]
+            Self::Unix(socket) => Ok(format!("unix:{}", socket.path.display())),
+        }
+    }
+}
+
+async fn bind_tcp(addr: &SocketAddr) -> io::Result<TcpListener> {
+    match TcpListener::bind(addr).await {
+        Err(e)
+            if addr.ip() == IpAddr::V6(Ipv6Addr::UNSPECIFIED)
+                && e.kind() != io::ErrorKind::AddrInUse =>
+        {
+            tracing::warn!("cannot listen on {addr} ({e}); falling back to 0.0.0.0");
+            TcpListener::bind((Ipv4Addr::UNSPECIFIED, addr.port())).await
+        }
+        result => result,
+    }
+}
+
+/// Serve `app` on every listener, each with its own accept loop, until one fails.
+pub async fn serve(listeners: Vec<Listener>, app: Router) -> io::Result<()> {
+    let app = app.layer(axum::middleware::map_request(authority_as_host));
+    let mut loops = JoinSet::new();
+    for listener in listeners {
+        match listener {
+            Listener::Tcp(listener) => {
+                let service = app
+                    .clone()
+                    .into_make_service_with_connect_info::<SocketAddr>();
+                loops.spawn(async move { axum::serve(listener, service).await })
+            }
+            Listener::Tls {
+                listener,
+                tls,
+                #[cfg(feature = "http3")]
+                quic,
+            } => {
+                #[cfg(feature = "http3")]
+                let app = match quic {
+                    Some(quic) => {
+                        let app = crate::http3::advertise(app.clone(), quic.local_addr()?.port());
+                        loops.spawn(crate::http3::serve(quic, app.clone()));
+                        app
+                    }
+                    None => app.clone(),
+                };
+                loops.spawn(serve_tls(listener, tls.acceptor, app.clone()))
+            }
+            // `Connected` can't be implemented for a listener of ours, so the
+            // peer address goes in as the extension `ConnectInfo` reads.
+            #[cfg(unix)]
+            Listener::Unix(socket) => {
+                let service = app
+                    .clone()
+                    .layer(axum::Extension(axum::extract::ConnectInfo(UNIX_PEER)))
+                    .into_make_service();
+                loops.spawn(async move { axum::serve(socket, service).await })
+            }
+        };
+    }
+    while let Some(result) = loops.join_next().await {
+        result.map_err(io::Error::other)??;
+    }
+    Ok(())
+}
+
+/// Accept TCP connections and hand each to its own task for the handshake, so a
+/// slow client never holds up the rest; ALPN decides between h2 and HTTP/1.1.
+async fn serve_tls(listener: TcpListener, acceptor: TlsAcceptor, app: Router) -> io::Result<()> {
+    loop {
+        let (stream, peer) = match listener.accept().await {
+            Ok(accepted) => accepted,
+            Err(e) => {
+                // Like `axum::serve`: a dropped connection is routine, anything else
+                // (out of file descriptors) gets a pause before the next try.
+                if !matches!(
+                    e.kind(),
+                    io::ErrorKind::ConnectionRefused
+                        | io::ErrorKind::ConnectionAborted
+                        | io::ErrorKind::ConnectionReset
+                ) {
+                    tracing::warn!("failed to accept a TLS connection: {e}");
+                    tokio::time::sleep(Duration::from_secs(1)).await;
+                }
+                continue;
+            }
+        };
+        let acceptor = acceptor.clone();
+        let service = axum::Extension(ConnectInfo(peer)).layer(app.clone());
+        tokio::spawn(async move {
+            let stream =
+                match tokio::time::timeout(TLS_HANDSHAKE_TIMEOUT, acceptor.accept(stream)).await {
+                    Ok(Ok(stream)) => stream,
+                    Ok(Err(e)) => return tracing::debug!(%peer, "TLS handshake failed: {e}"),
+                    Err(_) => return tracing::debug!(%peer, "TLS handshake timed out"),
+                };
+            if let Err(e) = auto::Builder::new(TokioExecutor::new())
+                .serve_connection_with_upgrades(
+                    TokioIo::new(stream),
+                    TowerToHyperService::new(service),
+                )
+                .await
+            {
+                tracing::debug!(%peer, "TLS connection ended: {e}");
+            }
+        });
+    }
+}
+
+/// HTTP/2 and HTTP/3 carry the host in `:authority`, not `Host`, and
+/// `origin::request_host` only reads headers.
+async fn authority_as_host(mut request: Request) -> Request {
+    if !request.headers().contains_key(header::HOST)
+        && let Some(host) = request
+            .uri()
+            .authority()
+            .and_then(|authority| HeaderValue::from_str(authority.as_str()).ok())
+    {
+        request.headers_mut().insert(header::HOST, host);
+    }
+    request
+}
+
+/// Write the TCP listeners' ports, one per line in `listen` order, for harnesses
+/// waiting on `port = 0`. Written to a temporary file and renamed, so a reader
+/// never sees a partial list.
+pub fn write_ready_file(path: &Path, listeners: &[Listener]) -> io::Result<()> {
+    let mut ports = String::new();
+    for listener in listeners {
+        if let Listener::Tcp(listener) | Listener::Tls { listener, .. } = listener {
+            let _ = writeln!(ports, "{}", listener.local_addr()?.port());
+        }
+    }
+    let mut tmp = path.as_os_str().to_owned();
+    tmp.push(".tmp");
+    std::fs::write(&tmp, ports)?;
+    std::fs::rename(&tmp, path)
+}
+
+/// A Unix domain socket listener whose peers appear as `UNIX_PEER`, so handlers
+/// keep extracting `ConnectInfo<SocketAddr>`.
+#[cfg(unix)]
+pub struct UnixSocket {
+    listener: tokio::net::UnixListener,
+    path: std::path::PathBuf,
+}
+
+#[cfg(unix)]
+impl UnixSocket {
+    /// Replaces a stale socket left by a previous run, but never another file.
+    fn bind(path: &Path) -> io::Result<Self> {
+        use std::os::unix::fs::FileTypeExt;
+
+        if let Ok(metadata) = std::fs::symlink_metadata(path)
+            && metadata.file_type().is_socket()
+        {
+            std::fs::remove_file(path)?;
+        }
+        Ok(Self {
+            listener: tokio::net::UnixListener::bind(path)?,
+            path: path.to_owned(),
+        })
+    }
+}
+
+#[cfg(unix)]
+impl axum::serve::Listener for UnixSocket {
+    type Io = tokio::net::UnixStream;
+    type Addr = SocketAddr;
+
+    async fn accept(&mut self) -> (Self::Io, Self::Addr) {
+        let (stream, _) = axum::serve::Listener::accept(&mut self.listener).await;
+        (stream, UNIX_PEER)
+    }
+
+    fn local_addr(&self) -> io::Result<Self::Addr> {
+        Ok(UNIX_PEER)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn localhost(port: u16) -> ListenAddr {
+        ListenAddr::Tcp(SocketAddr::new(Ipv4Addr::LOCALHOST.into(), port))
+    }
+
+    #[tokio::test]
+    async fn port_zero_binds_a_free_port_and_reports_it() {
+        let listeners = vec![
+            Listener::bind(&localhost(0), None).await.unwrap(),
+            Listener::bind(&localhost(0), None).await.unwrap(),
+        ];
+        let ports: Vec<u16> = listeners
+            .iter()
+            .map(|l| l.describe().unwrap().parse::<SocketAddr>().unwrap().port())
+            .collect();
+        assert!(ports.iter().all(|&port| port != 0));
+
+        let path = std::env::temp_dir().join(format!("den-ready-{}", std::process::id()));
+        write_ready_file(&path, &listeners).unwrap();
+        assert_eq!(
+            std::fs::read_to_string(&path).unwrap(),
+            format!("{}\n{}\n", ports[0], ports[1])
+        );
+        std::fs::remove_file(&path).unwrap();
+    }
+
+    #[tokio::test]
+    async fn a_taken_port_is_not_retried() {
+        let Listener::Tcp(taken) = Listener::bind(&localhost(0), None).await.unwrap() else {
+            unreachable!()
+        };
+        let port = taken.local_addr().unwrap().port();
+        let error = Listener::bind(&localhost(port), None).await.err().unwrap();
+        assert_eq!(error.kind(), io::ErrorKind::AddrInUse);
+    }
+
+    #[cfg(unix)]
+    #[tokio::test]
+    async fn unix_sockets_replace_stale_sockets_but_not_files() {
+        let path = std::env::temp_dir().join(format!("den-{}.sock", std::process::id()));
+        let first = Listener::bind(&ListenAddr::Unix(path.clone()), None)
+            .await
+            .unwrap();
+        drop(first);
+        let socket = Listener::bind(&ListenAddr::Unix(path.clone()), None)
+            .await
+            .unwrap();
+        assert_eq!(
+            socket.describe().unwrap(),
+            format!("unix:{}", path.display())
+        );
+        drop(socket);
+
+        std::fs::remove_file(&path).unwrap();
+        std::fs::write(&path, "not a socket").unwrap();
+        assert!(
+            Listener::bind(&ListenAddr::Unix(path.clone()), None)
+                .await
+                .is_err()
+        );
+        std::fs::remove_file(&path).unwrap();
+    }
+}
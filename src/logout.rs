@@ -0,0 +1,140 @@
+use jsonwebtoken::{EncodingKey, Header, encode};
+use serde::{Deserialize, Serialize};
+use time::{Duration, OffsetDateTime};
+use url::form_urlencoded;
+use uuid::Uuid;
+
+use crate::apps::{AppPolicy, BackchannelLogout};
+use crate::http;
+use crate::state::AppState;
+
+/// Event key marking a JWT as an OIDC Back-Channel Logout 1.0 logout token.
+const BACKCHANNEL_LOGOUT_EVENT: &str = "http://schemas.openid.net/event/backchannel-logout";
+/// Apps should reject logout tokens received much later than they were issued.
+const LOGOUT_TOKEN_TTL: Duration = Duration::minutes(2);
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct LogoutClaims {
+    pub iss: String,
+    pub aud: String,
+    pub iat: i64,
+    pub exp: i64,
+    pub jti: String,
+    pub sub: String,
+    /// The den session that ended; absent when every session of `sub` did.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub sid: Option<String>,
+    pub events: serde_json::Value,
+}
+
+/// Tell every app with a `logout_uri` that `session_id` (or, without one, every
+/// session of `user_id`) ended. Fire-and-forget: failures are logged, never surfaced.
+pub fn notify_apps(state: &AppState, user_id: &str, session_id: Option<&str>) {
+    for app in state.apps.iter() {
+        let Some(logout) = &app.backchannel_logout else {
+            continue;
+        };
+        let token = match logout_token(&state.rp_origin, app, logout, user_id, session_id) {
+            Ok(token) => token,
+            Err(error) => {
+                tracing::error!(app = app.name, error = %error, "failed to sign logout token");
+                continue;
+            }
+        };
+        let (app, uri) = (app.name.clone(), logout.uri.clone());
+        tokio::task::spawn_blocking(move || {
+            let body = form_urlencoded::Serializer::new(String::new())
+                .append_pair("logout_token", &token)
+                .finish();
+            let headers = [("Content-Type", "application/x-www-form-urlencoded")];
+            match http::post(&uri, &headers, body.as_bytes()) {
+                Ok(response) if response.is_success() => {}
+                Ok(response) => {
+                    tracing::warn!(
+                        app,
+                        status = response.status,
+                        "back-channel logout rejected"
+                    );
+                }
+                Err(error) => tracing::warn!(app, error = %error, "back-channel logout failed"),
+            }
+        });
+    }
+}
+
+/// HS256 with the app's `logout_secret`, `aud` set to the app name.
+pub fn logout_token(
+    issuer: &str,
+    app: &AppPolicy,
+    logout: &BackchannelLogout,
+    user_id: &str,
+    session_id: Option<&str>,
+) -> Result<String, jsonwebtoken::errors::Error> {
+    let now = OffsetDateTime::now_utc();
+    let claims = LogoutClaims {
+        iss: issuer.to_owned(),
+        aud: app.name.clone(),
+        iat: now.unix_timestamp(),
+        exp: (now + LOGOUT_TOKEN_TTL).unix_timestamp(),
+        jti: Uuid::new_v4().to_string(),
+        sub: user_id.to_owned(),
+        sid: session_id.map(str::to_owned),
+        events: serde_json::json!({ BACKCHANNEL_LOGOUT_EVENT: {} }),
+    };
+    let header = Header {
+        typ: Some("logout+jwt".into()),
+        ..Header::default()
+    };
+    encode(
+        &header,
+        &claims,
+        &EncodingKey::from_secret(logout.secret.as_bytes()),
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use jsonwebtoken::{DecodingKey, Validation, decode};
+    use url::Url;
+
+    use super::*;
+
+    #[test]
+    fn logout_token_verifies_with_the_app_secret() {
+        let logout = BackchannelLogout {
+            uri: Url::parse("https://grafana.lab.example/logout").unwrap(),
+            secret: "s3cret".into(),
+        };
+        let app = AppPolicy {
+            name: "grafana".into(),
+            origin: "https://grafana.lab.example".into(),
+            allowed_users: None,
+            session_ttl: None,
+            scopes: Vec::new(),
+            backchannel_logout: Some(logout.clone()),
+            access: Default::default(),
+        };
+        let token = logout_token(
+            "https://den.lab.example",
+            &app,
+            &logout,
+            "user-1",
+            Some("sid-1"),
+        )
+        .unwrap();
+
+        let mut validation = Validation::default();
+        validation.set_audience(&["grafana"]);
+        validation.set_issuer(&["https://den.lab.example"]);
+        let decoded =
+            decode::<LogoutClaims>(&token, &DecodingKey::from_secret(b"s3cret"), &validation)
+                .unwrap();
+        assert_eq!(decoded.header.typ.as_deref(), Some("logout+jwt"));
+        assert_eq!(decoded.claims.sid.as_deref(), Some("sid-1"));
+        assert!(decoded.claims.events[BACKCHANNEL_LOGOUT_EVENT].is_object());
+        assert!(
+            decode::<LogoutClaims>(&token, &DecodingKey::from_secret(b"other"), &validation)
+                .is_err()
+        );
+    }
+}
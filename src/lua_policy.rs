@@ -0,0 +1,282 @@
+use std::path::{Path, PathBuf};
+use std::sync::Mutex;
+use std::time::{Duration, Instant, SystemTime};
+
+use mlua::{HookTriggers, Lua, LuaOptions, LuaSerdeExt, SerializeOptions, StdLib, Value, VmState};
+
+use crate::config::{HookEvent, HookFailure, LuaPolicyConfig};
+
+/// Instructions between deadline checks.
+const CHECK_EVERY: u32 = 1000;
+/// Heap a script may hold; the deadline alone doesn't stop one big allocation.
+const MEMORY_LIMIT: usize = 16 << 20;
+/// Base library functions that read files or compile code.
+const UNSAFE_GLOBALS: [&str; 4] = ["load", "loadfile", "dofile", "collectgarbage"];
+
+/// `[lua_policy]`: the script's functions, reloaded when its file changes.
+pub struct LuaPolicy {
+    path: PathBuf,
+    timeout: Duration,
+    on_error: HookFailure,
+    loaded: Mutex<Loaded>,
+}
+
+struct Loaded {
+    lua: Lua,
+    /// Modification time of the file `lua` was loaded from.
+    modified: Option<SystemTime>,
+}
+
+/// The script function asked before `event`.
+fn function_name(event: HookEvent) -> &'static str {
+    match event {
+        HookEvent::Session => "on_login",
+        HookEvent::Registration => "on_register",
+        HookEvent::Redirect => "on_redirect",
+    }
+}
+
+impl LuaPolicy {
+    /// A script that doesn't load stops startup; later broken edits only log and
+    /// keep the last good version.
+    pub fn load(config: &LuaPolicyConfig) -> mlua::Result<Self> {
+        let modified = modified(&config.script);
+        let source = std::fs::read_to_string(&config.script).map_err(mlua::Error::external)?;
+        Ok(Self {
+            path: config.script.clone(),
+            timeout: Duration::from_millis(config.timeout_ms),
+            on_error: config.on_error,
+            loaded: Mutex::new(Loaded {
+                lua: sandbox(&source, &config.script)?,
+                modified,
+            }),
+        })
+    }
+
+    /// The script's answer for `event`, given the `hooks::HookContext` as JSON:
+    /// `Err(reason)` on a deny, or on a failure when `on_error` is deny. Without
+    /// the function, everything is allowed. Blocks for up to the timeout.
+    pub fn check(&self, event: HookEvent, context: &serde_json::Value) -> Result<(), String> {
+        let mut loaded = self.loaded.lock().unwrap_or_else(|e| e.into_inner());
+        self.reload_if_changed(&mut loaded);
+        match call(&loaded.lua, self.timeout, event, context) {
+            Ok(Ok(())) => Ok(()),
+            Ok(Err(reason)) => Err(reason),
+            Err(error) => {
+                tracing::warn!(
+                    script = %self.path.display(),
+                    function = function_name(event),
+                    error = %error,
+                    "lua policy failed"
+                );
+                match self.on_error {
+                    HookFailure::Deny => Err("lua policy failed".into()),
+                    HookFailure::Allow => Ok(()),
+                }
+            }
+        }
+    }
+
+    fn reload_if_changed(&self, loaded: &mut Loaded) {
+        let modified = modified(&self.path);
+        if modified == loaded.modified {
+            return;
+        }
+        // Remember the attempt either way, so a broken edit is reported once.
+        loaded.modified = modified;
+        let reloaded = std::fs::read_to_string(&self.path)
+            .map_err(mlua::Error::external)
+            .and_then(|source| sandbox(&source, &self.path));
+        match reloaded {
+            Ok(lua) => {
+                loaded.lua = lua;
+                tracing::info!(script = %self.path.display(), "lua policy reloaded");
+            }
+            Err(error) => tracing::warn!(
+                script = %self.path.display(),
+                error = %error,
+                "lua policy edit failed to load; keeping the previous version"
+            ),
+        }
+    }
+}
+
+fn modified(path: &Path) -> Option<SystemTime> {
+    std::fs::metadata(path).and_then(|m| m.modified()).ok()
+}
+
+/// A fresh interpreter with only the pure standard libraries (no `io`, `os` or
+/// `require`) and capped memory, running `source` to define the policy functions.
+/// mlua always opens the base library, so its file and code loaders are removed.
+fn sandbox(source: &str, path: &Path) -> mlua::Result<Lua> {
+    let lua = Lua::new_with(
+        StdLib::TABLE | StdLib::STRING | StdLib::MATH | StdLib::UTF8,
+        LuaOptions::default(),
+    )?;
+    let globals = lua.globals();
+    for name in UNSAFE_GLOBALS {
+        globals.set(name, Value::Nil)?;
+    }
+    lua.set_memory_limit(MEMORY_LIMIT)?;
+    lua.load(source)
+        .set_name(path.display().to_string())
+        .exec()?;
+    Ok(lua)
+}
+
+/// `Ok(Err(reason))` is a deny; the outer error is the script failing.
+fn call(
+    lua: &Lua,
+    timeout: Duration,
+    event: HookEvent,
+    context: &serde_json::Value,
+) -> mlua::Result<Result<(), String>> {
+    let Some(function) = lua
+        .globals()
+        .get::<Option<mlua::Function>>(function_name(event))?
+    else {
+        return Ok(Ok(()));
+    };
+    let deadline = Instant::now() + timeout;
+    lua.set_hook(
+        HookTriggers::new().every_nth_instruction(CHECK_EVERY),
+        move |_, _| {
+            if Instant::now() > deadline {
+                return Err(mlua::Error::runtime("timed out"));
+            }
+            Ok(VmState::Continue)
+        },
+    );
+    // Absent fields are nil, not `null` userdata that Lua would treat as true.
+    let options = SerializeOptions::new()
+        .serialize_none_to_null(false)
+        .serialize_unit_to_null(false);
+    let context = lua.to_value_with(context, options)?;
+    let result = function.call::<(Value, Option<String>)>(context);
+    lua.remove_hook();
+    let (verdict, reason) = result?;
+    match verdict {
+        Value::Nil => Ok(Ok(())),
+        Value::String(verdict) => match &*verdict.to_str()? {
+            "allow" => Ok(Ok(())),
+            "deny" => Ok(Err(reason.unwrap_or_else(|| "denied by lua policy".into()))),
+            other => Err(mlua::Error::runtime(format!("unknown verdict {other:?}"))),
+        },
+        other => Err(mlua::Error::runtime(format!(
+            "expected \"allow\" or \"deny\", got a {}",
+            other.type_name()
+        ))),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::hooks::HookContext;
+
+    fn policy(name: &str, source: &str, on_error: HookFailure) -> (LuaPolicy, PathBuf) {
+        let path = std::env::temp_dir().join(format!("den-lua-{}-{name}.lua", std::process::id()));
+        std::fs::write(&path, source).unwrap();
+        let policy = LuaPolicy::load(&LuaPolicyConfig {
+            script: path.clone(),
+            timeout_ms: 50,
+            on_error,
+        })
+        .unwrap();
+        (policy, path)
+    }
+
+    /// Rewrite `path` with an mtime `secs` ahead, so coarse filesystem clocks
+    /// still see a change.
+    fn edit(path: &Path, source: &str, secs: u64) {
+        std::fs::write(path, source).unwrap();
+        let file = std::fs::File::options().write(true).open(path).unwrap();
+        file.set_modified(SystemTime::now() + Duration::from_secs(secs))
+            .unwrap();
+    }
+
+    fn check(policy: &LuaPolicy, event: HookEvent) -> Result<(), String> {
+        let context = serde_json::to_value(HookContext {
+            event,
+            user_id: "u1",
+            user_name: Some("alice"),
+            passkey_name: None,
+            ip: Some("192.0.2.1"),
+            country: Some("DE"),
+            user_agent: None,
+            origin: Some("https://wiki.example"),
+        })
+        .unwrap();
+        policy.check(event, &context)
+    }
+
+    #[test]
+    fn functions_see_the_context_and_decide() {
+        let (policy, path) = policy(
+            "decide",
+            "function on_login(ctx)\n\
+               if ctx.user_agent then return 'allow' end\n\
+               if ctx.country == 'DE' then return 'deny', 'not from ' .. ctx.country end\n\
+             end\n\
+             function on_redirect(ctx) return 'allow' end\n",
+            HookFailure::Deny,
+        );
+        assert_eq!(
+            check(&policy, HookEvent::Session),
+            Err("not from DE".into())
+        );
+        assert_eq!(check(&policy, HookEvent::Redirect), Ok(()));
+        // No on_register: allowed.
+        assert_eq!(check(&policy, HookEvent::Registration), Ok(()));
+
+        // A broken edit keeps the old script; a good one replaces it.
+        edit(&path, "function on_login(ctx) return 'deny' end end", 1);
+        assert_eq!(
+            check(&policy, HookEvent::Session),
+            Err("not from DE".into())
+        );
+        edit(&path, "function on_login(ctx) return nil end", 2);
+        assert_eq!(check(&policy, HookEvent::Session), Ok(()));
+        std::fs::remove_file(&path).unwrap();
+    }
+
+    #[test]
+    fn failures_follow_on_error_and_the_sandbox_holds() {
+        let (deny, path) = policy(
+            "fail",
+            "function on_login(ctx) while true do end end\n\
+             function on_register(ctx) return os.exit() end\n\
+             function on_redirect(ctx) return 42 end\n",
+            HookFailure::Deny,
+        );
+        let started = Instant::now();
+        assert!(check(&deny, HookEvent::Session).is_err());
+        assert!(started.elapsed() < Duration::from_secs(2));
+        assert!(check(&deny, HookEvent::Registration).is_err());
+        assert!(check(&deny, HookEvent::Redirect).is_err());
+        std::fs::remove_file(&path).unwrap();
+
+        let (sandboxed, path) = policy(
+            "sandbox",
+            "function on_login(ctx)\n\
+               if load or loadfile or dofile or collectgarbage then return 'deny' end\n\
+             end\n\
+             function on_register(ctx) local s = string.rep('x', 1e9) end\n",
+            HookFailure::Deny,
+        );
+        assert_eq!(check(&sandboxed, HookEvent::Session), Ok(()));
+        assert_eq!(
+            check(&sandboxed, HookEvent::Registration),
+            Err("lua policy failed".into())
+        );
+        std::fs::remove_file(&path).unwrap();
+
+        let (allow, path) = policy(
+            "boom",
+            "function on_login(ctx) error('boom') end",
+            HookFailure::Allow,
+        );
+        assert_eq!(check(&allow, HookEvent::Session), Ok(()));
+        std::fs::remove_file(&path).unwrap();
+    }
+}
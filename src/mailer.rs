@@ -0,0 +1,147 @@
+use std::time::Duration;
+
+use lettre::message::Mailbox;
+use lettre::message::header::ContentType;
+use lettre::transport::smtp::authentication::Credentials;
+use lettre::transport::smtp::client::{Tls, TlsParameters};
+use lettre::transport::smtp::extension::ClientId;
+use lettre::{SmtpTransport, Transport};
+
+use crate::config::{ConfigError, SmtpConfig, SmtpTls, invalid};
+use crate::notify::SecurityEvent;
+use crate::template::Message;
+
+const SMTP_TIMEOUT: Duration = Duration::from_secs(15);
+
+#[derive(Debug)]
+pub struct Mailer {
+    transport: SmtpTransport,
+    from: Mailbox,
+    to: Mailbox,
+    events: Option<Vec<String>>,
+}
+
+impl Mailer {
+    /// STARTTLS is `Tls::Required`: a server that won't upgrade gets nothing past
+    /// `EHLO`, so there is no downgrade to plaintext.
+    pub fn new(config: SmtpConfig) -> Result<Self, ConfigError> {
+        let mailbox = |key, address: &str| {
+            address
+                .parse::<Mailbox>()
+                .map_err(|e| invalid(key, format!("{address:?}: {e}")))
+        };
+        let tls = TlsParameters::new(config.host.clone())
+            .map_err(|e| invalid("smtp.host", e.to_string()))?;
+        let mut transport = SmtpTransport::builder_dangerous(&config.host)
+            .port(config.port())
+            .tls(match config.tls {
+                SmtpTls::Implicit => Tls::Wrapper(tls),
+                SmtpTls::Starttls => Tls::Required(tls),
+            })
+            .hello_name(ClientId::Domain("den".into()))
+            .timeout(Some(SMTP_TIMEOUT));
+        if let (Some(username), Some(password)) = (&config.username, &config.password) {
+            transport = transport.credentials(Credentials::new(username.clone(), password.clone()));
+        }
+        Ok(Self {
+            transport: transport.build(),
+            from: mailbox("smtp.from", &config.from)?,
+            to: mailbox("smtp.to", &config.to)?,
+            events: config.events,
+        })
+    }
+
+    fn enabled(&self, event: &SecurityEvent) -> bool {
+        self.events
+            .as_ref()
+            .is_none_or(|events| events.iter().any(|e| e == event.kind()))
+    }
+
+    fn email(&self, message: &Message) -> Result<lettre::Message, lettre::error::Error> {
+        lettre::Message::builder()
+            .from(self.from.clone())
+            .to(self.to.clone())
+            .subject(format!("[den] {}", message.title))
+            .header(ContentType::TEXT_PLAIN)
+            .body(message.body.clone())
+    }
+
+    /// Fire-and-forget delivery; failures are logged and never block the request.
+    pub fn send(&self, event: &SecurityEvent, message: &Message) {
+        if !self.enabled(event) {
+            return;
+        }
+        let email = match self.email(message) {
+            Ok(email) => email,
+            Err(error) => {
+                tracing::warn!(error = %error, "smtp message could not be built");
+                return;
+            }
+        };
+        let transport = self.transport.clone();
+        tokio::task::spawn_blocking(move || {
+            if let Err(error) = transport.send(&email) {
+                tracing::warn!(error = %error, "smtp delivery failed");
+            }
+        });
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn config() -> SmtpConfig {
+        SmtpConfig {
+            host: "mail.example".into(),
+            port: None,
+            username: None,
+            password: None,
+            from: "den@example.com".into(),
+            to: "me@example.com".into(),
+            tls: SmtpTls::Starttls,
+            events: Some(vec!["passkey_removed".into()]),
+        }
+    }
+
+    #[test]
+    fn subjects_are_encoded_and_bodies_kept() {
+        let mailer = Mailer::new(config()).unwrap();
+        let email = mailer
+            .email(&Message {
+                title: "Passkey „Schlüssel“ removed".into(),
+                body: "a\n.b".into(),
+            })
+            .unwrap();
+        let formatted = String::from_utf8(email.formatted()).unwrap();
+        let subject = formatted
+            .lines()
+            .find(|line| line.starts_with("Subject: "))
+            .unwrap();
+        assert!(
+            subject.is_ascii() && subject.contains("=?utf-8?"),
+            "{subject}"
+        );
+        assert!(formatted.contains("\r\nDate: "));
+        assert!(formatted.ends_with("\r\n\r\na\r\n.b"), "{formatted}");
+    }
+
+    #[test]
+    fn bad_addresses_name_their_key() {
+        let mut bad = config();
+        bad.to = "not an address".into();
+        let error = Mailer::new(bad).unwrap_err();
+        assert!(error.to_string().contains("smtp.to"), "{error}");
+    }
+
+    #[test]
+    fn event_filter_respects_enable_list() {
+        let mailer = Mailer::new(config()).unwrap();
+        assert!(mailer.enabled(&SecurityEvent::PasskeyRemoved {
+            passkey: "yubikey".into()
+        }));
+        assert!(!mailer.enabled(&SecurityEvent::SignCountRegression {
+            credential_id: "abc".into()
+        }));
+    }
+}
@@ -1,121 +1,284 @@
-mod api;
-mod auth;
-mod config;
-mod frontend;
-mod middleware;
-mod origin;
-mod state;
-
+use std::fmt::Display;
+use std::future::pending;
 use std::path::Path;
-use std::sync::Arc;
+use std::time::Duration;
 
-use axum::middleware::from_fn_with_state;
-use config::{AppConfig, load_app_config};
-use state::AppState;
-use tower_http::compression::CompressionLayer;
+use den::cli;
+use den::config::{self, EX_CANTCREAT, EX_UNAVAILABLE, MismatchAction, load_app_config};
+use den::db::Db;
+use den::diagnostics::{RecentLogs, expand_log_preset, reloadable_filter};
+use den::doctor;
+use den::listen::{self, Listener};
+use den::systemd;
+use den::web_integrity;
+use den::web_source;
 use tracing_subscriber::EnvFilter;
-use url::Url;
-use webauthn_rs::prelude::*;
+use tracing_subscriber::layer::SubscriberExt;
+use tracing_subscriber::util::SubscriberInitExt;
 
 const DEFAULT_RUST_LOG: &str = "info";
+/// sysexits(3) codes for startup failures past config loading (see `ConfigError`).
+const EX_DATAERR: i32 = 65;
+const EX_SOFTWARE: i32 = 70;
+
+/// Print `message` and exit with `code` instead of panicking, so service managers
+/// see a meaningful status.
+fn fail(code: i32, message: impl Display) -> ! {
+    eprintln!("error: {message}");
+    std::process::exit(code);
+}
+
+fn main() {
+    #[cfg(all(windows, feature = "windows-service"))]
+    if windows::dispatch() {
+        return;
+    }
+    run(pending(), || {});
+}
 
+/// Everything `main` does; under the Windows service control manager `serve` also
+/// calls `ready` once listening and returns when `shutdown` completes.
 #[tokio::main]
-async fn main() {
-    let AppConfig {
-        port,
-        rust_log,
-        rp_id,
-        rp_origin,
-        allowed_hosts: configured_allowed_hosts,
-        database_path,
-    } = load_app_config();
-
-    let env_filter = EnvFilter::try_new(&rust_log).unwrap_or_else(|_| {
+async fn run(shutdown: impl Future<Output = ()>, ready: impl FnOnce()) {
+    let mut args: Vec<String> = std::env::args().skip(1).collect();
+    let command = cli::take_config_flag(&mut args)
+        .and_then(|config_path| {
+            if let Some(path) = config_path {
+                config::set_config_path(path);
+            }
+            cli::parse(&args)
+        })
+        .unwrap_or_else(|e| {
+            eprintln!("{e}");
+            std::process::exit(2);
+        });
+
+    // Before `load_app_config`, which stops at the first bad setting.
+    if matches!(command, cli::Command::Doctor) {
+        let healthy = doctor::run().await;
+        std::process::exit(if healthy { 0 } else { 1 });
+    }
+
+    let config = load_app_config().unwrap_or_else(|e| fail(e.exit_code(), e));
+
+    if !matches!(command, cli::Command::Serve) {
+        if let Err(e) = cli::run(command, &config).await {
+            eprintln!("error: {e}");
+            std::process::exit(1);
+        }
+        return;
+    }
+
+    let rust_log = expand_log_preset(&config.rust_log, &config.log_presets);
+    let env_filter = EnvFilter::try_new(rust_log).unwrap_or_else(|_| {
         eprintln!("invalid rust_log value in config, falling back to '{DEFAULT_RUST_LOG}'");
         EnvFilter::new(DEFAULT_RUST_LOG)
     });
-    tracing_subscriber::fmt().with_env_filter(env_filter).init();
+    tracing_subscriber::registry()
+        .with(reloadable_filter(env_filter))
+        .with(tracing_subscriber::fmt::layer())
+        .with(RecentLogs)
+        .init();
 
-    let db_dir = database_path.parent().unwrap_or_else(|| Path::new("."));
+    let db_dir = config
+        .database_path
+        .parent()
+        .unwrap_or_else(|| Path::new("."));
     std::fs::create_dir_all(db_dir).unwrap_or_else(|e| {
-        panic!(
-            "failed to create data directory at {}: {e}",
-            db_dir.display()
+        fail(
+            EX_CANTCREAT,
+            format!(
+                "failed to create data directory at {}: {e}",
+                db_dir.display()
+            ),
         )
     });
 
-    let db_url = sqlite_url_for_path(&database_path);
-    let db = sqlx::SqlitePool::connect(&db_url).await.unwrap();
-    sqlx::migrate!().run(&db).await.unwrap();
+    let pool = den::connect_database(&config.database_path, &config.database)
+        .await
+        .unwrap_or_else(|e| {
+            fail(
+                EX_CANTCREAT,
+                format!(
+                    "failed to open database at {}: {e}",
+                    config.database_path.display()
+                ),
+            )
+        });
+    let db = Db::new(pool, Duration::from_millis(config.database.slow_query_ms));
+    db.migrate()
+        .await
+        .unwrap_or_else(|e| fail(EX_DATAERR, format!("database migration failed: {e}")));
     tracing::info!("database ready");
+    if config.upstream_oidc.is_none() {
+        match db.remove_stranded_users().await {
+            Ok(removed) => {
+                for name in removed {
+                    tracing::warn!(
+                        user = %name,
+                        "removed a user left without passkeys by an interrupted registration"
+                    );
+                }
+            }
+            Err(e) => tracing::warn!("failed to check for users without passkeys: {e}"),
+        }
+    }
 
-    let secure_cookies = rp_origin.starts_with("https://");
-    let rp_origin_url = Url::parse(&rp_origin).expect("invalid rp_origin in config");
-    let rp_origin = rp_origin_url.origin().ascii_serialization();
-    let allowed_hosts = origin::load_allowed_hosts(&rp_origin, &configured_allowed_hosts);
-
-    let webauthn = WebauthnBuilder::new(&rp_id, &rp_origin_url)
-        .expect("failed to create WebauthnBuilder")
-        .rp_name("den")
-        .build()
-        .expect("failed to build Webauthn");
-
-    let jwt_secret = init_jwt_secret(&db).await;
-
-    let state = AppState {
-        db,
-        webauthn: Arc::new(webauthn),
-        jwt_secret: Arc::new(jwt_secret),
-        secure_cookies,
-        rp_origin,
-        allowed_hosts: Arc::new(allowed_hosts),
-    };
+    let web_cache = web_source::cache_dir(&config.database_path);
+    if let Some(source) = &config.web_source {
+        match web_source::load(source, &web_cache).await {
+            Ok(dir) => web_source::serve(&web_cache, dir, config.spa_routes.as_deref()),
+            Err(e) => fail(
+                EX_UNAVAILABLE,
+                format!("failed to fetch web assets from web_source: {e}"),
+            ),
+        }
+    }
 
-    let app = axum::Router::new()
-        .nest("/api", api::router())
-        .fallback_service(frontend::service())
-        .layer(from_fn_with_state(
-            state.clone(),
-            middleware::enforce_canonical_auth_origin,
-        ))
-        .layer(CompressionLayer::new())
-        .with_state(state);
-
-    let addr = format!("[::]:{port}");
-    tracing::info!("listening on {addr}");
-
-    let listener = tokio::net::TcpListener::bind(&addr).await.unwrap();
-    axum::serve(listener, app).await.unwrap();
-}
+    if let Some(integrity) = &config.web_integrity {
+        match web_integrity::check(integrity) {
+            Ok(detail) => tracing::info!("web assets verified ({detail})"),
+            Err(e) if integrity.on_mismatch == MismatchAction::Warn => {
+                tracing::warn!("web assets failed verification, serving anyway: {e}")
+            }
+            Err(e) => fail(EX_DATAERR, format!("web assets failed verification: {e}")),
+        }
+    }
 
-fn sqlite_url_for_path(database_path: &Path) -> String {
-    format!("sqlite:{}?mode=rwc", database_path.display())
-}
+    let listen = config.listen.clone();
+    let ready_file = config.ready_file.clone();
+    let tls = config.tls.as_ref().map(|tls| {
+        listen::Tls::load(tls).unwrap_or_else(|e| {
+            fail(
+                EX_DATAERR,
+                format!("failed to load the [tls] certificate: {e}"),
+            )
+        })
+    });
+    if let Some(source) = config.web_source.clone() {
+        web_source::spawn(
+            source,
+            web_cache,
+            config.web_integrity.clone(),
+            config.spa_routes.clone(),
+        );
+    }
+    let app = den::app(config, db.clone())
+        .await
+        .unwrap_or_else(|e| fail(e.exit_code(), e));
 
-async fn init_jwt_secret(db: &sqlx::SqlitePool) -> Vec<u8> {
-    let existing: Option<Vec<u8>> =
-        sqlx::query_scalar("SELECT secret FROM signing_key WHERE id = 1")
-            .fetch_optional(db)
+    let mut listeners = Vec::with_capacity(listen.len());
+    for addr in &listen {
+        let listener = Listener::bind(addr, tls.as_ref())
             .await
-            .unwrap();
-
-    match existing {
-        Some(secret) => {
-            tracing::info!("loaded existing JWT signing key");
-            secret
-        }
-        None => {
-            use rand::Rng;
-            let mut secret = vec![0u8; 64];
-            rand::rng().fill_bytes(&mut secret);
-
-            sqlx::query("INSERT INTO signing_key (id, secret) VALUES (1, ?)")
-                .bind(&secret)
-                .execute(db)
-                .await
-                .unwrap();
-            tracing::info!("generated new JWT signing key");
-            secret
+            .unwrap_or_else(|e| fail(EX_UNAVAILABLE, format!("failed to listen on {addr}: {e}")));
+        let bound = listener
+            .describe()
+            .unwrap_or_else(|e| fail(EX_UNAVAILABLE, format!("failed to read {addr}: {e}")));
+        tracing::info!("listening on {bound}");
+        listeners.push(listener);
+    }
+    if let Some(path) = &ready_file {
+        listen::write_ready_file(path, &listeners).unwrap_or_else(|e| {
+            fail(
+                EX_CANTCREAT,
+                format!("failed to write ready file {}: {e}", path.display()),
+            )
+        });
+    }
+    // Only now: migrations ran and every listener is bound.
+    if let Err(e) = systemd::notify("READY=1") {
+        tracing::warn!("failed to notify systemd of readiness: {e}");
+    }
+    ready();
+    systemd::spawn_watchdog(db);
+    tokio::select! {
+        result = listen::serve(listeners, app) => {
+            result.unwrap_or_else(|e| fail(EX_SOFTWARE, format!("server error: {e}")));
         }
+        () = shutdown => tracing::info!("stopping"),
+    }
+}
+
+/// `den` registered as a Windows service (`sc.exe create den binPath= "C:\...\den.exe
+/// --config C:\...\config.toml"`). Started from a console, the dispatcher refuses
+/// and den runs as usual.
+#[cfg(all(windows, feature = "windows-service"))]
+mod windows {
+    use std::ffi::OsString;
+    use std::time::Duration;
+
+    use tokio::sync::oneshot;
+    use windows_service::service::{
+        ServiceControl, ServiceControlAccept, ServiceExitCode, ServiceState, ServiceStatus,
+        ServiceType,
+    };
+    use windows_service::service_control_handler::{
+        self, ServiceControlHandlerResult, ServiceStatusHandle,
+    };
+    use windows_service::{define_windows_service, service_dispatcher};
+
+    const SERVICE_NAME: &str = "den";
+    /// How long the service control manager waits for `Running` before giving up.
+    const START_WAIT_HINT: Duration = Duration::from_secs(60);
+
+    define_windows_service!(ffi_service_main, service_main);
+
+    /// True once den ran as a service and stopped; false when not started by the
+    /// service control manager.
+    pub fn dispatch() -> bool {
+        service_dispatcher::start(SERVICE_NAME, ffi_service_main).is_ok()
+    }
+
+    fn service_main(_arguments: Vec<OsString>) {
+        let (stop, stopped) = oneshot::channel();
+        let mut stop = Some(stop);
+        let handler = move |control| match control {
+            ServiceControl::Stop | ServiceControl::Shutdown => {
+                if let Some(stop) = stop.take() {
+                    let _ = stop.send(());
+                }
+                ServiceControlHandlerResult::NoError
+            }
+            ServiceControl::Interrogate => ServiceControlHandlerResult::NoError,
+            _ => ServiceControlHandlerResult::NotImplemented,
+        };
+        let Ok(status) = service_control_handler::register(SERVICE_NAME, handler) else {
+            return;
+        };
+        report(
+            status,
+            ServiceState::StartPending,
+            ServiceControlAccept::empty(),
+        );
+        super::run(
+            async {
+                let _ = stopped.await;
+            },
+            || {
+                report(
+                    status,
+                    ServiceState::Running,
+                    ServiceControlAccept::STOP | ServiceControlAccept::SHUTDOWN,
+                )
+            },
+        );
+        report(status, ServiceState::Stopped, ServiceControlAccept::empty());
+    }
+
+    fn report(status: ServiceStatusHandle, state: ServiceState, accept: ServiceControlAccept) {
+        let _ = status.set_service_status(ServiceStatus {
+            service_type: ServiceType::OWN_PROCESS,
+            current_state: state,
+            controls_accepted: accept,
+            exit_code: ServiceExitCode::Win32(0),
+            checkpoint: 0,
+            wait_hint: if state == ServiceState::StartPending {
+                START_WAIT_HINT
+            } else {
+                Duration::default()
+            },
+            process_id: None,
+        });
     }
 }
@@ -0,0 +1,391 @@
+use std::collections::BTreeMap;
+use std::fmt::Write;
+use std::sync::Mutex;
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+use axum::http::{Method, StatusCode};
+
+use crate::config::SloConfig;
+
+/// Upper bounds (seconds) of the latency buckets; `+Inf` follows.
+const BUCKETS: [f64; 11] = [
+    0.005, 0.01, 0.025, 0.05, 0.1, 0.25, 0.5, 1.0, 2.5, 5.0, 10.0,
+];
+/// Route label for requests no route matched (the frontend, unknown paths).
+pub const FALLBACK_ROUTE: &str = "fallback";
+const FAST_WINDOW_MINUTES: u64 = 5;
+const SLOW_WINDOW_MINUTES: u64 = 60;
+/// Requests needed in the slow window before a burn counts: a few failures on an
+/// idle instance are noise, not a spent budget.
+const MIN_REQUESTS: u64 = 20;
+
+/// This replica's request latency histograms and SLO window, exposed in
+/// OpenMetrics text at `/api/admin/metrics`. In memory, reset on restart.
+pub struct Metrics {
+    /// Keyed by (matched route, method); ordered so the exposition is stable.
+    routes: Mutex<BTreeMap<(String, &'static str), RouteStats>>,
+    slo: Option<Slo>,
+}
+
+#[derive(Default)]
+struct RouteStats {
+    /// Per-bucket (not cumulative) counts; the last is `+Inf`.
+    buckets: [u64; BUCKETS.len() + 1],
+    /// The latest observation in each bucket, linking it to a trace.
+    exemplars: [Option<Exemplar>; BUCKETS.len() + 1],
+    sum: f64,
+    /// Responses by status class, 1xx to 5xx.
+    classes: [u64; 5],
+}
+
+struct Exemplar {
+    trace_id: String,
+    seconds: f64,
+    /// Unix time of the observation.
+    at: f64,
+}
+
+struct Slo {
+    config: SloConfig,
+    window: Mutex<SloWindow>,
+}
+
+struct SloWindow {
+    /// (unix minute, requests, bad requests), indexed by minute modulo the window.
+    minutes: [(u64, u64, u64); SLOW_WINDOW_MINUTES as usize],
+    alerting: bool,
+}
+
+/// A fast error-budget burn: how many times faster than sustainable the budget
+/// was spent over the last 5 minutes and the last hour.
+#[derive(Debug, PartialEq)]
+pub struct Burn {
+    pub objective: f64,
+    pub fast: f64,
+    pub slow: f64,
+}
+
+impl Metrics {
+    pub fn new(slo: Option<SloConfig>) -> Self {
+        Self {
+            routes: Mutex::default(),
+            slo: slo.map(|config| Slo {
+                config,
+                window: Mutex::new(SloWindow {
+                    minutes: [(0, 0, 0); SLOW_WINDOW_MINUTES as usize],
+                    alerting: false,
+                }),
+            }),
+        }
+    }
+
+    /// Record one response; `elapsed` runs until the response headers.
+    pub fn observe(
+        &self,
+        route: &str,
+        method: &Method,
+        status: StatusCode,
+        elapsed: Duration,
+        trace_id: Option<String>,
+    ) {
+        self.observe_at(route, method, status, elapsed, trace_id, unix_now());
+    }
+
+    fn observe_at(
+        &self,
+        route: &str,
+        method: &Method,
+        status: StatusCode,
+        elapsed: Duration,
+        trace_id: Option<String>,
+        now: Duration,
+    ) {
+        let seconds = elapsed.as_secs_f64();
+        let bucket = BUCKETS
+            .iter()
+            .position(|&le| seconds <= le)
+            .unwrap_or(BUCKETS.len());
+        {
+            let mut routes = self.routes.lock().unwrap_or_else(|e| e.into_inner());
+            let stats = routes
+                .entry((route.to_owned(), method_label(method)))
+                .or_default();
+            stats.buckets[bucket] += 1;
+            stats.sum += seconds;
+            let class = usize::from(status.as_u16() / 100).clamp(1, 5) - 1;
+            stats.classes[class] += 1;
+            if let Some(trace_id) = trace_id {
+                stats.exemplars[bucket] = Some(Exemplar {
+                    trace_id,
+                    seconds,
+                    at: now.as_secs_f64(),
+                });
+            }
+        }
+        if let Some(slo) = &self.slo {
+            let slow = slo
+                .config
+                .latency_ms
+                .is_some_and(|ms| elapsed > Duration::from_millis(ms));
+            slo.record(now.as_secs() / 60, status.is_server_error() || slow);
+        }
+    }
+
+    /// Called once a minute; `Some` when a fast burn starts. It isn't reported again
+    /// until the burn rate has dropped below `slo.burn_rate` in between.
+    pub fn check_slo(&self) -> Option<Burn> {
+        self.check_slo_at(unix_now().as_secs() / 60)
+    }
+
+    fn check_slo_at(&self, minute: u64) -> Option<Burn> {
+        let slo = self.slo.as_ref()?;
+        let mut window = slo.window.lock().unwrap_or_else(|e| e.into_inner());
+        let (fast, slow) = slo.burn_rates(&window, minute);
+        let burning = fast >= slo.config.burn_rate && slow >= slo.config.burn_rate;
+        let started = burning && !window.alerting;
+        window.alerting = burning;
+        started.then_some(Burn {
+            objective: slo.config.objective,
+            fast,
+            slow,
+        })
+    }
+
+    /// OpenMetrics text exposition (exemplars need OpenMetrics, not the classic
+    /// Prometheus format).
+    pub fn render(&self) -> String {
+        const HISTOGRAM: &str = "den_http_request_duration_seconds";
+        let mut out = String::new();
+        let routes = self.routes.lock().unwrap_or_else(|e| e.into_inner());
+
+        let _ = writeln!(out, "# TYPE {HISTOGRAM} histogram");
+        let _ = writeln!(out, "# UNIT {HISTOGRAM} seconds");
+        let _ = writeln!(
+            out,
+            "# HELP {HISTOGRAM} Time until response headers, per matched route."
+        );
+        for ((route, method), stats) in routes.iter() {
+            let labels = format!("route=\"{}\",method=\"{method}\"", escape(route));
+            let mut cumulative = 0;
+            for (i, count) in stats.buckets.iter().enumerate() {
+                cumulative += count;
+                let le = BUCKETS
+                    .get(i)
+                    .map_or_else(|| "+Inf".to_owned(), |le| format!("{le:?}"));
+                let _ = write!(
+                    out,
+                    "{HISTOGRAM}_bucket{{{labels},le=\"{le}\"}} {cumulative}"
+                );
+                if let Some(e) = &stats.exemplars[i] {
+                    let _ = write!(
+                        out,
+                        " # {{trace_id=\"{}\"}} {:?} {:.3}",
+                        escape(&e.trace_id),
+                        e.seconds,
+                        e.at
+                    );
+                }
+                out.push('\n');
+            }
+            let _ = writeln!(out, "{HISTOGRAM}_count{{{labels}}} {cumulative}");
+            let _ = writeln!(out, "{HISTOGRAM}_sum{{{labels}}} {:?}", stats.sum);
+        }
+
+        let _ = writeln!(out, "# TYPE den_http_responses counter");
+        let _ = writeln!(
+            out,
+            "# HELP den_http_responses Responses per matched route and status class."
+        );
+        for ((route, method), stats) in routes.iter() {
+            for (i, count) in stats.classes.iter().enumerate() {
+                if *count > 0 {
+                    let _ = writeln!(
+                        out,
+                        "den_http_responses_total{{route=\"{}\",method=\"{method}\",class=\"{}xx\"}} {count}",
+                        escape(route),
+                        i + 1
+                    );
+                }
+            }
+        }
+        drop(routes);
+
+        if let Some(slo) = &self.slo {
+            let window = slo.window.lock().unwrap_or_else(|e| e.into_inner());
+            let (fast, slow) = slo.burn_rates(&window, unix_now().as_secs() / 60);
+            let _ = writeln!(out, "# TYPE den_slo_objective gauge");
+            let _ = writeln!(out, "den_slo_objective {:?}", slo.config.objective);
+            let _ = writeln!(out, "# TYPE den_slo_burn_rate gauge");
+            let _ = writeln!(
+                out,
+                "# HELP den_slo_burn_rate Error budget spend relative to the sustainable rate."
+            );
+            let _ = writeln!(out, "den_slo_burn_rate{{window=\"5m\"}} {fast:?}");
+            let _ = writeln!(out, "den_slo_burn_rate{{window=\"1h\"}} {slow:?}");
+        }
+        out.push_str("# EOF\n");
+        out
+    }
+}
+
+impl Slo {
+    fn record(&self, minute: u64, bad: bool) {
+        let mut window = self.window.lock().unwrap_or_else(|e| e.into_inner());
+        let slot = &mut window.minutes[(minute % SLOW_WINDOW_MINUTES) as usize];
+        if slot.0 != minute {
+            *slot = (minute, 0, 0);
+        }
+        slot.1 += 1;
+        slot.2 += u64::from(bad);
+    }
+
+    /// Burn rates over the fast and slow windows ending at `minute`; 0 until the
+    /// slow window has `MIN_REQUESTS`.
+    fn burn_rates(&self, window: &SloWindow, minute: u64) -> (f64, f64) {
+        let totals = |minutes: u64| {
+            window
+                .minutes
+                .iter()
+                .filter(|(at, _, _)| *at <= minute && minute - at < minutes)
+                .fold((0, 0), |(total, bad), (_, t, b)| (total + t, bad + b))
+        };
+        let (total, _) = totals(SLOW_WINDOW_MINUTES);
+        if total < MIN_REQUESTS {
+            return (0.0, 0.0);
+        }
+        let budget = 1.0 - self.config.objective;
+        let rate = |(total, bad): (u64, u64)| match total {
+            0 => 0.0,
+            total => bad as f64 / total as f64 / budget,
+        };
+        (
+            rate(totals(FAST_WINDOW_MINUTES)),
+            rate(totals(SLOW_WINDOW_MINUTES)),
+        )
+    }
+}
+
+/// Keeps the method label bounded whatever clients send.
+fn method_label(method: &Method) -> &'static str {
+    match *method {
+        Method::GET => "GET",
+        Method::HEAD => "HEAD",
+        Method::POST => "POST",
+        Method::PUT => "PUT",
+        Method::PATCH => "PATCH",
+        Method::DELETE => "DELETE",
+        Method::OPTIONS => "OPTIONS",
+        _ => "OTHER",
+    }
+}
+
+fn escape(value: &str) -> String {
+    value
+        .replace('\\', "\\\\")
+        .replace('"', "\\\"")
+        .replace('\n', "\\n")
+}
+
+fn unix_now() -> Duration {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .unwrap_or_default()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const NOW: Duration = Duration::from_secs(1_767_225_600);
+
+    #[test]
+    fn histogram_buckets_are_cumulative_with_exemplars() {
+        let metrics = Metrics::new(None);
+        let observe = |ms, status, trace: Option<&str>| {
+            metrics.observe_at(
+                "/api/login/complete",
+                &Method::POST,
+                status,
+                Duration::from_millis(ms),
+                trace.map(str::to_owned),
+                NOW,
+            )
+        };
+        observe(3, StatusCode::OK, None);
+        observe(40, StatusCode::OK, Some("4bf92f3577b34da6a3ce929d0e0e4736"));
+        observe(20_000, StatusCode::INTERNAL_SERVER_ERROR, Some("slow"));
+
+        let text = metrics.render();
+        let labels = "route=\"/api/login/complete\",method=\"POST\"";
+        for line in [
+            format!("den_http_request_duration_seconds_bucket{{{labels},le=\"0.005\"}} 1"),
+            format!(
+                "den_http_request_duration_seconds_bucket{{{labels},le=\"0.05\"}} 2 \
+                 # {{trace_id=\"4bf92f3577b34da6a3ce929d0e0e4736\"}} 0.04 1767225600.000"
+            ),
+            format!("den_http_request_duration_seconds_bucket{{{labels},le=\"10.0\"}} 2"),
+            format!(
+                "den_http_request_duration_seconds_bucket{{{labels},le=\"+Inf\"}} 3 \
+                 # {{trace_id=\"slow\"}} 20.0 1767225600.000"
+            ),
+            format!("den_http_request_duration_seconds_count{{{labels}}} 3"),
+            format!("den_http_responses_total{{{labels},class=\"2xx\"}} 2"),
+            format!("den_http_responses_total{{{labels},class=\"5xx\"}} 1"),
+        ] {
+            assert!(text.lines().any(|l| l == line), "{line}\n{text}");
+        }
+        assert!(text.ends_with("# EOF\n"));
+    }
+
+    #[test]
+    fn fast_burns_are_reported_once() {
+        let metrics = Metrics::new(Some(SloConfig {
+            objective: 0.99,
+            latency_ms: Some(1000),
+            burn_rate: 10.0,
+        }));
+        let minute = NOW.as_secs() / 60;
+        let observe = |at: Duration, ms, status| {
+            metrics.observe_at(
+                "/api/verify",
+                &Method::GET,
+                status,
+                Duration::from_millis(ms),
+                None,
+                at,
+            )
+        };
+        // 100 good requests spread over the last hour: no burn.
+        for i in 0..100 {
+            observe(NOW - Duration::from_secs(i * 30), 10, StatusCode::OK);
+        }
+        assert_eq!(metrics.check_slo_at(minute), None);
+
+        // 20 failures (half of them merely slow) in the last minute: two thirds of
+        // the last 5 minutes, a sixth of the hour, both past 10x a 1% budget.
+        for i in 0..20 {
+            let (ms, status) = if i % 2 == 0 {
+                (10, StatusCode::BAD_GATEWAY)
+            } else {
+                (2000, StatusCode::OK)
+            };
+            observe(NOW, ms, status);
+        }
+        let burn = metrics.check_slo_at(minute).unwrap();
+        assert!(burn.fast > 10.0 && burn.slow > 10.0, "{burn:?}");
+        assert_eq!(metrics.check_slo_at(minute), None);
+
+        // An hour later the window is empty again, which re-arms the alert.
+        assert_eq!(metrics.check_slo_at(minute + 61), None);
+        assert!(
+            !metrics
+                .slo
+                .as_ref()
+                .unwrap()
+                .window
+                .lock()
+                .unwrap()
+                .alerting
+        );
+    }
+}
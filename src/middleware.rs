@@ -1,14 +1,28 @@
 use axum::body::Body;
-use axum::extract::State;
-use axum::http::Request;
+use std::net::SocketAddr;
+use std::sync::atomic::Ordering;
+use std::time::Instant;
+
+use axum::extract::{ConnectInfo, MatchedPath, State};
+use axum::http::{HeaderValue, Method, Request, StatusCode, header};
 use axum::middleware::Next;
 use axum::response::{IntoResponse, Redirect, Response};
+use tracing::Instrument;
 use url::form_urlencoded;
+use uuid::Uuid;
 
-use crate::origin::{origin_host, request_fallback_scheme, request_origin};
+use crate::audit::{self, AuditEvent, AuditKind};
+use crate::frontend;
+use crate::honeypot;
+use crate::metrics::FALLBACK_ROUTE;
+use crate::origin::{
+    client_ip, is_related_origin, origin_host, request_fallback_scheme, request_origin,
+    request_user_agent,
+};
 use crate::state::AppState;
 
-fn path_matches(path: &str, route: &str) -> bool {
+/// `path` is `route` or below it.
+pub(crate) fn path_matches(path: &str, route: &str) -> bool {
     path == route
         || path
             .strip_prefix(route)
@@ -37,6 +51,10 @@ pub async fn enforce_canonical_auth_origin(
     if origin.eq_ignore_ascii_case(&state.rp_origin) {
         return next.run(request).await;
     }
+    // Related origins sign in on their own host; setup stays on rp_origin.
+    if is_login_path && is_related_origin(&state.related_origins, &origin) {
+        return next.run(request).await;
+    }
 
     let mut q = form_urlencoded::Serializer::new(String::new());
     let (mut has_origin, mut has_path) = (false, false);
@@ -71,3 +89,209 @@ pub async fn enforce_canonical_auth_origin(
     };
     Redirect::temporary(&format!("{}{path}{query}", state.rp_origin)).into_response()
 }
+
+/// Reject clients outside `access_control`; registration has its own rules so it
+/// can be locked to the LAN while login stays reachable.
+pub async fn enforce_access_control(
+    State(state): State<AppState>,
+    ConnectInfo(peer): ConnectInfo<SocketAddr>,
+    request: Request<Body>,
+    next: Next,
+) -> Response {
+    let ip = client_ip(request.headers(), peer.ip(), &state.trusted_proxies);
+    let rules = if path_matches(request.uri().path(), "/api/register") {
+        &state.access_control.register
+    } else {
+        &state.access_control.default
+    };
+    if !rules.permits(ip) {
+        tracing::warn!(%ip, path = request.uri().path(), "blocked by access_control");
+        return StatusCode::FORBIDDEN.into_response();
+    }
+    next.run(request).await
+}
+
+/// With `[honeypot]`, refuse blocked clients, and audit, block and tarpit requests
+/// for decoy paths. Decoys answer 404 like any other missing page.
+pub async fn trap_scanners(
+    State(state): State<AppState>,
+    ConnectInfo(peer): ConnectInfo<SocketAddr>,
+    request: Request<Body>,
+    next: Next,
+) -> Response {
+    let Some(honeypot) = state.honeypot.clone() else {
+        return next.run(request).await;
+    };
+    let ip = client_ip(request.headers(), peer.ip(), &state.trusted_proxies);
+    if honeypot.is_blocked(ip) {
+        return StatusCode::FORBIDDEN.into_response();
+    }
+    let path = request.uri().path();
+    if !honeypot.is_decoy(path) || honeypot.is_exempt(ip) {
+        return next.run(request).await;
+    }
+    if honeypot::undeclared_proxy(peer.ip(), &state.trusted_proxies) {
+        tracing::warn!(
+            %ip,
+            path,
+            "honeypot hit from a private address outside trusted_proxies; not blocking it"
+        );
+        return StatusCode::NOT_FOUND.into_response();
+    }
+
+    tracing::warn!(%ip, path, "honeypot hit, blocking client");
+    honeypot.block(ip, honeypot.block);
+    audit::record(
+        &state,
+        AuditKind::Honeypot,
+        AuditEvent {
+            ip: Some(&ip.to_string()),
+            user_agent: Some(&request_user_agent(request.headers())),
+            detail: Some(path),
+            ..Default::default()
+        },
+    )
+    .await;
+    if let Some(_slot) = honeypot.tarpit_slot() {
+        tokio::time::sleep(honeypot.delay).await;
+    }
+    StatusCode::NOT_FOUND.into_response()
+}
+
+const MAINTENANCE_RETRY_AFTER_SECONDS: &str = "60";
+
+/// In maintenance mode, refuse anything that writes auth state (logins, registration,
+/// passkey edits) with 503. Session validation, refresh and sign-out keep working. With
+/// `error_pages.maintenance`, browsers get that page, including on `/login` and
+/// `/setup`, which could only fail.
+pub async fn reject_during_maintenance(
+    State(state): State<AppState>,
+    request: Request<Body>,
+    next: Next,
+) -> Response {
+    if !state.maintenance.load(Ordering::Relaxed) {
+        return next.run(request).await;
+    }
+    let path = request.uri().path();
+    let exempt = path_matches(path, "/api/admin/maintenance")
+        || path_matches(path, "/api/refresh")
+        || path_matches(path, "/api/logout");
+    let writes = !matches!(*request.method(), Method::GET | Method::HEAD)
+        || path_matches(path, "/api/login")
+        || path_matches(path, "/api/oidc");
+    let refused = path_matches(path, "/api") && writes && !exempt;
+    let page = state
+        .error_pages
+        .maintenance
+        .as_deref()
+        .filter(|_| accepts_html(&request));
+    if let Some(page) = page
+        && (refused || canonical_auth_path(path))
+        && let Some(mut response) =
+            frontend::error_page(page, StatusCode::SERVICE_UNAVAILABLE).await
+    {
+        response.headers_mut().insert(
+            header::RETRY_AFTER,
+            HeaderValue::from_static(MAINTENANCE_RETRY_AFTER_SECONDS),
+        );
+        return response;
+    }
+    if refused {
+        return (
+            StatusCode::SERVICE_UNAVAILABLE,
+            [(header::RETRY_AFTER, MAINTENANCE_RETRY_AFTER_SECONDS)],
+        )
+            .into_response();
+    }
+    next.run(request).await
+}
+
+/// A browser navigation rather than `fetch`, which sends `*/*`.
+fn accepts_html(request: &Request<Body>) -> bool {
+    request
+        .headers()
+        .get(header::ACCEPT)
+        .and_then(|v| v.to_str().ok())
+        .is_some_and(|accept| accept.contains("text/html"))
+}
+
+const REQUEST_ID_HEADER: &str = "x-request-id";
+
+tokio::task_local! {
+    static REQUEST_ID: String;
+}
+
+/// Id of the request being handled, for error bodies; `None` outside `assign_request_id`.
+pub fn current_request_id() -> Option<String> {
+    REQUEST_ID.try_with(Clone::clone).ok()
+}
+
+/// Tag every request with an id (a sane incoming `X-Request-Id` from the proxy, or a
+/// fresh one), echo it on the response and log it, so a reported error can be traced.
+pub async fn assign_request_id(mut request: Request<Body>, next: Next) -> Response {
+    let id = request
+        .headers()
+        .get(REQUEST_ID_HEADER)
+        .and_then(|v| v.to_str().ok())
+        .filter(|v| {
+            !v.is_empty()
+                && v.len() <= 64
+                && v.bytes()
+                    .all(|b| b.is_ascii_alphanumeric() || b == b'-' || b == b'_')
+        })
+        .map_or_else(|| Uuid::new_v4().to_string(), str::to_owned);
+    let value = HeaderValue::from_str(&id).expect("request id is header-safe");
+    request
+        .headers_mut()
+        .insert(REQUEST_ID_HEADER, value.clone());
+    let span = tracing::info_span!("request", request_id = %id);
+    let mut response = REQUEST_ID
+        .scope(id, next.run(request).instrument(span))
+        .await;
+    response.headers_mut().insert(REQUEST_ID_HEADER, value);
+    response
+}
+
+/// Time every response per matched route (`/api/passkeys/{id}`, never the raw
+/// path) for `/api/admin/metrics`. The exemplar's trace id is the W3C
+/// `traceparent` trace id when the proxy sent one, else the request id.
+pub async fn record_metrics(
+    State(state): State<AppState>,
+    request: Request<Body>,
+    next: Next,
+) -> Response {
+    let route = request
+        .extensions()
+        .get::<MatchedPath>()
+        .map_or(FALLBACK_ROUTE, MatchedPath::as_str)
+        .to_owned();
+    let method = request.method().clone();
+    let trace_id = request
+        .headers()
+        .get("traceparent")
+        .and_then(|v| v.to_str().ok())
+        .and_then(traceparent_trace_id)
+        .or_else(current_request_id);
+    let started = Instant::now();
+    let response = next.run(request).await;
+    state.metrics.observe(
+        &route,
+        &method,
+        response.status(),
+        started.elapsed(),
+        trace_id,
+    );
+    response
+}
+
+/// `00-<32 hex trace id>-<16 hex parent id>-<2 hex flags>`; an all-zero id is invalid.
+fn traceparent_trace_id(value: &str) -> Option<String> {
+    let mut parts = value.trim().split('-');
+    let (_version, trace_id) = (parts.next()?, parts.next()?);
+    let valid = trace_id.len() == 32
+        && trace_id
+            .bytes()
+            .all(|b| b.is_ascii_digit() || (b'a'..=b'f').contains(&b))
+        && trace_id.bytes().any(|b| b != b'0');
+    valid.then(|| trace_id.to_owned())
+}
@@ -0,0 +1,173 @@
+use std::io;
+
+use serde::Serialize;
+use url::Url;
+
+use crate::config::PushConfig;
+use crate::http;
+use crate::mailer::Mailer;
+use crate::template::{Message, Templates};
+
+#[derive(Debug, Clone, Serialize)]
+#[serde(tag = "event", rename_all = "snake_case")]
+pub enum SecurityEvent {
+    SignCountRegression {
+        credential_id: String,
+    },
+    NewDevice {
+        passkey: String,
+        user_agent: String,
+        ip: String,
+    },
+    PasskeyRemoved {
+        passkey: String,
+    },
+    RecoveryRequested {
+        user_name: String,
+        ready_at: String,
+    },
+    RecoveryCancelled {
+        user_name: String,
+    },
+    RecoveryCompleted {
+        user_name: String,
+    },
+    /// The recovery URL answered a login step-up in place of a second passkey.
+    RecoveryCodeUsed {
+        user_agent: String,
+        ip: String,
+    },
+    /// Not about an account: den is failing fast enough to spend its error budget.
+    SloBurn {
+        instance_id: String,
+        objective: String,
+        burn_rate_5m: String,
+        burn_rate_1h: String,
+    },
+}
+
+impl SecurityEvent {
+    pub fn kind(&self) -> &'static str {
+        match self {
+            Self::SignCountRegression { .. } => "sign_count_regression",
+            Self::NewDevice { .. } => "new_device",
+            Self::PasskeyRemoved { .. } => "passkey_removed",
+            Self::RecoveryRequested { .. } => "recovery_requested",
+            Self::RecoveryCancelled { .. } => "recovery_cancelled",
+            Self::RecoveryCompleted { .. } => "recovery_completed",
+            Self::RecoveryCodeUsed { .. } => "recovery_code_used",
+            Self::SloBurn { .. } => "slo_burn",
+        }
+    }
+
+    /// ntfy-style priority (1 = min, 5 = max).
+    fn priority(&self) -> u8 {
+        match self {
+            Self::SignCountRegression { .. } => 5,
+            Self::NewDevice { .. } => 4,
+            Self::PasskeyRemoved { .. } => 3,
+            Self::RecoveryRequested { .. } => 5,
+            Self::RecoveryCancelled { .. } => 3,
+            Self::RecoveryCompleted { .. } => 5,
+            Self::RecoveryCodeUsed { .. } => 5,
+            Self::SloBurn { .. } => 4,
+        }
+    }
+}
+
+#[derive(Debug, Default)]
+pub struct Notifier {
+    webhook_url: Option<Url>,
+    mailer: Option<Mailer>,
+    push: Option<PushConfig>,
+    templates: Templates,
+}
+
+impl Notifier {
+    pub fn new(
+        webhook_url: Option<Url>,
+        mailer: Option<Mailer>,
+        push: Option<PushConfig>,
+        templates: Templates,
+    ) -> Self {
+        Self {
+            webhook_url,
+            mailer,
+            push,
+            templates,
+        }
+    }
+
+    /// Fire-and-forget delivery; failures are logged and never block the request.
+    pub fn send(&self, event: SecurityEvent) {
+        let message = self.templates.render(&event);
+        if let Some(mailer) = &self.mailer {
+            mailer.send(&event, &message);
+        }
+        if let Some(push) = self.push.clone() {
+            let event = event.clone();
+            let message = message.clone();
+            tokio::task::spawn_blocking(move || match send_push(&push, &event, &message) {
+                Ok(status) if (200..300).contains(&status) => {}
+                Ok(status) => tracing::warn!(status, "push notification rejected"),
+                Err(error) => tracing::warn!(error = %error, "push notification failed"),
+            });
+        }
+        let Some(url) = self.webhook_url.clone() else {
+            return;
+        };
+        tokio::task::spawn_blocking(move || {
+            let body = match serde_json::to_vec(&event) {
+                Ok(body) => body,
+                Err(error) => {
+                    tracing::warn!(error = %error, "failed to encode webhook payload");
+                    return;
+                }
+            };
+            match post(&url, &[("Content-Type", "application/json")], &body) {
+                Ok(status) if (200..300).contains(&status) => {}
+                Ok(status) => tracing::warn!(status, "alert webhook rejected event"),
+                Err(error) => tracing::warn!(error = %error, "alert webhook delivery failed"),
+            }
+        });
+    }
+}
+
+fn send_push(push: &PushConfig, event: &SecurityEvent, message: &Message) -> io::Result<u16> {
+    let url = Url::parse(push.url()).map_err(io::Error::other)?;
+    let priority = event.priority().to_string();
+    match push {
+        PushConfig::Ntfy { token, .. } => {
+            let authorization = token.as_ref().map(|t| format!("Bearer {t}"));
+            let mut headers = vec![
+                ("Title", message.title.as_str()),
+                ("Priority", priority.as_str()),
+                ("Tags", event.kind()),
+            ];
+            if let Some(authorization) = &authorization {
+                headers.push(("Authorization", authorization.as_str()));
+            }
+            post(&url, &headers, message.body.as_bytes())
+        }
+        PushConfig::Gotify { token, .. } => {
+            let url = url.join("message").map_err(io::Error::other)?;
+            let body = serde_json::to_vec(&serde_json::json!({
+                "title": message.title,
+                "message": message.body,
+                "priority": event.priority(),
+            }))?;
+            post(
+                &url,
+                &[
+                    ("Content-Type", "application/json"),
+                    ("X-Gotify-Key", token.as_str()),
+                ],
+                &body,
+            )
+        }
+    }
+}
+
+fn post(url: &Url, headers: &[(&str, &str)], body: &[u8]) -> io::Result<u16> {
+    http::post(url, headers, body).map(|r| r.status)
+}
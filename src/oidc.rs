@@ -0,0 +1,199 @@
+use base64::Engine;
+use base64::engine::general_purpose::URL_SAFE_NO_PAD as BASE64URL;
+use jsonwebtoken::jwk::JwkSet;
+use jsonwebtoken::{Algorithm, DecodingKey, Validation, decode, decode_header};
+use openssl::rand::rand_bytes;
+use serde::Deserialize;
+use sha2::{Digest, Sha256};
+use url::Url;
+
+use crate::config::UpstreamOidcConfig;
+use crate::http;
+
+/// Signature algorithms accepted on upstream ID tokens; symmetric and `none` never are.
+const ALLOWED_ALGORITHMS: &[Algorithm] = &[
+    Algorithm::RS256,
+    Algorithm::RS384,
+    Algorithm::RS512,
+    Algorithm::PS256,
+    Algorithm::PS384,
+    Algorithm::PS512,
+    Algorithm::ES256,
+    Algorithm::ES384,
+    Algorithm::EdDSA,
+];
+
+#[derive(Deserialize)]
+struct Discovery {
+    issuer: String,
+    authorization_endpoint: Url,
+    token_endpoint: Url,
+    jwks_uri: Url,
+}
+
+#[derive(Deserialize)]
+struct TokenResponse {
+    id_token: String,
+}
+
+/// A started authorization-code flow; everything but `url` stays server-side.
+pub struct AuthorizationRequest {
+    pub url: String,
+    pub state: String,
+    pub nonce: String,
+    pub verifier: String,
+}
+
+/// Authorization-code + PKCE client for an external IdP. Blocking: call from
+/// `spawn_blocking`. Discovery is fetched per flow so IdP key rotation just works.
+pub struct UpstreamOidc {
+    config: UpstreamOidcConfig,
+    redirect_uri: String,
+}
+
+impl UpstreamOidc {
+    pub fn new(config: UpstreamOidcConfig, rp_origin: &str) -> Self {
+        Self {
+            config,
+            redirect_uri: format!("{rp_origin}/api/oidc/callback"),
+        }
+    }
+
+    pub fn label(&self) -> &str {
+        &self.config.label
+    }
+
+    pub fn authorize(&self) -> Result<AuthorizationRequest, String> {
+        let discovery = self.discover()?;
+        let state = random_token();
+        let nonce = random_token();
+        let verifier = random_token();
+        let mut url = discovery.authorization_endpoint;
+        url.query_pairs_mut()
+            .append_pair("response_type", "code")
+            .append_pair("client_id", &self.config.client_id)
+            .append_pair("redirect_uri", &self.redirect_uri)
+            .append_pair("scope", &self.config.scopes.join(" "))
+            .append_pair("state", &state)
+            .append_pair("nonce", &nonce)
+            .append_pair("code_challenge", &pkce_challenge(&verifier))
+            .append_pair("code_challenge_method", "S256");
+        Ok(AuthorizationRequest {
+            url: url.into(),
+            state,
+            nonce,
+            verifier,
+        })
+    }
+
+    /// Redeem `code` and verify the ID token; returns the allowed `user_claim` value.
+    pub fn complete(&self, code: &str, verifier: &str, nonce: &str) -> Result<String, String> {
+        let discovery = self.discover()?;
+        let form = url::form_urlencoded::Serializer::new(String::new())
+            .append_pair("grant_type", "authorization_code")
+            .append_pair("code", code)
+            .append_pair("redirect_uri", &self.redirect_uri)
+            .append_pair("client_id", &self.config.client_id)
+            .append_pair("client_secret", &self.config.client_secret)
+            .append_pair("code_verifier", verifier)
+            .finish();
+        let response = http::post(
+            &discovery.token_endpoint,
+            &[
+                ("Content-Type", "application/x-www-form-urlencoded"),
+                ("Accept", "application/json"),
+            ],
+            form.as_bytes(),
+        )
+        .map_err(|e| format!("token request failed: {e}"))?;
+        if !response.is_success() {
+            return Err(format!("token endpoint returned {}", response.status));
+        }
+        let tokens: TokenResponse = serde_json::from_slice(&response.body)
+            .map_err(|e| format!("malformed token response: {e}"))?;
+
+        let claims = self.verify_id_token(&discovery, &tokens.id_token)?;
+        if claims.get("nonce").and_then(|n| n.as_str()) != Some(nonce) {
+            return Err("id_token nonce mismatch".into());
+        }
+        let user = claims
+            .get(&self.config.user_claim)
+            .and_then(|v| v.as_str())
+            .ok_or_else(|| format!("id_token has no {} claim", self.config.user_claim))?;
+        if !self.config.allowed_users.iter().any(|u| u == user) {
+            return Err(format!("{user} is not in upstream_oidc.allowed_users"));
+        }
+        Ok(user.to_owned())
+    }
+
+    fn discover(&self) -> Result<Discovery, String> {
+        let base = self.config.issuer.trim_end_matches('/');
+        let url = Url::parse(&format!("{base}/.well-known/openid-configuration"))
+            .map_err(|e| e.to_string())?;
+        let discovery: Discovery = get_json(&url)?;
+        if discovery.issuer.trim_end_matches('/') != base {
+            return Err(format!("discovery issuer mismatch: {}", discovery.issuer));
+        }
+        Ok(discovery)
+    }
+
+    fn verify_id_token(
+        &self,
+        discovery: &Discovery,
+        token: &str,
+    ) -> Result<serde_json::Map<String, serde_json::Value>, String> {
+        let header = decode_header(token).map_err(|e| format!("malformed id_token: {e}"))?;
+        if !ALLOWED_ALGORITHMS.contains(&header.alg) {
+            return Err(format!(
+                "id_token uses disallowed algorithm {:?}",
+                header.alg
+            ));
+        }
+        let jwks: JwkSet = get_json(&discovery.jwks_uri)?;
+        let jwk = match &header.kid {
+            Some(kid) => jwks.find(kid),
+            None => jwks.keys.first(),
+        }
+        .ok_or("no matching key in jwks")?;
+        let key = DecodingKey::from_jwk(jwk).map_err(|e| format!("unusable jwk: {e}"))?;
+
+        let mut validation = Validation::new(header.alg);
+        validation.set_issuer(&[&discovery.issuer]);
+        validation.set_audience(&[&self.config.client_id]);
+        decode(token, &key, &validation)
+            .map(|data| data.claims)
+            .map_err(|e| format!("id_token rejected: {e}"))
+    }
+}
+
+fn get_json<T: serde::de::DeserializeOwned>(url: &Url) -> Result<T, String> {
+    let response =
+        http::get(url, &[("Accept", "application/json")]).map_err(|e| format!("{url}: {e}"))?;
+    if !response.is_success() {
+        return Err(format!("{url} returned {}", response.status));
+    }
+    serde_json::from_slice(&response.body).map_err(|e| format!("{url}: {e}"))
+}
+
+fn random_token() -> String {
+    let mut bytes = [0u8; 32];
+    rand_bytes(&mut bytes).expect("openssl rng failed");
+    BASE64URL.encode(bytes)
+}
+
+fn pkce_challenge(verifier: &str) -> String {
+    BASE64URL.encode(Sha256::digest(verifier.as_bytes()))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn pkce_challenge_matches_rfc7636_example() {
+        assert_eq!(
+            pkce_challenge("dBjftJeZ4CVP-mB92K27uhbUJU1p1r_wW1gFWFOEjXk"),
+            "E9Melhoa2OwvFrEMTJguCHaoeK1t8URWbuGJSstw-cM"
+        );
+    }
+}
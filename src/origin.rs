@@ -1,8 +1,12 @@
 use std::collections::HashSet;
+use std::net::IpAddr;
+use std::sync::{Arc, RwLock};
 
 use axum::http::{HeaderMap, header};
 use url::Url;
 
+use crate::access::IpNet;
+
 pub fn request_origin(headers: &HeaderMap, fallback_scheme: &str) -> Option<String> {
     let proto = header_value_first(headers, "x-forwarded-proto").unwrap_or(fallback_scheme);
     request_host(headers).map(|host| format!("{proto}://{host}"))
@@ -14,6 +18,37 @@ pub fn request_host(headers: &HeaderMap) -> Option<String> {
         .map(str::to_owned)
 }
 
+/// Walk `X-Forwarded-For` right to left while the hop we got it from is a trusted
+/// proxy; the first untrusted address is the client.
+pub fn client_ip(headers: &HeaderMap, peer: IpAddr, trusted: &[IpNet]) -> IpAddr {
+    let forwarded: Vec<&str> = headers
+        .get_all("x-forwarded-for")
+        .iter()
+        .filter_map(|v| v.to_str().ok())
+        .flat_map(|v| v.split(','))
+        .map(str::trim)
+        .collect();
+    let mut ip = peer.to_canonical();
+    for hop in forwarded.iter().rev() {
+        if !trusted.iter().any(|net| net.contains(ip)) {
+            break;
+        }
+        match hop.parse::<IpAddr>() {
+            Ok(hop) => ip = hop.to_canonical(),
+            Err(_) => break,
+        }
+    }
+    ip
+}
+
+pub fn request_user_agent(headers: &HeaderMap) -> String {
+    headers
+        .get(header::USER_AGENT)
+        .and_then(|v| v.to_str().ok())
+        .unwrap_or("unknown")
+        .to_string()
+}
+
 fn header_value_first(
     headers: &HeaderMap,
     name: impl axum::http::header::AsHeaderName,
@@ -71,7 +106,7 @@ pub fn origin_host(origin: &str) -> Option<String> {
     host_with_port(&parsed)
 }
 
-fn normalize_host(candidate: &str) -> Option<String> {
+pub fn normalize_host(candidate: &str) -> Option<String> {
     let candidate = candidate.trim();
     if candidate.is_empty() {
         return None;
@@ -87,6 +122,20 @@ fn normalize_host(candidate: &str) -> Option<String> {
     host_with_port(&parsed)
 }
 
+/// Whether `host` (optionally with port) is `domain` or one of its subdomains.
+pub fn host_in_domain(host: &str, domain: &str) -> bool {
+    let Some(host) = Url::parse(&format!("http://{host}"))
+        .ok()
+        .and_then(|u| u.host_str().map(str::to_ascii_lowercase))
+    else {
+        return false;
+    };
+    host == domain
+        || host
+            .strip_suffix(domain)
+            .is_some_and(|rest| rest.ends_with('.'))
+}
+
 pub fn request_fallback_scheme(headers: &HeaderMap, rp_origin: &str) -> &'static str {
     let rp_fallback = if rp_origin.starts_with("https://") {
         "https"
@@ -121,6 +170,63 @@ pub fn load_allowed_hosts(rp_origin: &str, configured_hosts: &[String]) -> HashS
     hosts
 }
 
+/// Whether `origin` is listed in `/.well-known/webauthn` (`related_origins`), so
+/// it may run passkey ceremonies itself.
+pub fn is_related_origin(related_origins: &[String], origin: &str) -> bool {
+    related_origins
+        .iter()
+        .any(|related| related.eq_ignore_ascii_case(origin))
+}
+
+/// Hosts den will hand sessions to: the startup set (rp_origin, `allowed_hosts`,
+/// `[[apps]]`) plus hosts added at runtime through the admin API.
+pub struct AllowedHosts {
+    configured: HashSet<String>,
+    runtime: RwLock<Arc<Vec<String>>>,
+    all: RwLock<Arc<HashSet<String>>>,
+}
+
+impl AllowedHosts {
+    pub fn new(configured: HashSet<String>) -> Self {
+        Self {
+            all: RwLock::new(Arc::new(configured.clone())),
+            configured,
+            runtime: RwLock::default(),
+        }
+    }
+
+    pub fn contains(&self, host: &str) -> bool {
+        self.all().contains(host)
+    }
+
+    /// Every allowed host, configured or runtime.
+    pub fn all(&self) -> Arc<HashSet<String>> {
+        self.all
+            .read()
+            .expect("allowed hosts lock poisoned")
+            .clone()
+    }
+
+    pub fn configured(&self) -> &HashSet<String> {
+        &self.configured
+    }
+
+    pub fn runtime(&self) -> Arc<Vec<String>> {
+        self.runtime
+            .read()
+            .expect("allowed hosts lock poisoned")
+            .clone()
+    }
+
+    /// Replace the runtime hosts (already normalized).
+    pub fn set_runtime(&self, hosts: Vec<String>) {
+        let mut all = self.configured.clone();
+        all.extend(hosts.iter().cloned());
+        *self.runtime.write().expect("allowed hosts lock poisoned") = Arc::new(hosts);
+        *self.all.write().expect("allowed hosts lock poisoned") = Arc::new(all);
+    }
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
@@ -158,6 +264,34 @@ mod tests {
         );
     }
 
+    #[test]
+    fn host_in_domain_matches_subdomains_only() {
+        assert!(host_in_domain("lab.example.com", "lab.example.com"));
+        assert!(host_in_domain(
+            "grafana.lab.example.com:8443",
+            "lab.example.com"
+        ));
+        assert!(!host_in_domain("evillab.example.com", "lab.example.com"));
+        assert!(!host_in_domain("example.com", "lab.example.com"));
+    }
+
+    #[test]
+    fn client_ip_stops_at_first_untrusted_hop() {
+        let trusted: Vec<IpNet> = vec!["127.0.0.1".parse().unwrap(), "10.0.0.0/8".parse().unwrap()];
+        let mut headers = HeaderMap::new();
+        headers.insert(
+            "x-forwarded-for",
+            HeaderValue::from_static("6.6.6.6, 203.0.113.9, 10.0.0.2"),
+        );
+        let loopback = "127.0.0.1".parse().unwrap();
+        assert_eq!(
+            client_ip(&headers, loopback, &trusted),
+            "203.0.113.9".parse::<IpAddr>().unwrap()
+        );
+        let direct = "198.51.100.1".parse().unwrap();
+        assert_eq!(client_ip(&headers, direct, &trusted), direct);
+    }
+
     #[test]
     fn request_origin_uses_fallback_scheme_when_proto_missing() {
         let mut headers = HeaderMap::new();
@@ -200,4 +334,15 @@ mod tests {
             "http"
         );
     }
+
+    #[test]
+    fn runtime_hosts_join_and_leave_the_configured_set() {
+        let hosts = AllowedHosts::new(HashSet::from(["auth.example.com".to_owned()]));
+        hosts.set_runtime(vec!["grafana.example.com".into()]);
+        assert!(hosts.contains("auth.example.com"));
+        assert!(hosts.contains("grafana.example.com"));
+        hosts.set_runtime(Vec::new());
+        assert!(hosts.contains("auth.example.com"));
+        assert!(!hosts.contains("grafana.example.com"));
+    }
 }
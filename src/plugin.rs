@@ -0,0 +1,212 @@
+use serde::Serialize;
+
+#[cfg(feature = "policy-plugin")]
+use crate::config::{HookFailure, PolicyPluginConfig};
+
+/// A policy plugin's answer for one sign-in.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Verdict {
+    Allow,
+    Deny,
+    /// Ask for a second passkey assertion, as an anomalous login would.
+    StepUp,
+}
+
+/// What the plugin is told about a passkey sign-in, as one JSON object.
+#[derive(Debug, Serialize)]
+pub struct PluginContext<'a> {
+    pub user_id: &'a str,
+    /// Name of the passkey that signed.
+    pub passkey: &'a str,
+    pub ip: &'a str,
+    pub country: Option<&'a str>,
+    pub asn: Option<u32>,
+    /// Browser family + OS, as the anomaly check sees it ("Firefox/Linux").
+    pub device: &'a str,
+    pub user_agent: &'a str,
+    /// The app the login redirects to, if any.
+    pub origin: Option<&'a str>,
+}
+
+/// `[policy_plugin]`: the module compiled once at startup; every sign-in gets a
+/// fresh instance with its own fuel and memory limit.
+#[cfg(feature = "policy-plugin")]
+pub struct PolicyPlugin {
+    engine: wasmtime::Engine,
+    module: wasmtime::Module,
+    fuel: u64,
+    max_memory_bytes: usize,
+    on_error: HookFailure,
+}
+
+#[cfg(feature = "policy-plugin")]
+impl PolicyPlugin {
+    pub fn load(config: &PolicyPluginConfig) -> wasmtime::Result<Self> {
+        let bytes = std::fs::read(&config.path)?;
+        Self::compile(&bytes, config)
+    }
+
+    /// Compile `bytes` (binary or text format) and check its interface up front,
+    /// so a broken module stops startup rather than every sign-in.
+    fn compile(bytes: &[u8], config: &PolicyPluginConfig) -> wasmtime::Result<Self> {
+        let mut engine_config = wasmtime::Config::new();
+        engine_config.consume_fuel(true);
+        let engine = wasmtime::Engine::new(&engine_config)?;
+        let module = wasmtime::Module::new(&engine, bytes)?;
+        // No imports means no host access at all: no clock, files or network.
+        if let Some(import) = module.imports().next() {
+            return Err(wasmtime::Error::msg(format!(
+                "imports {}::{}; policy plugins can't import anything",
+                import.module(),
+                import.name()
+            )));
+        }
+        for export in ["memory", "alloc", "evaluate"] {
+            if module.get_export(export).is_none() {
+                return Err(wasmtime::Error::msg(format!("doesn't export {export}")));
+            }
+        }
+        Ok(Self {
+            engine,
+            module,
+            fuel: config.fuel,
+            max_memory_bytes: config.max_memory_mb as usize * 1024 * 1024,
+            on_error: config.on_error,
+        })
+    }
+
+    /// The module's verdict; a trap, exhausted fuel or unknown verdict counts as
+    /// `on_error`.
+    pub async fn evaluate(self: std::sync::Arc<Self>, context: &PluginContext<'_>) -> Verdict {
+        let input = serde_json::to_vec(context).expect("plugin context serializes");
+        let plugin = self.clone();
+        let result = tokio::task::spawn_blocking(move || plugin.run(&input))
+            .await
+            .map_err(wasmtime::Error::from)
+            .and_then(|result| result);
+        match result {
+            Ok(verdict) => verdict,
+            Err(error) => {
+                tracing::warn!(error = format!("{error:#}"), "policy plugin failed");
+                match self.on_error {
+                    HookFailure::Deny => Verdict::Deny,
+                    HookFailure::Allow => Verdict::Allow,
+                }
+            }
+        }
+    }
+
+    fn run(&self, input: &[u8]) -> wasmtime::Result<Verdict> {
+        let limits = wasmtime::StoreLimitsBuilder::new()
+            .memory_size(self.max_memory_bytes)
+            .instances(1)
+            .build();
+        let mut store = wasmtime::Store::new(&self.engine, limits);
+        store.limiter(|limits| limits);
+        store.set_fuel(self.fuel)?;
+        let instance = wasmtime::Instance::new(&mut store, &self.module, &[])?;
+        let memory = instance
+            .get_memory(&mut store, "memory")
+            .ok_or_else(|| wasmtime::Error::msg("memory is not a memory"))?;
+        let alloc = instance.get_typed_func::<i32, i32>(&mut store, "alloc")?;
+        let evaluate = instance.get_typed_func::<(i32, i32), i32>(&mut store, "evaluate")?;
+
+        let len = i32::try_from(input.len())?;
+        let ptr = alloc.call(&mut store, len)?;
+        memory.write(&mut store, usize::try_from(ptr)?, input)?;
+        match evaluate.call(&mut store, (ptr, len))? {
+            0 => Ok(Verdict::Allow),
+            1 => Ok(Verdict::Deny),
+            2 => Ok(Verdict::StepUp),
+            other => Err(wasmtime::Error::msg(format!("unknown verdict {other}"))),
+        }
+    }
+}
+
+#[cfg(all(test, feature = "policy-plugin"))]
+mod tests {
+    use std::sync::Arc;
+
+    use super::*;
+
+    fn config(on_error: HookFailure) -> PolicyPluginConfig {
+        PolicyPluginConfig {
+            path: String::new(),
+            fuel: 100_000,
+            max_memory_mb: 1,
+            on_error,
+        }
+    }
+
+    /// A module whose `evaluate` runs `body`, with the context copied to 1024.
+    fn module(body: &str) -> String {
+        format!(
+            r#"(module
+                (memory (export "memory") 1)
+                (func (export "alloc") (param i32) (result i32) i32.const 1024)
+                (func (export "evaluate") (param $ptr i32) (param $len i32) (result i32)
+                  {body}))"#
+        )
+    }
+
+    fn context() -> PluginContext<'static> {
+        PluginContext {
+            user_id: "u1",
+            passkey: "laptop",
+            ip: "192.0.2.1",
+            country: Some("DE"),
+            asn: None,
+            device: "Firefox/Linux",
+            user_agent: "Mozilla/5.0",
+            origin: None,
+        }
+    }
+
+    async fn verdict(body: &str, on_error: HookFailure) -> Verdict {
+        let plugin = PolicyPlugin::compile(module(body).as_bytes(), &config(on_error)).unwrap();
+        Arc::new(plugin).evaluate(&context()).await
+    }
+
+    #[tokio::test]
+    async fn verdicts_come_from_the_module() {
+        // The context is in memory: '{' (123) at `ptr`.
+        let reads_input = "(if (result i32) (i32.eq (i32.load8_u (local.get $ptr)) (i32.const 123)) \
+                           (then (i32.const 2)) (else (i32.const 1)))";
+        assert_eq!(
+            verdict(reads_input, HookFailure::Allow).await,
+            Verdict::StepUp
+        );
+        assert_eq!(
+            verdict("i32.const 0", HookFailure::Deny).await,
+            Verdict::Allow
+        );
+        assert_eq!(
+            verdict("i32.const 1", HookFailure::Allow).await,
+            Verdict::Deny
+        );
+    }
+
+    #[tokio::test]
+    async fn failures_follow_on_error() {
+        let spin = "(loop $spin (br $spin)) i32.const 0";
+        assert_eq!(verdict(spin, HookFailure::Deny).await, Verdict::Deny);
+        assert_eq!(verdict(spin, HookFailure::Allow).await, Verdict::Allow);
+        assert_eq!(
+            verdict("i32.const 7", HookFailure::Deny).await,
+            Verdict::Deny
+        );
+        assert_eq!(
+            verdict("unreachable", HookFailure::Allow).await,
+            Verdict::Allow
+        );
+    }
+
+    #[test]
+    fn modules_may_not_import() {
+        let wat = r#"(module (import "env" "now" (func)) (memory (export "memory") 1))"#;
+        let error = PolicyPlugin::compile(wat.as_bytes(), &config(HookFailure::Deny))
+            .err()
+            .unwrap();
+        assert!(error.to_string().contains("env::now"), "{error}");
+    }
+}
@@ -0,0 +1,321 @@
+use std::net::IpAddr;
+
+use time::{OffsetDateTime, Weekday};
+use uuid::Uuid;
+
+use crate::access::{IpNet, parse_nets};
+use crate::config::{ConfigError, LoginMethod, PolicyDay, PolicyEffect, PolicyRuleConfig, invalid};
+use crate::origin::normalize_origin;
+
+/// A session about to be issued, as `[[policies]]` sees it.
+#[derive(Debug, Clone, Copy)]
+pub struct Request<'a> {
+    pub ip: IpAddr,
+    /// The origin the session is for; den's own `rp_origin` for a plain sign-in.
+    pub app: &'a str,
+    pub method: LoginMethod,
+    /// The signing passkey's authenticator model, when its attestation named one.
+    pub aaguid: Option<Uuid>,
+}
+
+/// The rule name refusals report when a delegated sub-account asks for an app
+/// outside its list.
+pub const SUB_ACCOUNT_RULE: &str = "sub-account apps";
+
+/// Whether a user limited to `allowed_apps` (normalized, lowercase origins) may
+/// reach `app`. `None` is anyone but a sub-account, who may reach every app.
+pub fn app_allowed(allowed_apps: Option<&[String]>, app: &str) -> bool {
+    allowed_apps.is_none_or(|apps| {
+        normalize_origin(app).is_some_and(|app| apps.iter().any(|a| a.eq_ignore_ascii_case(&app)))
+    })
+}
+
+/// `[[policies]]`, in config order.
+#[derive(Debug, Default)]
+pub struct Policies {
+    rules: Vec<Rule>,
+}
+
+#[derive(Debug)]
+struct Rule {
+    name: String,
+    effect: PolicyEffect,
+    networks: Vec<IpNet>,
+    window: Window,
+    /// Normalized, lowercase.
+    apps: Vec<String>,
+    methods: Vec<LoginMethod>,
+    aaguids: Vec<Uuid>,
+}
+
+impl Policies {
+    pub fn load(config: &[PolicyRuleConfig]) -> Result<Self, ConfigError> {
+        let rules = config
+            .iter()
+            .map(|rule| {
+                Ok(Rule {
+                    name: rule.name.clone(),
+                    effect: rule.effect,
+                    networks: parse_nets(&rule.networks, "policies.networks")?,
+                    window: Window::load(rule.hours.as_deref(), &rule.days, "policies.hours")?,
+                    apps: rule
+                        .apps
+                        .iter()
+                        .map(|app| {
+                            normalize_origin(app)
+                                .map(|origin| origin.to_ascii_lowercase())
+                                .ok_or_else(|| {
+                                    invalid(
+                                        "policies.apps",
+                                        format!("{app:?} is not an http(s) origin"),
+                                    )
+                                })
+                        })
+                        .collect::<Result<_, _>>()?,
+                    methods: rule.methods.clone(),
+                    aaguids: rule
+                        .aaguids
+                        .iter()
+                        .map(|aaguid| {
+                            Uuid::parse_str(aaguid.trim()).map_err(|_| {
+                                invalid("policies.aaguids", format!("{aaguid:?} is not an AAGUID"))
+                            })
+                        })
+                        .collect::<Result<_, _>>()?,
+                })
+            })
+            .collect::<Result<_, ConfigError>>()?;
+        Ok(Self { rules })
+    }
+
+    /// The rule refusing `request` at `now`: the first one that matches, if it
+    /// denies. `None` means allowed.
+    pub fn denied_by(&self, request: &Request<'_>, now: OffsetDateTime) -> Option<&str> {
+        self.rules
+            .iter()
+            .find(|rule| rule.matches(request, now))
+            .filter(|rule| rule.effect == PolicyEffect::Deny)
+            .map(|rule| rule.name.as_str())
+    }
+}
+
+impl Rule {
+    fn matches(&self, request: &Request<'_>, now: OffsetDateTime) -> bool {
+        (self.networks.is_empty() || self.networks.iter().any(|net| net.contains(request.ip)))
+            && self.window.contains(now)
+            && (self.apps.is_empty() || app_allowed(Some(&self.apps), request.app))
+            && (self.methods.is_empty() || self.methods.contains(&request.method))
+            && (self.aaguids.is_empty()
+                || request
+                    .aaguid
+                    .is_some_and(|aaguid| self.aaguids.contains(&aaguid)))
+    }
+}
+
+/// UTC hours of the day and days of the week; a part left out means any.
+#[derive(Debug, Clone, Default)]
+pub struct Window {
+    /// Minutes after midnight, end exclusive.
+    hours: Option<(u16, u16)>,
+    days: Vec<Weekday>,
+}
+
+impl Window {
+    /// `key` names the hours setting in the error for a malformed window.
+    pub fn load(
+        hours: Option<&str>,
+        days: &[PolicyDay],
+        key: &'static str,
+    ) -> Result<Self, ConfigError> {
+        let hours = match hours {
+            Some(hours) => Some(parse_hours(hours).ok_or_else(|| {
+                invalid(key, format!("{hours:?} must look like \"08:00-18:00\""))
+            })?),
+            None => None,
+        };
+        Ok(Self {
+            hours,
+            days: days.iter().map(|day| weekday(*day)).collect(),
+        })
+    }
+
+    /// Whether `now` falls inside. Days are checked against the current UTC day,
+    /// so the early hours of a window wrapping past midnight count as the next day.
+    pub fn contains(&self, now: OffsetDateTime) -> bool {
+        let minute = u16::from(now.hour()) * 60 + u16::from(now.minute());
+        self.hours.is_none_or(|hours| within(minute, hours))
+            && (self.days.is_empty() || self.days.contains(&now.weekday()))
+    }
+}
+
+/// `"HH:MM-HH:MM"` as minutes after midnight. The end may be `24:00`, or before
+/// the start to wrap past midnight; an empty window is refused.
+pub fn parse_hours(hours: &str) -> Option<(u16, u16)> {
+    let (start, end) = hours.split_once('-')?;
+    let window = (minutes(start.trim())?, minutes(end.trim())?);
+    (window.0 != window.1 && window.0 < 24 * 60).then_some(window)
+}
+
+fn minutes(time: &str) -> Option<u16> {
+    let (hour, minute) = time.split_once(':')?;
+    if hour.len() != 2 || minute.len() != 2 {
+        return None;
+    }
+    let (hour, minute) = (hour.parse::<u16>().ok()?, minute.parse::<u16>().ok()?);
+    let total = hour * 60 + minute;
+    (minute < 60 && total <= 24 * 60).then_some(total)
+}
+
+fn within(minute: u16, (start, end): (u16, u16)) -> bool {
+    if start < end {
+        (start..end).contains(&minute)
+    } else {
+        minute >= start || minute < end
+    }
+}
+
+fn weekday(day: PolicyDay) -> Weekday {
+    match day {
+        PolicyDay::Mon => Weekday::Monday,
+        PolicyDay::Tue => Weekday::Tuesday,
+        PolicyDay::Wed => Weekday::Wednesday,
+        PolicyDay::Thu => Weekday::Thursday,
+        PolicyDay::Fri => Weekday::Friday,
+        PolicyDay::Sat => Weekday::Saturday,
+        PolicyDay::Sun => Weekday::Sunday,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Friday 2026-10-16, `hour`:00 UTC, plus `days`.
+    fn friday(days: i64, hour: i64) -> OffsetDateTime {
+        OffsetDateTime::from_unix_timestamp(1_792_108_800 + days * 86_400 + hour * 3600).unwrap()
+    }
+
+    fn rule(effect: PolicyEffect) -> PolicyRuleConfig {
+        PolicyRuleConfig {
+            name: format!("{effect:?}"),
+            effect,
+            networks: Vec::new(),
+            hours: None,
+            days: Vec::new(),
+            apps: Vec::new(),
+            methods: Vec::new(),
+            aaguids: Vec::new(),
+        }
+    }
+
+    fn request(ip: &str, app: &'static str, method: LoginMethod) -> Request<'static> {
+        Request {
+            ip: ip.parse().unwrap(),
+            app,
+            method,
+            aaguid: None,
+        }
+    }
+
+    #[test]
+    fn first_matching_rule_decides() {
+        // The wiki only from the office, by passkey, on weekday office hours.
+        let policies = Policies::load(&[
+            PolicyRuleConfig {
+                networks: vec!["10.0.0.0/8".into()],
+                hours: Some("08:00-18:00".into()),
+                days: vec![PolicyDay::Mon, PolicyDay::Fri],
+                apps: vec!["HTTPS://wiki.example:443".into()],
+                methods: vec![LoginMethod::Passkey, LoginMethod::Redirect],
+                ..rule(PolicyEffect::Allow)
+            },
+            PolicyRuleConfig {
+                apps: vec!["https://wiki.example".into()],
+                ..rule(PolicyEffect::Deny)
+            },
+        ])
+        .unwrap();
+        let friday_noon = friday(0, 12);
+        let wiki = request("10.1.2.3", "https://wiki.example", LoginMethod::Passkey);
+        assert_eq!(policies.denied_by(&wiki, friday_noon), None);
+        let spelled_out = request("10.1.2.3", "HTTPS://WIKI.example:443", LoginMethod::Passkey);
+        assert_eq!(
+            policies.denied_by(&spelled_out, friday(0, 18)),
+            Some("Deny")
+        );
+        assert_eq!(policies.denied_by(&wiki, friday(0, 18)), Some("Deny"));
+        assert_eq!(policies.denied_by(&wiki, friday(1, 12)), Some("Deny"));
+        let from_home = Request {
+            ip: "192.0.2.1".parse().unwrap(),
+            ..wiki
+        };
+        assert_eq!(policies.denied_by(&from_home, friday_noon), Some("Deny"));
+        let by_ldap = Request {
+            method: LoginMethod::Ldap,
+            ..wiki
+        };
+        assert_eq!(policies.denied_by(&by_ldap, friday_noon), Some("Deny"));
+        // Other apps match no rule.
+        let den = request("192.0.2.1", "https://den.example", LoginMethod::Ldap);
+        assert_eq!(policies.denied_by(&den, friday_noon), None);
+    }
+
+    #[test]
+    fn sub_accounts_reach_only_their_apps() {
+        let apps = vec!["https://wiki.example".to_owned()];
+        assert!(app_allowed(Some(&apps), "https://WIKI.example:443"));
+        assert!(!app_allowed(Some(&apps), "https://den.example"));
+        assert!(!app_allowed(Some(&apps), "not an origin"));
+        assert!(!app_allowed(Some(&[]), "https://wiki.example"));
+        assert!(app_allowed(None, "https://den.example"));
+    }
+
+    #[test]
+    fn authenticator_rules_need_a_named_model() {
+        let yubikey = Uuid::parse_str("cb69481e-8ff7-4039-93ec-0a2729a154a8").unwrap();
+        let policies = Policies::load(&[
+            PolicyRuleConfig {
+                aaguids: vec![yubikey.to_string().to_uppercase()],
+                ..rule(PolicyEffect::Allow)
+            },
+            rule(PolicyEffect::Deny),
+        ])
+        .unwrap();
+        let now = friday(0, 12);
+        let signed = Request {
+            aaguid: Some(yubikey),
+            ..request("192.0.2.1", "https://den.example", LoginMethod::Passkey)
+        };
+        assert_eq!(policies.denied_by(&signed, now), None);
+        let unnamed = Request {
+            aaguid: None,
+            ..signed
+        };
+        assert_eq!(policies.denied_by(&unnamed, now), Some("Deny"));
+        let not_an_aaguid = PolicyRuleConfig {
+            aaguids: vec!["yubikey".into()],
+            ..rule(PolicyEffect::Allow)
+        };
+        assert!(Policies::load(&[not_an_aaguid]).is_err());
+    }
+
+    #[test]
+    fn hours_may_wrap_past_midnight() {
+        assert_eq!(parse_hours("08:00-18:00"), Some((480, 1080)));
+        assert_eq!(parse_hours("00:00 - 24:00"), Some((0, 1440)));
+        for invalid in [
+            "8:00-18:00",
+            "08:00",
+            "08:60-09:00",
+            "24:00-01:00",
+            "09:00-09:00",
+        ] {
+            assert_eq!(parse_hours(invalid), None, "{invalid}");
+        }
+        let night = parse_hours("22:00-06:00").unwrap();
+        assert!(within(23 * 60, night));
+        assert!(within(5 * 60 + 59, night));
+        assert!(!within(6 * 60, night));
+        assert!(!within(12 * 60, night));
+    }
+}
@@ -0,0 +1,185 @@
+use std::net::IpAddr;
+
+use jsonwebtoken::Validation;
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+use time::{Duration, OffsetDateTime};
+use uuid::Uuid;
+
+use crate::access::{IpNet, parse_nets};
+use crate::config::{ConfigError, LoginPuzzleConfig};
+use crate::signing_key::SigningKeys;
+
+/// Time to solve a puzzle and start the login with it.
+const PUZZLE_TTL: Duration = Duration::minutes(2);
+/// `aud` of puzzle tokens, so no other token signed with the JWT secret passes.
+const AUDIENCE: &str = "den:login-puzzle";
+/// `auth::token_key` purpose puzzle tokens are signed with.
+const KEY_PURPOSE: &str = "login-puzzle";
+/// Nonces are decimal counters; anything longer is not a solver's.
+const MAX_NONCE_LEN: usize = 20;
+
+#[derive(Debug)]
+pub struct LoginPuzzle {
+    pub difficulty: u8,
+    exempt: Vec<IpNet>,
+}
+
+/// What `GET /api/login/puzzle` hands out: find a `nonce` for which
+/// SHA-256(`token` ":" `nonce`) starts with `difficulty` zero bits.
+#[derive(Debug, Serialize)]
+pub struct Puzzle {
+    pub token: String,
+    pub difficulty: u8,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct PuzzleClaims {
+    pub aud: String,
+    /// The client IP the puzzle was issued to.
+    pub sub: String,
+    pub jti: String,
+    pub exp: i64,
+    pub difficulty: u8,
+}
+
+impl LoginPuzzle {
+    pub fn load(config: &LoginPuzzleConfig) -> Result<Self, ConfigError> {
+        Ok(Self {
+            difficulty: config.difficulty,
+            exempt: parse_nets(&config.exempt, "login_puzzle.exempt")?,
+        })
+    }
+
+    pub fn required(&self, ip: IpAddr) -> bool {
+        !self.exempt.iter().any(|net| net.contains(ip))
+    }
+
+    pub fn issue(
+        &self,
+        keys: &SigningKeys,
+        ip: &str,
+    ) -> Result<Puzzle, jsonwebtoken::errors::Error> {
+        let claims = PuzzleClaims {
+            aud: AUDIENCE.into(),
+            sub: ip.into(),
+            jti: Uuid::new_v4().to_string(),
+            exp: (OffsetDateTime::now_utc() + PUZZLE_TTL).unix_timestamp(),
+            difficulty: self.difficulty,
+        };
+        let token = keys.encode(Some(KEY_PURPOSE), &claims)?;
+        Ok(Puzzle {
+            token,
+            difficulty: self.difficulty,
+        })
+    }
+
+    /// The claims of `token` when it was issued to `ip`, is unexpired, is at least
+    /// as hard as the current difficulty and `nonce` solves it. The caller burns
+    /// `jti` so each solution starts one login.
+    pub fn check(
+        &self,
+        keys: &SigningKeys,
+        ip: &str,
+        token: &str,
+        nonce: &str,
+    ) -> Option<PuzzleClaims> {
+        let mut validation = Validation::default();
+        validation.leeway = 0;
+        validation.set_audience(&[AUDIENCE]);
+        validation.set_required_spec_claims(&["exp", "aud", "sub"]);
+        let claims = keys
+            .decode::<PuzzleClaims>(Some(KEY_PURPOSE), token, &validation)
+            .ok()?
+            .claims;
+        (claims.sub == ip
+            && claims.difficulty >= self.difficulty
+            && solves(token, nonce, claims.difficulty))
+        .then_some(claims)
+    }
+}
+
+pub fn solves(token: &str, nonce: &str, difficulty: u8) -> bool {
+    if nonce.is_empty() || nonce.len() > MAX_NONCE_LEN {
+        return false;
+    }
+    let digest = Sha256::digest(format!("{token}:{nonce}"));
+    leading_zero_bits(&digest) >= u32::from(difficulty)
+}
+
+fn leading_zero_bits(bytes: &[u8]) -> u32 {
+    let mut bits = 0;
+    for byte in bytes {
+        bits += byte.leading_zeros();
+        if *byte != 0 {
+            break;
+        }
+    }
+    bits
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::signing_key::SigningKey;
+
+    fn keys(secret: &[u8]) -> SigningKeys {
+        SigningKeys::new(vec![SigningKey {
+            kid: "k".into(),
+            secret: secret.to_vec(),
+            created: 0,
+        }])
+    }
+
+    fn solve(token: &str, difficulty: u8) -> String {
+        (0u64..)
+            .map(|n| n.to_string())
+            .find(|nonce| solves(token, nonce, difficulty))
+            .unwrap()
+    }
+
+    #[test]
+    fn counts_leading_zero_bits() {
+        assert_eq!(leading_zero_bits(&[0x00, 0x1f, 0xff]), 11);
+        assert_eq!(leading_zero_bits(&[0x80]), 0);
+        assert_eq!(leading_zero_bits(&[0, 0]), 16);
+    }
+
+    #[test]
+    fn solutions_are_bound_to_the_client_and_secret() {
+        let puzzle = LoginPuzzle::load(&LoginPuzzleConfig {
+            difficulty: 8,
+            exempt: vec!["10.0.0.0/8".into()],
+        })
+        .unwrap();
+        assert!(!puzzle.required("10.1.2.3".parse().unwrap()));
+        assert!(puzzle.required("192.0.2.1".parse().unwrap()));
+
+        let secret = keys(b"s3cret");
+        let issued = puzzle.issue(&secret, "192.0.2.1").unwrap();
+        let nonce = solve(&issued.token, issued.difficulty);
+        let claims = puzzle.check(&secret, "192.0.2.1", &issued.token, &nonce);
+        assert_eq!(claims.unwrap().difficulty, 8);
+        assert!(
+            puzzle
+                .check(&secret, "192.0.2.2", &issued.token, &nonce)
+                .is_none()
+        );
+        assert!(
+            puzzle
+                .check(&keys(b"other"), "192.0.2.1", &issued.token, &nonce)
+                .is_none()
+        );
+
+        let harder = LoginPuzzle::load(&LoginPuzzleConfig {
+            difficulty: 12,
+            exempt: Vec::new(),
+        })
+        .unwrap();
+        assert!(
+            harder
+                .check(&secret, "192.0.2.1", &issued.token, &nonce)
+                .is_none()
+        );
+    }
+}
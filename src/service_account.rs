@@ -0,0 +1,112 @@
+use axum::http::{HeaderMap, StatusCode, header};
+
+use crate::db::Db;
+use crate::session;
+
+/// Bearer token prefix, so service tokens are recognisable in logs and secret scanners.
+pub const TOKEN_PREFIX: &str = "den_sa_";
+
+/// Everything a service account can be granted.
+pub const SCOPES: &[&str] = &[METRICS_READ, FORWARD_AUTH_VERIFY, LOG_LEVEL_WRITE];
+pub const METRICS_READ: &str = "metrics:read";
+pub const FORWARD_AUTH_VERIFY: &str = "forward-auth:verify";
+pub const LOG_LEVEL_WRITE: &str = "log-level:write";
+
+/// A non-human principal authenticated by `Authorization: Bearer den_sa_...`.
+pub struct ServiceAccount {
+    pub id: String,
+    pub name: String,
+    scopes: Vec<String>,
+}
+
+impl ServiceAccount {
+    pub fn has_scope(&self, scope: &str) -> bool {
+        self.scopes.iter().any(|s| s == scope)
+    }
+}
+
+pub fn new_token() -> String {
+    format!("{TOKEN_PREFIX}{}", session::new_refresh_token())
+}
+
+/// Parse and validate requested scopes; `None` when any is unknown or none given.
+pub fn normalize_scopes(requested: &[String]) -> Option<String> {
+    let mut scopes: Vec<&str> = Vec::new();
+    for scope in requested {
+        let known = SCOPES.iter().find(|s| **s == scope.trim())?;
+        if !scopes.contains(known) {
+            scopes.push(known);
+        }
+    }
+    (!scopes.is_empty()).then(|| scopes.join(" "))
+}
+
+/// Service token from the `Authorization` header, if one was sent.
+fn bearer_token(headers: &HeaderMap) -> Option<&str> {
+    headers
+        .get(header::AUTHORIZATION)?
+        .to_str()
+        .ok()?
+        .strip_prefix("Bearer ")
+        .map(str::trim)
+        .filter(|t| t.starts_with(TOKEN_PREFIX))
+}
+
+/// Resolve an unexpired account from the request's bearer token.
+pub async fn authenticate(
+    db: &Db,
+    headers: &HeaderMap,
+) -> Result<Option<ServiceAccount>, sqlx::Error> {
+    let Some(token) = bearer_token(headers) else {
+        return Ok(None);
+    };
+    let row = db.use_service_account(&session::hash_token(token)).await?;
+    Ok(row.map(|(id, name, scopes)| ServiceAccount {
+        id,
+        name,
+        scopes: scopes.split_whitespace().map(str::to_owned).collect(),
+    }))
+}
+
+/// Require a service account holding `scope`: 401 without a valid token, 403 without the scope.
+pub async fn require(
+    db: &Db,
+    headers: &HeaderMap,
+    scope: &str,
+) -> Result<ServiceAccount, StatusCode> {
+    let account = authenticate(db, headers)
+        .await
+        .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?
+        .ok_or(StatusCode::UNAUTHORIZED)?;
+    if !account.has_scope(scope) {
+        tracing::warn!(account = account.name, scope, "service account lacks scope");
+        return Err(StatusCode::FORBIDDEN);
+    }
+    Ok(account)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn normalize_scopes_rejects_unknown_and_dedupes() {
+        let scopes =
+            |v: &[&str]| normalize_scopes(&v.iter().map(|s| s.to_string()).collect::<Vec<_>>());
+        assert_eq!(
+            scopes(&["metrics:read", "forward-auth:verify", "metrics:read"]).as_deref(),
+            Some("metrics:read forward-auth:verify")
+        );
+        assert_eq!(scopes(&["admin"]), None);
+        assert_eq!(scopes(&[]), None);
+    }
+
+    #[test]
+    fn bearer_token_needs_service_prefix() {
+        let mut headers = HeaderMap::new();
+        headers.insert(header::AUTHORIZATION, "Bearer den_sa_abc".parse().unwrap());
+        assert_eq!(bearer_token(&headers), Some("den_sa_abc"));
+        headers.insert(header::AUTHORIZATION, "Bearer eyJhbGciOi".parse().unwrap());
+        assert_eq!(bearer_token(&headers), None);
+    }
+}
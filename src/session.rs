@@ -0,0 +1,552 @@
+use rand::Rng;
+use sha2::{Digest, Sha256};
+use time::{Duration, OffsetDateTime};
+use uuid::Uuid;
+
+use crate::db::{Db, SessionRecord};
+use crate::redis::{Redis, Reply};
+use crate::storage::{REDIS_PREFIX, Storage, StorageError};
+
+/// Reuse of a just-rotated token inside this window is treated as a benign race
+/// (parallel tabs refreshing) rather than theft.
+const REUSE_GRACE_SECONDS: i64 = 30;
+
+pub struct NewSession {
+    pub id: String,
+    pub refresh_token: String,
+}
+
+pub struct RotatedSession {
+    pub id: String,
+    pub user_id: String,
+    pub refresh_token: String,
+    pub ttl: Duration,
+}
+
+/// Where a session was opened from, shown in the sessions list.
+pub struct ClientInfo<'a> {
+    pub user_agent: Option<&'a str>,
+    pub ip: Option<&'a str>,
+    pub country: Option<&'a str>,
+    /// Origin the session was opened on, when not `rp_origin`; sets the token audience.
+    pub origin: Option<&'a str>,
+}
+
+enum Rotation {
+    Rotated {
+        id: String,
+        user_id: String,
+        ttl_seconds: i64,
+    },
+    /// An old token came back outside the grace window; the session is now revoked.
+    Reused(String),
+    Unknown,
+}
+
+pub fn new_refresh_token() -> String {
+    let mut bytes = [0u8; 32];
+    rand::rng().fill_bytes(&mut bytes);
+    bytes.iter().map(|b| format!("{b:02x}")).collect()
+}
+
+pub fn hash_token(token: &str) -> String {
+    format!("{:x}", Sha256::digest(token.as_bytes()))
+}
+
+pub async fn create(
+    storage: &Storage,
+    user_id: &str,
+    ttl: Duration,
+    client: &ClientInfo<'_>,
+) -> Result<NewSession, StorageError> {
+    let id = Uuid::new_v4().to_string();
+    let refresh_token = new_refresh_token();
+    let token_hash = hash_token(&refresh_token);
+    match storage {
+        Storage::Sqlite(db) => {
+            db.create_session(
+                &id,
+                user_id,
+                &token_hash,
+                ttl.whole_seconds(),
+                client.user_agent,
+                client.ip,
+                client.country,
+                client.origin,
+            )
+            .await?;
+        }
+        Storage::Redis(redis) => {
+            let now = OffsetDateTime::now_utc();
+            let args = [
+                REDIS_PREFIX,
+                &id,
+                user_id,
+                &token_hash,
+                &ttl.whole_seconds().to_string(),
+                client.user_agent.unwrap_or_default(),
+                client.ip.unwrap_or_default(),
+                client.country.unwrap_or_default(),
+                &sqlite_datetime(now),
+                &now.unix_timestamp().to_string(),
+                client.origin.unwrap_or_default(),
+            ];
+            redis.eval(CREATE_SCRIPT, &args).await?;
+        }
+    }
+    Ok(NewSession { id, refresh_token })
+}
+
+/// Swap a live refresh token for a fresh one. Presenting an already-rotated token
+/// outside the grace window revokes the whole session.
+pub async fn rotate(
+    storage: &Storage,
+    token: &str,
+) -> Result<Option<RotatedSession>, StorageError> {
+    let presented = hash_token(token);
+    let refresh_token = new_refresh_token();
+    let fresh = hash_token(&refresh_token);
+    let rotation = match storage {
+        Storage::Sqlite(db) => rotate_sqlite(db, &presented, &fresh).await?,
+        Storage::Redis(redis) => rotate_redis(redis, &presented, &fresh).await?,
+    };
+    match rotation {
+        Rotation::Rotated {
+            id,
+            user_id,
+            ttl_seconds,
+        } => Ok(Some(RotatedSession {
+            id,
+            user_id,
+            refresh_token,
+            ttl: Duration::seconds(ttl_seconds),
+        })),
+        Rotation::Reused(id) => {
+            tracing::warn!(
+                session_id = id,
+                "refresh token reuse detected, session revoked"
+            );
+            Ok(None)
+        }
+        Rotation::Unknown => Ok(None),
+    }
+}
+
+/// Owner of a live (unrevoked, unexpired) session.
+pub async fn live_user(
+    storage: &Storage,
+    session_id: &str,
+) -> Result<Option<String>, StorageError> {
+    match storage {
+        Storage::Sqlite(db) => Ok(db.live_session_user(session_id).await?),
+        Storage::Redis(redis) => Ok(redis
+            .query(&["HGET", &session_key(session_id), "user_id"])
+            .await?
+            .into_string()),
+    }
+}
+
+/// Revoke the session behind a refresh token, returning its `(id, user_id)` if it
+/// was still live.
+pub async fn revoke_by_token(
+    storage: &Storage,
+    token: &str,
+) -> Result<Option<(String, String)>, StorageError> {
+    match storage {
+        Storage::Sqlite(db) => Ok(db.revoke_session_by_token(&hash_token(token)).await?),
+        Storage::Redis(redis) => {
+            let key = format!("{REDIS_PREFIX}refresh:{}", hash_token(token));
+            let Some(id) = redis.query(&["GET", &key]).await?.into_string() else {
+                return Ok(None);
+            };
+            let user_id = revoke_redis(redis, &id, None).await?;
+            Ok(user_id.map(|user_id| (id, user_id)))
+        }
+    }
+}
+
+/// End every live session of `user_id`, returning how many there were.
+pub async fn revoke_all(storage: &Storage, user_id: &str) -> Result<u64, StorageError> {
+    match storage {
+        Storage::Sqlite(db) => Ok(db.revoke_user_sessions(user_id).await?),
+        Storage::Redis(redis) => {
+            let mut revoked = 0;
+            for id in user_session_ids(redis, user_id).await? {
+                if revoke_redis(redis, &id, Some(user_id)).await?.is_some() {
+                    revoked += 1;
+                }
+            }
+            Ok(revoked)
+        }
+    }
+}
+
+/// End `user_id`'s sessions opened on `origin`, returning their ids.
+pub async fn revoke_origin(
+    storage: &Storage,
+    user_id: &str,
+    origin: &str,
+) -> Result<Vec<String>, StorageError> {
+    let redis = match storage {
+        Storage::Sqlite(db) => {
+            return Ok(db.revoke_origin_sessions(user_id, origin).await?);
+        }
+        Storage::Redis(redis) => redis,
+    };
+    let mut revoked = Vec::new();
+    for id in user_session_ids(redis, user_id).await? {
+        let opened_on = redis
+            .query(&["HGET", &session_key(&id), "origin"])
+            .await?
+            .into_string();
+        if opened_on.as_deref() == Some(origin)
+            && revoke_redis(redis, &id, Some(user_id)).await?.is_some()
+        {
+            revoked.push(id);
+        }
+    }
+    Ok(revoked)
+}
+
+/// Live sessions of `user_id`, most recently used first.
+pub async fn list(storage: &Storage, user_id: &str) -> Result<Vec<SessionRecord>, StorageError> {
+    let redis = match storage {
+        Storage::Sqlite(db) => return Ok(db.list_sessions(user_id).await?),
+        Storage::Redis(redis) => redis,
+    };
+    let mut sessions = Vec::new();
+    for id in user_session_ids(redis, user_id).await? {
+        let fields = redis
+            .query(&[
+                "HMGET",
+                &session_key(&id),
+                "user_id",
+                "name",
+                "user_agent",
+                "ip",
+                "country",
+                "created",
+                "last_used",
+            ])
+            .await?
+            .into_array();
+        let mut fields = fields
+            .into_iter()
+            .map(|field| field.into_string().filter(|value| !value.is_empty()));
+        let mut next = || fields.next().flatten();
+        if next().as_deref() != Some(user_id) {
+            // Expired: the set is only pruned lazily.
+            redis
+                .query(&["SREM", &user_sessions_key(user_id), &id])
+                .await?;
+            continue;
+        }
+        sessions.push(SessionRecord {
+            id,
+            name: next(),
+            user_agent: next(),
+            ip: next(),
+            country: next(),
+            created: next().unwrap_or_default(),
+            last_used: next().unwrap_or_default(),
+        });
+    }
+    sessions.sort_by(|a, b| b.last_used.cmp(&a.last_used));
+    Ok(sessions)
+}
+
+/// False if `id` isn't a live session of `user_id`.
+pub async fn rename(
+    storage: &Storage,
+    user_id: &str,
+    id: &str,
+    name: Option<&str>,
+) -> Result<bool, StorageError> {
+    match storage {
+        Storage::Sqlite(db) => Ok(db.rename_session(user_id, id, name).await?),
+        Storage::Redis(redis) => {
+            let args = [REDIS_PREFIX, id, user_id, name.unwrap_or_default()];
+            Ok(redis.eval(RENAME_SCRIPT, &args).await? == Reply::Int(1))
+        }
+    }
+}
+
+/// False if `id` isn't a live session of `user_id`.
+pub async fn revoke(storage: &Storage, user_id: &str, id: &str) -> Result<bool, StorageError> {
+    match storage {
+        Storage::Sqlite(db) => Ok(db.revoke_session(user_id, id).await?),
+        Storage::Redis(redis) => Ok(revoke_redis(redis, id, Some(user_id)).await?.is_some()),
+    }
+}
+
+/// Live sessions across all users, for the admin overview.
+pub async fn active_count(storage: &Storage) -> Result<i64, StorageError> {
+    let redis = match storage {
+        Storage::Sqlite(db) => return Ok(db.active_session_count().await?),
+        Storage::Redis(redis) => redis,
+    };
+    let pattern = format!("{REDIS_PREFIX}session:*");
+    let mut cursor = "0".to_owned();
+    let mut count = 0;
+    loop {
+        let mut reply = redis
+            .query(&["SCAN", &cursor, "MATCH", &pattern, "COUNT", "1000"])
+            .await?
+            .into_array()
+            .into_iter();
+        cursor = reply
+            .next()
+            .and_then(Reply::into_string)
+            .unwrap_or_else(|| "0".into());
+        count += reply.next().map_or(0, |keys| keys.into_array().len());
+        if cursor == "0" {
+            return Ok(count as i64);
+        }
+    }
+}
+
+/// Sessions opened or refreshed since midnight UTC.
+pub async fn used_today(storage: &Storage) -> Result<i64, StorageError> {
+    let redis = match storage {
+        Storage::Sqlite(db) => return Ok(db.sessions_used_today().await?),
+        Storage::Redis(redis) => redis,
+    };
+    let midnight = OffsetDateTime::now_utc()
+        .replace_time(time::Time::MIDNIGHT)
+        .unix_timestamp();
+    let pattern = format!("{REDIS_PREFIX}session:*");
+    let mut cursor = "0".to_owned();
+    let mut count = 0;
+    loop {
+        let mut reply = redis
+            .query(&["SCAN", &cursor, "MATCH", &pattern, "COUNT", "1000"])
+            .await?
+            .into_array()
+            .into_iter();
+        cursor = reply
+            .next()
+            .and_then(Reply::into_string)
+            .unwrap_or_else(|| "0".into());
+        for key in reply.next().map(Reply::into_array).unwrap_or_default() {
+            let Some(key) = key.into_string() else {
+                continue;
+            };
+            let last_used = redis
+                .query(&["HGET", &key, "last_used_unix"])
+                .await?
+                .into_string()
+                .and_then(|unix| unix.parse::<i64>().ok());
+            if last_used.is_some_and(|unix| unix >= midnight) {
+                count += 1;
+            }
+        }
+        if cursor == "0" {
+            return Ok(count);
+        }
+    }
+}
+
+/// Drop SQLite rows of sessions that ended over a day ago; Redis expires its own.
+pub async fn purge_ended(storage: &Storage) -> Result<(), StorageError> {
+    let Storage::Sqlite(db) = storage else {
+        return Ok(());
+    };
+    Ok(db.purge_ended_sessions().await?)
+}
+
+async fn rotate_sqlite(db: &Db, presented: &str, fresh: &str) -> Result<Rotation, sqlx::Error> {
+    if let Some((id, user_id, ttl_seconds)) = db.rotate_session(presented, fresh).await? {
+        return Ok(Rotation::Rotated {
+            id,
+            user_id,
+            ttl_seconds,
+        });
+    }
+    let revoked = db
+        .revoke_reused_session(presented, REUSE_GRACE_SECONDS)
+        .await?;
+    Ok(revoked.map_or(Rotation::Unknown, Rotation::Reused))
+}
+
+// --- Redis backend ---
+//
+// `session:{id}` is a hash expiring with the session; empty fields mean NULL.
+// `refresh:{hash}` points at the session holding that token and `refresh_prev:{hash}`
+// at the one that rotated it away, for reuse detection. `user_sessions:{user}` is
+// a set of ids, pruned lazily. Scripts take the key prefix as ARGV[1] and build
+// their own keys, so they assume a single (non-cluster) Redis.
+
+const CREATE_SCRIPT: &str = r"
+local prefix, id, user, hash, ttl = ARGV[1], ARGV[2], ARGV[3], ARGV[4], ARGV[5]
+local key = prefix .. 'session:' .. id
+redis.call('HSET', key, 'user_id', user, 'token_hash', hash, 'ttl_seconds', ttl, 'name', '',
+  'user_agent', ARGV[6], 'ip', ARGV[7], 'country', ARGV[8],
+  'created', ARGV[9], 'last_used', ARGV[9], 'last_used_unix', ARGV[10], 'origin', ARGV[11])
+redis.call('EXPIRE', key, ttl)
+redis.call('SET', prefix .. 'refresh:' .. hash, id, 'EX', ttl)
+local sessions = prefix .. 'user_sessions:' .. user
+redis.call('SADD', sessions, id)
+if redis.call('TTL', sessions) < tonumber(ttl) then
+  redis.call('EXPIRE', sessions, ttl)
+end
+return 1
+";
+
+const ROTATE_SCRIPT: &str = r"
+local prefix, presented, fresh, now = ARGV[1], ARGV[2], ARGV[3], tonumber(ARGV[5])
+local id = redis.call('GET', prefix .. 'refresh:' .. presented)
+if id then
+  redis.call('DEL', prefix .. 'refresh:' .. presented)
+  local key = prefix .. 'session:' .. id
+  local fields = redis.call('HMGET', key, 'user_id', 'ttl_seconds')
+  if not fields[1] then
+    return false
+  end
+  local ttl = fields[2]
+  redis.call('HSET', key, 'token_hash', fresh, 'last_used', ARGV[4], 'last_used_unix', ARGV[5])
+  redis.call('EXPIRE', key, ttl)
+  redis.call('SET', prefix .. 'refresh:' .. fresh, id, 'EX', ttl)
+  redis.call('SET', prefix .. 'refresh_prev:' .. presented, id, 'EX', ttl)
+  return {'rotated', id, fields[1], ttl}
+end
+id = redis.call('GET', prefix .. 'refresh_prev:' .. presented)
+if not id then
+  return false
+end
+local key = prefix .. 'session:' .. id
+local fields = redis.call('HMGET', key, 'user_id', 'token_hash', 'last_used_unix')
+if not fields[1] or tonumber(fields[3]) >= now - tonumber(ARGV[6]) then
+  return false
+end
+redis.call('DEL', key, prefix .. 'refresh:' .. fields[2])
+redis.call('SREM', prefix .. 'user_sessions:' .. fields[1], id)
+return {'revoked', id}
+";
+
+/// ARGV[3] is the expected owner, or empty for any.
+const REVOKE_SCRIPT: &str = r"
+local key = ARGV[1] .. 'session:' .. ARGV[2]
+local fields = redis.call('HMGET', key, 'user_id', 'token_hash')
+if not fields[1] or (ARGV[3] ~= '' and fields[1] ~= ARGV[3]) then
+  return false
+end
+redis.call('DEL', key, ARGV[1] .. 'refresh:' .. fields[2])
+redis.call('SREM', ARGV[1] .. 'user_sessions:' .. fields[1], ARGV[2])
+return fields[1]
+";
+
+const RENAME_SCRIPT: &str = r"
+local key = ARGV[1] .. 'session:' .. ARGV[2]
+if redis.call('HGET', key, 'user_id') ~= ARGV[3] then
+  return 0
+end
+redis.call('HSET', key, 'name', ARGV[4])
+return 1
+";
+
+async fn rotate_redis(redis: &Redis, presented: &str, fresh: &str) -> std::io::Result<Rotation> {
+    let now = OffsetDateTime::now_utc();
+    let args = [
+        REDIS_PREFIX,
+        presented,
+        fresh,
+        &sqlite_datetime(now),
+        &now.unix_timestamp().to_string(),
+        &REUSE_GRACE_SECONDS.to_string(),
+    ];
+    let mut reply = redis
+        .eval(ROTATE_SCRIPT, &args)
+        .await?
+        .into_array()
+        .into_iter()
+        .filter_map(Reply::into_string);
+    let rotation = match reply.next().as_deref() {
+        Some("rotated") => {
+            let (Some(id), Some(user_id), Some(ttl_seconds)) =
+                (reply.next(), reply.next(), reply.next())
+            else {
+                return Err(std::io::Error::other("malformed rotate reply"));
+            };
+            Rotation::Rotated {
+                id,
+                user_id,
+                ttl_seconds: ttl_seconds
+                    .parse()
+                    .map_err(|_| std::io::Error::other("malformed session ttl"))?,
+            }
+        }
+        Some("revoked") => Rotation::Reused(reply.next().unwrap_or_default()),
+        _ => Rotation::Unknown,
+    };
+    Ok(rotation)
+}
+
+/// Returns the owner if `id` was live (and owned by `user_id`, when given).
+async fn revoke_redis(
+    redis: &Redis,
+    id: &str,
+    user_id: Option<&str>,
+) -> std::io::Result<Option<String>> {
+    let args = [REDIS_PREFIX, id, user_id.unwrap_or_default()];
+    Ok(redis.eval(REVOKE_SCRIPT, &args).await?.into_string())
+}
+
+async fn user_session_ids(redis: &Redis, user_id: &str) -> std::io::Result<Vec<String>> {
+    let reply = redis
+        .query(&["SMEMBERS", &user_sessions_key(user_id)])
+        .await?;
+    Ok(reply
+        .into_array()
+        .into_iter()
+        .filter_map(Reply::into_string)
+        .collect())
+}
+
+fn session_key(id: &str) -> String {
+    format!("{REDIS_PREFIX}session:{id}")
+}
+
+fn user_sessions_key(user_id: &str) -> String {
+    format!("{REDIS_PREFIX}user_sessions:{user_id}")
+}
+
+/// The `YYYY-MM-DD HH:MM:SS` form SQLite's `datetime('now')` produces, so both
+/// backends list sessions alike.
+fn sqlite_datetime(t: OffsetDateTime) -> String {
+    format!(
+        "{:04}-{:02}-{:02} {:02}:{:02}:{:02}",
+        t.year(),
+        u8::from(t.month()),
+        t.day(),
+        t.hour(),
+        t.minute(),
+        t.second()
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn refresh_tokens_are_random_hex() {
+        let a = new_refresh_token();
+        assert_eq!(a.len(), 64);
+        assert!(a.bytes().all(|b| b.is_ascii_hexdigit()));
+        assert_ne!(a, new_refresh_token());
+    }
+
+    #[test]
+    fn hash_token_is_sha256_hex() {
+        assert_eq!(
+            hash_token(""),
+            "e3b0c44298fc1c149afbf4c8996fb92427ae41e4649b934ca495991b7852b855"
+        );
+    }
+
+    #[test]
+    fn redis_timestamps_match_sqlite_datetime() {
+        let t = OffsetDateTime::from_unix_timestamp(1_700_000_005).unwrap();
+        assert_eq!(sqlite_datetime(t), "2023-11-14 22:13:25");
+    }
+}
@@ -0,0 +1,205 @@
+use std::sync::{Arc, RwLock};
+
+use jsonwebtoken::{
+    DecodingKey, EncodingKey, Header, TokenData, Validation, decode, decode_header, encode,
+    errors::{Error, ErrorKind},
+};
+use serde::Serialize;
+use serde::de::DeserializeOwned;
+use time::{Duration, OffsetDateTime};
+use uuid::Uuid;
+
+use crate::auth;
+
+/// The key every token minted before keys were versioned was signed with; tokens
+/// without a `kid` are checked against it.
+pub const LEGACY_KID: &str = "legacy";
+/// How long a key signs before the housekeeping leader adds the next one.
+pub const ROTATE_AFTER: Duration = Duration::days(30);
+/// The new key, the one it replaces and the one before that: an app-host access
+/// token is honoured while its session lives, so a key keeps verifying for a full
+/// rotation after it stops signing.
+pub const KEEP: usize = 3;
+/// Two housekeeping ticks, so every replica has loaded a key before any signs with it.
+pub const SIGN_AFTER: Duration = Duration::minutes(2);
+
+/// One version of the JWT secret. Session tokens are signed with `secret` itself;
+/// other kinds with `auth::token_key` derived from it.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct SigningKey {
+    pub kid: String,
+    pub secret: Vec<u8>,
+    /// Unix seconds.
+    pub created: i64,
+}
+
+impl SigningKey {
+    pub fn generate() -> Self {
+        use rand::Rng;
+        let mut secret = vec![0u8; 64];
+        rand::rng().fill_bytes(&mut secret);
+        Self {
+            kid: Uuid::new_v4().simple().to_string(),
+            secret,
+            created: OffsetDateTime::now_utc().unix_timestamp(),
+        }
+    }
+
+    fn secret_for(&self, purpose: Option<&str>) -> Vec<u8> {
+        purpose.map_or_else(
+            || self.secret.clone(),
+            |purpose| auth::token_key(&self.secret, purpose),
+        )
+    }
+}
+
+/// Newest first, then by `kid`, so replicas holding the same keys agree on the order.
+pub fn sort(keys: &mut [SigningKey]) {
+    keys.sort_by(|a, b| b.created.cmp(&a.created).then_with(|| b.kid.cmp(&a.kid)));
+}
+
+/// This replica's copy of the shared signing keys, newest first; housekeeping
+/// replaces it every minute. Any held key verifies, and the newest one at least
+/// [`SIGN_AFTER`] old signs (or the newest, while none is).
+pub struct SigningKeys {
+    keys: RwLock<Arc<[SigningKey]>>,
+}
+
+impl SigningKeys {
+    pub fn new(keys: Vec<SigningKey>) -> Self {
+        let held = Self {
+            keys: RwLock::new(Arc::from([])),
+        };
+        held.set(keys);
+        held
+    }
+
+    pub fn set(&self, mut keys: Vec<SigningKey>) {
+        sort(&mut keys);
+        *self.keys.write().unwrap() = Arc::from(keys);
+    }
+
+    pub fn all(&self) -> Arc<[SigningKey]> {
+        self.keys.read().unwrap().clone()
+    }
+
+    /// The key new tokens are signed with.
+    pub fn current(&self) -> SigningKey {
+        let keys = self.all();
+        let cutoff = (OffsetDateTime::now_utc() - SIGN_AFTER).unix_timestamp();
+        keys.iter()
+            .find(|key| key.created <= cutoff)
+            .or(keys.first())
+            .cloned()
+            .expect("at least one signing key is loaded")
+    }
+
+    /// Sign `claims` with the current key, or the key derived from it for `purpose`
+    /// (see `auth::token_key`), naming it in the header's `kid`.
+    pub fn encode<T: Serialize>(&self, purpose: Option<&str>, claims: &T) -> Result<String, Error> {
+        let key = self.current();
+        let header = Header {
+            kid: Some(key.kid.clone()),
+            ..Header::default()
+        };
+        encode(
+            &header,
+            claims,
+            &EncodingKey::from_secret(&key.secret_for(purpose)),
+        )
+    }
+
+    /// Verify `token` against the held key its `kid` names; an unknown `kid` fails.
+    pub fn decode<T: DeserializeOwned>(
+        &self,
+        purpose: Option<&str>,
+        token: &str,
+        validation: &Validation,
+    ) -> Result<TokenData<T>, Error> {
+        let header = decode_header(token)?;
+        let kid = header.kid.as_deref().unwrap_or(LEGACY_KID);
+        let keys = self.all();
+        let key = keys
+            .iter()
+            .find(|key| key.kid == kid)
+            .ok_or_else(|| Error::from(ErrorKind::InvalidSignature))?;
+        decode(
+            token,
+            &DecodingKey::from_secret(&key.secret_for(purpose)),
+            validation,
+        )
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn key(kid: &str, age: Duration) -> SigningKey {
+        SigningKey {
+            kid: kid.into(),
+            secret: kid.as_bytes().to_vec(),
+            created: (OffsetDateTime::now_utc() - age).unix_timestamp(),
+        }
+    }
+
+    fn claims() -> serde_json::Value {
+        let exp = (OffsetDateTime::now_utc() + Duration::minutes(1)).unix_timestamp();
+        serde_json::json!({ "sub": "u", "exp": exp })
+    }
+
+    #[test]
+    fn a_new_key_verifies_before_it_signs() {
+        let keys = SigningKeys::new(vec![
+            key("old", Duration::days(40)),
+            key("next", Duration::seconds(30)),
+            key("current", Duration::days(10)),
+        ]);
+        assert_eq!(keys.current().kid, "current");
+        let token = keys.encode(None, &claims()).unwrap();
+        assert_eq!(
+            decode_header(&token).unwrap().kid.as_deref(),
+            Some("current")
+        );
+
+        // Every held key verifies what it signed.
+        let next = SigningKeys::new(vec![key("next", Duration::minutes(3))]);
+        let token = next.encode(None, &claims()).unwrap();
+        let validation = Validation::default();
+        assert!(
+            keys.decode::<serde_json::Value>(None, &token, &validation)
+                .is_ok()
+        );
+        // Purposes never verify as each other.
+        assert!(
+            keys.decode::<serde_json::Value>(Some("x"), &token, &validation)
+                .is_err()
+        );
+
+        // A kid no longer held fails.
+        keys.set(vec![key("current", Duration::days(10))]);
+        assert!(
+            keys.decode::<serde_json::Value>(None, &token, &validation)
+                .is_err()
+        );
+    }
+
+    #[test]
+    fn tokens_without_a_kid_use_the_legacy_key() {
+        let keys = SigningKeys::new(vec![
+            key(LEGACY_KID, Duration::days(5)),
+            key("fresh", Duration::days(1)),
+        ]);
+        let token = encode(
+            &Header::default(),
+            &claims(),
+            &EncodingKey::from_secret(LEGACY_KID.as_bytes()),
+        )
+        .unwrap();
+        let validation = Validation::default();
+        assert!(
+            keys.decode::<serde_json::Value>(None, &token, &validation)
+                .is_ok()
+        );
+    }
+}
@@ -1,15 +1,114 @@
-use std::collections::HashSet;
+use std::collections::{BTreeMap, HashSet};
+use std::path::PathBuf;
 use std::sync::Arc;
+#[cfg(feature = "testing")]
+use std::sync::Mutex;
+use std::sync::atomic::{AtomicBool, AtomicU64};
+use std::time::Instant;
 
-use sqlx::SqlitePool;
+#[cfg(feature = "testing")]
+use webauthn_authenticator_rs::{WebauthnAuthenticator, softpasskey::SoftPasskey};
 use webauthn_rs::prelude::Webauthn;
 
+use crate::access::{AccessControl, IpNet};
+use crate::apps::AppPolicies;
+use crate::attestation::AttestationPolicy;
+use crate::audit_export::AuditExporter;
+use crate::auth::CookieSettings;
+use crate::branding::Branding;
+use crate::breach::BreachCheck;
+use crate::client_cert::ClientCertAuth;
+use crate::config::{
+    ErrorPagesConfig, ForwardAuthConfig, LoginAnomalyConfig, LoginThrottleConfig,
+    PasskeyPolicyConfig, RecoveryConfig, SecurityTxtConfig,
+};
+use crate::db::Db;
+use crate::geoip::GeoIp;
+use crate::honeypot::Honeypot;
+use crate::hooks::Hooks;
+use crate::i18n::Catalogs;
+use crate::ldap::LdapVerifier;
+use crate::metrics::Metrics;
+use crate::notify::Notifier;
+use crate::oidc::UpstreamOidc;
+use crate::origin::AllowedHosts;
+#[cfg(feature = "policy-plugin")]
+use crate::plugin::PolicyPlugin;
+use crate::policy::Policies;
+use crate::puzzle::LoginPuzzle;
+use crate::signing_key::SigningKeys;
+use crate::storage::Storage;
+
 #[derive(Clone)]
 pub struct AppState {
-    pub db: SqlitePool,
+    pub db: Db,
+    /// Challenges, sessions and used redirect tokens; SQLite (`db`) or Redis.
+    pub storage: Storage,
+    /// This replica's name in background-task leases and the health check.
+    pub instance_id: Arc<str>,
     pub webauthn: Arc<Webauthn>,
-    pub jwt_secret: Arc<Vec<u8>>,
+    /// Sign and verify every JWT; housekeeping reloads them each minute.
+    pub signing_keys: Arc<SigningKeys>,
     pub secure_cookies: bool,
+    pub rp_id: String,
     pub rp_origin: String,
-    pub allowed_hosts: Arc<HashSet<String>>,
+    pub allowed_hosts: Arc<AllowedHosts>,
+    /// Origins listed in `/.well-known/webauthn`; empty unless `related_origins`.
+    pub related_origins: Arc<Vec<String>>,
+    pub security_txt: Option<Arc<SecurityTxtConfig>>,
+    /// Enables `/api/recovery`.
+    pub recovery: Option<RecoveryConfig>,
+    pub trusted_proxies: Arc<Vec<IpNet>>,
+    pub access_control: Arc<AccessControl>,
+    pub geoip: Option<Arc<GeoIp>>,
+    pub asn: Option<Arc<GeoIp>>,
+    pub deny_login_countries: Arc<HashSet<String>>,
+    pub login_anomaly: LoginAnomalyConfig,
+    pub login_throttle: Option<LoginThrottleConfig>,
+    pub login_puzzle: Option<Arc<LoginPuzzle>>,
+    pub honeypot: Option<Arc<Honeypot>>,
+    /// `[[hooks]]`; empty when none are configured.
+    pub hooks: Arc<Hooks>,
+    /// `[[policies]]`; allows everything when none are configured.
+    pub policies: Arc<Policies>,
+    /// Asked about every passkey sign-in; `policy-plugin` builds only.
+    #[cfg(feature = "policy-plugin")]
+    pub policy_plugin: Option<Arc<PolicyPlugin>>,
+    pub passkeys: PasskeyPolicyConfig,
+    pub apps: Arc<AppPolicies>,
+    pub forward_auth: Arc<ForwardAuthConfig>,
+    pub cookie: Arc<CookieSettings>,
+    pub client_cert: Option<Arc<ClientCertAuth>>,
+    pub attestation: Option<Arc<AttestationPolicy>>,
+    pub upstream_oidc: Option<Arc<UpstreamOidc>>,
+    pub ldap: Option<Arc<LdapVerifier>>,
+    /// Rejects known-breached passphrases when set.
+    pub breach_check: Option<Arc<BreachCheck>>,
+    /// Copies audit events to syslog or a rotating file.
+    pub audit_export: Option<Arc<AuditExporter>>,
+    pub notifier: Arc<Notifier>,
+    /// Runtime-toggleable per replica (`POST /api/admin/maintenance`); starts from config.
+    pub maintenance: Arc<AtomicBool>,
+    pub error_pages: Arc<ErrorPagesConfig>,
+    /// Expired challenges this replica's housekeeping deleted since start.
+    pub expired_challenges_purged: Arc<AtomicU64>,
+    /// Per-route latency histograms and the SLO burn window, per replica.
+    pub metrics: Arc<Metrics>,
+    /// `AppConfig::diagnostics`, captured before the config is consumed.
+    pub diagnostics_config: Arc<serde_json::Value>,
+    pub branding: Arc<Branding>,
+    pub catalogs: Arc<Catalogs>,
+    /// Required by `register_begin` while no user exists.
+    pub bootstrap_token: Option<String>,
+    /// Enables `POST /api/dev-login`.
+    #[cfg(feature = "dev-auth")]
+    pub dev_login_password: Option<String>,
+    /// Enables `POST /api/login/break-glass`.
+    pub break_glass_token_file: Option<PathBuf>,
+    /// Config `log_presets`, for `PUT /api/admin/log-level`.
+    pub log_presets: Arc<BTreeMap<String, String>>,
+    /// Answers WebAuthn ceremonies for `/api/testing/authenticator/*`.
+    #[cfg(feature = "testing")]
+    pub soft_authenticator: Arc<Mutex<WebauthnAuthenticator<SoftPasskey>>>,
+    pub started: Instant,
 }
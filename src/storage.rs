@@ -0,0 +1,313 @@
+use std::sync::Arc;
+use std::{fmt, io};
+
+use base64::Engine;
+use base64::engine::general_purpose::STANDARD as BASE64;
+
+use crate::config::{ConfigError, StorageConfig, invalid};
+use crate::db::Db;
+use crate::redis::{Redis, Reply};
+use crate::signing_key::{self, SigningKey};
+
+/// Every key den writes to Redis starts with this.
+pub const REDIS_PREFIX: &str = "den:";
+
+/// How long past `exp` a burned `jti` is remembered. Single-use tokens are decoded
+/// with `leeway = 0`; this covers jsonwebtoken's default leeway and clock skew
+/// between replicas all the same.
+pub const BURN_LEEWAY_SECONDS: i64 = 60;
+
+/// Where short-lived auth state lives: login/registration challenges, sessions and
+/// burned redirect tokens. With Redis, several den replicas can share it behind a
+/// load balancer; users, passkeys and everything else stay in SQLite.
+#[derive(Clone)]
+pub enum Storage {
+    Sqlite(Db),
+    Redis(Arc<Redis>),
+}
+
+#[derive(Debug)]
+pub enum StorageError {
+    Sqlite(sqlx::Error),
+    Redis(io::Error),
+}
+
+impl fmt::Display for StorageError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Sqlite(error) => write!(f, "sqlite: {error}"),
+            Self::Redis(error) => write!(f, "redis: {error}"),
+        }
+    }
+}
+
+impl std::error::Error for StorageError {}
+
+impl From<sqlx::Error> for StorageError {
+    fn from(error: sqlx::Error) -> Self {
+        Self::Sqlite(error)
+    }
+}
+
+impl From<io::Error> for StorageError {
+    fn from(error: io::Error) -> Self {
+        Self::Redis(error)
+    }
+}
+
+impl Storage {
+    pub fn new(config: &StorageConfig, db: Db) -> Result<Self, ConfigError> {
+        Ok(match config {
+            StorageConfig::Sqlite => Self::Sqlite(db),
+            StorageConfig::Redis { redis_url } => Self::Redis(Arc::new(
+                Redis::new(redis_url).map_err(|e| invalid("storage.redis_url", e))?,
+            )),
+        })
+    }
+
+    /// SQLite is pinged by the health check already; this covers Redis.
+    pub async fn ping(&self) -> Result<(), StorageError> {
+        match self {
+            Self::Sqlite(_) => Ok(()),
+            Self::Redis(redis) => redis.query(&["PING"]).await.map(|_| ()).map_err(Into::into),
+        }
+    }
+
+    /// The signing keys every replica shares, newest first, creating the first one
+    /// when there is none. `held` are this replica's keys, empty at startup. SQLite
+    /// replicas share the table; with Redis, a set that lacks `held`'s newest key
+    /// was flushed (and maybe restarted by a replica that came up since), so `held`
+    /// is published again rather than left to diverge from the other replicas.
+    pub async fn signing_keys(&self, held: &[SigningKey]) -> Result<Vec<SigningKey>, StorageError> {
+        match self {
+            Self::Sqlite(db) => {
+                let keys = db.signing_keys().await?;
+                if !keys.is_empty() {
+                    return Ok(keys);
+                }
+                db.add_signing_key(&SigningKey::generate(), signing_key::KEEP as i64)
+                    .await?;
+                Ok(db.signing_keys().await?)
+            }
+            Self::Redis(redis) => {
+                let keys = redis_signing_keys(redis).await?;
+                let publish = match held.first() {
+                    Some(newest) if !keys.iter().any(|key| key.kid == newest.kid) => {
+                        tracing::warn!(
+                            kid = newest.kid,
+                            "signing keys missing from Redis; publishing them again"
+                        );
+                        held.to_vec()
+                    }
+                    Some(_) => return Ok(keys),
+                    None if !keys.is_empty() => return Ok(keys),
+                    None => vec![
+                        legacy_redis_signing_key(redis)
+                            .await?
+                            .unwrap_or_else(SigningKey::generate),
+                    ],
+                };
+                let hash = format!("{REDIS_PREFIX}signing_keys");
+                for key in &publish {
+                    redis
+                        .query(&["HSETNX", &hash, &key.kid, &redis_signing_key(key)])
+                        .await?;
+                }
+                redis_signing_keys(redis).await
+            }
+        }
+    }
+
+    /// Add `key` to the shared signing keys and drop all but the newest
+    /// [`signing_key::KEEP`]. Only the `housekeeping` leader rotates.
+    pub async fn add_signing_key(&self, key: &SigningKey) -> Result<(), StorageError> {
+        match self {
+            Self::Sqlite(db) => Ok(db.add_signing_key(key, signing_key::KEEP as i64).await?),
+            Self::Redis(redis) => {
+                let hash = format!("{REDIS_PREFIX}signing_keys");
+                redis
+                    .query(&["HSETNX", &hash, &key.kid, &redis_signing_key(key)])
+                    .await?;
+                let keys = redis_signing_keys(redis).await?;
+                for stale in keys.iter().skip(signing_key::KEEP) {
+                    redis.query(&["HDEL", &hash, &stale.kid]).await?;
+                }
+                Ok(())
+            }
+        }
+    }
+
+    /// Take or renew the `name` lease for `holder` for `ttl_seconds`; false while
+    /// another replica holds it.
+    pub async fn acquire_lease(
+        &self,
+        name: &str,
+        holder: &str,
+        ttl_seconds: i64,
+    ) -> Result<bool, StorageError> {
+        match self {
+            Self::Sqlite(db) => Ok(db.acquire_lease(name, holder, ttl_seconds).await?),
+            Self::Redis(redis) => {
+                let key = format!("{REDIS_PREFIX}lease:{name}");
+                let args = [key.as_str(), holder, &ttl_seconds.to_string()];
+                Ok(redis.eval(LEASE_SCRIPT, &args).await? == Reply::Int(1))
+            }
+        }
+    }
+
+    /// Redis expires challenge keys on its own.
+    pub async fn purge_expired_challenges(&self, limit: i64) -> Result<u64, StorageError> {
+        match self {
+            Self::Sqlite(db) => Ok(db.purge_expired_challenges(limit).await?),
+            Self::Redis(_) => Ok(0),
+        }
+    }
+
+    /// Redis expires burned token keys on its own.
+    pub async fn purge_used_redirect_tokens(&self, limit: i64) -> Result<u64, StorageError> {
+        match self {
+            Self::Sqlite(db) => Ok(db.purge_used_redirect_tokens(limit).await?),
+            Self::Redis(_) => Ok(0),
+        }
+    }
+
+    /// `binding` identifies the client that may take the challenge back.
+    pub async fn insert_challenge(
+        &self,
+        id: &str,
+        kind: &str,
+        binding: &str,
+        state_json: &str,
+        ttl_minutes: i64,
+    ) -> Result<(), StorageError> {
+        match self {
+            Self::Sqlite(db) => Ok(db
+                .insert_challenge(id, kind, binding, state_json, ttl_minutes)
+                .await?),
+            Self::Redis(redis) => {
+                let ttl = (ttl_minutes * 60).to_string();
+                let key = challenge_key(kind, id);
+                let args = [key.as_str(), binding, state_json, &ttl];
+                redis.eval(INSERT_CHALLENGE_SCRIPT, &args).await?;
+                Ok(())
+            }
+        }
+    }
+
+    /// Fetch and delete an unexpired challenge (single-use), returning its state JSON.
+    /// A wrong `binding` leaves the challenge for its owner.
+    pub async fn take_challenge(
+        &self,
+        id: &str,
+        kind: &str,
+        binding: &str,
+    ) -> Result<Option<String>, StorageError> {
+        match self {
+            Self::Sqlite(db) => Ok(db.take_challenge(id, kind, binding).await?),
+            Self::Redis(redis) => {
+                let key = challenge_key(kind, id);
+                let reply = redis
+                    .eval(TAKE_CHALLENGE_SCRIPT, &[key.as_str(), binding])
+                    .await?;
+                Ok(reply.into_string())
+            }
+        }
+    }
+
+    /// Mark a single-use token's `jti` (login redirect, solved login puzzle) used
+    /// until `BURN_LEEWAY_SECONDS` past `exp`; false if it already was.
+    pub async fn burn_redirect_token(&self, jti: &str, exp: i64) -> Result<bool, StorageError> {
+        let until = exp + BURN_LEEWAY_SECONDS;
+        match self {
+            Self::Sqlite(db) => Ok(db.burn_redirect_token(jti, until).await?),
+            Self::Redis(redis) => {
+                let key = format!("{REDIS_PREFIX}used_redirect_token:{jti}");
+                let reply = redis
+                    .query(&["SET", &key, "1", "NX", "EXAT", &until.to_string()])
+                    .await?;
+                Ok(reply != Reply::Nil)
+            }
+        }
+    }
+}
+
+const LEASE_SCRIPT: &str = r"
+local holder = redis.call('GET', ARGV[1])
+if holder and holder ~= ARGV[2] then
+  return 0
+end
+redis.call('SET', ARGV[1], ARGV[2], 'EX', ARGV[3])
+return 1
+";
+
+const INSERT_CHALLENGE_SCRIPT: &str = r"
+redis.call('HSET', ARGV[1], 'binding', ARGV[2], 'state', ARGV[3])
+redis.call('EXPIRE', ARGV[1], ARGV[4])
+return 1
+";
+
+const TAKE_CHALLENGE_SCRIPT: &str = r"
+if redis.call('HGET', ARGV[1], 'binding') ~= ARGV[2] then
+  return false
+end
+local state = redis.call('HGET', ARGV[1], 'state')
+redis.call('DEL', ARGV[1])
+return state
+";
+
+fn challenge_key(kind: &str, id: &str) -> String {
+    format!("{REDIS_PREFIX}challenge:{kind}:{id}")
+}
+
+/// `den:signing_keys` maps each `kid` to `<created>:<base64 secret>`.
+fn redis_signing_key(key: &SigningKey) -> String {
+    format!("{}:{}", key.created, BASE64.encode(&key.secret))
+}
+
+async fn redis_signing_keys(redis: &Redis) -> Result<Vec<SigningKey>, StorageError> {
+    let hash = format!("{REDIS_PREFIX}signing_keys");
+    let mut fields = redis
+        .query(&["HGETALL", &hash])
+        .await?
+        .into_array()
+        .into_iter();
+    let mut keys = Vec::new();
+    while let (Some(kid), Some(value)) = (fields.next(), fields.next()) {
+        let (Some(kid), Some(value)) = (kid.into_string(), value.into_string()) else {
+            continue;
+        };
+        let parsed = value.split_once(':').and_then(|(created, secret)| {
+            Some((created.parse().ok()?, BASE64.decode(secret).ok()?))
+        });
+        let Some((created, secret)) = parsed else {
+            return Err(io::Error::new(io::ErrorKind::InvalidData, "invalid signing key").into());
+        };
+        keys.push(SigningKey {
+            kid,
+            secret,
+            created,
+        });
+    }
+    signing_key::sort(&mut keys);
+    Ok(keys)
+}
+
+/// The single key replicas shared before keys were versioned, so tokens it signed
+/// keep verifying as [`signing_key::LEGACY_KID`].
+async fn legacy_redis_signing_key(redis: &Redis) -> Result<Option<SigningKey>, StorageError> {
+    let Some(secret) = redis
+        .query(&["GET", &format!("{REDIS_PREFIX}signing_key")])
+        .await?
+        .into_string()
+    else {
+        return Ok(None);
+    };
+    let secret = BASE64
+        .decode(secret)
+        .map_err(|_| io::Error::new(io::ErrorKind::InvalidData, "invalid signing key"))?;
+    Ok(Some(SigningKey {
+        kid: signing_key::LEGACY_KID.into(),
+        secret,
+        created: time::OffsetDateTime::now_utc().unix_timestamp(),
+    }))
+}
@@ -0,0 +1,117 @@
+use std::io;
+use std::time::Duration;
+
+use tokio::time::MissedTickBehavior;
+
+use crate::db::Db;
+
+/// Tell systemd about a state change (`READY=1`, `WATCHDOG=1`, …) over
+/// `$NOTIFY_SOCKET`, as sd_notify(3) does. Returns false when not started by a
+/// `Type=notify` unit, which is not an error.
+pub fn notify(state: &str) -> io::Result<bool> {
+    match std::env::var("NOTIFY_SOCKET") {
+        Ok(socket) if !socket.is_empty() => send(&socket, state).map(|()| true),
+        _ => Ok(false),
+    }
+}
+
+#[cfg(unix)]
+fn send(socket: &str, state: &str) -> io::Result<()> {
+    use std::os::unix::net::UnixDatagram;
+
+    let datagram = UnixDatagram::unbound()?;
+    match socket.strip_prefix('@') {
+        #[cfg(target_os = "linux")]
+        Some(name) => {
+            use std::os::linux::net::SocketAddrExt;
+            let addr = std::os::unix::net::SocketAddr::from_abstract_name(name)?;
+            datagram.send_to_addr(state.as_bytes(), &addr)?;
+        }
+        _ => {
+            datagram.send_to(state.as_bytes(), socket)?;
+        }
+    }
+    Ok(())
+}
+
+#[cfg(not(unix))]
+fn send(_socket: &str, _state: &str) -> io::Result<()> {
+    Err(io::Error::new(
+        io::ErrorKind::Unsupported,
+        "NOTIFY_SOCKET needs a unix host",
+    ))
+}
+
+/// Half of `WatchdogSec=`, the ping interval sd_watchdog_enabled(3) recommends, when
+/// the watchdog is on and meant for this process.
+fn watchdog_interval(usec: Option<&str>, pid: Option<&str>) -> Option<Duration> {
+    if pid.is_some_and(|pid| pid.parse::<u32>().ok() != Some(std::process::id())) {
+        return None;
+    }
+    let usec: u64 = usec?.parse().ok().filter(|&usec| usec > 0)?;
+    Some(Duration::from_micros(usec) / 2)
+}
+
+/// With `WatchdogSec=` set, ping the watchdog while the database answers, so a hung
+/// process or a wedged database gets den restarted.
+pub fn spawn_watchdog(db: Db) {
+    let Some(interval) = watchdog_interval(
+        std::env::var("WATCHDOG_USEC").ok().as_deref(),
+        std::env::var("WATCHDOG_PID").ok().as_deref(),
+    ) else {
+        return;
+    };
+    tracing::info!("pinging the systemd watchdog every {interval:?}");
+    tokio::spawn(async move {
+        let mut ticks = tokio::time::interval(interval);
+        ticks.set_missed_tick_behavior(MissedTickBehavior::Delay);
+        loop {
+            ticks.tick().await;
+            match tokio::time::timeout(interval, db.ping()).await {
+                Ok(Ok(())) => {
+                    if let Err(error) = notify("WATCHDOG=1") {
+                        tracing::warn!(error = %error, "failed to ping the systemd watchdog");
+                    }
+                }
+                Ok(Err(error)) => {
+                    tracing::warn!(error = %error, "database ping failed, skipping watchdog ping")
+                }
+                Err(_) => tracing::warn!("database ping timed out, skipping watchdog ping"),
+            }
+        }
+    });
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn watchdog_interval_is_half_the_timeout_for_this_process() {
+        let pid = std::process::id().to_string();
+        assert_eq!(
+            watchdog_interval(Some("30000000"), None),
+            Some(Duration::from_secs(15))
+        );
+        assert_eq!(
+            watchdog_interval(Some("30000000"), Some(&pid)),
+            Some(Duration::from_secs(15))
+        );
+        assert_eq!(watchdog_interval(Some("30000000"), Some("1")), None);
+        assert_eq!(watchdog_interval(Some("0"), None), None);
+        assert_eq!(watchdog_interval(None, None), None);
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn send_writes_one_datagram() {
+        let path = std::env::temp_dir().join(format!("den-notify-{}.sock", std::process::id()));
+        let _ = std::fs::remove_file(&path);
+        let receiver = std::os::unix::net::UnixDatagram::bind(&path).unwrap();
+        send(path.to_str().unwrap(), "READY=1").unwrap();
+        let mut buf = [0; 64];
+        let n = receiver.recv(&mut buf).unwrap();
+        assert_eq!(&buf[..n], b"READY=1");
+        std::fs::remove_file(&path).unwrap();
+    }
+}
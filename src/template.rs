@@ -0,0 +1,247 @@
+use std::collections::HashMap;
+use std::path::Path;
+
+use serde_json::Value;
+
+use crate::config::{ConfigError, invalid};
+use crate::notify::SecurityEvent;
+
+/// One per `SecurityEvent::kind`, overridable by `<notification_templates>/<kind>.txt`.
+const BUNDLED: &[(&str, &str)] = &[
+    (
+        "sign_count_regression",
+        include_str!("../templates/sign_count_regression.txt"),
+    ),
+    ("new_device", include_str!("../templates/new_device.txt")),
+    (
+        "passkey_removed",
+        include_str!("../templates/passkey_removed.txt"),
+    ),
+    (
+        "recovery_requested",
+        include_str!("../templates/recovery_requested.txt"),
+    ),
+    (
+        "recovery_cancelled",
+        include_str!("../templates/recovery_cancelled.txt"),
+    ),
+    (
+        "recovery_completed",
+        include_str!("../templates/recovery_completed.txt"),
+    ),
+    (
+        "recovery_code_used",
+        include_str!("../templates/recovery_code_used.txt"),
+    ),
+    ("slo_burn", include_str!("../templates/slo_burn.txt")),
+];
+
+/// A rendered notification: email subject / push title, and the text below it.
+#[derive(Debug, Clone)]
+pub struct Message {
+    pub title: String,
+    pub body: String,
+}
+
+/// Per-event notification text. A template's first line is the title and the rest
+/// (after one blank line) the body; `{{ field }}` is replaced by that field of the
+/// event's webhook JSON, e.g. `{{ passkey }}` or `{{ ip }}`.
+#[derive(Debug)]
+pub struct Templates {
+    by_kind: HashMap<&'static str, Template>,
+}
+
+impl Default for Templates {
+    fn default() -> Self {
+        Self::load(None).expect("bundled templates are valid")
+    }
+}
+
+impl Templates {
+    /// The bundled templates, each replaced by `<dir>/<kind>.txt` where that exists.
+    pub fn load(dir: Option<&Path>) -> Result<Self, ConfigError> {
+        const KEY: &str = "notification_templates";
+        let by_kind = BUNDLED
+            .iter()
+            .map(|&(kind, bundled)| {
+                let path = dir.map(|dir| dir.join(format!("{kind}.txt")));
+                let source = match path.as_deref().filter(|p| p.is_file()) {
+                    Some(path) => {
+                        std::fs::read_to_string(path).map_err(|source| ConfigError::ReadFile {
+                            key: KEY,
+                            path: path.to_owned(),
+                            source,
+                        })?
+                    }
+                    None => bundled.to_owned(),
+                };
+                let template = Template::parse(&source)
+                    .map_err(|e| invalid(KEY, format!("invalid template for {kind}: {e}")))?;
+                Ok::<_, ConfigError>((kind, template))
+            })
+            .collect::<Result<_, ConfigError>>()?;
+        Ok(Self { by_kind })
+    }
+
+    pub fn render(&self, event: &SecurityEvent) -> Message {
+        let fields = match serde_json::to_value(event) {
+            Ok(Value::Object(fields)) => fields,
+            _ => Default::default(),
+        };
+        let Some(template) = self.by_kind.get(event.kind()) else {
+            return Message {
+                title: event.kind().to_owned(),
+                body: Value::Object(fields).to_string(),
+            };
+        };
+        let field = |name: &str| match fields.get(name) {
+            Some(Value::String(value)) => Some(value.clone()),
+            Some(value) => Some(value.to_string()),
+            None => None,
+        };
+        Message {
+            // Titles end up in mail and HTTP headers.
+            title: render(&template.title, &field).replace(['\r', '\n'], " "),
+            body: render(&template.body, &field),
+        }
+    }
+}
+
+#[derive(Debug)]
+struct Template {
+    title: Vec<Part>,
+    body: Vec<Part>,
+}
+
+#[derive(Debug, PartialEq)]
+enum Part {
+    Text(String),
+    Field(String),
+}
+
+impl Template {
+    fn parse(source: &str) -> Result<Self, String> {
+        let (title, body) = source.split_once('\n').unwrap_or((source, ""));
+        let body = body.strip_prefix('\n').unwrap_or(body);
+        Ok(Self {
+            title: parse_parts(title.trim())?,
+            body: parse_parts(body.trim_end())?,
+        })
+    }
+}
+
+fn parse_parts(mut source: &str) -> Result<Vec<Part>, String> {
+    let mut parts = Vec::new();
+    while let Some(start) = source.find("{{") {
+        if start > 0 {
+            parts.push(Part::Text(source[..start].to_owned()));
+        }
+        let rest = &source[start + 2..];
+        let end = rest.find("}}").ok_or("unclosed {{")?;
+        let name = rest[..end].trim();
+        if name.is_empty() || !name.bytes().all(|b| b.is_ascii_alphanumeric() || b == b'_') {
+            return Err(format!("invalid field {{{{ {name} }}}}"));
+        }
+        parts.push(Part::Field(name.to_owned()));
+        source = &rest[end + 2..];
+    }
+    if !source.is_empty() {
+        parts.push(Part::Text(source.to_owned()));
+    }
+    Ok(parts)
+}
+
+/// Unknown fields are left as written so a typo shows up in the notification.
+fn render(parts: &[Part], field: &impl Fn(&str) -> Option<String>) -> String {
+    parts
+        .iter()
+        .map(|part| match part {
+            Part::Text(text) => text.clone(),
+            Part::Field(name) => field(name).unwrap_or_else(|| format!("{{{{ {name} }}}}")),
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn bundled_templates_fill_every_field() {
+        let templates = Templates::default();
+        let events = [
+            SecurityEvent::SignCountRegression {
+                credential_id: "abc".into(),
+            },
+            SecurityEvent::NewDevice {
+                passkey: "laptop".into(),
+                user_agent: "Firefox on Linux".into(),
+                ip: "192.0.2.1".into(),
+            },
+            SecurityEvent::PasskeyRemoved {
+                passkey: "laptop".into(),
+            },
+            SecurityEvent::RecoveryRequested {
+                user_name: "alice".into(),
+                ready_at: "2026-01-04 12:00:00".into(),
+            },
+            SecurityEvent::RecoveryCancelled {
+                user_name: "alice".into(),
+            },
+            SecurityEvent::RecoveryCompleted {
+                user_name: "alice".into(),
+            },
+            SecurityEvent::RecoveryCodeUsed {
+                user_agent: "Firefox on Linux".into(),
+                ip: "192.0.2.1".into(),
+            },
+            SecurityEvent::SloBurn {
+                instance_id: "den-1".into(),
+                objective: "99.9%".into(),
+                burn_rate_5m: "40.0".into(),
+                burn_rate_1h: "15.2".into(),
+            },
+        ];
+        assert_eq!(events.len(), BUNDLED.len());
+        for event in &events {
+            let message = templates.render(event);
+            assert!(!message.title.is_empty(), "{}", event.kind());
+            assert!(!message.body.contains("{{"), "{}", message.body);
+        }
+        let message = templates.render(&events[1]);
+        assert_eq!(message.title, "Login from a new device");
+        assert!(message.body.starts_with(
+            "Passkey \"laptop\" was used from a new device (Firefox on Linux, 192.0.2.1)."
+        ));
+    }
+
+    #[test]
+    fn broken_overrides_are_config_errors() {
+        let dir = std::env::temp_dir().join(format!("den-templates-{}", std::process::id()));
+        std::fs::create_dir_all(&dir).unwrap();
+        std::fs::write(dir.join("new_device.txt"), "Login from {{ ip").unwrap();
+        let error = Templates::load(Some(&dir)).unwrap_err();
+        std::fs::remove_dir_all(&dir).unwrap();
+        assert!(matches!(
+            error,
+            ConfigError::Invalid {
+                key: "notification_templates",
+                ..
+            }
+        ));
+    }
+
+    #[test]
+    fn parses_fields_and_rejects_unclosed_braces() {
+        assert_eq!(
+            parse_parts("hi {{ user_name }}!").unwrap(),
+            vec![
+                Part::Text("hi ".into()),
+                Part::Field("user_name".into()),
+                Part::Text("!".into()),
+            ]
+        );
+        assert!(parse_parts("hi {{ user_name").is_err());
+        assert!(parse_parts("hi {{ user name }}").is_err());
+    }
+}
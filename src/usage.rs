@@ -0,0 +1,41 @@
+use serde::Serialize;
+
+use crate::db::{AppUsage, Db, UsageDay};
+
+/// Days of counters kept; the stats endpoint shows the most recent `STATS_DAYS`.
+const RETENTION_DAYS: u32 = 400;
+pub const STATS_DAYS: u32 = 30;
+
+/// The admin's own usage, from `usage_daily`: counts per UTC day, nothing per user.
+#[derive(Debug, Serialize)]
+pub struct Usage {
+    /// Newest first; days without activity are left out.
+    pub days: Vec<UsageDay>,
+    /// Redirect origins by sign-ins handed to them, most used first.
+    pub apps: Vec<AppUsage>,
+}
+
+/// Best-effort: count a login redirect to `origin` today.
+pub async fn record_redirect(db: &Db, origin: &str) {
+    if let Err(error) = db.count_redirect(origin).await {
+        tracing::warn!(error = %error, origin, "failed to count redirect");
+    }
+}
+
+/// Raise today's sessions-used count to `used_today`; a lower reading never wins.
+pub async fn record_sessions(db: &Db, used_today: i64) -> Result<(), sqlx::Error> {
+    db.record_session_usage(used_today).await
+}
+
+pub async fn purge(db: &Db) -> Result<u64, sqlx::Error> {
+    db.purge_usage(RETENTION_DAYS).await
+}
+
+/// The last `days` days, today included.
+pub async fn summary(db: &Db, days: u32) -> Result<Usage, sqlx::Error> {
+    let since = format!("-{} days", days.saturating_sub(1));
+    Ok(Usage {
+        days: db.usage_days(&since).await?,
+        apps: db.usage_apps(&since).await?,
+    })
+}
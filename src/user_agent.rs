@@ -0,0 +1,117 @@
+/// Coarse browser/OS summary of a `User-Agent`, good enough to tell devices apart
+/// in a session list. Order matters: most UAs claim to be several browsers at once.
+#[derive(Debug, Default, PartialEq, Eq)]
+pub struct Device {
+    pub browser: Option<&'static str>,
+    pub version: Option<String>,
+    pub os: Option<&'static str>,
+}
+
+impl Device {
+    /// Browser with its major version, e.g. "Firefox 128".
+    pub fn browser_label(&self) -> Option<String> {
+        let browser = self.browser?;
+        Some(match &self.version {
+            Some(v) => format!("{browser} {v}"),
+            None => browser.to_string(),
+        })
+    }
+}
+
+const BROWSERS: &[(&str, &str)] = &[
+    ("Edg/", "Edge"),
+    ("EdgA/", "Edge"),
+    ("EdgiOS/", "Edge"),
+    ("OPR/", "Opera"),
+    ("SamsungBrowser/", "Samsung Internet"),
+    ("Firefox/", "Firefox"),
+    ("FxiOS/", "Firefox"),
+    ("CriOS/", "Chrome"),
+    ("Chromium/", "Chromium"),
+    ("Chrome/", "Chrome"),
+    ("Version/", "Safari"),
+];
+
+const SYSTEMS: &[(&str, &str)] = &[
+    ("iPhone", "iOS"),
+    ("iPad", "iPadOS"),
+    ("Android", "Android"),
+    ("CrOS", "ChromeOS"),
+    ("Windows", "Windows"),
+    ("Mac OS X", "macOS"),
+    ("Macintosh", "macOS"),
+    ("Linux", "Linux"),
+];
+
+pub fn parse(ua: &str) -> Device {
+    let found = BROWSERS.iter().find_map(|(token, name)| {
+        let rest = &ua[ua.find(token)? + token.len()..];
+        if *name == "Safari" && !ua.contains("Safari/") {
+            return None;
+        }
+        let major: String = rest.chars().take_while(char::is_ascii_digit).collect();
+        Some((*name, Some(major).filter(|m| !m.is_empty())))
+    });
+    let os = SYSTEMS
+        .iter()
+        .find(|(token, _)| ua.contains(token))
+        .map(|(_, name)| *name);
+    let (browser, version) = found.unzip();
+    Device {
+        browser,
+        version: version.flatten(),
+        os,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn summary(ua: &str) -> (Option<String>, Option<&'static str>) {
+        let d = parse(ua);
+        (d.browser_label(), d.os)
+    }
+
+    #[test]
+    fn recognises_common_browsers() {
+        assert_eq!(
+            summary(
+                "Mozilla/5.0 (Windows NT 10.0; Win64; x64) AppleWebKit/537.36 \
+                 (KHTML, like Gecko) Chrome/126.0.0.0 Safari/537.36 Edg/126.0.2592.87"
+            ),
+            (Some("Edge 126".into()), Some("Windows"))
+        );
+        assert_eq!(
+            summary(
+                "Mozilla/5.0 (Macintosh; Intel Mac OS X 10_15_7) AppleWebKit/605.1.15 \
+                 (KHTML, like Gecko) Version/17.5 Safari/605.1.15"
+            ),
+            (Some("Safari 17".into()), Some("macOS"))
+        );
+        assert_eq!(
+            summary("Mozilla/5.0 (X11; Linux x86_64; rv:128.0) Gecko/20100101 Firefox/128.0"),
+            (Some("Firefox 128".into()), Some("Linux"))
+        );
+        assert_eq!(
+            summary(
+                "Mozilla/5.0 (Linux; Android 14; Pixel 8) AppleWebKit/537.36 \
+                 (KHTML, like Gecko) Chrome/126.0.0.0 Mobile Safari/537.36"
+            ),
+            (Some("Chrome 126".into()), Some("Android"))
+        );
+        assert_eq!(
+            summary(
+                "Mozilla/5.0 (iPhone; CPU iPhone OS 17_5 like Mac OS X) AppleWebKit/605.1.15 \
+                 (KHTML, like Gecko) CriOS/126.0.6478.54 Mobile/15E148 Safari/604.1"
+            ),
+            (Some("Chrome 126".into()), Some("iOS"))
+        );
+    }
+
+    #[test]
+    fn unknown_agents_yield_nothing() {
+        assert_eq!(summary("curl/8.8.0"), (None, None));
+        assert_eq!(summary("unknown"), (None, None));
+    }
+}
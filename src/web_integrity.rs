@@ -0,0 +1,161 @@
+use std::collections::BTreeMap;
+use std::path::Path;
+
+use base64::Engine;
+use base64::engine::general_purpose::STANDARD;
+use openssl::pkey::{Id, PKey};
+use openssl::sign::Verifier;
+use serde::Deserialize;
+use sha2::{Digest, Sha256};
+
+use crate::config::WebIntegrityConfig;
+use crate::frontend;
+
+/// Written into every web build by `web/vite.config.ts`.
+pub const MANIFEST: &str = "den-manifest.json";
+/// Base64 Ed25519 signature over the manifest's exact bytes, when built with
+/// `DEN_WEB_SIGNING_KEY`.
+pub const SIGNATURE: &str = "den-manifest.json.sig";
+
+#[derive(Deserialize)]
+pub(crate) struct Manifest {
+    /// Every file in the build, `/`-separated relative path to SHA-256 hex.
+    pub(crate) files: BTreeMap<String, String>,
+}
+
+/// Verify the served web directory against `web_integrity`, returning what was
+/// checked. No web directory is not an error: there is nothing to tamper with.
+pub fn check(config: &WebIntegrityConfig) -> Result<String, String> {
+    let Some(dir) = frontend::resolve_web_out_dir() else {
+        return Ok("no web directory to verify".into());
+    };
+    check_dir(config, &dir)
+}
+
+/// `check` for a given directory, such as a build `web_source` just fetched.
+pub fn check_dir(config: &WebIntegrityConfig, dir: &Path) -> Result<String, String> {
+    let pem = std::fs::read(&config.public_key)
+        .map_err(|e| format!("failed to read web_integrity.public_key: {e}"))?;
+    let files = verify(dir, &pem).map_err(|e| format!("{}: {e}", dir.display()))?;
+    Ok(format!("{}: {files} files match", dir.display()))
+}
+
+/// Check the manifest signature, then that `dir` holds exactly the listed files
+/// with the listed hashes. Returns the number of files.
+pub fn verify(dir: &Path, public_key_pem: &[u8]) -> Result<usize, String> {
+    let key = PKey::public_key_from_pem(public_key_pem)
+        .map_err(|e| format!("invalid web_integrity.public_key: {e}"))?;
+    if key.id() != Id::ED25519 {
+        return Err("web_integrity.public_key must be an Ed25519 key".into());
+    }
+    let manifest =
+        std::fs::read(dir.join(MANIFEST)).map_err(|e| format!("failed to read {MANIFEST}: {e}"))?;
+    let signature = std::fs::read_to_string(dir.join(SIGNATURE))
+        .map_err(|e| format!("failed to read {SIGNATURE}: {e}"))?;
+    let signature = STANDARD
+        .decode(signature.trim())
+        .map_err(|_| format!("{SIGNATURE} is not base64"))?;
+    let valid = Verifier::new_without_digest(&key)
+        .and_then(|mut verifier| verifier.verify_oneshot(&signature, &manifest))
+        .unwrap_or(false);
+    if !valid {
+        return Err(format!("{MANIFEST} signature does not verify"));
+    }
+    let manifest: Manifest =
+        serde_json::from_slice(&manifest).map_err(|e| format!("{MANIFEST} is malformed: {e}"))?;
+
+    let mut present = BTreeMap::new();
+    hash_files(dir, "", &mut present).map_err(|e| format!("failed to read files: {e}"))?;
+    let mut problems: Vec<String> = Vec::new();
+    for (path, expected) in &manifest.files {
+        match present.get(path) {
+            Some(actual) if actual.eq_ignore_ascii_case(expected) => {}
+            Some(_) => problems.push(format!("{path} was modified")),
+            None => problems.push(format!("{path} is missing")),
+        }
+    }
+    for path in present.keys() {
+        if !manifest.files.contains_key(path) {
+            problems.push(format!("{path} is not in the manifest"));
+        }
+    }
+    if problems.is_empty() {
+        Ok(present.len())
+    } else {
+        Err(problems.join(", "))
+    }
+}
+
+/// SHA-256 of every regular file under `dir`, except the manifest and signature.
+fn hash_files(dir: &Path, prefix: &str, out: &mut BTreeMap<String, String>) -> std::io::Result<()> {
+    for entry in std::fs::read_dir(dir)? {
+        let entry = entry?;
+        let name = entry.file_name().to_string_lossy().into_owned();
+        let path = format!("{prefix}{name}");
+        // Follows symlinks: whatever they point at would be served.
+        if std::fs::metadata(entry.path())?.is_dir() {
+            hash_files(&entry.path(), &format!("{path}/"), out)?;
+        } else if path != MANIFEST && path != SIGNATURE {
+            let data = std::fs::read(entry.path())?;
+            out.insert(path, format!("{:x}", Sha256::digest(&data)));
+        }
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use openssl::pkey::Private;
+    use openssl::sign::Signer;
+
+    use super::*;
+
+    fn build(dir: &Path, key: &PKey<Private>, files: &[(&str, &str)]) {
+        let _ = std::fs::remove_dir_all(dir);
+        std::fs::create_dir_all(dir.join("assets")).unwrap();
+        let mut listed = BTreeMap::new();
+        for (path, contents) in files {
+            std::fs::write(dir.join(path), contents).unwrap();
+            listed.insert(
+                (*path).to_owned(),
+                format!("{:x}", Sha256::digest(contents.as_bytes())),
+            );
+        }
+        let manifest = serde_json::json!({ "files": listed }).to_string();
+        let signature = Signer::new_without_digest(key)
+            .unwrap()
+            .sign_oneshot_to_vec(manifest.as_bytes())
+            .unwrap();
+        std::fs::write(dir.join(MANIFEST), &manifest).unwrap();
+        std::fs::write(dir.join(SIGNATURE), STANDARD.encode(signature)).unwrap();
+    }
+
+    #[test]
+    fn detects_modified_added_and_unsigned_files() {
+        let key = PKey::generate_ed25519().unwrap();
+        let public = key.public_key_to_pem().unwrap();
+        let dir = std::env::temp_dir().join(format!("den-web-{}", std::process::id()));
+        let files = [
+            ("index.html", "<script src=/assets/app.js></script>"),
+            ("assets/app.js", "login()"),
+        ];
+
+        build(&dir, &key, &files);
+        assert_eq!(verify(&dir, &public), Ok(2));
+
+        std::fs::write(dir.join("assets/app.js"), "steal()").unwrap();
+        std::fs::write(dir.join("assets/extra.js"), "steal()").unwrap();
+        assert_eq!(
+            verify(&dir, &public),
+            Err("assets/app.js was modified, assets/extra.js is not in the manifest".into())
+        );
+
+        let other = PKey::generate_ed25519().unwrap();
+        build(&dir, &other, &files);
+        assert_eq!(
+            verify(&dir, &public),
+            Err(format!("{MANIFEST} signature does not verify"))
+        );
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+}
@@ -0,0 +1,341 @@
+use std::path::{Component, Path, PathBuf};
+use std::time::{Duration, SystemTime};
+
+use aws_credential_types::Credentials;
+use aws_sigv4::http_request::{
+    PayloadChecksumKind, SignableBody, SignableRequest, SigningSettings, sign,
+};
+use aws_sigv4::sign::v4;
+use sha2::{Digest, Sha256};
+use tokio::time::{Instant, MissedTickBehavior};
+use url::Url;
+
+use crate::config::{MismatchAction, WebIntegrityConfig, WebSource};
+use crate::frontend;
+use crate::http;
+use crate::web_integrity::{self, MANIFEST, Manifest, SIGNATURE};
+
+const REFRESH_INTERVAL: Duration = Duration::from_secs(300);
+/// Per file; Vite chunks are far smaller, this only bounds a misbehaving origin.
+const MAX_FILE_BYTES: u64 = 64 << 20;
+/// Names the cached build last served, for starts while the origin is down.
+const CURRENT: &str = "current";
+
+/// Builds are cached next to the database, so a restart survives an unreachable origin.
+pub fn cache_dir(database_path: &Path) -> PathBuf {
+    database_path
+        .parent()
+        .unwrap_or_else(|| Path::new("."))
+        .join("web")
+}
+
+/// Fetch the build at `source` into `cache/<manifest hash>`, unless a complete copy
+/// is already there, and return its directory. Every file must match the hash in
+/// `den-manifest.json`; the manifest itself is only authenticated by `[web_integrity]`.
+pub fn sync(source: &WebSource, cache: &Path) -> Result<PathBuf, String> {
+    let manifest_bytes = fetch(source, MANIFEST)?;
+    let manifest: Manifest = serde_json::from_slice(&manifest_bytes)
+        .map_err(|e| format!("{MANIFEST} is malformed: {e}"))?;
+    let version = format!("{:x}", Sha256::digest(&manifest_bytes))[..16].to_owned();
+    let dir = cache.join(&version);
+    if dir.is_dir() {
+        return Ok(dir);
+    }
+
+    let partial = cache.join(format!("{version}.partial"));
+    let _ = std::fs::remove_dir_all(&partial);
+    let write = |path: &Path, data: &[u8]| {
+        if let Some(parent) = path.parent() {
+            std::fs::create_dir_all(parent)?;
+        }
+        std::fs::write(path, data)
+    };
+    for (path, expected) in &manifest.files {
+        if !is_safe_rel_path(path) {
+            return Err(format!("{MANIFEST} lists unsafe path {path:?}"));
+        }
+        let data = fetch(source, path)?;
+        if !format!("{:x}", Sha256::digest(&data)).eq_ignore_ascii_case(expected) {
+            return Err(format!("{path} does not match {MANIFEST}"));
+        }
+        write(&partial.join(path), &data).map_err(|e| format!("failed to cache {path}: {e}"))?;
+    }
+    write(&partial.join(MANIFEST), &manifest_bytes)
+        .map_err(|e| format!("failed to cache {MANIFEST}: {e}"))?;
+    // Unsigned builds are fine unless `[web_integrity]` says otherwise.
+    if let Ok(signature) = fetch(source, SIGNATURE) {
+        write(&partial.join(SIGNATURE), &signature)
+            .map_err(|e| format!("failed to cache {SIGNATURE}: {e}"))?;
+    }
+    std::fs::rename(&partial, &dir).map_err(|e| format!("failed to cache build: {e}"))?;
+    Ok(dir)
+}
+
+/// A fresh sync, else the build the last run served.
+pub async fn load(source: &WebSource, cache: &Path) -> Result<PathBuf, String> {
+    let (source, cache) = (source.clone(), cache.to_owned());
+    let cached = cache.clone();
+    let synced = tokio::task::spawn_blocking(move || sync(&source, &cache))
+        .await
+        .map_err(|e| e.to_string())?;
+    synced.or_else(|e| {
+        let version = std::fs::read_to_string(cached.join(CURRENT)).map_err(|_| e.clone())?;
+        let dir = cached.join(version.trim());
+        if !dir.is_dir() {
+            return Err(e);
+        }
+        tracing::warn!("web_source unreachable, serving the cached build: {e}");
+        Ok(dir)
+    })
+}
+
+/// Serve `dir` and record it as current, dropping cached builds other than it and
+/// the one it replaces, which may still be answering requests.
+pub fn serve(cache: &Path, dir: PathBuf, spa_routes: Option<&[String]>) {
+    let previous = frontend::resolve_web_out_dir();
+    if let Some(version) = dir.file_name()
+        && let Err(e) = std::fs::write(cache.join(CURRENT), version.as_encoded_bytes())
+    {
+        tracing::warn!("failed to record the current web build: {e}");
+    }
+    if let Ok(entries) = std::fs::read_dir(cache) {
+        for entry in entries.flatten() {
+            let path = entry.path();
+            if path.is_dir() && path != dir && Some(&path) != previous.as_ref() {
+                let _ = std::fs::remove_dir_all(&path);
+            }
+        }
+    }
+    frontend::serve_synced(dir, spa_routes);
+}
+
+/// Re-sync every five minutes and serve a new build once it is complete and, with
+/// `[web_integrity]`, verified. A failed sync keeps the current build.
+pub fn spawn(
+    source: WebSource,
+    cache: PathBuf,
+    integrity: Option<WebIntegrityConfig>,
+    spa_routes: Option<Vec<String>>,
+) {
+    tokio::spawn(async move {
+        let mut interval =
+            tokio::time::interval_at(Instant::now() + REFRESH_INTERVAL, REFRESH_INTERVAL);
+        interval.set_missed_tick_behavior(MissedTickBehavior::Delay);
+        loop {
+            interval.tick().await;
+            let (url, dir) = (source.clone(), cache.clone());
+            let dir = match tokio::task::spawn_blocking(move || sync(&url, &dir)).await {
+                Ok(Ok(dir)) => dir,
+                Ok(Err(error)) => {
+                    tracing::warn!(error = %error, "failed to refresh web assets");
+                    continue;
+                }
+                Err(error) => {
+                    tracing::warn!(error = %error, "web asset refresh panicked");
+                    continue;
+                }
+            };
+            if frontend::resolve_web_out_dir().as_ref() == Some(&dir) {
+                continue;
+            }
+            if let Some(integrity) = &integrity
+                && let Err(error) = web_integrity::check_dir(integrity, &dir)
+            {
+                if integrity.on_mismatch == MismatchAction::Refuse {
+                    tracing::warn!(
+                        error = %error,
+                        "new web build failed verification, not serving it"
+                    );
+                    continue;
+                }
+                tracing::warn!(error = %error, "new web build failed verification, serving anyway");
+            }
+            tracing::info!("serving web build {}", dir.display());
+            serve(&cache, dir, spa_routes.as_deref());
+        }
+    });
+}
+
+fn fetch(source: &WebSource, path: &str) -> Result<Vec<u8>, String> {
+    let url = source.url.join(path).map_err(|e| format!("{path}: {e}"))?;
+    let signed = match &source.s3_region {
+        Some(region) => s3_headers(&url, region, SystemTime::now())
+            .map_err(|e| format!("{url}: signing failed: {e}"))?,
+        None => Vec::new(),
+    };
+    let headers: Vec<_> = signed
+        .iter()
+        .map(|(name, value)| (name.as_str(), value.as_str()))
+        .collect();
+    let response =
+        http::get_large(&url, &headers, MAX_FILE_BYTES).map_err(|e| format!("{url}: {e}"))?;
+    if !response.is_success() {
+        return Err(format!("{url}: HTTP {}", response.status));
+    }
+    Ok(response.body)
+}
+
+/// SigV4 headers for an S3 GET of `url`, from the standard `AWS_ACCESS_KEY_ID`,
+/// `AWS_SECRET_ACCESS_KEY` and `AWS_SESSION_TOKEN` variables, read per request so
+/// rotated session credentials are picked up. None set: an anonymous GET.
+fn s3_headers(
+    url: &Url,
+    region: &str,
+    now: SystemTime,
+) -> Result<Vec<(String, String)>, Box<dyn std::error::Error>> {
+    let var = |name| std::env::var(name).ok().filter(|v| !v.is_empty());
+    let (Some(access_key), Some(secret_key)) =
+        (var("AWS_ACCESS_KEY_ID"), var("AWS_SECRET_ACCESS_KEY"))
+    else {
+        return Ok(Vec::new());
+    };
+    let credentials = Credentials::new(
+        access_key,
+        secret_key,
+        var("AWS_SESSION_TOKEN"),
+        None,
+        "environment",
+    );
+    sign_s3_get(url, region, &credentials, now)
+}
+
+fn sign_s3_get(
+    url: &Url,
+    region: &str,
+    credentials: &Credentials,
+    now: SystemTime,
+) -> Result<Vec<(String, String)>, Box<dyn std::error::Error>> {
+    let mut settings = SigningSettings::default();
+    // S3 wants the payload hash in `x-amz-content-sha256`.
+    settings.payload_checksum_kind = PayloadChecksumKind::XAmzSha256;
+    let identity = credentials.clone().into();
+    let params = v4::SigningParams::builder()
+        .identity(&identity)
+        .region(region)
+        .name("s3")
+        .time(now)
+        .settings(settings)
+        .build()?
+        .into();
+    let request = SignableRequest::new(
+        "GET",
+        url.as_str(),
+        std::iter::empty(),
+        SignableBody::Bytes(&[]),
+    )?;
+    let (instructions, _) = sign(request, &params)?.into_parts();
+    Ok(instructions
+        .headers()
+        .map(|(name, value)| (name.to_owned(), value.to_owned()))
+        .collect())
+}
+
+fn is_safe_rel_path(path: &str) -> bool {
+    !path.is_empty()
+        && Path::new(path)
+            .components()
+            .all(|component| matches!(component, Component::Normal(_)))
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::BTreeMap;
+    use std::io::{Read, Write};
+    use std::net::TcpListener;
+
+    use super::*;
+
+    /// Answers each GET from `files` (by path) with one connection per request.
+    fn origin(files: BTreeMap<String, Vec<u8>>) -> WebSource {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let url = Url::parse(&format!("http://{}/build/", listener.local_addr().unwrap())).unwrap();
+        std::thread::spawn(move || {
+            for mut stream in listener.incoming().flatten() {
+                let mut buf = [0; 4096];
+                let n = stream.read(&mut buf).unwrap_or(0);
+                let request = String::from_utf8_lossy(&buf[..n]);
+                let path = request.split(' ').nth(1).unwrap_or_default();
+                let response = match files.get(path.trim_start_matches("/build/")) {
+                    Some(body) => {
+                        let mut r =
+                            format!("HTTP/1.1 200 OK\r\nContent-Length: {}\r\n\r\n", body.len())
+                                .into_bytes();
+                        r.extend_from_slice(body);
+                        r
+                    }
+                    None => b"HTTP/1.1 404 Not Found\r\nContent-Length: 0\r\n\r\n".to_vec(),
+                };
+                let _ = stream.write_all(&response);
+            }
+        });
+        WebSource {
+            url,
+            s3_region: None,
+        }
+    }
+
+    #[test]
+    fn s3_gets_are_signed_for_the_region() {
+        let url =
+            Url::parse("https://den-web.s3.eu-central-1.amazonaws.com/build/den-manifest.json")
+                .unwrap();
+        let credentials = Credentials::new("AKIDEXAMPLE", "secret", None, None, "test");
+        let now = SystemTime::UNIX_EPOCH + Duration::from_secs(1_700_000_000);
+        let headers = sign_s3_get(&url, "eu-central-1", &credentials, now).unwrap();
+        let header = |name: &str| {
+            headers
+                .iter()
+                .find(|(n, _)| n == name)
+                .map(|(_, v)| v.as_str())
+                .unwrap()
+        };
+        assert!(
+            header("authorization").starts_with(
+                "AWS4-HMAC-SHA256 Credential=AKIDEXAMPLE/20231114/eu-central-1/s3/aws4_request"
+            ),
+            "{headers:?}"
+        );
+        assert_eq!(header("x-amz-date"), "20231114T221320Z");
+        assert_eq!(
+            header("x-amz-content-sha256"),
+            format!("{:x}", Sha256::digest(b""))
+        );
+    }
+
+    #[test]
+    fn sync_fetches_verifies_and_reuses_builds() {
+        let cache = std::env::temp_dir().join(format!("den-web-source-{}", std::process::id()));
+        let _ = std::fs::remove_dir_all(&cache);
+        let app = b"login()".to_vec();
+        let index = format!("{:x}", Sha256::digest(b"<html>"));
+        let manifest = |app: String| {
+            let files = serde_json::json!({ "index.html": index, "assets/app.js": app });
+            serde_json::json!({ "files": files })
+                .to_string()
+                .into_bytes()
+        };
+        let files = |manifest: Vec<u8>, app: &[u8]| {
+            BTreeMap::from([
+                (MANIFEST.to_owned(), manifest),
+                ("index.html".to_owned(), b"<html>".to_vec()),
+                ("assets/app.js".to_owned(), app.to_vec()),
+            ])
+        };
+
+        let good = manifest(format!("{:x}", Sha256::digest(&app)));
+        let dir = sync(&origin(files(good.clone(), &app)), &cache).unwrap();
+        assert_eq!(std::fs::read(dir.join("assets/app.js")).unwrap(), app);
+        assert_eq!(std::fs::read(dir.join(MANIFEST)).unwrap(), good);
+        assert!(!dir.join(SIGNATURE).exists());
+        // Same manifest: nothing is fetched but the manifest.
+        let unreachable_files = BTreeMap::from([(MANIFEST.to_owned(), good)]);
+        assert_eq!(sync(&origin(unreachable_files), &cache).unwrap(), dir);
+
+        let tampered = manifest(format!("{:x}", Sha256::digest(b"other")));
+        assert_eq!(
+            sync(&origin(files(tampered, &app)), &cache),
+            Err(format!("assets/app.js does not match {MANIFEST}"))
+        );
+        std::fs::remove_dir_all(&cache).unwrap();
+    }
+}
@@ -0,0 +1,121 @@
+use std::fmt::Write;
+
+use axum::extract::State;
+use axum::http::{StatusCode, header};
+use axum::response::{IntoResponse, Redirect, Response};
+use axum::routing::get;
+use axum::{Json, Router};
+use serde::Serialize;
+use time::{Duration, OffsetDateTime};
+
+use crate::config::SecurityTxtConfig;
+use crate::state::AppState;
+
+/// `/.well-known/*`, answered on every host den serves.
+pub fn router() -> Router<AppState> {
+    Router::new()
+        .route("/security.txt", get(security_txt))
+        .route("/change-password", get(change_password))
+        .route("/webauthn", get(webauthn))
+}
+
+async fn security_txt(State(state): State<AppState>) -> Response {
+    let Some(config) = &state.security_txt else {
+        return StatusCode::NOT_FOUND.into_response();
+    };
+    let body = render_security_txt(config, &state.rp_origin, OffsetDateTime::now_utc());
+    ([(header::CONTENT_TYPE, "text/plain; charset=utf-8")], body).into_response()
+}
+
+fn render_security_txt(config: &SecurityTxtConfig, rp_origin: &str, now: OffsetDateTime) -> String {
+    let mut out = String::new();
+    let mut field = |name: &str, value: &str| {
+        let value = value.trim();
+        if !value.is_empty() {
+            let _ = writeln!(out, "{name}: {value}");
+        }
+    };
+    for contact in &config.contact {
+        field("Contact", contact);
+    }
+    match &config.expires {
+        Some(expires) => field("Expires", expires),
+        None => field("Expires", &rfc3339_date(now + Duration::days(365))),
+    }
+    for encryption in &config.encryption {
+        field("Encryption", encryption);
+    }
+    if let Some(acknowledgments) = &config.acknowledgments {
+        field("Acknowledgments", acknowledgments);
+    }
+    field(
+        "Preferred-Languages",
+        &config.preferred_languages.join(", "),
+    );
+    field(
+        "Canonical",
+        &format!("{rp_origin}/.well-known/security.txt"),
+    );
+    if let Some(policy) = &config.policy {
+        field("Policy", policy);
+    }
+    if let Some(hiring) = &config.hiring {
+        field("Hiring", hiring);
+    }
+    out
+}
+
+/// Midnight UTC of `at`'s day, so the default Expires doesn't change every second.
+fn rfc3339_date(at: OffsetDateTime) -> String {
+    format!(
+        "{:04}-{:02}-{:02}T00:00:00Z",
+        at.year(),
+        u8::from(at.month()),
+        at.day()
+    )
+}
+
+/// Password managers send users here to "change the password"; for den that is
+/// the passkey list in settings.
+async fn change_password(State(state): State<AppState>) -> Redirect {
+    Redirect::to(&format!("{}/settings", state.rp_origin))
+}
+
+#[derive(Serialize)]
+struct RelatedOrigins<'a> {
+    origins: &'a [String],
+}
+
+/// WebAuthn related origin requests: browsers fetch this from the RP ID's host
+/// before letting another origin use its passkeys.
+async fn webauthn(State(state): State<AppState>) -> Response {
+    if state.related_origins.is_empty() {
+        return StatusCode::NOT_FOUND.into_response();
+    }
+    Json(RelatedOrigins {
+        origins: &state.related_origins,
+    })
+    .into_response()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn security_txt_defaults_expiry_and_skips_unset_fields() {
+        let config = SecurityTxtConfig {
+            contact: vec!["mailto:security@example.com".into()],
+            preferred_languages: vec!["en".into(), "de".into()],
+            ..Default::default()
+        };
+        let now = OffsetDateTime::from_unix_timestamp(1_767_225_600).unwrap();
+        assert_eq!(
+            render_security_txt(&config, "https://auth.example.com", now),
+            "Contact: mailto:security@example.com\n\
+             Expires: 2027-01-01T00:00:00Z\n\
+             Preferred-Languages: en, de\n\
+             Canonical: https://auth.example.com/.well-known/security.txt\n"
+        );
+    }
+}
@@ -0,0 +1,87 @@
+mod support;
+
+use axum::http::{Method, StatusCode};
+use serde_json::json;
+use support::{Authenticator, RP_ORIGIN, TestApp};
+
+const WIKI_ORIGIN: &str = "http://wiki.localhost:3002";
+
+#[tokio::test]
+async fn runtime_hosts_take_effect_without_a_restart() {
+    let app = TestApp::new().await;
+    let mut key = Authenticator::default();
+    app.register(
+        &mut key,
+        json!({ "user_name": "alice", "passkey_name": "laptop" }),
+    )
+    .await;
+
+    let refused = app
+        .login(&mut key, json!({ "redirect_origin": WIKI_ORIGIN }))
+        .await;
+    assert_eq!(refused.status, StatusCode::BAD_REQUEST);
+
+    let put = app
+        .send(
+            RP_ORIGIN,
+            Method::PUT,
+            "/api/admin/allowed-hosts",
+            Some(json!({ "hosts": [WIKI_ORIGIN, "wiki.localhost:3002"] })),
+        )
+        .await;
+    assert_eq!(put.status, StatusCode::OK);
+    assert_eq!(put.json()["runtime"], json!(["wiki.localhost:3002"]));
+    let listed = app.get(RP_ORIGIN, "/api/admin/allowed-hosts").await.json();
+    assert_eq!(
+        listed["configured"],
+        json!(["app.localhost:3001", "localhost:3000"])
+    );
+    assert_eq!(listed["runtime"], json!(["wiki.localhost:3002"]));
+
+    let login = app
+        .login(&mut key, json!({ "redirect_origin": WIKI_ORIGIN }))
+        .await;
+    assert_eq!(login.status, StatusCode::OK, "{:?}", login.json());
+    let redirect_url = login.json()["redirect_url"].as_str().unwrap().to_owned();
+    assert!(redirect_url.starts_with(WIKI_ORIGIN), "{redirect_url}");
+
+    let cleared = app
+        .send(
+            RP_ORIGIN,
+            Method::PUT,
+            "/api/admin/allowed-hosts",
+            Some(json!({ "hosts": [] })),
+        )
+        .await;
+    assert_eq!(cleared.status, StatusCode::OK);
+    let refused = app
+        .login(&mut key, json!({ "redirect_origin": WIKI_ORIGIN }))
+        .await;
+    assert_eq!(refused.status, StatusCode::BAD_REQUEST);
+}
+
+#[tokio::test]
+async fn runtime_hosts_must_be_hosts_and_need_a_session() {
+    let app = TestApp::new().await;
+    let body = Some(json!({ "hosts": ["wiki.localhost/path"] }));
+    let anonymous = app
+        .send(
+            RP_ORIGIN,
+            Method::PUT,
+            "/api/admin/allowed-hosts",
+            body.clone(),
+        )
+        .await;
+    assert_eq!(anonymous.status, StatusCode::UNAUTHORIZED);
+
+    let mut key = Authenticator::default();
+    app.register(
+        &mut key,
+        json!({ "user_name": "alice", "passkey_name": "laptop" }),
+    )
+    .await;
+    let invalid = app
+        .send(RP_ORIGIN, Method::PUT, "/api/admin/allowed-hosts", body)
+        .await;
+    assert_eq!(invalid.status, StatusCode::BAD_REQUEST);
+}
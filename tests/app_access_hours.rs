@@ -0,0 +1,63 @@
+mod support;
+
+use axum::http::{HeaderName, Method, StatusCode};
+use serde_json::json;
+use support::{APP_ORIGIN, Authenticator, RP_ORIGIN, TestApp, memory_db};
+
+/// An hour-long `access_hours` window starting two hours from now.
+fn closed_now() -> String {
+    let hour = time::OffsetDateTime::now_utc().hour();
+    format!("{:02}:00-{:02}:00", (hour + 2) % 24, (hour + 3) % 24)
+}
+
+#[tokio::test]
+async fn apps_outside_their_access_hours_are_refused_and_audited() {
+    let db = memory_db().await;
+    let app = TestApp::with_db(
+        &format!(
+            "[[apps]]\n\
+             name = \"admin\"\n\
+             origin = \"{APP_ORIGIN}\"\n\
+             access_hours = \"{}\"",
+            closed_now()
+        ),
+        db.clone(),
+    )
+    .await;
+    let mut key = Authenticator::default();
+    app.register(
+        &mut key,
+        json!({ "user_name": "alice", "passkey_name": "laptop" }),
+    )
+    .await;
+    app.clear_cookies();
+
+    let closed = app
+        .login(&mut key, json!({ "redirect_origin": APP_ORIGIN }))
+        .await;
+    assert_eq!(closed.status, StatusCode::FORBIDDEN);
+    assert_eq!(closed.json()["code"], "app_closed");
+
+    // den itself has no window; forward-auth for the app still refuses.
+    assert_eq!(app.login(&mut key, json!({})).await.status, StatusCode::OK);
+    let verify = app
+        .send_with(
+            RP_ORIGIN,
+            Method::GET,
+            "/api/verify",
+            None,
+            &[(
+                HeaderName::from_static("x-forwarded-host"),
+                "app.localhost:3001",
+            )],
+        )
+        .await;
+    assert_eq!(verify.status, StatusCode::FORBIDDEN);
+
+    let denials: i64 =
+        sqlx::query_scalar("SELECT COUNT(*) FROM audit_event WHERE kind = 'access_denied'")
+            .fetch_one(db.pool())
+            .await
+            .unwrap();
+    assert_eq!(denials, 2);
+}
@@ -0,0 +1,42 @@
+mod support;
+
+use std::time::Duration;
+
+use axum::http::{Method, StatusCode};
+use serde_json::{Value, json};
+use support::{Authenticator, RP_ORIGIN, TestApp};
+
+#[tokio::test]
+async fn logins_are_appended_to_the_export_file() {
+    let dir = std::env::temp_dir().join(format!("den-audit-export-{}", std::process::id()));
+    std::fs::create_dir_all(&dir).unwrap();
+    let path = dir.join("audit.jsonl");
+    let app = TestApp::with_config(&format!(
+        "[audit_export]\ntarget = \"file\"\npath = {:?}\n",
+        path.display().to_string()
+    ))
+    .await;
+    let mut key = Authenticator::default();
+    app.register(
+        &mut key,
+        json!({ "user_name": "alice", "passkey_name": "laptop" }),
+    )
+    .await;
+    app.send(RP_ORIGIN, Method::POST, "/api/logout", None).await;
+    let login = app.login(&mut key, json!({})).await;
+    assert_eq!(login.status, StatusCode::OK);
+
+    // Lines are written by the export thread.
+    let mut contents = String::new();
+    for _ in 0..50 {
+        contents = std::fs::read_to_string(&path).unwrap_or_default();
+        if !contents.is_empty() {
+            break;
+        }
+        tokio::time::sleep(Duration::from_millis(20)).await;
+    }
+    let line: Value = serde_json::from_str(contents.lines().next().unwrap()).unwrap();
+    assert_eq!(line["kind"], "login");
+    assert!(line["user_id"].is_string());
+    std::fs::remove_dir_all(&dir).unwrap();
+}
@@ -0,0 +1,99 @@
+mod support;
+
+use std::time::{Duration, SystemTime};
+
+use axum::http::StatusCode;
+use serde_json::json;
+use support::{Authenticator, RP_ORIGIN, TestApp};
+
+const TOKEN: &str = "2f1c9a7e6b4d40c8a53e91d7f0b2c6e8";
+
+#[tokio::test]
+async fn break_glass_token_signs_in_once() {
+    let file = std::env::temp_dir().join(format!("den-break-glass-{}", std::process::id()));
+    let _ = std::fs::remove_file(&file);
+    let app = TestApp::with_config(&format!(
+        "break_glass_token_file = {:?}\n",
+        file.display().to_string()
+    ))
+    .await;
+    app.register(
+        &mut Authenticator::default(),
+        json!({ "user_name": "alice", "passkey_name": "laptop" }),
+    )
+    .await;
+    app.clear_cookies();
+    let login = |token: &'static str| {
+        app.post(
+            RP_ORIGIN,
+            "/api/login/break-glass",
+            json!({ "token": token }),
+        )
+    };
+
+    assert_eq!(login(TOKEN).await.status, StatusCode::UNAUTHORIZED);
+    std::fs::write(&file, "short\n").unwrap();
+    assert_eq!(login("short").await.status, StatusCode::UNAUTHORIZED);
+
+    std::fs::write(&file, format!("{TOKEN}\n")).unwrap();
+    let wrong = login("00000000000000000000000000000000").await;
+    assert_eq!(wrong.status, StatusCode::UNAUTHORIZED);
+    assert_eq!(wrong.json()["code"], "unauthenticated");
+    assert!(file.exists());
+    let accepted = login(TOKEN).await;
+    assert_eq!(accepted.status, StatusCode::OK);
+    assert_eq!(accepted.json()["user_name"], "alice");
+    assert!(!file.exists());
+    assert_eq!(
+        app.get(RP_ORIGIN, "/api/passkeys").await.status,
+        StatusCode::OK
+    );
+    app.clear_cookies();
+    assert_eq!(login(TOKEN).await.status, StatusCode::UNAUTHORIZED);
+
+    // A file left over from long ago is removed, not honored.
+    std::fs::write(&file, TOKEN).unwrap();
+    let old = SystemTime::now() - Duration::from_secs(3600);
+    std::fs::File::options()
+        .write(true)
+        .open(&file)
+        .unwrap()
+        .set_modified(old)
+        .unwrap();
+    assert_eq!(login(TOKEN).await.status, StatusCode::UNAUTHORIZED);
+    assert!(!file.exists());
+}
+
+#[tokio::test]
+async fn break_glass_is_off_without_a_token_file() {
+    let app = TestApp::new().await;
+    let response = app
+        .post(
+            RP_ORIGIN,
+            "/api/login/break-glass",
+            json!({ "token": TOKEN }),
+        )
+        .await;
+    assert_eq!(response.status, StatusCode::NOT_FOUND);
+    assert_eq!(response.json()["code"], "not_found");
+}
+
+#[tokio::test]
+async fn break_glass_needs_a_user_to_sign_in_as() {
+    let file = std::env::temp_dir().join(format!("den-break-glass-empty-{}", std::process::id()));
+    std::fs::write(&file, TOKEN).unwrap();
+    let app = TestApp::with_config(&format!(
+        "break_glass_token_file = {:?}\n",
+        file.display().to_string()
+    ))
+    .await;
+    let response = app
+        .post(
+            RP_ORIGIN,
+            "/api/login/break-glass",
+            json!({ "token": TOKEN }),
+        )
+        .await;
+    assert_eq!(response.status, StatusCode::CONFLICT);
+    assert_eq!(response.json()["code"], "no_user");
+}
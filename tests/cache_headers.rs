@@ -0,0 +1,57 @@
+mod support;
+
+use axum::http::{Method, StatusCode, header};
+use support::{RP_ORIGIN, TestApp};
+
+#[tokio::test]
+async fn auth_responses_are_never_stored() {
+    let app = TestApp::new().await;
+    for path in [
+        "/api/passkeys",
+        "/api/sessions",
+        "/api/health",
+        "/api/missing",
+    ] {
+        let response = app.get(RP_ORIGIN, path).await;
+        assert_eq!(
+            response.headers[header::CACHE_CONTROL],
+            "no-store",
+            "{path}"
+        );
+        assert!(!response.headers.contains_key(header::ETAG), "{path}");
+    }
+}
+
+#[tokio::test]
+async fn public_config_revalidates_by_etag() {
+    let app = TestApp::new().await;
+    let response = app.get(RP_ORIGIN, "/api/config/client").await;
+    assert_eq!(response.status, StatusCode::OK);
+    assert_eq!(
+        response.headers[header::CACHE_CONTROL],
+        "private, max-age=60"
+    );
+    let etag = response.headers[header::ETAG].to_str().unwrap().to_owned();
+
+    let headers = [(header::IF_NONE_MATCH, etag.as_str())];
+    let response = app
+        .send_with(RP_ORIGIN, Method::GET, "/api/config/client", None, &headers)
+        .await;
+    assert_eq!(response.status, StatusCode::NOT_MODIFIED);
+    assert_eq!(response.headers[header::ETAG], etag.as_str());
+    assert!(response.body.is_empty());
+
+    // The catalog varies by language, and so does its tag.
+    let german = [(header::ACCEPT_LANGUAGE, "de")];
+    let de = app
+        .send_with(
+            RP_ORIGIN,
+            Method::GET,
+            "/api/config/i18n/auto",
+            None,
+            &german,
+        )
+        .await;
+    let en = app.get(RP_ORIGIN, "/api/config/i18n/auto").await;
+    assert_ne!(de.headers[header::ETAG], en.headers[header::ETAG]);
+}
@@ -0,0 +1,135 @@
+mod support;
+
+use axum::http::StatusCode;
+use den::signing_key::{KEEP, SigningKey};
+use den::storage::Storage;
+use serde_json::json;
+use support::{Authenticator, RP_ORIGIN, TestApp, memory_db};
+
+#[tokio::test]
+async fn lease_is_held_by_one_instance_until_it_expires() {
+    let storage = Storage::Sqlite(memory_db().await);
+    assert!(storage.acquire_lease("task", "a", 60).await.unwrap());
+    assert!(!storage.acquire_lease("task", "b", 60).await.unwrap());
+    // The holder renews its own lease; other leases are independent.
+    assert!(storage.acquire_lease("task", "a", 60).await.unwrap());
+    assert!(storage.acquire_lease("other", "b", 60).await.unwrap());
+
+    // An expired lease goes to whoever asks next.
+    assert!(storage.acquire_lease("stale", "a", -1).await.unwrap());
+    assert!(storage.acquire_lease("stale", "b", 60).await.unwrap());
+    assert!(!storage.acquire_lease("stale", "a", 60).await.unwrap());
+}
+
+/// What the housekeeping leader does when the newest key is due, `seconds` later.
+async fn rotate(storage: &Storage, seconds: i64) -> SigningKey {
+    let newest = storage.signing_keys(&[]).await.unwrap().remove(0);
+    let key = SigningKey {
+        created: newest.created + seconds,
+        ..SigningKey::generate()
+    };
+    storage.add_signing_key(&key).await.unwrap();
+    key
+}
+
+#[tokio::test]
+async fn rotation_keeps_the_newest_keys() {
+    let storage = Storage::Sqlite(memory_db().await);
+    let first = storage.signing_keys(&[]).await.unwrap();
+    assert_eq!(first.len(), 1);
+    // Every replica on the database loads the same key.
+    assert_eq!(storage.signing_keys(&first).await.unwrap(), first);
+
+    let mut added = Vec::new();
+    for day in 1..=KEEP as i64 {
+        added.push(rotate(&storage, day * 86_400).await.kid);
+    }
+    let kids: Vec<_> = storage
+        .signing_keys(&first)
+        .await
+        .unwrap()
+        .into_iter()
+        .map(|key| key.kid)
+        .collect();
+    added.reverse();
+    assert_eq!(kids, added);
+}
+
+#[tokio::test]
+async fn a_session_outlives_rotation_until_its_key_is_dropped() {
+    let db = memory_db().await;
+    let app = TestApp::with_db("instance_id = \"den-1\"", db.clone()).await;
+    let mut key = Authenticator::default();
+    let registered = app
+        .register(
+            &mut key,
+            json!({ "user_name": "alice", "passkey_name": "laptop" }),
+        )
+        .await;
+    assert_eq!(registered.status, StatusCode::OK);
+    let token = app.cookie(RP_ORIGIN, "den_session").unwrap();
+    let storage = Storage::Sqlite(db.clone());
+    let signed_by = storage.signing_keys(&[]).await.unwrap().remove(0).kid;
+    let header = jsonwebtoken::decode_header(&token).unwrap();
+    assert_eq!(header.kid.as_deref(), Some(signed_by.as_str()));
+
+    // A replica that loaded the keys after a rotation still takes the token.
+    rotate(&storage, 1).await;
+    let replica = TestApp::with_db("", db.clone()).await;
+    replica.set_cookie(RP_ORIGIN, "den_session", &token);
+    assert_eq!(
+        replica.get(RP_ORIGIN, "/api/passkeys").await.status,
+        StatusCode::OK
+    );
+
+    // Once enough rotations drop its key, it is refused.
+    for seconds in 2..=KEEP as i64 {
+        rotate(&storage, seconds).await;
+    }
+    let replica = TestApp::with_db("", db).await;
+    replica.set_cookie(RP_ORIGIN, "den_session", &token);
+    assert_eq!(
+        replica.get(RP_ORIGIN, "/api/passkeys").await.status,
+        StatusCode::UNAUTHORIZED
+    );
+}
+
+#[tokio::test]
+async fn health_names_the_instance() {
+    let app = TestApp::with_config("instance_id = \"den-1\"").await;
+    let health = app.get(RP_ORIGIN, "/api/health").await;
+    assert_eq!(health.status, StatusCode::OK);
+    assert_eq!(health.json()["instance"], "den-1");
+}
+
+#[tokio::test]
+async fn expired_challenges_are_purged_in_batches() {
+    let db = memory_db().await;
+    for i in 0..5 {
+        sqlx::query(
+            "INSERT INTO auth_challenge (id, state, kind, expires_at) \
+             VALUES (?, '{}', 'authentication', datetime('now', '-1 minutes'))",
+        )
+        .bind(format!("expired-{i}"))
+        .execute(db.pool())
+        .await
+        .unwrap();
+    }
+    let storage = Storage::Sqlite(db);
+    storage
+        .insert_challenge("live", "authentication", "browser", "{}", 5)
+        .await
+        .unwrap();
+
+    assert_eq!(storage.purge_expired_challenges(2).await.unwrap(), 2);
+    assert_eq!(storage.purge_expired_challenges(2).await.unwrap(), 2);
+    assert_eq!(storage.purge_expired_challenges(2).await.unwrap(), 1);
+    assert_eq!(storage.purge_expired_challenges(2).await.unwrap(), 0);
+    assert!(
+        storage
+            .take_challenge("live", "authentication", "browser")
+            .await
+            .unwrap()
+            .is_some()
+    );
+}
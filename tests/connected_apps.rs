@@ -0,0 +1,50 @@
+mod support;
+
+use axum::http::{Method, StatusCode};
+use serde_json::json;
+use support::{APP_ORIGIN, Authenticator, RP_ORIGIN, TestApp};
+
+const REVOKE_PATH: &str = "/api/user/apps?origin=http%3A%2F%2Fapp.localhost%3A3001";
+
+async fn hand_off_to_app(app: &TestApp, key: &mut Authenticator) {
+    let login = app
+        .login(key, json!({ "redirect_origin": APP_ORIGIN }))
+        .await;
+    let redirect_url = login.json()["redirect_url"].as_str().unwrap().to_owned();
+    let handoff = app
+        .get(APP_ORIGIN, redirect_url.strip_prefix(APP_ORIGIN).unwrap())
+        .await;
+    assert_eq!(handoff.status, StatusCode::SEE_OTHER);
+}
+
+#[tokio::test]
+async fn redeemed_redirects_are_listed_and_revocable() {
+    let app = TestApp::new().await;
+    let mut key = Authenticator::default();
+    app.register(
+        &mut key,
+        json!({ "user_name": "alice", "passkey_name": "laptop" }),
+    )
+    .await;
+    assert_eq!(app.get(RP_ORIGIN, "/api/user/apps").await.json(), json!([]));
+
+    hand_off_to_app(&app, &mut key).await;
+    hand_off_to_app(&app, &mut key).await;
+    let apps = app.get(RP_ORIGIN, "/api/user/apps").await.json();
+    assert_eq!(apps.as_array().unwrap().len(), 1);
+    assert_eq!(apps[0]["origin"], APP_ORIGIN);
+    assert_eq!(apps[0]["count"], 2);
+    assert!(apps[0]["name"].is_null());
+
+    let revoke = app.send(RP_ORIGIN, Method::DELETE, REVOKE_PATH, None).await;
+    assert_eq!(revoke.status, StatusCode::NO_CONTENT);
+    assert_eq!(app.get(RP_ORIGIN, "/api/user/apps").await.json(), json!([]));
+    // The app host's session is gone; den's own survives.
+    let refresh = app.post(APP_ORIGIN, "/api/refresh", json!({})).await;
+    assert_eq!(refresh.status, StatusCode::UNAUTHORIZED);
+    let refresh = app.post(RP_ORIGIN, "/api/refresh", json!({})).await;
+    assert_eq!(refresh.status, StatusCode::OK);
+
+    let again = app.send(RP_ORIGIN, Method::DELETE, REVOKE_PATH, None).await;
+    assert_eq!(again.status, StatusCode::NOT_FOUND);
+}
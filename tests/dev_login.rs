@@ -0,0 +1,47 @@
+#![cfg(feature = "dev-auth")]
+
+mod support;
+
+use axum::http::StatusCode;
+use serde_json::json;
+use support::{Authenticator, RP_ORIGIN, TestApp};
+
+#[tokio::test]
+async fn dev_password_signs_in_as_the_existing_user() {
+    let app = TestApp::with_config("dev_login_password = \"dev\"").await;
+    let early = app
+        .post(RP_ORIGIN, "/api/dev-login", json!({ "password": "dev" }))
+        .await;
+    assert_eq!(early.status, StatusCode::CONFLICT);
+
+    app.register(
+        &mut Authenticator::default(),
+        json!({ "user_name": "alice", "passkey_name": "laptop" }),
+    )
+    .await;
+    app.clear_cookies();
+
+    let wrong = app
+        .post(RP_ORIGIN, "/api/dev-login", json!({ "password": "nope" }))
+        .await;
+    assert_eq!(wrong.status, StatusCode::UNAUTHORIZED);
+
+    let login = app
+        .post(RP_ORIGIN, "/api/dev-login", json!({ "password": "dev" }))
+        .await;
+    assert_eq!(login.status, StatusCode::OK);
+    assert_eq!(login.json()["user_name"], "alice");
+    assert_eq!(
+        app.get(RP_ORIGIN, "/api/passkeys").await.status,
+        StatusCode::OK
+    );
+}
+
+#[tokio::test]
+async fn dev_login_is_off_without_a_password() {
+    let app = TestApp::new().await;
+    let response = app
+        .post(RP_ORIGIN, "/api/dev-login", json!({ "password": "" }))
+        .await;
+    assert_eq!(response.status, StatusCode::NOT_FOUND);
+}
@@ -0,0 +1,31 @@
+mod support;
+
+use axum::http::StatusCode;
+use serde_json::json;
+use support::{Authenticator, RP_ORIGIN, TestApp};
+
+#[tokio::test]
+async fn diagnostics_report_migrations_without_secrets() {
+    let app = TestApp::with_config("bootstrap_token = \"boot-secret\"").await;
+    let anonymous = app.get(RP_ORIGIN, "/api/admin/diagnostics").await;
+    assert_eq!(anonymous.status, StatusCode::UNAUTHORIZED);
+
+    let mut key = Authenticator::default();
+    app.register(
+        &mut key,
+        json!({
+            "user_name": "alice",
+            "passkey_name": "laptop",
+            "bootstrap_token": "boot-secret",
+        }),
+    )
+    .await;
+    let response = app.get(RP_ORIGIN, "/api/admin/diagnostics").await;
+    assert_eq!(response.status, StatusCode::OK);
+    let diagnostics = response.json();
+    assert_eq!(diagnostics["migrations"]["pending"], json!([]));
+    assert_eq!(diagnostics["migrations"]["failed"], json!([]));
+    assert_eq!(diagnostics["config"]["rp_origin"], RP_ORIGIN);
+    assert_eq!(diagnostics["config"]["bootstrap_token"], true);
+    assert!(!response.json().to_string().contains("boot-secret"));
+}
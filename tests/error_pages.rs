@@ -0,0 +1,36 @@
+mod support;
+
+use axum::http::{Method, StatusCode, header};
+use serde_json::json;
+use support::{RP_ORIGIN, TestApp};
+
+#[tokio::test]
+async fn maintenance_page_is_shown_to_browsers_only() {
+    let page = std::env::temp_dir().join(format!("den-maintenance-{}.html", std::process::id()));
+    std::fs::write(&page, "<h1>Back soon</h1>").unwrap();
+    let app = TestApp::with_config(&format!(
+        "maintenance = true\n\
+         [error_pages]\n\
+         maintenance = {:?}\n",
+        page.display().to_string()
+    ))
+    .await;
+
+    for path in ["/login", "/api/oidc/login"] {
+        let response = app.navigate(RP_ORIGIN, path).await;
+        assert_eq!(response.status, StatusCode::SERVICE_UNAVAILABLE, "{path}");
+        assert_eq!(response.body, "<h1>Back soon</h1>", "{path}");
+        assert_eq!(response.headers[header::CACHE_CONTROL], "no-store");
+        assert_eq!(response.headers[header::RETRY_AFTER], "60");
+    }
+
+    // `fetch` callers keep the bare 503 they already handle.
+    let response = app.post(RP_ORIGIN, "/api/login/begin", json!({})).await;
+    assert_eq!(response.status, StatusCode::SERVICE_UNAVAILABLE);
+    assert!(response.body.is_empty());
+
+    // Signing out still works.
+    let response = app.send(RP_ORIGIN, Method::POST, "/api/logout", None).await;
+    assert_eq!(response.status, StatusCode::OK);
+    std::fs::remove_file(&page).unwrap();
+}
@@ -0,0 +1,69 @@
+mod support;
+
+use axum::http::StatusCode;
+use den::audit;
+use support::{RP_ORIGIN, TestApp, memory_db};
+
+#[tokio::test]
+async fn decoy_hits_are_audited_and_block_the_client() {
+    let db = memory_db().await;
+    // The test peer is loopback; declared as a proxy, it is a client like any other.
+    let app = TestApp::with_db(
+        "trusted_proxies = [\"127.0.0.1\"]\n[honeypot]\ndelay_seconds = 0",
+        db.clone(),
+    )
+    .await;
+    assert_eq!(
+        app.get(RP_ORIGIN, "/api/health").await.status,
+        StatusCode::OK
+    );
+
+    let decoy = app.get(RP_ORIGIN, "/wp-admin/install.php").await;
+    assert_eq!(decoy.status, StatusCode::NOT_FOUND);
+    assert_eq!(
+        app.get(RP_ORIGIN, "/api/health").await.status,
+        StatusCode::FORBIDDEN
+    );
+
+    let hits = audit::honeypot_hits(&db, 60).await.unwrap();
+    assert_eq!(hits.len(), 1);
+    assert_eq!(hits[0].0, "127.0.0.1");
+    assert!(hits[0].1 > 59 * 60, "{}", hits[0].1);
+    let detail: String =
+        sqlx::query_scalar("SELECT detail FROM audit_event WHERE kind = 'honeypot'")
+            .fetch_one(db.pool())
+            .await
+            .unwrap();
+    assert_eq!(detail, "/wp-admin/install.php");
+}
+
+#[tokio::test]
+async fn exempt_networks_are_never_blocked() {
+    let app = TestApp::with_config(
+        "[honeypot]\ndelay_seconds = 0\npaths = [\"/.env\"]\nexempt = [\"127.0.0.1\"]",
+    )
+    .await;
+    app.get(RP_ORIGIN, "/.env").await;
+    assert_eq!(
+        app.get(RP_ORIGIN, "/api/health").await.status,
+        StatusCode::OK
+    );
+}
+
+#[tokio::test]
+async fn undeclared_proxies_are_never_blocked() {
+    let db = memory_db().await;
+    // The test client's loopback peer, left out of `trusted_proxies`.
+    let app = TestApp::with_db(
+        "trusted_proxies = [\"10.0.0.0/8\"]\n[honeypot]\ndelay_seconds = 0",
+        db.clone(),
+    )
+    .await;
+    let decoy = app.get(RP_ORIGIN, "/.env").await;
+    assert_eq!(decoy.status, StatusCode::NOT_FOUND);
+    assert_eq!(
+        app.get(RP_ORIGIN, "/api/health").await.status,
+        StatusCode::OK
+    );
+    assert!(audit::honeypot_hits(&db, 60).await.unwrap().is_empty());
+}
@@ -0,0 +1,107 @@
+mod support;
+
+use axum::http::StatusCode;
+use serde_json::json;
+use support::{APP_ORIGIN, Authenticator, RP_ORIGIN, TestApp};
+
+#[tokio::test]
+async fn registration_hook_vetoes_before_the_passkey_is_stored() {
+    let app = TestApp::with_config(
+        "[[hooks]]\n\
+         event = \"registration\"\n\
+         command = [\"sh\", \"-c\", \"! grep -q '\\\"passkey_name\\\":\\\"shared\\\"'\"]",
+    )
+    .await;
+    let mut key = Authenticator::default();
+    let vetoed = app
+        .register(
+            &mut key,
+            json!({ "user_name": "alice", "passkey_name": "shared" }),
+        )
+        .await;
+    assert_eq!(vetoed.status, StatusCode::FORBIDDEN);
+    assert_eq!(vetoed.json()["code"], "hook_denied");
+    let status = app.get(RP_ORIGIN, "/api/setup/status").await.json();
+    assert_eq!(status["setup_complete"], false);
+
+    let mut key = Authenticator::default();
+    let allowed = app
+        .register(
+            &mut key,
+            json!({ "user_name": "alice", "passkey_name": "laptop" }),
+        )
+        .await;
+    assert_eq!(allowed.status, StatusCode::OK);
+
+    // A vetoed invited passkey leaves the invite usable.
+    let invite = app.post(RP_ORIGIN, "/api/passkeys/invite", json!({})).await;
+    let url = invite.json()["url"].as_str().unwrap().to_owned();
+    let token = url.split_once("/invite?token=").unwrap().1;
+    app.clear_cookies();
+    for (name, expected) in [("shared", StatusCode::FORBIDDEN), ("phone", StatusCode::OK)] {
+        let mut spare = Authenticator::default();
+        let registered = app
+            .register(
+                &mut spare,
+                json!({ "passkey_name": name, "invite_token": token }),
+            )
+            .await;
+        assert_eq!(registered.status, expected, "{name}");
+    }
+}
+
+#[tokio::test]
+async fn session_hook_vetoes_sign_ins() {
+    let lock = std::env::temp_dir().join(format!("den-hook-lock-{}", std::process::id()));
+    let app = TestApp::with_config(&format!(
+        "[[hooks]]\n\
+         event = \"session\"\n\
+         command = [\"sh\", \"-c\", \"test ! -e {}\"]",
+        lock.display()
+    ))
+    .await;
+    let mut key = Authenticator::default();
+    app.register(
+        &mut key,
+        json!({ "user_name": "alice", "passkey_name": "laptop" }),
+    )
+    .await;
+    app.clear_cookies();
+    assert_eq!(app.login(&mut key, json!({})).await.status, StatusCode::OK);
+
+    app.clear_cookies();
+    std::fs::write(&lock, "").unwrap();
+    let vetoed = app.login(&mut key, json!({})).await;
+    std::fs::remove_file(&lock).unwrap();
+    assert_eq!(vetoed.status, StatusCode::FORBIDDEN);
+    assert_eq!(vetoed.json()["code"], "hook_denied");
+    assert_eq!(
+        app.get(RP_ORIGIN, "/api/sessions").await.status,
+        StatusCode::UNAUTHORIZED
+    );
+}
+
+#[tokio::test]
+async fn redirect_hook_vetoes_handing_a_login_to_an_app() {
+    let app = TestApp::with_config(
+        "[[hooks]]\n\
+         event = \"redirect\"\n\
+         command = [\"sh\", \"-c\", \"! grep -q app.localhost\"]",
+    )
+    .await;
+    let mut key = Authenticator::default();
+    app.register(
+        &mut key,
+        json!({ "user_name": "alice", "passkey_name": "laptop" }),
+    )
+    .await;
+    app.clear_cookies();
+    assert_eq!(app.login(&mut key, json!({})).await.status, StatusCode::OK);
+
+    app.clear_cookies();
+    let vetoed = app
+        .login(&mut key, json!({ "redirect_origin": APP_ORIGIN }))
+        .await;
+    assert_eq!(vetoed.status, StatusCode::FORBIDDEN);
+    assert_eq!(vetoed.json()["code"], "hook_denied");
+}
@@ -0,0 +1,82 @@
+mod support;
+
+use axum::http::{Method, StatusCode};
+use den::diagnostics::reloadable_filter;
+use serde_json::json;
+use support::{Authenticator, RP_ORIGIN, TestApp};
+use tracing_subscriber::EnvFilter;
+use tracing_subscriber::layer::SubscriberExt;
+use tracing_subscriber::util::SubscriberInitExt;
+
+#[tokio::test]
+async fn log_filter_changes_without_a_restart() {
+    tracing_subscriber::registry()
+        .with(reloadable_filter(EnvFilter::new("info")))
+        .init();
+    let app = TestApp::with_config("[log_presets]\npasskeys = \"info,webauthn_rs=debug\"").await;
+    let anonymous = app
+        .send(
+            RP_ORIGIN,
+            Method::PUT,
+            "/api/admin/log-level",
+            Some(json!({ "filter": "debug" })),
+        )
+        .await;
+    assert_eq!(anonymous.status, StatusCode::UNAUTHORIZED);
+
+    let mut key = Authenticator::default();
+    app.register(
+        &mut key,
+        json!({ "user_name": "alice", "passkey_name": "laptop" }),
+    )
+    .await;
+    let current = app.get(RP_ORIGIN, "/api/admin/log-level").await;
+    assert_eq!(current.status, StatusCode::OK);
+    assert_eq!(current.json()["filter"], "info");
+    assert_eq!(
+        current.json()["presets"]["passkeys"],
+        "info,webauthn_rs=debug"
+    );
+
+    let put = app
+        .send(
+            RP_ORIGIN,
+            Method::PUT,
+            "/api/admin/log-level",
+            Some(json!({ "filter": "info,den::api::auth=debug" })),
+        )
+        .await;
+    assert_eq!(put.status, StatusCode::OK);
+    let filter = app.get(RP_ORIGIN, "/api/admin/log-level").await.json()["filter"]
+        .as_str()
+        .unwrap()
+        .to_owned();
+    assert!(filter.contains("den::api::auth=debug"), "{filter}");
+    assert!(tracing::enabled!(target: "den::api::auth", tracing::Level::DEBUG));
+
+    let preset = app
+        .send(
+            RP_ORIGIN,
+            Method::PUT,
+            "/api/admin/log-level",
+            Some(json!({ "filter": "sql-trace" })),
+        )
+        .await;
+    assert_eq!(preset.status, StatusCode::OK);
+    assert!(
+        preset.json()["filter"]
+            .as_str()
+            .unwrap()
+            .contains("sqlx::query=trace")
+    );
+
+    let invalid = app
+        .send(
+            RP_ORIGIN,
+            Method::PUT,
+            "/api/admin/log-level",
+            Some(json!({ "filter": "den=loud" })),
+        )
+        .await;
+    assert_eq!(invalid.status, StatusCode::BAD_REQUEST);
+}
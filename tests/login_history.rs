@@ -0,0 +1,46 @@
+mod support;
+
+use axum::http::StatusCode;
+use serde_json::json;
+use support::{APP_ORIGIN, Authenticator, RP_ORIGIN, TestApp};
+
+#[tokio::test]
+async fn logins_are_listed_newest_first_in_pages() {
+    let app = TestApp::new().await;
+    let anonymous = app.get(RP_ORIGIN, "/api/user/logins").await;
+    assert_eq!(anonymous.status, StatusCode::UNAUTHORIZED);
+
+    let mut key = Authenticator::default();
+    app.register(
+        &mut key,
+        json!({ "user_name": "alice", "passkey_name": "laptop" }),
+    )
+    .await;
+    let login = app.login(&mut key, json!({})).await;
+    assert_eq!(login.status, StatusCode::OK);
+    let login = app
+        .login(&mut key, json!({ "redirect_origin": APP_ORIGIN }))
+        .await;
+    assert_eq!(login.status, StatusCode::OK);
+
+    let all = app.get(RP_ORIGIN, "/api/user/logins").await.json();
+    let logins = all["logins"].as_array().unwrap();
+    assert_eq!(logins.len(), 2);
+    assert_eq!(logins[0]["kind"], "login");
+    assert_eq!(logins[0]["app"], APP_ORIGIN);
+    assert!(logins[1]["app"].is_null());
+    assert!(all["next_before"].is_null());
+
+    let first = app.get(RP_ORIGIN, "/api/user/logins?limit=1").await.json();
+    assert_eq!(first["logins"][0]["id"], logins[0]["id"]);
+    let cursor = first["next_before"].as_i64().unwrap();
+    let second = app
+        .get(
+            RP_ORIGIN,
+            &format!("/api/user/logins?limit=1&before={cursor}"),
+        )
+        .await
+        .json();
+    assert_eq!(second["logins"][0]["id"], logins[1]["id"]);
+    assert!(second["next_before"].is_null());
+}
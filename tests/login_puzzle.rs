@@ -0,0 +1,64 @@
+mod support;
+
+use axum::http::StatusCode;
+use den::puzzle::solves;
+use serde_json::json;
+use support::{Authenticator, RP_ORIGIN, TestApp};
+
+#[tokio::test]
+async fn login_begin_wants_a_solved_puzzle_once() {
+    let app = TestApp::with_config("[login_puzzle]\ndifficulty = 8").await;
+    let mut key = Authenticator::default();
+    app.register(
+        &mut key,
+        json!({ "user_name": "alice", "passkey_name": "laptop" }),
+    )
+    .await;
+    app.clear_cookies();
+
+    let unsolved = app.post(RP_ORIGIN, "/api/login/begin", json!({})).await;
+    assert_eq!(unsolved.status, StatusCode::PRECONDITION_REQUIRED);
+    assert_eq!(unsolved.json()["code"], "login_puzzle_required");
+
+    let puzzle = app.get(RP_ORIGIN, "/api/login/puzzle").await.json();
+    assert_eq!(puzzle["difficulty"], 8);
+    let token = puzzle["token"].as_str().unwrap();
+    let nonce = |solved: bool| {
+        (0u64..)
+            .map(|n| n.to_string())
+            .find(|nonce| solves(token, nonce, 8) == solved)
+            .unwrap()
+    };
+    let solved = json!({ "puzzle": token, "nonce": nonce(true) });
+    let wrong = json!({ "puzzle": token, "nonce": nonce(false) });
+    assert_eq!(
+        app.post(RP_ORIGIN, "/api/login/begin", wrong).await.status,
+        StatusCode::PRECONDITION_REQUIRED
+    );
+
+    assert_eq!(
+        app.login(&mut key, solved.clone()).await.status,
+        StatusCode::OK
+    );
+    let replayed = app.post(RP_ORIGIN, "/api/login/begin", solved).await;
+    assert_eq!(replayed.status, StatusCode::PRECONDITION_REQUIRED);
+}
+
+#[tokio::test]
+async fn exempt_networks_skip_the_puzzle() {
+    let app = TestApp::with_config("[login_puzzle]\nexempt = [\"127.0.0.0/8\"]").await;
+    let mut key = Authenticator::default();
+    app.register(
+        &mut key,
+        json!({ "user_name": "alice", "passkey_name": "laptop" }),
+    )
+    .await;
+    app.clear_cookies();
+    assert_eq!(app.login(&mut key, json!({})).await.status, StatusCode::OK);
+
+    let off = TestApp::new().await;
+    assert_eq!(
+        off.get(RP_ORIGIN, "/api/login/puzzle").await.status,
+        StatusCode::NOT_FOUND
+    );
+}
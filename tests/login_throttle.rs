@@ -0,0 +1,65 @@
+mod support;
+
+use axum::http::StatusCode;
+use serde_json::json;
+use support::{Authenticator, RP_ORIGIN, TestApp};
+
+#[tokio::test]
+async fn repeated_failures_are_refused_with_retry_after() {
+    let file = std::env::temp_dir().join(format!("den-throttle-{}", std::process::id()));
+    let app = TestApp::with_config(&format!(
+        "break_glass_token_file = {:?}\n[login_throttle]\nmax_failures = 2\nwindow_minutes = 15\n",
+        file.display().to_string()
+    ))
+    .await;
+    let mut key = Authenticator::default();
+    app.register(
+        &mut key,
+        json!({ "user_name": "alice", "passkey_name": "laptop" }),
+    )
+    .await;
+    let stats = app.get(RP_ORIGIN, "/api/admin/stats").await.json();
+    assert_eq!(stats["throttled_clients"], 0);
+
+    // Any failed sign-in counts, whichever method it used.
+    for _ in 0..2 {
+        let miss = app
+            .post(
+                RP_ORIGIN,
+                "/api/login/break-glass",
+                json!({ "token": "wrong" }),
+            )
+            .await;
+        assert_eq!(miss.status, StatusCode::UNAUTHORIZED);
+    }
+    let stats = app.get(RP_ORIGIN, "/api/admin/stats").await.json();
+    assert_eq!(stats["throttled_clients"], 1);
+    app.clear_cookies();
+
+    let refused = app.login(&mut key, json!({})).await;
+    assert_eq!(refused.status, StatusCode::TOO_MANY_REQUESTS);
+    let body = refused.json();
+    assert_eq!(body["code"], "login_throttled");
+    assert_eq!(body["retryable"], true);
+    let seconds = body["retry_after"].as_u64().unwrap();
+    assert!((1..=15 * 60).contains(&seconds), "{seconds}");
+    assert_eq!(
+        refused.headers["retry-after"].to_str().unwrap(),
+        seconds.to_string()
+    );
+
+    // Break-glass is refused before its token is read, so the file stays for later.
+    let token = "2f1c9a7e6b4d40c8a53e91d7f0b2c6e8";
+    std::fs::write(&file, token).unwrap();
+    let break_glass = app
+        .post(
+            RP_ORIGIN,
+            "/api/login/break-glass",
+            json!({ "token": token }),
+        )
+        .await;
+    assert_eq!(break_glass.status, StatusCode::TOO_MANY_REQUESTS);
+    assert_eq!(break_glass.json()["code"], "login_throttled");
+    assert!(file.exists());
+    let _ = std::fs::remove_file(&file);
+}
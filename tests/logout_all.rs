@@ -0,0 +1,87 @@
+mod support;
+
+use axum::http::StatusCode;
+use serde_json::json;
+use support::{APP_ORIGIN, Authenticator, RP_ORIGIN, TestApp};
+
+#[tokio::test]
+async fn logout_all_revokes_sessions_on_every_host() {
+    let app = TestApp::new().await;
+    let mut key = Authenticator::default();
+    app.register(
+        &mut key,
+        json!({ "user_name": "alice", "passkey_name": "laptop" }),
+    )
+    .await;
+    let login = app
+        .login(&mut key, json!({ "redirect_origin": APP_ORIGIN }))
+        .await;
+    let redirect_url = login.json()["redirect_url"].as_str().unwrap().to_owned();
+    let handoff = app
+        .get(APP_ORIGIN, redirect_url.strip_prefix(APP_ORIGIN).unwrap())
+        .await;
+    assert_eq!(handoff.status, StatusCode::SEE_OTHER);
+    assert!(app.cookie(APP_ORIGIN, "den_session").is_some());
+
+    let logout = app.post(RP_ORIGIN, "/api/logout/all", json!({})).await;
+    assert_eq!(logout.status, StatusCode::OK);
+    let logout = logout.json();
+    assert_eq!(logout["revoked"], 3);
+    let urls = logout["frontchannel_urls"].as_array().unwrap();
+    assert_eq!(urls.len(), 1);
+    let frontchannel_path = urls[0]
+        .as_str()
+        .unwrap()
+        .strip_prefix(APP_ORIGIN)
+        .unwrap()
+        .to_owned();
+    assert!(frontchannel_path.starts_with("/api/logout/frontchannel?token="));
+    assert!(app.cookie(RP_ORIGIN, "den_session").is_none());
+
+    // The app host's refresh token is already dead server-side.
+    let refresh = app.post(APP_ORIGIN, "/api/refresh", json!({})).await;
+    assert_eq!(refresh.status, StatusCode::UNAUTHORIZED);
+
+    let frontchannel = app.get(APP_ORIGIN, &frontchannel_path).await;
+    assert_eq!(frontchannel.status, StatusCode::OK);
+    assert!(app.cookie(APP_ORIGIN, "den_session").is_none());
+    assert!(app.cookie(APP_ORIGIN, "den_session_refresh").is_none());
+    let replayed = app.get(APP_ORIGIN, &frontchannel_path).await;
+    assert_eq!(replayed.status, StatusCode::FORBIDDEN);
+}
+
+#[tokio::test]
+async fn frontchannel_logout_needs_a_token() {
+    let app = TestApp::new().await;
+    let mut key = Authenticator::default();
+    app.register(
+        &mut key,
+        json!({ "user_name": "alice", "passkey_name": "laptop" }),
+    )
+    .await;
+    let login = app
+        .login(&mut key, json!({ "redirect_origin": APP_ORIGIN }))
+        .await;
+    let redirect_url = login.json()["redirect_url"].as_str().unwrap().to_owned();
+    app.get(APP_ORIGIN, redirect_url.strip_prefix(APP_ORIGIN).unwrap())
+        .await;
+
+    // What a cross-site `<img>` can send: no token, or a made-up one.
+    for path in [
+        "/api/logout/frontchannel",
+        "/api/logout/frontchannel?token=forged",
+    ] {
+        let frontchannel = app.get(APP_ORIGIN, path).await;
+        assert_eq!(frontchannel.status, StatusCode::FORBIDDEN);
+    }
+    assert!(app.cookie(APP_ORIGIN, "den_session_refresh").is_some());
+    let refresh = app.post(APP_ORIGIN, "/api/refresh", json!({})).await;
+    assert_eq!(refresh.status, StatusCode::OK);
+}
+
+#[tokio::test]
+async fn logout_all_needs_a_session() {
+    let app = TestApp::new().await;
+    let logout = app.post(RP_ORIGIN, "/api/logout/all", json!({})).await;
+    assert_eq!(logout.status, StatusCode::UNAUTHORIZED);
+}
@@ -0,0 +1,60 @@
+#![cfg(feature = "lua-policy")]
+
+mod support;
+
+use axum::http::StatusCode;
+use serde_json::json;
+use support::{Authenticator, RP_ORIGIN, TestApp};
+
+#[tokio::test]
+async fn script_functions_veto_their_events() {
+    let script = std::env::temp_dir().join(format!("den-policy-{}.lua", std::process::id()));
+    std::fs::write(
+        &script,
+        "function on_register(ctx)\n\
+           if ctx.passkey_name == 'shared' then return 'deny', 'no shared passkeys' end\n\
+         end\n\
+         function on_login(ctx)\n\
+           if ctx.user_agent == 'blocked' then return 'deny' end\n\
+         end\n",
+    )
+    .unwrap();
+    let app = TestApp::with_config(&format!(
+        "[lua_policy]\nscript = {:?}",
+        script.display().to_string()
+    ))
+    .await;
+
+    let mut key = Authenticator::default();
+    let vetoed = app
+        .register(
+            &mut key,
+            json!({ "user_name": "alice", "passkey_name": "shared" }),
+        )
+        .await;
+    assert_eq!(vetoed.status, StatusCode::FORBIDDEN);
+    assert_eq!(vetoed.json()["code"], "hook_denied");
+
+    let mut key = Authenticator::default();
+    app.register(
+        &mut key,
+        json!({ "user_name": "alice", "passkey_name": "laptop" }),
+    )
+    .await;
+    app.clear_cookies();
+    assert_eq!(app.login(&mut key, json!({})).await.status, StatusCode::OK);
+
+    // Edits apply without a restart.
+    std::fs::write(&script, "function on_login(ctx) return 'deny' end").unwrap();
+    let file = std::fs::File::options().write(true).open(&script).unwrap();
+    file.set_modified(std::time::SystemTime::now() + std::time::Duration::from_secs(5))
+        .unwrap();
+    app.clear_cookies();
+    let denied = app.login(&mut key, json!({})).await;
+    std::fs::remove_file(&script).unwrap();
+    assert_eq!(denied.status, StatusCode::FORBIDDEN);
+    assert_eq!(
+        app.get(RP_ORIGIN, "/api/sessions").await.status,
+        StatusCode::UNAUTHORIZED
+    );
+}
@@ -0,0 +1,44 @@
+mod support;
+
+use axum::http::{Method, StatusCode, header};
+use serde_json::json;
+use support::{Authenticator, RP_ORIGIN, TestApp};
+
+#[tokio::test]
+async fn metrics_label_matched_routes_with_exemplars() {
+    let app = TestApp::with_config("[slo]\nobjective = 0.99").await;
+    let anonymous = app.get(RP_ORIGIN, "/api/admin/metrics").await;
+    assert_eq!(anonymous.status, StatusCode::UNAUTHORIZED);
+
+    let mut key = Authenticator::default();
+    app.register(
+        &mut key,
+        json!({ "user_name": "alice", "passkey_name": "laptop" }),
+    )
+    .await;
+    let missing = app
+        .send(RP_ORIGIN, Method::DELETE, "/api/passkeys/999", None)
+        .await;
+    assert_eq!(missing.status, StatusCode::NOT_FOUND);
+
+    let response = app.get(RP_ORIGIN, "/api/admin/metrics").await;
+    assert_eq!(response.status, StatusCode::OK);
+    assert!(
+        response.headers[header::CONTENT_TYPE]
+            .to_str()
+            .unwrap()
+            .starts_with("application/openmetrics-text")
+    );
+    let text = std::str::from_utf8(&response.body).unwrap();
+    assert!(text.contains(
+        "den_http_responses_total{route=\"/api/passkeys/{id}\",method=\"DELETE\",class=\"4xx\"} 1"
+    ));
+    assert!(!text.contains("/api/passkeys/999"));
+    let bucket = text
+        .lines()
+        .find(|l| l.contains("route=\"/api/passkeys/{id}\"") && l.contains(" # {trace_id="))
+        .expect("exemplar on the DELETE bucket");
+    assert!(bucket.starts_with("den_http_request_duration_seconds_bucket{"));
+    assert!(text.contains("den_slo_objective 0.99\n"));
+    assert!(text.ends_with("# EOF\n"));
+}
@@ -0,0 +1,72 @@
+mod support;
+
+use axum::http::StatusCode;
+use serde_json::json;
+use support::{Authenticator, RP_ORIGIN, TestApp, passkey_names};
+
+#[tokio::test]
+async fn bulk_changes_apply_together_or_not_at_all() {
+    let app = TestApp::new().await;
+    let mut key = Authenticator::default();
+    app.register(
+        &mut key,
+        json!({ "user_name": "alice", "passkey_name": "laptop" }),
+    )
+    .await;
+    let invites = app
+        .post(RP_ORIGIN, "/api/passkeys/invites", json!({ "count": 2 }))
+        .await
+        .json();
+    app.clear_cookies();
+    for (i, invite) in invites.as_array().unwrap().iter().enumerate() {
+        let url = invite["url"].as_str().unwrap();
+        let token = url.split_once("/invite?token=").unwrap().1;
+        let body = json!({ "user_name": "alice", "passkey_name": format!("key {i}"), "invite_token": token });
+        app.register(&mut Authenticator::default(), body).await;
+        app.clear_cookies();
+    }
+    app.login(&mut key, json!({})).await;
+    let passkeys = app.get(RP_ORIGIN, "/api/passkeys").await.json();
+    let ids: Vec<i64> = passkeys
+        .as_array()
+        .unwrap()
+        .iter()
+        .map(|p| p["id"].as_i64().unwrap())
+        .collect();
+
+    let everything = app
+        .post(RP_ORIGIN, "/api/passkeys/bulk", json!({ "delete": ids }))
+        .await;
+    assert_eq!(everything.status, StatusCode::BAD_REQUEST);
+    assert_eq!(everything.json()["code"], "last_passkey");
+
+    let unknown = app
+        .post(
+            RP_ORIGIN,
+            "/api/passkeys/bulk",
+            json!({ "rename": [{ "id": ids[0], "name": "desktop" }], "delete": [ids[1], 9999] }),
+        )
+        .await;
+    assert_eq!(unknown.status, StatusCode::NOT_FOUND);
+    let repeated = app
+        .post(
+            RP_ORIGIN,
+            "/api/passkeys/bulk",
+            json!({ "rename": [{ "id": ids[1], "name": "desktop" }], "delete": [ids[1]] }),
+        )
+        .await;
+    assert_eq!(repeated.status, StatusCode::BAD_REQUEST);
+    let unchanged = app.get(RP_ORIGIN, "/api/passkeys").await.json();
+    assert_eq!(passkey_names(&unchanged), ["laptop", "key 0", "key 1"]);
+
+    let applied = app
+        .post(
+            RP_ORIGIN,
+            "/api/passkeys/bulk",
+            json!({ "rename": [{ "id": ids[0], "name": "desktop" }], "delete": [ids[1], ids[2]] }),
+        )
+        .await;
+    assert_eq!(applied.status, StatusCode::NO_CONTENT);
+    let left = app.get(RP_ORIGIN, "/api/passkeys").await.json();
+    assert_eq!(passkey_names(&left), ["desktop"]);
+}
@@ -0,0 +1,100 @@
+mod support;
+
+use axum::http::{Method, StatusCode};
+use serde_json::json;
+use support::{Authenticator, RP_ORIGIN, TestApp, memory_db};
+
+#[tokio::test]
+async fn expired_temporary_passkeys_cannot_sign_in() {
+    let db = memory_db().await;
+    let app = TestApp::with_db("", db.clone()).await;
+    let mut key = Authenticator::default();
+    app.register(
+        &mut key,
+        json!({ "user_name": "alice", "passkey_name": "laptop" }),
+    )
+    .await;
+    let mut borrowed = Authenticator::default();
+    app.register(&mut borrowed, json!({ "passkey_name": "borrowed" }))
+        .await;
+
+    let passkeys = app.get(RP_ORIGIN, "/api/passkeys").await.json();
+    let id = passkeys[1]["id"].as_i64().unwrap();
+    let path = format!("/api/passkeys/{id}/expiry");
+    let marked = app
+        .send(
+            RP_ORIGIN,
+            Method::PUT,
+            &path,
+            Some(json!({ "expires_in_days": 7 })),
+        )
+        .await;
+    assert_eq!(marked.status, StatusCode::OK);
+    assert!(marked.json()["expires_at"].is_string());
+    let passkeys = app.get(RP_ORIGIN, "/api/passkeys").await.json();
+    assert_eq!(passkeys[1]["temporary"], true);
+    assert!(passkeys[0]["expires_at"].is_null());
+    let stats = app.get(RP_ORIGIN, "/api/admin/stats").await.json();
+    assert_eq!(stats["passkeys_expiring"], 1);
+    assert_eq!(stats["passkeys_expired"], 0);
+
+    sqlx::query("UPDATE passkey SET expires_at = datetime('now', '-1 minutes') WHERE id = ?")
+        .bind(id)
+        .execute(db.pool())
+        .await
+        .unwrap();
+    let stats = app.get(RP_ORIGIN, "/api/admin/stats").await.json();
+    assert_eq!(stats["passkeys_expired"], 1);
+
+    // The expired passkey doesn't count as one left to sign in with.
+    let only = passkeys[0]["id"].as_i64().unwrap();
+    let refused = app
+        .send(
+            RP_ORIGIN,
+            Method::DELETE,
+            &format!("/api/passkeys/{only}"),
+            None,
+        )
+        .await;
+    assert_eq!(refused.json()["code"], "last_passkey");
+
+    app.send(RP_ORIGIN, Method::POST, "/api/logout", None).await;
+    let rejected = app.login(&mut borrowed, json!({})).await;
+    assert_eq!(rejected.status, StatusCode::UNAUTHORIZED);
+    assert_eq!(rejected.json()["code"], "passkey_expired");
+    let login = app.login(&mut key, json!({})).await;
+    assert_eq!(login.status, StatusCode::OK);
+
+    let permanent = app
+        .send(
+            RP_ORIGIN,
+            Method::PUT,
+            &path,
+            Some(json!({ "expires_in_days": null })),
+        )
+        .await;
+    assert!(permanent.json()["expires_at"].is_null());
+}
+
+#[tokio::test]
+async fn passkeys_past_max_age_must_be_rotated() {
+    let db = memory_db().await;
+    let app = TestApp::with_db("[passkeys]\nmax_age_days = 30", db.clone()).await;
+    let mut key = Authenticator::default();
+    app.register(
+        &mut key,
+        json!({ "user_name": "alice", "passkey_name": "laptop" }),
+    )
+    .await;
+    let passkeys = app.get(RP_ORIGIN, "/api/passkeys").await.json();
+    assert_eq!(passkeys[0]["temporary"], false);
+    assert!(passkeys[0]["expires_at"].is_string());
+
+    sqlx::query("UPDATE passkey SET created = datetime('now', '-31 days')")
+        .execute(db.pool())
+        .await
+        .unwrap();
+    app.send(RP_ORIGIN, Method::POST, "/api/logout", None).await;
+    let rejected = app.login(&mut key, json!({})).await;
+    assert_eq!(rejected.json()["code"], "passkey_expired");
+}
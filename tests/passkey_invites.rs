@@ -0,0 +1,62 @@
+mod support;
+
+use axum::http::{Method, StatusCode};
+use serde_json::json;
+use support::{Authenticator, RP_ORIGIN, TestApp};
+
+#[tokio::test]
+async fn batch_invites_enroll_several_keys_and_can_be_revoked() {
+    let app = TestApp::new().await;
+    let mut key = Authenticator::default();
+    app.register(
+        &mut key,
+        json!({ "user_name": "alice", "passkey_name": "laptop" }),
+    )
+    .await;
+    let too_many = app
+        .post(RP_ORIGIN, "/api/passkeys/invites", json!({ "count": 21 }))
+        .await;
+    assert_eq!(too_many.status, StatusCode::BAD_REQUEST);
+
+    let minted = app
+        .post(RP_ORIGIN, "/api/passkeys/invites", json!({ "count": 3 }))
+        .await
+        .json();
+    let minted = minted.as_array().unwrap();
+    assert_eq!(minted.len(), 3);
+    let pending = app.get(RP_ORIGIN, "/api/passkeys/invites").await.json();
+    assert_eq!(pending.as_array().unwrap().len(), 3);
+    assert!(pending[0].get("url").is_none());
+
+    let revoked = format!("/api/passkeys/invites/{}", minted[2]["id"]);
+    let response = app.send(RP_ORIGIN, Method::DELETE, &revoked, None).await;
+    assert_eq!(response.status, StatusCode::NO_CONTENT);
+    let again = app.send(RP_ORIGIN, Method::DELETE, &revoked, None).await;
+    assert_eq!(again.status, StatusCode::NOT_FOUND);
+
+    app.clear_cookies();
+    for (i, invite) in minted.iter().enumerate() {
+        let url = invite["url"].as_str().unwrap();
+        let token = url.split_once("/invite?token=").unwrap().1;
+        let mut spare = Authenticator::default();
+        let registered = app
+            .register(
+                &mut spare,
+                json!({ "user_name": "alice", "passkey_name": format!("key {i}"), "invite_token": token }),
+            )
+            .await;
+        let expected = if i < 2 {
+            StatusCode::OK
+        } else {
+            StatusCode::FORBIDDEN
+        };
+        assert_eq!(registered.status, expected, "{:?}", registered.json());
+        app.clear_cookies();
+    }
+
+    app.login(&mut key, json!({})).await;
+    let passkeys = app.get(RP_ORIGIN, "/api/passkeys").await.json();
+    assert_eq!(passkeys.as_array().unwrap().len(), 3);
+    let pending = app.get(RP_ORIGIN, "/api/passkeys/invites").await.json();
+    assert_eq!(pending, json!([]));
+}
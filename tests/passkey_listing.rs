@@ -0,0 +1,50 @@
+mod support;
+
+use axum::http::StatusCode;
+use serde_json::json;
+use support::{Authenticator, RP_ORIGIN, TestApp, passkey_names};
+
+#[tokio::test]
+async fn passkeys_page_sort_and_filter_with_a_total() {
+    let app = TestApp::new().await;
+    let mut key = Authenticator::default();
+    app.register(
+        &mut key,
+        json!({ "user_name": "alice", "passkey_name": "laptop" }),
+    )
+    .await;
+    let invites = app
+        .post(RP_ORIGIN, "/api/passkeys/invites", json!({ "count": 2 }))
+        .await
+        .json();
+    app.clear_cookies();
+    for (invite, name) in invites.as_array().unwrap().iter().zip(["Phone", "yubikey"]) {
+        let url = invite["url"].as_str().unwrap();
+        let token = url.split_once("/invite?token=").unwrap().1;
+        let body = json!({ "user_name": "alice", "passkey_name": name, "invite_token": token });
+        let registered = app.register(&mut Authenticator::default(), body).await;
+        assert_eq!(registered.status, StatusCode::OK);
+        app.clear_cookies();
+    }
+    app.login(&mut key, json!({})).await;
+
+    let all = app.get(RP_ORIGIN, "/api/passkeys?sort=name").await;
+    assert_eq!(passkey_names(&all.json()), ["laptop", "Phone", "yubikey"]);
+    assert_eq!(all.headers["x-total-count"], "3");
+
+    let page = app
+        .get(
+            RP_ORIGIN,
+            "/api/passkeys?sort=name&order=desc&limit=1&offset=1",
+        )
+        .await;
+    assert_eq!(passkey_names(&page.json()), ["Phone"]);
+    assert_eq!(page.headers["x-total-count"], "3");
+
+    let filtered = app.get(RP_ORIGIN, "/api/passkeys?q=KEY").await;
+    assert_eq!(passkey_names(&filtered.json()), ["yubikey"]);
+    assert_eq!(filtered.headers["x-total-count"], "1");
+
+    let invalid = app.get(RP_ORIGIN, "/api/passkeys?sort=color").await;
+    assert_eq!(invalid.status, StatusCode::BAD_REQUEST);
+}
@@ -0,0 +1,138 @@
+mod support;
+
+use axum::http::{Method, StatusCode};
+use serde_json::json;
+use support::{Authenticator, RP_ORIGIN, TestApp};
+
+#[tokio::test]
+async fn register_then_log_in_with_the_same_passkey() {
+    let app = TestApp::new().await;
+    let mut key = Authenticator::default();
+
+    let registered = app
+        .register(
+            &mut key,
+            json!({ "user_name": "alice", "passkey_name": "laptop" }),
+        )
+        .await;
+    assert_eq!(registered.status, StatusCode::OK, "{:?}", registered.json());
+    assert!(app.cookie(RP_ORIGIN, "den_session").is_some());
+
+    let passkeys = app.get(RP_ORIGIN, "/api/passkeys").await;
+    assert_eq!(passkeys.status, StatusCode::OK);
+    assert_eq!(passkeys.json()[0]["name"], "laptop");
+
+    app.send(RP_ORIGIN, Method::POST, "/api/logout", None).await;
+    assert!(app.cookie(RP_ORIGIN, "den_session").is_none());
+    let passkeys = app.get(RP_ORIGIN, "/api/passkeys").await;
+    assert_eq!(passkeys.status, StatusCode::UNAUTHORIZED);
+
+    let login = app.login(&mut key, json!({})).await;
+    assert_eq!(login.status, StatusCode::OK, "{:?}", login.json());
+    assert_eq!(login.json()["user_name"], "alice");
+    assert_eq!(login.json()["redirect_url"], serde_json::Value::Null);
+    assert_eq!(
+        app.get(RP_ORIGIN, "/api/passkeys").await.status,
+        StatusCode::OK
+    );
+}
+
+#[tokio::test]
+async fn one_device_bound_passkey_does_not_survive_device_loss() {
+    let app = TestApp::new().await;
+    let mut key = Authenticator::default();
+    app.register(
+        &mut key,
+        json!({ "user_name": "alice", "passkey_name": "laptop" }),
+    )
+    .await;
+    let passkeys = app.get(RP_ORIGIN, "/api/passkeys").await.json();
+    assert_eq!(passkeys[0]["backup_state"], false);
+    assert!(passkeys[0]["backup_eligible"].is_boolean());
+    let recovery = app.get(RP_ORIGIN, "/api/passkeys/recovery").await.json();
+    assert_eq!(
+        recovery,
+        json!({ "survives_device_loss": false, "synced": 0, "device_bound": 1 })
+    );
+
+    let mut spare = Authenticator::default();
+    let added = app
+        .register(&mut spare, json!({ "passkey_name": "security key" }))
+        .await;
+    assert_eq!(added.status, StatusCode::OK, "{:?}", added.json());
+    let recovery = app.get(RP_ORIGIN, "/api/passkeys/recovery").await.json();
+    assert_eq!(recovery["survives_device_loss"], true);
+    assert_eq!(recovery["device_bound"], 2);
+}
+
+#[tokio::test]
+async fn second_registration_needs_a_session_or_invite() {
+    let app = TestApp::new().await;
+    let mut key = Authenticator::default();
+    app.register(
+        &mut key,
+        json!({ "user_name": "alice", "passkey_name": "laptop" }),
+    )
+    .await;
+    app.clear_cookies();
+
+    let mut other = Authenticator::default();
+    let rejected = app
+        .register(
+            &mut other,
+            json!({ "user_name": "mallory", "passkey_name": "phone" }),
+        )
+        .await;
+    assert_eq!(rejected.status, StatusCode::UNAUTHORIZED);
+    assert_eq!(rejected.json()["code"], "unauthenticated");
+    assert!(rejected.json()["request_id"].is_string());
+}
+
+#[tokio::test]
+async fn login_challenge_is_single_use() {
+    let app = TestApp::new().await;
+    let mut key = Authenticator::default();
+    app.register(
+        &mut key,
+        json!({ "user_name": "alice", "passkey_name": "laptop" }),
+    )
+    .await;
+    app.clear_cookies();
+
+    let assertion = app.login_assertion(&mut key, json!({})).await.unwrap();
+    let first = app
+        .post(RP_ORIGIN, "/api/login/complete", assertion.clone())
+        .await;
+    assert_eq!(first.status, StatusCode::OK);
+
+    let replayed = app.post(RP_ORIGIN, "/api/login/complete", assertion).await;
+    assert_eq!(replayed.status, StatusCode::BAD_REQUEST);
+    assert_eq!(replayed.json()["code"], "challenge_expired");
+}
+
+#[tokio::test]
+async fn challenge_only_completes_in_the_browser_that_started_it() {
+    let app = TestApp::new().await;
+    let mut key = Authenticator::default();
+    app.register(
+        &mut key,
+        json!({ "user_name": "alice", "passkey_name": "laptop" }),
+    )
+    .await;
+    app.clear_cookies();
+
+    let assertion = app.login_assertion(&mut key, json!({})).await.unwrap();
+    let binding = app.cookie(RP_ORIGIN, "den_session_challenge").unwrap();
+    // Someone else who learned the challenge_id (and got an assertion for it).
+    app.clear_cookies();
+    let stolen = app
+        .post(RP_ORIGIN, "/api/login/complete", assertion.clone())
+        .await;
+    assert_eq!(stolen.status, StatusCode::BAD_REQUEST);
+    assert_eq!(stolen.json()["code"], "challenge_expired");
+
+    // The failed attempt didn't use the challenge up for its owner.
+    app.set_cookie(RP_ORIGIN, "den_session_challenge", &binding);
+    let owner = app.post(RP_ORIGIN, "/api/login/complete", assertion).await;
+    assert_eq!(owner.status, StatusCode::OK);
+}
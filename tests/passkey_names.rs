@@ -0,0 +1,106 @@
+mod support;
+
+use axum::http::{Method, StatusCode};
+use serde_json::json;
+use support::{Authenticator, RP_ORIGIN, TestApp, passkey_names};
+
+#[tokio::test]
+async fn passkey_names_are_unique_per_user() {
+    let app = TestApp::new().await;
+    let mut key = Authenticator::default();
+    app.register(
+        &mut key,
+        json!({ "user_name": "alice", "passkey_name": "laptop" }),
+    )
+    .await;
+
+    let mut spare = Authenticator::default();
+    let taken = app
+        .register(&mut spare, json!({ "passkey_name": "Laptop" }))
+        .await;
+    assert_eq!(taken.status, StatusCode::CONFLICT);
+    assert_eq!(taken.json()["code"], "passkey_name_taken");
+    let suggested = app
+        .get(RP_ORIGIN, "/api/passkeys/available-name?name=Laptop")
+        .await
+        .json();
+    assert_eq!(suggested["name"], "Laptop 2");
+    let added = app
+        .register(&mut spare, json!({ "passkey_name": "Laptop 2" }))
+        .await;
+    assert_eq!(added.status, StatusCode::OK, "{:?}", added.json());
+
+    let passkeys = app.get(RP_ORIGIN, "/api/passkeys").await.json();
+    let spare_id = &passkeys[1]["id"];
+    let rename = app
+        .send(
+            RP_ORIGIN,
+            Method::PATCH,
+            &format!("/api/passkeys/{spare_id}"),
+            Some(json!({ "name": "LAPTOP" })),
+        )
+        .await;
+    assert_eq!(rename.status, StatusCode::CONFLICT);
+    let bulk = app
+        .post(
+            RP_ORIGIN,
+            "/api/passkeys/bulk",
+            json!({ "rename": [{ "id": spare_id, "name": "laptop" }] }),
+        )
+        .await;
+    assert_eq!(bulk.status, StatusCode::CONFLICT);
+    let free = app
+        .get(RP_ORIGIN, "/api/passkeys/available-name?name=phone")
+        .await
+        .json();
+    assert_eq!(free["name"], "phone");
+}
+
+#[tokio::test]
+async fn bulk_changes_can_swap_and_reuse_names() {
+    let app = TestApp::new().await;
+    let mut key = Authenticator::default();
+    app.register(
+        &mut key,
+        json!({ "user_name": "alice", "passkey_name": "laptop" }),
+    )
+    .await;
+    for name in ["phone", "tablet"] {
+        let added = app
+            .register(
+                &mut Authenticator::default(),
+                json!({ "passkey_name": name }),
+            )
+            .await;
+        assert_eq!(added.status, StatusCode::OK, "{:?}", added.json());
+    }
+    let passkeys = app.get(RP_ORIGIN, "/api/passkeys").await.json();
+    let ids: Vec<i64> = (0..3)
+        .map(|i| passkeys[i]["id"].as_i64().unwrap())
+        .collect();
+
+    let swap = app
+        .post(
+            RP_ORIGIN,
+            "/api/passkeys/bulk",
+            json!({ "rename": [
+                { "id": ids[0], "name": "phone" },
+                { "id": ids[1], "name": "laptop" },
+            ] }),
+        )
+        .await;
+    assert_eq!(swap.status, StatusCode::NO_CONTENT, "{:?}", swap.json());
+    let swapped = app.get(RP_ORIGIN, "/api/passkeys").await.json();
+    assert_eq!(passkey_names(&swapped), ["phone", "laptop", "tablet"]);
+
+    let reuse = app
+        .post(
+            RP_ORIGIN,
+            "/api/passkeys/bulk",
+            json!({ "rename": [{ "id": ids[1], "name": "Tablet" }], "delete": [ids[2]] }),
+        )
+        .await;
+    assert_eq!(reuse.status, StatusCode::NO_CONTENT, "{:?}", reuse.json());
+    let reused = app.get(RP_ORIGIN, "/api/passkeys").await.json();
+    assert_eq!(passkey_names(&reused), ["phone", "Tablet"]);
+}
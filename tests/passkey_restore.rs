@@ -0,0 +1,103 @@
+mod support;
+
+use axum::http::{HeaderName, Method, StatusCode};
+use serde_json::json;
+use support::{Authenticator, RP_ORIGIN, TestApp, memory_db};
+
+#[tokio::test]
+async fn deleted_passkeys_can_be_restored_until_purged() {
+    let db = memory_db().await;
+    let app = TestApp::with_db("", db.clone()).await;
+    let mut key = Authenticator::default();
+    app.register(
+        &mut key,
+        json!({ "user_name": "alice", "passkey_name": "laptop" }),
+    )
+    .await;
+    app.register(
+        &mut Authenticator::default(),
+        json!({ "passkey_name": "phone" }),
+    )
+    .await;
+    let passkeys = app.get(RP_ORIGIN, "/api/passkeys").await.json();
+    let id = passkeys[1]["id"].as_i64().unwrap();
+    let path = format!("/api/passkeys/{id}");
+    let restore = format!("/api/passkeys/{id}/restore");
+
+    let not_deleted = app.post(RP_ORIGIN, &restore, json!({})).await;
+    assert_eq!(not_deleted.status, StatusCode::NOT_FOUND);
+    let deleted = app.send(RP_ORIGIN, Method::DELETE, &path, None).await;
+    assert_eq!(deleted.status, StatusCode::NO_CONTENT);
+    let passkeys = app.get(RP_ORIGIN, "/api/passkeys").await.json();
+    assert_eq!(passkeys.as_array().unwrap().len(), 1);
+    // Within the grace period the deleted key is kept, but its name is free.
+    assert_eq!(db.purge_deleted_passkeys(24).await.unwrap(), 0);
+    let suggested = app
+        .get(RP_ORIGIN, "/api/passkeys/available-name?name=phone")
+        .await
+        .json();
+    assert_eq!(suggested["name"], "phone");
+
+    let restored = app.post(RP_ORIGIN, &restore, json!({})).await;
+    assert_eq!(restored.status, StatusCode::NO_CONTENT);
+    let passkeys = app.get(RP_ORIGIN, "/api/passkeys").await.json();
+    assert_eq!(passkeys[1]["name"], "phone");
+
+    app.send(RP_ORIGIN, Method::DELETE, &path, None).await;
+    assert_eq!(db.purge_deleted_passkeys(0).await.unwrap(), 1);
+    let purged = app.post(RP_ORIGIN, &restore, json!({})).await;
+    assert_eq!(purged.status, StatusCode::NOT_FOUND);
+}
+
+#[tokio::test]
+async fn deleted_passkeys_can_be_imported_again() {
+    let app = TestApp::new().await;
+    let mut laptop = Authenticator::default();
+    app.register(
+        &mut laptop,
+        json!({ "user_name": "alice", "passkey_name": "laptop" }),
+    )
+    .await;
+    let mut phone = Authenticator::default();
+    app.register(&mut phone, json!({ "passkey_name": "phone" }))
+        .await;
+    let passphrase = "correct horse battery";
+    let envelope = app
+        .send_with(
+            RP_ORIGIN,
+            Method::GET,
+            "/api/passkeys/export",
+            None,
+            &[(HeaderName::from_static("x-den-passphrase"), passphrase)],
+        )
+        .await;
+    assert_eq!(envelope.status, StatusCode::OK);
+    let id = app.get(RP_ORIGIN, "/api/passkeys").await.json()[1]["id"]
+        .as_i64()
+        .unwrap();
+    let deleted = app
+        .send(
+            RP_ORIGIN,
+            Method::DELETE,
+            &format!("/api/passkeys/{id}"),
+            None,
+        )
+        .await;
+    assert_eq!(deleted.status, StatusCode::NO_CONTENT);
+
+    // The deleted row, still restorable, doesn't hold on to the credential.
+    let import = json!({ "passphrase": passphrase, "envelope": envelope.json() });
+    let imported = app.post(RP_ORIGIN, "/api/passkeys/import", import).await;
+    assert_eq!(imported.status, StatusCode::OK);
+    assert_eq!(imported.json(), json!({ "imported": 1, "skipped": 1 }));
+    app.clear_cookies();
+    assert_eq!(
+        app.login(&mut phone, json!({})).await.status,
+        StatusCode::OK
+    );
+
+    // Restoring it now would repeat a live credential.
+    let restore = format!("/api/passkeys/{id}/restore");
+    let restored = app.post(RP_ORIGIN, &restore, json!({})).await;
+    assert_eq!(restored.status, StatusCode::NOT_FOUND);
+}
@@ -0,0 +1,118 @@
+mod support;
+
+use axum::http::StatusCode;
+use serde_json::json;
+use support::{APP_ORIGIN, Authenticator, RP_ORIGIN, TestApp, memory_db};
+
+#[tokio::test]
+async fn first_matching_policy_decides_per_app() {
+    let db = memory_db().await;
+    let app = TestApp::with_db(
+        &format!(
+            "[[policies]]\n\
+             name = \"office network\"\n\
+             effect = \"allow\"\n\
+             apps = [\"{APP_ORIGIN}\"]\n\
+             networks = [\"10.0.0.0/8\"]\n\
+             [[policies]]\n\
+             name = \"app only from the office\"\n\
+             effect = \"deny\"\n\
+             apps = [\"{APP_ORIGIN}\"]\n\
+             methods = [\"passkey\", \"redirect\"]"
+        ),
+        db.clone(),
+    )
+    .await;
+    let mut key = Authenticator::default();
+    app.register(
+        &mut key,
+        json!({ "user_name": "alice", "passkey_name": "laptop" }),
+    )
+    .await;
+    app.clear_cookies();
+
+    let denied = app
+        .login(&mut key, json!({ "redirect_origin": APP_ORIGIN }))
+        .await;
+    assert_eq!(denied.status, StatusCode::FORBIDDEN);
+    assert_eq!(denied.json()["code"], "policy_denied");
+    assert_eq!(
+        app.get(RP_ORIGIN, "/api/sessions").await.status,
+        StatusCode::UNAUTHORIZED
+    );
+
+    // No rule is about den itself.
+    assert_eq!(app.login(&mut key, json!({})).await.status, StatusCode::OK);
+    let detail: String =
+        sqlx::query_scalar("SELECT detail FROM audit_event WHERE kind = 'login_failed'")
+            .fetch_one(db.pool())
+            .await
+            .unwrap();
+    assert_eq!(detail, "policy denied: app only from the office");
+}
+
+#[tokio::test]
+async fn related_origin_logins_check_the_origin_they_sign_in_on() {
+    let app = TestApp::with_config(&format!(
+        "related_origins = true\n\
+         [[policies]]\n\
+         name = \"no app\"\n\
+         effect = \"deny\"\n\
+         apps = [\"{APP_ORIGIN}\"]"
+    ))
+    .await;
+    let mut key = Authenticator::default();
+    app.register(
+        &mut key,
+        json!({ "user_name": "alice", "passkey_name": "laptop" }),
+    )
+    .await;
+    app.clear_cookies();
+
+    // The session is minted for the app the ceremony runs on, wherever it redirects.
+    let other = APP_ORIGIN.replacen("http:", "https:", 1);
+    let denied = app
+        .login_on(APP_ORIGIN, &mut key, json!({ "redirect_origin": other }))
+        .await;
+    assert_eq!(denied.status, StatusCode::FORBIDDEN);
+    assert_eq!(denied.json()["code"], "policy_denied");
+    assert!(app.cookie(APP_ORIGIN, "den_session").is_none());
+}
+
+#[tokio::test]
+async fn rules_can_name_the_authenticator_model() {
+    let db = memory_db().await;
+    let app = TestApp::with_db(
+        "[[policies]]\n\
+         name = \"security keys only\"\n\
+         effect = \"allow\"\n\
+         aaguids = [\"cb69481e-8ff7-4039-93ec-0a2729a154a8\"]\n\
+         [[policies]]\n\
+         name = \"other authenticators\"\n\
+         effect = \"deny\"",
+        db.clone(),
+    )
+    .await;
+    let mut key = Authenticator::default();
+    app.register(
+        &mut key,
+        json!({ "user_name": "alice", "passkey_name": "laptop" }),
+    )
+    .await;
+    app.clear_cookies();
+
+    // The soft passkey only self-attests, so its model is unknown.
+    let denied = app.login(&mut key, json!({})).await;
+    assert_eq!(denied.status, StatusCode::FORBIDDEN);
+    assert_eq!(denied.json()["code"], "policy_denied");
+
+    // As a key with a vendor attestation certificate registers.
+    sqlx::query(
+        "UPDATE passkey SET data = json_set(data, '$.cred.attestation.metadata', \
+         json('{\"Packed\":{\"aaguid\":\"cb69481e-8ff7-4039-93ec-0a2729a154a8\"}}'))",
+    )
+    .execute(db.pool())
+    .await
+    .unwrap();
+    assert_eq!(app.login(&mut key, json!({})).await.status, StatusCode::OK);
+}
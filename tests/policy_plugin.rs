@@ -0,0 +1,116 @@
+#![cfg(feature = "policy-plugin")]
+
+mod support;
+
+use axum::http::StatusCode;
+use serde_json::json;
+use support::{Authenticator, RP_ORIGIN, TestApp, policy_plugin};
+
+/// den with a plugin answering `verdict` to every sign-in; `extra` is more config
+/// before that table.
+async fn app_with_verdict(verdict: i32, extra: &str) -> TestApp {
+    let path = policy_plugin(verdict);
+    let app = TestApp::with_config(&format!(
+        "{extra}\n[policy_plugin]\npath = {:?}",
+        path.display().to_string()
+    ))
+    .await;
+    std::fs::remove_file(&path).unwrap();
+    app
+}
+
+async fn registered(app: &TestApp) -> Authenticator {
+    let mut key = Authenticator::default();
+    app.register(
+        &mut key,
+        json!({ "user_name": "alice", "passkey_name": "laptop" }),
+    )
+    .await;
+    app.clear_cookies();
+    key
+}
+
+#[tokio::test]
+async fn plugin_denies_passkey_sign_ins() {
+    let app = app_with_verdict(1, "").await;
+    let mut key = registered(&app).await;
+    let denied = app.login(&mut key, json!({})).await;
+    assert_eq!(denied.status, StatusCode::FORBIDDEN);
+    assert_eq!(denied.json()["code"], "policy_denied");
+}
+
+#[tokio::test]
+async fn plugin_step_up_wants_another_passkey() {
+    let app = app_with_verdict(2, "").await;
+    let mut key = Authenticator::default();
+    app.register(
+        &mut key,
+        json!({ "user_name": "alice", "passkey_name": "laptop" }),
+    )
+    .await;
+    let mut spare = Authenticator::default();
+    app.register(&mut spare, json!({ "passkey_name": "spare" }))
+        .await;
+    app.clear_cookies();
+    let first = app.login(&mut key, json!({})).await;
+    assert_eq!(first.status, StatusCode::OK);
+    // Only the passkey that didn't sign first may confirm.
+    let allowed = &first.json()["step_up"]["options"]["publicKey"]["allowCredentials"];
+    assert_eq!(allowed.as_array().unwrap().len(), 1);
+    let complete = spare.answer(RP_ORIGIN, &first.json()["step_up"]);
+
+    // The plugin still says step-up; a finished step-up is what it asked for.
+    let second = app.post(RP_ORIGIN, "/api/login/complete", complete).await;
+    assert_eq!(second.status, StatusCode::OK);
+    assert!(second.json().get("step_up").is_none());
+    assert_eq!(
+        app.get(RP_ORIGIN, "/api/sessions").await.status,
+        StatusCode::OK
+    );
+}
+
+#[tokio::test]
+async fn single_passkey_step_up_needs_the_recovery_url() {
+    let app = app_with_verdict(2, "").await;
+    let mut key = registered(&app).await;
+    let refused = app.login(&mut key, json!({})).await;
+    assert_eq!(refused.json()["code"], "step_up_unavailable");
+
+    let app = app_with_verdict(2, "[recovery]").await;
+    let mut key = Authenticator::default();
+    app.register(
+        &mut key,
+        json!({ "user_name": "alice", "passkey_name": "laptop" }),
+    )
+    .await;
+    let code = app.post(RP_ORIGIN, "/api/recovery/code", json!({})).await;
+    let url = code.json()["url"].as_str().unwrap().to_owned();
+    let token = url.split_once("/recover?token=").unwrap().1;
+    app.clear_cookies();
+
+    let first = app.login(&mut key, json!({})).await;
+    let challenge_id = first.json()["step_up_recovery"]["challenge_id"].clone();
+    assert!(challenge_id.is_string());
+    let wrong = app
+        .post(
+            RP_ORIGIN,
+            "/api/login/recovery",
+            json!({ "challenge_id": challenge_id, "token": "nope" }),
+        )
+        .await;
+    assert_eq!(wrong.json()["code"], "recovery_code_invalid");
+
+    let first = app.login(&mut key, json!({})).await;
+    let confirmed = app
+        .post(
+            RP_ORIGIN,
+            "/api/login/recovery",
+            json!({ "challenge_id": first.json()["step_up_recovery"]["challenge_id"], "token": token }),
+        )
+        .await;
+    assert_eq!(confirmed.status, StatusCode::OK);
+    assert_eq!(
+        app.get(RP_ORIGIN, "/api/sessions").await.status,
+        StatusCode::OK
+    );
+}
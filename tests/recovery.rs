@@ -0,0 +1,186 @@
+mod support;
+
+use axum::http::{Method, StatusCode};
+use serde_json::json;
+use support::{Authenticator, RP_ORIGIN, TestApp, memory_db};
+
+const RECOVERY: &str = "[recovery]\ndelay_hours = 72\n";
+
+/// Registers alice and returns the token of a fresh recovery URL.
+async fn alice_with_recovery_url(app: &TestApp, key: &mut Authenticator) -> String {
+    app.register(
+        key,
+        json!({ "user_name": "alice", "passkey_name": "laptop" }),
+    )
+    .await;
+    let code = app.post(RP_ORIGIN, "/api/recovery/code", json!({})).await;
+    assert_eq!(code.status, StatusCode::OK);
+    let url = code.json()["url"].as_str().unwrap().to_owned();
+    url.split_once("/recover?token=").unwrap().1.to_owned()
+}
+
+#[tokio::test]
+async fn recovery_is_off_unless_configured() {
+    let app = TestApp::new().await;
+    let mut key = Authenticator::default();
+    app.register(
+        &mut key,
+        json!({ "user_name": "alice", "passkey_name": "laptop" }),
+    )
+    .await;
+    let code = app.post(RP_ORIGIN, "/api/recovery/code", json!({})).await;
+    assert_eq!(code.status, StatusCode::NOT_FOUND);
+    let config = app.get(RP_ORIGIN, "/api/config/client").await.json();
+    assert!(config["recovery_delay_hours"].is_null());
+}
+
+#[tokio::test]
+async fn requested_recovery_waits_and_can_be_cancelled() {
+    let app = TestApp::with_config(RECOVERY).await;
+    let mut key = Authenticator::default();
+    let token = alice_with_recovery_url(&app, &mut key).await;
+
+    let request = json!({ "token": token });
+    let requested = app
+        .post(RP_ORIGIN, "/api/recovery/request", request.clone())
+        .await;
+    assert_eq!(requested.status, StatusCode::OK);
+    let again = app
+        .post(RP_ORIGIN, "/api/recovery/request", request.clone())
+        .await;
+    assert_eq!(again.json()["ready_at"], requested.json()["ready_at"]);
+    let early = app
+        .post(RP_ORIGIN, "/api/recovery/complete", request.clone())
+        .await;
+    assert_eq!(early.status, StatusCode::CONFLICT);
+    assert_eq!(early.json()["code"], "recovery_not_ready");
+
+    let status = app.get(RP_ORIGIN, "/api/recovery").await.json();
+    assert_eq!(status["has_code"], true);
+    assert_eq!(status["pending"]["ready"], false);
+    let cancelled = app
+        .send(RP_ORIGIN, Method::DELETE, "/api/recovery/request", None)
+        .await;
+    assert_eq!(cancelled.status, StatusCode::NO_CONTENT);
+    let status = app.get(RP_ORIGIN, "/api/recovery").await.json();
+    assert!(status["pending"].is_null());
+    assert_eq!(status["has_code"], false);
+    // Whoever asked can't simply start over with the same URL.
+    let restarted = app
+        .post(RP_ORIGIN, "/api/recovery/request", request.clone())
+        .await;
+    assert_eq!(restarted.status, StatusCode::UNAUTHORIZED);
+
+    let forged = json!({ "token": "not-a-recovery-token" });
+    let forged = app.post(RP_ORIGIN, "/api/recovery/request", forged).await;
+    assert_eq!(forged.status, StatusCode::UNAUTHORIZED);
+    assert_eq!(forged.json()["code"], "recovery_code_invalid");
+}
+
+#[tokio::test]
+async fn completed_recovery_signs_out_and_invites_a_new_passkey() {
+    let db = memory_db().await;
+    let app = TestApp::with_db(RECOVERY, db.clone()).await;
+    let mut key = Authenticator::default();
+    let token = alice_with_recovery_url(&app, &mut key).await;
+    let request = json!({ "token": token });
+    app.post(RP_ORIGIN, "/api/recovery/request", request.clone())
+        .await;
+    sqlx::query("UPDATE recovery_request SET ready_at = datetime('now', '-1 minutes')")
+        .execute(db.pool())
+        .await
+        .unwrap();
+
+    let completed = app
+        .post(RP_ORIGIN, "/api/recovery/complete", request.clone())
+        .await;
+    assert_eq!(completed.status, StatusCode::OK);
+    // Sessions held before the recovery are dead server-side.
+    let refresh = app.post(RP_ORIGIN, "/api/refresh", json!({})).await;
+    assert_eq!(refresh.status, StatusCode::UNAUTHORIZED);
+    let reused = app.post(RP_ORIGIN, "/api/recovery/complete", request).await;
+    assert_eq!(reused.status, StatusCode::UNAUTHORIZED);
+
+    let invite_url = completed.json()["invite_url"].as_str().unwrap().to_owned();
+    let invite = invite_url.split_once("/invite?token=").unwrap().1;
+    app.clear_cookies();
+    let mut replacement = Authenticator::default();
+    let registered = app
+        .register(
+            &mut replacement,
+            json!({ "user_name": "alice", "passkey_name": "new", "invite_token": invite }),
+        )
+        .await;
+    assert_eq!(registered.status, StatusCode::OK, "{:?}", registered.json());
+}
+
+/// A webhook receiver for `alert_webhook_url`: each POSTed event comes out of the
+/// channel.
+#[cfg(feature = "policy-plugin")]
+fn webhook() -> (String, std::sync::mpsc::Receiver<serde_json::Value>) {
+    use std::io::{BufRead, BufReader, Read, Write};
+
+    let listener = std::net::TcpListener::bind("127.0.0.1:0").unwrap();
+    let url = format!("http://{}/hook", listener.local_addr().unwrap());
+    let (tx, rx) = std::sync::mpsc::channel();
+    std::thread::spawn(move || {
+        for stream in listener.incoming().flatten() {
+            let mut reader = BufReader::new(&stream);
+            let mut length = 0;
+            let mut line = String::new();
+            while reader.read_line(&mut line).unwrap_or(0) > 2 {
+                if let Some((name, value)) = line.split_once(':')
+                    && name.eq_ignore_ascii_case("content-length")
+                {
+                    length = value.trim().parse().unwrap();
+                }
+                line.clear();
+            }
+            let mut body = vec![0; length];
+            reader.read_exact(&mut body).unwrap();
+            let _ = (&stream).write_all(b"HTTP/1.1 200 OK\r\ncontent-length: 0\r\n\r\n");
+            let _ = tx.send(serde_json::from_slice(&body).unwrap());
+        }
+    });
+    (url, rx)
+}
+
+#[cfg(feature = "policy-plugin")]
+#[tokio::test]
+async fn step_up_with_the_recovery_url_is_notified() {
+    let (url, events) = webhook();
+    let plugin = support::policy_plugin(2);
+    let app = TestApp::with_config(&format!(
+        "alert_webhook_url = {url:?}\n{RECOVERY}[policy_plugin]\npath = {:?}\n",
+        plugin.display().to_string()
+    ))
+    .await;
+    std::fs::remove_file(&plugin).unwrap();
+    let mut key = Authenticator::default();
+    let token = alice_with_recovery_url(&app, &mut key).await;
+    app.clear_cookies();
+
+    // Same device as registration, so only the recovery URL's use is news.
+    let first = app.login(&mut key, json!({})).await;
+    let confirmed = app
+        .post(
+            RP_ORIGIN,
+            "/api/login/recovery",
+            json!({ "challenge_id": first.json()["step_up_recovery"]["challenge_id"], "token": token }),
+        )
+        .await;
+    assert_eq!(confirmed.status, StatusCode::OK);
+    let event = tokio::task::spawn_blocking(move || {
+        loop {
+            let event = events
+                .recv_timeout(std::time::Duration::from_secs(5))
+                .expect("recovery_code_used sent");
+            if event["event"] == "recovery_code_used" {
+                return event;
+            }
+        }
+    })
+    .await
+    .unwrap();
+    assert_eq!(event["ip"], "127.0.0.1");
+}
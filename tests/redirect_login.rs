@@ -0,0 +1,202 @@
+mod support;
+
+use std::time::SystemTime;
+
+use axum::http::{Method, StatusCode, header};
+use base64::Engine;
+use base64::engine::general_purpose::URL_SAFE_NO_PAD;
+use den::signing_key::SigningKeys;
+use serde_json::json;
+use support::{APP_ORIGIN, Authenticator, RP_ORIGIN, TestApp};
+
+async fn registered_app() -> (TestApp, Authenticator) {
+    let app = TestApp::new().await;
+    let mut key = Authenticator::default();
+    app.register(
+        &mut key,
+        json!({ "user_name": "alice", "passkey_name": "laptop" }),
+    )
+    .await;
+    app.clear_cookies();
+    (app, key)
+}
+
+/// `redirect_url` is absolute; the test client wants the host-relative part.
+fn path_and_query(url: &str) -> &str {
+    let url = url
+        .strip_prefix(APP_ORIGIN)
+        .expect("redirect should target the app host");
+    assert!(url.starts_with("/api/login/redirect?token="), "{url}");
+    url
+}
+
+#[tokio::test]
+async fn login_for_another_host_hands_off_a_session_there() {
+    let (app, mut key) = registered_app().await;
+
+    let login = app
+        .login(
+            &mut key,
+            json!({ "redirect_origin": APP_ORIGIN, "redirect_path": "/dashboard" }),
+        )
+        .await;
+    assert_eq!(login.status, StatusCode::OK, "{:?}", login.json());
+    let redirect_url = login.json()["redirect_url"].as_str().unwrap().to_owned();
+    assert!(app.cookie(APP_ORIGIN, "den_session").is_none());
+
+    let handoff = app.get(APP_ORIGIN, path_and_query(&redirect_url)).await;
+    assert_eq!(handoff.status, StatusCode::SEE_OTHER);
+    assert_eq!(handoff.location(), Some("/dashboard"));
+    assert!(app.cookie(APP_ORIGIN, "den_session").is_some());
+    assert_eq!(
+        app.get(APP_ORIGIN, "/api/passkeys").await.status,
+        StatusCode::OK
+    );
+
+    // Both hosts now hold their own, separate sessions.
+    assert_ne!(
+        app.cookie(APP_ORIGIN, "den_session"),
+        app.cookie(RP_ORIGIN, "den_session")
+    );
+}
+
+#[tokio::test]
+async fn handoff_lands_on_the_exact_original_url() {
+    let (app, mut key) = registered_app().await;
+    let login = app
+        .login(
+            &mut key,
+            json!({
+                "redirect_origin": APP_ORIGIN,
+                "redirect_path": "/wiki/Some page?rev=2&diff=prev#History",
+            }),
+        )
+        .await;
+    let redirect_url = login.json()["redirect_url"].as_str().unwrap().to_owned();
+
+    let handoff = app.get(APP_ORIGIN, path_and_query(&redirect_url)).await;
+    assert_eq!(handoff.status, StatusCode::SEE_OTHER);
+    assert_eq!(
+        handoff.location(),
+        Some("/wiki/Some%20page?rev=2&diff=prev#History")
+    );
+}
+
+#[tokio::test]
+async fn same_host_login_returns_to_the_requested_path() {
+    let (app, mut key) = registered_app().await;
+    let login = app
+        .login(
+            &mut key,
+            json!({ "redirect_path": "/sessions?sort=recent" }),
+        )
+        .await;
+    assert_eq!(login.status, StatusCode::OK);
+    assert_eq!(login.json()["redirect_url"], "/sessions?sort=recent");
+
+    app.clear_cookies();
+    let outside = app
+        .login(&mut key, json!({ "redirect_path": "//evil.example/" }))
+        .await;
+    assert_eq!(outside.json()["redirect_url"], "/");
+}
+
+#[tokio::test]
+async fn redirect_token_is_single_use_and_bound_to_its_host() {
+    let (app, mut key) = registered_app().await;
+    let login = app
+        .login(&mut key, json!({ "redirect_origin": APP_ORIGIN }))
+        .await;
+    let redirect_url = login.json()["redirect_url"].as_str().unwrap().to_owned();
+    let path = path_and_query(&redirect_url);
+
+    let elsewhere = app.get(RP_ORIGIN, path).await;
+    assert_eq!(elsewhere.status, StatusCode::UNAUTHORIZED);
+    assert_eq!(elsewhere.json()["code"], "redirect_token_invalid");
+
+    assert_eq!(
+        app.get(APP_ORIGIN, path).await.status,
+        StatusCode::SEE_OTHER
+    );
+    let replayed = app.get(APP_ORIGIN, path).await;
+    assert_eq!(replayed.status, StatusCode::UNAUTHORIZED);
+    assert_eq!(replayed.json()["code"], "redirect_token_invalid");
+}
+
+#[tokio::test]
+async fn redirect_token_is_not_a_session_cookie() {
+    let (app, mut key) = registered_app().await;
+    let login = app
+        .login(&mut key, json!({ "redirect_origin": APP_ORIGIN }))
+        .await;
+    let redirect_url = login.json()["redirect_url"].as_str().unwrap().to_owned();
+    let token = redirect_url.split_once("token=").unwrap().1.to_owned();
+    app.clear_cookies();
+
+    let cookie = format!("den_session={token}");
+    let pasted = app
+        .send_with(
+            APP_ORIGIN,
+            Method::GET,
+            "/api/passkeys",
+            None,
+            &[(header::COOKIE, &cookie)],
+        )
+        .await;
+    assert_eq!(pasted.status, StatusCode::UNAUTHORIZED);
+}
+
+#[tokio::test]
+async fn redirects_to_unlisted_hosts_are_refused() {
+    let (app, mut key) = registered_app().await;
+    let login = app
+        .login(
+            &mut key,
+            json!({ "redirect_origin": "http://evil.localhost:3002" }),
+        )
+        .await;
+    assert_eq!(login.status, StatusCode::BAD_REQUEST);
+    assert_eq!(login.json()["code"], "invalid_redirect");
+}
+
+#[tokio::test]
+async fn redirect_token_past_exp_is_refused_within_the_default_leeway() {
+    let db = support::memory_db().await;
+    let app = TestApp::with_db("", db.clone()).await;
+    let mut key = Authenticator::default();
+    app.register(
+        &mut key,
+        json!({ "user_name": "alice", "passkey_name": "laptop" }),
+    )
+    .await;
+    app.clear_cookies();
+    let login = app
+        .login(&mut key, json!({ "redirect_origin": APP_ORIGIN }))
+        .await;
+    let redirect_url = login.json()["redirect_url"].as_str().unwrap().to_owned();
+    let path = path_and_query(&redirect_url);
+    assert_eq!(
+        app.get(APP_ORIGIN, path).await.status,
+        StatusCode::SEE_OTHER
+    );
+
+    // The same token, re-signed as if it expired ten seconds ago: jsonwebtoken's
+    // default 60 s leeway would still accept it.
+    let token = path.split_once("token=").unwrap().1;
+    let payload = token.split('.').nth(1).unwrap();
+    let mut claims: serde_json::Value =
+        serde_json::from_slice(&URL_SAFE_NO_PAD.decode(payload).unwrap()).unwrap();
+    let now = SystemTime::now()
+        .duration_since(SystemTime::UNIX_EPOCH)
+        .unwrap()
+        .as_secs() as i64;
+    claims["exp"] = json!(now - 10);
+    let keys = SigningKeys::new(db.signing_keys().await.unwrap());
+    let expired = keys.encode(Some("login-redirect"), &claims).unwrap();
+
+    let replayed = app
+        .get(APP_ORIGIN, &format!("/api/login/redirect?token={expired}"))
+        .await;
+    assert_eq!(replayed.status, StatusCode::UNAUTHORIZED);
+    assert_eq!(replayed.json()["code"], "redirect_token_invalid");
+}
@@ -0,0 +1,82 @@
+#![cfg(feature = "testing")]
+
+mod support;
+
+use axum::http::{Method, StatusCode};
+use serde_json::json;
+use support::{RP_ORIGIN, TestApp};
+
+/// The same round trips `web/e2e/soft-authenticator.js` makes from the browser.
+#[tokio::test]
+async fn server_side_authenticator_answers_real_ceremonies() {
+    let app = TestApp::new().await;
+
+    let begin = app
+        .post(
+            RP_ORIGIN,
+            "/api/register/begin",
+            json!({ "user_name": "alice", "passkey_name": "e2e" }),
+        )
+        .await
+        .json();
+    let credential = app
+        .post(
+            RP_ORIGIN,
+            "/api/testing/authenticator/create",
+            begin["options"].clone(),
+        )
+        .await;
+    assert_eq!(credential.status, StatusCode::OK);
+    let registered = app
+        .post(
+            RP_ORIGIN,
+            "/api/register/complete",
+            json!({ "challenge_id": begin["challenge_id"], "credential": credential.json() }),
+        )
+        .await;
+    assert_eq!(registered.status, StatusCode::OK);
+    app.clear_cookies();
+
+    let begin = app
+        .post(RP_ORIGIN, "/api/login/begin", json!({}))
+        .await
+        .json();
+    let assertion = app
+        .post(
+            RP_ORIGIN,
+            "/api/testing/authenticator/get",
+            begin["options"].clone(),
+        )
+        .await;
+    assert_eq!(assertion.status, StatusCode::OK);
+    let login = app
+        .post(
+            RP_ORIGIN,
+            "/api/login/complete",
+            json!({ "challenge_id": begin["challenge_id"], "credential": assertion.json() }),
+        )
+        .await;
+    assert_eq!(login.status, StatusCode::OK);
+
+    let reset = app
+        .send(
+            RP_ORIGIN,
+            Method::DELETE,
+            "/api/testing/authenticator",
+            None,
+        )
+        .await;
+    assert_eq!(reset.status, StatusCode::NO_CONTENT);
+    let begin = app
+        .post(RP_ORIGIN, "/api/login/begin", json!({}))
+        .await
+        .json();
+    let forgotten = app
+        .post(
+            RP_ORIGIN,
+            "/api/testing/authenticator/get",
+            begin["options"].clone(),
+        )
+        .await;
+    assert_eq!(forgotten.status, StatusCode::BAD_REQUEST);
+}
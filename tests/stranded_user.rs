@@ -0,0 +1,27 @@
+mod support;
+
+use axum::http::StatusCode;
+use serde_json::json;
+use support::{Authenticator, RP_ORIGIN, TestApp, memory_db};
+
+#[tokio::test]
+async fn a_user_without_passkeys_is_removed_so_setup_reopens() {
+    let db = memory_db().await;
+    // What a crash between the two inserts of the old registration left behind.
+    assert!(db.create_only_user("stranded", "alice").await.unwrap());
+    assert_eq!(db.remove_stranded_users().await.unwrap(), ["alice"]);
+    assert!(!db.user_exists().await.unwrap());
+
+    let app = TestApp::with_db("", db.clone()).await;
+    let mut key = Authenticator::default();
+    let registered = app
+        .register(
+            &mut key,
+            json!({ "user_name": "alice", "passkey_name": "laptop" }),
+        )
+        .await;
+    assert_eq!(registered.status, StatusCode::OK);
+    let passkeys = app.get(RP_ORIGIN, "/api/passkeys").await.json();
+    assert_eq!(passkeys.as_array().unwrap().len(), 1);
+    assert!(db.remove_stranded_users().await.unwrap().is_empty());
+}
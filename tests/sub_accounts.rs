@@ -0,0 +1,122 @@
+mod support;
+
+use axum::http::{HeaderName, Method, StatusCode};
+use serde_json::json;
+use support::{APP_ORIGIN, Authenticator, RP_ORIGIN, TestApp};
+
+/// `/api/verify` as the proxy in front of `APP_ORIGIN` calls it.
+async fn verify_app(app: &TestApp) -> StatusCode {
+    let host = (
+        HeaderName::from_static("x-forwarded-host"),
+        "app.localhost:3001",
+    );
+    app.send_with(RP_ORIGIN, Method::GET, "/api/verify", None, &[host])
+        .await
+        .status
+}
+
+#[tokio::test]
+async fn sub_accounts_only_reach_their_apps() {
+    let app = TestApp::new().await;
+    let mut owner_key = Authenticator::default();
+    app.register(
+        &mut owner_key,
+        json!({ "user_name": "alice", "passkey_name": "laptop" }),
+    )
+    .await;
+
+    for apps in [json!(["http://evil.localhost:3002"]), json!([RP_ORIGIN])] {
+        let refused = app
+            .post(
+                RP_ORIGIN,
+                "/api/admin/sub-accounts",
+                json!({ "name": "bob", "apps": apps }),
+            )
+            .await;
+        assert_eq!(refused.status, StatusCode::BAD_REQUEST);
+    }
+    let created = app
+        .post(
+            RP_ORIGIN,
+            "/api/admin/sub-accounts",
+            json!({ "name": "bob", "apps": [APP_ORIGIN] }),
+        )
+        .await;
+    assert_eq!(created.status, StatusCode::OK);
+    let created = created.json();
+    let id = created["id"].as_str().unwrap().to_owned();
+    let taken = app
+        .post(
+            RP_ORIGIN,
+            "/api/admin/sub-accounts",
+            json!({ "name": "Bob", "apps": [] }),
+        )
+        .await;
+    assert_eq!(taken.status, StatusCode::CONFLICT);
+
+    let url = created["invite"]["url"].as_str().unwrap();
+    let token = url.split_once("/invite?token=").unwrap().1;
+    app.clear_cookies();
+    let mut key = Authenticator::default();
+    let registered = app
+        .register(
+            &mut key,
+            json!({ "passkey_name": "phone", "invite_token": token }),
+        )
+        .await;
+    assert_eq!(registered.status, StatusCode::OK, "{:?}", registered.json());
+
+    app.clear_cookies();
+    let login = app
+        .login(&mut key, json!({ "redirect_origin": APP_ORIGIN }))
+        .await;
+    assert_eq!(login.status, StatusCode::OK, "{:?}", login.json());
+    // den's own API stays the owner's.
+    assert_eq!(
+        app.get(RP_ORIGIN, "/api/sessions").await.status,
+        StatusCode::FORBIDDEN
+    );
+    let denied = app.login(&mut key, json!({})).await;
+    assert_eq!(denied.status, StatusCode::FORBIDDEN);
+    assert_eq!(denied.json()["code"], "policy_denied");
+    assert_eq!(verify_app(&app).await, StatusCode::OK);
+    let bob_session = app.cookie(RP_ORIGIN, "den_session").unwrap();
+
+    app.clear_cookies();
+    app.login(&mut owner_key, json!({})).await;
+    let listed = app.get(RP_ORIGIN, "/api/admin/sub-accounts").await.json();
+    assert_eq!(listed[0]["name"], "bob");
+    assert_eq!(listed[0]["apps"], json!([APP_ORIGIN]));
+    assert_eq!(listed[0]["passkeys"], 1);
+    let cleared = app
+        .send(
+            RP_ORIGIN,
+            Method::PUT,
+            &format!("/api/admin/sub-accounts/{id}/apps"),
+            Some(json!({ "apps": [] })),
+        )
+        .await;
+    assert_eq!(cleared.status, StatusCode::NO_CONTENT);
+    // The revoked session's still-unexpired access token no longer passes.
+    app.clear_cookies();
+    app.set_cookie(RP_ORIGIN, "den_session", &bob_session);
+    assert_eq!(verify_app(&app).await, StatusCode::UNAUTHORIZED);
+
+    app.clear_cookies();
+    let denied = app
+        .login(&mut key, json!({ "redirect_origin": APP_ORIGIN }))
+        .await;
+    assert_eq!(denied.status, StatusCode::FORBIDDEN);
+
+    app.clear_cookies();
+    app.login(&mut owner_key, json!({})).await;
+    let path = format!("/api/admin/sub-accounts/{id}");
+    let removed = app.send(RP_ORIGIN, Method::DELETE, &path, None).await;
+    assert_eq!(removed.status, StatusCode::NO_CONTENT);
+    let again = app.send(RP_ORIGIN, Method::DELETE, &path, None).await;
+    assert_eq!(again.status, StatusCode::NOT_FOUND);
+    assert_eq!(
+        app.get(RP_ORIGIN, "/api/admin/sub-accounts").await.json(),
+        json!([])
+    );
+}
@@ -0,0 +1,345 @@
+//! Integration harness: the real router over an in-memory database, driven with
+//! `oneshot` requests, a cookie jar per host and a software authenticator.
+#![allow(dead_code)] // each test binary uses a different subset
+
+use std::collections::{BTreeMap, HashMap};
+use std::net::SocketAddr;
+use std::path::PathBuf;
+use std::sync::Mutex;
+use std::time::Duration;
+
+use axum::Router;
+use axum::body::{Body, Bytes};
+use axum::extract::connect_info::MockConnectInfo;
+use axum::http::{HeaderMap, Method, Request, StatusCode, header};
+use den::config::parse_app_config;
+use den::db::Db;
+use serde_json::{Value, json};
+use sqlx::sqlite::SqlitePoolOptions;
+use tower::ServiceExt;
+use url::Url;
+use webauthn_authenticator_rs::AuthenticatorBackend;
+use webauthn_authenticator_rs::softpasskey::SoftPasskey;
+use webauthn_rs::prelude::{CreationChallengeResponse, RequestChallengeResponse};
+
+/// Where den itself is served.
+pub const RP_ORIGIN: &str = "http://localhost:3000";
+/// A second host behind den, listed in `allowed_hosts`.
+pub const APP_ORIGIN: &str = "http://app.localhost:3001";
+const TIMEOUT_MS: u32 = 60_000;
+
+pub struct TestApp {
+    router: Router,
+    /// Cookies by host, as a browser would keep host-only cookies.
+    cookies: Mutex<HashMap<String, BTreeMap<String, String>>>,
+}
+
+#[derive(Debug)]
+pub struct TestResponse {
+    pub status: StatusCode,
+    pub headers: HeaderMap,
+    pub body: Bytes,
+}
+
+/// A migrated in-memory database.
+pub async fn memory_db() -> Db {
+    // Every connection to `sqlite::memory:` is its own database, so keep exactly one.
+    let pool = SqlitePoolOptions::new()
+        .max_connections(1)
+        .idle_timeout(None)
+        .max_lifetime(None)
+        .connect("sqlite::memory:")
+        .await
+        .expect("open in-memory database");
+    let db = Db::new(pool, Duration::from_secs(1));
+    db.migrate().await.expect("migrate in-memory database");
+    db
+}
+
+/// A `[policy_plugin]` module answering `verdict` (0 allow, 1 deny, 2 step-up) to
+/// every sign-in, written to a fresh file that may be removed once the app is up.
+pub fn policy_plugin(verdict: i32) -> PathBuf {
+    let path = std::env::temp_dir().join(format!("den-policy-{}.wat", uuid::Uuid::new_v4()));
+    std::fs::write(
+        &path,
+        format!(
+            r#"(module
+                (memory (export "memory") 1)
+                (func (export "alloc") (param i32) (result i32) i32.const 1024)
+                (func (export "evaluate") (param i32 i32) (result i32) i32.const {verdict}))"#
+        ),
+    )
+    .unwrap();
+    path
+}
+
+/// The `name`s of a `GET /api/passkeys` body, in order.
+pub fn passkey_names(passkeys: &Value) -> Vec<&str> {
+    passkeys
+        .as_array()
+        .unwrap()
+        .iter()
+        .map(|p| p["name"].as_str().unwrap())
+        .collect()
+}
+
+impl TestResponse {
+    pub fn json(&self) -> Value {
+        serde_json::from_slice(&self.body).unwrap_or(Value::Null)
+    }
+
+    pub fn location(&self) -> Option<&str> {
+        self.headers
+            .get(header::LOCATION)
+            .and_then(|v| v.to_str().ok())
+    }
+}
+
+impl TestApp {
+    pub async fn new() -> Self {
+        Self::with_config("").await
+    }
+
+    /// `extra` is appended to the base config, so it may add keys or `[tables]`.
+    pub async fn with_config(extra: &str) -> Self {
+        Self::with_db(extra, memory_db().await).await
+    }
+
+    /// Like `with_config`, over a database the test keeps a handle to.
+    pub async fn with_db(extra: &str, db: Db) -> Self {
+        let config = parse_app_config(
+            &format!(
+                "rp_id = \"localhost\"\n\
+                 rp_origin = \"{RP_ORIGIN}\"\n\
+                 allowed_hosts = [\"{APP_ORIGIN}\"]\n\
+                 {extra}"
+            ),
+            PathBuf::from(":memory:"),
+        )
+        .expect("test config");
+        let router = den::app(config, db)
+            .await
+            .expect("test app")
+            .layer(MockConnectInfo(SocketAddr::from(([127, 0, 0, 1], 40000))));
+        Self {
+            router,
+            cookies: Mutex::default(),
+        }
+    }
+
+    pub async fn get(&self, origin: &str, path: &str) -> TestResponse {
+        self.send(origin, Method::GET, path, None).await
+    }
+
+    pub async fn post(&self, origin: &str, path: &str, body: Value) -> TestResponse {
+        self.send(origin, Method::POST, path, Some(body)).await
+    }
+
+    /// A top-level browser navigation: `GET` with the `Accept` browsers send for pages.
+    pub async fn navigate(&self, origin: &str, path: &str) -> TestResponse {
+        let accept = "text/html,application/xhtml+xml,*/*;q=0.8";
+        self.send_with(origin, Method::GET, path, None, &[(header::ACCEPT, accept)])
+            .await
+    }
+
+    pub async fn send(
+        &self,
+        origin: &str,
+        method: Method,
+        path: &str,
+        body: Option<Value>,
+    ) -> TestResponse {
+        self.send_with(origin, method, path, body, &[]).await
+    }
+
+    pub async fn send_with(
+        &self,
+        origin: &str,
+        method: Method,
+        path: &str,
+        body: Option<Value>,
+        headers: &[(header::HeaderName, &str)],
+    ) -> TestResponse {
+        let host = host(origin);
+        let mut request = Request::builder()
+            .method(method)
+            .uri(path)
+            .header(header::HOST, &host)
+            .header(header::USER_AGENT, "den-tests");
+        for (name, value) in headers {
+            request = request.header(name, *value);
+        }
+        if let Some(cookie) = self.cookie_header(&host) {
+            request = request.header(header::COOKIE, cookie);
+        }
+        let request = match body {
+            Some(body) => request
+                .header(header::CONTENT_TYPE, "application/json")
+                .body(Body::from(body.to_string())),
+            None => request.body(Body::empty()),
+        }
+        .unwrap();
+
+        let response = self.router.clone().oneshot(request).await.unwrap();
+        let (parts, body) = response.into_parts();
+        self.store_cookies(&host, &parts.headers);
+        TestResponse {
+            status: parts.status,
+            headers: parts.headers,
+            body: axum::body::to_bytes(body, usize::MAX).await.unwrap(),
+        }
+    }
+
+    /// Forget every cookie, as if a new browser showed up.
+    pub fn clear_cookies(&self) {
+        self.cookies.lock().unwrap().clear();
+    }
+
+    pub fn cookie(&self, origin: &str, name: &str) -> Option<String> {
+        self.cookies
+            .lock()
+            .unwrap()
+            .get(&host(origin))
+            .and_then(|jar| jar.get(name).cloned())
+    }
+
+    /// Full registration ceremony against den's own origin; `body` is the
+    /// `/api/register/begin` request (`user_name`, `passkey_name`, tokens).
+    pub async fn register(&self, key: &mut Authenticator, body: Value) -> TestResponse {
+        let begin = self.post(RP_ORIGIN, "/api/register/begin", body).await;
+        if begin.status != StatusCode::OK {
+            return begin;
+        }
+        let begin = begin.json();
+        let options: CreationChallengeResponse =
+            serde_json::from_value(begin["options"].clone()).unwrap();
+        let credential = key
+            .0
+            .perform_register(
+                Url::parse(RP_ORIGIN).unwrap(),
+                options.public_key,
+                TIMEOUT_MS,
+            )
+            .expect("soft passkey registration");
+        self.post(
+            RP_ORIGIN,
+            "/api/register/complete",
+            json!({ "challenge_id": begin["challenge_id"], "credential": credential }),
+        )
+        .await
+    }
+
+    /// Full login ceremony against den's own origin; `body` is the `/api/login/begin`
+    /// request (`redirect_origin`, `redirect_path`).
+    pub async fn login(&self, key: &mut Authenticator, body: Value) -> TestResponse {
+        self.login_on(RP_ORIGIN, key, body).await
+    }
+
+    /// Like `login`, but the whole ceremony runs on `origin` (related origin requests).
+    pub async fn login_on(
+        &self,
+        origin: &str,
+        key: &mut Authenticator,
+        body: Value,
+    ) -> TestResponse {
+        match self.assertion_on(origin, key, body).await {
+            Ok(complete) => self.post(origin, "/api/login/complete", complete).await,
+            Err(begin) => begin,
+        }
+    }
+
+    /// Start a login and let `key` answer it, returning the `/api/login/complete`
+    /// body, or the `/api/login/begin` response if den refused to start.
+    pub async fn login_assertion(
+        &self,
+        key: &mut Authenticator,
+        body: Value,
+    ) -> Result<Value, TestResponse> {
+        self.assertion_on(RP_ORIGIN, key, body).await
+    }
+
+    async fn assertion_on(
+        &self,
+        origin: &str,
+        key: &mut Authenticator,
+        body: Value,
+    ) -> Result<Value, TestResponse> {
+        let begin = self.post(origin, "/api/login/begin", body).await;
+        if begin.status != StatusCode::OK {
+            return Err(begin);
+        }
+        Ok(key.answer(origin, &begin.json()))
+    }
+
+    pub fn set_cookie(&self, origin: &str, name: &str, value: &str) {
+        self.cookies
+            .lock()
+            .unwrap()
+            .entry(host(origin))
+            .or_default()
+            .insert(name.to_owned(), value.to_owned());
+    }
+
+    fn cookie_header(&self, host: &str) -> Option<String> {
+        let cookies = self.cookies.lock().unwrap();
+        let jar = cookies.get(host).filter(|jar| !jar.is_empty())?;
+        Some(
+            jar.iter()
+                .map(|(name, value)| format!("{name}={value}"))
+                .collect::<Vec<_>>()
+                .join("; "),
+        )
+    }
+
+    fn store_cookies(&self, host: &str, headers: &HeaderMap) {
+        let mut cookies = self.cookies.lock().unwrap();
+        let jar = cookies.entry(host.to_owned()).or_default();
+        for set_cookie in headers.get_all(header::SET_COOKIE) {
+            let set_cookie = set_cookie.to_str().unwrap();
+            let mut attributes = set_cookie.split(';').map(str::trim);
+            let Some((name, value)) = attributes.next().and_then(|c| c.split_once('=')) else {
+                continue;
+            };
+            let removed =
+                value.is_empty() || attributes.any(|a| a.eq_ignore_ascii_case("max-age=0"));
+            if removed {
+                jar.remove(name);
+            } else {
+                jar.insert(name.to_owned(), value.to_owned());
+            }
+        }
+    }
+}
+
+/// A software passkey that answers den's WebAuthn challenges. It is driven without
+/// `WebauthnAuthenticator`'s client checks, which refuse `http://app.localhost`
+/// although browsers treat every `*.localhost` origin as secure.
+pub struct Authenticator(SoftPasskey);
+
+impl Default for Authenticator {
+    fn default() -> Self {
+        // Claim user verification, as a platform authenticator with a PIN would.
+        Self(SoftPasskey::new(true))
+    }
+}
+
+impl Authenticator {
+    /// The `/api/login/complete` body answering `begin` (`{challenge_id, options}`),
+    /// from `/api/login/begin` or a step-up.
+    pub fn answer(&mut self, origin: &str, begin: &Value) -> Value {
+        let options: RequestChallengeResponse =
+            serde_json::from_value(begin["options"].clone()).unwrap();
+        let credential = self
+            .0
+            .perform_auth(Url::parse(origin).unwrap(), options.public_key, TIMEOUT_MS)
+            .expect("soft passkey authentication");
+        json!({ "challenge_id": begin["challenge_id"], "credential": credential })
+    }
+}
+
+fn host(origin: &str) -> String {
+    origin
+        .split_once("://")
+        .map_or(origin, |(_, host)| host)
+        .to_owned()
+}
@@ -0,0 +1,49 @@
+mod support;
+
+use axum::http::StatusCode;
+use den::storage::Storage;
+use den::{session, usage};
+use serde_json::json;
+use support::{APP_ORIGIN, Authenticator, RP_ORIGIN, TestApp, memory_db};
+
+#[tokio::test]
+async fn stats_count_redirects_per_app_and_sessions_per_day() {
+    let db = memory_db().await;
+    let app = TestApp::with_db("", db.clone()).await;
+    let mut key = Authenticator::default();
+    app.register(
+        &mut key,
+        json!({ "user_name": "alice", "passkey_name": "laptop" }),
+    )
+    .await;
+    let stats = app.get(RP_ORIGIN, "/api/admin/stats").await.json();
+    assert_eq!(stats["usage"], json!({ "days": [], "apps": [] }));
+
+    for _ in 0..2 {
+        let login = app
+            .login(&mut key, json!({ "redirect_origin": APP_ORIGIN }))
+            .await;
+        let redirect_url = login.json()["redirect_url"].as_str().unwrap().to_owned();
+        let handoff = app
+            .get(APP_ORIGIN, redirect_url.strip_prefix(APP_ORIGIN).unwrap())
+            .await;
+        assert_eq!(handoff.status, StatusCode::SEE_OTHER);
+    }
+
+    // What the housekeeping leader does each minute; a lower reading never wins.
+    let used_today = session::used_today(&Storage::Sqlite(db.clone()))
+        .await
+        .unwrap();
+    // Registration's session and one per handoff, at least.
+    assert!(used_today >= 3, "{used_today}");
+    usage::record_sessions(&db, used_today).await.unwrap();
+    usage::record_sessions(&db, 1).await.unwrap();
+
+    let usage = &app.get(RP_ORIGIN, "/api/admin/stats").await.json()["usage"];
+    assert_eq!(usage["days"].as_array().unwrap().len(), 1);
+    assert_eq!(usage["days"][0]["active_sessions"], used_today);
+    assert_eq!(usage["days"][0]["redirects"], 2);
+    assert_eq!(usage["apps"][0]["app"], APP_ORIGIN);
+    assert_eq!(usage["apps"][0]["redirects"], 2);
+    assert_eq!(usage["apps"][0]["last_day"], usage["days"][0]["day"]);
+}
@@ -0,0 +1,95 @@
+mod support;
+
+use axum::http::{StatusCode, header};
+use serde_json::json;
+use support::{APP_ORIGIN, Authenticator, RP_ORIGIN, TestApp};
+
+#[tokio::test]
+async fn unconfigured_documents_are_not_found() {
+    let app = TestApp::new().await;
+    for path in ["/.well-known/security.txt", "/.well-known/webauthn"] {
+        let response = app.get(APP_ORIGIN, path).await;
+        assert_eq!(response.status, StatusCode::NOT_FOUND, "{path}");
+    }
+}
+
+#[tokio::test]
+async fn security_txt_is_served_on_every_host() {
+    let app = TestApp::with_config(
+        "[security_txt]\n\
+         contact = [\"mailto:security@example.com\"]\n\
+         expires = \"2030-01-01T00:00:00Z\"\n\
+         policy = \"https://example.com/security\"\n",
+    )
+    .await;
+    let response = app.get(APP_ORIGIN, "/.well-known/security.txt").await;
+    assert_eq!(response.status, StatusCode::OK);
+    assert_eq!(
+        response.headers[header::CONTENT_TYPE],
+        "text/plain; charset=utf-8"
+    );
+    assert_eq!(
+        std::str::from_utf8(&response.body).unwrap(),
+        "Contact: mailto:security@example.com\n\
+         Expires: 2030-01-01T00:00:00Z\n\
+         Canonical: http://localhost:3000/.well-known/security.txt\n\
+         Policy: https://example.com/security\n"
+    );
+}
+
+#[tokio::test]
+async fn change_password_points_at_passkey_settings() {
+    let app = TestApp::new().await;
+    let response = app.get(APP_ORIGIN, "/.well-known/change-password").await;
+    assert_eq!(response.status, StatusCode::SEE_OTHER);
+    assert_eq!(response.location(), Some("http://localhost:3000/settings"));
+}
+
+#[tokio::test]
+async fn related_origins_list_configured_hosts() {
+    let app = TestApp::with_config("related_origins = true").await;
+    let response = app.get(RP_ORIGIN, "/.well-known/webauthn").await;
+    assert_eq!(response.status, StatusCode::OK);
+    assert_eq!(
+        response.json(),
+        json!({ "origins": [RP_ORIGIN, APP_ORIGIN] })
+    );
+}
+
+#[tokio::test]
+async fn related_origins_sign_in_without_the_redirect_hop() {
+    let app = TestApp::with_config("related_origins = true").await;
+    let mut key = Authenticator::default();
+    app.register(
+        &mut key,
+        json!({ "user_name": "alice", "passkey_name": "laptop" }),
+    )
+    .await;
+
+    let login_page = app.get(APP_ORIGIN, "/login").await;
+    assert_ne!(login_page.status, StatusCode::TEMPORARY_REDIRECT);
+    let login = app.login_on(APP_ORIGIN, &mut key, json!({})).await;
+    assert_eq!(login.status, StatusCode::OK, "{:?}", login.json());
+    assert!(login.json()["redirect_url"].is_null());
+    let refresh = app.post(APP_ORIGIN, "/api/refresh", json!({})).await;
+    assert_eq!(refresh.status, StatusCode::OK);
+
+    let apps = app.get(RP_ORIGIN, "/api/user/apps").await.json();
+    assert_eq!(apps[0]["origin"], APP_ORIGIN);
+}
+
+#[tokio::test]
+async fn unrelated_origins_cannot_assert() {
+    let app = TestApp::new().await;
+    let mut key = Authenticator::default();
+    app.register(
+        &mut key,
+        json!({ "user_name": "alice", "passkey_name": "laptop" }),
+    )
+    .await;
+
+    let login_page = app.get(APP_ORIGIN, "/login").await;
+    assert_eq!(login_page.status, StatusCode::TEMPORARY_REDIRECT);
+    let login = app.login_on(APP_ORIGIN, &mut key, json!({})).await;
+    assert_eq!(login.status, StatusCode::UNAUTHORIZED);
+}
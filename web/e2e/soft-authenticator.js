@@ -0,0 +1,83 @@
+/**
+ * Playwright init script for den builds with the `testing` feature: routes
+ * navigator.credentials through den's server-side soft passkey
+ * (/api/testing/authenticator/*), so the SPA's real passkey flows run without an
+ * authenticator.
+ *
+ *   await page.addInitScript({ path: "e2e/soft-authenticator.js" });
+ *   await request.delete("/api/testing/authenticator"); // forget credentials
+ */
+(() => {
+  const toBase64url = (buffer) => {
+    const bytes = ArrayBuffer.isView(buffer)
+      ? new Uint8Array(buffer.buffer, buffer.byteOffset, buffer.byteLength)
+      : new Uint8Array(buffer);
+    let binary = "";
+    for (const byte of bytes) binary += String.fromCharCode(byte);
+    return btoa(binary)
+      .replace(/\+/g, "-")
+      .replace(/\//g, "_")
+      .replace(/=+$/, "");
+  };
+
+  const toBuffer = (base64url) => {
+    const base64 = base64url.replace(/-/g, "+").replace(/_/g, "/");
+    const binary = atob(base64.padEnd(Math.ceil(base64.length / 4) * 4, "="));
+    return Uint8Array.from(binary, (c) => c.charCodeAt(0)).buffer;
+  };
+
+  const encodeIds = (credentials) =>
+    credentials?.map((c) => ({ ...c, id: toBase64url(c.id) }));
+
+  const answer = async (path, publicKey) => {
+    const res = await fetch(`/api/testing/authenticator/${path}`, {
+      method: "POST",
+      headers: { "Content-Type": "application/json" },
+      body: JSON.stringify({ publicKey }),
+    });
+    if (!res.ok) {
+      throw new DOMException("Soft authenticator refused", "NotAllowedError");
+    }
+    return res.json();
+  };
+
+  const credential = (json, response) => ({
+    id: json.id,
+    rawId: toBuffer(json.rawId),
+    type: json.type,
+    authenticatorAttachment: "platform",
+    response,
+    getClientExtensionResults: () => json.extensions ?? {},
+  });
+
+  navigator.credentials.create = async ({ publicKey }) => {
+    const json = await answer("create", {
+      ...publicKey,
+      challenge: toBase64url(publicKey.challenge),
+      user: { ...publicKey.user, id: toBase64url(publicKey.user.id) },
+      excludeCredentials: encodeIds(publicKey.excludeCredentials),
+    });
+    return credential(json, {
+      attestationObject: toBuffer(json.response.attestationObject),
+      clientDataJSON: toBuffer(json.response.clientDataJSON),
+    });
+  };
+
+  navigator.credentials.get = async ({ publicKey, mediation }) => {
+    // Autofill would otherwise sign in on every page load; never settle it.
+    if (mediation === "conditional") return new Promise(() => {});
+    const json = await answer("get", {
+      ...publicKey,
+      challenge: toBase64url(publicKey.challenge),
+      allowCredentials: encodeIds(publicKey.allowCredentials),
+    });
+    return credential(json, {
+      authenticatorData: toBuffer(json.response.authenticatorData),
+      clientDataJSON: toBuffer(json.response.clientDataJSON),
+      signature: toBuffer(json.response.signature),
+      userHandle: json.response.userHandle
+        ? toBuffer(json.response.userHandle)
+        : null,
+    });
+  };
+})();
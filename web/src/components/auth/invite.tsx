@@ -0,0 +1,73 @@
+"use client";
+
+import { useState } from "react";
+import { Button } from "@/components/ui/button";
+import { Input } from "@/components/ui/input";
+import {
+  Card,
+  CardContent,
+  CardDescription,
+  CardHeader,
+  CardTitle,
+} from "@/components/ui/card";
+import { Label } from "@/components/ui/label";
+import { useBranding } from "@/lib/branding";
+import { registerPasskey } from "@/lib/webauthn";
+
+interface InviteProps {
+  token: string;
+  onComplete: () => void | Promise<void>;
+}
+
+export function Invite({ token, onComplete }: InviteProps) {
+  const [name, setName] = useState("");
+  const [error, setError] = useState<string | null>(null);
+  const [loading, setLoading] = useState(false);
+  const branding = useBranding();
+
+  const handleRegister = async () => {
+    const trimmedName = name.trim();
+    if (!trimmedName) return;
+    setLoading(true);
+    setError(null);
+    try {
+      await registerPasskey(null, trimmedName, undefined, {
+        inviteToken: token,
+      });
+      await onComplete();
+    } catch (e) {
+      setError(e instanceof Error ? e.message : "Registration failed");
+    } finally {
+      setLoading(false);
+    }
+  };
+
+  return (
+    <Card className="w-full max-w-sm">
+      <CardHeader>
+        <CardTitle>{branding.title ?? "den"}</CardTitle>
+        <CardDescription>Add a passkey on this device</CardDescription>
+      </CardHeader>
+      <CardContent className="space-y-4">
+        <div className="space-y-2">
+          <Label htmlFor="passkey-name">Passkey name</Label>
+          <Input
+            id="passkey-name"
+            value={name}
+            onChange={(e) => setName(e.target.value)}
+            placeholder="e.g. Work laptop"
+            onKeyDown={(e) => e.key === "Enter" && handleRegister()}
+          />
+        </div>
+        {error && <p className="text-destructive text-sm">{error}</p>}
+        <Button
+          onClick={handleRegister}
+          disabled={loading || !name.trim()}
+          className="w-full"
+        >
+          {loading ? "Registering..." : "Register passkey"}
+        </Button>
+      </CardContent>
+    </Card>
+  );
+}
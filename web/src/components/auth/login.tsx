@@ -1,7 +1,9 @@
 "use client";
 
-import { useState } from "react";
+import { useEffect, useState } from "react";
 import { Button } from "@/components/ui/button";
+import { Input } from "@/components/ui/input";
+import { Label } from "@/components/ui/label";
 import {
   Card,
   CardContent,
@@ -10,11 +12,19 @@ import {
   CardTitle,
 } from "@/components/ui/card";
 import {
+  confirmStepUp,
+  confirmStepUpWithRecovery,
   loginWithPasskey,
   type PasskeyAuthResult,
   type RedirectRequest,
+  type StepUpChallenge,
 } from "@/lib/webauthn";
-import { isUnauthorizedError } from "@/lib/api-fetch";
+import { ApiError, isUnauthorizedError } from "@/lib/api-fetch";
+import { useBranding } from "@/lib/branding";
+import { fetchClientConfig } from "@/lib/client-config";
+import { useTranslate } from "@/lib/i18n";
+import { loginWithDevPassword } from "@/lib/dev-login";
+import { loginWithLdap } from "@/lib/ldap";
 
 interface LoginProps {
   redirect?: RedirectRequest;
@@ -23,17 +33,123 @@ interface LoginProps {
 
 export function Login({ onComplete, redirect }: LoginProps) {
   const [error, setError] = useState<string | null>(null);
+  const [retryAt, setRetryAt] = useState<number | null>(null);
+  const [now, setNow] = useState(() => Date.now());
   const [loading, setLoading] = useState(false);
+  const [stepUp, setStepUp] = useState<StepUpChallenge | null>(null);
+  const [stepUpRecovery, setStepUpRecovery] = useState<string | null>(null);
+  const [recoveryCode, setRecoveryCode] = useState("");
+  const [oidcLabel, setOidcLabel] = useState<string | null>(null);
+  const [ldapEnabled, setLdapEnabled] = useState(false);
+  const [showLdap, setShowLdap] = useState(false);
+  const [ldap, setLdap] = useState({ username: "", password: "", code: "" });
+  const [devLoginEnabled, setDevLoginEnabled] = useState(false);
+  const [devPassword, setDevPassword] = useState("");
+  const branding = useBranding();
+  const t = useTranslate();
+
+  useEffect(() => {
+    fetchClientConfig()
+      .then((config) => {
+        setOidcLabel(config.upstream_oidc_label);
+        setLdapEnabled(config.login_methods.includes("ldap"));
+        setDevLoginEnabled(config.login_methods.includes("dev_password"));
+      })
+      .catch(() => {});
+  }, []);
+
+  // Tick while throttled so the countdown moves, then lift the lock.
+  useEffect(() => {
+    if (retryAt === null) return;
+    const timer = window.setInterval(() => {
+      setNow(Date.now());
+      if (Date.now() >= retryAt) setRetryAt(null);
+    }, 1000);
+    return () => window.clearInterval(timer);
+  }, [retryAt]);
+
+  const showError = (e: unknown) => {
+    if (e instanceof ApiError && e.retryAfter !== null) {
+      setNow(Date.now());
+      setRetryAt(Date.now() + e.retryAfter * 1000);
+    }
+    setError(
+      e instanceof Error ? e.message : t("login.failed", "Login failed"),
+    );
+  };
+  const waitSeconds =
+    retryAt === null ? 0 : Math.max(1, Math.ceil((retryAt - now) / 1000));
+  const blocked = loading || retryAt !== null;
+  const confirming = stepUp !== null || stepUpRecovery !== null;
+
+  const handleLdapLogin = async () => {
+    setLoading(true);
+    setError(null);
+    try {
+      await onComplete(await loginWithLdap(ldap, redirect));
+    } catch (e) {
+      showError(e);
+    } finally {
+      setLoading(false);
+    }
+  };
+
+  const handleDevLogin = async () => {
+    setLoading(true);
+    setError(null);
+    try {
+      await onComplete(await loginWithDevPassword(devPassword));
+    } catch (e) {
+      showError(e);
+    } finally {
+      setLoading(false);
+    }
+  };
+
+  const handleOidcLogin = () => {
+    const params = new URLSearchParams();
+    if (redirect?.redirectOrigin) {
+      params.set("redirect_origin", redirect.redirectOrigin);
+    }
+    if (redirect?.redirectPath) {
+      params.set("redirect_path", redirect.redirectPath);
+    }
+    const query = params.toString();
+    window.location.assign(`/api/oidc/login${query ? `?${query}` : ""}`);
+  };
 
   const handleLogin = async () => {
     setLoading(true);
     setError(null);
     try {
-      const result = await loginWithPasskey(redirect);
+      const result = stepUp
+        ? await confirmStepUp(stepUp)
+        : await loginWithPasskey(redirect);
+      setStepUp(result.stepUp);
+      setStepUpRecovery(result.stepUpRecovery);
+      if (result.stepUp || result.stepUpRecovery) return;
       await onComplete(result);
     } catch (e) {
       if (isUnauthorizedError(e)) return;
-      setError(e instanceof Error ? e.message : "Login failed");
+      showError(e);
+    } finally {
+      setLoading(false);
+    }
+  };
+
+  const handleRecoveryStepUp = async () => {
+    if (stepUpRecovery === null) return;
+    setLoading(true);
+    setError(null);
+    try {
+      const result = await confirmStepUpWithRecovery(
+        stepUpRecovery,
+        recoveryCode,
+      );
+      setStepUpRecovery(null);
+      await onComplete(result);
+    } catch (e) {
+      showError(e);
     } finally {
       setLoading(false);
     }
@@ -42,14 +158,184 @@ export function Login({ onComplete, redirect }: LoginProps) {
   return (
     <Card className="w-full max-w-sm">
       <CardHeader>
-        <CardTitle>den</CardTitle>
-        <CardDescription>Sign in to continue</CardDescription>
+        {branding.logo_url && (
+          <img src={branding.logo_url} alt="" className="mb-2 h-10 w-auto" />
+        )}
+        <CardTitle>{branding.title ?? "den"}</CardTitle>
+        <CardDescription>
+          {t("login.subtitle", "Sign in to continue")}
+        </CardDescription>
       </CardHeader>
       <CardContent className="space-y-4">
+        {stepUp && (
+          <p className="text-sm">
+            {t(
+              "login.step_up",
+              "This sign-in looks different from usual. Confirm it's you with another of your passkeys.",
+            )}
+          </p>
+        )}
+        {stepUpRecovery && (
+          <p className="text-sm">
+            {t(
+              "login.step_up_recovery",
+              "This sign-in looks different from usual. Confirm it's you with the recovery URL you printed.",
+            )}
+          </p>
+        )}
         {error && <p className="text-destructive text-sm">{error}</p>}
-        <Button onClick={handleLogin} disabled={loading} className="w-full">
-          {loading ? "Authenticating..." : "Sign in with passkey"}
-        </Button>
+        {retryAt !== null && (
+          <p className="text-muted-foreground text-sm" aria-live="polite">
+            {t("login.retry_in", "Try again in {seconds} s.").replace(
+              "{seconds}",
+              String(waitSeconds),
+            )}
+          </p>
+        )}
+        {stepUpRecovery ? (
+          <form
+            onSubmit={(e) => {
+              e.preventDefault();
+              handleRecoveryStepUp();
+            }}
+            className="space-y-3"
+          >
+            <div className="space-y-1">
+              <Label htmlFor="recovery-code">
+                {t("login.recovery_code", "Recovery URL")}
+              </Label>
+              <Input
+                id="recovery-code"
+                autoComplete="off"
+                value={recoveryCode}
+                onChange={(e) => setRecoveryCode(e.target.value)}
+              />
+            </div>
+            <Button type="submit" disabled={blocked} className="w-full">
+              {loading
+                ? t("login.authenticating", "Authenticating...")
+                : t("login.confirm_recovery", "Confirm with recovery URL")}
+            </Button>
+          </form>
+        ) : (
+          <Button onClick={handleLogin} disabled={blocked} className="w-full">
+            {loading
+              ? t("login.authenticating", "Authenticating...")
+              : stepUp
+                ? t("login.confirm_passkey", "Confirm with passkey")
+                : t("login.with_passkey", "Sign in with passkey")}
+          </Button>
+        )}
+        {oidcLabel && !confirming && (
+          <Button
+            variant="outline"
+            onClick={handleOidcLogin}
+            disabled={blocked}
+            className="w-full"
+          >
+            {oidcLabel}
+          </Button>
+        )}
+        {ldapEnabled && !confirming && !showLdap && (
+          <Button
+            variant="link"
+            onClick={() => setShowLdap(true)}
+            disabled={blocked}
+            className="w-full"
+          >
+            {t("login.use_password", "Use password and authenticator code")}
+          </Button>
+        )}
+        {showLdap && !confirming && (
+          <form
+            onSubmit={(e) => {
+              e.preventDefault();
+              handleLdapLogin();
+            }}
+            className="space-y-3"
+          >
+            <div className="space-y-1">
+              <Label htmlFor="ldap-username">
+                {t("login.username", "Username")}
+              </Label>
+              <Input
+                id="ldap-username"
+                autoComplete="username"
+                value={ldap.username}
+                onChange={(e) =>
+                  setLdap({ ...ldap, username: e.target.value })
+                }
+              />
+            </div>
+            <div className="space-y-1">
+              <Label htmlFor="ldap-password">
+                {t("login.password", "Password")}
+              </Label>
+              <Input
+                id="ldap-password"
+                type="password"
+                autoComplete="current-password"
+                value={ldap.password}
+                onChange={(e) =>
+                  setLdap({ ...ldap, password: e.target.value })
+                }
+              />
+            </div>
+            <div className="space-y-1">
+              <Label htmlFor="ldap-code">
+                {t("login.code", "Authenticator code")}
+              </Label>
+              <Input
+                id="ldap-code"
+                inputMode="numeric"
+                autoComplete="one-time-code"
+                maxLength={6}
+                value={ldap.code}
+                onChange={(e) => setLdap({ ...ldap, code: e.target.value })}
+              />
+            </div>
+            <Button
+              type="submit"
+              variant="outline"
+              disabled={blocked}
+              className="w-full"
+            >
+              {t("login.with_password", "Sign in with password")}
+            </Button>
+          </form>
+        )}
+        {devLoginEnabled && !confirming && (
+          <form
+            onSubmit={(e) => {
+              e.preventDefault();
+              handleDevLogin();
+            }}
+            className="space-y-3 rounded-md border border-dashed p-3"
+          >
+            <div className="space-y-1">
+              <Label htmlFor="dev-password">Dev login password</Label>
+              <Input
+                id="dev-password"
+                type="password"
+                value={devPassword}
+                onChange={(e) => setDevPassword(e.target.value)}
+              />
+            </div>
+            <Button
+              type="submit"
+              variant="outline"
+              disabled={blocked}
+              className="w-full"
+            >
+              Sign in (dev-auth build)
+            </Button>
+          </form>
+        )}
+        {branding.footer_text && (
+          <p className="text-muted-foreground text-center text-xs">
+            {branding.footer_text}
+          </p>
+        )}
       </CardContent>
     </Card>
   );
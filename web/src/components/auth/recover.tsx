@@ -0,0 +1,99 @@
+"use client";
+
+import { useState } from "react";
+import { Button } from "@/components/ui/button";
+import {
+  Card,
+  CardContent,
+  CardDescription,
+  CardHeader,
+  CardTitle,
+} from "@/components/ui/card";
+import { responseError } from "@/lib/api-fetch";
+import { useBranding } from "@/lib/branding";
+
+interface RecoverProps {
+  token: string;
+  onInvite: (inviteUrl: string) => void;
+}
+
+function formatDateTime(iso: string): string {
+  return new Date(iso.replace(" ", "T") + "Z").toLocaleString(undefined, {
+    dateStyle: "medium",
+    timeStyle: "short",
+  });
+}
+
+async function post(path: string, token: string): Promise<Response> {
+  return fetch(path, {
+    method: "POST",
+    headers: { "Content-Type": "application/json" },
+    body: JSON.stringify({ token }),
+  });
+}
+
+export function Recover({ token, onInvite }: RecoverProps) {
+  const [readyAt, setReadyAt] = useState<string | null>(null);
+  const [error, setError] = useState<string | null>(null);
+  const [loading, setLoading] = useState(false);
+  const branding = useBranding();
+
+  const run = async (action: () => Promise<void>) => {
+    setLoading(true);
+    setError(null);
+    try {
+      await action();
+    } catch (e) {
+      setError(e instanceof Error ? e.message : "Recovery failed");
+    } finally {
+      setLoading(false);
+    }
+  };
+
+  const handleRequest = () =>
+    run(async () => {
+      const res = await post("/api/recovery/request", token);
+      if (!res.ok) throw await responseError(res, "Failed to request recovery");
+      setReadyAt(((await res.json()) as { ready_at: string }).ready_at);
+    });
+
+  const handleComplete = () =>
+    run(async () => {
+      const res = await post("/api/recovery/complete", token);
+      if (!res.ok) {
+        throw await responseError(res, "Failed to complete recovery");
+      }
+      onInvite(((await res.json()) as { invite_url: string }).invite_url);
+    });
+
+  return (
+    <Card className="w-full max-w-sm">
+      <CardHeader>
+        <CardTitle>{branding.title ?? "den"}</CardTitle>
+        <CardDescription>Recover your account</CardDescription>
+      </CardHeader>
+      <CardContent className="space-y-4">
+        {readyAt ? (
+          <p className="text-sm">
+            Reset scheduled. Come back to this URL after{" "}
+            {formatDateTime(readyAt)} to add a new passkey. Every signed-in
+            device was notified and can cancel it until then.
+          </p>
+        ) : (
+          <p className="text-muted-foreground text-sm">
+            Resetting signs out every device and lets you add a new passkey
+            once the waiting period has passed.
+          </p>
+        )}
+        {error && <p className="text-destructive text-sm">{error}</p>}
+        <Button
+          onClick={readyAt ? handleComplete : handleRequest}
+          disabled={loading}
+          className="w-full"
+        >
+          {readyAt ? "Complete reset" : "Request reset"}
+        </Button>
+      </CardContent>
+    </Card>
+  );
+}
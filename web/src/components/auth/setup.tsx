@@ -1,6 +1,6 @@
 "use client";
 
-import { useState } from "react";
+import { useEffect, useState } from "react";
 import { Button } from "@/components/ui/button";
 import { Input } from "@/components/ui/input";
 import {
@@ -20,16 +20,29 @@ interface SetupProps {
 
 export function Setup({ onComplete }: SetupProps) {
   const [name, setName] = useState("");
+  const [token, setToken] = useState("");
+  const [tokenRequired, setTokenRequired] = useState(false);
   const [error, setError] = useState<string | null>(null);
   const [loading, setLoading] = useState(false);
 
+  useEffect(() => {
+    fetch("/api/setup/status", { cache: "no-store" })
+      .then((res) => (res.ok ? res.json() : null))
+      .then((status: { bootstrap_token_required?: boolean } | null) =>
+        setTokenRequired(Boolean(status?.bootstrap_token_required)),
+      )
+      .catch(() => {});
+  }, []);
+
   const handleSetup = async () => {
     const trimmedName = name.trim();
     if (!trimmedName) return;
     setLoading(true);
     setError(null);
     try {
-      await registerPasskey(trimmedName, "initial");
+      await registerPasskey(trimmedName, "initial", undefined, {
+        bootstrapToken: tokenRequired ? token.trim() : undefined,
+      });
       await onComplete(trimmedName);
     } catch (e) {
       if (isUnauthorizedError(e)) return;
@@ -56,10 +69,23 @@ export function Setup({ onComplete }: SetupProps) {
             onKeyDown={(e) => e.key === "Enter" && handleSetup()}
           />
         </div>
+        {tokenRequired && (
+          <div className="space-y-2">
+            <Label htmlFor="bootstrap-token">Setup token</Label>
+            <Input
+              id="bootstrap-token"
+              type="password"
+              value={token}
+              onChange={(e) => setToken(e.target.value)}
+              placeholder="bootstrap_token from config.toml"
+              onKeyDown={(e) => e.key === "Enter" && handleSetup()}
+            />
+          </div>
+        )}
         {error && <p className="text-destructive text-sm">{error}</p>}
         <Button
           onClick={handleSetup}
-          disabled={loading || !name.trim()}
+          disabled={loading || !name.trim() || (tokenRequired && !token.trim())}
           className="w-full"
         >
           {loading ? "Setting up..." : "Register passkey"}
@@ -3,6 +3,7 @@
 import { useEffect, useState } from "react";
 import { Link } from "@tanstack/react-router";
 import { Button } from "@/components/ui/button";
+import { logoutEverywhere } from "@/lib/logout";
 
 interface DashboardProps {
   onLogout: () => void;
@@ -23,6 +24,11 @@ export function Dashboard({ onLogout }: DashboardProps) {
     onLogout();
   };
 
+  const handleLogoutEverywhere = async () => {
+    await logoutEverywhere().catch(() => undefined);
+    onLogout();
+  };
+
   return (
     <main className="flex min-h-screen items-center justify-center">
       <div className="space-y-4 text-center">
@@ -41,6 +47,9 @@ export function Dashboard({ onLogout }: DashboardProps) {
           <Button variant="outline" onClick={handleLogout}>
             Sign out
           </Button>
+          <Button variant="outline" onClick={handleLogoutEverywhere}>
+            Sign out everywhere
+          </Button>
           <Button variant="outline" size="icon" asChild>
             <Link to="/settings" aria-label="Settings">
               <svg
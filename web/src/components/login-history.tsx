@@ -0,0 +1,120 @@
+"use client";
+
+import { useCallback, useEffect, useState } from "react";
+import { Button } from "@/components/ui/button";
+import { apiFetch, isUnauthorizedError } from "@/lib/api-fetch";
+
+interface Login {
+  id: number;
+  kind: "login" | "login_failed" | "login_anomaly";
+  time: string;
+  browser: string | null;
+  os: string | null;
+  ip: string | null;
+  country: string | null;
+  app: string | null;
+  app_name: string | null;
+  detail: string | null;
+}
+
+interface LoginPage {
+  logins: Login[];
+  next_before: number | null;
+}
+
+const KIND_LABELS: Record<Login["kind"], string> = {
+  login: "Signed in",
+  login_failed: "Failed sign-in",
+  login_anomaly: "Unusual sign-in",
+};
+
+function formatTime(iso: string): string {
+  return new Date(iso + "Z").toLocaleString(undefined, {
+    year: "numeric",
+    month: "short",
+    day: "numeric",
+    hour: "numeric",
+    minute: "2-digit",
+  });
+}
+
+function deviceLabel(login: Login): string {
+  const parts = [login.browser, login.os].filter(Boolean);
+  return parts.length ? parts.join(" on ") : "Unknown device";
+}
+
+export function LoginHistory() {
+  const [logins, setLogins] = useState<Login[]>([]);
+  const [nextBefore, setNextBefore] = useState<number | null>(null);
+  const [loading, setLoading] = useState(true);
+  const [error, setError] = useState<string | null>(null);
+
+  const fetchPage = useCallback(async (before: number | null) => {
+    try {
+      const query = before === null ? "" : `?before=${before}`;
+      const res = await apiFetch(`/api/user/logins${query}`);
+      if (!res.ok) throw new Error("Failed to load activity");
+      const page: LoginPage = await res.json();
+      setLogins((prev) =>
+        before === null ? page.logins : [...prev, ...page.logins],
+      );
+      setNextBefore(page.next_before);
+    } catch (error) {
+      if (isUnauthorizedError(error)) return;
+      setError("Failed to load recent activity");
+    } finally {
+      setLoading(false);
+    }
+  }, []);
+
+  useEffect(() => {
+    fetchPage(null);
+  }, [fetchPage]);
+
+  if (loading) {
+    return <p className="text-muted-foreground text-sm">Loading activity...</p>;
+  }
+
+  return (
+    <div className="space-y-4">
+      {error && <p className="text-destructive text-sm">{error}</p>}
+
+      {logins.length === 0 ? (
+        <p className="text-muted-foreground text-sm">No sign-ins yet.</p>
+      ) : (
+        <div className="divide-y rounded-lg border">
+          {logins.map((l) => (
+            <div key={l.id} className="px-4 py-3">
+              <p
+                className={
+                  l.kind === "login"
+                    ? "text-sm font-medium"
+                    : "text-destructive text-sm font-medium"
+                }
+              >
+                {KIND_LABELS[l.kind]}
+                {(l.app_name ?? l.app) && <> to {l.app_name ?? l.app}</>}
+              </p>
+              <p className="text-muted-foreground text-xs">
+                {deviceLabel(l)} &middot;{" "}
+                {[l.ip, l.country].filter(Boolean).join(", ") ||
+                  "Unknown location"}{" "}
+                &middot; {formatTime(l.time)}
+              </p>
+            </div>
+          ))}
+        </div>
+      )}
+
+      {nextBefore !== null && (
+        <Button
+          variant="outline"
+          size="sm"
+          onClick={() => fetchPage(nextBefore)}
+        >
+          Load more
+        </Button>
+      )}
+    </div>
+  );
+}
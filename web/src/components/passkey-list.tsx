@@ -19,6 +19,28 @@ interface Passkey {
   name: string;
   created: string;
   last_used: string | null;
+  backup_eligible: boolean;
+  backup_state: boolean;
+  aaguid: string | null;
+  temporary: boolean;
+  expires_at: string | null;
+}
+
+interface RecoveryStatus {
+  survives_device_loss: boolean;
+  synced: number;
+  device_bound: number;
+}
+
+interface Invite {
+  url: string;
+  expires_at: string;
+}
+
+interface PendingInvite {
+  id: number;
+  created: string;
+  expires_at: string;
 }
 
 function formatDate(iso: string): string {
@@ -35,14 +57,25 @@ export function PasskeyList() {
   const [editingId, setEditingId] = useState<number | null>(null);
   const [editName, setEditName] = useState("");
   const [deleteTarget, setDeleteTarget] = useState<Passkey | null>(null);
+  const [removed, setRemoved] = useState<Passkey | null>(null);
   const [adding, setAdding] = useState(false);
+  const [invite, setInvite] = useState<Invite | null>(null);
+  const [batch, setBatch] = useState<Invite[] | null>(null);
+  const [pending, setPending] = useState<PendingInvite[]>([]);
+  const [recovery, setRecovery] = useState<RecoveryStatus | null>(null);
   const [error, setError] = useState<string | null>(null);
 
   const fetchPasskeys = useCallback(async () => {
     try {
-      const res = await apiFetch("/api/passkeys");
+      const [res, recoveryRes, invitesRes] = await Promise.all([
+        apiFetch("/api/passkeys"),
+        apiFetch("/api/passkeys/recovery"),
+        apiFetch("/api/passkeys/invites"),
+      ]);
       if (!res.ok) throw new Error("Failed to load passkeys");
       setPasskeys(await res.json());
+      setRecovery(recoveryRes.ok ? await recoveryRes.json() : null);
+      setPending(invitesRes.ok ? await invitesRes.json() : []);
     } catch (error) {
       if (isUnauthorizedError(error)) return;
       setError("Failed to load passkeys");
@@ -80,6 +113,7 @@ export function PasskeyList() {
         method: "DELETE",
       });
       if (!res.ok) throw new Error("Delete failed");
+      setRemoved(deleteTarget);
       setDeleteTarget(null);
       await fetchPasskeys();
     } catch (error) {
@@ -88,6 +122,42 @@ export function PasskeyList() {
     }
   };
 
+  const handleRestore = async () => {
+    if (!removed) return;
+    try {
+      const res = await apiFetch(`/api/passkeys/${removed.id}/restore`, {
+        method: "POST",
+      });
+      if (!res.ok) throw new Error("Restore failed");
+      setRemoved(null);
+      await fetchPasskeys();
+    } catch (error) {
+      if (isUnauthorizedError(error)) return;
+      setError("Failed to restore passkey");
+    }
+  };
+
+  const handleExpiry = async (pk: Passkey) => {
+    let days: number | null = null;
+    if (!pk.temporary) {
+      const input = prompt("Days until this passkey expires:", "7");
+      days = Number(input?.trim());
+      if (!Number.isInteger(days) || days < 1) return;
+    }
+    try {
+      const res = await apiFetch(`/api/passkeys/${pk.id}/expiry`, {
+        method: "PUT",
+        headers: { "Content-Type": "application/json" },
+        body: JSON.stringify({ expires_in_days: days }),
+      });
+      if (!res.ok) throw new Error("Expiry update failed");
+      await fetchPasskeys();
+    } catch (error) {
+      if (isUnauthorizedError(error)) return;
+      setError("Failed to update passkey expiry");
+    }
+  };
+
   const handleAdd = async () => {
     const name = prompt("Name for this passkey:");
     if (!name?.trim()) return;
@@ -104,6 +174,52 @@ export function PasskeyList() {
     }
   };
 
+  const handleInvite = async () => {
+    setError(null);
+    try {
+      const res = await apiFetch("/api/passkeys/invite", { method: "POST" });
+      if (!res.ok) throw new Error("Invite failed");
+      setInvite(await res.json());
+      await fetchPasskeys();
+    } catch (error) {
+      if (isUnauthorizedError(error)) return;
+      setError("Failed to create invite link");
+    }
+  };
+
+  const handleBatchInvite = async () => {
+    const input = prompt("How many security keys to enroll?", "3");
+    const count = Number(input?.trim());
+    if (!Number.isInteger(count) || count < 1) return;
+    setError(null);
+    try {
+      const res = await apiFetch("/api/passkeys/invites", {
+        method: "POST",
+        headers: { "Content-Type": "application/json" },
+        body: JSON.stringify({ count }),
+      });
+      if (!res.ok) throw new Error("Invite failed");
+      setBatch(await res.json());
+      await fetchPasskeys();
+    } catch (error) {
+      if (isUnauthorizedError(error)) return;
+      setError("Failed to create invite links");
+    }
+  };
+
+  const handleRevokeInvite = async (id: number) => {
+    try {
+      const res = await apiFetch(`/api/passkeys/invites/${id}`, {
+        method: "DELETE",
+      });
+      if (!res.ok) throw new Error("Revoke failed");
+      await fetchPasskeys();
+    } catch (error) {
+      if (isUnauthorizedError(error)) return;
+      setError("Failed to revoke invite");
+    }
+  };
+
   if (loading) {
     return <p className="text-muted-foreground text-sm">Loading passkeys...</p>;
   }
@@ -112,6 +228,23 @@ export function PasskeyList() {
     <div className="space-y-4">
       {error && <p className="text-destructive text-sm">{error}</p>}
 
+      {removed && (
+        <div className="flex items-center justify-between gap-2 rounded-lg border px-4 py-3 text-sm">
+          <span>Removed &ldquo;{removed.name}&rdquo;.</span>
+          <Button variant="ghost" size="sm" onClick={handleRestore}>
+            Undo
+          </Button>
+        </div>
+      )}
+
+      {recovery && !recovery.survives_device_loss && (
+        <p className="rounded-lg border px-4 py-3 text-sm">
+          Only one device can sign you in. Add a synced passkey (for example
+          from your phone&rsquo;s password manager) so losing a device
+          doesn&rsquo;t lock you out.
+        </p>
+      )}
+
       <div className="divide-y rounded-lg border">
         {passkeys.map((pk) => (
           <div
@@ -157,14 +290,34 @@ export function PasskeyList() {
                     {pk.name}
                   </button>
                   <p className="text-muted-foreground text-xs">
-                    Added {formatDate(pk.created)}
+                    {pk.backup_state ? "Synced" : "This device only"}{" "}
+                    &middot; Added {formatDate(pk.created)}
                     {pk.last_used && (
                       <> &middot; Last used {formatDate(pk.last_used)}</>
                     )}
+                    {pk.expires_at && (
+                      <>
+                        {" "}
+                        &middot;{" "}
+                        {new Date(pk.expires_at + "Z") <= new Date()
+                          ? "Expired"
+                          : "Expires"}{" "}
+                        {formatDate(pk.expires_at)}
+                      </>
+                    )}
                   </p>
                 </>
               )}
             </div>
+            {editingId !== pk.id && (
+              <Button
+                variant="ghost"
+                size="sm"
+                onClick={() => handleExpiry(pk)}
+              >
+                {pk.temporary ? "Make permanent" : "Make temporary"}
+              </Button>
+            )}
             {editingId !== pk.id && (
               <Button
                 variant="ghost"
@@ -180,9 +333,114 @@ export function PasskeyList() {
         ))}
       </div>
 
-      <Button variant="outline" onClick={handleAdd} disabled={adding}>
-        {adding ? "Adding..." : "Add passkey"}
-      </Button>
+      <div className="flex gap-2">
+        <Button variant="outline" onClick={handleAdd} disabled={adding}>
+          {adding ? "Adding..." : "Add passkey"}
+        </Button>
+        <Button variant="outline" onClick={handleInvite}>
+          Invite another device
+        </Button>
+        <Button variant="outline" onClick={handleBatchInvite}>
+          Enroll several keys
+        </Button>
+      </div>
+
+      {pending.length > 0 && (
+        <div className="divide-y rounded-lg border">
+          {pending.map((p) => (
+            <div
+              key={p.id}
+              className="flex items-center justify-between gap-4 px-4 py-2"
+            >
+              <p className="text-muted-foreground text-xs">
+                Pending invite &middot; Expires{" "}
+                {new Date(p.expires_at + "Z").toLocaleTimeString()}
+              </p>
+              <Button
+                variant="ghost"
+                size="sm"
+                className="text-destructive hover:text-destructive"
+                onClick={() => handleRevokeInvite(p.id)}
+              >
+                Revoke
+              </Button>
+            </div>
+          ))}
+        </div>
+      )}
+
+      <Dialog
+        open={batch !== null}
+        onOpenChange={(open) => !open && setBatch(null)}
+      >
+        <DialogContent>
+          <DialogHeader>
+            <DialogTitle>Enroll several keys</DialogTitle>
+            <DialogDescription>
+              Open one link per security key. Each works once and expires
+              at{" "}
+              {batch?.[0] &&
+                new Date(batch[0].expires_at + "Z").toLocaleTimeString()}
+              .
+            </DialogDescription>
+          </DialogHeader>
+          <textarea
+            readOnly
+            rows={Math.min(batch?.length ?? 1, 8)}
+            className="w-full rounded-md border px-3 py-2 font-mono text-xs"
+            value={batch?.map((i) => i.url).join("\n") ?? ""}
+            onFocus={(e) => e.target.select()}
+          />
+          <DialogFooter>
+            <Button
+              variant="outline"
+              onClick={() =>
+                batch &&
+                navigator.clipboard?.writeText(
+                  batch.map((i) => i.url).join("\n"),
+                )
+              }
+            >
+              Copy links
+            </Button>
+            <Button onClick={() => setBatch(null)}>Done</Button>
+          </DialogFooter>
+        </DialogContent>
+      </Dialog>
+
+      <Dialog
+        open={invite !== null}
+        onOpenChange={(open) => !open && setInvite(null)}
+      >
+        <DialogContent>
+          <DialogHeader>
+            <DialogTitle>Invite another device</DialogTitle>
+            <DialogDescription>
+              Open this link on the new device to register a passkey. It works
+              once and expires at{" "}
+              {invite &&
+                new Date(invite.expires_at + "Z").toLocaleTimeString()}
+              .
+            </DialogDescription>
+          </DialogHeader>
+          <Input
+            readOnly
+            value={invite?.url ?? ""}
+            onFocus={(e) => e.target.select()}
+          />
+          <DialogFooter>
+            <Button
+              variant="outline"
+              onClick={() =>
+                invite && navigator.clipboard?.writeText(invite.url)
+              }
+            >
+              Copy link
+            </Button>
+            <Button onClick={() => setInvite(null)}>Done</Button>
+          </DialogFooter>
+        </DialogContent>
+      </Dialog>
 
       <Dialog
         open={deleteTarget !== null}
@@ -0,0 +1,110 @@
+"use client";
+
+import { useCallback, useEffect, useState } from "react";
+import { Button } from "@/components/ui/button";
+import { apiFetch, isUnauthorizedError } from "@/lib/api-fetch";
+
+interface RecoveryStatus {
+  has_code: boolean;
+  pending: { requested_at: string; ready_at: string; ready: boolean } | null;
+  delay_hours: number;
+}
+
+function formatDateTime(iso: string): string {
+  return new Date(iso.replace(" ", "T") + "Z").toLocaleString(undefined, {
+    dateStyle: "medium",
+    timeStyle: "short",
+  });
+}
+
+export function RecoverySettings() {
+  const [status, setStatus] = useState<RecoveryStatus | null>(null);
+  const [url, setUrl] = useState<string | null>(null);
+  const [error, setError] = useState<string | null>(null);
+
+  const fetchStatus = useCallback(async () => {
+    try {
+      const res = await apiFetch("/api/recovery");
+      if (!res.ok) throw new Error("Failed to load recovery status");
+      setStatus((await res.json()) as RecoveryStatus);
+    } catch (error) {
+      if (isUnauthorizedError(error)) return;
+      setError("Failed to load recovery status");
+    }
+  }, []);
+
+  useEffect(() => {
+    fetchStatus();
+  }, [fetchStatus]);
+
+  const handleCreate = async () => {
+    setError(null);
+    try {
+      const res = await apiFetch("/api/recovery/code", { method: "POST" });
+      if (!res.ok) throw new Error("Create failed");
+      setUrl(((await res.json()) as { url: string }).url);
+      await fetchStatus();
+    } catch (error) {
+      if (isUnauthorizedError(error)) return;
+      setError("Failed to create recovery URL");
+    }
+  };
+
+  const handleCancel = async () => {
+    setError(null);
+    try {
+      const res = await apiFetch("/api/recovery/request", { method: "DELETE" });
+      if (!res.ok && res.status !== 404) throw new Error("Cancel failed");
+      await fetchStatus();
+    } catch (error) {
+      if (isUnauthorizedError(error)) return;
+      setError("Failed to cancel recovery");
+    }
+  };
+
+  if (!status && !error) {
+    return <p className="text-muted-foreground text-sm">Loading recovery...</p>;
+  }
+
+  return (
+    <div className="space-y-4">
+      <p className="text-muted-foreground text-sm">
+        Print a recovery URL and keep it somewhere safe. Opening it starts a
+        reset that completes after {status?.delay_hours ?? "a few"} hours
+        unless you cancel it here.
+      </p>
+      {error && <p className="text-destructive text-sm">{error}</p>}
+      {status?.pending && (
+        <div className="border-destructive space-y-2 rounded-md border p-3">
+          <p className="text-sm font-medium">
+            A reset was requested and can complete{" "}
+            {status.pending.ready
+              ? "now"
+              : `on ${formatDateTime(status.pending.ready_at)}`}
+            .
+          </p>
+          <p className="text-muted-foreground text-sm">
+            Cancelling also revokes your recovery URL; create a new one
+            afterwards.
+          </p>
+          <Button variant="destructive" onClick={handleCancel}>
+            Cancel reset
+          </Button>
+        </div>
+      )}
+      {url && (
+        <div className="space-y-2">
+          <p className="text-sm">
+            Print or write down this URL now; it won't be shown again.
+          </p>
+          <code className="bg-muted block rounded-md p-2 text-xs break-all">
+            {url}
+          </code>
+        </div>
+      )}
+      <Button variant="outline" onClick={handleCreate}>
+        {status?.has_code ? "Replace recovery URL" : "Create recovery URL"}
+      </Button>
+    </div>
+  );
+}
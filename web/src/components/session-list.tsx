@@ -0,0 +1,166 @@
+"use client";
+
+import { useCallback, useEffect, useState } from "react";
+import { Button } from "@/components/ui/button";
+import { Input } from "@/components/ui/input";
+import { apiFetch, isUnauthorizedError } from "@/lib/api-fetch";
+
+interface Session {
+  id: string;
+  name: string | null;
+  browser: string | null;
+  os: string | null;
+  ip: string | null;
+  country: string | null;
+  created: string;
+  last_used: string;
+  current: boolean;
+}
+
+function formatDate(iso: string): string {
+  return new Date(iso + "Z").toLocaleDateString(undefined, {
+    year: "numeric",
+    month: "short",
+    day: "numeric",
+  });
+}
+
+function deviceLabel(session: Session): string {
+  const parts = [session.browser, session.os].filter(Boolean);
+  return parts.length ? parts.join(" on ") : "Unknown device";
+}
+
+export function SessionList() {
+  const [sessions, setSessions] = useState<Session[]>([]);
+  const [loading, setLoading] = useState(true);
+  const [editingId, setEditingId] = useState<string | null>(null);
+  const [editName, setEditName] = useState("");
+  const [error, setError] = useState<string | null>(null);
+
+  const fetchSessions = useCallback(async () => {
+    try {
+      const res = await apiFetch("/api/sessions");
+      if (!res.ok) throw new Error("Failed to load sessions");
+      setSessions(await res.json());
+    } catch (error) {
+      if (isUnauthorizedError(error)) return;
+      setError("Failed to load sessions");
+    } finally {
+      setLoading(false);
+    }
+  }, []);
+
+  useEffect(() => {
+    fetchSessions();
+  }, [fetchSessions]);
+
+  const handleRename = async (id: string) => {
+    try {
+      const res = await apiFetch(`/api/sessions/${id}`, {
+        method: "PATCH",
+        headers: { "Content-Type": "application/json" },
+        body: JSON.stringify({ name: editName.trim() || null }),
+      });
+      if (!res.ok) throw new Error("Rename failed");
+      setEditingId(null);
+      await fetchSessions();
+    } catch (error) {
+      if (isUnauthorizedError(error)) return;
+      setError("Failed to rename session");
+    }
+  };
+
+  const handleRevoke = async (id: string) => {
+    try {
+      const res = await apiFetch(`/api/sessions/${id}`, { method: "DELETE" });
+      if (!res.ok) throw new Error("Revoke failed");
+      await fetchSessions();
+    } catch (error) {
+      if (isUnauthorizedError(error)) return;
+      setError("Failed to sign out session");
+    }
+  };
+
+  if (loading) {
+    return <p className="text-muted-foreground text-sm">Loading sessions...</p>;
+  }
+
+  return (
+    <div className="space-y-4">
+      {error && <p className="text-destructive text-sm">{error}</p>}
+
+      <div className="divide-y rounded-lg border">
+        {sessions.map((s) => (
+          <div
+            key={s.id}
+            className="flex items-center justify-between gap-4 px-4 py-3"
+          >
+            <div className="min-w-0 flex-1">
+              {editingId === s.id ? (
+                <form
+                  className="flex items-center gap-2"
+                  onSubmit={(e) => {
+                    e.preventDefault();
+                    handleRename(s.id);
+                  }}
+                >
+                  <Input
+                    value={editName}
+                    onChange={(e) => setEditName(e.target.value)}
+                    placeholder={deviceLabel(s)}
+                    className="h-7 text-sm"
+                    autoFocus
+                  />
+                  <Button type="submit" variant="outline" size="sm">
+                    Save
+                  </Button>
+                  <Button
+                    type="button"
+                    variant="ghost"
+                    size="sm"
+                    onClick={() => setEditingId(null)}
+                  >
+                    Cancel
+                  </Button>
+                </form>
+              ) : (
+                <>
+                  <button
+                    className="text-sm font-medium hover:underline"
+                    onClick={() => {
+                      setEditingId(s.id);
+                      setEditName(s.name ?? "");
+                    }}
+                  >
+                    {s.name ?? deviceLabel(s)}
+                  </button>
+                  {s.current && (
+                    <span className="text-muted-foreground ml-2 text-xs">
+                      This device
+                    </span>
+                  )}
+                  <p className="text-muted-foreground text-xs">
+                    {s.name && <>{deviceLabel(s)} &middot; </>}
+                    {[s.ip, s.country].filter(Boolean).join(", ") ||
+                      "Unknown location"}{" "}
+                    &middot; Last active {formatDate(s.last_used)}
+                  </p>
+                </>
+              )}
+            </div>
+            {editingId !== s.id && !s.current && (
+              <Button
+                variant="ghost"
+                size="sm"
+                className="text-destructive hover:text-destructive"
+                onClick={() => handleRevoke(s.id)}
+              >
+                Sign out
+              </Button>
+            )}
+          </div>
+        ))}
+      </div>
+    </div>
+  );
+}
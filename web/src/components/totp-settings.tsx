@@ -0,0 +1,138 @@
+"use client";
+
+import { useCallback, useEffect, useState } from "react";
+import QRCode from "qrcode";
+import { Button } from "@/components/ui/button";
+import { Input } from "@/components/ui/input";
+import { apiFetch, isUnauthorizedError } from "@/lib/api-fetch";
+
+export function TotpSettings() {
+  const [enrolled, setEnrolled] = useState<boolean | null>(null);
+  const [qrDataUrl, setQrDataUrl] = useState<string | null>(null);
+  const [code, setCode] = useState("");
+  const [error, setError] = useState<string | null>(null);
+
+  const fetchStatus = useCallback(async () => {
+    try {
+      const res = await apiFetch("/api/ldap/totp");
+      if (!res.ok) throw new Error("Failed to load authenticator status");
+      setEnrolled(((await res.json()) as { enrolled: boolean }).enrolled);
+    } catch (error) {
+      if (isUnauthorizedError(error)) return;
+      setError("Failed to load authenticator status");
+    }
+  }, []);
+
+  useEffect(() => {
+    fetchStatus();
+  }, [fetchStatus]);
+
+  const handleEnroll = async () => {
+    setError(null);
+    try {
+      const res = await apiFetch("/api/ldap/totp/enroll", { method: "POST" });
+      if (!res.ok) throw new Error("Enroll failed");
+      const { uri } = (await res.json()) as { uri: string };
+      setQrDataUrl(await QRCode.toDataURL(uri, { margin: 1, width: 200 }));
+      setEnrolled(false);
+    } catch (error) {
+      if (isUnauthorizedError(error)) return;
+      setError("Failed to start authenticator setup");
+    }
+  };
+
+  const handleConfirm = async () => {
+    setError(null);
+    try {
+      const res = await apiFetch("/api/ldap/totp/confirm", {
+        method: "POST",
+        headers: { "Content-Type": "application/json" },
+        body: JSON.stringify({ code: code.trim() }),
+      });
+      if (res.status === 400) {
+        setError("That code didn't match. Check your device's clock.");
+        return;
+      }
+      if (!res.ok) throw new Error("Confirm failed");
+      setQrDataUrl(null);
+      setCode("");
+      setEnrolled(true);
+    } catch (error) {
+      if (isUnauthorizedError(error)) return;
+      setError("Failed to confirm authenticator");
+    }
+  };
+
+  const handleRemove = async () => {
+    setError(null);
+    try {
+      const res = await apiFetch("/api/ldap/totp", { method: "DELETE" });
+      if (!res.ok) throw new Error("Remove failed");
+      setEnrolled(false);
+    } catch (error) {
+      if (isUnauthorizedError(error)) return;
+      setError("Failed to remove authenticator");
+    }
+  };
+
+  if (enrolled === null && !error) {
+    return (
+      <p className="text-muted-foreground text-sm">Loading authenticator...</p>
+    );
+  }
+
+  return (
+    <div className="space-y-4">
+      <p className="text-muted-foreground text-sm">
+        Lets you sign in with your directory password and an authenticator code
+        when no passkey is at hand.
+      </p>
+      {error && <p className="text-destructive text-sm">{error}</p>}
+      {qrDataUrl ? (
+        <form
+          className="space-y-3"
+          onSubmit={(e) => {
+            e.preventDefault();
+            handleConfirm();
+          }}
+        >
+          <img
+            src={qrDataUrl}
+            alt="Authenticator setup QR code"
+            className="rounded-md border"
+          />
+          <div className="flex items-center gap-2">
+            <Input
+              value={code}
+              onChange={(e) => setCode(e.target.value)}
+              placeholder="6-digit code"
+              inputMode="numeric"
+              autoComplete="one-time-code"
+              maxLength={6}
+            />
+            <Button type="submit" variant="outline">
+              Confirm
+            </Button>
+          </div>
+        </form>
+      ) : enrolled ? (
+        <div className="flex gap-2">
+          <Button variant="outline" onClick={handleEnroll}>
+            Replace authenticator
+          </Button>
+          <Button
+            variant="ghost"
+            className="text-destructive hover:text-destructive"
+            onClick={handleRemove}
+          >
+            Remove
+          </Button>
+        </div>
+      ) : (
+        <Button variant="outline" onClick={handleEnroll}>
+          Set up authenticator
+        </Button>
+      )}
+    </div>
+  );
+}
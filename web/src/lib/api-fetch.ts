@@ -1,3 +1,5 @@
+import { translate } from "@/lib/i18n";
+
 export class UnauthorizedError extends Error {
   constructor() {
     super("Unauthorized");
@@ -5,13 +7,76 @@ export class UnauthorizedError extends Error {
   }
 }
 
+/** A structured error body from the auth API. */
+export class ApiError extends Error {
+  constructor(
+    message: string,
+    readonly code: string,
+    readonly retryable: boolean,
+    readonly requestId: string | null,
+    /** Seconds until a retry can succeed, when the server said. */
+    readonly retryAfter: number | null = null,
+  ) {
+    super(message);
+    this.name = "ApiError";
+  }
+}
+
+/** Turn a failed response into an error, preferring the server's (translated) message. */
+export async function responseError(
+  res: Response,
+  fallback: string,
+): Promise<Error> {
+  const body = await res.json().catch(() => null);
+  if (typeof body?.code !== "string" || typeof body?.message !== "string") {
+    return new Error(fallback);
+  }
+  return new ApiError(
+    await translate(`errors.${body.code}`, body.message),
+    body.code,
+    body.retryable === true,
+    body.request_id ?? null,
+    typeof body.retry_after === "number" ? body.retry_after : null,
+  );
+}
+
+let refreshInFlight: Promise<boolean> | null = null;
+
+// Access tokens are short-lived; share one refresh across concurrent 401s so
+// the rotating refresh token is only presented once.
+function refreshSession(): Promise<boolean> {
+  refreshInFlight ??= fetch("/api/refresh", { method: "POST" })
+    .then((res) => res.ok)
+    .catch(() => false)
+    .finally(() => {
+      refreshInFlight = null;
+    });
+  return refreshInFlight;
+}
+
+/** Send the browser to login, remembering the full URL to come back to. */
+function redirectToLogin() {
+  const { pathname, search, hash } = window.location;
+  const params = new URLSearchParams({
+    redirect_path: `${pathname}${search}${hash}`,
+  });
+  window.location.replace(`/login?${params}`);
+}
+
 export async function apiFetch(
   input: RequestInfo | URL,
   init?: RequestInit,
 ): Promise<Response> {
-  const res = await fetch(input, init);
+  let res = await fetch(input, init);
+  if (res.status === 401 && (await refreshSession())) {
+    res = await fetch(input, init);
+  }
   if (res.status === 401) {
-    if (typeof window !== "undefined") window.location.replace("/login");
+    if (typeof window === "undefined") throw new UnauthorizedError();
+    // On the login page a 401 is the answer itself (a rejected passkey), and
+    // reloading would drop the redirect target.
+    if (window.location.pathname === "/login") return res;
+    redirectToLogin();
     throw new UnauthorizedError();
   }
   return res;
@@ -0,0 +1,31 @@
+import { useEffect, useState } from "react";
+
+export interface Branding {
+  title?: string;
+  logo_url?: string;
+  accent_color?: string;
+  footer_text?: string;
+}
+
+let brandingPromise: Promise<Branding> | null = null;
+
+function fetchBranding(): Promise<Branding> {
+  brandingPromise ??= fetch("/api/config/branding")
+    .then((res) => (res.ok ? (res.json() as Promise<Branding>) : {}))
+    .catch(() => ({}));
+  return brandingPromise;
+}
+
+export function useBranding(): Branding {
+  const [branding, setBranding] = useState<Branding>({});
+  useEffect(() => {
+    let active = true;
+    fetchBranding().then((b) => {
+      if (active) setBranding(b);
+    });
+    return () => {
+      active = false;
+    };
+  }, []);
+  return branding;
+}
@@ -0,0 +1,17 @@
+import type { Branding } from "@/lib/branding";
+
+export interface ClientConfig {
+  rp_id: string;
+  setup_complete: boolean;
+  branding: Branding;
+  login_methods: string[];
+  upstream_oidc_label: string | null;
+  allowed_redirect_hosts: string[];
+  recovery_delay_hours: number | null;
+}
+
+export async function fetchClientConfig(): Promise<ClientConfig> {
+  const res = await fetch("/api/config/client", { cache: "no-store" });
+  if (!res.ok) throw new Error("Failed to load client config");
+  return (await res.json()) as ClientConfig;
+}
@@ -0,0 +1,19 @@
+import type { PasskeyAuthResult } from "@/lib/webauthn";
+
+/** Password sign-in offered only by `dev-auth` builds with `dev_login_password` set. */
+export async function loginWithDevPassword(
+  password: string,
+): Promise<PasskeyAuthResult> {
+  const res = await fetch("/api/dev-login", {
+    method: "POST",
+    headers: { "Content-Type": "application/json" },
+    body: JSON.stringify({ password }),
+  });
+  if (res.status === 401) throw new Error("Wrong dev login password.");
+  if (res.status === 409) throw new Error("Register a passkey first.");
+  if (!res.ok) throw new Error("Login failed");
+  const data = (await res.json()) as { user_name: string };
+  return { userName: data.user_name, redirectUrl: null, stepUp: null,
+    stepUpRecovery: null,
+  };
+}
@@ -0,0 +1,55 @@
+import { useEffect, useState } from "react";
+
+export interface Messages {
+  lang: string;
+  languages: string[];
+  strings: Record<string, string>;
+}
+
+const LANGUAGE_KEY = "den-language";
+
+let messagesPromise: Promise<Messages | null> | null = null;
+
+/** An explicit choice saved in this browser; otherwise the server uses Accept-Language. */
+function requestedLanguage(): string {
+  if (typeof window === "undefined") return "auto";
+  return window.localStorage.getItem(LANGUAGE_KEY) ?? "auto";
+}
+
+export function fetchMessages(): Promise<Messages | null> {
+  messagesPromise ??= fetch(
+    `/api/config/i18n/${encodeURIComponent(requestedLanguage())}`,
+  )
+    .then((res) => (res.ok ? (res.json() as Promise<Messages>) : null))
+    .then((messages) => {
+      if (messages) document.documentElement.lang = messages.lang;
+      return messages;
+    })
+    .catch(() => null);
+  return messagesPromise;
+}
+
+export type Translate = (key: string, fallback: string) => string;
+
+function translator(messages: Messages | null): Translate {
+  return (key, fallback) => messages?.strings[key] ?? fallback;
+}
+
+/** Translation lookup that renders the English fallback until the catalog loads. */
+export function useTranslate(): Translate {
+  const [messages, setMessages] = useState<Messages | null>(null);
+  useEffect(() => {
+    let active = true;
+    fetchMessages().then((m) => {
+      if (active) setMessages(m);
+    });
+    return () => {
+      active = false;
+    };
+  }, []);
+  return translator(messages);
+}
+
+export async function translate(key: string, fallback: string) {
+  return translator(await fetchMessages())(key, fallback);
+}
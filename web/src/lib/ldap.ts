@@ -0,0 +1,45 @@
+import { responseError } from "@/lib/api-fetch";
+import {
+  applyRedirectPayload,
+  type PasskeyAuthResult,
+  type RedirectRequest,
+} from "@/lib/webauthn";
+
+export interface LdapCredentials {
+  username: string;
+  password: string;
+  code: string;
+}
+
+/** Fallback sign-in with a directory password plus an authenticator code. */
+export async function loginWithLdap(
+  credentials: LdapCredentials,
+  redirect?: RedirectRequest,
+): Promise<PasskeyAuthResult> {
+  const payload: {
+    username: string;
+    password: string;
+    code: string;
+    redirect_origin?: string;
+    redirect_path?: string;
+  } = { ...credentials };
+  applyRedirectPayload(payload, redirect);
+
+  // Plain fetch: a 401 here means wrong credentials, not an expired session.
+  const res = await fetch("/api/ldap/login", {
+    method: "POST",
+    headers: { "Content-Type": "application/json" },
+    body: JSON.stringify(payload),
+  });
+  if (res.status === 401) {
+    throw new Error("Username, password or code is incorrect.");
+  }
+  if (!res.ok) throw await responseError(res, "Login failed");
+  const data = (await res.json()) as { redirect_url?: string | null };
+  return {
+    userName: credentials.username,
+    redirectUrl: data.redirect_url ?? null,
+    stepUp: null,
+    stepUpRecovery: null,
+  };
+}
@@ -0,0 +1,49 @@
+import { apiFetch, responseError } from "@/lib/api-fetch";
+
+export interface SolvedPuzzle {
+  puzzle: string;
+  nonce: string;
+}
+
+/** Hashes per batch; yielding between batches keeps the page responsive. */
+const BATCH = 256;
+
+function leadingZeroBits(bytes: Uint8Array): number {
+  let bits = 0;
+  for (const byte of bytes) {
+    if (byte === 0) {
+      bits += 8;
+      continue;
+    }
+    return bits + Math.clz32(byte) - 24;
+  }
+  return bits;
+}
+
+/**
+ * Fetch a login puzzle and find a nonce whose SHA-256 over `token:nonce`
+ * starts with `difficulty` zero bits, as den's `[login_puzzle]` requires.
+ */
+export async function solveLoginPuzzle(): Promise<SolvedPuzzle> {
+  const res = await apiFetch("/api/login/puzzle");
+  if (!res.ok) throw await responseError(res, "Login failed to start");
+  const { token, difficulty } = (await res.json()) as {
+    token: string;
+    difficulty: number;
+  };
+  const encoder = new TextEncoder();
+  for (let nonce = 0; ; nonce += BATCH) {
+    const digests = await Promise.all(
+      Array.from({ length: BATCH }, (_, i) =>
+        crypto.subtle.digest(
+          "SHA-256",
+          encoder.encode(`${token}:${nonce + i}`),
+        ),
+      ),
+    );
+    const found = digests.findIndex(
+      (digest) => leadingZeroBits(new Uint8Array(digest)) >= difficulty,
+    );
+    if (found >= 0) return { puzzle: token, nonce: String(nonce + found) };
+  }
+}
@@ -0,0 +1,30 @@
+import { apiFetch, responseError } from "@/lib/api-fetch";
+
+/** Give up on hosts that never finish loading; their sessions are revoked anyway. */
+const FRONTCHANNEL_TIMEOUT_MS = 3_000;
+
+/** Load a host's front-channel logout page in a hidden iframe so it drops its cookies. */
+function loadFrontchannel(url: string): Promise<void> {
+  return new Promise((resolve) => {
+    const frame = document.createElement("iframe");
+    frame.hidden = true;
+    const done = () => {
+      window.clearTimeout(timer);
+      frame.remove();
+      resolve();
+    };
+    const timer = window.setTimeout(done, FRONTCHANNEL_TIMEOUT_MS);
+    frame.addEventListener("load", done);
+    frame.addEventListener("error", done);
+    frame.src = url;
+    document.body.append(frame);
+  });
+}
+
+/** Revoke every session and clear the cookies on all hosts behind den. */
+export async function logoutEverywhere(): Promise<void> {
+  const res = await apiFetch("/api/logout/all", { method: "POST" });
+  if (!res.ok) throw await responseError(res, "Sign out failed");
+  const data = (await res.json()) as { frontchannel_urls: string[] };
+  await Promise.all(data.frontchannel_urls.map(loadFrontchannel));
+}
@@ -1,17 +1,35 @@
-import { apiFetch } from "@/lib/api-fetch";
+import { apiFetch, responseError } from "@/lib/api-fetch";
+import { solveLoginPuzzle } from "@/lib/login-puzzle";
 
 const DEFAULT_WEBAUTHN_TIMEOUT_MS = 60_000;
 const MIN_WEBAUTHN_TIMEOUT_MS = 5_000;
 const MAX_WEBAUTHN_TIMEOUT_MS = 120_000;
 
+/** Where to land after login; without an origin, `redirectPath` is on this host. */
 export interface RedirectRequest {
-  redirectOrigin: string;
+  redirectOrigin?: string;
   redirectPath?: string | null;
 }
 
+export interface StepUpChallenge {
+  challenge_id: string;
+  // eslint-disable-next-line @typescript-eslint/no-explicit-any
+  options: any;
+}
+
 export interface PasskeyAuthResult {
   userName: string | null;
   redirectUrl: string | null;
+  /** Set when the login looked unusual and needs another passkey to confirm it. */
+  stepUp: StepUpChallenge | null;
+  /** Like `stepUp`, for accounts with one passkey: the challenge id to answer
+   * with the recovery code. */
+  stepUpRecovery: string | null;
+}
+
+export interface RegistrationGrant {
+  bootstrapToken?: string;
+  inviteToken?: string;
 }
 
 export interface PasskeyRegistrationResult {
@@ -131,7 +149,7 @@ function assertPasskeySupport(): void {
   }
 }
 
-function applyRedirectPayload(
+export function applyRedirectPayload(
   payload: {
     redirect_origin?: string;
     redirect_path?: string;
@@ -139,9 +157,10 @@ function applyRedirectPayload(
   redirect?: RedirectRequest,
 ): void {
   if (!redirect) return;
-  const redirectOrigin = redirect.redirectOrigin.trim();
-  if (!redirectOrigin) return;
-  payload.redirect_origin = redirectOrigin;
+  const redirectOrigin = redirect.redirectOrigin?.trim();
+  if (redirectOrigin) {
+    payload.redirect_origin = redirectOrigin;
+  }
   const redirectPath = redirect.redirectPath?.trim();
   if (redirectPath) {
     payload.redirect_path = redirectPath;
@@ -209,12 +228,15 @@ export async function registerPasskey(
   userName: string | null,
   passkeyName: string,
   redirect?: RedirectRequest,
+  grant?: RegistrationGrant,
 ): Promise<PasskeyRegistrationResult> {
   assertPasskeySupport();
 
   const payload: {
     passkey_name: string;
     user_name?: string;
+    bootstrap_token?: string;
+    invite_token?: string;
     redirect_origin?: string;
     redirect_path?: string;
   } = {
@@ -223,6 +245,8 @@ export async function registerPasskey(
   if (userName && userName.trim()) {
     payload.user_name = userName.trim();
   }
+  if (grant?.bootstrapToken) payload.bootstrap_token = grant.bootstrapToken;
+  if (grant?.inviteToken) payload.invite_token = grant.inviteToken;
   applyRedirectPayload(payload, redirect);
 
   const beginRes = await apiFetch("/api/register/begin", {
@@ -230,7 +254,9 @@ export async function registerPasskey(
     headers: { "Content-Type": "application/json" },
     body: JSON.stringify(payload),
   });
-  if (!beginRes.ok) throw new Error("Registration failed to start");
+  if (!beginRes.ok) {
+    throw await responseError(beginRes, "Registration failed to start");
+  }
   const { challenge_id, options } = await beginRes.json();
 
   const timeoutMs = resolveWebAuthnTimeout(options.publicKey.timeout);
@@ -284,7 +310,9 @@ export async function registerPasskey(
     headers: { "Content-Type": "application/json" },
     body: JSON.stringify({ challenge_id, credential: credentialData }),
   });
-  if (!completeRes.ok) throw new Error("Registration failed to complete");
+  if (!completeRes.ok) {
+    throw await responseError(completeRes, "Registration failed to complete");
+  }
   const completeData = (await completeRes.json()) as RegisterCompleteResponse;
   return {
     redirectUrl: completeData.redirect_url ?? null,
@@ -294,6 +322,8 @@ export async function registerPasskey(
 interface LoginCompleteResponse {
   user_name?: string | null;
   redirect_url?: string | null;
+  step_up?: StepUpChallenge;
+  step_up_recovery?: { challenge_id: string };
 }
 
 interface RegisterCompleteResponse {
@@ -308,17 +338,41 @@ export async function loginWithPasskey(
   const beginPayload: {
     redirect_origin?: string;
     redirect_path?: string;
+    puzzle?: string;
+    nonce?: string;
   } = {};
   applyRedirectPayload(beginPayload, redirect);
 
-  const beginRes = await apiFetch("/api/login/begin", {
-    method: "POST",
-    headers: { "Content-Type": "application/json" },
-    body: JSON.stringify(beginPayload),
-  });
-  if (!beginRes.ok) throw new Error("Login failed to start");
-  const { challenge_id, options } = await beginRes.json();
+  const begin = () =>
+    apiFetch("/api/login/begin", {
+      method: "POST",
+      headers: { "Content-Type": "application/json" },
+      body: JSON.stringify(beginPayload),
+    });
+  let beginRes = await begin();
+  // 428: den wants proof of work from this network before a challenge.
+  if (beginRes.status === 428) {
+    Object.assign(beginPayload, await solveLoginPuzzle());
+    beginRes = await begin();
+  }
+  if (!beginRes.ok) {
+    throw await responseError(beginRes, "Login failed to start");
+  }
+  return completeLogin(await beginRes.json());
+}
 
+/** Answer the extra challenge den issues when a login looks unusual. */
+export async function confirmStepUp(
+  stepUp: StepUpChallenge,
+): Promise<PasskeyAuthResult> {
+  assertPasskeySupport();
+  return completeLogin(stepUp);
+}
+
+async function completeLogin({
+  challenge_id,
+  options,
+}: StepUpChallenge): Promise<PasskeyAuthResult> {
   const timeoutMs = resolveWebAuthnTimeout(options.publicKey.timeout);
   const publicKey: PublicKeyCredentialRequestOptions = {
     ...options.publicKey,
@@ -368,10 +422,39 @@ export async function loginWithPasskey(
     headers: { "Content-Type": "application/json" },
     body: JSON.stringify({ challenge_id, credential: credentialData }),
   });
-  if (!completeRes.ok) throw new Error("Login failed to complete");
-  const completeData = (await completeRes.json()) as LoginCompleteResponse;
+  if (!completeRes.ok) {
+    throw await responseError(completeRes, "Login failed to complete");
+  }
+  return loginResult((await completeRes.json()) as LoginCompleteResponse);
+}
+
+/** Answer a step-up with the printed recovery URL, or just its token. */
+export async function confirmStepUpWithRecovery(
+  challengeId: string,
+  code: string,
+): Promise<PasskeyAuthResult> {
+  let token = code.trim();
+  try {
+    token = new URL(token).searchParams.get("token") ?? token;
+  } catch {
+    // Not a URL: the bare token.
+  }
+  const res = await apiFetch("/api/login/recovery", {
+    method: "POST",
+    headers: { "Content-Type": "application/json" },
+    body: JSON.stringify({ challenge_id: challengeId, token }),
+  });
+  if (!res.ok) {
+    throw await responseError(res, "Login failed to complete");
+  }
+  return loginResult((await res.json()) as LoginCompleteResponse);
+}
+
+function loginResult(data: LoginCompleteResponse): PasskeyAuthResult {
   return {
-    userName: completeData.user_name ?? null,
-    redirectUrl: completeData.redirect_url ?? null,
+    userName: data.user_name ?? null,
+    redirectUrl: data.redirect_url ?? null,
+    stepUp: data.step_up ?? null,
+    stepUpRecovery: data.step_up_recovery?.challenge_id ?? null,
   };
 }
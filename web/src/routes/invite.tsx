@@ -0,0 +1,37 @@
+import { useState } from "react";
+
+import { createFileRoute, useNavigate } from "@tanstack/react-router";
+
+import { Invite } from "@/components/auth/invite";
+
+export const Route = createFileRoute("/invite")({
+  component: InviteRouteComponent,
+});
+
+function InviteRouteComponent() {
+  const navigate = useNavigate();
+  const [token] = useState(
+    () => new URLSearchParams(window.location.search).get("token")?.trim() ?? "",
+  );
+
+  if (!token) {
+    return (
+      <main className="flex min-h-screen items-center justify-center">
+        <p className="text-muted-foreground text-sm">
+          This invite link is missing its token.
+        </p>
+      </main>
+    );
+  }
+
+  return (
+    <main className="flex min-h-screen items-center justify-center">
+      <Invite
+        token={token}
+        onComplete={async () => {
+          navigate({ to: "/login", replace: true });
+        }}
+      />
+    </main>
+  );
+}
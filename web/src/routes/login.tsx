@@ -3,19 +3,23 @@ import { useCallback, useEffect, useState } from "react";
 import { createFileRoute, useNavigate } from "@tanstack/react-router";
 
 import { Login } from "@/components/auth/login";
+import { fetchClientConfig } from "@/lib/client-config";
 import { type PasskeyAuthResult, type RedirectRequest } from "@/lib/webauthn";
 
 export const Route = createFileRoute("/login")({
   component: LoginRouteComponent,
 });
 
-async function startRedirect(redirect: RedirectRequest): Promise<string> {
+async function startRedirect(
+  redirectOrigin: string,
+  redirectPath?: string | null,
+): Promise<string> {
   const res = await fetch("/api/login/redirect", {
     method: "POST",
     headers: { "Content-Type": "application/json" },
     body: JSON.stringify({
-      redirect_origin: redirect.redirectOrigin,
-      redirect_path: redirect.redirectPath ?? "/",
+      redirect_origin: redirectOrigin,
+      redirect_path: redirectPath ?? "/",
     }),
   });
   if (!res.ok) throw new Error("Failed to start redirect");
@@ -26,23 +30,39 @@ async function startRedirect(redirect: RedirectRequest): Promise<string> {
 
 function readRedirectFromLocation(): RedirectRequest | undefined {
   const searchParams = new URLSearchParams(window.location.search);
+  // Forward-auth proxies (Traefik, Caddy) send the original URL as `rd`.
+  const rd = searchParams.get("rd")?.trim();
+  if (rd) {
+    try {
+      const url = new URL(rd);
+      return {
+        redirectOrigin: url.origin,
+        redirectPath: `${url.pathname}${url.search}${url.hash}`,
+      };
+    } catch {
+      // Ignore malformed rd and fall back to explicit params.
+    }
+  }
   const redirectOrigin = searchParams.get("redirect_origin")?.trim();
-  if (!redirectOrigin) return undefined;
   const redirectPath = searchParams.get("redirect_path")?.trim();
+  if (!redirectOrigin && !redirectPath) return undefined;
   return {
-    redirectOrigin,
+    redirectOrigin: redirectOrigin || undefined,
     redirectPath: redirectPath || undefined,
   };
 }
 
 async function isSetupComplete(): Promise<boolean> {
-  const res = await fetch("/api/register/begin", {
-    method: "POST",
-    headers: { "Content-Type": "application/json" },
-    cache: "no-store",
-    body: JSON.stringify({ user_name: "", passkey_name: "" }),
-  });
-  return res.status === 401;
+  try {
+    const config = await fetchClientConfig();
+    // An upstream IdP can sign in (and create) the user without passkey setup.
+    return (
+      config.setup_complete || config.login_methods.includes("upstream_oidc")
+    );
+  } catch {
+    // Assume an existing instance rather than offering setup on a transient error.
+    return true;
+  }
 }
 
 function LoginRouteComponent() {
@@ -65,9 +85,13 @@ function LoginRouteComponent() {
         window.location.assign(result.redirectUrl);
         return;
       }
-      if (redirect) {
+      // Same-host paths come back as `redirectUrl`; only other hosts need a token.
+      if (redirect?.redirectOrigin) {
         try {
-          const redirectUrl = await startRedirect(redirect);
+          const redirectUrl = await startRedirect(
+            redirect.redirectOrigin,
+            redirect.redirectPath,
+          );
           window.location.assign(redirectUrl);
           return;
         } catch {
@@ -0,0 +1,34 @@
+import { useState } from "react";
+
+import { createFileRoute } from "@tanstack/react-router";
+
+import { Recover } from "@/components/auth/recover";
+
+export const Route = createFileRoute("/recover")({
+  component: RecoverRouteComponent,
+});
+
+function RecoverRouteComponent() {
+  const [token] = useState(
+    () => new URLSearchParams(window.location.search).get("token")?.trim() ?? "",
+  );
+
+  if (!token) {
+    return (
+      <main className="flex min-h-screen items-center justify-center">
+        <p className="text-muted-foreground text-sm">
+          This recovery link is missing its token.
+        </p>
+      </main>
+    );
+  }
+
+  return (
+    <main className="flex min-h-screen items-center justify-center">
+      <Recover
+        token={token}
+        onInvite={(inviteUrl) => window.location.assign(inviteUrl)}
+      />
+    </main>
+  );
+}
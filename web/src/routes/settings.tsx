@@ -1,14 +1,32 @@
+import { useEffect, useState } from "react";
 import { Link, createFileRoute } from "@tanstack/react-router";
 
 import { DeviceLoginQr } from "@/components/device-login-qr";
+import { LoginHistory } from "@/components/login-history";
 import { PasskeyList } from "@/components/passkey-list";
+import { RecoverySettings } from "@/components/recovery-settings";
+import { SessionList } from "@/components/session-list";
 import { ThemeToggle } from "@/components/theme-toggle";
+import { TotpSettings } from "@/components/totp-settings";
+import { fetchClientConfig } from "@/lib/client-config";
 
 export const Route = createFileRoute("/settings")({
   component: SettingsRouteComponent,
 });
 
 function SettingsRouteComponent() {
+  const [ldapEnabled, setLdapEnabled] = useState(false);
+  const [recoveryEnabled, setRecoveryEnabled] = useState(false);
+
+  useEffect(() => {
+    fetchClientConfig()
+      .then((config) => {
+        setLdapEnabled(config.login_methods.includes("ldap"));
+        setRecoveryEnabled(config.recovery_delay_hours !== null);
+      })
+      .catch(() => {});
+  }, []);
+
   return (
     <main className="mx-auto max-w-lg px-4 py-12">
       <div className="mb-8 flex items-center gap-4">
@@ -34,10 +52,34 @@ function SettingsRouteComponent() {
         <DeviceLoginQr />
       </section>
 
-      <section>
+      <section className="mb-10">
         <h2 className="mb-4 text-lg font-semibold">Passkeys</h2>
         <PasskeyList />
       </section>
+
+      {ldapEnabled && (
+        <section className="mb-10">
+          <h2 className="mb-4 text-lg font-semibold">Password Sign-In</h2>
+          <TotpSettings />
+        </section>
+      )}
+
+      {recoveryEnabled && (
+        <section className="mb-10">
+          <h2 className="mb-4 text-lg font-semibold">Account Recovery</h2>
+          <RecoverySettings />
+        </section>
+      )}
+
+      <section className="mb-10">
+        <h2 className="mb-4 text-lg font-semibold">Sessions</h2>
+        <SessionList />
+      </section>
+
+      <section>
+        <h2 className="mb-4 text-lg font-semibold">Recent Activity</h2>
+        <LoginHistory />
+      </section>
     </main>
   );
 }
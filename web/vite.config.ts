@@ -1,11 +1,128 @@
+import { createHash, createPrivateKey, sign } from "node:crypto";
+import { readdir, readFile, stat, writeFile } from "node:fs/promises";
 import path from "node:path";
+import zlib from "node:zlib";
 
 import react from "@vitejs/plugin-react-swc";
 import { tanstackRouter } from "@tanstack/router-plugin/vite";
-import { defineConfig } from "vite";
+import { defineConfig, type Plugin } from "vite";
+
+const PRECOMPRESS_PATTERN = /\.(?:js|mjs|css|html|svg|json|txt|webmanifest)$/;
+const PRECOMPRESS_MIN_BYTES = 1024;
+const MANIFEST = "den-manifest.json";
+
+// Write .br/.gz (and .zst where Node supports it) next to text assets so the
+// server can serve them without compressing on the fly.
+function precompress(): Plugin {
+  let outDir = "";
+  return {
+    name: "den-precompress",
+    apply: "build",
+    configResolved(config) {
+      outDir = path.resolve(config.root, config.build.outDir);
+    },
+    async closeBundle() {
+      const files = await readdir(outDir, { recursive: true });
+      await Promise.all(
+        files
+          .filter((file) => PRECOMPRESS_PATTERN.test(file))
+          .map(async (file) => {
+            const full = path.join(outDir, file);
+            const data = await readFile(full);
+            if (data.length < PRECOMPRESS_MIN_BYTES) return;
+            await writeFile(`${full}.gz`, zlib.gzipSync(data, { level: 9 }));
+            await writeFile(
+              `${full}.br`,
+              zlib.brotliCompressSync(data, {
+                params: { [zlib.constants.BROTLI_PARAM_QUALITY]: 11 },
+              }),
+            );
+            if (typeof zlib.zstdCompressSync === "function") {
+              await writeFile(`${full}.zst`, zlib.zstdCompressSync(data));
+            }
+          }),
+      );
+    },
+  };
+}
+
+// Emit den-routes.json from the router's generated route tree, so den serves
+// index.html for exactly these paths and a real 404 for anything else.
+function routeList(): Plugin {
+  let root = "";
+  return {
+    name: "den-route-list",
+    apply: "build",
+    configResolved(config) {
+      root = config.root;
+    },
+    async generateBundle() {
+      const tree = await readFile(
+        path.join(root, "src/routeTree.gen.ts"),
+        "utf8",
+      );
+      const body = /interface FileRoutesByFullPath \{([^}]*)\}/.exec(tree)?.[1];
+      if (!body)
+        this.error("FileRoutesByFullPath not found in routeTree.gen.ts");
+      const routes = [...body.matchAll(/^\s*'([^']+)':/gm)].map((m) => m[1]);
+      this.emitFile({
+        type: "asset",
+        fileName: "den-routes.json",
+        source: JSON.stringify({ routes }),
+      });
+    },
+  };
+}
+
+// Hash every output file into den-manifest.json, the file list den fetches a
+// `web_source` build by; with DEN_WEB_SIGNING_KEY (an Ed25519 private key PEM),
+// also sign it for den's `[web_integrity]` check. Runs after precompress() so
+// the .br/.gz/.zst siblings are covered too.
+function buildManifest(): Plugin {
+  let outDir = "";
+  return {
+    name: "den-build-manifest",
+    apply: "build",
+    configResolved(config) {
+      outDir = path.resolve(config.root, config.build.outDir);
+    },
+    closeBundle: {
+      order: "post",
+      sequential: true,
+      async handler() {
+        const entries = await readdir(outDir, { recursive: true });
+        const files: Record<string, string> = {};
+        for (const file of entries.sort()) {
+          const full = path.join(outDir, file);
+          if (!(await stat(full)).isFile()) continue;
+          const name = file.split(path.sep).join("/");
+          if (name === MANIFEST || name === `${MANIFEST}.sig`) continue;
+          files[name] = createHash("sha256")
+            .update(await readFile(full))
+            .digest("hex");
+        }
+        const manifest = Buffer.from(JSON.stringify({ files }));
+        await writeFile(path.join(outDir, MANIFEST), manifest);
+        const keyPath = process.env.DEN_WEB_SIGNING_KEY;
+        if (!keyPath) return;
+        const key = createPrivateKey(await readFile(keyPath));
+        await writeFile(
+          path.join(outDir, `${MANIFEST}.sig`),
+          sign(null, manifest, key).toString("base64"),
+        );
+      },
+    },
+  };
+}
 
 export default defineConfig({
-  plugins: [tanstackRouter(), react()],
+  plugins: [
+    tanstackRouter(),
+    react(),
+    routeList(),
+    precompress(),
+    buildManifest(),
+  ],
   resolve: {
     alias: {
       "@": path.resolve(__dirname, "./src"),